@@ -4,22 +4,74 @@ echo "Compiling shaders..."
 
 rm resources/shaders/*.spv
 
-for file in src/shaders/*.vert
-do
+# Prints the permutations of a .variants file as <file suffix>|<comma separated defines>.
+# Each line of the file is either a flag (FOG) or a choice of values (LIGHTING=PHONG,DIFFUSE),
+# see renderer::variant::ShaderVariants.
+permutations() {
+	perms="|"
+	while read -r define || [ -n "$define" ]; do
+		[ -z "$define" ] && continue
+		next=""
+		for p in $perms; do
+			p_suffix=${p%%|*}
+			p_defines=${p#*|}
+			case $define in
+			*=*)
+				define_name=${define%%=*}
+				for value in $(echo "${define#*=}" | tr ',' ' ')
+				do
+					lower=$(echo "${define_name}_${value}" | tr 'A-Z' 'a-z')
+					next="$next ${p_suffix}_${lower}|${p_defines},-D${define_name}_${value}"
+				done
+				;;
+			*)
+				lower=$(echo "$define" | tr 'A-Z' 'a-z')
+				next="$next ${p_suffix}|${p_defines} ${p_suffix}_${lower}|${p_defines},-D${define}"
+				;;
+			esac
+		done
+		perms=$next
+	done < "$1"
+	echo $perms
+}
+
+compile() {
+	file=$1
+	stage=$2
 	base=`basename $file`
-	output="resources/shaders/${base%%.*}_vert.spv"
-	echo "Compiling $file to $output"
-	glslc $file -o $output
-done
+	name=${base%%.*}
+	variants="src/shaders/${name}.variants"
+
+	if [ ! -f "$variants" ]
+	then
+		output="resources/shaders/${name}_${stage}.spv"
+		echo "Compiling $file to $output"
+		glslc -I resources/shaders $file -o $output
+		return
+	fi
 
+	for perm in $(permutations $variants)
+	do
+		output="resources/shaders/${name}${perm%%|*}_${stage}.spv"
+		defines=$(echo "${perm#*|}" | tr ',' ' ')
+		echo "Compiling $file to $output ($defines )"
+		glslc -I resources/shaders $defines $file -o $output
+	done
+}
 
+for file in src/shaders/*.vert
+do
+	compile $file vert
+done
 
 for file in src/shaders/*.frag
 do
-	base=`basename $file`
-	output="resources/shaders/${base%%.*}_frag.spv"
-	echo "Compiling $file to $output"
-	glslc $file -o $output
+	compile $file frag
+done
+
+for file in src/shaders/*.comp
+do
+	compile $file comp
 done
 
 echo "Compilation done."
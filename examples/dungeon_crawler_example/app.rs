@@ -1,11 +1,12 @@
-use cgmath::{Vector2, Vector4};
+use cgmath::Vector2;
 use winit::event::{ElementState, VirtualKeyCode};
 use vulkrap::engine::camera::Camera;
 use vulkrap::engine::cvars::{ConfigVariables, FOV};
 use vulkrap::engine::datatypes::WindowExtent;
 use vulkrap::engine::runtime::{ControlSignal, EngineParameters, VulkrapApplication};
-use vulkrap::engine::ui::widgets::TexturedQuadRenderer;
-use vulkrap::renderer::context::Context;
+use vulkrap::engine::ui::lowres::LowResolutionPresenter;
+use vulkrap::log_error;
+use vulkrap::renderer::context::{Context, Frame};
 use crate::dungeon_crawler_example::movement::{Movement, MovementInput, Orientation};
 use crate::dungeon_crawler_example::scene::Scene;
 
@@ -14,7 +15,7 @@ pub struct DungeonCrawler {
     scene: Scene,
     camera: Camera,
     movement: Movement,
-    main: TexturedQuadRenderer,
+    presenter: LowResolutionPresenter,
 }
 
 impl VulkrapApplication for DungeonCrawler {
@@ -22,12 +23,12 @@ impl VulkrapApplication for DungeonCrawler {
         self.movement.update(delta_time_s);
         self.movement.update_camera(context, &mut self.camera);
 
-        self.scene.update(context, delta_time_s);
+        self.scene.update(context, delta_time_s, &self.movement);
     }
 
-    fn draw(&mut self, context: &mut Context) {
-        self.scene.draw(context, &self.movement);
-        self.main.draw(context);
+    fn draw(&mut self, frame: &mut Frame) {
+        self.scene.draw(frame, &self.camera);
+        self.presenter.draw(frame);
     }
 
     fn reconfigure(&mut self, config: &ConfigVariables) {
@@ -39,10 +40,20 @@ impl VulkrapApplication for DungeonCrawler {
     }
 
     fn handle_window_resize(&mut self, _context: &mut Context, new_size: WindowExtent) {
-        self.main.set(
-            Vector2::new((new_size.width / 2) as f32, (new_size.height / 2) as f32),
-            Vector2::new(new_size.width as f32, new_size.height as f32),
-            Vector4::new(1.0, 1.0, 1.0, 1.0));
+        self.presenter.handle_window_resize(new_size);
+    }
+
+    fn handle_console_command(&mut self, _context: &mut Context, _config: &mut ConfigVariables, args: &[&str]) -> Option<ControlSignal> {
+        match args {
+            ["enemy", x, y] => {
+                match (x.parse::<i32>(), y.parse::<i32>()) {
+                    (Ok(x), Ok(y)) if self.scene.set_enemy_position(Vector2::new(x, y)) => {}
+                    _ => log_error!("enemy: no walkable cell at {} {}", x, y),
+                }
+                Some(ControlSignal::None)
+            }
+            _ => None,
+        }
     }
 
     fn handle_keyboard_event(&mut self, _context: &mut Context, key: VirtualKeyCode, state: ElementState) -> ControlSignal {
@@ -71,28 +82,23 @@ impl DungeonCrawler {
         let mut camera = Camera::new(context, engine_params.config);
         camera.set_pitch(-0.1); /* Slight pitch downward */
 
-        let scene = Scene::new(context, engine_params.mesh_manager, &camera);
-        let movement = Movement::new(start_position, Orientation::North);
-        movement.update_camera(context, &mut camera);
-
-        let sampler = context.add_sampler();
-        let mut texture_quad_renderer = TexturedQuadRenderer::new(
+        let presenter = LowResolutionPresenter::new(
             context,
             engine_params.hud_vp_uniform,
             engine_params.mesh_manager,
-            scene.get_target_texture(),
-            sampler);
+            WindowExtent::new(384, 216),
+            1000,
+            engine_params.window_extent);
 
-        texture_quad_renderer.set(
-            Vector2::new((engine_params.window_extent.width / 2) as f32, (engine_params.window_extent.height / 2) as f32),
-            Vector2::new(engine_params.window_extent.width as f32, engine_params.window_extent.height as f32),
-            Vector4::new(1.0, 1.0, 1.0, 1.0));
+        let scene = Scene::new(context, engine_params.mesh_manager, &camera, presenter.get_render_pass());
+        let movement = Movement::new(start_position, Orientation::North);
+        movement.update_camera(context, &mut camera);
 
         DungeonCrawler {
             scene,
             camera,
             movement,
-            main: texture_quad_renderer,
+            presenter,
         }
     }
 }
@@ -1,301 +1,129 @@
-use std::cmp::{max, min};
-use std::mem::swap;
 use std::path::Path;
-use cgmath::{Deg, Matrix4, Vector2, Vector3, Vector4};
+use cgmath::{Vector2, Vector3, Vector4};
+use vulkrap::engine::billboard::{BillboardMode, BillboardRenderer};
 use vulkrap::engine::camera::Camera;
 use vulkrap::engine::cvars::ConfigVariables;
 
-use vulkrap::engine::datatypes::{Mesh, NormalVertex, TransformColorPushConstant};
+use vulkrap::engine::gridmap::{GridMap, GridMapRenderer};
+use vulkrap::engine::image;
 use vulkrap::engine::mesh::{MeshHandle, MeshManager};
 use vulkrap::engine::mesh::PredefinedMesh::NormaledQuad;
-use vulkrap::log_debug;
-use vulkrap::renderer::context::Context;
-use vulkrap::renderer::types::{DrawCommand, PipelineConfiguration, PipelineHandle, TextureHandle, VertexTopology};
-use vulkrap::util::file;
-use crate::dungeon_crawler_example::movement::{Movement, Orientation};
+use vulkrap::engine::navigation::{find_path, has_line_of_sight, PathDebugRenderer};
+use vulkrap::engine::worldtext::WorldTextRenderer;
+use vulkrap::renderer::context::{Context, Frame};
+use vulkrap::renderer::types::{RenderPassHandle, SamplerConfiguration};
+use crate::dungeon_crawler_example::movement::Movement;
 
-const BLOCK_WIDTH: usize = 16;
-const BLOCK_HEIGHT: usize = 16;
-const BLOCK_SIZE: usize = BLOCK_WIDTH * BLOCK_HEIGHT;
-
-
-
-#[derive(Debug)]
-struct Geometry {
-    mesh: Mesh,
-    push_constant: TransformColorPushConstant,
-}
-
-
-struct Block {
-    grid: Box<[Option<Cell>; BLOCK_SIZE]>,
-}
-
-impl Block {
-    pub fn new() -> Block {
-        Block {
-            grid: Box::new(std::array::from_fn(|_| None))
-        }
-    }
-
-    pub fn set(&mut self, x: i32, y: i32, color: Vector4<f32>, floor_mesh: Mesh, wall_mesh: Mesh) {
-        let floor_rot = Matrix4::from_angle_x(Deg(-90.0));
-        let floor_trans = Matrix4::from_translation(Vector3::new(x as f32, 0.0, y as f32));
-        let floor_geom = Geometry {
-            mesh: floor_mesh,
-            push_constant: TransformColorPushConstant::new(floor_trans * floor_rot, color - Vector4::new(0.3, 0.3, 0.3, 0.0)),
-        };
-        let roof_rot = Matrix4::from_angle_x(Deg(90.0));
-        let roof_trans = Matrix4::from_translation(Vector3::new(x as f32, 1.0, y as f32));
-        let roof_geom = Geometry {
-            mesh: floor_mesh,
-            push_constant: TransformColorPushConstant::new(roof_trans * roof_rot, color - Vector4::new(0.3, 0.3, 0.3, 0.0)),
-        };
-
-
-        let mut cell = Cell::new(floor_geom, roof_geom);
-
-        let west_wall_rot = Matrix4::from_angle_y(Deg(90.0));
-        let west_wall_trans = Matrix4::from_translation(Vector3::new(x as f32 - 0.5, 0.5, y as f32));
-        let west_wall_geom = Geometry {
-            mesh: wall_mesh,
-            push_constant: TransformColorPushConstant::new(west_wall_trans * west_wall_rot, color),
-        };
-        let east_wall_rot = Matrix4::from_angle_y(Deg(-90.0));
-        let east_wall_trans = Matrix4::from_translation(Vector3::new(x as f32 + 0.5, 0.5, y as f32));
-        let east_wall_geom = Geometry {
-            mesh: wall_mesh,
-            push_constant: TransformColorPushConstant::new(east_wall_trans * east_wall_rot, color),
-        };
-
-        let north_wall_rot = Matrix4::from_angle_y(Deg(0.0));
-        let north_wall_trans = Matrix4::from_translation(Vector3::new(x as f32, 0.5, y as f32 - 0.5));
-        let north_wall_geom = Geometry {
-            mesh: wall_mesh,
-            push_constant: TransformColorPushConstant::new(north_wall_trans * north_wall_rot, color),
-        };
-
-        let south_wall_rot = Matrix4::from_angle_y(Deg(180.0));
-        let south_wall_trans = Matrix4::from_translation(Vector3::new(x as f32, 0.5, y as f32 + 0.5));
-        let south_wall_geom = Geometry {
-            mesh: wall_mesh,
-            push_constant: TransformColorPushConstant::new(south_wall_trans * south_wall_rot, color),
-        };
-
-        cell.west_wall = Some(Box::new(west_wall_geom));
-        cell.east_wall = Some(Box::new(east_wall_geom));
-        cell.north_wall = Some(Box::new(north_wall_geom));
-        cell.south_wall = Some(Box::new(south_wall_geom));
-
-        /* Clear walls */
-        if x > 0 {
-            if let Some(western_cell) = &mut self.grid[y as usize * BLOCK_WIDTH + (x - 1) as usize] {
-                cell.west_wall = None;
-                western_cell.east_wall = None;
-            }
-        }
-        if (x + 1) < BLOCK_WIDTH as i32 {
-            if let Some(eastern_cell) = &mut self.grid[y as usize * BLOCK_WIDTH + (x + 1) as usize] {
-                cell.east_wall = None;
-                eastern_cell.west_wall = None;
-            }
-        }
-        if y > 0 {
-            if let Some(northern_cell) = &mut self.grid[(y - 1) as usize * BLOCK_WIDTH + x as usize] {
-                cell.north_wall = None;
-                northern_cell.south_wall = None;
-            }
-        }
-        if (y + 1) < BLOCK_HEIGHT as i32 {
-            if let Some(southern_cell) = &mut self.grid[(y + 1) as usize * BLOCK_WIDTH + x as usize] {
-                cell.south_wall = None;
-                southern_cell.north_wall = None;
-            }
-        }
-
-        self.grid[y as usize * BLOCK_WIDTH + x as usize] = Some(cell);
-    }
-
-    pub fn draw(&mut self, context: &mut Context, pipeline: PipelineHandle, movement: &Movement) {
-
-        // TODO: this is so bad. replace with some real line tracing
-        // Simple culling to only attempt to draw close cells in the direction of the player
-        let mut start_x =  movement.discrete_position.x;
-        let mut end_x= movement.discrete_position.x;
-        let mut start_y = movement.discrete_position.y;
-        let mut end_y = movement.discrete_position.y;
-        match movement.orientation {
-            Orientation::North => {
-                start_y += 2;
-                end_y -= 10;
-                start_x -= 5;
-                end_x += 5;
-            }
-            Orientation::East => {
-                start_x -= 1;
-                end_x += 10;
-                start_y -= 5;
-                end_y += 5;
-            }
-            Orientation::South => {
-                start_y -= 1;
-                end_y += 10;
-                start_x -= 5;
-                end_x += 5;
-            }
-            Orientation::West => {
-                start_x +=2;
-                end_x -= 10;
-                start_y -= 5;
-                end_y += 5;
-            }
-        }
-
-        if start_y > end_y {
-            swap(&mut start_y, &mut end_y);
-        }
-        if start_x > end_x {
-            swap(&mut start_x, &mut end_x);
-        }
-        start_y = start_y.max(0);
-        start_x = start_x.max(0);
-        end_y = end_y.min(BLOCK_HEIGHT as i32);
-        end_x = end_x.min(BLOCK_HEIGHT as i32);
-
-        for y in start_y..end_y {
-            for x in start_x..end_x {
-                if let Some(block) = &self.grid[y as usize * BLOCK_WIDTH + x as usize] {
-                    // Draw floor
-                    context.add_draw_command(DrawCommand::new_buffered(pipeline, &block.floor.push_constant,
-                                                                       block.floor.mesh,
-                    ));
-                    // Draw roof
-                    context.add_draw_command(DrawCommand::new_buffered(pipeline, &block.roof.push_constant,
-                                                                       block.roof.mesh,
-                    ));
-
-                    if let Some(wall) = &block.west_wall {
-                        context.add_draw_command(DrawCommand::new_buffered(pipeline, &wall.push_constant,
-                                                                           wall.mesh,
-                        ));
-                    }
-                    if let Some(wall) = &block.east_wall {
-                        context.add_draw_command(DrawCommand::new_buffered(pipeline, &wall.push_constant,
-                                                                           wall.mesh,
-                        ));
-                    }
-                    if let Some(wall) = &block.north_wall {
-                        context.add_draw_command(DrawCommand::new_buffered(pipeline, &wall.push_constant,
-                                                                           wall.mesh,
-                        ));
-                    }
-                    if let Some(wall) = &block.south_wall {
-                        context.add_draw_command(DrawCommand::new_buffered(pipeline, &wall.push_constant,
-                                                                           wall.mesh,
-                        ));
-                    }
-                }
-
-
-            }
-        }
-
-    }
-}
-
-#[derive(Debug)]
-struct Cell {
-    floor: Box<Geometry>,
-    roof: Box<Geometry>,
-
-    north_wall: Option<Box<Geometry>>,
-    east_wall: Option<Box<Geometry>>,
-    west_wall: Option<Box<Geometry>>,
-    south_wall: Option<Box<Geometry>>,
-}
-
-impl Cell {
-    pub fn new(floor: Geometry, roof: Geometry) -> Cell {
-        Cell {
-            floor: Box::new(floor),
-            roof: Box::new(roof),
-            north_wall: None,
-            east_wall: None,
-            west_wall: None,
-            south_wall: None,
-        }
-    }
-}
+const ENEMY_STEP_S: f32 = 0.8;
 
 pub struct Scene {
-    block: Block,
-    geometry_pipeline: PipelineHandle,
-    target_texture_handle: TextureHandle,
+    map: GridMap,
+    map_renderer: GridMapRenderer,
+    billboards: BillboardRenderer,
+    path_debug: PathDebugRenderer,
+    nameplates: WorldTextRenderer,
+    time_s: f32,
+
+    enemy_position: Vector2<i32>,
+    enemy_path: Vec<Vector2<i32>>,
+    enemy_step_s: f32,
 }
 
 impl Scene {
-    pub fn new(context: &mut Context, mesh_manager: &mut MeshManager, camera: &Camera) -> Scene {
+    pub fn new(context: &mut Context, mesh_manager: &mut MeshManager, camera: &Camera, pass: RenderPassHandle) -> Scene {
         let floor_mesh = *mesh_manager.get_mesh(NormaledQuad as MeshHandle);
         let (_, mesh) = mesh_manager.load_new_mesh(context, Path::new("./resources/models/wall.obj")).unwrap();
         let wall_mesh = *mesh;
 
-        let render_texture = context.add_render_texture(384, 216);
-        let pass = context.create_render_pass(render_texture, 1000).unwrap();
-
-        let pipeline_config = PipelineConfiguration::builder()
-            .with_push_constant::<TransformColorPushConstant>()
-            .with_vertex_shader(file::read_file(Path::new("./resources/shaders/dc_environ_vert.spv")))
-            .with_fragment_shader(file::read_file(Path::new("./resources/shaders/dc_environ_frag.spv")))
-            .with_vertex_topology(VertexTopology::Triangle)
-            .with_vertex_uniform(0, camera.get_uniform())
-            .build();
-
-        let pipeline = context.add_pipeline::<NormalVertex>(pass, pipeline_config);
+        let map = GridMap::load(Path::new("./resources/maps/dungeon.map")).expect("Failed to load dungeon map");
+        // Walls hide most of the dungeon, the pieces are culled against the depth of the previous frame
+        context.set_hiz_source(pass).expect("Failed to set the HiZ source");
+        let map_renderer =
+            GridMapRenderer::new_culled(context, pass, camera.get_uniform(), floor_mesh, wall_mesh, &map);
 
-        log_debug!("block size {}",   std::mem::size_of::<Block>() as u32);
-        log_debug!("cell size {}",   std::mem::size_of::<Cell>() as u32);
-        let mut block = Block::new();
+        let sprite_texture = image::load_texture(context, Path::new("./resources/textures/test.png"));
+        let sampler = context.add_sampler(SamplerConfiguration::default());
+        let billboards = BillboardRenderer::new(context, mesh_manager, pass, camera.get_uniform(), sprite_texture, sampler);
+        let path_debug = PathDebugRenderer::new(context, mesh_manager, pass, camera.get_uniform());
 
-        block.set(1, 1, Vector4::new(1.0, 0.8, 0.8, 1.0), floor_mesh, wall_mesh);
-        block.set(1, 2, Vector4::new(1.0, 0.8, 0.8, 1.0), floor_mesh, wall_mesh);
-        block.set(2, 1, Vector4::new(0.8, 0.4, 0.4, 1.0), floor_mesh, wall_mesh);
-        block.set(2, 2, Vector4::new(0.8, 0.4, 0.4, 1.0), floor_mesh, wall_mesh);
-        block.set(2, 3, Vector4::new(0.8, 0.4, 0.4, 1.0), floor_mesh, wall_mesh);
-        block.set(2, 4, Vector4::new(0.8, 0.4, 0.4, 1.0), floor_mesh, wall_mesh);
-        block.set(2, 5, Vector4::new(0.1, 0.5, 0.1, 1.0), floor_mesh, wall_mesh);
-        block.set(1, 5, Vector4::new(0.1, 0.5, 0.1, 1.0), floor_mesh, wall_mesh);
-        block.set(2, 6, Vector4::new(0.1, 0.5, 0.1, 1.0), floor_mesh, wall_mesh);
-        block.set(1, 6, Vector4::new(0.1, 0.5, 0.1, 1.0), floor_mesh, wall_mesh);
-        block.set(3, 3, Vector4::new(0.8, 0.8, 0.8, 1.0), floor_mesh, wall_mesh);
-        block.set(4, 3, Vector4::new(0.8, 0.8, 0.8, 1.0), floor_mesh, wall_mesh);
-        block.set(5, 3, Vector4::new(0.8, 0.8, 0.8, 1.0), floor_mesh, wall_mesh);
-        block.set(6, 3, Vector4::new(0.8, 0.8, 0.8, 1.0), floor_mesh, wall_mesh);
-        block.set(7, 2, Vector4::new(0.8, 0.8, 0.8, 1.0), floor_mesh, wall_mesh);
-        block.set(7, 3, Vector4::new(0.8, 0.8, 0.8, 1.0), floor_mesh, wall_mesh);
-        block.set(7, 4, Vector4::new(0.8, 0.8, 0.8, 1.0), floor_mesh, wall_mesh);
-        block.set(8, 2, Vector4::new(0.8, 0.8, 0.8, 1.0), floor_mesh, wall_mesh);
-        block.set(8, 3, Vector4::new(0.8, 0.8, 0.8, 1.0), floor_mesh, wall_mesh);
-        block.set(8, 4, Vector4::new(0.8, 0.8, 0.8, 1.0), floor_mesh, wall_mesh);
-        block.set(9, 2, Vector4::new(0.8, 0.8, 0.8, 1.0), floor_mesh, wall_mesh);
-        block.set(9, 3, Vector4::new(0.8, 0.8, 0.8, 1.0), floor_mesh, wall_mesh);
-        block.set(9, 4, Vector4::new(0.8, 0.8, 0.8, 1.0), floor_mesh, wall_mesh);
+        let font_texture = image::load_texture(context, Path::new("./resources/textures/font.png"));
+        let nameplates =
+            WorldTextRenderer::new(context, mesh_manager, pass, camera.get_uniform(), font_texture, sampler, true);
 
         Scene {
-            block,
-            geometry_pipeline: pipeline,
-            target_texture_handle: render_texture,
+            map,
+            map_renderer,
+            billboards,
+            path_debug,
+            nameplates,
+            time_s: 0.0,
+
+            enemy_position: Vector2::new(8, 3),
+            enemy_path: Vec::new(),
+            enemy_step_s: 0.0,
         }
     }
 
     pub fn reconfigure(&mut self, _config: &ConfigVariables) {}
 
-    pub fn update(&mut self, _context: &mut Context, _delta_time_s: f32) {}
+    pub fn set_enemy_position(&mut self, position: Vector2<i32>) -> bool {
+        if !self.map.is_walkable(position.x, position.y) {
+            return false;
+        }
+        self.enemy_position = position;
+        self.enemy_path.clear();
+
+        true
+    }
+
+    pub fn update(&mut self, _context: &mut Context, delta_time_s: f32, movement: &Movement) {
+        self.time_s += delta_time_s;
 
-    pub fn draw(&mut self, context: &mut Context, movement: &Movement) {
-        self.block.draw(context, self.geometry_pipeline, movement);
+        // The enemy chases the player while it can see them, stopping on the neighbouring cell
+        self.enemy_step_s += delta_time_s;
+        if self.enemy_step_s >= ENEMY_STEP_S {
+            self.enemy_step_s = 0.0;
+
+            let player = movement.discrete_position;
+            self.enemy_path.clear();
+            if has_line_of_sight(&self.map, self.enemy_position, player) {
+                self.enemy_path = find_path(&self.map, self.enemy_position, player).unwrap_or_default();
+            }
+            if self.enemy_path.len() > 2 {
+                self.enemy_path.remove(0);
+                self.enemy_position = self.enemy_path[0];
+            }
+        }
     }
 
-    pub fn get_target_texture(&self) -> TextureHandle {
-        self.target_texture_handle
+    pub fn draw(&mut self, frame: &mut Frame, camera: &Camera) {
+        self.map_renderer.draw_culled(frame, camera.view_projection());
+
+        // An enemy standing on the floor and a floating item
+        self.billboards.add(
+            BillboardMode::Cylindrical,
+            Vector3::new(self.enemy_position.x as f32, 0.35, self.enemy_position.y as f32),
+            Vector2::new(0.5, 0.7),
+            Vector4::new(1.0, 1.0, 1.0, 1.0),
+        );
+        self.billboards.add(
+            BillboardMode::Spherical,
+            Vector3::new(2.0, 0.3 + (self.time_s * 2.0).sin() * 0.05, 5.0),
+            Vector2::new(0.2, 0.2),
+            Vector4::new(1.0, 0.9, 0.3, 1.0),
+        );
+        self.billboards.draw(frame);
+
+        self.nameplates.add_billboard(
+            "Skeleton",
+            Vector3::new(self.enemy_position.x as f32, 0.8, self.enemy_position.y as f32),
+            BillboardMode::Spherical,
+            0.06,
+            Vector4::new(1.0, 0.3, 0.3, 1.0),
+        );
+        self.nameplates.draw(frame);
+
+        self.path_debug.add_path(&self.enemy_path, Vector4::new(1.0, 0.2, 0.2, 1.0));
+        self.path_debug.draw(frame);
     }
 }
\ No newline at end of file
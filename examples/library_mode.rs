@@ -0,0 +1,99 @@
+use std::path::Path;
+use std::time::Instant;
+use cgmath::{Deg, Matrix4, Quaternion, Rotation3, SquareMatrix, Vector3};
+use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
+use winit::dpi::{LogicalSize, PhysicalSize};
+use winit::event::{Event, WindowEvent};
+use winit::event_loop::{ControlFlow, EventLoop};
+use winit::window::WindowBuilder;
+use vulkrap::engine::datatypes::{ColoredVertex, ViewProjectionUniform};
+use vulkrap::engine::math::transform::Transform;
+use vulkrap::engine::mesh::{MeshHandle, MeshManager, PredefinedMesh};
+use vulkrap::renderer::context::Context;
+use vulkrap::renderer::types::{DrawCommand, PipelineConfiguration, SWAPCHAIN_PASS, UniformStage};
+
+const WINDOW_TITLE: &str = "library mode example";
+const WINDOW_WIDTH: u32 = 1280;
+const WINDOW_HEIGHT: u32 = 720;
+
+// The application owns the window and the event loop, vulkrap only renders into it
+fn main() {
+    let event_loop = EventLoop::new();
+    let window = WindowBuilder::new()
+        .with_title(WINDOW_TITLE)
+        .with_inner_size(LogicalSize::new(WINDOW_WIDTH, WINDOW_HEIGHT))
+        .build(&event_loop)
+        .expect("Failed to create window.");
+
+    // The window is moved into the event loop along with the context, so it lives as long as the context does
+    let size = window.inner_size();
+    let mut context = unsafe {
+        Context::from_raw_handles(
+            window.raw_display_handle(),
+            window.raw_window_handle(),
+            (size.width, size.height),
+        )
+    }
+    .expect("Failed to create context");
+
+    let mesh_manager = MeshManager::new(&mut context);
+    let mesh = *mesh_manager.get_mesh(PredefinedMesh::ColoredQuad as MeshHandle);
+
+    let vp_uniform = context.create_uniform_buffer::<ViewProjectionUniform>(UniformStage::Vertex);
+    context.set_buffer_object(vp_uniform, create_view_projection_uniform(size));
+
+    let pipeline_config = PipelineConfiguration::builder()
+        .with_vertex_shader_file(Path::new("./resources/shaders/example_hello_krap_vert.spv"))
+        .with_fragment_shader_file(Path::new("./resources/shaders/example_hello_krap_frag.spv"))
+        .with_push_constant::<Matrix4<f32>>()
+        .with_vertex_uniform(0, vp_uniform)
+        .with_frame_globals(1)
+        .build()
+        .expect("Invalid pipeline configuration!");
+    let pipeline = context.add_pipeline::<ColoredVertex>(SWAPCHAIN_PASS, pipeline_config);
+
+    let start = Instant::now();
+    let mut last_frame = start;
+    let mut size = size;
+
+    event_loop.run(move |event, _, control_flow| match event {
+        Event::WindowEvent { event, .. } => match event {
+            WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+            WindowEvent::Resized(new_size) => {
+                size = new_size;
+                context.set_buffer_object(vp_uniform, create_view_projection_uniform(size));
+                context.resize_surface(size.width, size.height);
+            }
+            _ => {}
+        },
+        Event::MainEventsCleared => {
+            window.request_redraw();
+        }
+        Event::RedrawRequested(_window_id) => {
+            let now = Instant::now();
+            context.update_frame_globals((now - last_frame).as_secs_f32());
+            last_frame = now;
+
+            let center = Vector3::new(size.width as f32 / 2.0, size.height as f32 / 2.0, 0.0);
+            let transform = Transform::from_translation(center)
+                .with_rotation(Quaternion::from_angle_z(Deg(start.elapsed().as_secs_f32() * -25.0)))
+                .with_scale(256.0)
+                .matrix();
+
+            let mut frame = context.begin_frame();
+            frame.add_draw_command(DrawCommand::new_buffered(pipeline, &transform, mesh));
+            frame.submit();
+        }
+        Event::LoopDestroyed => unsafe {
+            context.wait_idle();
+        },
+        _ => {}
+    });
+}
+
+fn create_view_projection_uniform(size: PhysicalSize<u32>) -> ViewProjectionUniform {
+    ViewProjectionUniform {
+        view: Matrix4::identity(),
+        proj: cgmath::ortho(0.0, size.width as f32, 0.0, size.height as f32, -1.0, 1.0),
+    }
+}
@@ -1,13 +1,17 @@
 use std::path::Path;
+use std::thread;
+use std::thread::JoinHandle;
 use cgmath::{Deg, Matrix4, Quaternion, Rotation, Rotation3, SquareMatrix, Vector3, Vector4};
 use winit::event::{ElementState, VirtualKeyCode};
 use vulkrap::engine::camera::Camera;
 use vulkrap::engine::cvars::ConfigVariables;
 use vulkrap::engine::datatypes::{Mesh, NormalVertex, TransformColorPushConstant, WindowExtent};
+use vulkrap::engine::math::transform::Transform;
+use vulkrap::engine::model::obj;
 use vulkrap::engine::runtime::{ControlSignal, EngineParameters, VulkrapApplication};
-use vulkrap::renderer::context::Context;
-use vulkrap::renderer::types::{DrawCommand, PipelineConfiguration, PipelineHandle, SWAPCHAIN_PASS};
-use vulkrap::util::file;
+use vulkrap::renderer::context::{Context, Frame};
+use vulkrap::renderer::types::{DrawCommand, PipelineConfiguration};
+use vulkrap::renderer::variant::{PipelineVariants, ShaderVariants, VariantSelection};
 use vulkrap::vulkrap_start;
 
 const WINDOW_TITLE: &str = "model example";
@@ -17,17 +21,22 @@ const WINDOW_HEIGHT: u32 = 850;
 const ROT_SPEED_SUZANNE: f32 = 20.0;
 const ROT_SPEED_SPHERE: f32 = 5.0;
 
+const FOG_BUTTON: VirtualKeyCode = VirtualKeyCode::F;
+
 struct ModelExample {
-    pipeline: PipelineHandle,
+    pipelines: PipelineVariants,
+    variant: VariantSelection,
+    fog: bool,
     camera: Camera,
 
     suzanne: Entity,
     sphere: Entity,
-
+    // The sphere is streamed in by a loader thread
+    sphere_loader: Option<JoinHandle<Mesh>>,
 }
 
 struct Entity {
-    mesh: Mesh,
+    mesh: Option<Mesh>,
     push_constant: TransformColorPushConstant,
     orientation: Quaternion<f32>
 }
@@ -35,29 +44,31 @@ struct Entity {
 
 impl VulkrapApplication for ModelExample {
     fn update(&mut self, _context: &mut Context, delta_time_s: f32) {
+        if self.sphere_loader.as_ref().is_some_and(|loader| loader.is_finished()) {
+            let loader = self.sphere_loader.take().unwrap();
+            self.sphere.mesh = Some(loader.join().expect("Failed to load sphere!"));
+        }
+
         self.suzanne.orientation = self.suzanne.orientation * Quaternion::from_angle_y(Deg(delta_time_s * ROT_SPEED_SUZANNE));
-        self.suzanne.push_constant.transform = Matrix4::from_translation(Vector3::new(0.0, 0.0, -3.0))
-            * Matrix4::from(self.suzanne.orientation);
+        let suzanne = Transform::from_translation(Vector3::new(0.0, 0.0, -3.0)).with_rotation(self.suzanne.orientation);
+        self.suzanne.push_constant.transform = suzanne.matrix();
 
+        // Orbits suzanne, without its rotation
         self.sphere.orientation = self.sphere.orientation
             * Quaternion::from_angle_z(Deg(delta_time_s * ROT_SPEED_SPHERE));
-        self.sphere.push_constant.transform = Matrix4::from_translation(Vector3::new(0.0, 0.0, -3.0))
-            * Matrix4::from(self.sphere.orientation) * Matrix4::from(self.suzanne.orientation.invert())
-            * Matrix4::from_translation(Vector3::new(2.0, 0.0, 0.0))
-            * Matrix4::from_scale(0.5);
+        let orbit = Transform::identity().with_rotation(self.sphere.orientation * self.suzanne.orientation.invert());
+        let sphere = Transform::from_translation(Vector3::new(2.0, 0.0, 0.0)).with_scale(0.5);
+        self.sphere.push_constant.transform =
+            (Transform::from_translation(suzanne.translation) * orbit * sphere).matrix();
     }
 
-    fn draw(&mut self, context: &mut Context) {
-       context.add_draw_command(DrawCommand::new_buffered(
-            self.pipeline,
-            &self.suzanne.push_constant,
-            self.suzanne.mesh,
-        ));
-        context.add_draw_command(DrawCommand::new_buffered(
-            self.pipeline,
-            &self.sphere.push_constant,
-            self.sphere.mesh,
-        ));
+    fn draw(&mut self, frame: &mut Frame) {
+        let pipeline = self.pipelines.select(&self.variant).expect("Invalid shader variant!");
+        for entity in [&self.suzanne, &self.sphere] {
+            if let Some(mesh) = entity.mesh {
+                frame.add_draw_command(DrawCommand::new_buffered(pipeline, &entity.push_constant, mesh));
+            }
+        }
     }
 
     fn reconfigure(&mut self, _config: &ConfigVariables) {}
@@ -68,7 +79,11 @@ impl VulkrapApplication for ModelExample {
         self.camera.update_uniform(context);
     }
 
-    fn handle_keyboard_event(&mut self, _context: &mut Context, _key: VirtualKeyCode, _state: ElementState) -> ControlSignal {
+    fn handle_keyboard_event(&mut self, _context: &mut Context, key: VirtualKeyCode, state: ElementState) -> ControlSignal {
+        if key == FOG_BUTTON && state == ElementState::Pressed {
+            self.fog = !self.fog;
+            self.variant.set_flag("FOG", self.fog);
+        }
         ControlSignal::None
     }
 }
@@ -78,16 +93,19 @@ impl ModelExample {
         let (_, mesh) = engine_params.mesh_manager.load_new_mesh(context, Path::new("./resources/models/suzanne.obj")).unwrap();
 
         let suzanne = Entity {
-            mesh: *mesh,
+            mesh: Some(*mesh),
             push_constant: TransformColorPushConstant::new(
                 Matrix4::identity(),
                 Vector4::from((0.25, 0.25, 0.12, 1.0))),
             orientation: Quaternion::from_angle_y(Deg(0.0)),
         };
 
-        let (_, mesh) = engine_params.mesh_manager.load_new_mesh(context, Path::new("./resources/models/sphere.obj")).unwrap();
+        let resources = context.resources();
+        let sphere_loader = thread::spawn(move || {
+            obj::load_obj_mesh_with_resources(&resources, Path::new("./resources/models/sphere.obj")).unwrap()
+        });
         let sphere = Entity {
-            mesh: *mesh,
+            mesh: None,
             push_constant: TransformColorPushConstant::new(
                 Matrix4::identity(),
                 Vector4::from((0.0, 0.75, 0.15, 1.0))),
@@ -97,23 +115,25 @@ impl ModelExample {
         let camera = Camera::new(context, engine_params.config);
         let vp_uniform = camera.get_uniform();
 
-        let pipeline_config = PipelineConfiguration::builder()
-            .with_vertex_shader(file::read_file(Path::new(
-                "./resources/shaders/default_ppl_vert.spv",
-            )))
-            .with_fragment_shader(file::read_file(Path::new(
-                "./resources/shaders/default_ppl_frag.spv",
-            )))
-            .with_vertex_uniform(0, vp_uniform)
-            .with_push_constant::<TransformColorPushConstant>()
-            .build();
-        let pipeline = context.add_pipeline::<NormalVertex>(SWAPCHAIN_PASS, pipeline_config);
+        let variants = ShaderVariants::new("./resources/shaders/default_ppl").with_flag("FOG");
+        let pipelines = context
+            .add_pipeline_variants::<NormalVertex>(
+                engine_params.scene_pass,
+                variants,
+                PipelineConfiguration::builder()
+                    .with_vertex_uniform(0, vp_uniform)
+                    .with_push_constant::<TransformColorPushConstant>(),
+            )
+            .expect("Invalid pipeline configuration!");
 
         ModelExample {
-            pipeline,
+            pipelines,
+            variant: VariantSelection::new(),
+            fog: false,
             camera,
             suzanne,
             sphere,
+            sphere_loader: Some(sphere_loader),
         }
     }
 }
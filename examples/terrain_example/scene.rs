@@ -1,10 +1,11 @@
 use vulkrap::engine::mesh::MeshManager;
 use vulkrap::engine::terrain::Terrain;
-use vulkrap::renderer::context::Context;
+use vulkrap::renderer::context::{Context, Frame};
 use vulkrap::renderer::types::PipelineHandle;
 
 pub struct Scene {
     terrain: Terrain,
+    minimap_pipeline: PipelineHandle,
 }
 
 impl Scene {
@@ -12,20 +13,23 @@ impl Scene {
         context: &mut Context,
         _mesh_manager: &MeshManager,
         terrain_pipeline: PipelineHandle,
+        minimap_pipeline: PipelineHandle,
     ) -> Scene {
 
 
         Scene {
             terrain: Terrain::new(context, terrain_pipeline),
+            minimap_pipeline,
         }
     }
 
     pub fn update(&mut self, _delta_time_s: f32) {
     }
 
-    pub fn draw(&mut self, context: &mut Context) {
+    pub fn draw(&mut self, frame: &mut Frame) {
 
-        self.terrain.draw(context);
+        self.terrain.draw(frame);
+        self.terrain.draw_with_pipeline(frame, self.minimap_pipeline);
     }
 
 }
@@ -1,27 +1,33 @@
 use std::path::Path;
 use bitflags::bitflags;
-use cgmath::{Vector2, Vector3, Vector4};
+use cgmath::{Vector2, Vector3};
 
 use winit::event::{ElementState, VirtualKeyCode};
 
-use vulkrap::engine::camera::Camera;
+use vulkrap::engine::camera::{Camera, CameraHandle, CameraManager, Projection};
 use vulkrap::engine::cvars::ConfigVariables;
 use vulkrap::engine::datatypes::{NormalVertex, WindowExtent};
+use vulkrap::engine::math::color::Color;
 use vulkrap::engine::runtime::{ControlSignal, EngineParameters, VulkrapApplication};
+use vulkrap::engine::ui::pip::{PictureInPicture, Placement};
 use vulkrap::engine::ui::widgets::TexturedQuadRenderer;
-use vulkrap::renderer::context::Context;
-use vulkrap::renderer::types::{PipelineConfiguration, UniformHandle, UniformStage, VertexTopology};
-use vulkrap::util::file;
+use vulkrap::renderer::context::{Context, Frame};
+use vulkrap::renderer::types::{PipelineConfiguration, RenderPassHandle, SamplerConfiguration, UniformHandle, UniformStage, VertexTopology};
 
 use crate::terrain_example::scene::Scene;
 
 pub struct TerrainApp {
     scene: Scene,
-    camera: Camera,
+    cameras: CameraManager,
+    main_camera: CameraHandle,
+    overhead_camera: CameraHandle,
+    pass: RenderPassHandle,
+    overhead_view: bool,
 
     texture_quad_renderer: TexturedQuadRenderer,
+    minimap: PictureInPicture,
 
-    flags_uniform: UniformHandle,
+    flags_uniform: UniformHandle<u32>,
     movement: MovementFlags,
 
     draw_wireframe: bool,
@@ -32,45 +38,47 @@ impl VulkrapApplication for TerrainApp {
     fn update(&mut self, context: &mut Context, delta_time_s: f32) {
 
         if self.movement.contains(MovementFlags::FORWARD) {
-            self.camera.move_(Vector3::new(0.0, 0.0, -1.0), delta_time_s);
+            self.cameras.get_mut(self.main_camera).move_(Vector3::new(0.0, 0.0, -1.0), delta_time_s);
         } else if self.movement.contains(MovementFlags::BACKWARD) {
-            self.camera.move_(Vector3::new(0.0, 0.0, 1.0), delta_time_s);
+            self.cameras.get_mut(self.main_camera).move_(Vector3::new(0.0, 0.0, 1.0), delta_time_s);
         }
         if self.movement.contains(MovementFlags::LEFT) {
-            self.camera.move_(Vector3::new(-1.0, 0.0, 0.0), delta_time_s);
+            self.cameras.get_mut(self.main_camera).move_(Vector3::new(-1.0, 0.0, 0.0), delta_time_s);
         } else if self.movement.contains(MovementFlags::RIGHT) {
-            self.camera.move_(Vector3::new(1.0, 0.0, 0.0), delta_time_s);
+            self.cameras.get_mut(self.main_camera).move_(Vector3::new(1.0, 0.0, 0.0), delta_time_s);
         }
         if self.movement.contains(MovementFlags::UP) {
-            self.camera.move_(Vector3::new(0.0, 1.0, 0.0), delta_time_s);
+            self.cameras.get_mut(self.main_camera).move_(Vector3::new(0.0, 1.0, 0.0), delta_time_s);
         } else if self.movement.contains(MovementFlags::DOWN) {
-            self.camera.move_(Vector3::new(0.0, -1.0, 0.0), delta_time_s);
+            self.cameras.get_mut(self.main_camera).move_(Vector3::new(0.0, -1.0, 0.0), delta_time_s);
         }
 
 
-        self.camera.update_uniform(context);
+        self.cameras.update_uniforms(context);
         self.scene.update(delta_time_s);
     }
 
-    fn draw(&mut self, context: &mut Context) {
-        self.scene.draw(context);
+    fn draw(&mut self, frame: &mut Frame) {
+        self.scene.draw(frame);
 
-        self.texture_quad_renderer.draw(context);
+        self.texture_quad_renderer.draw(frame);
+        self.minimap.draw(frame);
     }
 
     fn reconfigure(&mut self, config: &ConfigVariables) {
-        self.camera.reconfigure(config);
+        self.cameras.reconfigure(config);
     }
 
     fn handle_mouse_input(&mut self, x_delta: f64, y_delta: f64) {
-        self.camera.update_yaw_pitch(x_delta as f32, y_delta as f32);
+        self.cameras.get_mut(self.main_camera).update_yaw_pitch(x_delta as f32, y_delta as f32);
     }
 
     fn handle_window_resize(&mut self, _context: &mut Context, new_size: WindowExtent) {
+        self.minimap.handle_window_resize(new_size);
         self.texture_quad_renderer.set(
             Vector2::new((new_size.width / 2) as f32, (new_size.height / 2) as f32),
             Vector2::new(new_size.width as f32, new_size.height as f32),
-            Vector4::new(1.0, 1.0, 1.0, 1.0));
+            Color::WHITE);
     }
 
     fn handle_keyboard_event(&mut self, context: &mut Context, key: VirtualKeyCode, state: ElementState) -> ControlSignal {
@@ -88,6 +96,8 @@ impl VulkrapApplication for TerrainApp {
             (VirtualKeyCode::C, ElementState::Pressed) => self.movement.insert(MovementFlags::DOWN),
             (VirtualKeyCode::C, ElementState::Released) => self.movement.remove(MovementFlags::DOWN),
             (VirtualKeyCode::F2, ElementState::Pressed) => self.toggle_wireframe(context),
+            (VirtualKeyCode::F3, ElementState::Pressed) => self.toggle_overhead_view(context),
+            (VirtualKeyCode::F4, ElementState::Pressed) => self.minimap.set_enabled(context, !self.minimap.is_enabled()),
 
             _ => {}
         }
@@ -99,39 +109,72 @@ impl VulkrapApplication for TerrainApp {
 
 impl TerrainApp {
     pub fn new(context: &mut Context, engine_params: EngineParameters) -> TerrainApp {
-        let camera = Camera::new(context, engine_params.config);
+        let mut cameras = CameraManager::new();
+        let main_camera = cameras.add(Camera::new(context, engine_params.config));
+
+        let mut overhead = Camera::new(context, engine_params.config);
+        overhead.set_projection(Projection::Orthographic { width: 256.0, height: 144.0 });
+        overhead.set_position(Vector3::new(0.0, 200.0, 0.0));
+        overhead.set_pitch(-std::f32::consts::FRAC_PI_2);
+        let overhead_camera = cameras.add(overhead);
+
         let flags_uniform = context.create_uniform_buffer::<u32>(UniformStage::Fragment);
 
         context.set_buffer_object(flags_uniform, 0_u32);
 
         //let render_texture = context.add_render_texture(384, 216);
-        let render_texture = context.add_render_texture(1920, 1080);
-        let sampler = context.add_sampler();
+        let render_texture = context.add_render_texture_scaled(1.0);
+        let sampler = context.add_sampler(SamplerConfiguration::default());
         let pass = context.create_render_pass(render_texture, 1000).unwrap();
+        cameras.bind_pass(context, pass, main_camera).unwrap();
+
+        let minimap = PictureInPicture::new(
+            context,
+            engine_params.hud_vp_uniform,
+            engine_params.mesh_manager,
+            cameras.get_uniform(overhead_camera),
+            WindowExtent::new(256, 144),
+            1001,
+            engine_params.window_extent,
+        )
+        .with_placement(Placement::TopRight, 16)
+        .with_border(2, Color::srgb(0.1, 0.1, 0.1, 1.0));
 
         // TODO: move all this shit to the scene
-        let pipeline_config = PipelineConfiguration::builder()
-            .with_vertex_shader(file::read_file(Path::new("./resources/shaders/terrain_vert.spv")))
-            .with_fragment_shader(file::read_file(Path::new("./resources/shaders/terrain_frag.spv")))
+        let mut pipeline_config = PipelineConfiguration::builder();
+        pipeline_config
+            .with_vertex_shader_file(Path::new("./resources/shaders/terrain_vert.spv"))
+            .with_fragment_shader_file(Path::new("./resources/shaders/terrain_frag.spv"))
             .with_vertex_topology(VertexTopology::TriangeStrip)
-            .with_vertex_uniform(0, camera.get_uniform())
-            .with_fragment_uniform(1, flags_uniform)
-            .build();
-        let terrain_pipeline = context.add_pipeline::<NormalVertex>(pass, pipeline_config);
-
-        let scene = Scene::new(context, engine_params.mesh_manager, terrain_pipeline);
+            .with_pass_camera(0)
+            .with_fragment_uniform(1, flags_uniform);
+        let terrain_pipeline = context.add_pipeline::<NormalVertex>(
+            pass,
+            pipeline_config.build().expect("Invalid pipeline configuration!"),
+        );
+        let minimap_pipeline = context.add_pipeline::<NormalVertex>(
+            minimap.get_render_pass(),
+            pipeline_config.build().expect("Invalid pipeline configuration!"),
+        );
+
+        let scene = Scene::new(context, engine_params.mesh_manager, terrain_pipeline, minimap_pipeline);
 
         let mut texture_quad_renderer = TexturedQuadRenderer::new(context, engine_params.hud_vp_uniform, engine_params.mesh_manager, render_texture, sampler);
         texture_quad_renderer.set(
             Vector2::new((engine_params.window_extent.width / 2) as f32, (engine_params.window_extent.height / 2) as f32),
             Vector2::new(engine_params.window_extent.width as f32, engine_params.window_extent.height as f32),
-            Vector4::new(1.0, 1.0, 1.0, 1.0));
+            Color::WHITE);
 
         TerrainApp {
             scene,
-            camera,
+            cameras,
+            main_camera,
+            overhead_camera,
+            pass,
+            overhead_view: false,
 
             texture_quad_renderer,
+            minimap,
 
             flags_uniform,
             movement: MovementFlags::ZERO,
@@ -139,6 +182,19 @@ impl TerrainApp {
         }
     }
 
+    fn toggle_overhead_view(&mut self, context: &mut Context) {
+        self.overhead_view = !self.overhead_view;
+
+        // The minimap shows whichever camera the main view doesn't
+        let (camera, minimap_camera) = if self.overhead_view {
+            (self.overhead_camera, self.main_camera)
+        } else {
+            (self.main_camera, self.overhead_camera)
+        };
+        self.cameras.bind_pass(context, self.pass, camera).unwrap();
+        self.minimap.set_camera(context, self.cameras.get_uniform(minimap_camera)).unwrap();
+    }
+
     fn toggle_wireframe(&mut self, context: &mut Context) {
         self.draw_wireframe = !self.draw_wireframe;
 
@@ -3,33 +3,20 @@ use cgmath::{Matrix4, SquareMatrix, Vector2};
 use winit::event::{ElementState, VirtualKeyCode};
 use vulkrap::engine::cvars::ConfigVariables;
 use vulkrap::engine::datatypes::{ViewProjectionUniform, WindowExtent};
-use vulkrap::engine::image::load_image;
+use vulkrap::engine::image::load_texture;
 use vulkrap::engine::mesh::{MeshHandle, PredefinedMesh};
 use vulkrap::engine::runtime::{ControlSignal, EngineParameters, VulkrapApplication};
 use vulkrap::engine::ui::widgets::TextRenderer;
-use vulkrap::renderer::context::Context;
-use vulkrap::renderer::types::{SWAPCHAIN_PASS, UniformHandle, UniformStage};
+use vulkrap::renderer::context::{Context, Frame};
+use vulkrap::renderer::types::{SamplerConfiguration, SWAPCHAIN_PASS, UniformHandle, UniformStage};
 use vulkrap::vulkrap_start;
 
 const WINDOW_TITLE: &str = "text sbo example";
 const WINDOW_WIDTH: u32 = 1500;
 const WINDOW_HEIGHT: u32 = 850;
 
-#[repr(C)]
-#[derive(Clone, Debug, Copy)]
-pub struct PushConstantType {
-    transform: Matrix4<f32>,
-    wobble: f32,
-}
-
-impl PushConstantType {
-    pub fn new(transform: Matrix4<f32>, wobble: f32) -> Self {
-        PushConstantType { transform, wobble }
-    }
-}
-
 struct TextSBO {
-    vp_uniform: UniformHandle,
+    vp_uniform: UniformHandle<ViewProjectionUniform>,
     text_renderer: TextRenderer,
 
     text_position: Vector2<u32>,
@@ -43,8 +30,8 @@ impl VulkrapApplication for TextSBO {
         self.text_renderer.set_position(self.text_position);
     }
 
-    fn draw(&mut self, context: &mut Context) {
-        self.text_renderer.draw(context, self.text.as_str());
+    fn draw(&mut self, frame: &mut Frame) {
+        self.text_renderer.draw(frame, self.text.as_str());
     }
 
     fn reconfigure(&mut self, _config: &ConfigVariables) {}
@@ -70,9 +57,8 @@ impl TextSBO {
     pub fn new(context: &mut Context, engine_params: EngineParameters) -> TextSBO {
         let mesh = *engine_params.mesh_manager.get_mesh(PredefinedMesh::TexturedQuad as MeshHandle);
 
-        let font_image = load_image(Path::new("./resources/textures/font.png"));
-        let font_texture = context.add_texture(font_image.width, font_image.height, &font_image.data);
-        let sampler = context.add_sampler();
+        let font_texture = load_texture(context, Path::new("./resources/textures/font.png"));
+        let sampler = context.add_sampler(SamplerConfiguration::default());
 
         let vp = create_view_projection_uniform(engine_params.window_extent);
         let vp_uniform = context.create_uniform_buffer::<ViewProjectionUniform>(UniformStage::Vertex);
@@ -1,33 +1,21 @@
 use std::path::Path;
-use cgmath::{Matrix4, SquareMatrix, Vector2, Vector4};
+use cgmath::{Matrix4, SquareMatrix, Vector2};
 use winit::event::{ElementState, VirtualKeyCode};
 use vulkrap::engine::cvars::ConfigVariables;
 use vulkrap::engine::datatypes::{ViewProjectionUniform, WindowExtent};
-use vulkrap::engine::image::load_image;
+use vulkrap::engine::image::load_texture;
 use vulkrap::engine::mesh::{MeshHandle, PredefinedMesh};
+use vulkrap::engine::math::color::Color;
 use vulkrap::engine::runtime::{ControlSignal, EngineParameters, VulkrapApplication};
 use vulkrap::engine::ui::widgets::{TextRenderer, TexturedQuadRenderer};
-use vulkrap::renderer::context::Context;
-use vulkrap::renderer::types::UniformStage;
+use vulkrap::renderer::context::{Context, Frame};
+use vulkrap::renderer::types::{SamplerConfiguration, UniformStage};
 use vulkrap::vulkrap_start;
 
 const WINDOW_TITLE: &str = "text sbo example offscreen";
 const WINDOW_WIDTH: u32 = 1500;
 const WINDOW_HEIGHT: u32 = 850;
 
-#[repr(C)]
-#[derive(Clone, Debug, Copy)]
-pub struct PushConstantType {
-    transform: Matrix4<f32>,
-    wobble: f32,
-}
-
-impl PushConstantType {
-    pub fn new(transform: Matrix4<f32>, wobble: f32) -> Self {
-        PushConstantType { transform, wobble }
-    }
-}
-
 struct TextSBO {
     texture_quad_renderer: TexturedQuadRenderer,
     text_renderer: TextRenderer,
@@ -43,9 +31,9 @@ impl VulkrapApplication for TextSBO {
         self.text_renderer.set_position(self.text_position);
     }
 
-    fn draw(&mut self, context: &mut Context) {
-        self.text_renderer.draw(context, self.text.as_str());
-        self.texture_quad_renderer.draw(context);
+    fn draw(&mut self, frame: &mut Frame) {
+        self.text_renderer.draw(frame, self.text.as_str());
+        self.texture_quad_renderer.draw(frame);
     }
 
     fn reconfigure(&mut self, _config: &ConfigVariables) {}
@@ -70,13 +58,12 @@ impl TextSBO {
     pub fn new(context: &mut Context, engine_params: EngineParameters) -> TextSBO {
 
         let render_texture = context.add_render_texture(384, 216);
-        let sampler = context.add_sampler();
+        let sampler = context.add_sampler(SamplerConfiguration::default());
         let pass = context.create_render_pass(render_texture, 1000).unwrap();
 
         let mesh = *engine_params.mesh_manager.get_mesh(PredefinedMesh::TexturedQuad as MeshHandle);
 
-        let font_image = load_image(Path::new("./resources/textures/font.png"));
-        let font_texture = context.add_texture(font_image.width, font_image.height, &font_image.data);
+        let font_texture = load_texture(context, Path::new("./resources/textures/font.png"));
         let vp = create_view_projection_uniform(WindowExtent::new(384, 216));
         let vp_uniform = context.create_uniform_buffer::<ViewProjectionUniform>(UniformStage::Vertex);
         context.set_buffer_object(vp_uniform, vp);
@@ -87,7 +74,7 @@ impl TextSBO {
         texture_quad_renderer.set(
             Vector2::new((engine_params.window_extent.width / 2) as f32, (engine_params.window_extent.height / 2) as f32),
             Vector2::new(engine_params.window_extent.width as f32, engine_params.window_extent.height as f32),
-            Vector4::new(1.0, 1.0, 1.0, 1.0));
+            Color::WHITE);
 
 
         TextSBO {
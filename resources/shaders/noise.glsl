@@ -0,0 +1,169 @@
+// Gradient noise matching engine::math::noise for the same seed, in [-1, 1].
+// Include with GL_GOOGLE_include_directive, build_shaders.sh passes resources/shaders as include directory:
+//
+//   #extension GL_GOOGLE_include_directive : require
+//   #include "noise.glsl"
+//
+//   float height = fbm_perlin2(position.xz, 1337u, 3, 4.0, 2.0, 0.5);
+
+#ifndef VULKRAP_NOISE_GLSL
+#define VULKRAP_NOISE_GLSL
+
+uint noise_hash(uint x) {
+    x ^= x >> 16;
+    x *= 0x7feb352du;
+    x ^= x >> 15;
+    x *= 0x846ca68bu;
+    return x ^ (x >> 16);
+}
+
+uint noise_hash2(uint seed, ivec2 p) {
+    return noise_hash(uint(p.x) + noise_hash(uint(p.y) + noise_hash(seed)));
+}
+
+uint noise_hash3(uint seed, ivec3 p) {
+    return noise_hash(uint(p.x) + noise_hash(uint(p.y) + noise_hash(uint(p.z) + noise_hash(seed))));
+}
+
+float noise_grad2(uint hash, vec2 p) {
+    switch (hash & 7u) {
+        case 0u: return p.x + p.y;
+        case 1u: return -p.x + p.y;
+        case 2u: return p.x - p.y;
+        case 3u: return -p.x - p.y;
+        case 4u: return p.x;
+        case 5u: return -p.x;
+        case 6u: return p.y;
+        default: return -p.y;
+    }
+}
+
+float noise_grad3(uint hash, vec3 p) {
+    uint h = hash & 15u;
+    float u = h < 8u ? p.x : p.y;
+    float v = h < 4u ? p.y : (h == 12u || h == 14u ? p.x : p.z);
+    return ((h & 1u) == 0u ? u : -u) + ((h & 2u) == 0u ? v : -v);
+}
+
+vec2 noise_fade(vec2 t) {
+    return t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+}
+
+vec3 noise_fade(vec3 t) {
+    return t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+}
+
+float perlin2(vec2 p, uint seed) {
+    vec2 p0 = floor(p);
+    ivec2 i = ivec2(p0);
+    vec2 f = p - p0;
+    vec2 u = noise_fade(f);
+
+    float n00 = noise_grad2(noise_hash2(seed, i), f);
+    float n10 = noise_grad2(noise_hash2(seed, i + ivec2(1, 0)), f - vec2(1.0, 0.0));
+    float n01 = noise_grad2(noise_hash2(seed, i + ivec2(0, 1)), f - vec2(0.0, 1.0));
+    float n11 = noise_grad2(noise_hash2(seed, i + ivec2(1, 1)), f - vec2(1.0, 1.0));
+
+    return mix(mix(n00, n10, u.x), mix(n01, n11, u.x), u.y);
+}
+
+float perlin3(vec3 p, uint seed) {
+    vec3 p0 = floor(p);
+    ivec3 i = ivec3(p0);
+    vec3 f = p - p0;
+    vec3 u = noise_fade(f);
+
+    float n000 = noise_grad3(noise_hash3(seed, i), f);
+    float n100 = noise_grad3(noise_hash3(seed, i + ivec3(1, 0, 0)), f - vec3(1.0, 0.0, 0.0));
+    float n010 = noise_grad3(noise_hash3(seed, i + ivec3(0, 1, 0)), f - vec3(0.0, 1.0, 0.0));
+    float n110 = noise_grad3(noise_hash3(seed, i + ivec3(1, 1, 0)), f - vec3(1.0, 1.0, 0.0));
+    float n001 = noise_grad3(noise_hash3(seed, i + ivec3(0, 0, 1)), f - vec3(0.0, 0.0, 1.0));
+    float n101 = noise_grad3(noise_hash3(seed, i + ivec3(1, 0, 1)), f - vec3(1.0, 0.0, 1.0));
+    float n011 = noise_grad3(noise_hash3(seed, i + ivec3(0, 1, 1)), f - vec3(0.0, 1.0, 1.0));
+    float n111 = noise_grad3(noise_hash3(seed, i + ivec3(1, 1, 1)), f - vec3(1.0, 1.0, 1.0));
+
+    float near = mix(mix(n000, n100, u.x), mix(n010, n110, u.x), u.y);
+    float far = mix(mix(n001, n101, u.x), mix(n011, n111, u.x), u.y);
+    return mix(near, far, u.z);
+}
+
+const float NOISE_F2 = 0.36602542;
+const float NOISE_G2 = 0.21132487;
+const float NOISE_F3 = 1.0 / 3.0;
+const float NOISE_G3 = 1.0 / 6.0;
+
+float simplex_corner2(uint seed, ivec2 i, vec2 d) {
+    float t = 0.5 - dot(d, d);
+    return t < 0.0 ? 0.0 : t * t * t * t * noise_grad2(noise_hash2(seed, i), d);
+}
+
+float simplex2(vec2 p, uint seed) {
+    float s = (p.x + p.y) * NOISE_F2;
+    vec2 i = floor(p + s);
+    float t = (i.x + i.y) * NOISE_G2;
+    vec2 d0 = p - (i - t);
+    ivec2 o1 = d0.x > d0.y ? ivec2(1, 0) : ivec2(0, 1);
+
+    ivec2 ii = ivec2(i);
+    return 70.0 * (simplex_corner2(seed, ii, d0)
+        + simplex_corner2(seed, ii + o1, d0 - vec2(o1) + NOISE_G2)
+        + simplex_corner2(seed, ii + ivec2(1, 1), d0 - 1.0 + 2.0 * NOISE_G2));
+}
+
+float simplex_corner3(uint seed, ivec3 i, vec3 d) {
+    float t = 0.6 - dot(d, d);
+    return t < 0.0 ? 0.0 : t * t * t * t * noise_grad3(noise_hash3(seed, i), d);
+}
+
+float simplex3(vec3 p, uint seed) {
+    float s = (p.x + p.y + p.z) * NOISE_F3;
+    vec3 i = floor(p + s);
+    float t = (i.x + i.y + i.z) * NOISE_G3;
+    vec3 d0 = p - (i - t);
+
+    ivec3 o1;
+    ivec3 o2;
+    if (d0.x >= d0.y) {
+        if (d0.y >= d0.z) {
+            o1 = ivec3(1, 0, 0); o2 = ivec3(1, 1, 0);
+        } else if (d0.x >= d0.z) {
+            o1 = ivec3(1, 0, 0); o2 = ivec3(1, 0, 1);
+        } else {
+            o1 = ivec3(0, 0, 1); o2 = ivec3(1, 0, 1);
+        }
+    } else if (d0.y < d0.z) {
+        o1 = ivec3(0, 0, 1); o2 = ivec3(0, 1, 1);
+    } else if (d0.x < d0.z) {
+        o1 = ivec3(0, 1, 0); o2 = ivec3(0, 1, 1);
+    } else {
+        o1 = ivec3(0, 1, 0); o2 = ivec3(1, 1, 0);
+    }
+
+    ivec3 ii = ivec3(i);
+    return 32.0 * (simplex_corner3(seed, ii, d0)
+        + simplex_corner3(seed, ii + o1, d0 - vec3(o1) + NOISE_G3)
+        + simplex_corner3(seed, ii + o2, d0 - vec3(o2) + 2.0 * NOISE_G3)
+        + simplex_corner3(seed, ii + ivec3(1, 1, 1), d0 - 1.0 + 3.0 * NOISE_G3));
+}
+
+// Fractal Brownian motion normalized by the sum of the amplitudes, see Fbm
+#define NOISE_FBM(NAME, NOISE, TYPE)                                                               \
+float NAME(TYPE p, uint seed, int octaves, float frequency, float lacunarity, float persistence) { \
+    float amplitude = 1.0;                                                                        \
+    float total = 0.0;                                                                            \
+    float total_amplitude = 0.0;                                                                  \
+    for (int octave = 0; octave < octaves; octave++) {                                            \
+        total += amplitude * NOISE(p * frequency, seed);                                          \
+        total_amplitude += amplitude;                                                             \
+        frequency *= lacunarity;                                                                  \
+        amplitude *= persistence;                                                                 \
+    }                                                                                             \
+    return total / total_amplitude;                                                               \
+}
+
+NOISE_FBM(fbm_perlin2, perlin2, vec2)
+NOISE_FBM(fbm_perlin3, perlin3, vec3)
+NOISE_FBM(fbm_simplex2, simplex2, vec2)
+NOISE_FBM(fbm_simplex3, simplex3, vec3)
+
+#endif
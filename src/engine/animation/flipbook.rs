@@ -0,0 +1,237 @@
+use cgmath::Vector4;
+
+// Grid of equally sized frames in one texture, numbered row by row from the top left
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpriteSheet {
+    columns: u32,
+    rows: u32,
+}
+
+impl SpriteSheet {
+    pub fn new(columns: u32, rows: u32) -> Self {
+        assert!(columns > 0 && rows > 0, "Sprite sheet must have at least one frame");
+        SpriteSheet { columns, rows }
+    }
+
+    pub fn frame_count(&self) -> u32 {
+        self.columns * self.rows
+    }
+
+    // Offset and size of the frame in texture coordinates, see BillboardRenderer::add_with_uv_rect
+    // and TexturedQuadRenderer::set_uv_rect
+    pub fn uv_rect(&self, frame: u32) -> Vector4<f32> {
+        let frame = frame % self.frame_count();
+        let width = 1.0 / self.columns as f32;
+        let height = 1.0 / self.rows as f32;
+
+        Vector4::new(
+            (frame % self.columns) as f32 * width,
+            (frame / self.columns) as f32 * height,
+            width,
+            height,
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlaybackMode {
+    // Stops on the last frame
+    Once,
+    Loop,
+    // Plays forward and backward
+    PingPong,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlipbookEvent {
+    // A Once animation reached its last frame
+    Finished,
+    // A Loop or PingPong animation is back on its first frame
+    Looped,
+}
+
+pub struct Flipbook {
+    sheet: SpriteSheet,
+    first_frame: u32,
+    frame_count: u32,
+    mode: PlaybackMode,
+    frame_duration_s: f32,
+
+    frame: u32,
+    reverse: bool,
+    time_s: f32,
+    playing: bool,
+}
+
+impl Flipbook {
+    pub fn new(sheet: SpriteSheet, fps: f32) -> Self {
+        Flipbook {
+            sheet,
+            first_frame: 0,
+            frame_count: sheet.frame_count(),
+            mode: PlaybackMode::Loop,
+            frame_duration_s: 1.0 / fps,
+
+            frame: 0,
+            reverse: false,
+            time_s: 0.0,
+            playing: true,
+        }
+    }
+
+    // Plays a range of the sheet, e.g. one row per animation
+    pub fn with_frames(mut self, first_frame: u32, frame_count: u32) -> Self {
+        assert!(frame_count > 0, "Flipbook must have at least one frame");
+        assert!(
+            first_frame + frame_count <= self.sheet.frame_count(),
+            "Flipbook frames outside of sprite sheet"
+        );
+        self.first_frame = first_frame;
+        self.frame_count = frame_count;
+        self.restart();
+
+        self
+    }
+
+    pub fn with_mode(mut self, mode: PlaybackMode) -> Self {
+        self.mode = mode;
+        self.restart();
+
+        self
+    }
+
+    pub fn set_fps(&mut self, fps: f32) {
+        self.frame_duration_s = 1.0 / fps;
+    }
+
+    pub fn play(&mut self) {
+        self.playing = true;
+    }
+
+    pub fn stop(&mut self) {
+        self.playing = false;
+    }
+
+    pub fn restart(&mut self) {
+        self.frame = 0;
+        self.reverse = false;
+        self.time_s = 0.0;
+        self.playing = true;
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    // Advances the animation, returns the last event if several frames were stepped
+    pub fn update(&mut self, dt_s: f32) -> Option<FlipbookEvent> {
+        if !self.playing {
+            return None;
+        }
+
+        let mut event = None;
+        self.time_s += dt_s;
+        while self.playing && self.time_s >= self.frame_duration_s {
+            self.time_s -= self.frame_duration_s;
+            if let Some(e) = self.step() {
+                event = Some(e);
+            }
+        }
+
+        event
+    }
+
+    // Frame in the sprite sheet
+    pub fn current_frame(&self) -> u32 {
+        self.first_frame + self.frame
+    }
+
+    pub fn uv_rect(&self) -> Vector4<f32> {
+        self.sheet.uv_rect(self.current_frame())
+    }
+
+    fn step(&mut self) -> Option<FlipbookEvent> {
+        let last = self.frame_count - 1;
+        match self.mode {
+            PlaybackMode::Once => {
+                if self.frame < last {
+                    self.frame += 1;
+                }
+                if self.frame == last {
+                    self.playing = false;
+                    return Some(FlipbookEvent::Finished);
+                }
+                None
+            }
+            PlaybackMode::Loop => {
+                self.frame = (self.frame + 1) % self.frame_count;
+                if self.frame == 0 {
+                    return Some(FlipbookEvent::Looped);
+                }
+                None
+            }
+            PlaybackMode::PingPong => {
+                if last == 0 {
+                    return Some(FlipbookEvent::Looped);
+                }
+                if self.reverse {
+                    self.frame -= 1;
+                } else {
+                    self.frame += 1;
+                }
+                if self.frame == last {
+                    self.reverse = true;
+                }
+                if self.frame == 0 {
+                    self.reverse = false;
+                    return Some(FlipbookEvent::Looped);
+                }
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames(flipbook: &mut Flipbook, steps: usize) -> Vec<(u32, Option<FlipbookEvent>)> {
+        (0..steps)
+            .map(|_| {
+                let event = flipbook.update(0.1);
+                (flipbook.current_frame(), event)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn playback_modes() {
+        let sheet = SpriteSheet::new(4, 2);
+        assert_eq!(sheet.uv_rect(5), Vector4::new(0.25, 0.5, 0.25, 0.5));
+
+        let mut once = Flipbook::new(sheet, 10.0).with_frames(4, 3).with_mode(PlaybackMode::Once);
+        assert_eq!(
+            frames(&mut once, 3),
+            vec![(5, None), (6, Some(FlipbookEvent::Finished)), (6, None)]
+        );
+        assert!(!once.is_playing());
+
+        let mut looping = Flipbook::new(sheet, 10.0).with_frames(0, 3);
+        assert_eq!(
+            frames(&mut looping, 3),
+            vec![(1, None), (2, None), (0, Some(FlipbookEvent::Looped))]
+        );
+
+        let mut ping_pong = Flipbook::new(sheet, 10.0).with_frames(0, 3).with_mode(PlaybackMode::PingPong);
+        assert_eq!(
+            frames(&mut ping_pong, 5),
+            vec![(1, None), (2, None), (1, None), (0, Some(FlipbookEvent::Looped)), (1, None)]
+        );
+
+        // Several frames in one update
+        let mut looping = Flipbook::new(sheet, 10.0);
+        assert_eq!(looping.update(0.85), Some(FlipbookEvent::Looped));
+        assert_eq!(looping.current_frame(), 0);
+    }
+}
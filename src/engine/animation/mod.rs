@@ -0,0 +1,2 @@
+pub mod flipbook;
+pub mod skeletal;
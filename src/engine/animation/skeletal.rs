@@ -0,0 +1,404 @@
+use cgmath::{Matrix4, One, Quaternion, Vector3, VectorSpace};
+
+use crate::engine::animation::flipbook::PlaybackMode;
+use crate::engine::datatypes::SkinnedVertex;
+use crate::renderer::context::Context;
+use crate::renderer::types::{BufferObjectHandle, SkinnedMeshHandle};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct JointPose {
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl JointPose {
+    pub fn new(translation: Vector3<f32>, rotation: Quaternion<f32>, scale: Vector3<f32>) -> Self {
+        JointPose {
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    pub fn identity() -> Self {
+        JointPose::new(Vector3::new(0.0, 0.0, 0.0), Quaternion::one(), Vector3::new(1.0, 1.0, 1.0))
+    }
+
+    pub fn blend(&self, other: &JointPose, f: f32) -> JointPose {
+        JointPose {
+            translation: self.translation.lerp(other.translation, f),
+            rotation: self.rotation.slerp(other.rotation, f),
+            scale: self.scale.lerp(other.scale, f),
+        }
+    }
+
+    pub fn matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+// Joint hierarchy, parents are added before their children
+#[derive(Clone, Debug, Default)]
+pub struct Skeleton {
+    parents: Vec<Option<usize>>,
+    rest_poses: Vec<JointPose>,
+    inverse_bind_matrices: Vec<Matrix4<f32>>,
+}
+
+impl Skeleton {
+    pub fn new() -> Self {
+        Skeleton::default()
+    }
+
+    // Joints are numbered in the order they are added
+    pub fn with_joint(mut self, parent: Option<usize>, rest_pose: JointPose, inverse_bind: Matrix4<f32>) -> Self {
+        assert!(parent.is_none_or(|parent| parent < self.parents.len()), "Parent joint must be added first");
+        self.parents.push(parent);
+        self.rest_poses.push(rest_pose);
+        self.inverse_bind_matrices.push(inverse_bind);
+
+        self
+    }
+
+    pub fn joint_count(&self) -> usize {
+        self.parents.len()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Keyframe {
+    pub time_s: f32,
+    pub pose: JointPose,
+}
+
+impl Keyframe {
+    pub fn new(time_s: f32, pose: JointPose) -> Self {
+        Keyframe { time_s, pose }
+    }
+}
+
+// Joints without a track keep their rest pose
+#[derive(Clone, Debug)]
+pub struct AnimationClip {
+    name: String,
+    duration_s: f32,
+    tracks: Vec<(usize, Vec<Keyframe>)>,
+    markers: Vec<(f32, String)>,
+}
+
+impl AnimationClip {
+    pub fn new(name: &str, duration_s: f32) -> Self {
+        assert!(duration_s > 0.0, "Animation clip must have a duration");
+        AnimationClip {
+            name: name.to_string(),
+            duration_s,
+            tracks: Vec::new(),
+            markers: Vec::new(),
+        }
+    }
+
+    // Keyframes sorted by time
+    pub fn with_track(mut self, joint: usize, keyframes: Vec<Keyframe>) -> Self {
+        assert!(!keyframes.is_empty(), "Track must have at least one keyframe");
+        assert!(
+            keyframes.windows(2).all(|pair| pair[0].time_s <= pair[1].time_s),
+            "Keyframes must be sorted by time"
+        );
+        self.tracks.push((joint, keyframes));
+
+        self
+    }
+
+    // Reported by AnimatedModel::update when playback passes the time, e.g. footsteps
+    pub fn with_marker(mut self, time_s: f32, name: &str) -> Self {
+        self.markers.push((time_s, name.to_string()));
+
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn duration_s(&self) -> f32 {
+        self.duration_s
+    }
+
+    pub fn sample(&self, joint: usize, time_s: f32) -> Option<JointPose> {
+        let (_, keyframes) = self.tracks.iter().find(|(track_joint, _)| *track_joint == joint)?;
+        let next = keyframes.iter().position(|keyframe| keyframe.time_s > time_s);
+
+        Some(match next {
+            Some(0) => keyframes[0].pose,
+            Some(next) => {
+                let (a, b) = (&keyframes[next - 1], &keyframes[next]);
+                a.pose.blend(&b.pose, (time_s - a.time_s) / (b.time_s - a.time_s))
+            }
+            None => keyframes.last().unwrap().pose,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AnimationEvent {
+    Marker { clip: String, marker: String },
+    // A Once clip reached its end
+    Finished(String),
+    // A Loop or PingPong clip is back at its start
+    Looped(String),
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Playback {
+    clip: usize,
+    mode: PlaybackMode,
+    time_s: f32,
+    reverse: bool,
+    playing: bool,
+}
+
+impl Playback {
+    fn new(clip: usize, mode: PlaybackMode) -> Self {
+        Playback {
+            clip,
+            mode,
+            time_s: 0.0,
+            reverse: false,
+            playing: true,
+        }
+    }
+
+    // Markers are passed in [from, to) when playing forward and in (to, from] when playing backward
+    fn advance(&mut self, clip: &AnimationClip, dt_s: f32, events: &mut Vec<AnimationEvent>) {
+        let mut remaining_s = dt_s;
+        while self.playing && remaining_s > 0.0 {
+            let from = self.time_s;
+            if self.reverse {
+                let step = remaining_s.min(from);
+                self.time_s = from - step;
+                remaining_s -= step;
+                _passed_markers(clip, |time| time > self.time_s && time <= from, events);
+                if self.time_s <= 0.0 {
+                    self.reverse = false;
+                    events.push(AnimationEvent::Looped(clip.name.clone()));
+                }
+            } else {
+                let step = remaining_s.min(clip.duration_s - from);
+                self.time_s = from + step;
+                remaining_s -= step;
+                _passed_markers(clip, |time| time >= from && time < self.time_s, events);
+                if self.time_s >= clip.duration_s {
+                    match self.mode {
+                        PlaybackMode::Once => {
+                            self.playing = false;
+                            events.push(AnimationEvent::Finished(clip.name.clone()));
+                        }
+                        PlaybackMode::Loop => {
+                            self.time_s = 0.0;
+                            events.push(AnimationEvent::Looped(clip.name.clone()));
+                        }
+                        PlaybackMode::PingPong => self.reverse = true,
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn _passed_markers(clip: &AnimationClip, passed: impl Fn(f32) -> bool, events: &mut Vec<AnimationEvent>) {
+    for (time_s, marker) in clip.markers.iter() {
+        if passed(*time_s) {
+            events.push(AnimationEvent::Marker {
+                clip: clip.name.clone(),
+                marker: marker.clone(),
+            });
+        }
+    }
+}
+
+// Skinned model playing clips of its skeleton. The joint matrices are written to a storage buffer, bind it with
+// PipelineConfiguration::with_storage_buffer_object in the skinning pipeline.
+pub struct AnimatedModel {
+    skeleton: Skeleton,
+    clips: Vec<AnimationClip>,
+    joint_buffer: BufferObjectHandle<Matrix4<f32>>,
+
+    current: Option<Playback>,
+    fading_out: Option<Playback>,
+    fade_duration_s: f32,
+    fade_time_s: f32,
+}
+
+impl AnimatedModel {
+    pub fn new(context: &mut Context, skeleton: Skeleton) -> Self {
+        let joint_buffer = context.create_storage_buffer::<Matrix4<f32>>(skeleton.joint_count().max(1));
+
+        AnimatedModel {
+            skeleton,
+            clips: Vec::new(),
+            joint_buffer,
+
+            current: None,
+            fading_out: None,
+            fade_duration_s: 0.0,
+            fade_time_s: 0.0,
+        }
+    }
+
+    pub fn with_clip(mut self, clip: AnimationClip) -> Self {
+        assert!(self.clip_index(clip.name()).is_none(), "Animation clip already added");
+        self.clips.push(clip);
+
+        self
+    }
+
+    pub fn joint_buffer(&self) -> BufferObjectHandle<Matrix4<f32>> {
+        self.joint_buffer
+    }
+
+    // Mesh skinned on the GPU with the joints of this model, see Context::add_skinned_mesh
+    pub fn add_skinned_mesh(&self, context: &mut Context, vertices: &[SkinnedVertex]) -> SkinnedMeshHandle {
+        context.add_skinned_mesh(vertices, self.joint_buffer)
+    }
+
+    pub fn play(&mut self, name: &str, mode: PlaybackMode) -> Result<(), &'static str> {
+        self.crossfade(name, mode, 0.0)
+    }
+
+    // Blends from the current clip to the new one over the duration
+    pub fn crossfade(&mut self, name: &str, mode: PlaybackMode, duration_s: f32) -> Result<(), &'static str> {
+        let clip = self.clip_index(name).ok_or("Unknown animation clip")?;
+        self.fading_out = if duration_s > 0.0 { self.current.take() } else { None };
+        self.current = Some(Playback::new(clip, mode));
+        self.fade_duration_s = duration_s;
+        self.fade_time_s = 0.0;
+
+        Ok(())
+    }
+
+    pub fn stop(&mut self) {
+        self.current = None;
+        self.fading_out = None;
+    }
+
+    // Name of the clip being played or faded to
+    pub fn current_clip(&self) -> Option<&str> {
+        self.current.map(|playback| self.clips[playback.clip].name())
+    }
+
+    // Advances playback, returns the events of the current clip in the order they happened
+    pub fn update(&mut self, dt_s: f32) -> Vec<AnimationEvent> {
+        let mut events = Vec::new();
+        if let Some(playback) = self.current.as_mut() {
+            playback.advance(&self.clips[playback.clip], dt_s, &mut events);
+        }
+        if let Some(playback) = self.fading_out.as_mut() {
+            playback.advance(&self.clips[playback.clip], dt_s, &mut Vec::new());
+            self.fade_time_s += dt_s;
+            if self.fade_time_s >= self.fade_duration_s {
+                self.fading_out = None;
+            }
+        }
+
+        events
+    }
+
+    pub fn local_poses(&self) -> Vec<JointPose> {
+        let current = self.poses(self.current);
+        match self.fading_out {
+            Some(fading_out) => {
+                let weight = self.fade_time_s / self.fade_duration_s;
+                self.poses(Some(fading_out))
+                    .iter()
+                    .zip(current.iter())
+                    .map(|(from, to)| from.blend(to, weight))
+                    .collect()
+            }
+            None => current,
+        }
+    }
+
+    // Model space transform of each joint times its inverse bind matrix
+    pub fn joint_matrices(&self) -> Vec<Matrix4<f32>> {
+        let mut globals: Vec<Matrix4<f32>> = Vec::with_capacity(self.skeleton.joint_count());
+        for (joint, pose) in self.local_poses().iter().enumerate() {
+            let global = match self.skeleton.parents[joint] {
+                Some(parent) => globals[parent] * pose.matrix(),
+                None => pose.matrix(),
+            };
+            globals.push(global);
+        }
+
+        globals
+            .iter()
+            .zip(self.skeleton.inverse_bind_matrices.iter())
+            .map(|(global, inverse_bind)| global * inverse_bind)
+            .collect()
+    }
+
+    // Call once per frame after update, before the skinned meshes are drawn
+    pub fn update_joint_buffer(&self, context: &mut Context) {
+        context.reset_buffer_object(self.joint_buffer);
+        for matrix in self.joint_matrices() {
+            context.push_to_buffer_object(self.joint_buffer, matrix);
+        }
+    }
+
+    fn poses(&self, playback: Option<Playback>) -> Vec<JointPose> {
+        (0..self.skeleton.joint_count())
+            .map(|joint| {
+                playback
+                    .and_then(|playback| self.clips[playback.clip].sample(joint, playback.time_s))
+                    .unwrap_or(self.skeleton.rest_poses[joint])
+            })
+            .collect()
+    }
+
+    fn clip_index(&self, name: &str) -> Option<usize> {
+        self.clips.iter().position(|clip| clip.name() == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(x: f32) -> JointPose {
+        JointPose::new(Vector3::new(x, 0.0, 0.0), Quaternion::one(), Vector3::new(1.0, 1.0, 1.0))
+    }
+
+    #[test]
+    fn clip_playback() {
+        let walk = AnimationClip::new("walk", 1.0)
+            .with_track(0, vec![Keyframe::new(0.0, at(0.0)), Keyframe::new(1.0, at(2.0))])
+            .with_marker(0.5, "step");
+        assert_eq!(walk.sample(0, 0.25), Some(at(0.5)));
+        assert_eq!(walk.sample(0, 3.0), Some(at(2.0)));
+        assert_eq!(walk.sample(1, 0.25), None);
+
+        let mut playback = Playback::new(0, PlaybackMode::Loop);
+        let mut events = Vec::new();
+        playback.advance(&walk, 1.75, &mut events);
+        let step = AnimationEvent::Marker {
+            clip: "walk".to_string(),
+            marker: "step".to_string(),
+        };
+        assert_eq!(events, vec![step.clone(), AnimationEvent::Looped("walk".to_string()), step.clone()]);
+        assert!((playback.time_s - 0.75).abs() < 1.0e-5);
+
+        let mut playback = Playback::new(0, PlaybackMode::PingPong);
+        events.clear();
+        playback.advance(&walk, 1.75, &mut events);
+        assert_eq!(events, vec![step.clone(), step]);
+        assert!(playback.reverse && (playback.time_s - 0.25).abs() < 1.0e-5);
+
+        let mut playback = Playback::new(0, PlaybackMode::Once);
+        events.clear();
+        playback.advance(&walk, 1.75, &mut events);
+        assert_eq!(events.last(), Some(&AnimationEvent::Finished("walk".to_string())));
+        assert!(!playback.playing && playback.time_s == 1.0);
+    }
+}
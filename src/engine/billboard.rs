@@ -0,0 +1,119 @@
+use cgmath::{Vector2, Vector3, Vector4};
+
+use crate::engine::datatypes::{InstancedBillboard, Mesh, TexturedVertex, ViewProjectionUniform, FULL_UV_RECT};
+use crate::engine::mesh::PredefinedMesh::TexturedQuad;
+use crate::engine::mesh::{MeshHandle, MeshManager};
+use crate::renderer::context::{Context, Frame};
+use crate::renderer::types::{
+    BufferObjectHandle, DrawCommand, PipelineConfiguration, PipelineHandle, RenderPassHandle, SamplerHandle,
+    TextureHandle, UniformHandle,
+};
+use crate::renderer::variant::{ShaderVariants, VariantSelection};
+
+const INITIAL_CAPACITY: usize = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BillboardMode {
+    // Always faces the camera, e.g. particles and pickups
+    Spherical,
+    // Only turns around the world up axis, e.g. enemies and trees standing on the ground
+    Cylindrical,
+}
+
+// Camera facing sprites of one texture. Sprites are added every frame and drawn instanced with alpha testing,
+// so they can be mixed with opaque geometry.
+pub struct BillboardRenderer {
+    storage_buffer: BufferObjectHandle<InstancedBillboard>,
+    spherical_pipeline: PipelineHandle,
+    cylindrical_pipeline: PipelineHandle,
+    mesh: Mesh,
+
+    spherical: Vec<InstancedBillboard>,
+    cylindrical: Vec<InstancedBillboard>,
+}
+
+impl BillboardRenderer {
+    pub fn new(
+        context: &mut Context,
+        mesh_manager: &MeshManager,
+        render_pass: RenderPassHandle,
+        camera_uniform: UniformHandle<ViewProjectionUniform>,
+        texture: TextureHandle,
+        sampler: SamplerHandle,
+    ) -> Self {
+        let mesh = *mesh_manager.get_mesh(TexturedQuad as MeshHandle);
+        let storage_buffer = context.create_storage_buffer::<InstancedBillboard>(INITIAL_CAPACITY);
+
+        let variants = ShaderVariants::new("./resources/shaders/billboard").with_flag("CYLINDRICAL");
+        let pipelines = context
+            .add_pipeline_variants::<TexturedVertex>(
+                render_pass,
+                variants,
+                PipelineConfiguration::builder()
+                    .with_vertex_uniform(0, camera_uniform)
+                    .add_texture(1, texture, sampler)
+                    .with_storage_buffer_object(2, storage_buffer),
+            )
+            .expect("Invalid pipeline configuration!");
+
+        BillboardRenderer {
+            storage_buffer,
+            spherical_pipeline: pipelines.select(&VariantSelection::new()).unwrap(),
+            cylindrical_pipeline: pipelines.select(&VariantSelection::new().with_flag("CYLINDRICAL")).unwrap(),
+            mesh,
+
+            spherical: Vec::new(),
+            cylindrical: Vec::new(),
+        }
+    }
+
+    // Size in world units, the sprite is centered on the position
+    pub fn add(&mut self, mode: BillboardMode, position: Vector3<f32>, size: Vector2<f32>, color: Vector4<f32>) {
+        self.add_with_uv_rect(mode, position, size, color, FULL_UV_RECT);
+    }
+
+    // Draws part of the texture, e.g. the current frame of a Flipbook
+    pub fn add_with_uv_rect(
+        &mut self,
+        mode: BillboardMode,
+        position: Vector3<f32>,
+        size: Vector2<f32>,
+        color: Vector4<f32>,
+        uv_rect: Vector4<f32>,
+    ) {
+        let billboard = InstancedBillboard::new(position, size, color, uv_rect);
+        match mode {
+            BillboardMode::Spherical => self.spherical.push(billboard),
+            BillboardMode::Cylindrical => self.cylindrical.push(billboard),
+        }
+    }
+
+    // Draws and clears the sprites added since the last draw. Both modes share the storage buffer,
+    // the cylindrical instances follow the spherical ones.
+    pub fn draw(&mut self, frame: &mut Frame) {
+        frame.reset_buffer_object(self.storage_buffer);
+
+        let spherical_count = self.spherical.len() as u32;
+        let cylindrical_count = self.cylindrical.len() as u32;
+        for billboard in self.spherical.drain(..).chain(self.cylindrical.drain(..)) {
+            frame.push_to_buffer_object(self.storage_buffer, billboard);
+        }
+
+        if spherical_count > 0 {
+            frame.add_draw_command(DrawCommand::new_buffered_instanced_nopush(
+                self.spherical_pipeline,
+                self.mesh,
+                spherical_count,
+                0,
+            ));
+        }
+        if cylindrical_count > 0 {
+            frame.add_draw_command(DrawCommand::new_buffered_instanced_nopush(
+                self.cylindrical_pipeline,
+                self.mesh,
+                cylindrical_count,
+                spherical_count,
+            ));
+        }
+    }
+}
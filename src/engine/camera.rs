@@ -1,28 +1,54 @@
-use crate::engine::cvars::{ConfigVariables, FOV, M_PITCH, M_SENSITIVITY, M_YAW};
-use crate::engine::datatypes::ViewProjectionUniform;
+use crate::engine::cvars::{ConfigVariables, FOV, M_PITCH, M_SENSITIVITY, M_YAW, R_ZFAR, R_ZNEAR};
+use crate::engine::datatypes::{StereoViewProjectionUniform, ViewProjectionUniform};
+use crate::engine::math::{Frustum, Ray};
 use crate::renderer::context::Context;
 use crate::renderer::types::BufferObjectHandle;
-use crate::renderer::types::{UniformHandle, UniformStage};
-use cgmath::{dot, Deg, Matrix4, Quaternion, Rad, Rotation3, Vector3};
+use crate::renderer::types::{RenderPassHandle, UniformHandle, UniformStage, Viewport};
+use cgmath::{dot, Deg, Matrix4, Quaternion, Rad, Rotation3, SquareMatrix, Vector2, Vector3, Vector4};
 
 const MOVE_SPEED: f32 = 25.0;
 
 const YAW_LIMIT: f32 = std::f32::consts::PI * 2.0;
 const PITCH_LIMIT: f32 = (std::f32::consts::PI / 2.0) - 0.05;
 
+// Orthographic cameras keep a finite far plane when r_zfar is infinite
+const ORTHOGRAPHIC_Z_FAR: f32 = 1000.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    // Vertical field of view from the fov cvar
+    Perspective,
+    // Size of the view volume in world units, e.g. for a top down minimap or a shadow map
+    Orthographic { width: f32, height: f32 },
+    // Pixel coordinates of the viewport without a view transform, e.g. for UI
+    Screen,
+}
+
 pub struct Camera {
     position: Vector3<f32>,
 
     pitch: f32,
     yaw: f32,
 
-    uniform: BufferObjectHandle,
+    uniform: BufferObjectHandle<ViewProjectionUniform>,
 
     sens_pitch: f32,
     sens_yaw: f32,
     sens_global: f32,
 
     fovy: f32,
+    z_near: f32,
+    // 0 = infinite
+    z_far: f32,
+    projection: Projection,
+    viewport: Option<Viewport>,
+    stereo: Option<(UniformHandle<StereoViewProjectionUniform>, f32)>,
+
+    // Of the last uniform update, for project and unproject
+    view_projection: Matrix4<f32>,
+    inverse_view_projection: Matrix4<f32>,
+    // x, y, width and height of the viewport in screen coordinates
+    screen_rect: Vector4<f32>,
 
     _flight_mode: bool,
 }
@@ -42,6 +68,15 @@ impl Camera {
             sens_global: 0.0,
 
             fovy: 60.0,
+            z_near: 0.1,
+            z_far: 1000.0,
+            projection: Projection::Perspective,
+            viewport: None,
+            stereo: None,
+
+            view_projection: Matrix4::identity(),
+            inverse_view_projection: Matrix4::identity(),
+            screen_rect: Vector4::new(0.0, 0.0, 1.0, 1.0),
 
             _flight_mode: true,
         };
@@ -50,23 +85,97 @@ impl Camera {
         cam
     }
 
-    pub fn get_uniform(&self) -> UniformHandle {
+    pub fn get_uniform(&self) -> UniformHandle<ViewProjectionUniform> {
         self.uniform
     }
 
+    // Each camera owns its uniform buffer, for split screen give every camera its own viewport and
+    // draw its pipelines with DrawCommand::with_viewport.
+    pub fn get_viewport(&self) -> Option<Viewport> {
+        self.viewport
+    }
+
+    pub fn set_viewport(&mut self, viewport: Option<Viewport>) {
+        self.viewport = viewport;
+    }
+
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection;
+    }
+
+    // Creates a second uniform with a view per eye for multiview passes, updated together with the regular one.
+    pub fn enable_stereo(&mut self, context: &mut Context, eye_separation: f32) -> UniformHandle<StereoViewProjectionUniform> {
+        let uniform = match self.stereo {
+            Some((uniform, _)) => uniform,
+            None => context.create_uniform_buffer::<StereoViewProjectionUniform>(UniformStage::Vertex),
+        };
+        self.stereo = Some((uniform, eye_separation));
+        self.update_uniform(context);
+
+        uniform
+    }
+
     pub fn reconfigure(&mut self, config: &ConfigVariables) {
         self.sens_pitch = config.get(M_PITCH).as_float();
         self.sens_yaw = config.get(M_YAW).as_float();
         self.sens_global = config.get(M_SENSITIVITY).as_float();
         self.fovy = config.get(FOV).as_float();
+        self.z_near = config.get(R_ZNEAR).as_float();
+        self.z_far = config.get(R_ZFAR).as_float();
     }
 
     pub fn update_uniform(&mut self, context: &mut Context) {
-        let data = ViewProjectionUniform {
-            view: self._get_view_matrix(),
-            proj: cgmath::perspective(Deg(self.fovy), context.get_aspect_ratio(), 0.1, 1000.0),
+        let aspect_ratio = match self.viewport {
+            Some(viewport) => viewport.aspect_ratio(),
+            None => context.get_aspect_ratio(),
         };
-        context.set_buffer_object(self.uniform, data);
+        let (view, proj) = match self.projection {
+            Projection::Perspective => (
+                self._get_view_matrix(),
+                _perspective(Deg(self.fovy), aspect_ratio, self.z_near, self.z_far),
+            ),
+            Projection::Orthographic { width, height } => {
+                let z_far = if self.z_far > self.z_near { self.z_far } else { ORTHOGRAPHIC_Z_FAR };
+                (
+                    self._get_view_matrix(),
+                    cgmath::ortho(-width / 2.0, width / 2.0, -height / 2.0, height / 2.0, self.z_near, z_far),
+                )
+            }
+            Projection::Screen => {
+                let (width, height) = match self.viewport {
+                    Some(viewport) => (viewport.width, viewport.height),
+                    None => context.get_framebuffer_extent(),
+                };
+                (
+                    Matrix4::identity(),
+                    cgmath::ortho(0.0, width as f32, 0.0, height as f32, -1.0, 1.0),
+                )
+            }
+        };
+        context.set_buffer_object(self.uniform, ViewProjectionUniform { view, proj });
+
+        let viewport = self.viewport.unwrap_or_else(|| context.get_viewport());
+        let (_, framebuffer_height) = context.get_framebuffer_extent();
+        self.view_projection = proj * view;
+        self.inverse_view_projection = self.view_projection.invert().unwrap_or_else(Matrix4::identity);
+        self.screen_rect = Vector4::new(
+            viewport.x as f32,
+            framebuffer_height.saturating_sub(viewport.y + viewport.height) as f32,
+            viewport.width as f32,
+            viewport.height as f32,
+        );
+
+        if let Some((uniform, eye_separation)) = self.stereo {
+            let eye_offset = Vector3::new(eye_separation / 2.0, 0.0, 0.0);
+            let data = StereoViewProjectionUniform {
+                view: [
+                    Matrix4::from_translation(eye_offset) * view,
+                    Matrix4::from_translation(-eye_offset) * view,
+                ],
+                proj: [proj, proj],
+            };
+            context.set_buffer_object(uniform, data);
+        }
     }
 
     pub fn update_yaw_pitch(&mut self, delta_yaw: f32, delta_pitch: f32) {
@@ -101,6 +210,41 @@ impl Camera {
         self.position += Quaternion::from_angle_y(Rad(self.yaw)) * (direction * MOVE_SPEED * delta_time_s);
     }
 
+    // Distance along the view direction, e.g. for DrawCommand::with_depth
+    pub fn view_depth(&self, position: Vector3<f32>) -> f32 {
+        -(self._get_view_matrix() * position.extend(1.0)).z
+    }
+
+    // Screen coordinates are pixels with the origin in the bottom left corner of the window, like Projection::Screen.
+    // The depth is -1 at the near and 1 at the far plane, None behind the camera. Uses the matrices of the last
+    // update_uniform.
+    pub fn project(&self, position: Vector3<f32>) -> Option<Vector3<f32>> {
+        _project(self.view_projection, self.screen_rect, position)
+    }
+
+    pub fn unproject(&self, screen_position: Vector2<f32>, depth: f32) -> Vector3<f32> {
+        _unproject(self.inverse_view_projection, self.screen_rect, screen_position, depth)
+    }
+
+    // From the near plane through the screen position, e.g. for picking under the mouse cursor
+    pub fn screen_ray(&self, screen_position: Vector2<f32>) -> Ray {
+        let near = self.unproject(screen_position, -1.0);
+        // Not the far plane, it may be infinitely far away
+        let further = self.unproject(screen_position, 0.0);
+
+        Ray::new(near, further - near)
+    }
+
+    // Of the last update_uniform, e.g. for Frame::cull_instances
+    pub fn view_projection(&self) -> Matrix4<f32> {
+        self.view_projection
+    }
+
+    // Of the last update_uniform, for culling with e.g. Bvh::query_frustum
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_matrix(self.view_projection)
+    }
+
     fn _get_view_matrix(&self) -> Matrix4<f32> {
         let cos_pitch = self.pitch.cos();
         let sin_pitch = self.pitch.sin();
@@ -131,3 +275,160 @@ impl Camera {
         )
     }
 }
+
+// Far planes not beyond the near plane, e.g. 0, are infinitely far away. Same depth range as cgmath::perspective.
+fn _perspective(fovy: Deg<f32>, aspect_ratio: f32, z_near: f32, z_far: f32) -> Matrix4<f32> {
+    if z_far > z_near {
+        return cgmath::perspective(fovy, aspect_ratio, z_near, z_far);
+    }
+
+    let f = 1.0 / (Rad::from(fovy).0 / 2.0).tan();
+    Matrix4::new(
+        f / aspect_ratio,
+        0.0,
+        0.0,
+        0.0,
+        0.0,
+        f,
+        0.0,
+        0.0,
+        0.0,
+        0.0,
+        -1.0,
+        -1.0,
+        0.0,
+        0.0,
+        -2.0 * z_near,
+        0.0,
+    )
+}
+
+fn _project(view_projection: Matrix4<f32>, screen_rect: Vector4<f32>, position: Vector3<f32>) -> Option<Vector3<f32>> {
+    let clip = view_projection * position.extend(1.0);
+    if clip.w <= f32::EPSILON {
+        return None;
+    }
+
+    let ndc = clip.truncate() / clip.w;
+    Some(Vector3::new(
+        screen_rect.x + (ndc.x + 1.0) / 2.0 * screen_rect.z,
+        screen_rect.y + (ndc.y + 1.0) / 2.0 * screen_rect.w,
+        ndc.z,
+    ))
+}
+
+fn _unproject(
+    inverse_view_projection: Matrix4<f32>,
+    screen_rect: Vector4<f32>,
+    screen_position: Vector2<f32>,
+    depth: f32,
+) -> Vector3<f32> {
+    let ndc = Vector4::new(
+        (screen_position.x - screen_rect.x) / screen_rect.z * 2.0 - 1.0,
+        (screen_position.y - screen_rect.y) / screen_rect.w * 2.0 - 1.0,
+        depth,
+        1.0,
+    );
+    let world = inverse_view_projection * ndc;
+
+    world.truncate() / world.w
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CameraHandle(usize);
+
+// Cameras of an application, e.g. main view, UI, minimap and shadow. Every camera owns its uniform buffer,
+// pipelines configured with_pass_camera bind the camera of their pass.
+#[derive(Default)]
+pub struct CameraManager {
+    cameras: Vec<Camera>,
+}
+
+impl CameraManager {
+    pub fn new() -> Self {
+        CameraManager { cameras: Vec::new() }
+    }
+
+    pub fn add(&mut self, camera: Camera) -> CameraHandle {
+        self.cameras.push(camera);
+
+        CameraHandle(self.cameras.len() - 1)
+    }
+
+    pub fn get(&self, handle: CameraHandle) -> &Camera {
+        &self.cameras[handle.0]
+    }
+
+    pub fn get_mut(&mut self, handle: CameraHandle) -> &mut Camera {
+        &mut self.cameras[handle.0]
+    }
+
+    pub fn get_uniform(&self, handle: CameraHandle) -> UniformHandle<ViewProjectionUniform> {
+        self.cameras[handle.0].get_uniform()
+    }
+
+    // Can be called again later to switch the camera of a pass
+    pub fn bind_pass(
+        &self,
+        context: &mut Context,
+        pass: RenderPassHandle,
+        handle: CameraHandle,
+    ) -> Result<(), &'static str> {
+        context.set_render_pass_camera(pass, self.get_uniform(handle))
+    }
+
+    pub fn reconfigure(&mut self, config: &ConfigVariables) {
+        for camera in self.cameras.iter_mut() {
+            camera.reconfigure(config);
+        }
+    }
+
+    pub fn update_uniforms(&mut self, context: &mut Context) {
+        for camera in self.cameras.iter_mut() {
+            camera.update_uniform(context);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{InnerSpace, Vector4};
+
+    #[test]
+    fn infinite_far_plane() {
+        let finite = _perspective(Deg(60.0), 1.5, 0.1, 1.0e7);
+        let infinite = _perspective(Deg(60.0), 1.5, 0.1, 0.0);
+        for column in 0..4 {
+            assert!((finite[column] - infinite[column]).magnitude() < 1.0e-4, "{:?}", infinite);
+        }
+
+        // Points at the near plane and very far away end up at both ends of the depth range
+        let depth = |distance: f32| {
+            let clip = infinite * Vector4::new(0.0, 0.0, -distance, 1.0);
+            clip.z / clip.w
+        };
+        assert!((depth(0.1) + 1.0).abs() < 1.0e-5);
+        assert!(depth(1.0e6) < 1.0 && depth(1.0e6) > 0.999);
+    }
+
+    #[test]
+    fn project_and_unproject() {
+        // Looking down -z from z = 5 into the top half of an 800x600 window
+        let view = Matrix4::from_translation(Vector3::new(0.0, 0.0, -5.0));
+        let view_projection = _perspective(Deg(90.0), 800.0 / 300.0, 0.1, 100.0) * view;
+        let inverse = view_projection.invert().unwrap();
+        let screen_rect = Vector4::new(0.0, 300.0, 800.0, 300.0);
+
+        let center = _project(view_projection, screen_rect, Vector3::new(0.0, 0.0, 0.0)).unwrap();
+        assert!((center.truncate() - Vector2::new(400.0, 450.0)).magnitude() < 1.0e-3, "{:?}", center);
+        let up = _project(view_projection, screen_rect, Vector3::new(0.0, 5.0, 0.0)).unwrap();
+        assert!((up.truncate() - Vector2::new(400.0, 600.0)).magnitude() < 1.0e-3, "{:?}", up);
+        assert_eq!(_project(view_projection, screen_rect, Vector3::new(0.0, 0.0, 10.0)), None);
+
+        let position = Vector3::new(1.5, -0.5, -3.0);
+        let screen = _project(view_projection, screen_rect, position).unwrap();
+        let unprojected = _unproject(inverse, screen_rect, screen.truncate(), screen.z);
+        assert!((unprojected - position).magnitude() < 1.0e-3, "{:?}", unprojected);
+    }
+}
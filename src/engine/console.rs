@@ -1,6 +1,17 @@
-use crate::engine::console::Command::{Quit, Unknown};
-use crate::engine::cvars::{ConfigVariables, CvarType};
+use crate::engine::console::Command::{
+    Clear, DumpFrame, DumpGraph, Find, Invalid, ListModes, Pause, Playback, Quit, Record, Step, StopRecord, SystemInfo,
+    Timedemo, Unknown, Unwatch, Watch,
+};
+use crate::engine::cvars::{ConfigVariables, CvarType, CON_BACKGROUND_COLOR, CON_REPEAT_DELAY, CON_REPEAT_RATE};
+use crate::engine::math::color::Color;
+use crate::engine::ui::draw::wrap_text;
 use crate::log::logger;
+use crate::log::logger::MessageLevel;
+use crate::error::VulkrapError;
+use crate::util::file;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
 use winit::event::{ElementState, VirtualKeyCode};
 use crate::engine::runtime::ControlSignal;
 use crate::log::logger::cvar;
@@ -14,18 +25,29 @@ pub struct Console {
     input_history: Vec<String>,
     input_history_index: usize,
 
+    // In wrapped lines
     scroll: usize,
+    history_filter: Option<String>,
+    // Messages are wrapped to this many characters, 0 = not wrapped
+    history_columns: usize,
 
     active: bool,
     input_buffer: Vec<char>,
     input_index: u32,
 
     current_draw_offset: f32,
+    background_color: Color,
 
     shift_active: bool,
 
     caret_visible: bool,
     caret_delta: f32,
+
+    key_repeat: KeyRepeat,
+    // Cvars shown by the text overlay, in the order they were added
+    watches: Vec<u32>,
+
+    application_command: Option<String>,
 }
 
 impl Default for Console {
@@ -43,15 +65,23 @@ impl Console {
             input_history_index: 0,
 
             scroll: 0,
+            history_filter: None,
+            history_columns: 0,
 
             active: false,
             input_buffer: Vec::new(),
             input_index: 0,
             current_draw_offset: 1.0,
+            background_color: Color::BLACK,
 
             caret_visible: false,
             caret_delta: 0.0,
             shift_active: false,
+
+            key_repeat: KeyRepeat::new(),
+            watches: Vec::new(),
+
+            application_command: None,
         }
     }
 
@@ -61,22 +91,25 @@ impl Console {
         key: VirtualKeyCode,
         state: ElementState,
     ) -> ControlSignal {
+        // The OS repeats held keys as well, those presses are dropped and update repeats at the rate of the cvars
+        if state == ElementState::Pressed && _is_repeatable(key, self.shift_active) {
+            if !self.key_repeat.press(key) {
+                return ControlSignal::None;
+            }
+        } else if state == ElementState::Released {
+            self.key_repeat.release(key);
+        }
+
         let char_inut = crate::window::winit::map_input_to_chr(key, state, self.shift_active);
 
         if let Some(x) = char_inut {
-            self.input_buffer.push(x);
-            self.input_index += 1;
-            self._reset_caret();
+            self._type_character(x);
         }
 
         let mut control = ControlSignal::None;
         match (key, state) {
             (VirtualKeyCode::Back, ElementState::Pressed) => {
-                if self.input_index > 0 {
-                    self.input_buffer.pop();
-                    self.input_index -= 1;
-                    self._reset_caret();
-                }
+                self._backspace();
             }
             (VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter, ElementState::Pressed) => {
                 control = self._handle_input(cfg);
@@ -113,7 +146,7 @@ impl Console {
         control
     }
 
-    pub fn update(&mut self, delta_time_s: f32) {
+    pub fn update(&mut self, cfg: &ConfigVariables, delta_time_s: f32) {
         if self.active && self.current_draw_offset > 0.0 {
             self.current_draw_offset -= TOGGLE_SPEED * delta_time_s;
 
@@ -123,8 +156,17 @@ impl Console {
         } else if !self.active && self.current_draw_offset <= 1.0 {
             self.current_draw_offset += TOGGLE_SPEED * delta_time_s;
         }
+        self.background_color = cfg.get(CON_BACKGROUND_COLOR).as_color();
 
         if self.active {
+            let delay_s = cfg.get(CON_REPEAT_DELAY).as_float() / 1000.0;
+            let rate = cfg.get(CON_REPEAT_RATE).as_float();
+            if let Some((key, count)) = self.key_repeat.update(delta_time_s, delay_s, rate) {
+                for _ in 0..count {
+                    self._repeat_key(key);
+                }
+            }
+
             self.caret_delta += CARET_BLINK_SPEED * delta_time_s;
             if self.caret_delta >= 1.0 {
                 self.caret_visible = !self.caret_visible;
@@ -141,6 +183,10 @@ impl Console {
         self.current_draw_offset
     }
 
+    pub fn get_background_color(&self) -> Color {
+        self.background_color
+    }
+
     pub fn is_caret_visible(&self) -> bool {
         self.caret_visible
     }
@@ -152,6 +198,7 @@ impl Console {
     pub fn toggle(&mut self) {
         self.active = !self.active;
         self.scroll = 0;
+        self.key_repeat = KeyRepeat::new();
     }
 
     pub fn is_active(&self) -> bool {
@@ -166,11 +213,90 @@ impl Console {
         self.scroll
     }
 
+    pub fn get_history_filter(&self) -> Option<&str> {
+        self.history_filter.as_deref()
+    }
+
+    pub fn set_history_columns(&mut self, history_columns: usize) {
+        self.history_columns = history_columns;
+    }
+
+    // The last line_count wrapped lines of the history above the scroll position, oldest first
+    pub fn get_history_lines(&self, line_count: usize) -> Vec<HistoryLine> {
+        let log = logger::get();
+        let messages = log.get_history(usize::MAX, 0, self.get_history_filter());
+        let newest_first = messages.iter().rev().map(|message| (message.level, message.get_text()));
+
+        _wrap_history(newest_first, self.history_columns, self.scroll, line_count)
+    }
+
+    pub fn get_watch_lines(&self, cfg: &ConfigVariables) -> Vec<String> {
+        self.watches
+            .iter()
+            .map(|cvar| format!("{} = {}", cfg.get_name(*cvar), cfg.get(*cvar).as_str()))
+            .collect()
+    }
+
+    // Keeps the last max_entries inputs, like a shell history
+    pub fn save_history(&self, path: &Path, max_entries: usize) -> Result<(), &'static str> {
+        // While browsing, the input being edited is the last entry
+        let browsing = if self.input_history_index > 0 { 1 } else { 0 };
+        let history = &self.input_history[..self.input_history.len() - browsing];
+
+        let mut file = File::create(path).map_err(|_| "Failed to create file")?;
+        for input in history[history.len().saturating_sub(max_entries)..].iter() {
+            writeln!(file, "{}", input).map_err(|_| "Failed to write file")?;
+        }
+
+        Ok(())
+    }
+
+    pub fn load_history(&mut self, path: &Path, max_entries: usize) -> Result<(), VulkrapError> {
+        let history: Vec<String> = file::read_lines(path)?
+            .map_while(Result::ok)
+            .filter(|input| !input.is_empty())
+            .collect();
+
+        self.input_history = history[history.len().saturating_sub(max_entries)..].to_vec();
+        self.input_history_index = 0;
+
+        Ok(())
+    }
+
+    // Input that was neither a cvar nor an engine command, handed to the application by the runtime
+    pub fn take_application_command(&mut self) -> Option<String> {
+        self.application_command.take()
+    }
+
     fn _reset_caret(&mut self) {
         self.caret_visible = true;
         self.caret_delta = 0.0;
     }
 
+    fn _type_character(&mut self, character: char) {
+        self.input_buffer.push(character);
+        self.input_index += 1;
+        self._reset_caret();
+    }
+
+    fn _backspace(&mut self) {
+        if self.input_index > 0 {
+            self.input_buffer.pop();
+            self.input_index -= 1;
+            self._reset_caret();
+        }
+    }
+
+    fn _repeat_key(&mut self, key: VirtualKeyCode) {
+        if key == VirtualKeyCode::Back {
+            self._backspace();
+        } else if let Some(character) =
+            crate::window::winit::map_input_to_chr(key, ElementState::Pressed, self.shift_active)
+        {
+            self._type_character(character);
+        }
+    }
+
     fn _handle_input(&mut self, cfg: &mut ConfigVariables) -> ControlSignal {
         self.scroll = 0;
         let mut control = ControlSignal::None;
@@ -193,14 +319,59 @@ impl Console {
             };
             control = _handle_input_cvar(cfg, cvar, cvar_argument);
         } else {
-            let command = _parse_input_command(split[0]);
+            let command = _parse_input_command(&split);
             match command {
                 Unknown => {
-                    log_error!("unknown command or cvar: {}", self.get_current_input());
+                    self.application_command = Some(input.clone());
+                    control = ControlSignal::ApplicationCommand;
                 }
+                Invalid => {}
                 Quit => {
                     control = ControlSignal::Quit;
                 }
+                Timedemo(duration_s) => {
+                    control = ControlSignal::Timedemo(duration_s);
+                }
+                Record => {
+                    control = ControlSignal::StartRecording;
+                }
+                StopRecord => {
+                    control = ControlSignal::StopRecording;
+                }
+                Playback => {
+                    control = ControlSignal::StartPlayback;
+                }
+                Find(filter) => {
+                    self.history_filter = filter;
+                }
+                Clear => {
+                    logger::clear();
+                    self.history_filter = None;
+                }
+                SystemInfo => {
+                    control = ControlSignal::PrintSystemInfo;
+                }
+                ListModes => {
+                    control = ControlSignal::ListVideoModes;
+                }
+                DumpGraph => {
+                    control = ControlSignal::DumpGraph;
+                }
+                DumpFrame => {
+                    control = ControlSignal::DumpFrame;
+                }
+                Watch(name) => {
+                    self._watch(cfg, &name);
+                }
+                Unwatch(name) => {
+                    self._unwatch(cfg, name.as_deref());
+                }
+                Pause => {
+                    control = ControlSignal::TogglePause;
+                }
+                Step(frames) => {
+                    control = ControlSignal::Step(frames);
+                }
             }
         }
 
@@ -216,6 +387,25 @@ impl Console {
         control
     }
 
+    fn _watch(&mut self, cfg: &ConfigVariables, name: &str) {
+        match cfg.get_cvar_id_from_str(name) {
+            Some(cvar) if !self.watches.contains(&cvar) => self.watches.push(cvar),
+            Some(_) => {}
+            None => log_error!("watch: unknown cvar: {}", name),
+        }
+    }
+
+    // Without a name all watches are removed
+    fn _unwatch(&mut self, cfg: &ConfigVariables, name: Option<&str>) {
+        match name {
+            Some(name) => match cfg.get_cvar_id_from_str(name) {
+                Some(cvar) if self.watches.contains(&cvar) => self.watches.retain(|watch| *watch != cvar),
+                _ => log_error!("unwatch: not watched: {}", name),
+            },
+            None => self.watches.clear(),
+        }
+    }
+
     fn _clear_input_buffer(&mut self) {
         self.input_buffer.clear();
         self.input_index = 0;
@@ -256,7 +446,11 @@ impl Console {
     }
 
     fn _scroll_up(&mut self) {
-        let history_length = logger::len();
+        let history_length: usize = logger::get()
+            .get_history(usize::MAX, 0, self.get_history_filter())
+            .iter()
+            .map(|message| wrap_text(&message.get_text(), self.history_columns).len())
+            .sum();
         self.scroll += SCROLL_LINES;
 
         if self.scroll > history_length {
@@ -273,6 +467,95 @@ impl Console {
     }
 }
 
+// A line of the console history, messages longer than the console continue on the lines after their first one
+pub struct HistoryLine {
+    pub level: MessageLevel,
+    pub text: String,
+    pub is_continuation: bool,
+}
+
+// Wraps messages until there are enough lines to skip the scrolled ones
+fn _wrap_history(
+    newest_first: impl Iterator<Item = (MessageLevel, String)>,
+    columns: usize,
+    scroll: usize,
+    line_count: usize,
+) -> Vec<HistoryLine> {
+    let mut lines = Vec::new();
+    for (level, text) in newest_first {
+        if lines.len() >= scroll + line_count {
+            break;
+        }
+        for (i, line) in wrap_text(&text, columns).into_iter().enumerate().rev() {
+            lines.push(HistoryLine {
+                level,
+                text: line.to_string(),
+                is_continuation: i > 0,
+            });
+        }
+    }
+
+    let mut history_lines: Vec<HistoryLine> = lines.into_iter().skip(scroll).take(line_count).collect();
+    history_lines.reverse();
+
+    history_lines
+}
+
+// Backspace and the keys typing a character
+fn _is_repeatable(key: VirtualKeyCode, shift_active: bool) -> bool {
+    key == VirtualKeyCode::Back
+        || crate::window::winit::map_input_to_chr(key, ElementState::Pressed, shift_active).is_some()
+}
+
+// The last repeatable key pressed, repeated while it's held
+struct KeyRepeat {
+    key: Option<VirtualKeyCode>,
+    held_s: f32,
+    repeat_count: u32,
+}
+
+impl KeyRepeat {
+    fn new() -> Self {
+        KeyRepeat {
+            key: None,
+            held_s: 0.0,
+            repeat_count: 0,
+        }
+    }
+
+    // Returns false if the key is already held
+    fn press(&mut self, key: VirtualKeyCode) -> bool {
+        if self.key == Some(key) {
+            return false;
+        }
+
+        *self = KeyRepeat::new();
+        self.key = Some(key);
+        true
+    }
+
+    fn release(&mut self, key: VirtualKeyCode) {
+        if self.key == Some(key) {
+            self.key = None;
+        }
+    }
+
+    // The held key and the number of repeats that came due since the last update
+    fn update(&mut self, delta_time_s: f32, delay_s: f32, rate: f32) -> Option<(VirtualKeyCode, u32)> {
+        let key = self.key?;
+        self.held_s += delta_time_s;
+        if rate <= 0.0 || self.held_s < delay_s {
+            return None;
+        }
+
+        let due = ((self.held_s - delay_s) * rate) as u32 + 1;
+        let count = due - self.repeat_count;
+        self.repeat_count = due;
+
+        (count > 0).then_some((key, count))
+    }
+}
+
 fn _handle_input_cvar(cfg: &mut ConfigVariables, cvar_id: u32, arg_opt: Option<&str>) -> ControlSignal {
     let mut ret = ControlSignal::None;
     if let Some(arg) = arg_opt {
@@ -294,6 +577,12 @@ fn _handle_input_cvar(cfg: &mut ConfigVariables, cvar_id: u32, arg_opt: Option<&
                     parsed = true;
                 }
             }
+            CvarType::Color => {
+                if let Ok(color) = Color::from_hex(arg) {
+                    cfg.set(cvar_id, color);
+                    parsed = true;
+                }
+            }
             CvarType::String => {
                 parsed = true;
                 let arg_split: Vec<&str> = arg.split('"').collect();
@@ -318,19 +607,212 @@ fn _handle_input_cvar(cfg: &mut ConfigVariables, cvar_id: u32, arg_opt: Option<&
     ret
 }
 
-fn _parse_input_command(command: &str) -> Command {
-    let command_string = command.to_lowercase();
+fn _parse_input_command(split: &[&str]) -> Command {
+    let command_string = split[0].trim_start_matches('/').to_lowercase();
 
     let command = match command_string.as_str() {
         "exit" => Quit,
         "quit" => Quit,
+        "timedemo" => _parse_timedemo_command(split),
+        "record" => Record,
+        "stoprecord" => StopRecord,
+        "playback" => Playback,
+        "find" => _parse_find_command(split),
+        "clear" => Clear,
+        "sysinfo" | "gpuinfo" => SystemInfo,
+        "listmodes" => ListModes,
+        "r_dumpgraph" => DumpGraph,
+        "dumpframe" => DumpFrame,
+        "watch" => _parse_watch_command(split),
+        "unwatch" => Unwatch(split.get(1).map(|name| name.to_string())),
+        "pause" => Pause,
+        "step" => _parse_step_command(split),
         _ => Unknown,
     };
 
     command
 }
 
+fn _parse_timedemo_command(split: &[&str]) -> Command {
+    if let Some(Ok(duration_s)) = split.get(1).map(|arg| arg.parse::<u32>()) {
+        if duration_s > 0 {
+            return Timedemo(duration_s);
+        }
+    }
+    log_error!("usage: timedemo <seconds>");
+
+    Invalid
+}
+
+// Steps a single frame without an argument
+fn _parse_step_command(split: &[&str]) -> Command {
+    match split.get(1).map(|arg| arg.parse::<u32>()) {
+        None => Step(1),
+        Some(Ok(frames)) if frames > 0 => Step(frames),
+        _ => {
+            log_error!("usage: step [frames]");
+            Invalid
+        }
+    }
+}
+
+fn _parse_watch_command(split: &[&str]) -> Command {
+    match split.get(1) {
+        Some(name) => Watch(name.to_string()),
+        None => {
+            log_error!("usage: watch <cvar>");
+            Invalid
+        }
+    }
+}
+
+// Filters the console history, find without an argument shows all lines again
+fn _parse_find_command(split: &[&str]) -> Command {
+    let filter = split[1..].join(" ");
+
+    if filter.is_empty() {
+        Find(None)
+    } else {
+        Find(Some(filter))
+    }
+}
+
 enum Command {
     Unknown,
+    Invalid,
     Quit,
+    Timedemo(u32),
+    Record,
+    StopRecord,
+    Playback,
+    Find(Option<String>),
+    Clear,
+    SystemInfo,
+    ListModes,
+    DumpGraph,
+    DumpFrame,
+    Watch(String),
+    Unwatch(Option<String>),
+    Pause,
+    Step(u32),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::cvars::FOV;
+
+    #[test]
+    fn key_repeat() {
+        let mut key_repeat = KeyRepeat::new();
+        assert_eq!(key_repeat.update(1.0, 0.5, 10.0), None);
+
+        assert!(key_repeat.press(VirtualKeyCode::Back));
+        assert!(!key_repeat.press(VirtualKeyCode::Back));
+        assert_eq!(key_repeat.update(0.4, 0.5, 10.0), None);
+        assert_eq!(key_repeat.update(0.1, 0.5, 10.0), Some((VirtualKeyCode::Back, 1)));
+        assert_eq!(key_repeat.update(0.05, 0.5, 10.0), None);
+        assert_eq!(key_repeat.update(0.26, 0.5, 10.0), Some((VirtualKeyCode::Back, 3)));
+        assert_eq!(key_repeat.update(1.0, 0.5, 0.0), None);
+
+        // Another key takes over, releasing the first one no longer stops it
+        assert!(key_repeat.press(VirtualKeyCode::A));
+        key_repeat.release(VirtualKeyCode::Back);
+        assert_eq!(key_repeat.update(0.5, 0.5, 10.0), Some((VirtualKeyCode::A, 1)));
+        key_repeat.release(VirtualKeyCode::A);
+        assert_eq!(key_repeat.update(1.0, 0.5, 10.0), None);
+    }
+
+    #[test]
+    fn watches() {
+        let mut cfg = ConfigVariables::new();
+        let mut console = Console::new();
+        let input = |console: &mut Console, cfg: &mut ConfigVariables, line: &str| {
+            console.input_buffer = line.chars().collect();
+            console._handle_input(cfg);
+        };
+
+        input(&mut console, &mut cfg, "watch fov");
+        input(&mut console, &mut cfg, "watch r_znear");
+        input(&mut console, &mut cfg, "watch fov");
+        input(&mut console, &mut cfg, "watch no_such_cvar");
+        cfg.set(FOV, 90f32);
+        assert_eq!(console.get_watch_lines(&cfg), vec!["fov = 90".to_string(), "r_znear = 0.1".to_string()]);
+
+        input(&mut console, &mut cfg, "unwatch fov");
+        assert_eq!(console.get_watch_lines(&cfg), vec!["r_znear = 0.1".to_string()]);
+        input(&mut console, &mut cfg, "watch fov");
+        input(&mut console, &mut cfg, "unwatch");
+        assert!(console.get_watch_lines(&cfg).is_empty());
+    }
+
+    #[test]
+    fn color_cvar() {
+        let mut cfg = ConfigVariables::new();
+        let mut console = Console::new();
+        for line in ["con_background_color #ff000080", "con_background_color red"].iter() {
+            console.input_buffer = line.chars().collect();
+            console._handle_input(&mut cfg);
+        }
+
+        console.update(&cfg, 0.0);
+        assert_eq!(console.get_background_color(), Color::srgb8(255, 0, 0, 128));
+        assert_eq!(cfg.get(CON_BACKGROUND_COLOR).as_str(), "#ff000080");
+        assert!(!cfg.get_numeric_ids().contains(&CON_BACKGROUND_COLOR));
+    }
+
+    #[test]
+    fn saved_history() {
+        let path = std::env::temp_dir().join(format!("vulkrap_console_history_{}.txt", std::process::id()));
+        let mut console = Console::new();
+        console.input_history = vec!["fov 90".to_string(), "sysinfo".to_string(), "find error".to_string()];
+        console._handle_up();
+        console.save_history(&path, 2).unwrap();
+
+        let mut loaded = Console::new();
+        loaded.load_history(&path, 10).unwrap();
+        assert_eq!(loaded.input_history, vec!["sysinfo".to_string(), "find error".to_string()]);
+        loaded.load_history(&path, 1).unwrap();
+        assert_eq!(loaded.input_history, vec!["find error".to_string()]);
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(loaded.load_history(&path, 10).is_err());
+    }
+
+    #[test]
+    fn wrapped_history() {
+        let messages = || {
+            vec![
+                (MessageLevel::Info, "newest".to_string()),
+                (MessageLevel::Cvar, "fov 60 vertical field of view".to_string()),
+                (MessageLevel::Input, "oldest".to_string()),
+            ]
+            .into_iter()
+        };
+        let lines = |scroll: usize, line_count: usize| -> Vec<(String, bool)> {
+            _wrap_history(messages(), 12, scroll, line_count)
+                .into_iter()
+                .map(|line| (line.text, line.is_continuation))
+                .collect()
+        };
+
+        assert_eq!(
+            lines(0, 3),
+            vec![
+                ("field of".to_string(), true),
+                ("view".to_string(), true),
+                ("newest".to_string(), false),
+            ]
+        );
+        assert_eq!(
+            lines(2, 3),
+            vec![
+                ("fov 60".to_string(), false),
+                ("vertical".to_string(), true),
+                ("field of".to_string(), true),
+            ]
+        );
+        assert_eq!(lines(5, 10), vec![("oldest".to_string(), false)]);
+        assert!(lines(6, 10).is_empty());
+    }
 }
@@ -1,10 +1,46 @@
 use std::collections::HashMap;
+use crate::engine::math::color::Color;
 use crate::engine::runtime::ControlSignal;
+use crate::log::logger;
+use crate::renderer::types::{DEFAULT_FRAMES_IN_FLIGHT, MAX_FRAMES_IN_FLIGHT};
 use crate::{MIN_HEIGHT, MIN_WIDTH};
 
 pub const WINDOW_TITLE: u32 = 100;
 pub const WINDOW_WIDTH: u32 = 101;
 pub const WINDOW_HEIGHT: u32 = 102;
+pub const WINDOW_FULLSCREEN: u32 = 103;
+pub const WINDOW_MONITOR: u32 = 104;
+pub const WINDOW_REFRESH_RATE: u32 = 105;
+
+pub const FULLSCREEN_WINDOWED: u32 = 0;
+pub const FULLSCREEN_EXCLUSIVE: u32 = 1;
+pub const FULLSCREEN_BORDERLESS: u32 = 2;
+
+pub const DEMO_FILE: u32 = 200;
+pub const TIMESCALE: u32 = 201;
+pub const RNG_SEED: u32 = 202;
+
+pub const LOG_CAPACITY: u32 = 300;
+pub const STATS_SPIKE_THRESHOLD: u32 = 301;
+
+pub const CON_REPEAT_DELAY: u32 = 350;
+pub const CON_REPEAT_RATE: u32 = 351;
+pub const CON_HISTORY_FILE: u32 = 352;
+pub const CON_HISTORY_SIZE: u32 = 353;
+pub const CON_BACKGROUND_COLOR: u32 = 354;
+
+pub const SCRIPT_AUTOEXEC: u32 = 360;
+
+pub const R_RENDERSCALE: u32 = 400;
+pub const R_DYNRES_TARGET: u32 = 401;
+pub const R_ZNEAR: u32 = 402;
+pub const R_ZFAR: u32 = 403;
+pub const R_FRAMES_IN_FLIGHT: u32 = 404;
+pub const R_VALIDATION: u32 = 405;
+pub const R_GPU_VALIDATION: u32 = 406;
+
+pub const MIN_RENDER_SCALE: f32 = 0.5;
+pub const MAX_RENDER_SCALE: f32 = 2.0;
 
 pub const M_SENSITIVITY: u32 = 1000;
 pub const M_YAW: u32 = 1001;
@@ -35,6 +71,7 @@ impl ConfigVariables {
             .name("window_title")
             .default("Untitled".to_string())
             .description("Window title")
+            .change_trigger(ControlSignal::UpdateWindowTitle)
             .build());
         id_to_cvar.insert(WINDOW_WIDTH, ConfigVariable::builder()
             .name("window_width")
@@ -50,6 +87,135 @@ impl ConfigVariables {
             .description("Window height")
             .change_trigger(ControlSignal::ResizeWindow)
             .build());
+        id_to_cvar.insert(WINDOW_FULLSCREEN, ConfigVariable::builder()
+            .name("window_fullscreen")
+            .default(FULLSCREEN_WINDOWED)
+            .max_value(FULLSCREEN_BORDERLESS)
+            .description("0 = windowed, 1 = exclusive fullscreen, 2 = borderless fullscreen")
+            .change_trigger(ControlSignal::UpdateFullscreen)
+            .build());
+        id_to_cvar.insert(WINDOW_MONITOR, ConfigVariable::builder()
+            .name("window_monitor")
+            .default(0)
+            .description("Fullscreen monitor index, see listmodes")
+            .change_trigger(ControlSignal::UpdateFullscreen)
+            .build());
+        id_to_cvar.insert(WINDOW_REFRESH_RATE, ConfigVariable::builder()
+            .name("window_refresh_rate")
+            .default(0)
+            .description("Exclusive fullscreen refresh rate in Hz, 0 = highest available")
+            .change_trigger(ControlSignal::UpdateFullscreen)
+            .build());
+
+        id_to_cvar.insert(DEMO_FILE, ConfigVariable::builder()
+            .name("demo_file")
+            .default("demo.rec".to_string())
+            .description("Input recording file used by record and playback")
+            .build());
+        id_to_cvar.insert(TIMESCALE, ConfigVariable::builder()
+            .name("ts")
+            .default(1f32)
+            .min_value(0f32)
+            .max_value(10f32)
+            .description("Speed of the simulation, ignored while recording, playing back or running a timedemo")
+            .build());
+        id_to_cvar.insert(RNG_SEED, ConfigVariable::builder()
+            .name("rng_seed")
+            .default(0)
+            .description("Seed of the engine random numbers, 0 = from the clock, set to the seed used at startup")
+            .build());
+
+        id_to_cvar.insert(LOG_CAPACITY, ConfigVariable::builder()
+            .name("log_capacity")
+            .default(logger::DEFAULT_CAPACITY as u32)
+            .min_value(100)
+            .description("Number of log messages kept in the console history")
+            .build());
+        id_to_cvar.insert(STATS_SPIKE_THRESHOLD, ConfigVariable::builder()
+            .name("stats_spike_threshold")
+            .default(0f32)
+            .min_value(0f32)
+            .description("Frames slower than this many ms are logged, 0 = disabled")
+            .build());
+
+        id_to_cvar.insert(CON_REPEAT_DELAY, ConfigVariable::builder()
+            .name("con_repeat_delay")
+            .default(400f32)
+            .min_value(0f32)
+            .description("Console keys held this many ms start repeating")
+            .build());
+        id_to_cvar.insert(CON_REPEAT_RATE, ConfigVariable::builder()
+            .name("con_repeat_rate")
+            .default(30f32)
+            .min_value(0f32)
+            .description("Repeats per second of held console keys, 0 = disabled")
+            .build());
+        id_to_cvar.insert(CON_HISTORY_FILE, ConfigVariable::builder()
+            .name("con_history_file")
+            .default("console_history.txt".to_string())
+            .description("Console input history, loaded at startup and saved on exit")
+            .build());
+        id_to_cvar.insert(CON_HISTORY_SIZE, ConfigVariable::builder()
+            .name("con_history_size")
+            .default(500)
+            .description("Number of console inputs kept in con_history_file, 0 = not saved")
+            .build());
+        id_to_cvar.insert(CON_BACKGROUND_COLOR, ConfigVariable::builder()
+            .name("con_background_color")
+            .default(Color::from_hex("#050505f2").unwrap())
+            .description("Console background as #rrggbbaa")
+            .build());
+
+        id_to_cvar.insert(SCRIPT_AUTOEXEC, ConfigVariable::builder()
+            .name("script_autoexec")
+            .default("autoexec.rhai".to_string())
+            .description("Script run at startup when it exists, needs the scripting feature")
+            .build());
+
+        id_to_cvar.insert(R_RENDERSCALE, ConfigVariable::builder()
+            .name("r_renderscale")
+            .default(1f32)
+            .min_value(MIN_RENDER_SCALE)
+            .max_value(MAX_RENDER_SCALE)
+            .description("Resolution of the scene relative to the window, upsampled when below 1")
+            .build());
+        id_to_cvar.insert(R_DYNRES_TARGET, ConfigVariable::builder()
+            .name("r_dynres_target")
+            .default(0f32)
+            .min_value(0f32)
+            .description("GPU frame time in ms the render scale adapts to, up to r_renderscale, 0 = disabled")
+            .build());
+        id_to_cvar.insert(R_ZNEAR, ConfigVariable::builder()
+            .name("r_znear")
+            .default(0.1f32)
+            .min_value(0.001f32)
+            .description("Distance of the near clipping plane of cameras")
+            .build());
+        id_to_cvar.insert(R_ZFAR, ConfigVariable::builder()
+            .name("r_zfar")
+            .default(1000f32)
+            .min_value(0f32)
+            .description("Distance of the far clipping plane of perspective cameras, 0 = infinite")
+            .build());
+        id_to_cvar.insert(R_FRAMES_IN_FLIGHT, ConfigVariable::builder()
+            .name("r_frames_in_flight")
+            .default(DEFAULT_FRAMES_IN_FLIGHT as u32)
+            .min_value(1u32)
+            .max_value(MAX_FRAMES_IN_FLIGHT as u32)
+            .description("Frames the CPU may be ahead of the GPU, fewer lower the input latency, more the stutter")
+            .build());
+        id_to_cvar.insert(R_VALIDATION, ConfigVariable::builder()
+            .name("r_validation")
+            .default(cfg!(debug_assertions) as u32)
+            .max_value(1u32)
+            .description("1 = enable the Vulkan validation layer and image layout checks, applied on restart")
+            .build());
+        id_to_cvar.insert(R_GPU_VALIDATION, ConfigVariable::builder()
+            .name("r_gpu_validation")
+            .default(0u32)
+            .max_value(1u32)
+            .description("1 = enable GPU-assisted validation and the validation layer, slow, applied on restart")
+            .build());
 
         id_to_cvar.insert(M_SENSITIVITY, ConfigVariable::builder()
             .name("m_sensitivity")
@@ -92,6 +258,10 @@ impl ConfigVariables {
         cvar.unwrap().value.as_ref()
     }
 
+    pub fn get_name(&self, id: u32) -> &str {
+        self.id_to_cvar.get(&id).expect("unknown cvar id").name
+    }
+
     pub fn get_desc(&self, id: u32) -> String {
         let cvar = self.id_to_cvar.get(&id).expect("unknown cvar id");
         match cvar.value.get_type() {
@@ -138,9 +308,48 @@ impl ConfigVariables {
                     cvar.default.as_str()
                 )
             }
+            CvarType::Color => {
+                format!(
+                    "{} = {} ({}, type: color, default: {})",
+                    cvar.name,
+                    cvar.value.as_str(),
+                    cvar.description,
+                    cvar.default.as_str()
+                )
+            }
         }
     }
 
+    // Descriptions of all cvars, sorted by name
+    pub fn get_all_desc(&self) -> Vec<String> {
+        let mut names: Vec<&String> = self.cvar_str_to_id.keys().collect();
+        names.sort();
+
+        names.into_iter().map(|name| self.get_desc(self.cvar_str_to_id[name])).collect()
+    }
+
+    // Float and integer cvars, sorted by name
+    pub fn get_numeric_ids(&self) -> Vec<u32> {
+        let mut names: Vec<&String> = self
+            .cvar_str_to_id
+            .iter()
+            .filter(|(_, id)| matches!(self.get(**id).get_type(), CvarType::Float | CvarType::Integer))
+            .map(|(name, _)| name)
+            .collect();
+        names.sort();
+
+        names.into_iter().map(|name| self.cvar_str_to_id[name]).collect()
+    }
+
+    // Declared min and max of a numeric cvar
+    pub fn get_range(&self, id: u32) -> (Option<f32>, Option<f32>) {
+        let cvar = self.id_to_cvar.get(&id).expect("unknown cvar id");
+        (
+            cvar.min_value.as_ref().map(|min| min.as_float()),
+            cvar.max_value.as_ref().map(|max| max.as_float()),
+        )
+    }
+
     pub fn get_trigger(&self, id: u32) -> ControlSignal {
         self.id_to_cvar.get(&id).expect("unknown cvar id").change_trigger
     }
@@ -275,12 +484,14 @@ pub enum CvarType {
     Float,
     Integer,
     String,
+    Color,
 }
 
 pub trait CvarValue {
     fn as_float(&self) -> f32;
     fn as_int(&self) -> u32;
     fn as_str(&self) -> String;
+    fn as_color(&self) -> Color;
     fn get_type(&self) -> CvarType;
     fn set(&mut self, val: &dyn CvarValue);
 
@@ -301,6 +512,10 @@ impl CvarValue for f32 {
         format!("{}", *self)
     }
 
+    fn as_color(&self) -> Color {
+        panic!()
+    }
+
     fn get_type(&self) -> CvarType {
         CvarType::Float
     }
@@ -331,6 +546,10 @@ impl CvarValue for u32 {
         format!("{}", *self)
     }
 
+    fn as_color(&self) -> Color {
+        panic!()
+    }
+
     fn get_type(&self) -> CvarType {
         CvarType::Integer
     }
@@ -361,6 +580,10 @@ impl CvarValue for String {
         self.clone()
     }
 
+    fn as_color(&self) -> Color {
+        panic!()
+    }
+
     fn get_type(&self) -> CvarType {
         CvarType::String
     }
@@ -377,3 +600,38 @@ impl CvarValue for String {
         self.len() < rhs.as_str().len()
     }
 }
+
+impl CvarValue for Color {
+    fn as_float(&self) -> f32 {
+        panic!()
+    }
+
+    fn as_int(&self) -> u32 {
+        panic!()
+    }
+
+    fn as_str(&self) -> String {
+        self.to_hex()
+    }
+
+    fn as_color(&self) -> Color {
+        *self
+    }
+
+    fn get_type(&self) -> CvarType {
+        CvarType::Color
+    }
+
+    fn set(&mut self, val: &dyn CvarValue) {
+        *self = val.as_color();
+    }
+
+    // Colors have no range
+    fn gt(&self, _rhs: &dyn CvarValue) -> bool {
+        false
+    }
+
+    fn lt(&self, _rhs: &dyn CvarValue) -> bool {
+        false
+    }
+}
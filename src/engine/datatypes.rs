@@ -3,6 +3,7 @@ use ash::vk;
 use ash::vk::{VertexInputAttributeDescription, VertexInputBindingDescription};
 use cgmath::{Matrix4, SquareMatrix, Vector2, Vector3, Vector4};
 use num::Zero;
+use serde::{Deserialize, Serialize};
 
 #[repr(C)]
 #[derive(Clone, Debug, Copy)]
@@ -11,6 +12,14 @@ pub struct ViewProjectionUniform {
     pub proj: Matrix4<f32>,
 }
 
+// One view and projection per eye, indexed with gl_ViewIndex in multiview passes
+#[repr(C)]
+#[derive(Clone, Debug, Copy)]
+pub struct StereoViewProjectionUniform {
+    pub view: [Matrix4<f32>; 2],
+    pub proj: [Matrix4<f32>; 2],
+}
+
 #[repr(C)]
 #[derive(Clone, Debug, Copy)]
 pub struct ColoredVertex {
@@ -179,7 +188,7 @@ impl VertexInputDescription for SimpleVertex {
 }
 
 #[repr(C)]
-#[derive(Clone, Debug, Copy)]
+#[derive(Clone, Debug, Copy, Serialize, Deserialize)]
 pub struct NormalVertex {
     pub position: Vector3<f32>,
     pub normal: Vector3<f32>,
@@ -218,6 +227,71 @@ impl VertexInputDescription for NormalVertex {
     }
 }
 
+// Source vertex of GPU skinning, see Context::add_skinned_mesh. Weights of a vertex sum up to one.
+#[repr(C)]
+#[derive(Clone, Debug, Copy)]
+pub struct SkinnedVertex {
+    pub position: Vector3<f32>,
+    pub normal: Vector3<f32>,
+    pub joints: [u32; 4],
+    pub weights: [f32; 4],
+}
+
+impl SkinnedVertex {
+    pub fn new(position: Vector3<f32>, normal: Vector3<f32>, joints: [u32; 4], weights: [f32; 4]) -> Self {
+        SkinnedVertex {
+            position,
+            normal,
+            joints,
+            weights,
+        }
+    }
+}
+
+// std430 layout of the instance bounds in occlusion_cull.comp, world space box of an instance
+#[repr(C)]
+#[derive(Clone, Debug, Copy)]
+pub struct InstanceBounds {
+    pub min: Vector4<f32>,
+    pub max: Vector4<f32>,
+}
+
+impl InstanceBounds {
+    pub fn new(min: Vector3<f32>, max: Vector3<f32>) -> Self {
+        InstanceBounds {
+            min: min.extend(1.0),
+            max: max.extend(1.0),
+        }
+    }
+}
+
+// Index of a visible instance written by the occlusion culling pre-pass, the second vertex stream of pipelines
+// drawing culled instances, e.g. (NormalVertex, CulledInstance)
+#[repr(C)]
+#[derive(Clone, Debug, Copy)]
+pub struct CulledInstance {
+    pub index: u32,
+}
+
+impl VertexInputDescription for CulledInstance {
+    fn binding_descriptions() -> Vec<VertexInputBindingDescription> {
+        vec![VertexInputBindingDescription {
+            binding: 0,
+            stride: std::mem::size_of::<Self>() as u32,
+            input_rate: vk::VertexInputRate::INSTANCE,
+        }]
+    }
+
+    fn attribute_descriptions() -> Vec<VertexInputAttributeDescription> {
+        vec![VertexInputAttributeDescription {
+            binding: 0,
+            location: 0,
+            format: vk::Format::R32_UINT,
+            offset: offset_of!(Self, index) as u32,
+        }]
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Debug, Copy)]
 pub struct InstancedCharacter {
@@ -252,6 +326,63 @@ impl InstancedQuad {
     }
 }
 
+// Offset and size in texture coordinates covering the whole texture
+pub const FULL_UV_RECT: Vector4<f32> = Vector4::new(0.0, 0.0, 1.0, 1.0);
+
+// std430 layout of billboard_data in billboard.vert
+#[repr(C)]
+#[derive(Clone, Debug, Copy)]
+pub struct InstancedBillboard {
+    pub position: Vector3<f32>,
+    _padding: f32,
+    pub color: Vector4<f32>,
+    pub uv_rect: Vector4<f32>,
+    pub size: Vector2<f32>,
+    _padding2: Vector2<f32>,
+}
+
+impl InstancedBillboard {
+    pub fn new(position: Vector3<f32>, size: Vector2<f32>, color: Vector4<f32>, uv_rect: Vector4<f32>) -> Self {
+        InstancedBillboard {
+            position,
+            _padding: 0.0,
+            color,
+            uv_rect,
+            size,
+            _padding2: Vector2::new(0.0, 0.0),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Debug, Copy)]
+pub struct InstancedWorldCharacter {
+    pub transform: Matrix4<f32>,
+    pub color: Vector4<f32>,
+    // Center of the glyph in the plane of the text
+    pub position: Vector2<f32>,
+    pub character: u32,
+    pub size: f32,
+}
+
+impl InstancedWorldCharacter {
+    pub fn new(
+        transform: Matrix4<f32>,
+        position: Vector2<f32>,
+        color: Vector4<f32>,
+        character: u32,
+        size: f32,
+    ) -> Self {
+        InstancedWorldCharacter {
+            transform,
+            color,
+            position,
+            character,
+            size,
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Debug, Copy)]
 pub struct ModelWoblyPushConstant {
@@ -296,13 +427,20 @@ pub struct PosSizeColor2dPushConstant {
     position: Vector2<f32>,
     size: Vector2<f32>,
     color: Vector4<f32>,
+    uv_rect: Vector4<f32>,
 }
 
 impl PosSizeColor2dPushConstant {
 
 
     pub fn new(position: Vector2<f32>, size: Vector2<f32>, color: Vector4<f32>) -> Self {
-        PosSizeColor2dPushConstant { position, size, color }
+        PosSizeColor2dPushConstant { position, size, color, uv_rect: FULL_UV_RECT }
+    }
+
+    // Offset and size of the sampled part of the texture, e.g. a frame of a sprite sheet
+    pub fn with_uv_rect(mut self, uv_rect: Vector4<f32>) -> Self {
+        self.uv_rect = uv_rect;
+        self
     }
 }
 
@@ -311,7 +449,8 @@ impl Default for PosSizeColor2dPushConstant {
         PosSizeColor2dPushConstant {
             position: Vector2::zero(),
             size: Vector2::zero(),
-            color: Vector4::new(1.0, 1.0, 1.0, 1.0)
+            color: Vector4::new(1.0, 1.0, 1.0, 1.0),
+            uv_rect: FULL_UV_RECT,
         }
     }
 }
@@ -331,7 +470,7 @@ impl TransformColorPushConstant {
 
 
 
-#[derive(Clone, Debug, Copy)]
+#[derive(Clone, Debug, Copy, PartialEq, Eq)]
 pub struct WindowExtent {
     pub width: u32,
     pub height: u32,
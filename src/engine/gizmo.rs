@@ -0,0 +1,416 @@
+use std::f32::consts::PI;
+use std::path::Path;
+
+use cgmath::{InnerSpace, Matrix4, Quaternion, Rad, Rotation3, Vector3, Vector4};
+
+use crate::engine::datatypes::{Mesh, SimpleVertex, TransformColorPushConstant, ViewProjectionUniform};
+use crate::engine::math::Ray;
+use crate::renderer::context::{Context, Frame};
+use crate::renderer::types::{
+    DrawCommand, Index, PipelineConfiguration, PipelineHandle, RenderPassHandle, UniformHandle,
+};
+
+// Relative to the size of the gizmo
+const PICK_RADIUS: f32 = 0.08;
+const HANDLE_THICKNESS: f32 = 0.03;
+const TIP_SIZE: f32 = 0.12;
+// Scaling stops this close to the center instead of flipping the object
+const MIN_SCALE_DISTANCE: f32 = 0.05;
+const RING_SEGMENTS: u32 = 48;
+
+const AXIS_COLORS: [Vector4<f32>; 3] = [
+    Vector4::new(0.9, 0.2, 0.2, 1.0),
+    Vector4::new(0.2, 0.8, 0.2, 1.0),
+    Vector4::new(0.2, 0.4, 0.9, 1.0),
+];
+const HIGHLIGHT_COLOR: Vector4<f32> = Vector4::new(1.0, 0.9, 0.2, 1.0);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GizmoMode {
+    Translate,
+    Rotate,
+    Scale,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GizmoAxis {
+    X,
+    Y,
+    Z,
+}
+
+impl GizmoAxis {
+    pub const ALL: [GizmoAxis; 3] = [GizmoAxis::X, GizmoAxis::Y, GizmoAxis::Z];
+
+    pub fn direction(&self) -> Vector3<f32> {
+        match self {
+            GizmoAxis::X => Vector3::new(1.0, 0.0, 0.0),
+            GizmoAxis::Y => Vector3::new(0.0, 1.0, 0.0),
+            GizmoAxis::Z => Vector3::new(0.0, 0.0, 1.0),
+        }
+    }
+
+    // Directions spanning the plane of the rotation ring, a positive angle turns the first towards the second
+    fn ring_basis(&self) -> (Vector3<f32>, Vector3<f32>) {
+        match self {
+            GizmoAxis::X => (GizmoAxis::Y.direction(), GizmoAxis::Z.direction()),
+            GizmoAxis::Y => (GizmoAxis::Z.direction(), GizmoAxis::X.direction()),
+            GizmoAxis::Z => (GizmoAxis::X.direction(), GizmoAxis::Y.direction()),
+        }
+    }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+// Change of the transform since the previous drag update. Translations are added to the position, rotations
+// applied before the orientation and scale factors multiplied with the scale.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GizmoDelta {
+    Translation(Vector3<f32>),
+    Rotation(Quaternion<f32>),
+    Scale(Vector3<f32>),
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Drag {
+    axis: GizmoAxis,
+    // Distance along the axis or angle on the ring at the last update
+    last: f32,
+}
+
+// World axis aligned handles at the position of an entity, picked and dragged with rays through the cursor.
+// Draw it with a GizmoRenderer.
+pub struct Gizmo {
+    mode: GizmoMode,
+    position: Vector3<f32>,
+    // Length of the handles and radius of the rings in world units
+    size: f32,
+    hovered: Option<GizmoAxis>,
+    drag: Option<Drag>,
+}
+
+impl Gizmo {
+    pub fn new(mode: GizmoMode, position: Vector3<f32>, size: f32) -> Self {
+        Gizmo {
+            mode,
+            position,
+            size,
+            hovered: None,
+            drag: None,
+        }
+    }
+
+    // Ends a drag in progress
+    pub fn set_mode(&mut self, mode: GizmoMode) {
+        self.mode = mode;
+        self.drag = None;
+    }
+
+    pub fn mode(&self) -> GizmoMode {
+        self.mode
+    }
+
+    pub fn set_position(&mut self, position: Vector3<f32>) {
+        self.position = position;
+    }
+
+    pub fn position(&self) -> Vector3<f32> {
+        self.position
+    }
+
+    // E.g. proportional to the distance to the camera to keep the gizmo the same size on screen
+    pub fn set_size(&mut self, size: f32) {
+        self.size = size;
+    }
+
+    pub fn size(&self) -> f32 {
+        self.size
+    }
+
+    // Closest handle hit by the ray
+    pub fn pick(&self, ray: &Ray) -> Option<GizmoAxis> {
+        let pick_radius = self.size * PICK_RADIUS;
+        let hits = GizmoAxis::ALL.iter().filter_map(|axis| {
+            let direction = axis.direction();
+            let distance = match self.mode {
+                GizmoMode::Translate | GizmoMode::Scale => {
+                    let (distance, along_axis) = ray.closest_to_line(self.position, direction)?;
+                    let on_handle = distance >= 0.0 && (0.0..=self.size).contains(&along_axis);
+                    let miss = (ray.at(distance) - (self.position + direction * along_axis)).magnitude();
+                    (on_handle && miss <= pick_radius).then_some(distance)?
+                }
+                GizmoMode::Rotate => {
+                    let distance = ray.intersect_plane(self.position, direction)?;
+                    let radius = (ray.at(distance) - self.position).magnitude();
+                    ((radius - self.size).abs() <= pick_radius).then_some(distance)?
+                }
+            };
+
+            Some((*axis, distance))
+        });
+
+        hits.min_by(|a, b| a.1.total_cmp(&b.1)).map(|(axis, _)| axis)
+    }
+
+    // Highlights the handle under the cursor, unless a drag is in progress
+    pub fn hover(&mut self, ray: &Ray) {
+        if self.drag.is_none() {
+            self.hovered = self.pick(ray);
+        }
+    }
+
+    // The dragged handle or else the hovered one
+    pub fn highlighted(&self) -> Option<GizmoAxis> {
+        self.drag.map(|drag| drag.axis).or(self.hovered)
+    }
+
+    // Returns false if the ray misses the handles
+    pub fn begin_drag(&mut self, ray: &Ray) -> bool {
+        let axis = match self.pick(ray) {
+            Some(axis) => axis,
+            None => return false,
+        };
+        let last = match self.drag_value(ray, axis) {
+            Some(last) => last,
+            None => return false,
+        };
+        self.drag = Some(Drag { axis, last });
+
+        true
+    }
+
+    // None without a drag or if the ray runs parallel to the dragged handle. Translations move the gizmo along.
+    pub fn drag(&mut self, ray: &Ray) -> Option<GizmoDelta> {
+        let mut drag = self.drag?;
+        let value = self.drag_value(ray, drag.axis)?;
+        let direction = drag.axis.direction();
+
+        let delta = match self.mode {
+            GizmoMode::Translate => {
+                let translation = direction * (value - drag.last);
+                // The handle moves with the position, so the grabbed point keeps its distance along the axis
+                self.position += translation;
+                GizmoDelta::Translation(translation)
+            }
+            GizmoMode::Scale => {
+                let factor = value / drag.last;
+                drag.last = value;
+                let scale = Vector3::new(1.0, 1.0, 1.0) + direction * (factor - 1.0);
+                GizmoDelta::Scale(scale)
+            }
+            GizmoMode::Rotate => {
+                let angle = _wrap_angle(value - drag.last);
+                drag.last = value;
+                GizmoDelta::Rotation(Quaternion::from_axis_angle(direction, Rad(angle)))
+            }
+        };
+        self.drag = Some(drag);
+
+        Some(delta)
+    }
+
+    pub fn end_drag(&mut self) {
+        self.drag = None;
+    }
+
+    pub fn is_dragging(&self) -> bool {
+        self.drag.is_some()
+    }
+
+    // Distance along the axis from the position for translate and scale, angle on the ring for rotate
+    fn drag_value(&self, ray: &Ray, axis: GizmoAxis) -> Option<f32> {
+        let direction = axis.direction();
+        match self.mode {
+            GizmoMode::Translate => ray.closest_to_line(self.position, direction).map(|(_, along_axis)| along_axis),
+            GizmoMode::Scale => ray
+                .closest_to_line(self.position, direction)
+                .map(|(_, along_axis)| along_axis.max(self.size * MIN_SCALE_DISTANCE)),
+            GizmoMode::Rotate => {
+                let distance = ray.intersect_plane(self.position, direction)?;
+                let offset = ray.at(distance) - self.position;
+                let (first, second) = axis.ring_basis();
+                Some(offset.dot(second).atan2(offset.dot(first)))
+            }
+        }
+    }
+}
+
+// Draws gizmos with flat colored boxes. Uses depth testing like any other pipeline, so add it to a pass where the
+// scene does not cover the handles.
+pub struct GizmoRenderer {
+    pipeline: PipelineHandle,
+    cube: Mesh,
+
+    pending: Vec<TransformColorPushConstant>,
+    // Push constants of the last draw, kept alive until the frame has been rendered
+    drawn: Vec<TransformColorPushConstant>,
+}
+
+impl GizmoRenderer {
+    pub fn new(
+        context: &mut Context,
+        render_pass: RenderPassHandle,
+        camera_uniform: UniformHandle<ViewProjectionUniform>,
+    ) -> Self {
+        let (vertices, indices) = _cube();
+        let vertex_buffer = context.create_static_vertex_buffer_sync(&vertices);
+        let index_buffer = context.create_static_index_buffer_sync(&indices);
+        let cube = Mesh::new(vertex_buffer, index_buffer, indices.len() as u32);
+
+        let pipeline_config = PipelineConfiguration::builder()
+            .with_name("gizmo")
+            .with_push_constant::<TransformColorPushConstant>()
+            .with_vertex_shader_file(Path::new("./resources/shaders/flat_color_vert.spv"))
+            .with_fragment_shader_file(Path::new("./resources/shaders/flat_color_frag.spv"))
+            .with_vertex_uniform(0, camera_uniform)
+            .build()
+            .expect("Invalid pipeline configuration!");
+        let pipeline = context.add_pipeline::<SimpleVertex>(render_pass, pipeline_config);
+
+        GizmoRenderer {
+            pipeline,
+            cube,
+
+            pending: Vec::new(),
+            drawn: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, gizmo: &Gizmo) {
+        let size = gizmo.size;
+        let thickness = size * HANDLE_THICKNESS;
+        for axis in GizmoAxis::ALL.iter() {
+            let color = if gizmo.highlighted() == Some(*axis) {
+                HIGHLIGHT_COLOR
+            } else {
+                AXIS_COLORS[axis.index()]
+            };
+            let direction = axis.direction();
+            let (first, second) = axis.ring_basis();
+
+            match gizmo.mode {
+                GizmoMode::Translate | GizmoMode::Scale => {
+                    let shaft_length = size * (1.0 - TIP_SIZE);
+                    let shaft = _box_transform(
+                        gizmo.position + direction * shaft_length / 2.0,
+                        [direction * shaft_length, first * thickness, second * thickness],
+                    );
+                    self.pending.push(TransformColorPushConstant::new(shaft, color));
+
+                    // Arrow head for translate, a cube at the end of the handle for scale
+                    let tip_size = size * TIP_SIZE;
+                    let tip_width = if gizmo.mode == GizmoMode::Translate { tip_size / 2.0 } else { tip_size };
+                    let tip = _box_transform(
+                        gizmo.position + direction * (size - tip_size / 2.0),
+                        [direction * tip_size, first * tip_width, second * tip_width],
+                    );
+                    self.pending.push(TransformColorPushConstant::new(tip, color));
+                }
+                GizmoMode::Rotate => {
+                    let chord = 2.0 * size * (PI / RING_SEGMENTS as f32).sin();
+                    for segment in 0..RING_SEGMENTS {
+                        let angle = (segment as f32 + 0.5) * 2.0 * PI / RING_SEGMENTS as f32;
+                        let radial = first * angle.cos() + second * angle.sin();
+                        let tangent = second * angle.cos() - first * angle.sin();
+                        let transform = _box_transform(
+                            gizmo.position + radial * size,
+                            [tangent * chord, direction * thickness, radial * thickness],
+                        );
+                        self.pending.push(TransformColorPushConstant::new(transform, color));
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn draw(&mut self, frame: &mut Frame) {
+        self.drawn.clear();
+        std::mem::swap(&mut self.drawn, &mut self.pending);
+
+        for push_constant in self.drawn.iter() {
+            frame.add_draw_command(DrawCommand::new_buffered(self.pipeline, push_constant, self.cube));
+        }
+    }
+}
+
+// Unit cube around the origin stretched along the edges
+fn _box_transform(center: Vector3<f32>, edges: [Vector3<f32>; 3]) -> Matrix4<f32> {
+    Matrix4::from_cols(edges[0].extend(0.0), edges[1].extend(0.0), edges[2].extend(0.0), center.extend(1.0))
+}
+
+fn _wrap_angle(angle: f32) -> f32 {
+    let wrapped = (angle + PI).rem_euclid(2.0 * PI) - PI;
+    if wrapped == -PI { PI } else { wrapped }
+}
+
+// Unit cube around the origin, faces wind clockwise seen from outside like the predefined quads
+fn _cube() -> (Vec<SimpleVertex>, Vec<Index>) {
+    let x = Vector3::new(1.0, 0.0, 0.0);
+    let y = Vector3::new(0.0, 1.0, 0.0);
+    let z = Vector3::new(0.0, 0.0, 1.0);
+    // Right and up of each face seen from outside
+    let faces = [(x, y), (-x, y), (-z, y), (z, y), (x, -z), (x, z)];
+
+    let mut vertices = Vec::with_capacity(24);
+    let mut indices = Vec::with_capacity(36);
+    for (right, up) in faces.iter() {
+        let center = right.cross(*up) * 0.5;
+        let first = vertices.len() as Index;
+        for corner in [-*right + *up, *right + *up, -*right - *up, *right - *up].iter() {
+            vertices.push(SimpleVertex::new(center + corner * 0.5));
+        }
+        indices.extend([0, 1, 2, 2, 1, 3].iter().map(|index| first + index));
+    }
+
+    (vertices, indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::Rotation;
+
+    #[test]
+    fn pick_and_drag() {
+        let mut gizmo = Gizmo::new(GizmoMode::Translate, Vector3::new(0.0, 0.0, 0.0), 1.0);
+        let looking_down = |x: f32, z: f32| Ray::new(Vector3::new(x, 10.0, z), Vector3::new(0.0, -1.0, 0.0));
+
+        assert_eq!(gizmo.pick(&looking_down(0.5, 0.02)), Some(GizmoAxis::X));
+        assert_eq!(gizmo.pick(&looking_down(0.02, 0.5)), Some(GizmoAxis::Z));
+        assert_eq!(gizmo.pick(&looking_down(0.5, 0.5)), None);
+        assert_eq!(gizmo.pick(&looking_down(1.5, 0.0)), None);
+
+        assert!(gizmo.begin_drag(&looking_down(0.5, 0.0)));
+        assert_eq!(gizmo.drag(&looking_down(0.75, 0.3)), Some(GizmoDelta::Translation(Vector3::new(0.25, 0.0, 0.0))));
+        assert_eq!(gizmo.position(), Vector3::new(0.25, 0.0, 0.0));
+        assert_eq!(gizmo.highlighted(), Some(GizmoAxis::X));
+        gizmo.end_drag();
+
+        gizmo.set_mode(GizmoMode::Scale);
+        gizmo.set_position(Vector3::new(0.0, 0.0, 0.0));
+        assert!(gizmo.begin_drag(&looking_down(0.0, 0.5)));
+        assert_eq!(gizmo.drag(&looking_down(0.0, 1.0)), Some(GizmoDelta::Scale(Vector3::new(1.0, 1.0, 2.0))));
+        gizmo.end_drag();
+
+        // A quarter turn around y on the ring, from +z towards +x
+        gizmo.set_mode(GizmoMode::Rotate);
+        assert_eq!(gizmo.pick(&looking_down(0.0, 0.0)), None);
+        assert!(gizmo.begin_drag(&looking_down(0.0, 1.0)));
+        let rotation = match gizmo.drag(&looking_down(1.0, 0.0)) {
+            Some(GizmoDelta::Rotation(rotation)) => rotation,
+            delta => panic!("{:?}", delta),
+        };
+        let turned = rotation.rotate_vector(Vector3::new(0.0, 0.0, 1.0));
+        assert!((turned - Vector3::new(1.0, 0.0, 0.0)).magnitude() < 1.0e-5, "{:?}", turned);
+        assert!((_wrap_angle(1.5 * PI) + 0.5 * PI).abs() < 1.0e-5);
+
+        let (vertices, indices) = _cube();
+        for triangle in indices.chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| vertices[triangle[i] as usize].position);
+            let outward = (a + b + c) / 3.0;
+            assert!((b - a).cross(c - a).dot(outward) < 0.0);
+        }
+    }
+}
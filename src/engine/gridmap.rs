@@ -0,0 +1,518 @@
+use std::ops::Range;
+use std::path::Path;
+
+use cgmath::{Deg, Matrix4, Vector2, Vector3, Vector4};
+
+use crate::engine::datatypes::{CulledInstance, InstanceBounds, Mesh, NormalVertex, ViewProjectionUniform};
+use crate::engine::math::Aabb;
+use crate::engine::transforms::TransformBuffer;
+use crate::renderer::context::{Context, Frame};
+use crate::renderer::types::{
+    CulledInstancesHandle, DrawCommand, PipelineConfiguration, PipelineHandle, RenderPassHandle, UniformHandle,
+    VertexTopology,
+};
+use crate::error::VulkrapError;
+use crate::util::file;
+
+const EMPTY_TILE: char = '.';
+const FLOOR_SHADE: f32 = 0.3;
+const INITIAL_CAPACITY: usize = 256;
+// Around the unit square of a piece, the wall mesh has some relief
+const PIECE_BOUNDS_MARGIN: f32 = 0.125;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tile {
+    pub wall_color: Vector4<f32>,
+    pub floor_color: Vector4<f32>,
+    // Open cells that can't be walked or seen through, e.g. a pillar or a closed door
+    pub blocks_movement: bool,
+    pub blocks_sight: bool,
+}
+
+impl Tile {
+    // Floor and roof are a shade darker than the walls
+    pub fn new(color: Vector4<f32>) -> Self {
+        Tile {
+            wall_color: color,
+            floor_color: color - Vector4::new(FLOOR_SHADE, FLOOR_SHADE, FLOOR_SHADE, 0.0),
+            blocks_movement: false,
+            blocks_sight: false,
+        }
+    }
+}
+
+// Grid of open cells, x is the column and y the row. Cell (x, y) is centered on world position (x, 0, y).
+pub struct GridMap {
+    width: u32,
+    height: u32,
+    cells: Vec<Option<Tile>>,
+}
+
+impl GridMap {
+    pub fn new(width: u32, height: u32) -> Self {
+        GridMap {
+            width,
+            height,
+            cells: vec![None; (width * height) as usize],
+        }
+    }
+
+    // Text format, lines starting with # are comments:
+    //   tile <char> <r> <g> <b> [a] [blocks_movement] [blocks_sight]
+    //   map
+    //   <one line per row, one char per cell, '.' or ' ' is solid rock>
+    pub fn load(path: &Path) -> Result<GridMap, VulkrapError> {
+        let lines = file::read_lines(path)?
+            .collect::<Result<Vec<String>, _>>()
+            .map_err(|error| VulkrapError::io(path, error))?;
+
+        Ok(GridMap::parse(lines.iter().map(|line| line.as_str()))?)
+    }
+
+    pub fn parse<'a>(lines: impl Iterator<Item = &'a str>) -> Result<GridMap, &'static str> {
+        let mut tiles: Vec<(char, Tile)> = Vec::new();
+        let mut rows: Option<Vec<&str>> = None;
+
+        for line in lines {
+            if let Some(rows) = &mut rows {
+                rows.push(line.trim_end());
+                continue;
+            }
+
+            let mut tokens = line.split_whitespace();
+            match tokens.next() {
+                None => {}
+                Some(token) if token.starts_with('#') => {}
+                Some("map") => rows = Some(Vec::new()),
+                Some("tile") => tiles.push(_parse_tile(tokens)?),
+                Some(_) => return Err("Unknown map directive"),
+            }
+        }
+
+        let rows = rows.ok_or("Map section missing")?;
+        let width = rows.iter().map(|row| row.chars().count()).max().unwrap_or(0) as u32;
+        let mut map = GridMap::new(width, rows.len() as u32);
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                if c == EMPTY_TILE || c == ' ' {
+                    continue;
+                }
+                let (_, tile) = tiles.iter().find(|(tc, _)| *tc == c).ok_or("Unknown tile in map")?;
+                map.set(x as i32, y as i32, Some(*tile));
+            }
+        }
+
+        Ok(map)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn set(&mut self, x: i32, y: i32, tile: Option<Tile>) {
+        let index = self.index(x, y).expect("Cell outside of grid map");
+        self.cells[index] = tile;
+    }
+
+    // Cells outside of the map are solid
+    pub fn get(&self, x: i32, y: i32) -> Option<&Tile> {
+        self.index(x, y).and_then(|index| self.cells[index].as_ref())
+    }
+
+    pub fn is_open(&self, x: i32, y: i32) -> bool {
+        self.get(x, y).is_some()
+    }
+
+    pub fn is_walkable(&self, x: i32, y: i32) -> bool {
+        self.get(x, y).is_some_and(|tile| !tile.blocks_movement)
+    }
+
+    pub fn is_transparent(&self, x: i32, y: i32) -> bool {
+        self.get(x, y).is_some_and(|tile| !tile.blocks_sight)
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return None;
+        }
+
+        Some(y as usize * self.width as usize + x as usize)
+    }
+}
+
+fn _parse_tile<'a>(mut tokens: impl Iterator<Item = &'a str>) -> Result<(char, Tile), &'static str> {
+    let mut chars = tokens.next().ok_or("Invalid tile definition")?.chars();
+    let c = chars.next().ok_or("Invalid tile definition")?;
+    if chars.next().is_some() || c == EMPTY_TILE {
+        return Err("Invalid tile definition");
+    }
+
+    let mut components = Vec::new();
+    let mut flags = Vec::new();
+    for token in tokens {
+        match token.parse::<f32>() {
+            Ok(component) if flags.is_empty() => components.push(component),
+            _ => flags.push(token),
+        }
+    }
+    let color = match components[..] {
+        [r, g, b] => Vector4::new(r, g, b, 1.0),
+        [r, g, b, a] => Vector4::new(r, g, b, a),
+        _ => return Err("Invalid tile color"),
+    };
+
+    let mut tile = Tile::new(color);
+    for flag in flags {
+        match flag {
+            "blocks_movement" => tile.blocks_movement = true,
+            "blocks_sight" => tile.blocks_sight = true,
+            _ => return Err("Unknown tile flag"),
+        }
+    }
+
+    Ok((c, tile))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Part {
+    Floor,
+    Roof,
+    Wall,
+}
+
+struct Piece {
+    part: Part,
+    color_index: usize,
+    cell: Vector2<i32>,
+    transform: Matrix4<f32>,
+}
+
+// See dc_environ_culled.vert, the visible instances of a batch are relative to its first transform
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct CulledBatchPushConstant {
+    color: Vector4<f32>,
+    instance_start: u32,
+}
+
+struct CulledBatch {
+    push_constant: CulledBatchPushConstant,
+    mesh: Mesh,
+    culled_instances: CulledInstancesHandle,
+}
+
+// Floor, roof and wall geometry of a GridMap. Walls are only generated between open and solid cells, and pieces
+// sharing mesh and color are drawn with one instanced draw command.
+pub struct GridMapRenderer {
+    pipeline: PipelineHandle,
+    transforms: TransformBuffer,
+    floor_mesh: Mesh,
+    wall_mesh: Mesh,
+
+    colors: Vec<Vector4<f32>>,
+    pieces: Vec<Piece>,
+    // Only for renderers created with new_culled
+    culled_batches: Option<Vec<CulledBatch>>,
+}
+
+impl GridMapRenderer {
+    // The floor mesh is a unit quad facing +z, e.g. NormaledQuad. The wall mesh is centered on the origin,
+    // one unit wide and high and facing +z.
+    pub fn new(
+        context: &mut Context,
+        render_pass: RenderPassHandle,
+        camera_uniform: UniformHandle<ViewProjectionUniform>,
+        floor_mesh: Mesh,
+        wall_mesh: Mesh,
+        map: &GridMap,
+    ) -> Self {
+        let transforms = TransformBuffer::new(context, INITIAL_CAPACITY);
+
+        let pipeline_config = PipelineConfiguration::builder()
+            .with_name("gridmap")
+            .with_push_constant::<Vector4<f32>>()
+            .with_vertex_shader_file(Path::new("./resources/shaders/dc_environ_vert.spv"))
+            .with_fragment_shader_file(Path::new("./resources/shaders/dc_environ_frag.spv"))
+            .with_vertex_topology(VertexTopology::Triangle)
+            .with_vertex_uniform(0, camera_uniform)
+            .with_storage_buffer_object(1, transforms.get_storage_buffer())
+            .build()
+            .expect("Invalid pipeline configuration!");
+        let pipeline = context.add_pipeline::<NormalVertex>(render_pass, pipeline_config);
+
+        let mut renderer = GridMapRenderer {
+            pipeline,
+            transforms,
+            floor_mesh,
+            wall_mesh,
+
+            colors: Vec::new(),
+            pieces: Vec::new(),
+            culled_batches: None,
+        };
+        renderer.set_map(context, map);
+
+        renderer
+    }
+
+    // Drawn with draw_culled, the pieces are culled on the GPU against the view frustum and the depth of the HiZ
+    // source pass, see Context::set_hiz_source
+    pub fn new_culled(
+        context: &mut Context,
+        render_pass: RenderPassHandle,
+        camera_uniform: UniformHandle<ViewProjectionUniform>,
+        floor_mesh: Mesh,
+        wall_mesh: Mesh,
+        map: &GridMap,
+    ) -> Self {
+        let transforms = TransformBuffer::new(context, INITIAL_CAPACITY);
+
+        let pipeline_config = PipelineConfiguration::builder()
+            .with_name("gridmap_culled")
+            .with_push_constant::<CulledBatchPushConstant>()
+            .with_vertex_shader_file(Path::new("./resources/shaders/dc_environ_culled_vert.spv"))
+            .with_fragment_shader_file(Path::new("./resources/shaders/dc_environ_frag.spv"))
+            .with_vertex_topology(VertexTopology::Triangle)
+            .with_vertex_uniform(0, camera_uniform)
+            .with_storage_buffer_object(1, transforms.get_storage_buffer())
+            .build()
+            .expect("Invalid pipeline configuration!");
+        let pipeline = context.add_pipeline::<(NormalVertex, CulledInstance)>(render_pass, pipeline_config);
+
+        let mut renderer = GridMapRenderer {
+            pipeline,
+            transforms,
+            floor_mesh,
+            wall_mesh,
+
+            colors: Vec::new(),
+            pieces: Vec::new(),
+            culled_batches: Some(Vec::new()),
+        };
+        renderer.set_map(context, map);
+
+        renderer
+    }
+
+    // Rebuilds the geometry, must not be called between draw and the end of the frame. Culled instances can't be
+    // removed, the ones of the previous map stay allocated.
+    pub fn set_map(&mut self, context: &mut Context, map: &GridMap) {
+        let (colors, pieces) = _build_pieces(map);
+        if self.culled_batches.is_some() {
+            let batches = _batch_ranges(&pieces)
+                .into_iter()
+                .map(|range| {
+                    let piece = &pieces[range.start];
+                    let mesh = self._mesh(piece.part);
+                    let bounds: Vec<InstanceBounds> = pieces[range.clone()].iter().map(_piece_bounds).collect();
+
+                    CulledBatch {
+                        push_constant: CulledBatchPushConstant {
+                            color: colors[piece.color_index],
+                            instance_start: range.start as u32,
+                        },
+                        mesh,
+                        culled_instances: context.add_culled_instances(mesh.index_count, &bounds),
+                    }
+                })
+                .collect();
+            self.culled_batches = Some(batches);
+        }
+        self.colors = colors;
+        self.pieces = pieces;
+    }
+
+    pub fn draw(&mut self, frame: &mut Frame) {
+        self.draw_region(frame, Vector2::new(i32::MIN, i32::MIN), Vector2::new(i32::MAX, i32::MAX));
+    }
+
+    // Only draws the cells from min up to but not including max, e.g. the ones in front of the player
+    pub fn draw_region(&mut self, frame: &mut Frame, min: Vector2<i32>, max: Vector2<i32>) {
+        assert!(self.culled_batches.is_none(), "Culled grid maps are drawn with draw_culled");
+        self.transforms.reset(frame);
+
+        for range in _batch_ranges(&self.pieces) {
+            let (part, color_index) = (self.pieces[range.start].part, self.pieces[range.start].color_index);
+            let instance_start = self.transforms.len();
+            for piece in self.pieces[range].iter() {
+                let cell = piece.cell;
+                if cell.x >= min.x && cell.y >= min.y && cell.x < max.x && cell.y < max.y {
+                    self.transforms.push(frame, piece.transform);
+                }
+            }
+
+            let instance_count = self.transforms.len() - instance_start;
+            if instance_count > 0 {
+                frame.add_draw_command(DrawCommand::new_buffered_instanced(
+                    self.pipeline,
+                    &self.colors[color_index],
+                    self._mesh(part),
+                    instance_count,
+                    instance_start,
+                ));
+            }
+        }
+    }
+
+    // With the camera of the HiZ source pass, e.g. Camera::view_projection
+    pub fn draw_culled(&mut self, frame: &mut Frame, view_projection: Matrix4<f32>) {
+        let batches = self.culled_batches.as_ref().expect("Grid map renderer created without culling");
+        self.transforms.reset(frame);
+        for piece in self.pieces.iter() {
+            self.transforms.push(frame, piece.transform);
+        }
+
+        for batch in batches.iter() {
+            frame.cull_instances(batch.culled_instances, view_projection);
+            let culled_instances = frame.culled_instances(batch.culled_instances);
+            frame.add_draw_command(
+                DrawCommand::new_buffered_instanced(self.pipeline, &batch.push_constant, batch.mesh, 0, 0)
+                    .with_culled_instances(culled_instances),
+            );
+        }
+    }
+
+    fn _mesh(&self, part: Part) -> Mesh {
+        match part {
+            Part::Floor | Part::Roof => self.floor_mesh,
+            Part::Wall => self.wall_mesh,
+        }
+    }
+}
+
+// Consecutive pieces sharing part and color
+fn _batch_ranges(pieces: &[Piece]) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    while start < pieces.len() {
+        let (part, color_index) = (pieces[start].part, pieces[start].color_index);
+        let end = start
+            + pieces[start..]
+                .iter()
+                .take_while(|piece| piece.part == part && piece.color_index == color_index)
+                .count();
+        ranges.push(start..end);
+
+        start = end;
+    }
+
+    ranges
+}
+
+// World space box of the unit square facing +z the meshes of all parts fit in
+fn _piece_bounds(piece: &Piece) -> InstanceBounds {
+    let corners = [(-0.5, -0.5), (0.5, -0.5), (-0.5, 0.5), (0.5, 0.5)]
+        .iter()
+        .map(|(x, y)| (piece.transform * Vector4::new(*x, *y, 0.0, 1.0)).truncate());
+    let bounds = Aabb::from_points(corners).expand(PIECE_BOUNDS_MARGIN);
+
+    InstanceBounds::new(bounds.min, bounds.max)
+}
+
+// Pieces are sorted by part and color so that every batch is one consecutive run
+fn _build_pieces(map: &GridMap) -> (Vec<Vector4<f32>>, Vec<Piece>) {
+    let mut colors: Vec<Vector4<f32>> = Vec::new();
+    let mut color_index = |color: Vector4<f32>| match colors.iter().position(|c| *c == color) {
+        Some(index) => index,
+        None => {
+            colors.push(color);
+            colors.len() - 1
+        }
+    };
+
+    let mut pieces = Vec::new();
+    for y in 0..map.height() as i32 {
+        for x in 0..map.width() as i32 {
+            let tile = match map.get(x, y) {
+                Some(tile) => *tile,
+                None => continue,
+            };
+            let cell = Vector2::new(x, y);
+            let floor_color = color_index(tile.floor_color);
+            let wall_color = color_index(tile.wall_color);
+
+            let floor_transform = Matrix4::from_translation(Vector3::new(x as f32, 0.0, y as f32));
+            let roof_transform = Matrix4::from_translation(Vector3::new(x as f32, 1.0, y as f32));
+            pieces.push(Piece {
+                part: Part::Floor,
+                color_index: floor_color,
+                cell,
+                transform: floor_transform * Matrix4::from_angle_x(Deg(-90.0)),
+            });
+            pieces.push(Piece {
+                part: Part::Roof,
+                color_index: floor_color,
+                cell,
+                transform: roof_transform * Matrix4::from_angle_x(Deg(90.0)),
+            });
+
+            // Neighbour offset, wall offset from the cell center and rotation facing into the cell
+            let walls = [
+                ((-1, 0), (-0.5, 0.0), 90.0),
+                ((1, 0), (0.5, 0.0), -90.0),
+                ((0, -1), (0.0, -0.5), 0.0),
+                ((0, 1), (0.0, 0.5), 180.0),
+            ];
+            for ((dx, dy), (offset_x, offset_y), angle) in walls.iter() {
+                if map.is_open(x + dx, y + dy) {
+                    continue;
+                }
+                let translation = Vector3::new(x as f32 + offset_x, 0.5, y as f32 + offset_y);
+                pieces.push(Piece {
+                    part: Part::Wall,
+                    color_index: wall_color,
+                    cell,
+                    transform: Matrix4::from_translation(translation) * Matrix4::from_angle_y(Deg(*angle)),
+                });
+            }
+        }
+    }
+    pieces.sort_by_key(|piece| (piece.part, piece.color_index));
+
+    (colors, pieces)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::InnerSpace;
+
+    #[test]
+    fn parse_and_build() {
+        let text = "# two rooms\ntile a 1.0 0.8 0.8\ntile b 0.5 0.5 0.5 0.5 blocks_sight\nmap\n.aa\n..b\n";
+        let map = GridMap::parse(text.lines()).unwrap();
+        assert_eq!((map.width(), map.height()), (3, 2));
+        assert!(!map.is_open(0, 0) && map.is_open(1, 0) && map.is_open(2, 1));
+        assert!(!map.is_open(-1, 0) && !map.is_open(3, 1));
+        assert_eq!(map.get(2, 1).unwrap().wall_color, Vector4::new(0.5, 0.5, 0.5, 0.5));
+        assert!(map.is_walkable(2, 1) && !map.is_transparent(2, 1));
+
+        // Shared walls are removed, 3 cells in an L have 8 walls
+        let (colors, pieces) = _build_pieces(&map);
+        assert_eq!(colors.len(), 4);
+        assert_eq!(pieces.iter().filter(|piece| piece.part == Part::Floor).count(), 3);
+        assert_eq!(pieces.iter().filter(|piece| piece.part == Part::Wall).count(), 8);
+        assert!(pieces.windows(2).all(|w| (w[0].part, w[0].color_index) <= (w[1].part, w[1].color_index)));
+
+        // Floor, roof and walls of both tiles, the ranges cover all pieces in order
+        let ranges = _batch_ranges(&pieces);
+        assert_eq!(ranges.len(), 6);
+        assert!(ranges.windows(2).all(|w| w[0].end == w[1].start));
+        assert_eq!((ranges[0].start, ranges[5].end), (0, pieces.len()));
+
+        // The floor is flat, the box is only grown by the margin
+        let floor = pieces.iter().find(|piece| piece.part == Part::Floor && piece.cell == Vector2::new(1, 0));
+        let bounds = _piece_bounds(floor.unwrap());
+        let margin = PIECE_BOUNDS_MARGIN;
+        assert!((bounds.min - Vector4::new(0.5 - margin, -margin, -0.5 - margin, 1.0)).magnitude() < 1.0e-5);
+        assert!((bounds.max - Vector4::new(1.5 + margin, margin, 0.5 + margin, 1.0)).magnitude() < 1.0e-5);
+
+        assert_eq!(GridMap::parse("map\n.x".lines()).err(), Some("Unknown tile in map"));
+        assert_eq!(GridMap::parse("tile a 1.0".lines()).err(), Some("Invalid tile color"));
+        assert_eq!(GridMap::parse("tile a 1 1 1".lines()).err(), Some("Map section missing"));
+    }
+}
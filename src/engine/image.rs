@@ -1,5 +1,13 @@
 use std::path::Path;
 
+use crate::error::VulkrapError;
+use crate::renderer::context::Context;
+use crate::renderer::types::TextureHandle;
+
+// Checkers of the fallback texture, magenta and black so missing textures stand out
+const FALLBACK_SIZE: u32 = 8;
+const FALLBACK_COLORS: [[u8; 4]; 2] = [[255, 0, 255, 255], [0, 0, 0, 255]];
+
 pub struct Image {
     pub width: u32,
     pub height: u32,
@@ -10,14 +18,36 @@ impl Image {
     fn new(width: u32, height: u32, data: Vec<u8>) -> Self {
         Image { width, height, data }
     }
+
+    pub fn fallback() -> Self {
+        let data = (0..FALLBACK_SIZE * FALLBACK_SIZE)
+            .flat_map(|i| FALLBACK_COLORS[((i % FALLBACK_SIZE + i / FALLBACK_SIZE) % 2) as usize])
+            .collect();
+
+        Image::new(FALLBACK_SIZE, FALLBACK_SIZE, data)
+    }
 }
 
-pub fn load_image(image_path: &Path) -> Image {
-    let image_object = image::open(image_path).unwrap();
+pub fn load_image(image_path: &Path) -> Result<Image, VulkrapError> {
+    let image_object = image::open(image_path).map_err(|error| VulkrapError::InvalidImage {
+        path: image_path.to_path_buf(),
+        reason: error.to_string(),
+    })?;
     let width = image_object.width();
     let height = image_object.height();
 
     let data = image_object.to_rgba8().into_raw();
 
-    Image::new(width, height, data)
+    Ok(Image::new(width, height, data))
+}
+
+// Logs why the file could not be loaded and returns the fallback texture of the context instead
+pub fn load_texture(context: &mut Context, image_path: &Path) -> TextureHandle {
+    match load_image(image_path) {
+        Ok(image) => context.add_texture(image.width, image.height, &image.data),
+        Err(error) => {
+            log_error!("{}, using the fallback texture", error);
+            context.fallback_texture()
+        }
+    }
 }
@@ -0,0 +1,192 @@
+use std::f32::consts::PI;
+use std::path::Path;
+
+use cgmath::{InnerSpace, Matrix4, Point3, Rad, Vector2, Vector3, Vector4};
+
+use crate::engine::animation::flipbook::SpriteSheet;
+use crate::engine::billboard::{BillboardMode, BillboardRenderer};
+use crate::engine::datatypes::{Mesh, NormalVertex, TransformColorPushConstant, ViewProjectionUniform};
+use crate::engine::math::Aabb;
+use crate::renderer::context::Context;
+use crate::renderer::types::{DrawCommand, PipelineConfiguration, TextureHandle, UniformStage, Viewport};
+
+// Level of detail of an instance, see Impostor::select_lod
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Lod {
+    Mesh,
+    // Offset and size of the atlas tile facing the camera
+    Impostor(Vector4<f32>),
+}
+
+// Mesh rendered from several directions around the up axis into an atlas texture, drawn as cylindrical billboards
+// of the tile facing the camera once instances are far away. Create a BillboardRenderer with the texture of the
+// impostor to draw them.
+pub struct Impostor {
+    texture: TextureHandle,
+    sheet: SpriteSheet,
+    // Center of the mesh bounds and edge length of the square covering the mesh from every direction
+    center: Vector3<f32>,
+    size: f32,
+    lod_distance: f32,
+}
+
+impl Impostor {
+    // Renders the tiles in the next frame, so it can only be called at load time between frames. The pass of the
+    // atlas is disabled afterwards and its order must not be in use by another pass.
+    pub fn bake(
+        context: &mut Context,
+        mesh: Mesh,
+        bounds: Aabb,
+        color: Vector4<f32>,
+        directions: u32,
+        tile_size: u32,
+        pass_order: u32,
+    ) -> Result<Impostor, &'static str> {
+        if directions == 0 || tile_size == 0 {
+            return Err("Impostor must have at least one direction and a tile size");
+        }
+
+        let texture = context.add_render_texture(tile_size * directions, tile_size);
+        let pass = context.create_render_pass(texture, pass_order)?;
+        context.set_render_pass_clear_color(pass, [0.0, 0.0, 0.0, 0.0])?;
+
+        let radius = bounds.size().magnitude() / 2.0;
+        let camera = context.create_uniform_buffer::<ViewProjectionUniform>(UniformStage::Vertex);
+        context.set_buffer_object(camera, _tile_view_projection(radius));
+
+        let pipeline_config = PipelineConfiguration::builder()
+            .with_name("impostor")
+            .with_push_constant::<TransformColorPushConstant>()
+            .with_vertex_shader_file(Path::new("./resources/shaders/default_ppl_vert.spv"))
+            .with_fragment_shader_file(Path::new("./resources/shaders/default_ppl_frag.spv"))
+            .with_vertex_uniform(0, camera)
+            .build()
+            .expect("Invalid pipeline configuration!");
+        let pipeline = context.add_pipeline::<NormalVertex>(pass, pipeline_config);
+
+        let mut frame = context.begin_frame();
+        for direction in 0..directions {
+            let push_constant = TransformColorPushConstant::new(
+                _tile_transform(bounds.center(), _direction_angle(direction, directions)),
+                color,
+            );
+            frame.add_draw_command(
+                DrawCommand::new_buffered(pipeline, &push_constant, mesh)
+                    .with_viewport(Viewport::new(direction * tile_size, 0, tile_size, tile_size)),
+            );
+        }
+        frame.submit();
+        context.set_render_pass_enabled(pass, false)?;
+
+        Ok(Impostor {
+            texture,
+            sheet: SpriteSheet::new(directions, 1),
+            center: bounds.center(),
+            size: radius * 2.0,
+            lod_distance: f32::MAX,
+        })
+    }
+
+    // Instances farther away from the camera are drawn as impostors
+    pub fn with_lod_distance(mut self, lod_distance: f32) -> Self {
+        self.lod_distance = lod_distance;
+
+        self
+    }
+
+    pub fn texture(&self) -> TextureHandle {
+        self.texture
+    }
+
+    // The instance is the mesh translated to the position, e.g. for static vegetation
+    pub fn select_lod(&self, camera_position: Vector3<f32>, instance_position: Vector3<f32>) -> Lod {
+        let to_camera = camera_position - (instance_position + self.center);
+        if to_camera.magnitude2() < self.lod_distance * self.lod_distance {
+            return Lod::Mesh;
+        }
+
+        let angle = to_camera.x.atan2(to_camera.z).rem_euclid(2.0 * PI);
+        let directions = self.sheet.frame_count();
+        let tile = (angle / (2.0 * PI) * directions as f32).round() as u32 % directions;
+
+        Lod::Impostor(self.sheet.uv_rect(tile))
+    }
+
+    // Adds the billboard of the instance if it is drawn as impostor, returns false if the mesh has to be drawn
+    pub fn add_billboard(
+        &self,
+        billboards: &mut BillboardRenderer,
+        camera_position: Vector3<f32>,
+        instance_position: Vector3<f32>,
+    ) -> bool {
+        match self.select_lod(camera_position, instance_position) {
+            Lod::Mesh => false,
+            Lod::Impostor(uv_rect) => {
+                billboards.add_with_uv_rect(
+                    BillboardMode::Cylindrical,
+                    instance_position + self.center,
+                    Vector2::new(self.size, self.size),
+                    Vector4::new(1.0, 1.0, 1.0, 1.0),
+                    uv_rect,
+                );
+                true
+            }
+        }
+    }
+}
+
+// Angle of the view direction around the up axis, 0 looks at the front of the mesh from +z
+fn _direction_angle(direction: u32, directions: u32) -> Rad<f32> {
+    Rad(2.0 * PI * direction as f32 / directions as f32)
+}
+
+// The camera stays on +z, so the mesh is turned the other way to be seen from the angle
+fn _tile_transform(center: Vector3<f32>, angle: Rad<f32>) -> Matrix4<f32> {
+    Matrix4::from_angle_y(-angle) * Matrix4::from_translation(-center)
+}
+
+// Orthographic camera on +z at twice the radius of the mesh. The projection maps the depths of the bounding sphere,
+// radius to three times the radius, to 0..1.
+fn _tile_view_projection(radius: f32) -> ViewProjectionUniform {
+    ViewProjectionUniform {
+        view: Matrix4::look_at_rh(
+            Point3::new(0.0, 0.0, 2.0 * radius),
+            Point3::new(0.0, 0.0, 0.0),
+            Vector3::new(0.0, 1.0, 0.0),
+        ),
+        proj: cgmath::ortho(-radius, radius, -radius, radius, -radius, 3.0 * radius),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tile_facing_camera() {
+        let impostor = Impostor {
+            texture: 0,
+            sheet: SpriteSheet::new(8, 1),
+            center: Vector3::new(0.0, 1.0, 0.0),
+            size: 2.0,
+            lod_distance: 10.0,
+        };
+        let tile = |camera: Vector3<f32>| match impostor.select_lod(camera, Vector3::new(5.0, 0.0, 5.0)) {
+            Lod::Mesh => None,
+            Lod::Impostor(uv_rect) => Some((uv_rect.x * 8.0).round() as u32),
+        };
+
+        assert_eq!(tile(Vector3::new(5.0, 1.0, 10.0)), None);
+        assert_eq!(tile(Vector3::new(5.0, 1.0, 50.0)), Some(0));
+        assert_eq!(tile(Vector3::new(50.0, 1.0, 5.0)), Some(2));
+        assert_eq!(tile(Vector3::new(5.0, 1.0, -50.0)), Some(4));
+        assert_eq!(tile(Vector3::new(-50.0, 1.0, 4.0)), Some(6));
+        // Just before a full turn
+        assert_eq!(tile(Vector3::new(-1.0, 1.0, 50.0)), Some(0));
+
+        // The tile angle seen from the camera on +z is the front of the turned mesh
+        let turn = _tile_transform(Vector3::new(0.0, 0.0, 0.0), _direction_angle(2, 8));
+        let turned = turn * Vector4::new(1.0, 0.0, 0.0, 0.0);
+        assert!((turned - Vector4::new(0.0, 0.0, 1.0, 0.0)).magnitude() < 1.0e-5);
+    }
+}
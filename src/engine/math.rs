@@ -1,10 +0,0 @@
-pub fn lerp(a: f32, b: f32, f: f32) -> f32 {
-    a * (1.0 - f) + (b * f)
-}
-
-
-/*
-pub fn lerp(a: f32, b: f32, f: f32) -> f32 {
-    a * (1.0 - f) + (b * f)
-}
-*/
\ No newline at end of file
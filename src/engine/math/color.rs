@@ -0,0 +1,162 @@
+use cgmath::Vector4;
+
+// RGBA color with sRGB encoded components like in image editors and hex codes, alpha is linear. Shaders get the
+// linear values, the sRGB render targets encode them again when writing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Color {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+impl Default for Color {
+    fn default() -> Self {
+        Color::WHITE
+    }
+}
+
+impl Color {
+    pub const WHITE: Color = Color::srgb(1.0, 1.0, 1.0, 1.0);
+    pub const BLACK: Color = Color::srgb(0.0, 0.0, 0.0, 1.0);
+    pub const TRANSPARENT: Color = Color::srgb(0.0, 0.0, 0.0, 0.0);
+
+    pub const fn srgb(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Color { r, g, b, a }
+    }
+
+    pub const fn srgb8(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Color::srgb(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, a as f32 / 255.0)
+    }
+
+    pub fn linear(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Color::srgb(linear_to_srgb(r), linear_to_srgb(g), linear_to_srgb(b), a)
+    }
+
+    // "#rrggbb" or "#rrggbbaa", the # is optional
+    pub fn from_hex(hex: &str) -> Result<Self, &'static str> {
+        let digits = hex.strip_prefix('#').unwrap_or(hex);
+        if !(digits.len() == 6 || digits.len() == 8) || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err("Expected a color like #rrggbb or #rrggbbaa");
+        }
+
+        let channel = |i: usize| u8::from_str_radix(&digits[i * 2..i * 2 + 2], 16).unwrap();
+        let a = if digits.len() == 8 { channel(3) } else { 255 };
+
+        Ok(Color::srgb8(channel(0), channel(1), channel(2), a))
+    }
+
+    pub fn to_hex(&self) -> String {
+        let [r, g, b, a] = self.to_srgb8();
+        format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a)
+    }
+
+    pub fn to_srgb8(&self) -> [u8; 4] {
+        let channel = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+        [channel(self.r), channel(self.g), channel(self.b), channel(self.a)]
+    }
+
+    // Hue in degrees, saturation and value in [0, 1]
+    pub fn from_hsv(hue: f32, saturation: f32, value: f32, a: f32) -> Self {
+        let h = hue.rem_euclid(360.0) / 60.0;
+        let chroma = value * saturation;
+        let x = chroma * (1.0 - (h % 2.0 - 1.0).abs());
+        let (r, g, b) = match h as u32 {
+            0 => (chroma, x, 0.0),
+            1 => (x, chroma, 0.0),
+            2 => (0.0, chroma, x),
+            3 => (0.0, x, chroma),
+            4 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x),
+        };
+        let m = value - chroma;
+
+        Color::srgb(r + m, g + m, b + m, a)
+    }
+
+    // (hue, saturation, value), the hue of grays is 0
+    pub fn to_hsv(&self) -> (f32, f32, f32) {
+        let max = self.r.max(self.g).max(self.b);
+        let chroma = max - self.r.min(self.g).min(self.b);
+        let hue = if chroma == 0.0 {
+            0.0
+        } else if max == self.r {
+            60.0 * ((self.g - self.b) / chroma).rem_euclid(6.0)
+        } else if max == self.g {
+            60.0 * ((self.b - self.r) / chroma + 2.0)
+        } else {
+            60.0 * ((self.r - self.g) / chroma + 4.0)
+        };
+        let saturation = if max == 0.0 { 0.0 } else { chroma / max };
+
+        (hue, saturation, max)
+    }
+
+    pub fn with_alpha(mut self, a: f32) -> Self {
+        self.a = a;
+        self
+    }
+
+    // What shaders writing to sRGB targets expect
+    pub fn to_linear(&self) -> Vector4<f32> {
+        Vector4::new(srgb_to_linear(self.r), srgb_to_linear(self.g), srgb_to_linear(self.b), self.a)
+    }
+
+    // Blended in linear space, like the GPU does
+    pub fn mix(&self, other: Color, f: f32) -> Color {
+        let (a, b) = (self.to_linear(), other.to_linear());
+        let mixed = a + (b - a) * f;
+        Color::linear(mixed.x, mixed.y, mixed.z, mixed.w)
+    }
+}
+
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_color_eq(a: Color, b: Color) {
+        let (a, b) = ([a.r, a.g, a.b, a.a], [b.r, b.g, b.b, b.a]);
+        assert!(a.iter().zip(b.iter()).all(|(a, b)| (a - b).abs() < 1e-4), "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn color_conversions() {
+        let color = Color::from_hex("#336699cc").unwrap();
+        assert_eq!(color, Color::srgb8(0x33, 0x66, 0x99, 0xcc));
+        assert_eq!(color.to_hex(), "#336699cc");
+        assert_eq!(Color::from_hex("FF0000").unwrap(), Color::srgb(1.0, 0.0, 0.0, 1.0));
+        assert!(Color::from_hex("#12345").is_err());
+        assert!(Color::from_hex("#gg0000").is_err());
+
+        let linear = Color::srgb(0.5, 0.0, 1.0, 0.5).to_linear();
+        assert!((linear.x - 0.214).abs() < 1e-3);
+        assert_eq!((linear.y, linear.z, linear.w), (0.0, 1.0, 0.5));
+        assert_color_eq(Color::linear(linear.x, linear.y, linear.z, linear.w), Color::srgb(0.5, 0.0, 1.0, 0.5));
+
+        assert_eq!(Color::from_hsv(0.0, 1.0, 1.0, 1.0), Color::srgb(1.0, 0.0, 0.0, 1.0));
+        assert_eq!(Color::from_hsv(240.0, 1.0, 0.5, 1.0), Color::srgb(0.0, 0.0, 0.5, 1.0));
+        assert_color_eq(Color::from_hsv(-60.0, 0.5, 1.0, 1.0), Color::srgb(1.0, 0.5, 1.0, 1.0));
+        let (h, s, v) = color.to_hsv();
+        assert_color_eq(Color::from_hsv(h, s, v, color.a), color);
+        assert_eq!(Color::srgb(0.4, 0.4, 0.4, 1.0).to_hsv(), (0.0, 0.0, 0.4));
+
+        // Linear light, the middle of black and white is brighter than 0.5
+        assert!(Color::BLACK.mix(Color::WHITE, 0.5).r > 0.7);
+    }
+}
@@ -0,0 +1,239 @@
+use std::f32::consts::PI;
+use std::ops::{Add, Mul, Sub};
+
+// Maps the progress t in [0, 1] of an animation to the eased progress, 0 and 1 stay in place. Back overshoots
+// below 0 or above 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Easing {
+    #[default]
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    SineIn,
+    SineOut,
+    SineInOut,
+    BackIn,
+    BackOut,
+    BounceOut,
+}
+
+const BACK_OVERSHOOT: f32 = 1.70158;
+
+impl Easing {
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::QuadIn => t * t,
+            Easing::QuadOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Easing::QuadInOut => _in_out(t, |t| t * t),
+            Easing::CubicIn => t * t * t,
+            Easing::CubicOut => 1.0 - (1.0 - t).powi(3),
+            Easing::CubicInOut => _in_out(t, |t| t * t * t),
+            Easing::SineIn => 1.0 - (t * PI / 2.0).cos(),
+            Easing::SineOut => (t * PI / 2.0).sin(),
+            Easing::SineInOut => (1.0 - (t * PI).cos()) / 2.0,
+            Easing::BackIn => _back_in(t),
+            Easing::BackOut => 1.0 - _back_in(1.0 - t),
+            Easing::BounceOut => _bounce_out(t),
+        }
+    }
+}
+
+// First half eased in, second half mirrored
+fn _in_out(t: f32, ease_in: impl Fn(f32) -> f32) -> f32 {
+    if t < 0.5 {
+        ease_in(t * 2.0) / 2.0
+    } else {
+        1.0 - ease_in((1.0 - t) * 2.0) / 2.0
+    }
+}
+
+fn _back_in(t: f32) -> f32 {
+    t * t * ((BACK_OVERSHOOT + 1.0) * t - BACK_OVERSHOOT)
+}
+
+fn _bounce_out(t: f32) -> f32 {
+    const N: f32 = 7.5625;
+    const D: f32 = 2.75;
+    if t < 1.0 / D {
+        N * t * t
+    } else if t < 2.0 / D {
+        let t = t - 1.5 / D;
+        N * t * t + 0.75
+    } else if t < 2.5 / D {
+        let t = t - 2.25 / D;
+        N * t * t + 0.9375
+    } else {
+        let t = t - 2.625 / D;
+        N * t * t + 0.984375
+    }
+}
+
+// Values a curve can interpolate, e.g. f32 and the cgmath vectors
+pub trait Interpolate: Copy + Add<Output = Self> + Sub<Output = Self> + Mul<f32, Output = Self> {}
+
+impl<T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f32, Output = T>> Interpolate for T {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CurveInterpolation {
+    // Holds the value of a key until the next
+    Step,
+    Linear,
+    // Smooth through all keys, the tangent of a key points from the key before it to the one after
+    CatmullRom,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CurveKey<T> {
+    pub time_s: f32,
+    pub value: T,
+    // Of the segment to the next key
+    pub easing: Easing,
+}
+
+// Keyframed value over time, e.g. a camera path or the position of a UI element. Evaluating before the first key
+// or after the last one returns the value of that key.
+#[derive(Clone, Debug)]
+pub struct Curve<T: Interpolate> {
+    interpolation: CurveInterpolation,
+    keys: Vec<CurveKey<T>>,
+}
+
+impl<T: Interpolate> Curve<T> {
+    pub fn new(interpolation: CurveInterpolation) -> Self {
+        Curve {
+            interpolation,
+            keys: Vec::new(),
+        }
+    }
+
+    // Keys are added in time order
+    pub fn with_key(self, time_s: f32, value: T) -> Self {
+        self.with_eased_key(time_s, value, Easing::Linear)
+    }
+
+    pub fn with_eased_key(mut self, time_s: f32, value: T, easing: Easing) -> Self {
+        assert!(
+            self.keys.last().is_none_or(|last| last.time_s < time_s),
+            "Curve keys must be added in time order"
+        );
+        self.keys.push(CurveKey { time_s, value, easing });
+
+        self
+    }
+
+    pub fn keys(&self) -> &[CurveKey<T>] {
+        &self.keys
+    }
+
+    // Time of the last key
+    pub fn duration_s(&self) -> f32 {
+        self.keys.last().map_or(0.0, |key| key.time_s)
+    }
+
+    pub fn evaluate(&self, time_s: f32) -> T {
+        assert!(!self.keys.is_empty(), "Curve has no keys");
+
+        let next = match self.keys.iter().position(|key| key.time_s > time_s) {
+            Some(0) => return self.keys[0].value,
+            Some(next) => next,
+            None => return self.keys.last().unwrap().value,
+        };
+        let (a, b) = (&self.keys[next - 1], &self.keys[next]);
+        let t = a.easing.apply((time_s - a.time_s) / (b.time_s - a.time_s));
+
+        match self.interpolation {
+            CurveInterpolation::Step => a.value,
+            CurveInterpolation::Linear => a.value + (b.value - a.value) * t,
+            CurveInterpolation::CatmullRom => {
+                // The end keys are repeated
+                let before = self.keys[next.saturating_sub(2)].value;
+                let after = self.keys[(next + 1).min(self.keys.len() - 1)].value;
+                catmull_rom(before, a.value, b.value, after, t)
+            }
+        }
+    }
+
+    // Repeats the curve from its first key after the last one
+    pub fn evaluate_looped(&self, time_s: f32) -> T {
+        let start_s = self.keys.first().map_or(0.0, |key| key.time_s);
+        let length_s = self.duration_s() - start_s;
+        if length_s <= 0.0 {
+            return self.evaluate(time_s);
+        }
+
+        self.evaluate(start_s + (time_s - start_s).rem_euclid(length_s))
+    }
+}
+
+// Uniform Catmull-Rom spline between p1 and p2
+pub fn catmull_rom<T: Interpolate>(p0: T, p1: T, p2: T, p3: T, t: f32) -> T {
+    let t2 = t * t;
+    let t3 = t2 * t;
+
+    (p1 * 2.0 + (p2 - p0) * t + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2 + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3)
+        * 0.5
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::Vector2;
+
+    #[test]
+    fn easing_and_curves() {
+        let easings = [
+            Easing::Linear,
+            Easing::QuadIn,
+            Easing::QuadOut,
+            Easing::QuadInOut,
+            Easing::CubicIn,
+            Easing::CubicOut,
+            Easing::CubicInOut,
+            Easing::SineIn,
+            Easing::SineOut,
+            Easing::SineInOut,
+            Easing::BackIn,
+            Easing::BackOut,
+            Easing::BounceOut,
+        ];
+        for easing in easings.iter() {
+            assert!(easing.apply(0.0).abs() < 1e-5, "{:?}", easing);
+            assert!((easing.apply(1.0) - 1.0).abs() < 1e-5, "{:?}", easing);
+        }
+        assert_eq!(Easing::QuadInOut.apply(0.5), 0.5);
+        assert_eq!(Easing::QuadIn.apply(2.0), 1.0);
+        assert!(Easing::BackIn.apply(0.2) < 0.0);
+
+        let curve = Curve::new(CurveInterpolation::Linear)
+            .with_key(1.0, 0.0)
+            .with_eased_key(2.0, 10.0, Easing::QuadIn)
+            .with_key(3.0, 20.0);
+        assert_eq!(curve.evaluate(0.0), 0.0);
+        assert_eq!(curve.evaluate(1.5), 5.0);
+        assert_eq!(curve.evaluate(2.5), 12.5);
+        assert_eq!(curve.evaluate(5.0), 20.0);
+        assert_eq!(curve.evaluate_looped(3.5), 5.0);
+        assert_eq!(curve.duration_s(), 3.0);
+
+        let step = Curve::new(CurveInterpolation::Step).with_key(0.0, 1.0).with_key(1.0, 2.0);
+        assert_eq!(step.evaluate(0.99), 1.0);
+        assert_eq!(step.evaluate(1.0), 2.0);
+
+        // Passes through the keys, linear in x as its keys are evenly spaced
+        let path = Curve::new(CurveInterpolation::CatmullRom)
+            .with_key(0.0, Vector2::new(0.0, 0.0))
+            .with_key(1.0, Vector2::new(1.0, 1.0))
+            .with_key(2.0, Vector2::new(2.0, 2.0))
+            .with_key(3.0, Vector2::new(3.0, 0.0));
+        assert_eq!(path.evaluate(1.0), Vector2::new(1.0, 1.0));
+        assert_eq!(path.evaluate(1.5).x, 1.5);
+        let between = path.evaluate(2.5);
+        assert!(between.x > 2.0 && between.x < 3.0 && between.y > 1.0);
+    }
+}
@@ -0,0 +1,380 @@
+use cgmath::{InnerSpace, Matrix, Matrix4, Vector3, Vector4};
+use serde::{Deserialize, Serialize};
+
+pub mod color;
+pub mod curve;
+pub mod noise;
+pub mod transform;
+
+pub fn lerp(a: f32, b: f32, f: f32) -> f32 {
+    a * (1.0 - f) + (b * f)
+}
+
+// Axis aligned bounding box
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Aabb {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+}
+
+impl Aabb {
+    pub fn new(min: Vector3<f32>, max: Vector3<f32>) -> Self {
+        Aabb { min, max }
+    }
+
+    // Zero sized box at the origin without points
+    pub fn from_points(points: impl IntoIterator<Item = Vector3<f32>>) -> Self {
+        let mut points = points.into_iter();
+        let first = match points.next() {
+            Some(point) => point,
+            None => return Aabb::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0)),
+        };
+
+        points.fold(Aabb::new(first, first), |aabb, point| {
+            Aabb::new(
+                Vector3::new(aabb.min.x.min(point.x), aabb.min.y.min(point.y), aabb.min.z.min(point.z)),
+                Vector3::new(aabb.max.x.max(point.x), aabb.max.y.max(point.y), aabb.max.z.max(point.z)),
+            )
+        })
+    }
+
+    pub fn center(&self) -> Vector3<f32> {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn size(&self) -> Vector3<f32> {
+        self.max - self.min
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb::new(
+            Vector3::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y), self.min.z.min(other.min.z)),
+            Vector3::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y), self.max.z.max(other.max.z)),
+        )
+    }
+
+    // Grown by the margin on every side
+    pub fn expand(&self, margin: f32) -> Aabb {
+        let margin = Vector3::new(margin, margin, margin);
+        Aabb::new(self.min - margin, self.max + margin)
+    }
+
+    pub fn contains(&self, other: &Aabb) -> bool {
+        self.min.x <= other.min.x
+            && self.min.y <= other.min.y
+            && self.min.z <= other.min.z
+            && self.max.x >= other.max.x
+            && self.max.y >= other.max.y
+            && self.max.z >= other.max.z
+    }
+
+    pub fn contains_point(&self, point: Vector3<f32>) -> bool {
+        self.contains(&Aabb::new(point, point))
+    }
+
+    // Touching boxes intersect
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.x <= other.max.x
+            && self.min.y <= other.max.y
+            && self.min.z <= other.max.z
+            && self.max.x >= other.min.x
+            && self.max.y >= other.min.y
+            && self.max.z >= other.min.z
+    }
+
+    pub fn intersects_sphere(&self, center: Vector3<f32>, radius: f32) -> bool {
+        let closest = Vector3::new(
+            center.x.clamp(self.min.x, self.max.x),
+            center.y.clamp(self.min.y, self.max.y),
+            center.z.clamp(self.min.z, self.max.z),
+        );
+        (closest - center).magnitude2() <= radius * radius
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let size = self.size();
+        2.0 * (size.x * size.y + size.y * size.z + size.z * size.x)
+    }
+}
+
+// The planes bounding a view volume with their normals pointing inside, e.g. of Camera::frustum. The far plane
+// of an infinite projection contains every point.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frustum {
+    // Normal and distance, normalized
+    planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    // Clip space like OpenGL, depth from -w to w as with cgmath::perspective
+    pub fn from_matrix(view_projection: Matrix4<f32>) -> Self {
+        let (x, y, z, w) = (
+            view_projection.row(0),
+            view_projection.row(1),
+            view_projection.row(2),
+            view_projection.row(3),
+        );
+        let mut planes = [w + x, w - x, w + y, w - y, w + z, w - z];
+        for plane in planes.iter_mut() {
+            let length = plane.truncate().magnitude();
+            if length > f32::EPSILON {
+                *plane /= length;
+            }
+        }
+
+        Frustum { planes }
+    }
+
+    pub fn contains_point(&self, point: Vector3<f32>) -> bool {
+        self.intersects_sphere(point, 0.0)
+    }
+
+    pub fn intersects_sphere(&self, center: Vector3<f32>, radius: f32) -> bool {
+        self.planes.iter().all(|plane| plane.truncate().dot(center) + plane.w >= -radius)
+    }
+
+    // Conservative, boxes near the corners outside of the frustum may pass
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the normal
+            let corner = Vector3::new(
+                if plane.x >= 0.0 { aabb.max.x } else { aabb.min.x },
+                if plane.y >= 0.0 { aabb.max.y } else { aabb.min.y },
+                if plane.z >= 0.0 { aabb.max.z } else { aabb.min.z },
+            );
+            plane.truncate().dot(corner) + plane.w >= 0.0
+        })
+    }
+}
+
+// Half line starting at the origin, e.g. a picking ray through the mouse cursor
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: Vector3<f32>,
+    // Normalized
+    pub direction: Vector3<f32>,
+}
+
+impl Ray {
+    pub fn new(origin: Vector3<f32>, direction: Vector3<f32>) -> Self {
+        Ray {
+            origin,
+            direction: direction.normalize(),
+        }
+    }
+
+    pub fn at(&self, distance: f32) -> Vector3<f32> {
+        self.origin + self.direction * distance
+    }
+
+    // Distance to the plane through the point, None if the ray is parallel to it or points away from it
+    pub fn intersect_plane(&self, point: Vector3<f32>, normal: Vector3<f32>) -> Option<f32> {
+        let denominator = self.direction.dot(normal);
+        if denominator.abs() < f32::EPSILON {
+            return None;
+        }
+
+        let distance = (point - self.origin).dot(normal) / denominator;
+        (distance >= 0.0).then_some(distance)
+    }
+
+    // Distance to where the ray enters the box, 0 if it starts inside. None if it misses the box.
+    pub fn intersect_aabb(&self, aabb: &Aabb) -> Option<f32> {
+        let mut near = 0.0f32;
+        let mut far = f32::INFINITY;
+        for axis in 0..3 {
+            let inverse = 1.0 / self.direction[axis];
+            let a = (aabb.min[axis] - self.origin[axis]) * inverse;
+            let b = (aabb.max[axis] - self.origin[axis]) * inverse;
+            // NaN when the ray runs along a side of the box, min and max ignore it
+            near = near.max(a.min(b));
+            far = far.min(a.max(b));
+        }
+
+        (near <= far).then_some(near)
+    }
+
+    // Distance along the ray and along the line of the closest points between both, None if they are parallel.
+    // The line direction must be normalized.
+    pub fn closest_to_line(&self, point: Vector3<f32>, direction: Vector3<f32>) -> Option<(f32, f32)> {
+        let offset = self.origin - point;
+        let cos = self.direction.dot(direction);
+        let denominator = 1.0 - cos * cos;
+        if denominator < f32::EPSILON {
+            return None;
+        }
+
+        let ray_offset = self.direction.dot(offset);
+        let line_offset = direction.dot(offset);
+        Some((
+            (cos * line_offset - ray_offset) / denominator,
+            (line_offset - cos * ray_offset) / denominator,
+        ))
+    }
+}
+
+// Seedable xoshiro256** generator, the same seed gives the same numbers on every platform. Systems that need
+// their own numbers, e.g. terrain and particles, take a stream with split so that they don't change each other's
+// sequences when they draw more or fewer numbers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rng {
+    state: [u64; 4],
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // splitmix64 expands the seed, the state must not be all zeros
+        let mut splitmix = seed;
+        let mut state = [0; 4];
+        for word in state.iter_mut() {
+            *word = _splitmix64(&mut splitmix);
+        }
+
+        Rng { state }
+    }
+
+    // Independent generator for a numbered stream of the seed, the same stream of a seed is always the same
+    pub fn from_stream(seed: u64, stream: u64) -> Self {
+        let mut splitmix = stream;
+        Rng::new(seed ^ _splitmix64(&mut splitmix))
+    }
+
+    // Returns a generator continuing this sequence and jumps this one 2^128 numbers ahead, so that both never
+    // overlap
+    pub fn split(&mut self) -> Rng {
+        let stream = self.clone();
+        self.jump();
+
+        stream
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let result = self.state[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = self.state[1] << 17;
+
+        self.state[2] ^= self.state[0];
+        self.state[3] ^= self.state[1];
+        self.state[1] ^= self.state[2];
+        self.state[0] ^= self.state[3];
+        self.state[2] ^= t;
+        self.state[3] = self.state[3].rotate_left(45);
+
+        result
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    // In [0, 1)
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    // In [min, max)
+    pub fn range_f32(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+
+    // In [min, max), the bias of the modulo is negligible for ranges far below 2^64
+    pub fn range_u32(&mut self, min: u32, max: u32) -> u32 {
+        assert!(min < max, "Empty range");
+        min + (self.next_u64() % (max - min) as u64) as u32
+    }
+
+    pub fn chance(&mut self, probability: f32) -> bool {
+        self.next_f32() < probability
+    }
+
+    fn jump(&mut self) {
+        const JUMP: [u64; 4] = [
+            0x180e_c6d3_3cfd_0aba,
+            0xd5a6_1266_f0c9_392c,
+            0xa958_2618_e03f_c9aa,
+            0x39ab_dc45_29b1_661c,
+        ];
+
+        let mut state = [0; 4];
+        for jump in JUMP.iter() {
+            for bit in 0..64 {
+                if jump & (1u64 << bit) != 0 {
+                    for (word, current) in state.iter_mut().zip(self.state.iter()) {
+                        *word ^= current;
+                    }
+                }
+                self.next_u64();
+            }
+        }
+        self.state = state;
+    }
+}
+
+fn _splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/*
+pub fn lerp(a: f32, b: f32, f: f32) -> f32 {
+    a * (1.0 - f) + (b * f)
+}
+*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rng_streams() {
+        let sequence = |rng: &mut Rng| (0..8).map(|_| rng.next_u64()).collect::<Vec<u64>>();
+        assert_eq!(sequence(&mut Rng::new(42)), sequence(&mut Rng::new(42)));
+        assert_ne!(sequence(&mut Rng::new(42)), sequence(&mut Rng::new(43)));
+        assert_eq!(sequence(&mut Rng::from_stream(42, 1)), sequence(&mut Rng::from_stream(42, 1)));
+        assert_ne!(sequence(&mut Rng::from_stream(42, 1)), sequence(&mut Rng::from_stream(42, 2)));
+
+        // The split stream continues the sequence, the parent moves on to another one
+        let mut parent = Rng::new(7);
+        let mut stream = parent.split();
+        let expected = sequence(&mut Rng::new(7));
+        assert_eq!(sequence(&mut stream), expected);
+        assert_ne!(sequence(&mut parent), expected);
+
+        let mut rng = Rng::new(1);
+        for _ in 0..1000 {
+            let value = rng.next_f32();
+            assert!((0.0..1.0).contains(&value));
+            let value = rng.range_f32(-2.0, 3.0);
+            assert!((-2.0..3.0).contains(&value));
+            assert!((5..8).contains(&rng.range_u32(5, 8)));
+        }
+        assert!(!rng.chance(0.0));
+        assert!(rng.chance(1.0));
+    }
+
+    #[test]
+    fn bounds_and_frustum() {
+        let aabb = Aabb::new(Vector3::new(-1.0, -1.0, -1.0), Vector3::new(1.0, 1.0, 1.0));
+        assert!(aabb.intersects(&Aabb::new(Vector3::new(1.0, 0.0, 0.0), Vector3::new(2.0, 1.0, 1.0))));
+        assert!(!aabb.intersects_sphere(Vector3::new(2.0, 2.0, 0.0), 1.0));
+        let point = Vector3::new(2.0, 0.0, 0.0);
+        assert!(aabb.expand(1.0).contains(&aabb.union(&Aabb::new(point, point))));
+        assert_eq!(aabb.surface_area(), 24.0);
+
+        assert_eq!(Ray::new(Vector3::new(-5.0, 0.5, 0.0), Vector3::unit_x()).intersect_aabb(&aabb), Some(4.0));
+        assert_eq!(Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::unit_y()).intersect_aabb(&aabb), Some(0.0));
+        assert_eq!(Ray::new(Vector3::new(-5.0, 0.5, 0.0), -Vector3::unit_x()).intersect_aabb(&aabb), None);
+        assert_eq!(Ray::new(Vector3::new(-5.0, 2.0, 0.0), Vector3::unit_x()).intersect_aabb(&aabb), None);
+
+        // Looking down -z from the origin
+        let frustum = Frustum::from_matrix(cgmath::perspective(cgmath::Deg(90.0), 1.0, 0.1, 100.0));
+        assert!(frustum.contains_point(Vector3::new(0.0, 0.0, -10.0)));
+        assert!(!frustum.contains_point(Vector3::new(0.0, 0.0, 10.0)));
+        assert!(!frustum.contains_point(Vector3::new(0.0, 0.0, -200.0)));
+        assert!(!frustum.contains_point(Vector3::new(20.0, 0.0, -10.0)));
+        assert!(frustum.intersects_sphere(Vector3::new(11.0, 0.0, -10.0), 1.0));
+        assert!(frustum.intersects_aabb(&aabb));
+        assert!(!frustum.intersects_aabb(&Aabb::new(Vector3::new(-1.0, -1.0, 1.0), Vector3::new(1.0, 1.0, 2.0))));
+    }
+}
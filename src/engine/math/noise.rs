@@ -0,0 +1,304 @@
+// Gradient noise for procedural content, in [-1, 1]. The lattice is hashed instead of shuffled into a permutation
+// table, which makes the functions match resources/shaders/noise.glsl for the same seed.
+pub trait Noise {
+    fn get2(&self, x: f32, y: f32) -> f32;
+    fn get3(&self, x: f32, y: f32, z: f32) -> f32;
+}
+
+// Zero on every lattice point
+#[derive(Clone, Copy, Debug)]
+pub struct Perlin {
+    seed: u32,
+}
+
+impl Perlin {
+    pub fn new(seed: u32) -> Self {
+        Perlin { seed }
+    }
+}
+
+impl Noise for Perlin {
+    fn get2(&self, x: f32, y: f32) -> f32 {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (xi, yi) = (x0 as i32, y0 as i32);
+        let (fx, fy) = (x - x0, y - y0);
+        let (u, v) = (_fade(fx), _fade(fy));
+
+        let n00 = _grad2(_hash2(self.seed, xi, yi), fx, fy);
+        let n10 = _grad2(_hash2(self.seed, xi + 1, yi), fx - 1.0, fy);
+        let n01 = _grad2(_hash2(self.seed, xi, yi + 1), fx, fy - 1.0);
+        let n11 = _grad2(_hash2(self.seed, xi + 1, yi + 1), fx - 1.0, fy - 1.0);
+
+        _lerp(_lerp(n00, n10, u), _lerp(n01, n11, u), v)
+    }
+
+    fn get3(&self, x: f32, y: f32, z: f32) -> f32 {
+        let (x0, y0, z0) = (x.floor(), y.floor(), z.floor());
+        let (xi, yi, zi) = (x0 as i32, y0 as i32, z0 as i32);
+        let (fx, fy, fz) = (x - x0, y - y0, z - z0);
+        let (u, v, w) = (_fade(fx), _fade(fy), _fade(fz));
+
+        let corner = |dx: i32, dy: i32, dz: i32| {
+            let hash = _hash3(self.seed, xi + dx, yi + dy, zi + dz);
+            _grad3(hash, fx - dx as f32, fy - dy as f32, fz - dz as f32)
+        };
+        let near = _lerp(_lerp(corner(0, 0, 0), corner(1, 0, 0), u), _lerp(corner(0, 1, 0), corner(1, 1, 0), u), v);
+        let far = _lerp(_lerp(corner(0, 0, 1), corner(1, 0, 1), u), _lerp(corner(0, 1, 1), corner(1, 1, 1), u), v);
+
+        _lerp(near, far, w)
+    }
+}
+
+// Cheaper than Perlin in 3D and without its axis aligned artifacts
+#[derive(Clone, Copy, Debug)]
+pub struct Simplex {
+    seed: u32,
+}
+
+impl Simplex {
+    pub fn new(seed: u32) -> Self {
+        Simplex { seed }
+    }
+}
+
+const F2: f32 = 0.366_025_42; // (sqrt(3) - 1) / 2
+const G2: f32 = 0.211_324_87; // (3 - sqrt(3)) / 6
+const F3: f32 = 1.0 / 3.0;
+const G3: f32 = 1.0 / 6.0;
+
+impl Noise for Simplex {
+    fn get2(&self, x: f32, y: f32) -> f32 {
+        // Skewed into the cell of the first corner
+        let s = (x + y) * F2;
+        let (i, j) = ((x + s).floor(), (y + s).floor());
+        let t = (i + j) * G2;
+        let (x0, y0) = (x - (i - t), y - (j - t));
+        let (i1, j1) = if x0 > y0 { (1, 0) } else { (0, 1) };
+
+        let (xi, yi) = (i as i32, j as i32);
+        let corners = [
+            (xi, yi, x0, y0),
+            (xi + i1, yi + j1, x0 - i1 as f32 + G2, y0 - j1 as f32 + G2),
+            (xi + 1, yi + 1, x0 - 1.0 + 2.0 * G2, y0 - 1.0 + 2.0 * G2),
+        ];
+        let sum: f32 = corners
+            .iter()
+            .map(|(cx, cy, dx, dy)| {
+                let t = 0.5 - dx * dx - dy * dy;
+                if t < 0.0 {
+                    0.0
+                } else {
+                    t * t * t * t * _grad2(_hash2(self.seed, *cx, *cy), *dx, *dy)
+                }
+            })
+            .sum();
+
+        70.0 * sum
+    }
+
+    fn get3(&self, x: f32, y: f32, z: f32) -> f32 {
+        let s = (x + y + z) * F3;
+        let (i, j, k) = ((x + s).floor(), (y + s).floor(), (z + s).floor());
+        let t = (i + j + k) * G3;
+        let (x0, y0, z0) = (x - (i - t), y - (j - t), z - (k - t));
+
+        // Second and third corner of the simplex, by the order of the offsets
+        let (o1, o2) = if x0 >= y0 {
+            if y0 >= z0 {
+                ((1, 0, 0), (1, 1, 0))
+            } else if x0 >= z0 {
+                ((1, 0, 0), (1, 0, 1))
+            } else {
+                ((0, 0, 1), (1, 0, 1))
+            }
+        } else if y0 < z0 {
+            ((0, 0, 1), (0, 1, 1))
+        } else if x0 < z0 {
+            ((0, 1, 0), (0, 1, 1))
+        } else {
+            ((0, 1, 0), (1, 1, 0))
+        };
+
+        let (xi, yi, zi) = (i as i32, j as i32, k as i32);
+        let corner = |(oi, oj, ok): (i32, i32, i32), g: f32| {
+            let (dx, dy, dz) = (x0 - oi as f32 + g, y0 - oj as f32 + g, z0 - ok as f32 + g);
+            let t = 0.6 - dx * dx - dy * dy - dz * dz;
+            if t < 0.0 {
+                0.0
+            } else {
+                t * t * t * t * _grad3(_hash3(self.seed, xi + oi, yi + oj, zi + ok), dx, dy, dz)
+            }
+        };
+
+        32.0 * (corner((0, 0, 0), 0.0) + corner(o1, G3) + corner(o2, 2.0 * G3) + corner((1, 1, 1), 3.0 * G3))
+    }
+}
+
+// Fractal Brownian motion, octaves of the source noise with rising frequency and falling amplitude. Normalized
+// by the sum of the amplitudes, so it stays in the range of the source.
+#[derive(Clone, Copy, Debug)]
+pub struct Fbm<N: Noise> {
+    source: N,
+    octaves: u32,
+    frequency: f32,
+    lacunarity: f32,
+    persistence: f32,
+}
+
+impl<N: Noise> Fbm<N> {
+    pub fn new(source: N) -> Self {
+        Fbm {
+            source,
+            octaves: 4,
+            frequency: 1.0,
+            lacunarity: 2.0,
+            persistence: 0.5,
+        }
+    }
+
+    pub fn with_octaves(mut self, octaves: u32) -> Self {
+        assert!(octaves > 0, "Fbm needs at least one octave");
+        self.octaves = octaves;
+        self
+    }
+
+    // Of the first octave
+    pub fn with_frequency(mut self, frequency: f32) -> Self {
+        self.frequency = frequency;
+        self
+    }
+
+    // Frequency multiplier between octaves
+    pub fn with_lacunarity(mut self, lacunarity: f32) -> Self {
+        self.lacunarity = lacunarity;
+        self
+    }
+
+    // Amplitude multiplier between octaves
+    pub fn with_persistence(mut self, persistence: f32) -> Self {
+        self.persistence = persistence;
+        self
+    }
+
+    fn sum(&self, sample: impl Fn(f32) -> f32) -> f32 {
+        let mut frequency = self.frequency;
+        let mut amplitude = 1.0;
+        let mut total = 0.0;
+        let mut total_amplitude = 0.0;
+        for _ in 0..self.octaves {
+            total += amplitude * sample(frequency);
+            total_amplitude += amplitude;
+            frequency *= self.lacunarity;
+            amplitude *= self.persistence;
+        }
+
+        total / total_amplitude
+    }
+}
+
+impl<N: Noise> Noise for Fbm<N> {
+    fn get2(&self, x: f32, y: f32) -> f32 {
+        self.sum(|frequency| self.source.get2(x * frequency, y * frequency))
+    }
+
+    fn get3(&self, x: f32, y: f32, z: f32) -> f32 {
+        self.sum(|frequency| self.source.get3(x * frequency, y * frequency, z * frequency))
+    }
+}
+
+// Integer hash with good avalanche, see hash() in noise.glsl
+fn _hash(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^ (x >> 16)
+}
+
+fn _hash2(seed: u32, x: i32, y: i32) -> u32 {
+    _hash((x as u32).wrapping_add(_hash((y as u32).wrapping_add(_hash(seed)))))
+}
+
+fn _hash3(seed: u32, x: i32, y: i32, z: i32) -> u32 {
+    _hash((x as u32).wrapping_add(_hash((y as u32).wrapping_add(_hash((z as u32).wrapping_add(_hash(seed)))))))
+}
+
+fn _grad2(hash: u32, x: f32, y: f32) -> f32 {
+    match hash & 7 {
+        0 => x + y,
+        1 => -x + y,
+        2 => x - y,
+        3 => -x - y,
+        4 => x,
+        5 => -x,
+        6 => y,
+        _ => -y,
+    }
+}
+
+// The 12 edges of a cube, 4 of them twice
+fn _grad3(hash: u32, x: f32, y: f32, z: f32) -> f32 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 {
+        y
+    } else if h == 12 || h == 14 {
+        x
+    } else {
+        z
+    };
+
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+fn _fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn _lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples() -> impl Iterator<Item = (f32, f32, f32)> {
+        (0..4000).map(|i| {
+            let i = i as f32;
+            ((i * 0.173).sin() * 50.0, (i * 0.311).cos() * 50.0, i * 0.0137 - 20.0)
+        })
+    }
+
+    #[test]
+    fn noise_ranges() {
+        let sources: [&dyn Noise; 4] = [
+            &Perlin::new(7),
+            &Simplex::new(7),
+            &Fbm::new(Perlin::new(7)).with_octaves(5),
+            &Fbm::new(Simplex::new(7)).with_frequency(0.5),
+        ];
+        for source in sources.iter() {
+            for (x, y, z) in samples() {
+                assert!(source.get2(x, y).abs() <= 1.0);
+                assert!(source.get3(x, y, z).abs() <= 1.0);
+            }
+            // Not constant
+            assert!(samples().any(|(x, y, _)| source.get2(x, y).abs() > 0.2));
+        }
+
+        assert_eq!(Perlin::new(3).get2(-4.0, 12.0), 0.0);
+        assert_eq!(Perlin::new(3).get3(5.0, -1.0, 2.0), 0.0);
+    }
+
+    #[test]
+    fn seeded_noise() {
+        let (a, b) = (Simplex::new(1), Simplex::new(2));
+        assert_eq!(a.get2(0.3, 4.7), Simplex::new(1).get2(0.3, 4.7));
+        assert!(samples().any(|(x, y, z)| a.get3(x, y, z) != b.get3(x, y, z)));
+
+        // A single octave is the source at the base frequency
+        let fbm = Fbm::new(Perlin::new(9)).with_octaves(1).with_frequency(3.0);
+        assert_eq!(fbm.get2(0.25, 0.5), Perlin::new(9).get2(0.75, 1.5));
+    }
+}
@@ -0,0 +1,142 @@
+use std::ops::Mul;
+
+use cgmath::{ElementWise, InnerSpace, Matrix4, One, Quaternion, Vector3, VectorSpace};
+
+// Translation, rotation and scale of an object relative to its parent, applied in the order scale, rotation,
+// translation. Composition and inverse are exact for uniform scales, with non-uniform ones the result has no
+// shear like the matrix product would.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Transform {
+    pub fn new(translation: Vector3<f32>, rotation: Quaternion<f32>, scale: Vector3<f32>) -> Self {
+        Transform {
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    pub fn identity() -> Self {
+        Transform::new(Vector3::new(0.0, 0.0, 0.0), Quaternion::one(), Vector3::new(1.0, 1.0, 1.0))
+    }
+
+    pub fn from_translation(translation: Vector3<f32>) -> Self {
+        Transform {
+            translation,
+            ..Transform::identity()
+        }
+    }
+
+    pub fn with_rotation(mut self, rotation: Quaternion<f32>) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: f32) -> Self {
+        self.scale = Vector3::new(scale, scale, scale);
+        self
+    }
+
+    pub fn with_nonuniform_scale(mut self, scale: Vector3<f32>) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+
+    pub fn transform_point(&self, point: Vector3<f32>) -> Vector3<f32> {
+        self.translation + self.transform_vector(point)
+    }
+
+    // Without the translation, e.g. a direction
+    pub fn transform_vector(&self, vector: Vector3<f32>) -> Vector3<f32> {
+        self.rotation * vector.mul_element_wise(self.scale)
+    }
+
+    pub fn inverse(&self) -> Transform {
+        let rotation = self.rotation.conjugate() / self.rotation.magnitude2();
+        let scale = Vector3::new(1.0 / self.scale.x, 1.0 / self.scale.y, 1.0 / self.scale.z);
+
+        Transform {
+            translation: (rotation * -self.translation).mul_element_wise(scale),
+            rotation,
+            scale,
+        }
+    }
+
+    // Rotations are spherically interpolated
+    pub fn lerp(&self, other: &Transform, f: f32) -> Transform {
+        Transform {
+            translation: self.translation.lerp(other.translation, f),
+            rotation: self.rotation.slerp(other.rotation, f),
+            scale: self.scale.lerp(other.scale, f),
+        }
+    }
+}
+
+// parent * child, the child is transformed first like with matrices
+impl Mul for Transform {
+    type Output = Transform;
+
+    fn mul(self, child: Transform) -> Transform {
+        Transform {
+            translation: self.transform_point(child.translation),
+            rotation: self.rotation * child.rotation,
+            scale: self.scale.mul_element_wise(child.scale),
+        }
+    }
+}
+
+impl From<Transform> for Matrix4<f32> {
+    fn from(transform: Transform) -> Self {
+        transform.matrix()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{Deg, Rotation3, SquareMatrix, Vector4};
+
+    fn assert_matrix_eq(a: Matrix4<f32>, b: Matrix4<f32>) {
+        let (a, b): (&[f32; 16], &[f32; 16]) = (a.as_ref(), b.as_ref());
+        assert!(a.iter().zip(b.iter()).all(|(a, b)| (a - b).abs() < 1e-4), "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn transform_composition() {
+        let parent = Transform::from_translation(Vector3::new(1.0, 2.0, 3.0))
+            .with_rotation(Quaternion::from_angle_y(Deg(90.0)))
+            .with_scale(2.0);
+        let child = Transform::from_translation(Vector3::new(0.0, 0.0, -4.0))
+            .with_rotation(Quaternion::from_angle_x(Deg(30.0)))
+            .with_nonuniform_scale(Vector3::new(1.0, 3.0, 0.5));
+
+        assert_matrix_eq((parent * child).matrix(), parent.matrix() * child.matrix());
+        assert_matrix_eq((parent * parent.inverse()).matrix(), Matrix4::identity());
+        assert_matrix_eq(parent.inverse().matrix(), parent.matrix().invert().unwrap());
+
+        let point = Vector3::new(0.5, -1.0, 2.0);
+        let transformed = parent.matrix() * Vector4::new(point.x, point.y, point.z, 1.0);
+        assert!((parent.transform_point(point) - transformed.truncate()).magnitude() < 1e-4);
+
+        assert_matrix_eq(parent.lerp(&child, 0.0).matrix(), parent.matrix());
+        assert_eq!(parent.lerp(&child, 0.5).scale, Vector3::new(1.5, 2.5, 1.25));
+        assert_eq!(Matrix4::from(Transform::default()), Matrix4::identity());
+    }
+}
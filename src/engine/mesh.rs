@@ -5,6 +5,7 @@ use cgmath::{Vector2, Vector3};
 
 use crate::engine::datatypes::{ColoredVertex, Mesh, SimpleVertex, TexturedVertex, NormalVertex};
 use crate::engine::model::obj;
+use crate::error::VulkrapError;
 use crate::renderer::context::Context;
 
 #[repr(u32)]
@@ -52,16 +53,16 @@ impl MeshManager {
             .expect("Failed to fetch mesh")
     }
 
-    pub fn load_new_mesh(&mut self, context: &mut Context, path: &Path) -> Result<(MeshHandle, &Mesh), &'static str> {
+    pub fn load_new_mesh(&mut self, context: &mut Context, path: &Path) -> Result<(MeshHandle, &Mesh), VulkrapError> {
         let extension = path.extension();
         if extension.is_none() {
-            return Err("Unknown file type");
+            return Err(VulkrapError::InvalidMesh { path: path.to_path_buf(), reason: "Unknown file type" });
         }
 
         let extension = extension.unwrap();
 
         if extension == "obj" {
-            let mesh = obj::load_obj_mesh(context, path).unwrap();
+            let mesh = obj::load_obj_mesh(context, path)?;
             let handle = self.next_handle;
             self.meshes.insert(handle ,mesh);
             self.next_handle = self.next_handle + 1;
@@ -70,7 +71,7 @@ impl MeshManager {
 
         }
 
-        Err("failed to load mesh")
+        Err(VulkrapError::InvalidMesh { path: path.to_path_buf(), reason: "Unsupported file type" })
 
     }
 
@@ -149,3 +150,78 @@ impl MeshManager {
         }
     }
 }
+
+// Quantization of vertex attributes to smaller formats, see VertexLayout::write
+
+// Nearest IEEE half float, out of range values become infinity
+pub fn quantize_half(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exponent == 0xff {
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if exponent <= 0 {
+        // Subnormal half
+        if exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - exponent) as u32;
+        let round = (mantissa >> (shift - 1)) & 1;
+        return sign | ((mantissa >> shift) + round) as u16;
+    }
+
+    // Rounding may carry into the exponent, which is still the nearest value
+    let half = ((exponent as u32) << 10) | (mantissa >> 13);
+    let round = (mantissa >> 12) & 1;
+    sign | (half + round) as u16
+}
+
+pub fn quantize_unorm8(value: f32) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+// A2B10G10R10_UNORM_PACK32, red in the lowest bits
+pub fn pack_rgb10a2(r: f32, g: f32, b: f32, a: f32) -> u32 {
+    let unorm10 = |value: f32| (value.clamp(0.0, 1.0) * 1023.0).round() as u32;
+    let alpha = (a.clamp(0.0, 1.0) * 3.0).round() as u32;
+
+    unorm10(r) | (unorm10(g) << 10) | (unorm10(b) << 20) | (alpha << 30)
+}
+
+// Unit vector mapped to 0..1, decode with n * 2.0 - 1.0 in the shader
+pub fn pack_normal(normal: Vector3<f32>) -> u32 {
+    pack_rgb10a2(normal.x * 0.5 + 0.5, normal.y * 0.5 + 0.5, normal.z * 0.5 + 0.5, 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quantization() {
+        assert_eq!(quantize_half(0.0), 0);
+        assert_eq!(quantize_half(1.0), 0x3c00);
+        assert_eq!(quantize_half(-2.5), 0xc100);
+        assert_eq!(quantize_half(65504.0), 0x7bff);
+        assert_eq!(quantize_half(1.0e6), 0x7c00);
+        assert_eq!(quantize_half(f32::NAN) & 0x7c00, 0x7c00);
+        // Smallest subnormal and underflow
+        assert_eq!(quantize_half(5.960_464_5e-8), 1);
+        assert_eq!(quantize_half(1.0e-9), 0);
+
+        assert_eq!(quantize_unorm8(0.5), 128);
+        assert_eq!(quantize_unorm8(2.0), 255);
+        assert_eq!(pack_rgb10a2(1.0, 0.0, 0.0, 1.0), 0xc000_03ff);
+        assert_eq!(pack_normal(Vector3::new(0.0, 0.0, 1.0)) >> 20, 1023);
+    }
+}
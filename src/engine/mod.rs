@@ -1,17 +1,37 @@
 pub mod datatypes;
 pub mod runtime;
 pub mod camera;
+pub mod billboard;
+pub mod animation;
 pub mod entity;
+pub mod gizmo;
+pub mod gridmap;
+pub mod navigation;
+#[cfg(feature = "physics")]
+pub mod physics;
 pub mod cvars;
 pub mod mesh;
+#[cfg(feature = "terrain")]
 pub mod terrain;
+#[cfg(feature = "ui")]
 pub mod ui;
 pub mod image;
+pub mod impostor;
 pub mod math;
 pub mod model;
+#[cfg(feature = "scripting")]
+pub mod scripting;
+pub mod skybox;
+pub mod spatial;
+pub mod transforms;
+pub mod worldtext;
 
+#[cfg(feature = "console")]
 mod console;
 
+mod recording;
 mod stats;
+mod timedemo;
+mod timescale;
 
 
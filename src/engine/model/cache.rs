@@ -0,0 +1,119 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::engine::datatypes::NormalVertex;
+use crate::engine::math::Aabb;
+
+pub const CACHE_DIR: &str = "./cache/meshes";
+
+// Bump when the layout changes, old files are then ignored
+const VERSION: u32 = 2;
+
+// Mesh as stored in the cache, bincode encoded
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MeshData {
+    pub vertices: Vec<NormalVertex>,
+    pub indices: Vec<u32>,
+    pub bounds: Aabb,
+}
+
+impl MeshData {
+    pub fn new(vertices: Vec<NormalVertex>, indices: Vec<u32>) -> Self {
+        let bounds = Aabb::from_points(vertices.iter().map(|vertex| vertex.position));
+        MeshData {
+            vertices,
+            indices,
+            bounds,
+        }
+    }
+}
+
+// Cache file of the source file contents and the loader options that change the result
+pub fn cache_path(source: &[u8], options: &str) -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    VERSION.hash(&mut hasher);
+    source.hash(&mut hasher);
+    options.hash(&mut hasher);
+
+    Path::new(CACHE_DIR).join(format!("{:016x}.mesh", hasher.finish()))
+}
+
+// None if there is no cache file or it is unreadable
+pub fn read(path: &Path) -> Option<MeshData> {
+    fs::read(path).ok().and_then(|bytes| decode(&bytes))
+}
+
+pub fn write(path: &Path, data: &MeshData) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let bytes = bincode::serialize(data).map_err(std::io::Error::other)?;
+    fs::write(path, bytes)
+}
+
+// Files cut short by an interrupted write or with indices past the vertices are rejected
+fn decode(bytes: &[u8]) -> Option<MeshData> {
+    let data: MeshData = bincode::deserialize(bytes).ok()?;
+    let vertex_count = data.vertices.len();
+    if data.indices.iter().any(|index| *index as usize >= vertex_count) {
+        return None;
+    }
+
+    Some(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::Vector3;
+
+    #[test]
+    fn encode_and_decode() {
+        let vertices = vec![
+            NormalVertex::new(Vector3::new(-1.0, 0.0, 2.0), Vector3::new(0.0, 1.0, 0.0)),
+            NormalVertex::new(Vector3::new(1.0, 3.0, 0.0), Vector3::new(0.0, 1.0, 0.0)),
+            NormalVertex::new(Vector3::new(0.0, -1.0, 0.5), Vector3::new(0.0, 0.0, 1.0)),
+        ];
+        let data = MeshData::new(vertices, vec![0, 1, 2]);
+        assert_eq!(data.bounds, Aabb::new(Vector3::new(-1.0, -1.0, 0.0), Vector3::new(1.0, 3.0, 2.0)));
+
+        let bytes = bincode::serialize(&data).unwrap();
+        let decoded = decode(&bytes).unwrap();
+        assert_eq!(decoded.indices, data.indices);
+        assert_eq!(decoded.bounds, data.bounds);
+        assert_eq!(decoded.vertices[1].position, data.vertices[1].position);
+        assert_eq!(decoded.vertices[2].normal, data.vertices[2].normal);
+
+        // Truncated, out of range indices, garbage and other sources
+        assert!(decode(&bytes[..bytes.len() - 1]).is_none());
+        assert!(decode(&[]).is_none());
+        let mut corrupt = data.clone();
+        corrupt.indices[2] = 7;
+        assert!(decode(&bincode::serialize(&corrupt).unwrap()).is_none());
+        assert!(decode(&[0xff; 64]).is_none());
+        assert_ne!(cache_path(b"v 0 0 0", "FromFile"), cache_path(b"v 0 0 1", "FromFile"));
+        assert_ne!(cache_path(b"v 0 0 0", "FromFile"), cache_path(b"v 0 0 0", "Flat"));
+    }
+
+    #[test]
+    fn read_and_write_files() {
+        let path = std::env::temp_dir().join(format!("vulkrap-cache-test-{}", std::process::id())).join("mesh.mesh");
+        let vertex = NormalVertex::new(Vector3::new(1.0, 2.0, 3.0), Vector3::new(0.0, 0.0, 1.0));
+        let data = MeshData::new(vec![vertex], vec![0]);
+
+        write(&path, &data).unwrap();
+        assert_eq!(read(&path).unwrap().vertices[0].position, Vector3::new(1.0, 2.0, 3.0));
+
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() / 2]).unwrap();
+        assert!(read(&path).is_none());
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        assert!(read(&path).is_none());
+    }
+}
@@ -0,0 +1,547 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use ::gltf::animation::util::ReadOutputs;
+use ::gltf::animation::{Interpolation, Property};
+use ::gltf::buffer::Source;
+use ::gltf::mesh::Mode;
+use ::gltf::{Buffer, Gltf};
+use cgmath::{Matrix4, Quaternion, SquareMatrix, Vector3, VectorSpace};
+use stopwatch::Stopwatch;
+
+use crate::engine::animation::skeletal::{AnimatedModel, AnimationClip, JointPose, Keyframe, Skeleton};
+use crate::engine::datatypes::{Mesh, SkinnedVertex};
+use crate::error::VulkrapError;
+use crate::renderer::context::Context;
+use crate::renderer::types::{Index, SkinnedMeshHandle};
+
+// Keyframes closer than this are merged when the channels of a joint are combined into one track
+const KEYFRAME_EPSILON_S: f32 = 1.0e-6;
+
+// Clips blend between keyframes, step channels hold their value until this long before the next keyframe
+const STEP_HOLD_S: f32 = 1.0e-4;
+
+// The first skin of a glTF file: its joints sorted parents first, every animation of the joints and the triangles
+// of the meshes using the skin. Vertex joints are indices into the skeleton.
+pub struct SkinnedModelData {
+    pub skeleton: Skeleton,
+    pub clips: Vec<AnimationClip>,
+    pub vertices: Vec<SkinnedVertex>,
+    pub indices: Vec<Index>,
+}
+
+// mesh draws the vertex buffer written by the skinning pass, queue skinned_mesh with Frame::skin_mesh every frame
+pub struct SkinnedModel {
+    pub animated: AnimatedModel,
+    pub skinned_mesh: SkinnedMeshHandle,
+    pub mesh: Mesh,
+}
+
+// Keyframes of one animated property of a joint
+struct Channel {
+    property: Property,
+    step: bool,
+    times: Vec<f32>,
+    // xyz of translations and scales, xyzw of rotations
+    values: Vec<[f32; 4]>,
+}
+
+pub fn load_gltf_skinned_model(context: &mut Context, path: &Path) -> Result<SkinnedModel, VulkrapError> {
+    let sw = Stopwatch::start_new();
+    let data = load_gltf_skinned_data(path)?;
+    let model = create_skinned_model(context, data);
+
+    log_info!("loaded skinned model in {} ms", sw.elapsed_ms());
+
+    Ok(model)
+}
+
+// For data changed after loading, e.g. clips given markers
+pub fn create_skinned_model(context: &mut Context, data: SkinnedModelData) -> SkinnedModel {
+    let animated = data
+        .clips
+        .into_iter()
+        .fold(AnimatedModel::new(context, data.skeleton), |model, clip| {
+            model.with_clip(clip)
+        });
+    let skinned_mesh = animated.add_skinned_mesh(context, &data.vertices);
+    let index_buffer = context.create_static_index_buffer_sync(&data.indices);
+    let mesh = Mesh::new(
+        context.skinned_vertex_buffer(skinned_mesh),
+        index_buffer,
+        data.indices.len() as u32,
+    );
+
+    SkinnedModel {
+        animated,
+        skinned_mesh,
+        mesh,
+    }
+}
+
+// Reads .glb files and .gltf files with external buffers. Transforms of the nodes above the skeleton are ignored,
+// the model is placed by the entity drawing it.
+pub fn load_gltf_skinned_data(path: &Path) -> Result<SkinnedModelData, VulkrapError> {
+    let invalid = |reason| VulkrapError::InvalidMesh {
+        path: path.to_path_buf(),
+        reason,
+    };
+    let source = std::fs::read(path).map_err(|error| VulkrapError::io(path, error))?;
+    let gltf = Gltf::from_slice(&source).map_err(|error| {
+        log_error!("load_gltf: {:?}: {}", path, error);
+        invalid("not a valid glTF file")
+    })?;
+    let buffers = _read_buffers(path, &gltf)?;
+    let get_buffer = |buffer: Buffer| buffers.get(buffer.index()).map(Vec::as_slice);
+    let document = &gltf.document;
+
+    let skin = document.skins().next().ok_or_else(|| invalid("no skin"))?;
+    if document.skins().len() > 1 {
+        log_warning!(
+            "load_gltf: {:?} has {} skins, only the first is loaded",
+            path,
+            document.skins().len()
+        );
+    }
+    let skin_nodes: Vec<usize> = skin.joints().map(|node| node.index()).collect();
+
+    let mut node_parents = vec![None; document.nodes().len()];
+    for node in document.nodes() {
+        for child in node.children() {
+            node_parents[child.index()] = Some(node.index());
+        }
+    }
+
+    // Preorder of the node hierarchy puts parents first
+    let mut joint_nodes = Vec::with_capacity(skin_nodes.len());
+    let mut visited = vec![false; node_parents.len()];
+    let mut stack: Vec<_> = document
+        .nodes()
+        .filter(|node| node_parents[node.index()].is_none())
+        .collect();
+    stack.reverse();
+    while let Some(node) = stack.pop() {
+        if std::mem::replace(&mut visited[node.index()], true) {
+            return Err(invalid("node hierarchy has a cycle"));
+        }
+        let mut children: Vec<_> = node.children().collect();
+        children.reverse();
+        stack.extend(children);
+        if skin_nodes.contains(&node.index()) {
+            joint_nodes.push(node);
+        }
+    }
+    if joint_nodes.len() != skin_nodes.len() {
+        return Err(invalid("skin joints are not in the node hierarchy"));
+    }
+    let joint_of_node: HashMap<usize, usize> = joint_nodes
+        .iter()
+        .enumerate()
+        .map(|(joint, node)| (node.index(), joint))
+        .collect();
+    let skin_joints: Vec<usize> = skin_nodes.iter().map(|node| joint_of_node[node]).collect();
+
+    let mut inverse_bind_matrices = vec![Matrix4::identity(); skin_nodes.len()];
+    if let Some(matrices) = skin.reader(get_buffer).read_inverse_bind_matrices() {
+        for (skin_joint, matrix) in matrices.enumerate().take(skin_joints.len()) {
+            inverse_bind_matrices[skin_joints[skin_joint]] = Matrix4::from(matrix);
+        }
+    }
+
+    let mut rest_poses = Vec::with_capacity(joint_nodes.len());
+    let mut skeleton = Skeleton::new();
+    for (joint, node) in joint_nodes.iter().enumerate() {
+        let mut parent = node_parents[node.index()];
+        while let Some(node) = parent.filter(|node| !joint_of_node.contains_key(node)) {
+            parent = node_parents[node];
+        }
+
+        let (translation, [x, y, z, w], scale) = node.transform().decomposed();
+        let rest_pose = JointPose::new(translation.into(), Quaternion::new(w, x, y, z), scale.into());
+        rest_poses.push(rest_pose);
+        skeleton = skeleton.with_joint(
+            parent.map(|node| joint_of_node[&node]),
+            rest_pose,
+            inverse_bind_matrices[joint],
+        );
+    }
+
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    let skinned_nodes = document
+        .nodes()
+        .filter(|node| node.skin().map(|node_skin| node_skin.index()) == Some(skin.index()));
+    for mesh in skinned_nodes.filter_map(|node| node.mesh()) {
+        for primitive in mesh.primitives() {
+            if primitive.mode() != Mode::Triangles {
+                log_warning!(
+                    "load_gltf: {:?} skipped a primitive of {:?} mode",
+                    path,
+                    primitive.mode()
+                );
+                continue;
+            }
+
+            let reader = primitive.reader(get_buffer);
+            let positions: Vec<[f32; 3]> = reader
+                .read_positions()
+                .ok_or_else(|| invalid("no positions"))?
+                .collect();
+            let normals: Vec<[f32; 3]> = reader.read_normals().ok_or_else(|| invalid("no normals"))?.collect();
+            let joints: Vec<[u16; 4]> = reader
+                .read_joints(0)
+                .ok_or_else(|| invalid("no joints"))?
+                .into_u16()
+                .collect();
+            let weights: Vec<[f32; 4]> = reader
+                .read_weights(0)
+                .ok_or_else(|| invalid("no weights"))?
+                .into_f32()
+                .collect();
+            if [normals.len(), joints.len(), weights.len()]
+                .iter()
+                .any(|len| *len != positions.len())
+            {
+                return Err(invalid("vertex attributes differ in length"));
+            }
+
+            let first = vertices.len() as Index;
+            let primitive_indices: Vec<Index> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..positions.len() as Index).collect(),
+            };
+            if !primitive_indices.len().is_multiple_of(3)
+                || primitive_indices.iter().any(|index| *index as usize >= positions.len())
+            {
+                return Err(invalid("invalid indices"));
+            }
+            indices.extend(primitive_indices.iter().map(|index| first + index));
+
+            for vertex in 0..positions.len() {
+                let mut vertex_joints = [0; 4];
+                for (joint, skin_joint) in vertex_joints.iter_mut().zip(joints[vertex].iter()) {
+                    *joint = *skin_joints
+                        .get(*skin_joint as usize)
+                        .ok_or_else(|| invalid("joint outside of the skin"))? as u32;
+                }
+                vertices.push(SkinnedVertex::new(
+                    positions[vertex].into(),
+                    normals[vertex].into(),
+                    vertex_joints,
+                    _normalize_weights(weights[vertex]),
+                ));
+            }
+        }
+    }
+    if indices.is_empty() {
+        return Err(invalid("no triangles use the skin"));
+    }
+
+    let mut clips: Vec<AnimationClip> = Vec::new();
+    for animation in document.animations() {
+        let mut joint_channels: Vec<Vec<Channel>> = (0..joint_nodes.len()).map(|_| Vec::new()).collect();
+        for channel in animation.channels() {
+            let joint = match joint_of_node.get(&channel.target().node().index()) {
+                Some(joint) => *joint,
+                None => continue,
+            };
+
+            let reader = channel.reader(get_buffer);
+            let times: Vec<f32> = reader
+                .read_inputs()
+                .ok_or_else(|| invalid("no keyframe times"))?
+                .collect();
+            let values: Vec<[f32; 4]> = match reader.read_outputs().ok_or_else(|| invalid("no keyframe values"))? {
+                ReadOutputs::Translations(values) => values.map(|[x, y, z]| [x, y, z, 0.0]).collect(),
+                ReadOutputs::Scales(values) => values.map(|[x, y, z]| [x, y, z, 0.0]).collect(),
+                ReadOutputs::Rotations(values) => values.into_f32().collect(),
+                ReadOutputs::MorphTargetWeights(_) => continue,
+            };
+            // Cubic splines store an in tangent, the value and an out tangent per keyframe, the tangents are
+            // dropped and the values interpolated linearly
+            let interpolation = channel.sampler().interpolation();
+            let values = match interpolation {
+                Interpolation::CubicSpline => values.chunks_exact(3).map(|keyframe| keyframe[1]).collect(),
+                _ => values,
+            };
+            if times.is_empty() || times.len() != values.len() {
+                return Err(invalid("keyframe times and values differ in length"));
+            }
+
+            joint_channels[joint].push(Channel {
+                property: channel.target().property(),
+                step: interpolation == Interpolation::Step,
+                times,
+                values,
+            });
+        }
+
+        let name = animation
+            .name()
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("animation{}", animation.index()));
+        let duration_s = joint_channels
+            .iter()
+            .flatten()
+            .filter_map(|channel| channel.times.last().copied())
+            .fold(0.0, f32::max);
+        if duration_s <= 0.0 {
+            log_warning!("load_gltf: {:?} skipped animation {} without a duration", path, name);
+            continue;
+        }
+        let name = if clips.iter().any(|clip| clip.name() == name) {
+            format!("{}{}", name, animation.index())
+        } else {
+            name
+        };
+
+        let mut clip = AnimationClip::new(&name, duration_s);
+        for (joint, channels) in joint_channels
+            .iter()
+            .enumerate()
+            .filter(|(_, channels)| !channels.is_empty())
+        {
+            let mut times: Vec<f32> = channels.iter().flat_map(Channel::keyframe_times).collect();
+            times.sort_by(f32::total_cmp);
+            times.dedup_by(|b, a| *b - *a < KEYFRAME_EPSILON_S);
+
+            let keyframes = times
+                .iter()
+                .map(|time_s| {
+                    let mut pose = rest_poses[joint];
+                    for channel in channels.iter() {
+                        channel.sample(*time_s, &mut pose);
+                    }
+                    Keyframe::new(*time_s, pose)
+                })
+                .collect();
+            clip = clip.with_track(joint, keyframes);
+        }
+        clips.push(clip);
+    }
+
+    Ok(SkinnedModelData {
+        skeleton,
+        clips,
+        vertices,
+        indices,
+    })
+}
+
+impl Channel {
+    fn keyframe_times(&self) -> Vec<f32> {
+        let mut times = self.times.clone();
+        if self.step {
+            let holds = self.times.windows(2).map(|pair| (pair[0], pair[1] - STEP_HOLD_S));
+            times.extend(holds.filter(|(from, hold)| hold > from).map(|(_, hold)| hold));
+        }
+
+        times
+    }
+
+    // Sets the property of the pose to its value at the time
+    fn sample(&self, time_s: f32, pose: &mut JointPose) {
+        let last = self.times.len() - 1;
+        let (a, b, f) = match self.times.iter().position(|time| *time > time_s) {
+            Some(0) => (0, 0, 0.0),
+            Some(next) if self.step => (next - 1, next - 1, 0.0),
+            Some(next) => {
+                let (from, to) = (self.times[next - 1], self.times[next]);
+                (next - 1, next, (time_s - from) / (to - from))
+            }
+            None => (last, last, 0.0),
+        };
+        let ([ax, ay, az, aw], [bx, by, bz, bw]) = (self.values[a], self.values[b]);
+        let (from, to) = (Vector3::new(ax, ay, az), Vector3::new(bx, by, bz));
+
+        match self.property {
+            Property::Translation => pose.translation = from.lerp(to, f),
+            Property::Scale => pose.scale = from.lerp(to, f),
+            Property::Rotation => {
+                pose.rotation = Quaternion::new(aw, ax, ay, az).slerp(Quaternion::new(bw, bx, by, bz), f)
+            }
+            Property::MorphTargetWeights => {}
+        }
+    }
+}
+
+// Buffers in the order of the document, the binary chunk of .glb files or files next to the .gltf file
+fn _read_buffers(path: &Path, gltf: &Gltf) -> Result<Vec<Vec<u8>>, VulkrapError> {
+    let invalid = |reason| VulkrapError::InvalidMesh {
+        path: path.to_path_buf(),
+        reason,
+    };
+
+    gltf.document
+        .buffers()
+        .map(|buffer| {
+            let data = match buffer.source() {
+                Source::Bin => gltf.blob.clone().ok_or_else(|| invalid("no binary chunk"))?,
+                Source::Uri(uri) if uri.starts_with("data:") => {
+                    return Err(invalid(
+                        "embedded buffers are not supported, export as .glb or with a .bin file",
+                    ))
+                }
+                Source::Uri(uri) => {
+                    let buffer_path = path.parent().unwrap_or_else(|| Path::new("")).join(uri);
+                    std::fs::read(&buffer_path).map_err(|error| VulkrapError::io(buffer_path, error))?
+                }
+            };
+            if data.len() < buffer.length() {
+                return Err(invalid("buffer is shorter than its length"));
+            }
+
+            Ok(data)
+        })
+        .collect()
+}
+
+// Weights sum to one, vertices without weights follow the first joint
+fn _normalize_weights(weights: [f32; 4]) -> [f32; 4] {
+    let sum: f32 = weights.iter().sum();
+    if sum > 0.0 {
+        weights.map(|weight| weight / sum)
+    } else {
+        [1.0, 0.0, 0.0, 0.0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{Deg, InnerSpace, Rotation3};
+    use std::fs;
+
+    // Two joint arm with a triangle, the skin lists the child joint first. The lower joint turns 90 degrees around
+    // z in the first second, the upper joint steps up by 1 at 0.5 s.
+    fn _write_arm(dir: &Path) -> std::path::PathBuf {
+        let mut bin: Vec<u8> = Vec::new();
+        let mut floats = |values: &[f32]| {
+            values
+                .iter()
+                .for_each(|value| bin.extend_from_slice(&value.to_le_bytes()))
+        };
+        floats(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 2.0, 0.0]);
+        floats(&[0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0]);
+        floats(&[1.0, 0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+        let half_turn = (std::f32::consts::FRAC_PI_4.sin(), std::f32::consts::FRAC_PI_4.cos());
+        let lower_inverse_bind = [
+            1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, -2.0, 0.0, 1.0,
+        ];
+        let upper_inverse_bind = [
+            1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, -1.0, 0.0, 1.0,
+        ];
+        floats(&lower_inverse_bind);
+        floats(&upper_inverse_bind);
+        floats(&[0.0, 1.0]);
+        floats(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, half_turn.0, half_turn.1]);
+        floats(&[0.0, 0.5]);
+        floats(&[0.0, 1.0, 0.0, 0.0, 2.0, 0.0]);
+        // Skin joints of the upper, the upper and lower and the lower joint, then indices
+        bin.extend_from_slice(&[1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0]);
+        bin.extend_from_slice(&[0, 0, 1, 0, 2, 0, 0, 0]);
+
+        let json = r#"{
+            "asset": {"version": "2.0"},
+            "scene": 0,
+            "scenes": [{"nodes": [0, 1]}],
+            "nodes": [
+                {"name": "armature", "translation": [5.0, 0.0, 0.0], "children": [2]},
+                {"name": "arm", "mesh": 0, "skin": 0},
+                {"name": "upper", "translation": [0.0, 1.0, 0.0], "children": [3]},
+                {"name": "lower", "translation": [0.0, 1.0, 0.0]}
+            ],
+            "skins": [{"joints": [3, 2], "inverseBindMatrices": 3}],
+            "meshes": [{"primitives": [{
+                "attributes": {"POSITION": 0, "NORMAL": 1, "WEIGHTS_0": 2, "JOINTS_0": 8},
+                "indices": 9
+            }]}],
+            "animations": [{
+                "name": "wave",
+                "samplers": [
+                    {"input": 4, "output": 5, "interpolation": "LINEAR"},
+                    {"input": 6, "output": 7, "interpolation": "STEP"}
+                ],
+                "channels": [
+                    {"sampler": 0, "target": {"node": 3, "path": "rotation"}},
+                    {"sampler": 1, "target": {"node": 2, "path": "translation"}}
+                ]
+            }],
+            "buffers": [{"uri": "arm.bin", "byteLength": 356}],
+            "bufferViews": [
+                {"buffer": 0, "byteOffset": 0, "byteLength": 36},
+                {"buffer": 0, "byteOffset": 36, "byteLength": 36},
+                {"buffer": 0, "byteOffset": 72, "byteLength": 48},
+                {"buffer": 0, "byteOffset": 120, "byteLength": 128},
+                {"buffer": 0, "byteOffset": 248, "byteLength": 8},
+                {"buffer": 0, "byteOffset": 256, "byteLength": 32},
+                {"buffer": 0, "byteOffset": 288, "byteLength": 8},
+                {"buffer": 0, "byteOffset": 296, "byteLength": 24},
+                {"buffer": 0, "byteOffset": 320, "byteLength": 12},
+                {"buffer": 0, "byteOffset": 332, "byteLength": 6}
+            ],
+            "accessors": [
+                {"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                 "min": [0.0, 0.0, 0.0], "max": [1.0, 2.0, 0.0]},
+                {"bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC3"},
+                {"bufferView": 2, "componentType": 5126, "count": 3, "type": "VEC4"},
+                {"bufferView": 3, "componentType": 5126, "count": 2, "type": "MAT4"},
+                {"bufferView": 4, "componentType": 5126, "count": 2, "type": "SCALAR", "min": [0.0], "max": [1.0]},
+                {"bufferView": 5, "componentType": 5126, "count": 2, "type": "VEC4"},
+                {"bufferView": 6, "componentType": 5126, "count": 2, "type": "SCALAR", "min": [0.0], "max": [0.5]},
+                {"bufferView": 7, "componentType": 5126, "count": 2, "type": "VEC3"},
+                {"bufferView": 8, "componentType": 5121, "count": 3, "type": "VEC4"},
+                {"bufferView": 9, "componentType": 5123, "count": 3, "type": "SCALAR"}
+            ]
+        }"#;
+
+        fs::create_dir_all(dir).unwrap();
+        bin.resize(356, 0);
+        fs::write(dir.join("arm.bin"), &bin).unwrap();
+        fs::write(dir.join("arm.gltf"), json).unwrap();
+
+        dir.join("arm.gltf")
+    }
+
+    #[test]
+    fn skins_and_clips() {
+        let dir = std::env::temp_dir().join(format!("vulkrap-gltf-test-{}", std::process::id()));
+        let data = load_gltf_skinned_data(&_write_arm(&dir)).unwrap();
+
+        // The upper joint is moved before the lower one, vertex joints follow
+        assert_eq!(data.skeleton.joint_count(), 2);
+        assert_eq!(data.indices, vec![0, 1, 2]);
+        assert_eq!(data.vertices[0].joints, [0, 1, 1, 1]);
+        assert_eq!(data.vertices[1].joints, [1, 0, 1, 1]);
+        assert_eq!(data.vertices[1].weights, [0.5, 0.5, 0.0, 0.0]);
+        assert_eq!(data.vertices[2].weights, [1.0, 0.0, 0.0, 0.0]);
+        assert_eq!(data.vertices[2].position, Vector3::new(0.0, 2.0, 0.0));
+
+        assert_eq!(data.clips.len(), 1);
+        let wave = &data.clips[0];
+        assert_eq!((wave.name(), wave.duration_s()), ("wave", 1.0));
+        let upper = wave.sample(0, 0.75).unwrap();
+        assert_eq!(upper.translation, Vector3::new(0.0, 2.0, 0.0));
+        assert_eq!(wave.sample(0, 0.25).unwrap().translation, Vector3::new(0.0, 1.0, 0.0));
+        // Keyframes of the other channels keep the rest pose of the joint
+        let lower = wave.sample(1, 0.5).unwrap();
+        assert_eq!(lower.translation, Vector3::new(0.0, 1.0, 0.0));
+        let quarter_turn = Quaternion::from_angle_z(Deg(45.0));
+        assert!(
+            (lower.rotation - quarter_turn).magnitude2() < 1.0e-6,
+            "{:?}",
+            lower.rotation
+        );
+
+        fs::write(dir.join("arm.bin"), [0; 16]).unwrap();
+        assert!(matches!(
+            load_gltf_skinned_data(&dir.join("arm.gltf")),
+            Err(VulkrapError::InvalidMesh { .. })
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(
+            load_gltf_skinned_data(&dir.join("arm.gltf")),
+            Err(VulkrapError::Io { .. })
+        ));
+    }
+}
@@ -1,2 +1,5 @@
+pub mod cache;
+pub mod gltf;
 pub mod model;
+pub mod normals;
 pub mod obj;
\ No newline at end of file
@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+
+use cgmath::{Deg, InnerSpace, Vector3, Zero};
+
+use crate::engine::datatypes::NormalVertex;
+
+// Smoothing group of faces that are never smoothed, "s off" in obj files
+pub const FLAT_GROUP: u32 = 0;
+
+// Faces meeting at a smaller angle share normals
+pub const DEFAULT_SMOOTHING_ANGLE: Deg<f32> = Deg(60.0);
+
+// Tolerance of the angle test, so that Deg(0.0) still merges coplanar faces
+const SMOOTHING_EPSILON: f32 = 1.0e-5;
+
+// Normals of triangles given as position indices, wound clockwise like the meshes of the engine. Neighbouring faces
+// of the same smoothing group share area weighted normals if they meet at less than smoothing_angle, Deg(0.0) gives
+// flat shading. Returns the vertices and indices of the mesh.
+pub fn generate_normals(
+    positions: &[Vector3<f32>],
+    triangles: &[[u32; 3]],
+    groups: &[u32],
+    smoothing_angle: Deg<f32>,
+) -> (Vec<NormalVertex>, Vec<u32>) {
+    assert_eq!(triangles.len(), groups.len(), "One smoothing group per triangle");
+
+    // The length is twice the area of the face
+    let face_normals: Vec<Vector3<f32>> = triangles
+        .iter()
+        .map(|triangle| {
+            let [a, b, c] = triangle.map(|index| positions[index as usize]);
+            (c - a).cross(b - a)
+        })
+        .collect();
+    let unit_normals: Vec<Vector3<f32>> = face_normals.iter().map(|normal| _normalize_or_zero(*normal)).collect();
+
+    let mut faces_at_position = vec![Vec::new(); positions.len()];
+    for (face, triangle) in triangles.iter().enumerate() {
+        for position in triangle.iter() {
+            faces_at_position[*position as usize].push(face);
+        }
+    }
+
+    let min_cos = smoothing_angle.0.to_radians().cos() - SMOOTHING_EPSILON;
+    let mut vertices = Vec::new();
+    let mut indices = Vec::with_capacity(triangles.len() * 3);
+    let mut vertex_indices = HashMap::new();
+
+    for (face, triangle) in triangles.iter().enumerate() {
+        for position in triangle.iter() {
+            let normal = if groups[face] == FLAT_GROUP {
+                unit_normals[face]
+            } else {
+                let smoothed = faces_at_position[*position as usize]
+                    .iter()
+                    .filter(|other| groups[**other] == groups[face])
+                    .filter(|other| unit_normals[**other].dot(unit_normals[face]) >= min_cos)
+                    .fold(Vector3::zero(), |sum, other| sum + face_normals[*other]);
+                _normalize_or_zero(smoothed)
+            };
+
+            // Corners with the same position and normal become one vertex. Adding zero turns -0.0 into 0.0,
+            // which would otherwise have other bits.
+            let normal = normal + Vector3::zero();
+            let key = (*position, [normal.x.to_bits(), normal.y.to_bits(), normal.z.to_bits()]);
+            let index = *vertex_indices.entry(key).or_insert_with(|| {
+                vertices.push(NormalVertex::new(positions[*position as usize], normal));
+                (vertices.len() - 1) as u32
+            });
+            indices.push(index);
+        }
+    }
+
+    (vertices, indices)
+}
+
+fn _normalize_or_zero(vector: Vector3<f32>) -> Vector3<f32> {
+    if vector.magnitude2() > 0.0 {
+        vector.normalize()
+    } else {
+        vector
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smoothing_angle_and_groups() {
+        // Two quads folded by 90 degrees along the edge between positions 1 and 4
+        let positions = vec![
+            Vector3::new(-1.0, 1.0, 0.0),
+            Vector3::new(0.0, 1.0, 0.0),
+            Vector3::new(-1.0, 0.0, 0.0),
+            Vector3::new(0.0, 1.0, -1.0),
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(0.0, 0.0, -1.0),
+        ];
+        let triangles = [[0, 1, 2], [2, 1, 4], [1, 3, 4], [4, 3, 5]];
+        let smooth_groups = [1; 4];
+
+        let (vertices, indices) = generate_normals(&positions, &triangles, &smooth_groups, DEFAULT_SMOOTHING_ANGLE);
+        assert_eq!(indices.len(), 12);
+        // Coplanar triangles share their edge, the fold is sharp
+        assert_eq!(vertices.len(), 8);
+        assert_eq!(vertices[indices[0] as usize].normal, Vector3::new(0.0, 0.0, 1.0));
+        assert_eq!(vertices[indices[6] as usize].normal, Vector3::new(1.0, 0.0, 0.0));
+
+        let (vertices, _) = generate_normals(&positions, &triangles, &smooth_groups, Deg(120.0));
+        assert_eq!(vertices.len(), 6);
+        let edge = vertices.iter().find(|vertex| vertex.position == positions[1]).unwrap();
+        assert!(edge.normal.x > 0.0 && edge.normal.z > 0.0);
+
+        let (vertices, _) = generate_normals(&positions, &triangles, &[1, 1, 2, 2], Deg(120.0));
+        assert_eq!(vertices.len(), 8);
+        let (vertices, _) = generate_normals(&positions, &triangles, &[FLAT_GROUP; 4], Deg(120.0));
+        assert_eq!(vertices.len(), 8);
+    }
+}
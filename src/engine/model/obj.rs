@@ -1,12 +1,18 @@
 use std::collections::HashMap;
 use std::path::Path;
 use std::str::{FromStr, SplitAsciiWhitespace};
-use cgmath::Vector3;
+use cgmath::{Deg, Vector2, Vector3};
 use regex::Regex;
 use stopwatch::Stopwatch;
 
 use crate::engine::datatypes::{Mesh, NormalVertex};
+use crate::engine::model::cache;
+use crate::engine::model::cache::MeshData;
+use crate::engine::model::normals::{generate_normals, DEFAULT_SMOOTHING_ANGLE, FLAT_GROUP};
 use crate::renderer::context::Context;
+use crate::renderer::resources::Resources;
+use crate::renderer::vertex::{VertexLayout, VertexSemantic};
+use crate::error::VulkrapError;
 use crate::util::file::read_lines;
 
 #[derive(Debug)]
@@ -23,44 +29,148 @@ struct VertexKey {
     texture_index: Option<u32>,
 }
 
-pub fn load_obj_mesh(context: &mut Context, path: &Path) -> Result<Mesh, &'static str> {
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NormalMode {
+    // Normals of the file, generated with DEFAULT_SMOOTHING_ANGLE if it has none
+    FromFile,
+    // Ignores the normals of the file, faces meeting at less than the angle are smoothed
+    Smooth(Deg<f32>),
+    Flat,
+}
+
+pub fn load_obj_mesh(context: &mut Context, path: &Path) -> Result<Mesh, VulkrapError> {
+    load_obj_mesh_with_normals(context, path, NormalMode::FromFile)
+}
+
+pub fn load_obj_mesh_with_normals(context: &mut Context, path: &Path, mode: NormalMode) -> Result<Mesh, VulkrapError> {
+    let sw = Stopwatch::start_new();
+    let data = load_obj_mesh_data(path, mode)?;
+
+    let vertex_buffer = context.create_static_vertex_buffer_sync(&data.vertices);
+    let index_buffer = context.create_static_index_buffer_sync(&data.indices);
+
+    log_info!("loaded model in {} ms", sw.elapsed_ms());
+
+    Ok(Mesh::new(vertex_buffer, index_buffer, data.indices.len() as u32))
+}
+
+// For loader threads, the file is parsed on the calling thread and the buffers are created by the render thread.
+// Blocks until the render thread has started a frame.
+pub fn load_obj_mesh_with_resources(resources: &Resources, path: &Path) -> Result<Mesh, VulkrapError> {
+    let sw = Stopwatch::start_new();
+    let data = load_obj_mesh_data(path, NormalMode::FromFile)?;
+
+    let index_count = data.indices.len() as u32;
+    let vertex_buffer = resources.create_static_vertex_buffer(data.vertices);
+    let index_buffer = resources.create_static_index_buffer(data.indices);
+    let mesh = Mesh::new(vertex_buffer.wait(), index_buffer.wait(), index_count);
+
+    log_info!("loaded model in {} ms", sw.elapsed_ms());
+
+    Ok(mesh)
+}
+
+// Vertices, indices and bounds of the mesh, read from the mesh cache if the file has been loaded with the same
+// normal mode before. Otherwise the file is parsed and the result written to the cache.
+pub fn load_obj_mesh_data(path: &Path, mode: NormalMode) -> Result<MeshData, VulkrapError> {
+    let source = std::fs::read(path).map_err(|error| VulkrapError::io(path, error))?;
+    let cache_path = cache::cache_path(&source, &format!("{:?}", mode));
+    if let Some(data) = cache::read(&cache_path) {
+        log_debug!("load_obj_mesh: {:?} read from cache {:?}", path, cache_path);
+        return Ok(data);
+    }
+
+    let obj = _read_obj(path)?;
+    let (normal_vertices, indices) = _normal_vertices(&obj, mode);
+    let data = MeshData::new(normal_vertices, indices);
+    if let Err(error) = cache::write(&cache_path, &data) {
+        log_warning!("load_obj_mesh: failed to write cache {:?}: {}", cache_path, error);
+    }
+
+    Ok(data)
+}
+
+// Fills the attributes of the layout found in the file, other attributes are zero
+pub fn load_obj_mesh_with_layout(
+    context: &mut Context,
+    path: &Path,
+    vertex_layout: &VertexLayout,
+) -> Result<Mesh, VulkrapError> {
     let sw = Stopwatch::start_new();
+    let obj = _read_obj(path)?;
+    let vertex_data = _pack_vertices(&obj, vertex_layout);
+
+    let vertex_buffer = context.create_static_vertex_buffer_from_bytes_sync(&vertex_data);
+    let index_buffer = context.create_static_index_buffer_sync(&obj.indices);
+
+    log_info!("loaded model in {} ms", sw.elapsed_ms());
+
+    Ok(Mesh::new(vertex_buffer, index_buffer, obj.indices.len() as u32))
+}
+
+struct ObjData {
+    positions: Vec<Vector3<f32>>,
+    normals: Vec<Vector3<f32>>,
+    texture_coords: Vec<Vector2<f32>>,
+    // One per unique vertex
+    vertex_keys: Vec<VertexKey>,
+    indices: Vec<u32>,
+    // Smoothing group of each triangle
+    face_groups: Vec<u32>,
+}
+
+fn _read_obj(path: &Path) -> Result<ObjData, VulkrapError> {
     log_debug!("loading obj_mesh: {:?}", path);
+    let invalid = |reason| VulkrapError::InvalidMesh { path: path.to_path_buf(), reason };
     let mut found_object = false;
 
     let mut raw_vertices = Vec::new();
     let mut raw_normals = Vec::new();
+    let mut raw_texture_coords = Vec::new();
     let mut faces = Vec::new();
+    let mut face_groups = Vec::new();
+    // Faces before any "s" statement are smoothed
+    let mut smoothing_group = 1;
 
     let face_pattern = Regex::new(r"(?m)^f (?P<v1>\d*)(/(?P<t1>\d*)(/(?P<n1>\d*))?)? (?P<v2>\d*)(/(?P<t2>\d*)(/(?P<n2>\d*))?)? (?P<v3>\d*)(/(?P<t3>\d*)(/(?P<n3>\d*))?)?$").unwrap();
 
-    if let Ok(lines) = read_lines(path) {
-        for line in lines {
-            if let Ok(line_str) = line {
-                if line_str.starts_with("o") {
-                    if found_object {
-                        return Err("Multiple object not supported");
-                    } else {
-                        found_object = true;
-                        let mut split = line_str.split_ascii_whitespace();
-                        split.next();
-                        log_debug!("load_obj_mesh: found o = {}",split.next().unwrap());
-                    }
-                } else if line_str.starts_with("v ") {
-                    let mut split = line_str.split_ascii_whitespace();
-                    split.next();
-                    let vertex = _parse_vec3(&mut split);
-                    raw_vertices.push(vertex);
-                } else if line_str.starts_with("vn ") {
-                    let mut split = line_str.split_ascii_whitespace();
-                    split.next();
-                    let normal = _parse_vec3(&mut split);
-                    raw_normals.push(normal);
-                } else if line_str.starts_with("f ") {
-                    let face = _parse_face_line(&face_pattern, &line_str);
-                    faces.push(face.unwrap());
-                }
+    for line in read_lines(path)? {
+        let line_str = line.map_err(|error| VulkrapError::io(path, error))?;
+        if line_str.starts_with("o") {
+            if found_object {
+                return Err(invalid("Multiple object not supported"));
+            } else {
+                found_object = true;
+                let mut split = line_str.split_ascii_whitespace();
+                split.next();
+                log_debug!("load_obj_mesh: found o = {}",split.next().unwrap_or(""));
             }
+        } else if line_str.starts_with("v ") {
+            let mut split = line_str.split_ascii_whitespace();
+            split.next();
+            let vertex = _parse_vec3(&mut split).ok_or_else(|| invalid("Invalid vertex"))?;
+            raw_vertices.push(vertex);
+        } else if line_str.starts_with("vn ") {
+            let mut split = line_str.split_ascii_whitespace();
+            split.next();
+            let normal = _parse_vec3(&mut split).ok_or_else(|| invalid("Invalid normal"))?;
+            raw_normals.push(normal);
+        } else if line_str.starts_with("vt ") {
+            let mut split = line_str.split_ascii_whitespace();
+            split.next();
+            let u = split.next().and_then(|u| f32::from_str(u).ok());
+            let v = split.next().map_or(Some(0.0), |v| f32::from_str(v).ok());
+            let (u, v) = u.zip(v).ok_or_else(|| invalid("Invalid texture coordinate"))?;
+            raw_texture_coords.push(Vector2::new(u, v));
+        } else if let Some(group) = line_str.strip_prefix("s ") {
+            smoothing_group = match group.trim() {
+                "off" => FLAT_GROUP,
+                number => u32::from_str(number).map_err(|_| invalid("Invalid smoothing group"))?,
+            };
+        } else if line_str.starts_with("f ") {
+            let face = _parse_face_line(&face_pattern, &line_str).ok_or_else(|| invalid("Invalid face"))?;
+            faces.push(face);
+            face_groups.push(smoothing_group);
         }
     }
 
@@ -68,85 +178,143 @@ pub fn load_obj_mesh(context: &mut Context, path: &Path) -> Result<Mesh, &'stati
     log_debug!("load_obj_mesh: obj normal count: {}", raw_normals.len());
     log_debug!("load_obj_mesh: obj face count {}", faces.len());
 
-    let mut normal_vertices = Vec::new();
-    let mut normal_vertex_to_index = HashMap::new();
+    let mut vertex_keys = Vec::new();
+    let mut vertex_key_to_index = HashMap::new();
     let mut indices = Vec::new();
 
+    // Indices start at 1
+    let in_range = |index: Option<Vector3<u32>>, count: usize| {
+        index.is_none_or(|index| [index.x, index.y, index.z].iter().all(|i| (1..=count as u32).contains(i)))
+    };
     for face in faces.iter() {
+        if !in_range(Some(face.vertices), raw_vertices.len())
+            || !in_range(face.normals, raw_normals.len())
+            || !in_range(face.texture, raw_texture_coords.len())
+        {
+            return Err(invalid("Face index out of range"));
+        }
         for i in 0..3 {
-
-            let v = face.vertices[i] - 1;
-            let n = if face.normals.is_some() {
-                Some(face.normals.unwrap()[i] - 1)
-            } else {
-                None
-            };
-
             let vertex_key = VertexKey {
-                vertex_index: v,
-                normal_index: n,
-                texture_index: None,
+                vertex_index: face.vertices[i] - 1,
+                normal_index: face.normals.map(|normals| normals[i] - 1),
+                texture_index: face.texture.map(|texture| texture[i] - 1),
             };
 
-            let existing_vertex = normal_vertex_to_index.get(&vertex_key);
+            let existing_vertex = vertex_key_to_index.get(&vertex_key);
 
             if let Some(index) = existing_vertex {
                 indices.push(*index);
             } else {
-                let index = normal_vertices.len() as u32;
-                let vertex = *raw_vertices.get(vertex_key.vertex_index as usize).unwrap();
-                let normal = *raw_normals.get(vertex_key.normal_index.unwrap() as usize).unwrap();
-                let normal_vertex = NormalVertex::new(vertex, normal);
-                normal_vertices.push(normal_vertex);
-                normal_vertex_to_index.insert(vertex_key, index);
+                let index = vertex_keys.len() as u32;
+                vertex_keys.push(vertex_key);
+                vertex_key_to_index.insert(vertex_key, index);
                 indices.push(index)
             }
         }
     }
 
-    log_debug!("load_obj_mesh: buf vertex count: {}", normal_vertices.len());
+    log_debug!("load_obj_mesh: buf vertex count: {}", vertex_keys.len());
     log_debug!("load_obj_mesh: buf index count: {}", indices.len());
 
-    let vertex_buffer = context.create_static_vertex_buffer_sync(&normal_vertices);
-    let index_buffer = context.create_static_index_buffer_sync(&indices);
+    Ok(ObjData {
+        positions: raw_vertices,
+        normals: raw_normals,
+        texture_coords: raw_texture_coords,
+        vertex_keys,
+        indices,
+        face_groups,
+    })
+}
 
-    log_info!("loaded model in {} ms", sw.elapsed_ms());
+fn _normal_vertices(obj: &ObjData, mode: NormalMode) -> (Vec<NormalVertex>, Vec<u32>) {
+    let has_normals = obj.vertex_keys.iter().all(|key| key.normal_index.is_some());
+    let smoothing_angle = match mode {
+        NormalMode::FromFile if has_normals => {
+            let vertices = obj
+                .vertex_keys
+                .iter()
+                .map(|key| {
+                    let vertex = *obj.positions.get(key.vertex_index as usize).unwrap();
+                    let normal = *obj.normals.get(key.normal_index.unwrap() as usize).unwrap();
+                    NormalVertex::new(vertex, normal)
+                })
+                .collect();
+            return (vertices, obj.indices.clone());
+        }
+        NormalMode::FromFile => DEFAULT_SMOOTHING_ANGLE,
+        NormalMode::Smooth(angle) => angle,
+        NormalMode::Flat => Deg(0.0),
+    };
+
+    let triangles: Vec<[u32; 3]> = obj
+        .indices
+        .chunks_exact(3)
+        .map(|triangle| [0, 1, 2].map(|corner| obj.vertex_keys[triangle[corner] as usize].vertex_index))
+        .collect();
+    log_debug!("load_obj_mesh: generating normals, smoothing angle {:?}", smoothing_angle);
+
+    generate_normals(&obj.positions, &triangles, &obj.face_groups, smoothing_angle)
+}
 
-    Ok(Mesh::new(vertex_buffer, index_buffer, indices.len() as u32))
+fn _pack_vertices(obj: &ObjData, vertex_layout: &VertexLayout) -> Vec<u8> {
+    let stride = vertex_layout.stride(0) as usize;
+    let mut vertex_data = vec![0_u8; obj.vertex_keys.len() * stride];
+
+    for (key, vertex) in obj.vertex_keys.iter().zip(vertex_data.chunks_exact_mut(stride)) {
+        let position = obj.positions[key.vertex_index as usize];
+        vertex_layout.write(vertex, VertexSemantic::Position, &[position.x, position.y, position.z]);
+        if let Some(normal) = key.normal_index.and_then(|index| obj.normals.get(index as usize)) {
+            vertex_layout.write(vertex, VertexSemantic::Normal, &[normal.x, normal.y, normal.z]);
+        }
+        if let Some(texture_coord) = key.texture_index.and_then(|index| obj.texture_coords.get(index as usize)) {
+            vertex_layout.write(vertex, VertexSemantic::TextureCoord, &[texture_coord.x, texture_coord.y]);
+        }
+        vertex_layout.write(vertex, VertexSemantic::Color, &[1.0, 1.0, 1.0, 1.0]);
+    }
+
+    vertex_data
 }
 
-fn _parse_vec3(split: &mut SplitAsciiWhitespace) -> Vector3<f32> {
-    let x = split.next().unwrap();
-    let y = split.next().unwrap();
-    let z = split.next().unwrap();
+fn _parse_vec3(split: &mut SplitAsciiWhitespace) -> Option<Vector3<f32>> {
+    let x = split.next()?;
+    let y = split.next()?;
+    let z = split.next()?;
 
-    Vector3::new(
-        f32::from_str(x).unwrap(),
-        f32::from_str(y).unwrap(),
-        f32::from_str(z).unwrap())
+    Some(Vector3::new(
+        f32::from_str(x).ok()?,
+        f32::from_str(y).ok()?,
+        f32::from_str(z).ok()?))
 }
 
 fn _parse_face_line(pattern: &Regex, str: &String) -> Option<Face> {
 
     if let Some(face) = pattern.captures(str.as_str()) {
-        let v1 = u32::from_str(face.name("v1").unwrap().as_str()).unwrap();
-        let v2 = u32::from_str(face.name("v2").unwrap().as_str()).unwrap();
-        let v3 = u32::from_str(face.name("v3").unwrap().as_str()).unwrap();
+        let v1 = u32::from_str(face.name("v1")?.as_str()).ok()?;
+        let v2 = u32::from_str(face.name("v2")?.as_str()).ok()?;
+        let v3 = u32::from_str(face.name("v3")?.as_str()).ok()?;
         let vertices = Vector3::from((v3, v2, v1));
 
         let normals = if face.name("n1").is_some() {
-            let n1 = u32::from_str(face.name("n1").unwrap().as_str()).unwrap();
-            let n2 = u32::from_str(face.name("n2").unwrap().as_str()).unwrap();
-            let n3 = u32::from_str(face.name("n3").unwrap().as_str()).unwrap();
+            let n1 = u32::from_str(face.name("n1")?.as_str()).ok()?;
+            let n2 = u32::from_str(face.name("n2")?.as_str()).ok()?;
+            let n3 = u32::from_str(face.name("n3")?.as_str()).ok()?;
 
             Some(Vector3::from((n3,n2,n1)))
         } else {
             None
         };
+        let texture = match (face.name("t1"), face.name("t2"), face.name("t3")) {
+            (Some(t1), Some(t2), Some(t3)) if !t1.as_str().is_empty() => Some(Vector3::new(
+                u32::from_str(t3.as_str()).ok()?,
+                u32::from_str(t2.as_str()).ok()?,
+                u32::from_str(t1.as_str()).ok()?,
+            )),
+            _ => None,
+        };
         let face = Face {
             vertices,
             normals,
-            texture: None,
+            texture,
         };
 
         return Some(face);
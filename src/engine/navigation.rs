@@ -0,0 +1,205 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::path::Path;
+
+use cgmath::{Deg, Matrix4, Vector2, Vector3, Vector4};
+
+use crate::engine::datatypes::{Mesh, SimpleVertex, TransformColorPushConstant, ViewProjectionUniform};
+use crate::engine::gridmap::GridMap;
+use crate::engine::mesh::PredefinedMesh::SimpleQuad;
+use crate::engine::mesh::{MeshHandle, MeshManager};
+use crate::renderer::context::{Context, Frame};
+use crate::renderer::types::{DrawCommand, PipelineConfiguration, PipelineHandle, RenderPassHandle, UniformHandle};
+
+const NEIGHBOURS: [(i32, i32); 4] = [(0, -1), (1, 0), (0, 1), (-1, 0)];
+
+const DEBUG_HEIGHT: f32 = 0.02;
+const DEBUG_MARKER_SIZE: f32 = 0.3;
+const DEBUG_LINE_WIDTH: f32 = 0.08;
+
+// Shortest 4-connected path over walkable cells using A*, including both ends. None if the goal can't be reached.
+pub fn find_path(map: &GridMap, from: Vector2<i32>, to: Vector2<i32>) -> Option<Vec<Vector2<i32>>> {
+    if !map.is_walkable(from.x, from.y) || !map.is_walkable(to.x, to.y) {
+        return None;
+    }
+
+    let width = map.width() as i32;
+    let index = |cell: Vector2<i32>| (cell.y * width + cell.x) as usize;
+    let cell_count = (map.width() * map.height()) as usize;
+    let mut cost = vec![u32::MAX; cell_count];
+    let mut came_from: Vec<Option<Vector2<i32>>> = vec![None; cell_count];
+    // Ordered by estimated total cost, ties go to the cell closest to the goal
+    let mut open = BinaryHeap::new();
+
+    cost[index(from)] = 0;
+    open.push(Reverse((_manhattan(from, to), _manhattan(from, to), from.x, from.y)));
+
+    while let Some(Reverse((_, _, x, y))) = open.pop() {
+        let cell = Vector2::new(x, y);
+        if cell == to {
+            let mut path = vec![to];
+            while let Some(previous) = came_from[index(*path.last().unwrap())] {
+                path.push(previous);
+            }
+            path.reverse();
+            return Some(path);
+        }
+
+        let next_cost = cost[index(cell)] + 1;
+        for (dx, dy) in NEIGHBOURS.iter() {
+            let neighbour = Vector2::new(x + dx, y + dy);
+            if !map.is_walkable(neighbour.x, neighbour.y) || next_cost >= cost[index(neighbour)] {
+                continue;
+            }
+
+            cost[index(neighbour)] = next_cost;
+            came_from[index(neighbour)] = Some(cell);
+            let remaining = _manhattan(neighbour, to);
+            open.push(Reverse((next_cost + remaining, remaining, neighbour.x, neighbour.y)));
+        }
+    }
+
+    None
+}
+
+// True if no cell between the two cells blocks sight. The cells themselves are not tested, so a monster standing
+// in a doorway can still be seen.
+pub fn has_line_of_sight(map: &GridMap, from: Vector2<i32>, to: Vector2<i32>) -> bool {
+    _line_cells(from, to)
+        .iter()
+        .filter(|cell| **cell != from && **cell != to)
+        .all(|cell| map.is_transparent(cell.x, cell.y))
+}
+
+fn _manhattan(a: Vector2<i32>, b: Vector2<i32>) -> u32 {
+    ((a.x - b.x).abs() + (a.y - b.y).abs()) as u32
+}
+
+// Bresenham line, including both ends
+fn _line_cells(from: Vector2<i32>, to: Vector2<i32>) -> Vec<Vector2<i32>> {
+    let dx = (to.x - from.x).abs();
+    let dy = -(to.y - from.y).abs();
+    let step_x = if from.x < to.x { 1 } else { -1 };
+    let step_y = if from.y < to.y { 1 } else { -1 };
+
+    let mut cells = Vec::new();
+    let mut cell = from;
+    let mut error = dx + dy;
+    loop {
+        cells.push(cell);
+        if cell == to {
+            break;
+        }
+        let e2 = 2 * error;
+        if e2 >= dy {
+            error += dy;
+            cell.x += step_x;
+        }
+        if e2 <= dx {
+            error += dx;
+            cell.y += step_y;
+        }
+    }
+
+    cells
+}
+
+// Draws paths as markers and lines just above the floor of a GridMap. Paths are added every frame.
+pub struct PathDebugRenderer {
+    pipeline: PipelineHandle,
+    mesh: Mesh,
+
+    pending: Vec<TransformColorPushConstant>,
+    // Push constants of the last draw, kept alive until the frame has been rendered
+    drawn: Vec<TransformColorPushConstant>,
+}
+
+impl PathDebugRenderer {
+    pub fn new(
+        context: &mut Context,
+        mesh_manager: &MeshManager,
+        render_pass: RenderPassHandle,
+        camera_uniform: UniformHandle<ViewProjectionUniform>,
+    ) -> Self {
+        let mesh = *mesh_manager.get_mesh(SimpleQuad as MeshHandle);
+
+        let pipeline_config = PipelineConfiguration::builder()
+            .with_name("navigation")
+            .with_push_constant::<TransformColorPushConstant>()
+            .with_vertex_shader_file(Path::new("./resources/shaders/flat_color_vert.spv"))
+            .with_fragment_shader_file(Path::new("./resources/shaders/flat_color_frag.spv"))
+            .with_vertex_uniform(0, camera_uniform)
+            .build()
+            .expect("Invalid pipeline configuration!");
+        let pipeline = context.add_pipeline::<SimpleVertex>(render_pass, pipeline_config);
+
+        PathDebugRenderer {
+            pipeline,
+            mesh,
+
+            pending: Vec::new(),
+            drawn: Vec::new(),
+        }
+    }
+
+    pub fn add_path(&mut self, path: &[Vector2<i32>], color: Vector4<f32>) {
+        let floor = Matrix4::from_angle_x(Deg(-90.0));
+        for cell in path.iter() {
+            let translation = Matrix4::from_translation(Vector3::new(cell.x as f32, DEBUG_HEIGHT, cell.y as f32));
+            let scale = Matrix4::from_scale(DEBUG_MARKER_SIZE);
+            self.pending.push(TransformColorPushConstant::new(translation * floor * scale, color));
+        }
+
+        // Neighbouring cells are one unit apart, the line is a stretched quad between their centers
+        for segment in path.windows(2) {
+            let center = Vector3::new(
+                (segment[0].x + segment[1].x) as f32 / 2.0,
+                DEBUG_HEIGHT,
+                (segment[0].y + segment[1].y) as f32 / 2.0,
+            );
+            let (scale_x, scale_y) = if segment[0].x != segment[1].x {
+                (1.0, DEBUG_LINE_WIDTH)
+            } else {
+                (DEBUG_LINE_WIDTH, 1.0)
+            };
+            let transform =
+                Matrix4::from_translation(center) * floor * Matrix4::from_nonuniform_scale(scale_x, scale_y, 1.0);
+            self.pending.push(TransformColorPushConstant::new(transform, color));
+        }
+    }
+
+    pub fn draw(&mut self, frame: &mut Frame) {
+        self.drawn.clear();
+        std::mem::swap(&mut self.drawn, &mut self.pending);
+
+        for push_constant in self.drawn.iter() {
+            frame.add_draw_command(DrawCommand::new_buffered(self.pipeline, push_constant, self.mesh));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn path_and_line_of_sight() {
+        let text = "tile a 1 1 1\ntile p 1 1 1 blocks_movement blocks_sight\ntile g 1 1 1 blocks_sight\nmap\naaaa\napga\naaaa\n";
+        let map = GridMap::parse(text.lines()).unwrap();
+
+        let path = find_path(&map, Vector2::new(0, 1), Vector2::new(3, 1)).unwrap();
+        assert_eq!(path.len(), 6);
+        assert_eq!(path[0], Vector2::new(0, 1));
+        assert_eq!(path[5], Vector2::new(3, 1));
+        assert!(path.windows(2).all(|w| _manhattan(w[0], w[1]) == 1));
+        assert!(path.iter().all(|cell| map.is_walkable(cell.x, cell.y)));
+        assert_eq!(find_path(&map, Vector2::new(0, 0), Vector2::new(0, 0)), Some(vec![Vector2::new(0, 0)]));
+        assert_eq!(find_path(&map, Vector2::new(0, 0), Vector2::new(1, 1)), None);
+        assert_eq!(find_path(&map, Vector2::new(0, 0), Vector2::new(9, 9)), None);
+
+        assert!(!has_line_of_sight(&map, Vector2::new(0, 1), Vector2::new(3, 1)));
+        assert!(has_line_of_sight(&map, Vector2::new(0, 0), Vector2::new(3, 0)));
+        assert!(has_line_of_sight(&map, Vector2::new(0, 1), Vector2::new(1, 1)));
+        assert_eq!(_line_cells(Vector2::new(0, 0), Vector2::new(2, 1)).len(), 3);
+    }
+}
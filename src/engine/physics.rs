@@ -0,0 +1,331 @@
+use std::collections::HashMap;
+use std::ops::IndexMut;
+
+use cgmath::{Quaternion, Vector3};
+use rapier3d::na::{Isometry3, Quaternion as NaQuaternion, Translation3, UnitQuaternion};
+use rapier3d::prelude::*;
+
+use crate::engine::entity::DefaultEntity;
+use crate::engine::math::transform::Transform;
+
+// Application objects moved by rigid bodies
+pub trait PhysicsEntity {
+    fn set_pose(&mut self, translation: Vector3<f32>, rotation: Quaternion<f32>);
+}
+
+// The push constant is rebuilt right away, so synced entities can be drawn as they are
+impl PhysicsEntity for DefaultEntity {
+    fn set_pose(&mut self, translation: Vector3<f32>, rotation: Quaternion<f32>) {
+        self.position = translation;
+        self.orientation = rotation;
+        self.update_push_constant_buffer();
+    }
+}
+
+impl PhysicsEntity for Transform {
+    fn set_pose(&mut self, translation: Vector3<f32>, rotation: Quaternion<f32>) {
+        self.translation = translation;
+        self.rotation = rotation;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BodyType {
+    Dynamic,
+    Fixed,
+    // Moved by the application with set_kinematic_pose, pushes dynamic bodies out of the way
+    Kinematic,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColliderShape {
+    // Half extents
+    Cuboid(Vector3<f32>),
+    Ball(f32),
+    // Along the y axis, the half height excludes the caps
+    Capsule { half_height: f32, radius: f32 },
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct BodyConfiguration {
+    body_type: BodyType,
+    shape: ColliderShape,
+    translation: Vector3<f32>,
+    rotation: Quaternion<f32>,
+    linear_velocity: Vector3<f32>,
+    density: f32,
+    friction: f32,
+    restitution: f32,
+}
+
+impl BodyConfiguration {
+    pub fn new(body_type: BodyType, shape: ColliderShape) -> Self {
+        BodyConfiguration {
+            body_type,
+            shape,
+            translation: Vector3::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            linear_velocity: Vector3::new(0.0, 0.0, 0.0),
+            density: 1.0,
+            friction: 0.5,
+            restitution: 0.0,
+        }
+    }
+
+    pub fn with_pose(mut self, translation: Vector3<f32>, rotation: Quaternion<f32>) -> Self {
+        self.translation = translation;
+        self.rotation = rotation;
+
+        self
+    }
+
+    pub fn with_linear_velocity(mut self, linear_velocity: Vector3<f32>) -> Self {
+        self.linear_velocity = linear_velocity;
+
+        self
+    }
+
+    pub fn with_density(mut self, density: f32) -> Self {
+        self.density = density;
+
+        self
+    }
+
+    pub fn with_friction(mut self, friction: f32) -> Self {
+        self.friction = friction;
+
+        self
+    }
+
+    // 0 doesn't bounce at all, 1 keeps all of the speed
+    pub fn with_restitution(mut self, restitution: f32) -> Self {
+        self.restitution = restitution;
+
+        self
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BodyHandle(RigidBodyHandle);
+
+// Rigid bodies with one collider each, tied to the entities of the application by a key, e.g. the index in its
+// entity list. Stepped from VulkrapApplication::fixed_update, sync then moves the entities to their bodies.
+pub struct PhysicsWorld<K> {
+    gravity: Vector<Real>,
+    integration_parameters: IntegrationParameters,
+    pipeline: PhysicsPipeline,
+    islands: IslandManager,
+    broad_phase: BroadPhase,
+    narrow_phase: NarrowPhase,
+    bodies: RigidBodySet,
+    colliders: ColliderSet,
+    impulse_joints: ImpulseJointSet,
+    multibody_joints: MultibodyJointSet,
+    ccd_solver: CCDSolver,
+    entities: HashMap<RigidBodyHandle, K>,
+}
+
+impl<K: Copy> PhysicsWorld<K> {
+    pub fn new(gravity: Vector3<f32>) -> Self {
+        PhysicsWorld {
+            gravity: _to_vector(gravity),
+            integration_parameters: IntegrationParameters::default(),
+            pipeline: PhysicsPipeline::new(),
+            islands: IslandManager::new(),
+            broad_phase: BroadPhase::new(),
+            narrow_phase: NarrowPhase::new(),
+            bodies: RigidBodySet::new(),
+            colliders: ColliderSet::new(),
+            impulse_joints: ImpulseJointSet::new(),
+            multibody_joints: MultibodyJointSet::new(),
+            ccd_solver: CCDSolver::new(),
+            entities: HashMap::new(),
+        }
+    }
+
+    pub fn add_body(&mut self, entity: K, configuration: BodyConfiguration) -> BodyHandle {
+        let builder = match configuration.body_type {
+            BodyType::Dynamic => RigidBodyBuilder::dynamic(),
+            BodyType::Fixed => RigidBodyBuilder::fixed(),
+            BodyType::Kinematic => RigidBodyBuilder::kinematic_position_based(),
+        };
+        let body = builder
+            .position(_to_isometry(configuration.translation, configuration.rotation))
+            .linvel(_to_vector(configuration.linear_velocity))
+            .build();
+        let handle = self.bodies.insert(body);
+
+        let collider = match configuration.shape {
+            ColliderShape::Cuboid(half_extents) => {
+                ColliderBuilder::cuboid(half_extents.x, half_extents.y, half_extents.z)
+            }
+            ColliderShape::Ball(radius) => ColliderBuilder::ball(radius),
+            ColliderShape::Capsule { half_height, radius } => ColliderBuilder::capsule_y(half_height, radius),
+        }
+        .density(configuration.density)
+        .friction(configuration.friction)
+        .restitution(configuration.restitution)
+        .build();
+        self.colliders.insert_with_parent(collider, handle, &mut self.bodies);
+
+        self.entities.insert(handle, entity);
+
+        BodyHandle(handle)
+    }
+
+    pub fn remove_body(&mut self, body: BodyHandle) {
+        self.bodies.remove(
+            body.0,
+            &mut self.islands,
+            &mut self.colliders,
+            &mut self.impulse_joints,
+            &mut self.multibody_joints,
+            true,
+        );
+        self.entities.remove(&body.0);
+    }
+
+    pub fn step(&mut self, timestep_s: f32) {
+        self.integration_parameters.dt = timestep_s;
+        self.pipeline.step(
+            &self.gravity,
+            &self.integration_parameters,
+            &mut self.islands,
+            &mut self.broad_phase,
+            &mut self.narrow_phase,
+            &mut self.bodies,
+            &mut self.colliders,
+            &mut self.impulse_joints,
+            &mut self.multibody_joints,
+            &mut self.ccd_solver,
+            None,
+            &(),
+            &(),
+        );
+    }
+
+    // Moves the entities to their bodies, entities of fixed bodies keep their pose
+    pub fn sync<E: PhysicsEntity>(&self, entities: &mut impl IndexMut<K, Output = E>) {
+        for (handle, entity) in self.entities.iter() {
+            let body = &self.bodies[*handle];
+            if body.is_fixed() {
+                continue;
+            }
+
+            let (translation, rotation) = _from_isometry(body.position());
+            entities[*entity].set_pose(translation, rotation);
+        }
+    }
+
+    pub fn pose(&self, body: BodyHandle) -> (Vector3<f32>, Quaternion<f32>) {
+        _from_isometry(self.bodies[body.0].position())
+    }
+
+    pub fn linear_velocity(&self, body: BodyHandle) -> Vector3<f32> {
+        let velocity = self.bodies[body.0].linvel();
+
+        Vector3::new(velocity.x, velocity.y, velocity.z)
+    }
+
+    pub fn apply_impulse(&mut self, body: BodyHandle, impulse: Vector3<f32>) {
+        self.bodies[body.0].apply_impulse(_to_vector(impulse), true);
+    }
+
+    // Reached at the end of the next step
+    pub fn set_kinematic_pose(&mut self, body: BodyHandle, translation: Vector3<f32>, rotation: Quaternion<f32>) {
+        let body = &mut self.bodies[body.0];
+        debug_assert!(body.is_kinematic(), "Body is not kinematic");
+
+        body.set_next_kinematic_position(_to_isometry(translation, rotation));
+    }
+
+    pub fn len(&self) -> usize {
+        self.bodies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bodies.is_empty()
+    }
+}
+
+fn _to_vector(vector: Vector3<f32>) -> Vector<Real> {
+    vector![vector.x, vector.y, vector.z]
+}
+
+fn _to_isometry(translation: Vector3<f32>, rotation: Quaternion<f32>) -> Isometry3<Real> {
+    let rotation = NaQuaternion::new(rotation.s, rotation.v.x, rotation.v.y, rotation.v.z);
+
+    Isometry3::from_parts(
+        Translation3::new(translation.x, translation.y, translation.z),
+        UnitQuaternion::from_quaternion(rotation),
+    )
+}
+
+fn _from_isometry(isometry: &Isometry3<Real>) -> (Vector3<f32>, Quaternion<f32>) {
+    let translation = isometry.translation.vector;
+    let rotation = isometry.rotation;
+
+    (
+        Vector3::new(translation.x, translation.y, translation.z),
+        Quaternion::new(rotation.w, rotation.i, rotation.j, rotation.k),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::runtime::FIXED_TIMESTEP;
+    use cgmath::{Deg, InnerSpace, Rotation3};
+
+    #[test]
+    fn bodies_move_their_entities() {
+        let mut world = PhysicsWorld::new(Vector3::new(0.0, -9.81, 0.0));
+        let mut entities = vec![Transform::identity(); 3];
+
+        let ground = Transform::from_translation(Vector3::new(0.0, -0.5, 0.0));
+        entities[0] = ground;
+        world.add_body(
+            0,
+            BodyConfiguration::new(BodyType::Fixed, ColliderShape::Cuboid(Vector3::new(10.0, 0.5, 10.0)))
+                .with_pose(ground.translation, ground.rotation),
+        );
+        let ball = world.add_body(
+            1,
+            BodyConfiguration::new(BodyType::Dynamic, ColliderShape::Ball(0.5))
+                .with_pose(Vector3::new(0.0, 3.0, 0.0), Quaternion::new(1.0, 0.0, 0.0, 0.0)),
+        );
+        let platform = world.add_body(
+            2,
+            BodyConfiguration::new(BodyType::Kinematic, ColliderShape::Cuboid(Vector3::new(1.0, 0.1, 1.0)))
+                .with_pose(Vector3::new(5.0, 1.0, 0.0), Quaternion::new(1.0, 0.0, 0.0, 0.0)),
+        );
+        assert_eq!(world.len(), 3);
+
+        let rotation = Quaternion::from_angle_y(Deg(90.0));
+        world.set_kinematic_pose(platform, Vector3::new(5.0, 2.0, 0.0), rotation);
+        for _ in 0..180 {
+            world.step(FIXED_TIMESTEP);
+        }
+        world.sync(&mut entities);
+
+        // Resting on the ground, the fixed ground is left alone
+        assert_eq!(entities[0], ground);
+        assert!((entities[1].translation.y - 0.5).abs() < 0.05, "{:?}", entities[1].translation);
+        assert!(world.linear_velocity(ball).y.abs() < 0.1);
+        assert_eq!(world.pose(ball).0, entities[1].translation);
+
+        assert!((entities[2].translation - Vector3::new(5.0, 2.0, 0.0)).magnitude2() < 1.0e-6);
+        assert!((entities[2].rotation - rotation).magnitude2() < 1.0e-6);
+
+        world.apply_impulse(ball, Vector3::new(0.0, 5.0, 0.0));
+        world.step(FIXED_TIMESTEP);
+        assert!(world.linear_velocity(ball).y > 1.0);
+
+        world.remove_body(ball);
+        assert_eq!(world.len(), 2);
+        entities[1] = Transform::identity();
+        world.step(FIXED_TIMESTEP);
+        world.sync(&mut entities);
+        assert_eq!(entities[1], Transform::identity());
+    }
+}
@@ -0,0 +1,172 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use winit::event::{ElementState, VirtualKeyCode};
+
+use crate::error::VulkrapError;
+use crate::util::file;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputEvent {
+    Keyboard(VirtualKeyCode, ElementState),
+    MouseMotion(f64, f64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RecordedInputEvent {
+    pub frame: u64,
+    pub event: InputEvent,
+}
+
+pub struct InputRecorder {
+    frame: u64,
+    events: Vec<RecordedInputEvent>,
+}
+
+impl InputRecorder {
+    pub fn new() -> Self {
+        InputRecorder { frame: 0, events: Vec::new() }
+    }
+
+    pub fn record(&mut self, event: InputEvent) {
+        self.events.push(RecordedInputEvent { frame: self.frame, event });
+    }
+
+    pub fn step(&mut self) {
+        self.frame += 1;
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), &'static str> {
+        let mut file = File::create(path).map_err(|_| "Failed to create file")?;
+
+        writeln!(file, "frames {}", self.frame).map_err(|_| "Failed to write file")?;
+        for recorded in self.events.iter() {
+            _write_event(&mut file, recorded).map_err(|_| "Failed to write file")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for InputRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct InputPlayback {
+    frame: u64,
+    frame_count: u64,
+    events: Vec<RecordedInputEvent>,
+    next_event: usize,
+}
+
+impl InputPlayback {
+    pub fn load(path: &Path) -> Result<Self, VulkrapError> {
+        let mut lines = file::read_lines(path)?;
+
+        let header = lines.next().ok_or("Empty recording")?.map_err(|error| VulkrapError::io(path, error))?;
+        let frame_count = _parse_header(&header)?;
+
+        let mut events = Vec::new();
+        for line in lines {
+            let line = line.map_err(|error| VulkrapError::io(path, error))?;
+            if !line.is_empty() {
+                events.push(_parse_event(&line)?);
+            }
+        }
+
+        Ok(InputPlayback { frame: 0, frame_count, events, next_event: 0 })
+    }
+
+    pub fn next_frame_events(&mut self) -> &[RecordedInputEvent] {
+        let start = self.next_event;
+        while self.next_event < self.events.len() && self.events[self.next_event].frame <= self.frame {
+            self.next_event += 1;
+        }
+        self.frame += 1;
+
+        &self.events[start..self.next_event]
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frame >= self.frame_count
+    }
+}
+
+fn _write_event(file: &mut File, recorded: &RecordedInputEvent) -> std::io::Result<()> {
+    match recorded.event {
+        InputEvent::Keyboard(key, state) => {
+            let pressed = if state == ElementState::Pressed { 1 } else { 0 };
+            writeln!(file, "{} k {} {}", recorded.frame, key as u32, pressed)
+        }
+        InputEvent::MouseMotion(x, y) => writeln!(file, "{} m {} {}", recorded.frame, x, y),
+    }
+}
+
+fn _parse_header(line: &str) -> Result<u64, &'static str> {
+    let mut split = line.split_ascii_whitespace();
+    if split.next() != Some("frames") {
+        return Err("Invalid recording header");
+    }
+    split.next().and_then(|frames| frames.parse::<u64>().ok()).ok_or("Invalid recording header")
+}
+
+fn _parse_event(line: &str) -> Result<RecordedInputEvent, &'static str> {
+    let split: Vec<&str> = line.split_ascii_whitespace().collect();
+    if split.len() != 4 {
+        return Err("Invalid recorded event");
+    }
+
+    let frame = split[0].parse::<u64>().map_err(|_| "Invalid event frame")?;
+    let event = match split[1] {
+        "k" => {
+            let key_code = split[2].parse::<u32>().map_err(|_| "Invalid key code")?;
+            let state = match split[3] {
+                "1" => ElementState::Pressed,
+                "0" => ElementState::Released,
+                _ => return Err("Invalid key state"),
+            };
+            InputEvent::Keyboard(_key_from_code(key_code)?, state)
+        }
+        "m" => {
+            let x = split[2].parse::<f64>().map_err(|_| "Invalid mouse delta")?;
+            let y = split[3].parse::<f64>().map_err(|_| "Invalid mouse delta")?;
+            InputEvent::MouseMotion(x, y)
+        }
+        _ => return Err("Invalid event type"),
+    };
+
+    Ok(RecordedInputEvent { frame, event })
+}
+
+fn _key_from_code(key_code: u32) -> Result<VirtualKeyCode, &'static str> {
+    if key_code > VirtualKeyCode::Cut as u32 {
+        return Err("Invalid key code");
+    }
+    // VirtualKeyCode is repr(u32) with contiguous discriminants ending at Cut.
+    Ok(unsafe { std::mem::transmute::<u32, VirtualKeyCode>(key_code) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_event() {
+        let key = _parse_event("12 k 36 1").unwrap();
+        assert_eq!(key.frame, 12);
+        assert_eq!(key.event, InputEvent::Keyboard(_key_from_code(36).unwrap(), ElementState::Pressed));
+
+        let mouse = _parse_event("3 m -1.5 2").unwrap();
+        assert_eq!(mouse.event, InputEvent::MouseMotion(-1.5, 2.0));
+
+        assert!(_parse_event("3 k 99999 1").is_err());
+        assert!(_parse_event("3 x 1 1").is_err());
+    }
+}
@@ -1,33 +1,107 @@
+use std::path::Path;
+#[cfg(feature = "ui")]
+use std::time::Duration;
+use std::time::Instant;
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use winit::event::{ElementState, VirtualKeyCode};
 use winit::window::Window;
 
-use crate::engine::datatypes::{WindowExtent};
+#[cfg(feature = "ui")]
+use crate::engine::datatypes::ViewProjectionUniform;
+use crate::engine::datatypes::WindowExtent;
 
+#[cfg(feature = "console")]
 use crate::engine::console::Console;
-use crate::engine::cvars::{ConfigVariables, WINDOW_HEIGHT, WINDOW_WIDTH};
+#[cfg(feature = "console")]
+use crate::engine::cvars::{CON_HISTORY_FILE, CON_HISTORY_SIZE};
+#[cfg(feature = "console")]
+use crate::engine::ui::cvarpanel::CvarPanel;
+#[cfg(feature = "console")]
+use crate::engine::ui::widgets::console_history_columns;
+use crate::engine::cvars::{
+    ConfigVariables, DEMO_FILE, FULLSCREEN_EXCLUSIVE, FULLSCREEN_WINDOWED, LOG_CAPACITY, R_FRAMES_IN_FLIGHT,
+    R_GPU_VALIDATION, R_VALIDATION, STATS_SPIKE_THRESHOLD, RNG_SEED, TIMESCALE, WINDOW_FULLSCREEN, WINDOW_HEIGHT,
+    WINDOW_TITLE, WINDOW_WIDTH,
+};
+#[cfg(feature = "ui")]
+use crate::engine::cvars::{R_DYNRES_TARGET, R_RENDERSCALE};
+#[cfg(feature = "scripting")]
+use crate::engine::cvars::SCRIPT_AUTOEXEC;
+use crate::engine::math::Rng;
 use crate::engine::mesh::{MeshManager};
+use crate::engine::recording::{InputEvent, InputPlayback, InputRecorder};
+#[cfg(feature = "scripting")]
+use crate::engine::scripting::Scripts;
 use crate::engine::stats;
+use crate::engine::timedemo::Timedemo;
+use crate::engine::timescale::TimeScale;
+#[cfg(feature = "ui")]
 use crate::engine::ui::hud::Hud;
-use crate::renderer::context::Context;
-use crate::renderer::types::UniformHandle;
+#[cfg(feature = "ui")]
+use crate::engine::ui::renderscale::{DynamicResolution, ScaledScenePresenter};
+use crate::log::{crash, logger};
+use crate::renderer::context::{Context, Frame};
+use crate::renderer::types::ContextConfiguration;
+#[cfg(feature = "ui")]
+use crate::renderer::types::{RenderPassHandle, UniformHandle};
+
+pub(crate) const FIXED_TIMESTEP: f32 = 1.0 / 60.0;
+// Fixed updates dropped after a long frame instead of trying to catch up
+const MAX_FIXED_UPDATES_PER_FRAME: u32 = 8;
+const FULLSCREEN_BUTTON: VirtualKeyCode = VirtualKeyCode::F11;
+const GRAPH_FILE: &str = "framegraph.dot";
+const FRAME_FILE: &str = "frame.json";
 
 pub trait VulkrapApplication {
 
     fn update(&mut self, context: &mut Context, delta_time_s: f32);
-    fn draw(&mut self, context: &mut Context);
+    // Called zero or more times before update with a constant timestep, e.g. for stepping physics
+    fn fixed_update(&mut self, _context: &mut Context, _timestep_s: f32) {}
+    fn draw(&mut self, frame: &mut Frame);
 
     fn reconfigure(&mut self, config: &ConfigVariables);
     fn handle_mouse_input(&mut self, x_delta: f64, y_delta: f64);
     fn handle_window_resize(&mut self, context: &mut Context, new_extent: WindowExtent);
     fn handle_keyboard_event(&mut self, context: &mut Context, key: VirtualKeyCode, state: ElementState) -> ControlSignal;
+
+    // Console input that is neither a cvar nor an engine command, split on whitespace. Returns None for
+    // unknown commands. Lets applications and scripting layers add their own commands.
+    fn handle_console_command(
+        &mut self,
+        _context: &mut Context,
+        _config: &mut ConfigVariables,
+        _args: &[&str],
+    ) -> Option<ControlSignal> {
+        None
+    }
+
+    // Appended to the window_title cvar, e.g. the name of the current level. Polled every frame.
+    fn window_title_suffix(&self) -> Option<String> {
+        None
+    }
+
+    // Extra extensions and device features, requested before the context and the application are created
+    fn context_configuration() -> ContextConfiguration
+    where
+        Self: Sized,
+    {
+        ContextConfiguration::new()
+    }
 }
 
 pub struct EngineParameters<'a> {
     pub mesh_manager: &'a mut MeshManager,
     pub config: &'a mut ConfigVariables,
     pub window_extent: WindowExtent,
-
-    pub hud_vp_uniform: UniformHandle,
+    // Seeded with the rng_seed cvar, split streams off it to reproduce a run with the same seed
+    pub rng: Rng,
+
+    #[cfg(feature = "ui")]
+    pub hud_vp_uniform: UniformHandle<ViewProjectionUniform>,
+    // Main scene rendered at r_renderscale, applications without pipelines in it draw to the swapchain directly
+    #[cfg(feature = "ui")]
+    pub scene_pass: RenderPassHandle,
 }
 
 pub type VulkrapApplicationFactory<T> = fn(context: &mut Context, engine_parameters : EngineParameters) -> T;
@@ -35,69 +109,274 @@ pub type VulkrapApplicationFactory<T> = fn(context: &mut Context, engine_paramet
 pub struct Runtime<T: VulkrapApplication> {
     context: Context,
     config: ConfigVariables,
+    #[cfg(feature = "console")]
     console: Console,
+    #[cfg(feature = "ui")]
     hud: Hud,
-    app: T
+    #[cfg(feature = "ui")]
+    scene: ScaledScenePresenter,
+    #[cfg(feature = "ui")]
+    dynamic_resolution: DynamicResolution,
+    #[cfg(feature = "scripting")]
+    scripts: Scripts,
+    app: T,
+    window_extent: WindowExtent,
+    window_title_suffix: Option<String>,
+    last_fullscreen_mode: u32,
+    fixed_update_time_s: f32,
+    time_scale: TimeScale,
+
+    timedemo: Option<Timedemo>,
+    recorder: Option<InputRecorder>,
+    playback: Option<InputPlayback>,
 }
 
 impl<T: VulkrapApplication> Runtime<T> {
-    pub fn new(window: &Window, mut config: ConfigVariables, app_factory: VulkrapApplicationFactory<T>) -> Runtime<T> {
-        let mut context = Context::new(window);
+    pub fn new(window: &Window, config: ConfigVariables, app_factory: VulkrapApplicationFactory<T>) -> Runtime<T> {
+        Self::from_context(
+            Context::new_with_configuration(
+                window,
+                T::context_configuration()
+                    .with_frames_in_flight(config.get(R_FRAMES_IN_FLIGHT).as_int() as usize)
+                    .with_validation(config.get(R_VALIDATION).as_int() != 0)
+                    .with_gpu_validation(config.get(R_GPU_VALIDATION).as_int() != 0),
+            ),
+            config,
+            app_factory,
+        )
+    }
+
+    // Library mode, the host creates the context with Context::from_raw_handles and calls update every frame
+    // from its own event loop. Control signals meant for the window are up to the host to handle.
+    pub fn from_context(mut context: Context, mut config: ConfigVariables, app_factory: VulkrapApplicationFactory<T>) -> Runtime<T> {
+        // Before any pipelines exist, cheap for a context not created by Runtime::new
+        _apply_frames_in_flight(&mut context, &config);
+
+        // The engine and application pipelines are created in one go once everything is set up
+        context.begin_pipeline_batch();
         let mut mesh_manager = MeshManager::new(&mut context);
 
         let (window_width, window_height) = context.get_framebuffer_extent();
         let window_extent = WindowExtent::new(window_width, window_height);
 
+        #[cfg(feature = "ui")]
         let hud = Hud::new(&mut context, window_extent, &mesh_manager);
+        #[cfg(feature = "ui")]
+        let mut scene = ScaledScenePresenter::new(
+            &mut context,
+            hud.get_vp_uniform(),
+            &mesh_manager,
+            config.get(R_RENDERSCALE).as_float(),
+            window_extent,
+        );
 
+        let rng = Rng::new(_resolve_rng_seed(&mut config) as u64);
         let engine_params = EngineParameters {
             mesh_manager: &mut mesh_manager,
             config: &mut config,
             window_extent,
-            hud_vp_uniform: hud.get_vp_uniform()
+            rng,
+            #[cfg(feature = "ui")]
+            hud_vp_uniform: hud.get_vp_uniform(),
+            #[cfg(feature = "ui")]
+            scene_pass: scene.get_render_pass(),
         };
 
         let app = app_factory(&mut context, engine_params);
+        context.end_pipeline_batch();
+        #[cfg(feature = "ui")]
+        if context.render_pass_pipeline_count(scene.get_render_pass()) == Ok(0) {
+            scene.set_enabled(&mut context, false);
+        }
+        #[cfg(feature = "ui")]
+        let mut dynamic_resolution = DynamicResolution::new();
+        #[cfg(feature = "ui")]
+        dynamic_resolution.set_target_frame_time(config.get(R_DYNRES_TARGET).as_float() / 1000.0);
+        logger::set_capacity(config.get(LOG_CAPACITY).as_int() as usize);
+        stats::get().set_spike_threshold(config.get(STATS_SPIKE_THRESHOLD).as_float() / 1000.0);
+        crash::set_cvars(config.get_all_desc());
+        #[cfg(feature = "console")]
+        let mut console = Console::new();
+        #[cfg(feature = "console")]
+        console.set_history_columns(console_history_columns(window_extent));
+        #[cfg(feature = "console")]
+        if config.get(CON_HISTORY_SIZE).as_int() > 0 {
+            let path = config.get(CON_HISTORY_FILE).as_str();
+            let history_size = config.get(CON_HISTORY_SIZE).as_int() as usize;
+            // Missing on the first run
+            if let Err(err) = console.load_history(Path::new(&path), history_size) {
+                log_debug!("console history: {}", err);
+            }
+        }
+        // Cvars set by the script are applied by the first update
+        #[cfg(feature = "scripting")]
+        let mut scripts = Scripts::new();
+        #[cfg(feature = "scripting")]
+        {
+            let path = config.get(SCRIPT_AUTOEXEC).as_str();
+            if !path.is_empty() && Path::new(&path).exists() {
+                if let Err(err) = scripts.run_file(&mut config, Path::new(&path)) {
+                    log_error!("script_autoexec: {}", err);
+                }
+            }
+        }
 
         Runtime {
             context,
             config,
-            console: Console::new(),
+            #[cfg(feature = "console")]
+            console,
+            #[cfg(feature = "ui")]
             hud,
+            #[cfg(feature = "ui")]
+            scene,
+            #[cfg(feature = "ui")]
+            dynamic_resolution,
+            #[cfg(feature = "scripting")]
+            scripts,
             app,
+            window_extent,
+            window_title_suffix: None,
+            last_fullscreen_mode: FULLSCREEN_EXCLUSIVE,
+            fixed_update_time_s: 0.0,
+            time_scale: TimeScale::new(),
+            timedemo: None,
+            recorder: None,
+            playback: None,
         }
     }
 
-    pub fn update(&mut self, delta_time_s: f32) {
-        self.console.update(delta_time_s);
+    pub fn update(&mut self, delta_time_s: f32) -> ControlSignal {
+        #[cfg(feature = "console")]
+        self.console.update(&self.config, delta_time_s);
+
+        if let Some(playback) = &mut self.playback {
+            for recorded in playback.next_frame_events() {
+                match recorded.event {
+                    InputEvent::Keyboard(key, state) => {
+                        self.app.handle_keyboard_event(&mut self.context, key, state);
+                    }
+                    InputEvent::MouseMotion(x_delta, y_delta) => self.app.handle_mouse_input(x_delta, y_delta),
+                }
+            }
+        }
 
-        self.app.update(&mut self.context, delta_time_s);
+        // Recordings and timedemos run at the fixed timestep to stay reproducible
+        let simulation_time_s = if self.is_fixed_timestep() {
+            FIXED_TIMESTEP
+        } else {
+            self.time_scale.simulation_time(delta_time_s, self.config.get(TIMESCALE).as_float())
+        };
+        self.context.update_frame_globals(simulation_time_s);
+        let update_start = Instant::now();
+        self.fixed_update(simulation_time_s);
+        #[cfg(feature = "scripting")]
+        let script_control = self.update_scripts(simulation_time_s);
+        self.app.update(&mut self.context, simulation_time_s);
+        let update_time = update_start.elapsed();
+
+        if let Some(recorder) = &mut self.recorder {
+            recorder.step();
+        }
+        if self.playback.as_ref().is_some_and(|playback| playback.is_finished()) {
+            self.playback = None;
+            log_info!("playback: finished");
+        }
 
-        self.context.begin_frame();
+        let mut frame = self.context.begin_frame();
+
+        #[cfg(feature = "ui")]
+        self.scene.draw(&mut frame);
+        self.app.draw(&mut frame);
+        #[cfg(feature = "console")]
+        self.hud.set_watch_lines(self.console.get_watch_lines(&self.config));
+        #[cfg(feature = "ui")]
+        self.hud.draw(&mut frame);
+        #[cfg(feature = "console")]
+        self.hud.draw_cvar_panel(&mut frame, &self.config);
+        #[cfg(feature = "console")]
+        self.hud.draw_console(&mut frame, &self.console);
+
+        let render_stats = frame.submit();
+        if let Some(capture) = self.context.take_frame_capture() {
+            match std::fs::write(FRAME_FILE, capture) {
+                Ok(()) => log_info!("dumpframe: wrote {}", FRAME_FILE),
+                Err(err) => log_error!("dumpframe: {}: {}", FRAME_FILE, err),
+            }
+        }
+        #[cfg(feature = "ui")]
+        self.update_dynamic_resolution(delta_time_s, render_stats.gpu_time);
 
-        self.app.draw(&mut self.context);
-        self.hud.draw(&mut self.context, &self.console);
+        if let Some(timedemo) = &mut self.timedemo {
+            timedemo.add_frame(delta_time_s, &render_stats);
+            if timedemo.is_finished() {
+                self.stop_timedemo();
+            }
+        }
 
-        let render_stats = self.context.end_frame();
         {
             let mut engine_stats = stats::get();
             engine_stats.update_delta_time(delta_time_s);
+            engine_stats.set_update_time(update_time);
             engine_stats.set_render_stats(render_stats);
         }
+
+        // A changed title suffix is picked up by the next frame when a script signal is returned
+        #[cfg(feature = "scripting")]
+        if script_control != ControlSignal::None {
+            return script_control;
+        }
+        let window_title_suffix = self.app.window_title_suffix();
+        if window_title_suffix != self.window_title_suffix {
+            self.window_title_suffix = window_title_suffix;
+            return ControlSignal::UpdateWindowTitle;
+        }
+
+        ControlSignal::None
     }
 
     pub fn handle_mouse_input(&mut self, x_delta: f64, y_delta: f64) {
+        if self.timedemo.is_some() || self.playback.is_some() {
+            return;
+        }
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(InputEvent::MouseMotion(x_delta, y_delta));
+        }
         self.app.handle_mouse_input(x_delta, y_delta);
     }
 
     pub fn handle_window_resize(&mut self, new_extent: WindowExtent) {
+        self.window_extent = new_extent;
         self.config.set(WINDOW_WIDTH, new_extent.width);
         self.config.set(WINDOW_HEIGHT, new_extent.height);
+        crash::set_cvars(self.config.get_all_desc());
 
+        #[cfg(feature = "ui")]
         self.hud.handle_window_resize(&mut self.context, new_extent);
+        #[cfg(feature = "console")]
+        self.console.set_history_columns(console_history_columns(new_extent));
+        #[cfg(feature = "ui")]
+        self.scene.handle_window_resize(new_extent);
         self.app.handle_window_resize(&mut self.context, new_extent);
 
-        self.context.handle_window_resize();
+        self.context.resize_surface(new_extent.width, new_extent.height);
+    }
+
+    pub fn get_config(&self) -> &ConfigVariables {
+        &self.config
+    }
+
+    pub fn get_window_title(&self) -> String {
+        let title = self.config.get(WINDOW_TITLE).as_str();
+
+        match &self.window_title_suffix {
+            Some(suffix) => format!("{} - {}", title, suffix),
+            None => title,
+        }
+    }
+
+    pub fn get_window_extent(&self) -> WindowExtent {
+        self.window_extent
     }
 
     pub fn get_configured_extent(&self) -> WindowExtent {
@@ -108,42 +387,326 @@ impl<T: VulkrapApplication> Runtime<T> {
         unsafe {
             self.context.wait_idle();
         }
+
+        #[cfg(feature = "console")]
+        if self.config.get(CON_HISTORY_SIZE).as_int() > 0 {
+            let path = self.config.get(CON_HISTORY_FILE).as_str();
+            let history_size = self.config.get(CON_HISTORY_SIZE).as_int() as usize;
+            if let Err(err) = self.console.save_history(Path::new(&path), history_size) {
+                log_error!("console history: {}: {}", path, err);
+            }
+        }
     }
 
     pub fn handle_keyboard_event(&mut self, key: VirtualKeyCode, state: ElementState) -> ControlSignal {
+        #[cfg(feature = "console")]
         if self.console.is_active() {
-            let control = self.console.handle_keyboard_event(&mut self.config, key, state);
+            let mut control = self.console.handle_keyboard_event(&mut self.config, key, state);
+            if control == ControlSignal::ApplicationCommand {
+                control = self.handle_application_command();
+            }
 
             if self.config.is_dirty() {
                 self.reconfigure();
             }
-            return control;
+
+            return self.handle_runtime_signal(control);
         }
 
         match (key, state) {
+            #[cfg(feature = "console")]
             (Console::TOGGLE_BUTTON, ElementState::Pressed) => self.console.toggle(),
+            #[cfg(feature = "console")]
+            (CvarPanel::TOGGLE_BUTTON, ElementState::Pressed) => self.hud.toggle_cvar_panel(),
+            (FULLSCREEN_BUTTON, ElementState::Pressed) => return self.toggle_fullscreen(),
             _ => {}
         }
 
-        
+        #[cfg(feature = "console")]
+        if let Some(control) = self.hud.handle_cvar_panel_key(&mut self.config, key, state) {
+            if self.config.is_dirty() {
+                self.reconfigure();
+            }
+
+            return self.handle_runtime_signal(control);
+        }
 
-        self.app.handle_keyboard_event(&mut self.context, key, state)
+        if self.timedemo.is_some() || self.playback.is_some() {
+            return ControlSignal::None;
+        }
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(InputEvent::Keyboard(key, state));
+        }
+
+        let control = self.app.handle_keyboard_event(&mut self.context, key, state);
+        self.handle_runtime_signal(control)
+    }
+
+    // Signals the runtime handles itself, sent by console commands or by the application when the console is disabled
+    fn handle_runtime_signal(&mut self, control: ControlSignal) -> ControlSignal {
+        match control {
+            ControlSignal::Timedemo(duration_s) => {
+                self.start_timedemo(duration_s);
+                ControlSignal::None
+            }
+            ControlSignal::StartRecording => {
+                self.start_recording();
+                ControlSignal::None
+            }
+            ControlSignal::StopRecording => {
+                self.stop_recording();
+                ControlSignal::None
+            }
+            ControlSignal::StartPlayback => {
+                self.start_playback();
+                ControlSignal::None
+            }
+            ControlSignal::PrintSystemInfo => {
+                for line in self.context.system_info() {
+                    logger::output(&line);
+                }
+                ControlSignal::None
+            }
+            ControlSignal::DumpGraph => {
+                match std::fs::write(GRAPH_FILE, self.context.dump_graph()) {
+                    Ok(()) => log_info!("r_dumpgraph: wrote {}", GRAPH_FILE),
+                    Err(err) => log_error!("r_dumpgraph: {}: {}", GRAPH_FILE, err),
+                }
+                ControlSignal::None
+            }
+            ControlSignal::DumpFrame => {
+                self.context.capture_next_frame();
+                ControlSignal::None
+            }
+            ControlSignal::TogglePause => {
+                self.time_scale.toggle_pause();
+                log_info!("pause: {}", if self.time_scale.is_paused() { "paused" } else { "resumed" });
+                ControlSignal::None
+            }
+            ControlSignal::Step(frames) => {
+                self.time_scale.step(frames);
+                ControlSignal::None
+            }
+            _ => control,
+        }
+    }
+
+    #[cfg(feature = "console")]
+    fn handle_application_command(&mut self) -> ControlSignal {
+        let Some(input) = self.console.take_application_command() else {
+            return ControlSignal::None;
+        };
+
+        let args: Vec<&str> = input.split_whitespace().collect();
+        #[cfg(feature = "scripting")]
+        if let Some(control) = self.scripts.handle_command(&mut self.config, &args) {
+            return control;
+        }
+        match self.app.handle_console_command(&mut self.context, &mut self.config, &args) {
+            Some(control) => control,
+            None => {
+                log_error!("unknown command or cvar: {}", input);
+                ControlSignal::None
+            }
+        }
+    }
+
+    #[cfg(feature = "scripting")]
+    fn update_scripts(&mut self, delta_time_s: f32) -> ControlSignal {
+        let control = self.scripts.update(&mut self.config, delta_time_s);
+        if self.config.is_dirty() {
+            self.reconfigure();
+        }
+
+        self.handle_runtime_signal(control)
+    }
+
+    // Switches between windowed and the last used fullscreen mode
+    fn toggle_fullscreen(&mut self) -> ControlSignal {
+        let fullscreen = self.config.get(WINDOW_FULLSCREEN).as_int();
+        if fullscreen == FULLSCREEN_WINDOWED {
+            self.config.set(WINDOW_FULLSCREEN, self.last_fullscreen_mode);
+        } else {
+            self.last_fullscreen_mode = fullscreen;
+            self.config.set(WINDOW_FULLSCREEN, FULLSCREEN_WINDOWED);
+        }
+        self.reconfigure();
+
+        ControlSignal::UpdateFullscreen
+    }
+
+    fn start_timedemo(&mut self, duration_s: u32) {
+        log_info!("timedemo: running for {} seconds", duration_s);
+
+        self.hide_console();
+        self.timedemo = Some(Timedemo::new(duration_s));
+    }
+
+    fn stop_timedemo(&mut self) {
+        if let Some(timedemo) = self.timedemo.take() {
+            let summary = timedemo.summary();
+            log_info!("timedemo: {} frames in {:.3} s", summary.frames, summary.total_time);
+            log_info!(
+                "timedemo: avg {:.1} fps, min {:.1} fps, max {:.1} fps, 1% low {:.1} fps",
+                summary.avg_fps,
+                summary.min_fps,
+                summary.max_fps,
+                summary.low_1_percent_fps
+            );
+            log_info!(
+                "timedemo: avg {} draw commands, avg {} triangles",
+                summary.avg_draw_commands,
+                summary.avg_triangles
+            );
+        }
+    }
+
+    fn start_recording(&mut self) {
+        if self.playback.is_some() {
+            log_error!("record: playback in progress");
+            return;
+        }
+        log_info!("record: recording input to {}", self.config.get(DEMO_FILE).as_str());
+        self.recorder = Some(InputRecorder::new());
+    }
+
+    fn stop_recording(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            let path = self.config.get(DEMO_FILE).as_str();
+            match recorder.save(Path::new(&path)) {
+                Ok(()) => log_info!("record: saved {} frames to {}", recorder.frame_count(), path),
+                Err(err) => log_error!("record: {}: {}", path, err),
+            }
+        } else {
+            log_error!("stoprecord: not recording");
+        }
+    }
+
+    fn start_playback(&mut self) {
+        if self.recorder.is_some() {
+            log_error!("playback: recording in progress");
+            return;
+        }
+
+        let path = self.config.get(DEMO_FILE).as_str();
+        match InputPlayback::load(Path::new(&path)) {
+            Ok(playback) => {
+                log_info!("playback: playing {}", path);
+                self.hide_console();
+                self.playback = Some(playback);
+            }
+            Err(err) => log_error!("playback: {}: {}", path, err),
+        }
+    }
+
+    fn hide_console(&mut self) {
+        #[cfg(feature = "console")]
+        if self.console.is_active() {
+            self.console.toggle();
+        }
+    }
+
+    fn fixed_update(&mut self, delta_time_s: f32) {
+        self.fixed_update_time_s += delta_time_s;
+
+        let mut updates = 0;
+        while self.fixed_update_time_s >= FIXED_TIMESTEP {
+            if updates == MAX_FIXED_UPDATES_PER_FRAME {
+                self.fixed_update_time_s = 0.0;
+                break;
+            }
+            self.app.fixed_update(&mut self.context, FIXED_TIMESTEP);
+            self.fixed_update_time_s -= FIXED_TIMESTEP;
+            updates += 1;
+        }
+    }
+
+    fn is_fixed_timestep(&self) -> bool {
+        self.timedemo.is_some() || self.recorder.is_some() || self.playback.is_some()
+    }
+
+    // Dynamic resolution only lowers the scale below r_renderscale
+    #[cfg(feature = "ui")]
+    fn reconfigure_render_scale(&mut self) {
+        let max_scale = self.config.get(R_RENDERSCALE).as_float();
+        self.dynamic_resolution
+            .set_target_frame_time(self.config.get(R_DYNRES_TARGET).as_float() / 1000.0);
+        if !self.dynamic_resolution.is_enabled() || self.scene.render_scale() > max_scale {
+            self.scene.set_render_scale(&mut self.context, max_scale);
+        }
+    }
+
+    #[cfg(feature = "ui")]
+    fn update_dynamic_resolution(&mut self, delta_time_s: f32, gpu_time: Option<Duration>) {
+        if !self.scene.is_enabled() {
+            return;
+        }
+
+        let render_scale = self.scene.render_scale();
+        let max_scale = self.config.get(R_RENDERSCALE).as_float();
+        if let Some(new_scale) = self.dynamic_resolution.update(delta_time_s, gpu_time, render_scale, max_scale) {
+            log_debug!("dynamic resolution: render scale {:.2} -> {:.2}", render_scale, new_scale);
+            self.scene.set_render_scale(&mut self.context, new_scale);
+        }
+
+        let dynamic = self.dynamic_resolution.is_enabled();
+        stats::get().set_render_scale(Some((self.scene.render_scale(), dynamic)));
     }
 
     fn reconfigure(&mut self) {
         // TODO: should add some method to config that returns the dirty cvar ids so we dont have to reconfigure everything every time.
         self.app.reconfigure(&self.config);
+        logger::set_capacity(self.config.get(LOG_CAPACITY).as_int() as usize);
+        stats::get().set_spike_threshold(self.config.get(STATS_SPIKE_THRESHOLD).as_float() / 1000.0);
+        #[cfg(feature = "ui")]
+        self.reconfigure_render_scale();
+        _apply_frames_in_flight(&mut self.context, &self.config);
+        crash::set_cvars(self.config.get_all_desc());
 
         self.config.clear_dirty();
     }
 
 }
 
+fn _apply_frames_in_flight(context: &mut Context, config: &ConfigVariables) {
+    // A no-op when unchanged
+    if let Err(err) = context.set_frames_in_flight(config.get(R_FRAMES_IN_FLIGHT).as_int() as usize) {
+        log_error!("r_frames_in_flight: {}", err);
+    }
+}
+
+// The seed is written back to the cvar and logged, so that a run seeded from the clock can be repeated
+fn _resolve_rng_seed(config: &mut ConfigVariables) -> u32 {
+    let mut seed = config.get(RNG_SEED).as_int();
+    if seed == 0 {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(1, |duration| duration.subsec_nanos());
+        seed = nanos.max(1);
+        config.set(RNG_SEED, seed);
+    }
+    log_info!("rng: seed {}", seed);
+
+    seed
+}
+
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum ControlSignal {
     None,
     Quit,
     ResizeWindow,
+    Timedemo(u32),
+    StartRecording,
+    StopRecording,
+    StartPlayback,
+    PrintSystemInfo,
+    UpdateWindowTitle,
+    UpdateFullscreen,
+    ListVideoModes,
+    DumpGraph,
+    DumpFrame,
+    TogglePause,
+    Step(u32),
+    // Sent by the console, handled by the runtime
+    ApplicationCommand,
 }
 
 
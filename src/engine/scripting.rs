@@ -0,0 +1,256 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::path::Path;
+use std::rc::Rc;
+
+use rhai::{Array, Dynamic, Engine, EvalAltResult, FnPtr, AST, FLOAT, INT};
+
+use crate::engine::cvars::{ConfigVariables, CvarType};
+use crate::engine::math::color::Color;
+use crate::engine::runtime::ControlSignal;
+
+// Shared with the functions registered on the rhai engine
+struct ScriptState {
+    // Swapped with the cvars of the runtime while a script runs
+    config: ConfigVariables,
+    // Change triggers of the cvars set by scripts, handled by the runtime like the ones of console input
+    control: ControlSignal,
+    // Index of the script running, callbacks are called with the AST they were defined in
+    current_script: usize,
+    commands: HashMap<String, (usize, FnPtr)>,
+    update_hooks: Vec<(usize, FnPtr)>,
+}
+
+// rhai scripts run by the runtime: the script in the script_autoexec cvar at startup and others with the exec
+// console command. Scripts read and write cvars with cvar(name) and set_cvar(name, value), add console commands
+// with register_command(name, callback) and hook the update of every frame with on_update(callback). Commands
+// get their arguments as an array of strings, update hooks the frame time in seconds.
+pub struct Scripts {
+    engine: Engine,
+    state: Rc<RefCell<ScriptState>>,
+    scripts: Vec<AST>,
+}
+
+impl Default for Scripts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scripts {
+    pub fn new() -> Self {
+        let state = Rc::new(RefCell::new(ScriptState {
+            config: ConfigVariables::new(),
+            control: ControlSignal::None,
+            current_script: 0,
+            commands: HashMap::new(),
+            update_hooks: Vec::new(),
+        }));
+
+        let mut engine = Engine::new();
+        engine.on_print(|text| log_info!("script: {}", text));
+        engine.on_debug(|text, _, position| log_debug!("script: {} ({})", text, position));
+
+        let cvar_state = state.clone();
+        engine.register_fn("cvar", move |name: &str| _get_cvar(&cvar_state.borrow().config, name));
+
+        let set_cvar_state = state.clone();
+        engine.register_fn("set_cvar", move |name: &str, value: Dynamic| {
+            let mut state = set_cvar_state.borrow_mut();
+            let control = _set_cvar(&mut state.config, name, value)?;
+            if control != ControlSignal::None {
+                state.control = control;
+            }
+
+            Ok::<(), Box<EvalAltResult>>(())
+        });
+
+        let command_state = state.clone();
+        engine.register_fn("register_command", move |name: &str, callback: FnPtr| {
+            let mut state = command_state.borrow_mut();
+            let script = state.current_script;
+            state.commands.insert(name.to_lowercase(), (script, callback));
+        });
+
+        let update_state = state.clone();
+        engine.register_fn("on_update", move |callback: FnPtr| {
+            let mut state = update_state.borrow_mut();
+            let script = state.current_script;
+            state.update_hooks.push((script, callback));
+        });
+
+        Scripts {
+            engine,
+            state,
+            scripts: Vec::new(),
+        }
+    }
+
+    pub fn run_file(&mut self, config: &mut ConfigVariables, path: &Path) -> Result<ControlSignal, String> {
+        let source = std::fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+
+        self.run(config, &path.display().to_string(), &source)
+    }
+
+    // Compiles and runs the top level statements of a script, its callbacks stay registered
+    pub fn run(&mut self, config: &mut ConfigVariables, name: &str, source: &str) -> Result<ControlSignal, String> {
+        let ast = self.engine.compile(source).map_err(|err| format!("{}: {}", name, err))?;
+        self.scripts.push(ast);
+        let script = self.scripts.len() - 1;
+
+        let result = self.with_config(config, script, |engine, ast| engine.run_ast(ast));
+
+        result.map_err(|err| format!("{}: {}", name, err))
+    }
+
+    // Runs the update hooks, errors are logged
+    pub fn update(&mut self, config: &mut ConfigVariables, delta_time_s: f32) -> ControlSignal {
+        let hooks = self.state.borrow().update_hooks.clone();
+
+        let mut control = ControlSignal::None;
+        for (script, callback) in hooks {
+            let result = self.with_config(config, script, |engine, ast| {
+                callback.call::<Dynamic>(engine, ast, (delta_time_s as FLOAT,)).map(|_| ())
+            });
+            match result {
+                Ok(ControlSignal::None) => {}
+                Ok(hook_control) => control = hook_control,
+                Err(err) => log_error!("script: {}: {}", callback.fn_name(), err),
+            }
+        }
+
+        control
+    }
+
+    // exec <file> and the commands registered by scripts, None for other input
+    pub fn handle_command(&mut self, config: &mut ConfigVariables, args: &[&str]) -> Option<ControlSignal> {
+        let command = args.first()?.trim_start_matches('/').to_lowercase();
+        if command == "exec" {
+            let control = match args {
+                [_, path] => self.run_file(config, Path::new(path)).unwrap_or_else(|err| {
+                    log_error!("exec: {}", err);
+                    ControlSignal::None
+                }),
+                _ => {
+                    log_error!("usage: exec <file>");
+                    ControlSignal::None
+                }
+            };
+            return Some(control);
+        }
+
+        let (script, callback) = self.state.borrow().commands.get(&command).cloned()?;
+        let command_args: Array = args[1..].iter().map(|arg| Dynamic::from(arg.to_string())).collect();
+        let result = self.with_config(config, script, |engine, ast| {
+            callback.call::<Dynamic>(engine, ast, (command_args,)).map(|_| ())
+        });
+
+        Some(result.unwrap_or_else(|err| {
+            log_error!("{}: {}", command, err);
+            ControlSignal::None
+        }))
+    }
+
+    fn with_config(
+        &mut self,
+        config: &mut ConfigVariables,
+        script: usize,
+        run: impl FnOnce(&Engine, &AST) -> Result<(), Box<EvalAltResult>>,
+    ) -> Result<ControlSignal, Box<EvalAltResult>> {
+        {
+            let mut state = self.state.borrow_mut();
+            std::mem::swap(config, &mut state.config);
+            state.current_script = script;
+            state.control = ControlSignal::None;
+        }
+
+        let result = run(&self.engine, &self.scripts[script]);
+
+        let mut state = self.state.borrow_mut();
+        std::mem::swap(config, &mut state.config);
+
+        result.map(|_| state.control)
+    }
+}
+
+fn _get_cvar(config: &ConfigVariables, name: &str) -> Result<Dynamic, Box<EvalAltResult>> {
+    let id = config.get_cvar_id_from_str(name).ok_or_else(|| format!("unknown cvar: {}", name))?;
+    let value = config.get(id);
+
+    Ok(match value.get_type() {
+        CvarType::Float => Dynamic::from(value.as_float() as FLOAT),
+        CvarType::Integer => Dynamic::from(value.as_int() as INT),
+        CvarType::String | CvarType::Color => Dynamic::from(value.as_str()),
+    })
+}
+
+// Set like console input, returns the change trigger of the cvar
+fn _set_cvar(config: &mut ConfigVariables, name: &str, value: Dynamic) -> Result<ControlSignal, Box<EvalAltResult>> {
+    let id = config.get_cvar_id_from_str(name).ok_or_else(|| format!("unknown cvar: {}", name))?;
+    let type_name = value.type_name();
+    let invalid = || format!("cvar {} can't be set to a {}", name, type_name);
+
+    match config.get(id).get_type() {
+        CvarType::Float => {
+            let value = value.as_float().or_else(|_| value.as_int().map(|value| value as FLOAT));
+            config.set(id, value.map_err(|_| invalid())? as f32);
+        }
+        CvarType::Integer => {
+            let value = value.as_int().ok().and_then(|value| u32::try_from(value).ok());
+            config.set(id, value.ok_or_else(invalid)?);
+        }
+        CvarType::String => {
+            config.set(id, value.into_string().map_err(|_| invalid())?);
+        }
+        CvarType::Color => {
+            let hex = value.into_string().map_err(|_| invalid())?;
+            config.set(id, Color::from_hex(&hex).map_err(|err| format!("cvar {}: {}", name, err))?);
+        }
+    }
+
+    Ok(config.get_trigger(id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::cvars::{FOV, R_RENDERSCALE, WINDOW_TITLE};
+
+    #[test]
+    fn cvars_commands_and_update_hooks() {
+        let mut config = ConfigVariables::new();
+        let mut scripts = Scripts::new();
+
+        let source = r#"
+            set_cvar("fov", cvar("fov") + 10);
+            set_cvar("r_renderscale", 0.75);
+
+            fn title(args) {
+                set_cvar("window_title", args[0]);
+            }
+            register_command("title", Fn("title"));
+
+            on_update(|delta_time_s| set_cvar("r_renderscale", cvar("r_renderscale") + delta_time_s));
+        "#;
+        let fov = config.get(FOV).as_float();
+        assert_eq!(scripts.run(&mut config, "test", source), Ok(ControlSignal::None));
+        assert_eq!(config.get(FOV).as_float(), fov + 10.0);
+        assert_eq!(config.get(R_RENDERSCALE).as_float(), 0.75);
+
+        // The change trigger of window_title
+        assert_eq!(
+            scripts.handle_command(&mut config, &["title", "scripted"]),
+            Some(ControlSignal::UpdateWindowTitle)
+        );
+        assert_eq!(config.get(WINDOW_TITLE).as_str(), "scripted");
+        assert_eq!(scripts.handle_command(&mut config, &["unknown"]), None);
+
+        scripts.update(&mut config, 0.25);
+        assert_eq!(config.get(R_RENDERSCALE).as_float(), 1.0);
+
+        assert!(scripts.run(&mut config, "invalid", r#"set_cvar("fov", "wide");"#).is_err());
+        assert!(scripts.run(&mut config, "missing", r#"cvar("no_such_cvar");"#).is_err());
+        assert_eq!(config.get(FOV).as_float(), fov + 10.0);
+    }
+}
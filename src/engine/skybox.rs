@@ -0,0 +1,100 @@
+use std::path::Path;
+
+use cgmath::Vector3;
+
+use crate::engine::datatypes::{Mesh, SimpleVertex, ViewProjectionUniform};
+use crate::renderer::context::{Context, Frame};
+use crate::renderer::types::{
+    DrawCommand, Index, PipelineConfiguration, PipelineHandle, RenderPassHandle, SamplerAddressMode,
+    SamplerConfiguration, SamplerFilter, TextureHandle, UniformHandle,
+};
+
+// Draws a cube texture of Context::add_cube_texture around the camera. The sky is drawn on the far plane, so it
+// can be added to the swapchain pass like any other pipeline and only fills what the scene leaves uncovered.
+pub struct SkyboxRenderer {
+    pipeline: PipelineHandle,
+    cube: Mesh,
+}
+
+impl SkyboxRenderer {
+    pub fn new(
+        context: &mut Context,
+        render_pass: RenderPassHandle,
+        camera_uniform: UniformHandle<ViewProjectionUniform>,
+        cube_texture: TextureHandle,
+    ) -> Self {
+        let (vertices, indices) = _inside_cube();
+        let vertex_buffer = context.create_static_vertex_buffer_sync(&vertices);
+        let index_buffer = context.create_static_index_buffer_sync(&indices);
+        let cube = Mesh::new(vertex_buffer, index_buffer, indices.len() as u32);
+
+        // Clamped so the seams between the faces don't blend in texels of the opposite edge
+        let sampler = context.add_sampler(
+            SamplerConfiguration::new()
+                .with_filter(SamplerFilter::Linear, SamplerFilter::Linear)
+                .with_address_mode(SamplerAddressMode::ClampToEdge),
+        );
+
+        let pipeline_config = PipelineConfiguration::builder()
+            .with_name("skybox")
+            .with_vertex_shader_file(Path::new("./resources/shaders/skybox_vert.spv"))
+            .with_fragment_shader_file(Path::new("./resources/shaders/skybox_frag.spv"))
+            .with_vertex_uniform(0, camera_uniform)
+            .add_cube_texture(1, cube_texture, sampler)
+            .build()
+            .expect("Invalid pipeline configuration!");
+        let pipeline = context.add_pipeline::<SimpleVertex>(render_pass, pipeline_config);
+
+        SkyboxRenderer { pipeline, cube }
+    }
+
+    pub fn draw(&self, frame: &mut Frame) {
+        frame.add_draw_command(DrawCommand::new_buffered(self.pipeline, &(), self.cube));
+    }
+}
+
+// Cube around the origin with its faces winding clockwise seen from inside, the positions are the directions
+// sampled from the cube texture
+fn _inside_cube() -> (Vec<SimpleVertex>, Vec<Index>) {
+    let x = Vector3::new(1.0, 0.0, 0.0);
+    let y = Vector3::new(0.0, 1.0, 0.0);
+    let z = Vector3::new(0.0, 0.0, 1.0);
+    // Right and up of each face seen from outside
+    let faces = [(x, y), (-x, y), (-z, y), (z, y), (x, -z), (x, z)];
+
+    let mut vertices = Vec::with_capacity(24);
+    let mut indices = Vec::with_capacity(36);
+    for (right, up) in faces.iter() {
+        let center = right.cross(*up);
+        let first = vertices.len() as Index;
+        for corner in [-*right + *up, *right + *up, -*right - *up, *right - *up].iter() {
+            vertices.push(SimpleVertex::new(center + corner));
+        }
+        indices.extend([0, 2, 1, 2, 3, 1].iter().map(|index| first + index));
+    }
+
+    (vertices, indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::InnerSpace;
+
+    #[test]
+    fn inside_cube_winding() {
+        let (vertices, indices) = _inside_cube();
+        assert_eq!(vertices.len(), 24);
+        assert_eq!(indices.len(), 36);
+
+        // Clockwise seen from the camera at the origin, the normal by the right hand rule points away from it
+        for triangle in indices.chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|corner| vertices[triangle[corner] as usize].position);
+            let normal = (b - a).cross(c - a);
+            assert!(normal.dot(a + b + c) > 0.0);
+        }
+        assert!(vertices.iter().all(|vertex| vertex.position.x.abs() == 1.0
+            && vertex.position.y.abs() == 1.0
+            && vertex.position.z.abs() == 1.0));
+    }
+}
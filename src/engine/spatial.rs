@@ -0,0 +1,711 @@
+use std::collections::HashMap;
+use std::ops::{Index, IndexMut};
+
+use cgmath::Vector3;
+
+use crate::engine::math::{Aabb, Frustum, Ray};
+
+// Moves of an item within this distance of the bounds it was inserted with don't change the tree
+const DEFAULT_BVH_MARGIN: f32 = 0.1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SpatialHandle(usize);
+
+// Broad phase over the bounds of items, e.g. for frustum culling, picking or finding the neighbours of a unit.
+// Queries report every item whose bounds pass once, exact tests are up to the callbacks. Handles of removed
+// items are reused.
+pub trait SpatialIndex<T> {
+    fn insert(&mut self, bounds: Aabb, value: T) -> SpatialHandle;
+    fn remove(&mut self, handle: SpatialHandle) -> T;
+    // After the item moved, cheap when it didn't move far
+    fn update(&mut self, handle: SpatialHandle, bounds: Aabb);
+
+    fn get(&self, handle: SpatialHandle) -> &T;
+    fn get_mut(&mut self, handle: SpatialHandle) -> &mut T;
+    fn bounds(&self, handle: SpatialHandle) -> Aabb;
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn query_aabb(&self, bounds: &Aabb, visit: impl FnMut(SpatialHandle, &T));
+    fn query_sphere(&self, center: Vector3<f32>, radius: f32, visit: impl FnMut(SpatialHandle, &T));
+    fn query_frustum(&self, frustum: &Frustum, visit: impl FnMut(SpatialHandle, &T));
+
+    // Closest hit up to max_distance. The callback gets the distance to the bounds of an item and returns the one
+    // to the item itself, None when the ray misses it. Return the distance as is to hit the bounds.
+    fn raycast(
+        &self,
+        ray: &Ray,
+        max_distance: f32,
+        hit: impl FnMut(SpatialHandle, &T, f32) -> Option<f32>,
+    ) -> Option<(SpatialHandle, f32)>;
+}
+
+// Vec with reused indices
+struct Slots<I> {
+    slots: Vec<Option<I>>,
+    free: Vec<usize>,
+}
+
+impl<I> Slots<I> {
+    fn new() -> Self {
+        Slots {
+            slots: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+
+    fn insert(&mut self, item: I) -> usize {
+        match self.free.pop() {
+            Some(index) => {
+                self.slots[index] = Some(item);
+                index
+            }
+            None => {
+                self.slots.push(Some(item));
+                self.slots.len() - 1
+            }
+        }
+    }
+
+    fn remove(&mut self, index: usize) -> I {
+        let item = self.slots[index].take().expect("Invalid spatial handle!");
+        self.free.push(index);
+
+        item
+    }
+
+    fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+}
+
+impl<I> Index<usize> for Slots<I> {
+    type Output = I;
+
+    fn index(&self, index: usize) -> &I {
+        self.slots[index].as_ref().expect("Invalid spatial handle!")
+    }
+}
+
+impl<I> IndexMut<usize> for Slots<I> {
+    fn index_mut(&mut self, index: usize) -> &mut I {
+        self.slots[index].as_mut().expect("Invalid spatial handle!")
+    }
+}
+
+struct BvhNode {
+    // Grown by the margin for leaves
+    bounds: Aabb,
+    parent: Option<usize>,
+    // None for leaves
+    children: Option<(usize, usize)>,
+    // 0 for leaves
+    height: u32,
+    // Of leaves
+    item: usize,
+}
+
+struct BvhItem<T> {
+    bounds: Aabb,
+    value: T,
+    node: usize,
+}
+
+// Dynamic AABB tree for items of any size and distribution, e.g. the objects of a scene. Items are inserted
+// where they grow the tree the least and the tree is rebalanced by rotations on the way up, so it stays good
+// without rebuilds. Leaves keep the bounds grown by a margin and are only reinserted when an item leaves them.
+pub struct Bvh<T> {
+    nodes: Slots<BvhNode>,
+    items: Slots<BvhItem<T>>,
+    root: Option<usize>,
+    margin: f32,
+}
+
+impl<T> Default for Bvh<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Bvh<T> {
+    pub fn new() -> Self {
+        Bvh {
+            nodes: Slots::new(),
+            items: Slots::new(),
+            root: None,
+            margin: DEFAULT_BVH_MARGIN,
+        }
+    }
+
+    // Larger margins make updates of moving items cheaper and queries slower, applies to items inserted or
+    // reinserted after
+    pub fn with_margin(mut self, margin: f32) -> Self {
+        self.margin = margin;
+        self
+    }
+
+    // Of the longest path to a leaf, 0 for a single item
+    pub fn height(&self) -> u32 {
+        self.root.map_or(0, |root| self.nodes[root].height)
+    }
+
+    fn insert_leaf(&mut self, leaf: usize) {
+        let root = match self.root {
+            Some(root) => root,
+            None => {
+                self.nodes[leaf].parent = None;
+                self.root = Some(leaf);
+                return;
+            }
+        };
+
+        // Descends while going down is cheaper than a new parent of the node, by the surface area heuristic
+        let bounds = self.nodes[leaf].bounds;
+        let mut sibling = root;
+        while let Some((left, right)) = self.nodes[sibling].children {
+            let area = self.nodes[sibling].bounds.surface_area();
+            let combined_area = self.nodes[sibling].bounds.union(&bounds).surface_area();
+            let cost = 2.0 * combined_area;
+            // Growing the node is paid by descending into either child
+            let inherited_cost = 2.0 * (combined_area - area);
+            let child_cost = |child: &BvhNode| {
+                let area = child.bounds.union(&bounds).surface_area();
+                match child.children {
+                    Some(_) => area - child.bounds.surface_area() + inherited_cost,
+                    None => area + inherited_cost,
+                }
+            };
+            let (left_cost, right_cost) = (child_cost(&self.nodes[left]), child_cost(&self.nodes[right]));
+
+            if cost < left_cost && cost < right_cost {
+                break;
+            }
+            sibling = if left_cost < right_cost { left } else { right };
+        }
+
+        let old_parent = self.nodes[sibling].parent;
+        let parent = self.nodes.insert(BvhNode {
+            bounds: bounds.union(&self.nodes[sibling].bounds),
+            parent: old_parent,
+            children: Some((sibling, leaf)),
+            height: self.nodes[sibling].height + 1,
+            item: 0,
+        });
+        self.replace_child(old_parent, sibling, parent);
+        self.nodes[sibling].parent = Some(parent);
+        self.nodes[leaf].parent = Some(parent);
+
+        self.refit_from(old_parent);
+    }
+
+    fn remove_leaf(&mut self, leaf: usize) {
+        let parent = match self.nodes[leaf].parent {
+            Some(parent) => parent,
+            None => {
+                self.root = None;
+                return;
+            }
+        };
+
+        let (left, right) = self.nodes[parent].children.unwrap();
+        let sibling = if left == leaf { right } else { left };
+        let grandparent = self.nodes.remove(parent).parent;
+        self.replace_child(grandparent, parent, sibling);
+        self.nodes[sibling].parent = grandparent;
+
+        self.refit_from(grandparent);
+    }
+
+    fn replace_child(&mut self, parent: Option<usize>, old: usize, new: usize) {
+        match parent {
+            Some(parent) => {
+                let (left, right) = self.nodes[parent].children.unwrap();
+                self.nodes[parent].children = Some(if left == old { (new, right) } else { (left, new) });
+            }
+            None => self.root = Some(new),
+        }
+    }
+
+    // Bounds and heights of the node and its ancestors after a child changed
+    fn refit_from(&mut self, mut node: Option<usize>) {
+        while let Some(index) = node {
+            let index = self.balance(index);
+            self.refit(index);
+            node = self.nodes[index].parent;
+        }
+    }
+
+    fn refit(&mut self, index: usize) {
+        let (left, right) = self.nodes[index].children.unwrap();
+        let (left, right) = (&self.nodes[left], &self.nodes[right]);
+        let (bounds, height) = (left.bounds.union(&right.bounds), 1 + left.height.max(right.height));
+
+        let node = &mut self.nodes[index];
+        node.bounds = bounds;
+        node.height = height;
+    }
+
+    // Rotates the higher child up when the heights of the children differ by more than one, returns the node
+    // now in the place of the given one
+    fn balance(&mut self, index: usize) -> usize {
+        let (left, right) = match self.nodes[index].children {
+            Some(children) if self.nodes[index].height >= 2 => children,
+            _ => return index,
+        };
+
+        let (left_height, right_height) = (self.nodes[left].height as i64, self.nodes[right].height as i64);
+        if right_height - left_height > 1 {
+            self.rotate(index, right, left)
+        } else if left_height - right_height > 1 {
+            self.rotate(index, left, right)
+        } else {
+            index
+        }
+    }
+
+    // The child moves into the place of the node, which takes the lower grandchild
+    fn rotate(&mut self, index: usize, up: usize, kept: usize) -> usize {
+        let (a, b) = self.nodes[up].children.unwrap();
+        let (higher, lower) = if self.nodes[a].height > self.nodes[b].height { (a, b) } else { (b, a) };
+
+        let parent = self.nodes[index].parent;
+        self.replace_child(parent, index, up);
+        self.nodes[up].parent = parent;
+        self.nodes[up].children = Some((index, higher));
+        self.nodes[index].parent = Some(up);
+        self.nodes[index].children = Some((kept, lower));
+        self.nodes[lower].parent = Some(index);
+
+        self.refit(index);
+        self.refit(up);
+
+        up
+    }
+
+    fn query(&self, overlaps: impl Fn(&Aabb) -> bool, mut visit: impl FnMut(SpatialHandle, &T)) {
+        let mut stack: Vec<usize> = self.root.into_iter().collect();
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !overlaps(&node.bounds) {
+                continue;
+            }
+
+            match node.children {
+                Some((left, right)) => stack.extend([left, right]),
+                None => {
+                    let item = &self.items[node.item];
+                    if overlaps(&item.bounds) {
+                        visit(SpatialHandle(node.item), &item.value);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<T> SpatialIndex<T> for Bvh<T> {
+    fn insert(&mut self, bounds: Aabb, value: T) -> SpatialHandle {
+        let item = self.items.insert(BvhItem { bounds, value, node: 0 });
+        let leaf = self.nodes.insert(BvhNode {
+            bounds: bounds.expand(self.margin),
+            parent: None,
+            children: None,
+            height: 0,
+            item,
+        });
+        self.items[item].node = leaf;
+        self.insert_leaf(leaf);
+
+        SpatialHandle(item)
+    }
+
+    fn remove(&mut self, handle: SpatialHandle) -> T {
+        let item = self.items.remove(handle.0);
+        self.remove_leaf(item.node);
+        self.nodes.remove(item.node);
+
+        item.value
+    }
+
+    fn update(&mut self, handle: SpatialHandle, bounds: Aabb) {
+        let item = &mut self.items[handle.0];
+        item.bounds = bounds;
+        let leaf = item.node;
+        if self.nodes[leaf].bounds.contains(&bounds) {
+            return;
+        }
+
+        self.remove_leaf(leaf);
+        self.nodes[leaf].bounds = bounds.expand(self.margin);
+        self.insert_leaf(leaf);
+    }
+
+    fn get(&self, handle: SpatialHandle) -> &T {
+        &self.items[handle.0].value
+    }
+
+    fn get_mut(&mut self, handle: SpatialHandle) -> &mut T {
+        &mut self.items[handle.0].value
+    }
+
+    fn bounds(&self, handle: SpatialHandle) -> Aabb {
+        self.items[handle.0].bounds
+    }
+
+    fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    fn query_aabb(&self, bounds: &Aabb, visit: impl FnMut(SpatialHandle, &T)) {
+        self.query(|node_bounds| node_bounds.intersects(bounds), visit);
+    }
+
+    fn query_sphere(&self, center: Vector3<f32>, radius: f32, visit: impl FnMut(SpatialHandle, &T)) {
+        self.query(|node_bounds| node_bounds.intersects_sphere(center, radius), visit);
+    }
+
+    fn query_frustum(&self, frustum: &Frustum, visit: impl FnMut(SpatialHandle, &T)) {
+        self.query(|node_bounds| frustum.intersects_aabb(node_bounds), visit);
+    }
+
+    fn raycast(
+        &self,
+        ray: &Ray,
+        mut max_distance: f32,
+        mut hit: impl FnMut(SpatialHandle, &T, f32) -> Option<f32>,
+    ) -> Option<(SpatialHandle, f32)> {
+        let mut closest = None;
+        let mut stack: Vec<usize> = self.root.into_iter().collect();
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if ray.intersect_aabb(&node.bounds).is_none_or(|distance| distance > max_distance) {
+                continue;
+            }
+
+            match node.children {
+                Some((left, right)) => stack.extend([left, right]),
+                None => {
+                    let item = &self.items[node.item];
+                    let handle = SpatialHandle(node.item);
+                    let distance = ray
+                        .intersect_aabb(&item.bounds)
+                        .filter(|distance| *distance <= max_distance)
+                        .and_then(|distance| hit(handle, &item.value, distance))
+                        .filter(|distance| *distance <= max_distance);
+                    if let Some(distance) = distance {
+                        max_distance = distance;
+                        closest = Some((handle, distance));
+                    }
+                }
+            }
+        }
+
+        closest
+    }
+}
+
+type Cell = [i32; 3];
+
+struct GridItem<T> {
+    bounds: Aabb,
+    value: T,
+    // First and last cell the bounds overlap
+    cells: (Cell, Cell),
+}
+
+// Hashed grid of cubic cells, only cells with items take memory. Updates and proximity queries are cheaper than
+// with the Bvh when the items are about the size of a cell or smaller, e.g. units or particles. Large items are
+// in many cells.
+pub struct UniformGrid<T> {
+    cell_size: f32,
+    cells: HashMap<Cell, Vec<usize>>,
+    items: Slots<GridItem<T>>,
+}
+
+impl<T> UniformGrid<T> {
+    pub fn new(cell_size: f32) -> Self {
+        assert!(cell_size > 0.0, "Cell size must be positive");
+        UniformGrid {
+            cell_size,
+            cells: HashMap::new(),
+            items: Slots::new(),
+        }
+    }
+
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    fn cell(&self, point: Vector3<f32>) -> Cell {
+        [
+            (point.x / self.cell_size).floor() as i32,
+            (point.y / self.cell_size).floor() as i32,
+            (point.z / self.cell_size).floor() as i32,
+        ]
+    }
+
+    fn cell_range(&self, bounds: &Aabb) -> (Cell, Cell) {
+        (self.cell(bounds.min), self.cell(bounds.max))
+    }
+
+    fn cell_bounds(&self, cell: Cell) -> Aabb {
+        let min = Vector3::new(cell[0] as f32, cell[1] as f32, cell[2] as f32) * self.cell_size;
+        Aabb::new(min, min + Vector3::new(self.cell_size, self.cell_size, self.cell_size))
+    }
+
+    fn add_to_cells(&mut self, index: usize, range: (Cell, Cell)) {
+        for cell in _cells(range) {
+            self.cells.entry(cell).or_default().push(index);
+        }
+    }
+
+    fn remove_from_cells(&mut self, index: usize, range: (Cell, Cell)) {
+        for cell in _cells(range) {
+            let items = self.cells.get_mut(&cell).unwrap();
+            let position = items.iter().position(|item| *item == index).unwrap();
+            items.swap_remove(position);
+            if items.is_empty() {
+                self.cells.remove(&cell);
+            }
+        }
+    }
+
+    // Items of the cells in the range, each reported in the first cell it shares with the range only
+    fn query_range(
+        &self,
+        range: (Cell, Cell),
+        overlaps: impl Fn(&Aabb) -> bool,
+        mut visit: impl FnMut(SpatialHandle, &T),
+    ) {
+        for cell in _cells(range) {
+            for index in self.cells.get(&cell).into_iter().flatten() {
+                let item = &self.items[*index];
+                let first = [0, 1, 2].map(|axis| item.cells.0[axis].max(range.0[axis]));
+                if first == cell && overlaps(&item.bounds) {
+                    visit(SpatialHandle(*index), &item.value);
+                }
+            }
+        }
+    }
+}
+
+impl<T> SpatialIndex<T> for UniformGrid<T> {
+    fn insert(&mut self, bounds: Aabb, value: T) -> SpatialHandle {
+        let cells = self.cell_range(&bounds);
+        let index = self.items.insert(GridItem { bounds, value, cells });
+        self.add_to_cells(index, cells);
+
+        SpatialHandle(index)
+    }
+
+    fn remove(&mut self, handle: SpatialHandle) -> T {
+        let item = self.items.remove(handle.0);
+        self.remove_from_cells(handle.0, item.cells);
+
+        item.value
+    }
+
+    fn update(&mut self, handle: SpatialHandle, bounds: Aabb) {
+        let cells = self.cell_range(&bounds);
+        let item = &mut self.items[handle.0];
+        item.bounds = bounds;
+        let old_cells = std::mem::replace(&mut item.cells, cells);
+        if old_cells != cells {
+            self.remove_from_cells(handle.0, old_cells);
+            self.add_to_cells(handle.0, cells);
+        }
+    }
+
+    fn get(&self, handle: SpatialHandle) -> &T {
+        &self.items[handle.0].value
+    }
+
+    fn get_mut(&mut self, handle: SpatialHandle) -> &mut T {
+        &mut self.items[handle.0].value
+    }
+
+    fn bounds(&self, handle: SpatialHandle) -> Aabb {
+        self.items[handle.0].bounds
+    }
+
+    fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    fn query_aabb(&self, bounds: &Aabb, visit: impl FnMut(SpatialHandle, &T)) {
+        self.query_range(self.cell_range(bounds), |item_bounds| item_bounds.intersects(bounds), visit);
+    }
+
+    fn query_sphere(&self, center: Vector3<f32>, radius: f32, visit: impl FnMut(SpatialHandle, &T)) {
+        let radius_vector = Vector3::new(radius, radius, radius);
+        let range = self.cell_range(&Aabb::new(center - radius_vector, center + radius_vector));
+        self.query_range(range, |item_bounds| item_bounds.intersects_sphere(center, radius), visit);
+    }
+
+    // Tests every cell in use, each item is reported in the first of its cells in the frustum
+    fn query_frustum(&self, frustum: &Frustum, mut visit: impl FnMut(SpatialHandle, &T)) {
+        for (cell, items) in self.cells.iter() {
+            if !frustum.intersects_aabb(&self.cell_bounds(*cell)) {
+                continue;
+            }
+
+            for index in items {
+                let item = &self.items[*index];
+                let first = _cells(item.cells).find(|cell| frustum.intersects_aabb(&self.cell_bounds(*cell)));
+                if first == Some(*cell) && frustum.intersects_aabb(&item.bounds) {
+                    visit(SpatialHandle(*index), &item.value);
+                }
+            }
+        }
+    }
+
+    // Walks the cells along the ray, max_distance must be finite
+    fn raycast(
+        &self,
+        ray: &Ray,
+        mut max_distance: f32,
+        mut hit: impl FnMut(SpatialHandle, &T, f32) -> Option<f32>,
+    ) -> Option<(SpatialHandle, f32)> {
+        assert!(max_distance.is_finite(), "Grid raycasts need a finite distance");
+
+        let mut cell = self.cell(ray.origin);
+        let step = [0, 1, 2].map(|axis| if ray.direction[axis] < 0.0 { -1 } else { 1 });
+        // Distance along the ray to the next cell boundary of each axis and between two boundaries
+        let mut next = [0, 1, 2].map(|axis| {
+            let boundary = (cell[axis] + step[axis].max(0)) as f32 * self.cell_size;
+            (boundary - ray.origin[axis]) / ray.direction[axis]
+        });
+        let delta = [0, 1, 2].map(|axis| self.cell_size / ray.direction[axis].abs());
+
+        let mut closest = None;
+        let mut previous: Option<Cell> = None;
+        let mut entry = 0.0;
+        while entry <= max_distance {
+            for index in self.cells.get(&cell).into_iter().flatten() {
+                let item = &self.items[*index];
+                // Tested when the ray entered its cells
+                if previous.is_some_and(|previous| _in_range(item.cells, previous)) {
+                    continue;
+                }
+
+                let handle = SpatialHandle(*index);
+                let distance = ray
+                    .intersect_aabb(&item.bounds)
+                    .filter(|distance| *distance <= max_distance)
+                    .and_then(|distance| hit(handle, &item.value, distance))
+                    .filter(|distance| *distance <= max_distance);
+                if let Some(distance) = distance {
+                    max_distance = distance;
+                    closest = Some((handle, distance));
+                }
+            }
+
+            previous = Some(cell);
+            let axis = (0..3).fold(0, |min, axis| if next[axis] < next[min] { axis } else { min });
+            entry = next[axis];
+            cell[axis] += step[axis];
+            next[axis] += delta[axis];
+        }
+
+        closest
+    }
+}
+
+fn _cells((min, max): (Cell, Cell)) -> impl Iterator<Item = Cell> {
+    (min[0]..=max[0]).flat_map(move |x| (min[1]..=max[1]).flat_map(move |y| (min[2]..=max[2]).map(move |z| [x, y, z])))
+}
+
+fn _in_range((min, max): (Cell, Cell), cell: Cell) -> bool {
+    (0..3).all(|axis| min[axis] <= cell[axis] && cell[axis] <= max[axis])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::math::Rng;
+    use cgmath::{Deg, Matrix4, Point3};
+
+    fn random_bounds(rng: &mut Rng) -> Aabb {
+        let min = Vector3::new(rng.range_f32(-50.0, 50.0), rng.range_f32(-10.0, 10.0), rng.range_f32(-50.0, 50.0));
+        let size = Vector3::new(rng.range_f32(0.1, 4.0), rng.range_f32(0.1, 4.0), rng.range_f32(0.1, 4.0));
+        Aabb::new(min, min + size)
+    }
+
+    fn sorted(mut handles: Vec<SpatialHandle>) -> Vec<usize> {
+        handles.sort_by_key(|handle| handle.0);
+        handles.into_iter().map(|handle| handle.0).collect()
+    }
+
+    // Compares the queries with testing every item
+    fn check_queries<S: SpatialIndex<u32>>(index: &mut S) {
+        let mut rng = Rng::new(5);
+        let mut handles: Vec<SpatialHandle> = (0..300).map(|i| index.insert(random_bounds(&mut rng), i)).collect();
+        for handle in handles.iter().step_by(2) {
+            // Mostly small moves that stay within the margin or cell
+            let bounds = index.bounds(*handle);
+            let offset = Vector3::new(rng.range_f32(-0.05, 0.05), 0.0, rng.range_f32(-3.0, 3.0));
+            index.update(*handle, Aabb::new(bounds.min + offset, bounds.max + offset));
+        }
+        for handle in handles.drain(..50) {
+            index.remove(handle);
+        }
+        assert_eq!(index.len(), 250);
+        assert_eq!(index.insert(random_bounds(&mut rng), 1000).0, 49);
+        handles.push(SpatialHandle(49));
+
+        let expected = |test: &dyn Fn(&Aabb) -> bool| {
+            sorted(handles.iter().copied().filter(|handle| test(&index.bounds(*handle))).collect())
+        };
+        let query = Aabb::new(Vector3::new(-20.0, -5.0, -20.0), Vector3::new(5.0, 5.0, 10.0));
+        let mut found = Vec::new();
+        index.query_aabb(&query, |handle, _| found.push(handle));
+        assert_eq!(sorted(found), expected(&|bounds| bounds.intersects(&query)));
+
+        let center = Vector3::new(10.0, 0.0, -10.0);
+        let mut found = Vec::new();
+        index.query_sphere(center, 12.0, |handle, _| found.push(handle));
+        assert_eq!(sorted(found), expected(&|bounds| bounds.intersects_sphere(center, 12.0)));
+
+        let view = Matrix4::look_at_rh(Point3::new(0.0, 5.0, 60.0), Point3::new(0.0, 0.0, 0.0), Vector3::unit_y());
+        let frustum = Frustum::from_matrix(cgmath::perspective(Deg(40.0), 1.5, 0.1, 80.0) * view);
+        let mut found = Vec::new();
+        index.query_frustum(&frustum, |handle, _| found.push(handle));
+        let visible = expected(&|bounds| frustum.intersects_aabb(bounds));
+        assert!(!visible.is_empty() && visible.len() < 250);
+        assert_eq!(sorted(found), visible);
+
+        for i in 0..20 {
+            let angle = i as f32 * 0.3;
+            let origin = Vector3::new(-60.0, 0.0, angle * 5.0 - 30.0);
+            let ray = Ray::new(origin, Vector3::new(angle.cos(), 0.01, angle.sin()));
+            let closest = handles
+                .iter()
+                .filter_map(|handle| ray.intersect_aabb(&index.bounds(*handle)))
+                .filter(|distance| *distance <= 100.0)
+                .fold(None, |closest: Option<f32>, distance| Some(closest.map_or(distance, |c| c.min(distance))));
+            let hit = index.raycast(&ray, 100.0, |_, _, distance| Some(distance));
+            assert_eq!(hit.map(|(_, distance)| distance), closest);
+        }
+
+        // The callback can reject items
+        let ray = Ray::new(Vector3::new(-60.0, 0.5, 0.0), Vector3::unit_x());
+        let first = index.raycast(&ray, 200.0, |_, _, distance| Some(distance)).unwrap();
+        let second = index.raycast(&ray, 200.0, |handle, _, distance| (handle != first.0).then_some(distance));
+        assert!(second.is_none_or(|(handle, distance)| handle != first.0 && distance >= first.1));
+
+        assert_eq!(*index.get(SpatialHandle(49)), 1000);
+    }
+
+    #[test]
+    fn spatial_queries() {
+        let mut bvh = Bvh::new();
+        check_queries(&mut bvh);
+        // Balanced, a list would be hundreds high
+        assert!(bvh.height() < 20, "height {}", bvh.height());
+
+        check_queries(&mut UniformGrid::new(4.0));
+    }
+}
@@ -1,7 +1,11 @@
 use crate::renderer::stats::RenderStats;
+use std::collections::VecDeque;
 use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
 
 const SAMPLE_WINDOW: f32 = 0.2;
+// Number of frames the pacing percentiles are computed over
+const PACING_WINDOW: usize = 1000;
 
 lazy_static! {
     static ref ENGINE_STATS: Mutex<EngineStatistics> = Mutex::new(EngineStatistics::new());
@@ -18,7 +22,40 @@ pub struct EngineStatistics {
     frame_time_samples: f32,
     frame_time_sample_count: u32,
 
+    frame_times: VecDeque<f32>,
+    frame_pacing: FramePacing,
+    // Frames taking longer are logged, 0 disables spike detection
+    spike_threshold_s: f32,
+
+    update_time: Duration,
     render_stats: RenderStats,
+    // Scale of the scene target and whether dynamic resolution sets it, None if the scene is not scaled
+    render_scale: Option<(f32, bool)>,
+}
+
+#[cfg_attr(not(feature = "ui"), allow(dead_code))]
+#[derive(Clone, Copy, Default)]
+pub struct FramePacing {
+    pub p95_frame_time: f32,
+    pub p99_frame_time: f32,
+    pub low_1_percent_fps: f32,
+}
+
+impl FramePacing {
+    fn from_frame_times(frame_times: &VecDeque<f32>) -> Self {
+        if frame_times.is_empty() {
+            return FramePacing::default();
+        }
+
+        let mut sorted: Vec<f32> = frame_times.iter().copied().collect();
+        sorted.sort_by(|a, b| b.total_cmp(a));
+
+        FramePacing {
+            p95_frame_time: _percentile(&sorted, 0.95),
+            p99_frame_time: _percentile(&sorted, 0.99),
+            low_1_percent_fps: 1.0 / low_1_percent_frame_time(&sorted),
+        }
+    }
 }
 
 impl EngineStatistics {
@@ -28,27 +65,79 @@ impl EngineStatistics {
             frame_time: 0.0,
             frame_time_samples: 0.0,
             frame_time_sample_count: 0,
+            frame_times: VecDeque::with_capacity(PACING_WINDOW),
+            frame_pacing: FramePacing::default(),
+            spike_threshold_s: 0.0,
+            update_time: Duration::ZERO,
             render_stats: RenderStats::new(),
+            render_scale: None,
         }
     }
 
+    // Must be called before the update time and render stats of the current frame are set, the delta time
+    // belongs to the previous frame.
     pub fn update_delta_time(&mut self, delta_time_s: f32) {
         self.frame_time_samples += delta_time_s;
         self.frame_time_sample_count += 1;
 
+        if self.frame_times.len() >= PACING_WINDOW {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(delta_time_s);
+
+        if self.spike_threshold_s > 0.0 && delta_time_s > self.spike_threshold_s {
+            self.log_spike(delta_time_s);
+        }
+
         if self.frame_time_samples >= SAMPLE_WINDOW {
             self.frame_time = self.frame_time_samples / self.frame_time_sample_count as f32;
             self.fps = (1.0 / self.frame_time) as u32;
+            self.frame_pacing = FramePacing::from_frame_times(&self.frame_times);
 
             self.frame_time_samples = 0.0;
             self.frame_time_sample_count = 0;
         }
     }
 
+    fn log_spike(&self, frame_time_s: f32) {
+        let phases = [
+            ("update", self.update_time),
+            ("transfer bake", self.render_stats.transfer_commands_bake_time),
+            ("draw bake", self.render_stats.draw_commands_bake_time),
+        ];
+        let (dominant_phase, _) = phases.iter().max_by_key(|(_, duration)| *duration).unwrap();
+
+        log_warning!(
+            "frame spike: {:.2} ms (update {:.2} ms, transfer bake {:.2} ms, draw bake {:.2} ms), mostly {}",
+            frame_time_s * 1000.0,
+            phases[0].1.as_secs_f32() * 1000.0,
+            phases[1].1.as_secs_f32() * 1000.0,
+            phases[2].1.as_secs_f32() * 1000.0,
+            dominant_phase
+        );
+    }
+
+    pub fn set_spike_threshold(&mut self, threshold_s: f32) {
+        self.spike_threshold_s = threshold_s;
+    }
+
+    pub fn set_update_time(&mut self, update_time: Duration) {
+        self.update_time = update_time;
+    }
+
     pub fn set_render_stats(&mut self, stats: RenderStats) {
         self.render_stats = stats;
     }
 
+    #[cfg_attr(not(feature = "ui"), allow(dead_code))]
+    pub fn set_render_scale(&mut self, render_scale: Option<(f32, bool)>) {
+        self.render_scale = render_scale;
+    }
+}
+
+// Read by the text overlay
+#[cfg_attr(not(feature = "ui"), allow(dead_code))]
+impl EngineStatistics {
     pub fn get_fps(&self) -> u32 {
         self.fps
     }
@@ -57,7 +146,53 @@ impl EngineStatistics {
         self.frame_time
     }
 
+    pub fn get_frame_pacing(&self) -> FramePacing {
+        self.frame_pacing
+    }
+
     pub fn get_render_stats(&self) -> &RenderStats {
         &self.render_stats
     }
+
+    pub fn get_render_scale(&self) -> Option<(f32, bool)> {
+        self.render_scale
+    }
+}
+
+// Average of the slowest percent of frames, frame times must be sorted in descending order
+pub(crate) fn low_1_percent_frame_time(sorted_frame_times: &[f32]) -> f32 {
+    let low_count = (sorted_frame_times.len() / 100).max(1);
+
+    sorted_frame_times[..low_count].iter().sum::<f32>() / low_count as f32
+}
+
+// Frame time not exceeded by the given fraction of frames, frame times must be sorted in descending order
+fn _percentile(sorted_frame_times: &[f32], fraction: f32) -> f32 {
+    let rank = (fraction * sorted_frame_times.len() as f32).round() as usize;
+    let index = sorted_frame_times.len().saturating_sub(rank);
+
+    sorted_frame_times[index.min(sorted_frame_times.len() - 1)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_pacing() {
+        let mut frame_times = VecDeque::new();
+        for i in 0..1000 {
+            let frame_time = match i % 100 {
+                0 => 0.1,
+                1..=4 => 0.02,
+                _ => 0.01,
+            };
+            frame_times.push_back(frame_time);
+        }
+
+        let pacing = FramePacing::from_frame_times(&frame_times);
+        assert_eq!(pacing.p99_frame_time, 0.02);
+        assert_eq!(pacing.p95_frame_time, 0.01);
+        assert!((pacing.low_1_percent_fps - 10.0).abs() < 0.01);
+    }
 }
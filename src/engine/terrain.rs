@@ -1,17 +1,14 @@
 use cgmath::{InnerSpace, Vector3};
-use noise::Add;
-use noise::Constant;
-use noise::Multiply;
 use num::Integer;
-use noise::NoiseFn;
-use noise::Perlin;
-use noise::ScalePoint;
 
 use crate::engine::datatypes::NormalVertex;
-use crate::renderer::context::Context;
+use crate::engine::math::noise::{Fbm, Noise, Perlin};
+use crate::renderer::context::{Context, Frame};
 use crate::renderer::types::{DrawCommand, VertexData, PipelineHandle};
 
 const QUAD_SIZE: f32 = 1.0;
+const SEED: u32 = 1337;
+const MAX_HEIGHT: f32 = 70.0;
 
 #[allow(dead_code)]
 pub struct _OctreeTerrainNode {
@@ -32,32 +29,14 @@ pub struct Terrain {
 
 impl Terrain {
     pub fn new(context: &mut Context, pipeline: PipelineHandle) -> Self {
-
-        let seed = 1337;
-
-        let scale = ScalePoint::new(Perlin::new(seed))
-            .set_x_scale(4.0)
-            .set_y_scale(4.0);
-        let pass_1 = Multiply::new(scale, Constant::new(40.0));
-
-        let scale = ScalePoint::new(Perlin::new(5432))
-        .set_x_scale(8.0)
-        .set_y_scale(8.0);
-        let pass_2 = Multiply::new(scale, Constant::new(20.0));
-
-        let scale = ScalePoint::new(Perlin::new(123145))
-        .set_x_scale(16.0)
-        .set_y_scale(16.0);
-        let pass_3 = Multiply::new(scale, Constant::new(10.0));
-
-        let result = Add::new(pass_1, Add::new(pass_2, pass_3));
+        let heights = Fbm::new(Perlin::new(SEED)).with_octaves(3).with_frequency(4.0);
 
         let quad_width = 256;
         let quad_height = 256;
 
         debug_assert_eq!(quad_width % 64, 0);
 
-        let raw_vertices = create_raw_vertices(quad_width, quad_height, &result);
+        let raw_vertices = create_raw_vertices(quad_width, quad_height, &heights);
         let chunk_data = create_flat_normaled_chunk(quad_width, quad_height, &raw_vertices);
 
         let vertex_buffer = context.create_static_vertex_buffer_sync(&chunk_data.vertices);
@@ -69,9 +48,14 @@ impl Terrain {
         }
     }
 
-    pub fn draw(&self, context: &mut Context) {
-        context.add_draw_command(DrawCommand::new_buffered_nopush(
-            self.pipeline,
+    pub fn draw(&self, frame: &mut Frame) {
+        self.draw_with_pipeline(frame, self.pipeline);
+    }
+
+    // E.g. to draw the same terrain into another render pass
+    pub fn draw_with_pipeline(&self, frame: &mut Frame, pipeline: PipelineHandle) {
+        frame.add_draw_command(DrawCommand::new_buffered_nopush(
+            pipeline,
             self.chunk,
         ));
     }
@@ -159,7 +143,7 @@ fn create_flat_normaled_chunk(
     }
 }
 
-fn create_raw_vertices<T: NoiseFn<f64, 2>>(
+fn create_raw_vertices<T: Noise>(
     quad_count_width: usize,
     quad_count_height: usize,
     noise_fn: &T,
@@ -177,11 +161,11 @@ fn create_raw_vertices<T: NoiseFn<f64, 2>>(
             let x_offset = j as f64 * QUAD_SIZE as f64;
             let z_offset = i as f64 * -QUAD_SIZE as f64;
 
-            let x = (j as f64 / width as f64) - 0.5;
-            let z = (i as f64 / height as f64) - 0.5;
-            let y = noise_fn.get([x, z]);
+            let x = (j as f32 / width as f32) - 0.5;
+            let z = (i as f32 / height as f32) - 0.5;
+            let y = noise_fn.get2(x, z) * MAX_HEIGHT;
 
-            vertices.push(Vector3::new(x_offset as f32, y as f32, z_offset as f32));
+            vertices.push(Vector3::new(x_offset as f32, y, z_offset as f32));
         }
     }
 
@@ -0,0 +1,103 @@
+use crate::engine::runtime::FIXED_TIMESTEP;
+use crate::engine::stats;
+use crate::renderer::stats::RenderStats;
+
+pub struct Timedemo {
+    frame_count: u32,
+    frame_times: Vec<f32>,
+
+    draw_command_count: u64,
+    triangle_count: u64,
+}
+
+pub struct TimedemoSummary {
+    pub frames: u32,
+    pub total_time: f32,
+
+    pub avg_fps: f32,
+    pub min_fps: f32,
+    pub max_fps: f32,
+    pub low_1_percent_fps: f32,
+
+    pub avg_draw_commands: u64,
+    pub avg_triangles: u64,
+}
+
+impl Timedemo {
+    pub fn new(duration_s: u32) -> Self {
+        let frame_count = ((duration_s as f32 / FIXED_TIMESTEP) as u32).max(1);
+
+        Timedemo {
+            frame_count,
+            frame_times: Vec::with_capacity(frame_count as usize),
+            draw_command_count: 0,
+            triangle_count: 0,
+        }
+    }
+
+    pub fn add_frame(&mut self, frame_time_s: f32, render_stats: &RenderStats) {
+        self.frame_times.push(frame_time_s);
+        self.draw_command_count += render_stats.draw_command_count as u64;
+        self.triangle_count += render_stats.triangle_count;
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frame_times.len() >= self.frame_count as usize
+    }
+
+    pub fn summary(&self) -> TimedemoSummary {
+        let frames = self.frame_times.len();
+        if frames == 0 {
+            return TimedemoSummary {
+                frames: 0,
+                total_time: 0.0,
+                avg_fps: 0.0,
+                min_fps: 0.0,
+                max_fps: 0.0,
+                low_1_percent_fps: 0.0,
+                avg_draw_commands: 0,
+                avg_triangles: 0,
+            };
+        }
+
+        let mut sorted = self.frame_times.clone();
+        sorted.sort_by(|a, b| b.partial_cmp(a).unwrap());
+
+        let total_time: f32 = sorted.iter().sum();
+        let low_time = stats::low_1_percent_frame_time(&sorted);
+
+        TimedemoSummary {
+            frames: frames as u32,
+            total_time,
+            avg_fps: frames as f32 / total_time,
+            min_fps: 1.0 / sorted[0],
+            max_fps: 1.0 / sorted[frames - 1],
+            low_1_percent_fps: 1.0 / low_time,
+            avg_draw_commands: self.draw_command_count / frames as u64,
+            avg_triangles: self.triangle_count / frames as u64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary() {
+        let mut timedemo = Timedemo::new(1);
+        let stats = RenderStats::new();
+
+        for i in 0..200 {
+            let frame_time = if i < 2 { 0.1 } else { 0.01 };
+            timedemo.add_frame(frame_time, &stats);
+        }
+
+        let summary = timedemo.summary();
+        assert_eq!(summary.frames, 200);
+        assert!((summary.min_fps - 10.0).abs() < 0.01);
+        assert!((summary.max_fps - 100.0).abs() < 0.01);
+        assert!((summary.low_1_percent_fps - 10.0).abs() < 0.01);
+        assert!(timedemo.is_finished());
+    }
+}
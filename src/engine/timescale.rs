@@ -0,0 +1,80 @@
+use crate::engine::runtime::FIXED_TIMESTEP;
+
+// Simulation time passed to the application, scaled by the ts cvar. While paused the simulation only advances
+// by steps of one fixed timestep each, see the pause and step console commands. The console and the UI keep
+// running on the real time.
+pub struct TimeScale {
+    paused: bool,
+    pending_steps: u32,
+}
+
+impl Default for TimeScale {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TimeScale {
+    pub fn new() -> Self {
+        TimeScale {
+            paused: false,
+            pending_steps: 0,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        self.pending_steps = 0;
+    }
+
+    // Pauses first when running
+    pub fn step(&mut self, frames: u32) {
+        self.paused = true;
+        self.pending_steps += frames;
+    }
+
+    pub fn simulation_time(&mut self, delta_time_s: f32, scale: f32) -> f32 {
+        if !self.paused {
+            return delta_time_s * scale;
+        }
+
+        if self.pending_steps > 0 {
+            self.pending_steps -= 1;
+            FIXED_TIMESTEP * scale
+        } else {
+            0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pause_and_step() {
+        let mut time_scale = TimeScale::new();
+        assert_eq!(time_scale.simulation_time(0.1, 0.5), 0.05);
+
+        time_scale.toggle_pause();
+        assert!(time_scale.is_paused());
+        assert_eq!(time_scale.simulation_time(0.1, 1.0), 0.0);
+
+        time_scale.step(2);
+        assert_eq!(time_scale.simulation_time(0.1, 1.0), FIXED_TIMESTEP);
+        assert_eq!(time_scale.simulation_time(0.1, 2.0), FIXED_TIMESTEP * 2.0);
+        assert_eq!(time_scale.simulation_time(0.1, 1.0), 0.0);
+
+        time_scale.toggle_pause();
+        assert_eq!(time_scale.simulation_time(0.1, 1.0), 0.1);
+
+        time_scale.step(1);
+        assert!(time_scale.is_paused());
+        assert_eq!(time_scale.simulation_time(0.1, 1.0), FIXED_TIMESTEP);
+        assert_eq!(time_scale.simulation_time(0.1, 1.0), 0.0);
+    }
+}
@@ -0,0 +1,46 @@
+use cgmath::Matrix4;
+
+use crate::renderer::context::Context;
+use crate::renderer::types::BufferObjectHandle;
+
+// Collects the model matrices of a frame into a storage buffer. Shaders index it with gl_InstanceIndex,
+// so a draw command picks its matrix through the instance_start returned by push.
+pub struct TransformBuffer {
+    sbo: BufferObjectHandle<Matrix4<f32>>,
+    count: u32,
+}
+
+impl TransformBuffer {
+    pub fn new(context: &mut Context, capacity: usize) -> Self {
+        let sbo = context.create_storage_buffer::<Matrix4<f32>>(capacity);
+
+        TransformBuffer { sbo, count: 0 }
+    }
+
+    pub fn get_storage_buffer(&self) -> BufferObjectHandle<Matrix4<f32>> {
+        self.sbo
+    }
+
+    // Must be called once per frame before the first push
+    pub fn reset(&mut self, context: &mut Context) {
+        context.reset_buffer_object(self.sbo);
+        self.count = 0;
+    }
+
+    pub fn push(&mut self, context: &mut Context, transform: Matrix4<f32>) -> u32 {
+        context.push_to_buffer_object(self.sbo, transform);
+
+        let instance_start = self.count;
+        self.count += 1;
+
+        instance_start
+    }
+
+    pub fn len(&self) -> u32 {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+}
@@ -1,11 +1,22 @@
-use cgmath::Vector4;
+use crate::engine::math::color::Color;
 
-pub const COLOR_WHITE: Vector4<f32> = Vector4::new(1.0, 1.0, 1.0, 1.0);
-pub const COLOR_BLACK: Vector4<f32> = Vector4::new(0.0, 0.0, 0.0, 1.0);
-pub const COLOR_INPUT_TEXT: Vector4<f32> = Vector4::new(1.0, 1.0, 1.0, 1.0);
-pub const COLOR_TEXT: Vector4<f32> = Vector4::new(0.7, 0.7, 0.8, 1.0);
-pub const COLOR_TEXT_ERROR: Vector4<f32> = Vector4::new(0.9, 0.3, 0.3, 1.0);
-pub const COLOR_TEXT_CVAR: Vector4<f32> = Vector4::new(0.3, 0.3, 0.9, 1.0);
-pub const COLOR_TEXT_INFO: Vector4<f32> = Vector4::new(0.3, 0.9, 0.3, 1.0);
-pub const COLOR_TEXT_DEBUG: Vector4<f32> = Vector4::new(0.3, 0.9, 0.9, 1.0);
-pub const COLOR_TEXT_KHRONOS: Vector4<f32> = Vector4::new(0.7, 0.3, 0.7, 1.0);
+pub const COLOR_WHITE: Color = Color::WHITE;
+pub const COLOR_BLACK: Color = Color::BLACK;
+#[cfg(feature = "console")]
+pub const COLOR_INPUT_TEXT: Color = Color::srgb(1.0, 1.0, 1.0, 1.0);
+#[cfg(feature = "console")]
+pub const COLOR_TEXT: Color = Color::srgb(0.7, 0.7, 0.8, 1.0);
+#[cfg(feature = "console")]
+pub const COLOR_TEXT_ERROR: Color = Color::srgb(0.9, 0.3, 0.3, 1.0);
+#[cfg(feature = "console")]
+pub const COLOR_TEXT_CVAR: Color = Color::srgb(0.3, 0.3, 0.9, 1.0);
+#[cfg(feature = "console")]
+pub const COLOR_TEXT_INFO: Color = Color::srgb(0.3, 0.9, 0.3, 1.0);
+#[cfg(feature = "console")]
+pub const COLOR_TEXT_DEBUG: Color = Color::srgb(0.3, 0.9, 0.9, 1.0);
+#[cfg(feature = "console")]
+pub const COLOR_TEXT_KHRONOS: Color = Color::srgb(0.7, 0.3, 0.7, 1.0);
+#[cfg(feature = "console")]
+pub const COLOR_PANEL_BACKGROUND: Color = Color::srgb(0.02, 0.02, 0.02, 0.85);
+#[cfg(feature = "console")]
+pub const COLOR_PANEL_BAR: Color = Color::srgb(0.2, 0.2, 0.25, 1.0);
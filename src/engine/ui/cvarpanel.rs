@@ -0,0 +1,259 @@
+use std::path::Path;
+
+use cgmath::Vector2;
+use winit::event::{ElementState, VirtualKeyCode};
+
+use crate::engine::cvars::{ConfigVariables, CvarType};
+use crate::engine::datatypes::{InstancedCharacter, InstancedQuad, Mesh, TexturedVertex, ViewProjectionUniform, WindowExtent};
+use crate::engine::runtime::ControlSignal;
+use crate::engine::ui::colors::{COLOR_INPUT_TEXT, COLOR_PANEL_BACKGROUND, COLOR_PANEL_BAR, COLOR_TEXT, COLOR_TEXT_CVAR};
+use crate::engine::ui::draw::{draw_quad, draw_text};
+use crate::renderer::context::{Context, Frame};
+use crate::renderer::types::{
+    BufferObjectHandle, DrawCommand, PipelineConfiguration, PipelineHandle, SamplerHandle, TextureHandle,
+    UniformHandle, SWAPCHAIN_PASS,
+};
+
+const BORDER_OFFSET: u32 = 8;
+const TEXT_SIZE_PX: u32 = 16;
+const ROW_HEIGHT_PX: u32 = 20;
+const VISIBLE_ROWS: usize = 12;
+const NAME_COLUMNS: u32 = 22;
+const SLIDER_WIDTH_PX: u32 = 200;
+// Steps between the min and max of a slider
+const SLIDER_STEPS: f32 = 100.0;
+
+// Debug panel for tuning numeric cvars without typing, toggled with TOGGLE_BUTTON. Up and down select a cvar, left
+// and right change it by a step, within the declared min and max. Cvars with both are drawn as sliders.
+pub struct CvarPanel {
+    active: bool,
+    selected: usize,
+
+    extent: WindowExtent,
+    text_sbo: BufferObjectHandle<InstancedCharacter>,
+    quad_sbo: BufferObjectHandle<InstancedQuad>,
+    text_pipeline: PipelineHandle,
+    quad_pipeline: PipelineHandle,
+    mesh: Mesh,
+}
+
+impl CvarPanel {
+    pub const TOGGLE_BUTTON: VirtualKeyCode = VirtualKeyCode::F10;
+
+    pub fn new(
+        context: &mut Context,
+        vp_uniform: UniformHandle<ViewProjectionUniform>,
+        mesh: Mesh,
+        extent: WindowExtent,
+        font_texture: TextureHandle,
+        sampler: SamplerHandle,
+    ) -> Self {
+        let text_sbo = context.create_storage_buffer::<InstancedCharacter>(500);
+        let quad_sbo = context.create_storage_buffer::<InstancedQuad>(50);
+
+        let text_pipeline_config = PipelineConfiguration::builder()
+            .with_name("cvar panel text")
+            .with_vertex_shader_file(Path::new("./resources/shaders/2d_text_ssbo_vert.spv"))
+            .with_fragment_shader_file(Path::new("./resources/shaders/2d_texture_ssbo_frag.spv"))
+            .with_vertex_uniform(0, vp_uniform)
+            .with_storage_buffer_object(2, text_sbo)
+            .with_alpha_blending()
+            .add_texture(1, font_texture, sampler)
+            .build()
+            .expect("Invalid pipeline configuration!");
+        let text_pipeline = context.add_pipeline::<TexturedVertex>(SWAPCHAIN_PASS, text_pipeline_config);
+        let quad_pipeline_config = PipelineConfiguration::builder()
+            .with_name("cvar panel quad")
+            .with_vertex_shader_file(Path::new("./resources/shaders/2d_flat_ssbo_vert.spv"))
+            .with_fragment_shader_file(Path::new("./resources/shaders/2d_flat_ssbo_frag.spv"))
+            .with_vertex_uniform(0, vp_uniform)
+            .with_storage_buffer_object(2, quad_sbo)
+            .with_alpha_blending()
+            .build()
+            .expect("Invalid pipeline configuration!");
+        let quad_pipeline = context.add_pipeline::<TexturedVertex>(SWAPCHAIN_PASS, quad_pipeline_config);
+
+        CvarPanel {
+            active: false,
+            selected: 0,
+            extent,
+            text_sbo,
+            quad_sbo,
+            text_pipeline,
+            quad_pipeline,
+            mesh,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.active = !self.active;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub fn handle_window_resize(&mut self, new_extent: WindowExtent) {
+        self.extent = new_extent;
+    }
+
+    // Returns None for keys the panel doesn't use, otherwise the change trigger of the cvar if it was changed
+    pub fn handle_keyboard_event(
+        &mut self,
+        cfg: &mut ConfigVariables,
+        key: VirtualKeyCode,
+        state: ElementState,
+    ) -> Option<ControlSignal> {
+        if state != ElementState::Pressed {
+            return None;
+        }
+
+        let cvars = cfg.get_numeric_ids();
+        let direction = match key {
+            VirtualKeyCode::Up => {
+                self.selected = (self.selected + cvars.len() - 1) % cvars.len();
+                return Some(ControlSignal::None);
+            }
+            VirtualKeyCode::Down => {
+                self.selected = (self.selected + 1) % cvars.len();
+                return Some(ControlSignal::None);
+            }
+            VirtualKeyCode::Left => -1.0,
+            VirtualKeyCode::Right => 1.0,
+            _ => return None,
+        };
+
+        let cvar = cvars[self.selected.min(cvars.len() - 1)];
+        let (min, max) = cfg.get_range(cvar);
+        match cfg.get(cvar).get_type() {
+            CvarType::Integer => {
+                let value = _adjusted_value(cfg.get(cvar).as_float(), min, max, true, direction);
+                cfg.set(cvar, value as u32);
+            }
+            _ => {
+                let value = _adjusted_value(cfg.get(cvar).as_float(), min, max, false, direction);
+                cfg.set(cvar, value);
+            }
+        }
+
+        Some(cfg.get_trigger(cvar))
+    }
+
+    pub fn draw(&mut self, frame: &mut Frame, cfg: &ConfigVariables) {
+        frame.reset_buffer_object(self.text_sbo);
+        frame.reset_buffer_object(self.quad_sbo);
+
+        let cvars = cfg.get_numeric_ids();
+        let selected = self.selected.min(cvars.len() - 1);
+        let first = selected.saturating_sub(VISIBLE_ROWS - 1);
+        let rows = cvars.len().min(VISIBLE_ROWS);
+        let width = BORDER_OFFSET * 4 + NAME_COLUMNS * TEXT_SIZE_PX + SLIDER_WIDTH_PX + 8 * TEXT_SIZE_PX;
+
+        let mut quad_instance_count = 0;
+        let mut text_instance_count = 0;
+
+        quad_instance_count += draw_quad(
+            frame,
+            self.quad_sbo,
+            Vector2::new(BORDER_OFFSET, BORDER_OFFSET),
+            Vector2::new(width, rows as u32 * ROW_HEIGHT_PX + 2 * BORDER_OFFSET),
+            COLOR_PANEL_BACKGROUND,
+        );
+
+        for (row, (i, cvar)) in cvars.iter().enumerate().skip(first).take(rows).enumerate() {
+            // First row at the top
+            let y = BORDER_OFFSET * 2 + (rows - 1 - row) as u32 * ROW_HEIGHT_PX;
+            let x_slider = BORDER_OFFSET * 2 + NAME_COLUMNS * TEXT_SIZE_PX;
+            let color = if i == selected { COLOR_INPUT_TEXT } else { COLOR_TEXT };
+
+            text_instance_count += draw_text(
+                frame,
+                self.text_sbo,
+                cfg.get_name(*cvar),
+                Vector2::new(BORDER_OFFSET * 2, y),
+                TEXT_SIZE_PX,
+                color,
+            );
+
+            let value = cfg.get(*cvar).as_float();
+            if let (Some(min), Some(max)) = cfg.get_range(*cvar) {
+                let fill = if max > min { ((value - min) / (max - min)).clamp(0.0, 1.0) } else { 1.0 };
+                quad_instance_count += draw_quad(
+                    frame,
+                    self.quad_sbo,
+                    Vector2::new(x_slider, y + 4),
+                    Vector2::new(SLIDER_WIDTH_PX, TEXT_SIZE_PX - 8),
+                    COLOR_PANEL_BAR,
+                );
+                quad_instance_count += draw_quad(
+                    frame,
+                    self.quad_sbo,
+                    Vector2::new(x_slider, y + 4),
+                    Vector2::new((fill * SLIDER_WIDTH_PX as f32) as u32, TEXT_SIZE_PX - 8),
+                    COLOR_TEXT_CVAR,
+                );
+            }
+            text_instance_count += draw_text(
+                frame,
+                self.text_sbo,
+                &cfg.get(*cvar).as_str(),
+                Vector2::new(x_slider + SLIDER_WIDTH_PX + BORDER_OFFSET, y),
+                TEXT_SIZE_PX,
+                color,
+            );
+        }
+
+        frame.add_draw_command(DrawCommand::new_buffered_instanced_nopush(
+            self.quad_pipeline,
+            self.mesh,
+            quad_instance_count,
+            0,
+        ));
+        frame.add_draw_command(DrawCommand::new_buffered_instanced_nopush(
+            self.text_pipeline,
+            self.mesh,
+            text_instance_count,
+            0,
+        ));
+    }
+}
+
+// Steps of 1 for integers, a fraction of the range for floats that have one and a fraction of the value otherwise
+fn _adjusted_value(value: f32, min: Option<f32>, max: Option<f32>, integer: bool, direction: f32) -> f32 {
+    let step = match (min, max) {
+        _ if integer => 1.0,
+        (Some(min), Some(max)) => (max - min) / SLIDER_STEPS,
+        _ => (value.abs() * 0.1).max(0.01),
+    };
+
+    let mut adjusted = value + direction * step;
+    if integer {
+        adjusted = adjusted.round().max(0.0);
+    }
+    if let Some(min) = min {
+        adjusted = adjusted.max(min);
+    }
+    if let Some(max) = max {
+        adjusted = adjusted.min(max);
+    }
+
+    adjusted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adjusted_values() {
+        let approx = |a: f32, b: f32| (a - b).abs() < 1.0e-5;
+        assert!(approx(_adjusted_value(60.0, Some(10.0), Some(170.0), false, 1.0), 61.6));
+        assert_eq!(_adjusted_value(169.0, Some(10.0), Some(170.0), false, 1.0), 170.0);
+        assert!(approx(_adjusted_value(0.08, None, None, false, -1.0), 0.07));
+        assert!(approx(_adjusted_value(2.0, None, None, false, 1.0), 2.2));
+        assert_eq!(_adjusted_value(2.0, None, Some(2.0), true, 1.0), 2.0);
+        assert_eq!(_adjusted_value(0.0, None, None, true, -1.0), 0.0);
+        assert_eq!(_adjusted_value(100.0, Some(100.0), None, true, -1.0), 100.0);
+        assert_eq!(_adjusted_value(100.0, Some(100.0), None, true, 1.0), 101.0);
+    }
+}
@@ -1,15 +1,19 @@
-use crate::engine::datatypes::{InstancedCharacter, InstancedQuad};
+use crate::engine::datatypes::InstancedCharacter;
+#[cfg(feature = "console")]
+use crate::engine::datatypes::InstancedQuad;
+use crate::engine::math::color::Color;
 
 use crate::renderer::context::Context;
 use crate::renderer::types::BufferObjectHandle;
-use cgmath::{Vector2, Vector4};
+use cgmath::Vector2;
 
+#[cfg(feature = "console")]
 pub fn draw_quad(
     context: &mut Context,
-    handle: BufferObjectHandle,
+    handle: BufferObjectHandle<InstancedQuad>,
     position: Vector2<u32>,
     extent: Vector2<u32>,
-    color: Vector4<f32>,
+    color: Color,
 ) -> u32 {
     let quad = InstancedQuad::new(
         Vector2::new(
@@ -17,7 +21,7 @@ pub fn draw_quad(
             (position.y + (extent.y / 2)) as f32,
         ),
         Vector2::new(extent.x as f32, extent.y as f32),
-        color,
+        color.to_linear(),
     );
     context.push_to_buffer_object(handle, quad);
 
@@ -26,12 +30,13 @@ pub fn draw_quad(
 
 pub fn draw_text(
     context: &mut Context,
-    handle: BufferObjectHandle,
+    handle: BufferObjectHandle<InstancedCharacter>,
     text: &str,
     position: Vector2<u32>,
     char_size_px: u32,
-    color: Vector4<f32>,
+    color: Color,
 ) -> u32 {
+    let color = color.to_linear();
     for (i, char) in text.chars().enumerate() {
         let char_position = Vector2::new(
             (position.x + (char_size_px / 2) + (i as u32 * char_size_px)) as f32,
@@ -48,12 +53,12 @@ pub fn draw_text(
 
 pub fn draw_text_shadowed(
     context: &mut Context,
-    handle: BufferObjectHandle,
+    handle: BufferObjectHandle<InstancedCharacter>,
     text: &str,
     position: Vector2<u32>,
     char_size_px: u32,
-    color: Vector4<f32>,
-    shadow_color: Vector4<f32>,
+    color: Color,
+    shadow_color: Color,
 ) -> u32 {
     let mut instance_count = 0;
     instance_count += draw_text(
@@ -68,3 +73,50 @@ pub fn draw_text_shadowed(
 
     instance_count
 }
+
+// Width of the text in pixels, the font is monospaced with square glyphs
+pub fn text_width(text: &str, char_size_px: u32) -> u32 {
+    text.chars().count() as u32 * char_size_px
+}
+
+// Splits the text into lines of at most max_columns characters, at the last space that fits if there is one. Words
+// longer than a line are split wherever the line ends.
+#[cfg(feature = "console")]
+pub fn wrap_text(text: &str, max_columns: usize) -> Vec<&str> {
+    let mut lines = Vec::new();
+    let mut rest = text;
+    while max_columns > 0 && rest.chars().count() > max_columns {
+        let (split, character) = rest.char_indices().nth(max_columns).unwrap();
+        match rest[..split + character.len_utf8()].rfind(' ').filter(|space| *space > 0) {
+            Some(space) => {
+                lines.push(&rest[..space]);
+                rest = &rest[space + 1..];
+            }
+            None => {
+                lines.push(&rest[..split]);
+                rest = &rest[split..];
+            }
+        }
+    }
+    lines.push(rest);
+
+    lines
+}
+
+#[cfg(all(test, feature = "console"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrap() {
+        assert_eq!(wrap_text("", 4), vec![""]);
+        assert_eq!(wrap_text("abcd", 4), vec!["abcd"]);
+        assert_eq!(wrap_text("ab cd ef", 5), vec!["ab cd", "ef"]);
+        assert_eq!(wrap_text("abcd efgh", 4), vec!["abcd", "efgh"]);
+        assert_eq!(wrap_text("abcdefghij", 4), vec!["abcd", "efgh", "ij"]);
+        assert_eq!(wrap_text("a bcdefgh", 4), vec!["a", "bcde", "fgh"]);
+        assert_eq!(wrap_text("äöü åäö", 3), vec!["äöü", "åäö"]);
+        assert_eq!(wrap_text("no wrapping", 0), vec!["no wrapping"]);
+        assert_eq!(text_width("äb", 16), 32);
+    }
+}
@@ -1,22 +1,36 @@
 use std::path::Path;
 use cgmath::{Matrix4, SquareMatrix};
 
+#[cfg(feature = "console")]
 use crate::engine::console::Console;
 use crate::engine::datatypes::{ViewProjectionUniform, WindowExtent};
 
 use crate::engine::image;
 use crate::engine::mesh::PredefinedMesh::TexturedQuad;
 use crate::engine::mesh::{MeshHandle, MeshManager};
-use crate::engine::ui::widgets::{ConsoleRenderer, TextOverlayRenderer};
-
-use crate::renderer::context::Context;
-use crate::renderer::types::{UniformHandle, UniformStage};
+#[cfg(feature = "console")]
+use crate::engine::cvars::ConfigVariables;
+#[cfg(feature = "console")]
+use crate::engine::runtime::ControlSignal;
+#[cfg(feature = "console")]
+use crate::engine::ui::cvarpanel::CvarPanel;
+#[cfg(feature = "console")]
+use crate::engine::ui::widgets::ConsoleRenderer;
+#[cfg(feature = "console")]
+use winit::event::{ElementState, VirtualKeyCode};
+use crate::engine::ui::widgets::TextOverlayRenderer;
+
+use crate::renderer::context::{Context, Frame};
+use crate::renderer::types::{SamplerConfiguration, UniformHandle, UniformStage};
 
 pub struct Hud {
-    uniform: UniformHandle,
+    uniform: UniformHandle<ViewProjectionUniform>,
 
     text_overlay_renderer: TextOverlayRenderer,
+    #[cfg(feature = "console")]
     console_renderer: ConsoleRenderer,
+    #[cfg(feature = "console")]
+    cvar_panel: CvarPanel,
 
     window_extent: WindowExtent,
 }
@@ -28,29 +42,69 @@ impl Hud {
         let data = _create_view_projection_uniform(window_extent);
         context.set_buffer_object(vp_uniform, data);
 
-        let font_image = image::load_image(Path::new("./resources/textures/font.png"));
-        let font_texture = context.add_texture(font_image.width, font_image.height, &font_image.data);
-        let sampler = context.add_sampler();
+        let font_texture = image::load_texture(context, Path::new("./resources/textures/font.png"));
+        let sampler = context.add_sampler(SamplerConfiguration::default());
 
         let mesh = *mesh_manager.get_mesh(TexturedQuad as MeshHandle);
 
         let text_overlay_renderer = TextOverlayRenderer::new(context, vp_uniform, mesh, window_extent, font_texture, sampler);
+        #[cfg(feature = "console")]
         let console_renderer = ConsoleRenderer::new(context, vp_uniform, mesh, window_extent, font_texture, sampler);
+        #[cfg(feature = "console")]
+        let cvar_panel = CvarPanel::new(context, vp_uniform, mesh, window_extent, font_texture, sampler);
 
         Hud {
             uniform: vp_uniform,
             text_overlay_renderer,
+            #[cfg(feature = "console")]
             console_renderer,
+            #[cfg(feature = "console")]
+            cvar_panel,
 
             window_extent,
         }
     }
 
-    pub fn draw(&mut self, context: &mut Context, console: &Console) {
-        self.text_overlay_renderer.draw(context);
+    pub fn draw(&mut self, frame: &mut Frame) {
+        self.text_overlay_renderer.draw(frame);
+    }
+
+    #[cfg(feature = "console")]
+    pub fn set_watch_lines(&mut self, watch_lines: Vec<String>) {
+        self.text_overlay_renderer.set_watch_lines(watch_lines);
+    }
+
+    #[cfg(feature = "console")]
+    pub fn draw_cvar_panel(&mut self, frame: &mut Frame, config: &ConfigVariables) {
+        if self.cvar_panel.is_active() {
+            self.cvar_panel.draw(frame, config);
+        }
+    }
+
+    #[cfg(feature = "console")]
+    pub fn toggle_cvar_panel(&mut self) {
+        self.cvar_panel.toggle();
+    }
+
+    // Keys used by the open cvar panel, see CvarPanel::handle_keyboard_event
+    #[cfg(feature = "console")]
+    pub fn handle_cvar_panel_key(
+        &mut self,
+        config: &mut ConfigVariables,
+        key: VirtualKeyCode,
+        state: ElementState,
+    ) -> Option<ControlSignal> {
+        if !self.cvar_panel.is_active() {
+            return None;
+        }
+
+        self.cvar_panel.handle_keyboard_event(config, key, state)
+    }
 
+    #[cfg(feature = "console")]
+    pub fn draw_console(&mut self, frame: &mut Frame, console: &Console) {
         if console.is_visible() {
-            self.console_renderer.draw(context, console);
+            self.console_renderer.draw(frame, console);
         }
     }
 
@@ -60,10 +114,13 @@ impl Hud {
         context.set_buffer_object(self.uniform, data);
 
         self.text_overlay_renderer.handle_window_resize(new_extent);
+        #[cfg(feature = "console")]
         self.console_renderer.handle_window_resize(new_extent);
+        #[cfg(feature = "console")]
+        self.cvar_panel.handle_window_resize(new_extent);
     }
 
-    pub fn get_vp_uniform(&self) -> UniformHandle {
+    pub fn get_vp_uniform(&self) -> UniformHandle<ViewProjectionUniform> {
         self.uniform
     }
 }
@@ -0,0 +1,106 @@
+use cgmath::Vector2;
+
+use crate::engine::datatypes::{ViewProjectionUniform, WindowExtent};
+use crate::engine::mesh::MeshManager;
+use crate::engine::ui::colors::COLOR_WHITE;
+use crate::engine::ui::widgets::TexturedQuadRenderer;
+use crate::renderer::context::{Context, Frame};
+use crate::renderer::types::{RenderPassHandle, SamplerConfiguration, SamplerFilter, TextureHandle, UniformHandle};
+
+// Renders into a small fixed resolution target and presents it on the swapchain scaled by the largest integer
+// factor that fits the window, letterboxed in the middle.
+pub struct LowResolutionPresenter {
+    resolution: WindowExtent,
+    texture: TextureHandle,
+    render_pass: RenderPassHandle,
+    quad_renderer: TexturedQuadRenderer,
+}
+
+impl LowResolutionPresenter {
+    pub fn new(
+        context: &mut Context,
+        vp_uniform: UniformHandle<ViewProjectionUniform>,
+        mesh_manager: &MeshManager,
+        resolution: WindowExtent,
+        pass_order: u32,
+        window_extent: WindowExtent,
+    ) -> Self {
+        let texture = context.add_render_texture(resolution.width, resolution.height);
+        let render_pass = context
+            .create_render_pass(texture, pass_order)
+            .expect("Failed to create low resolution render pass");
+
+        let sampler_config = SamplerConfiguration::new()
+            .with_filter(SamplerFilter::Nearest, SamplerFilter::Nearest)
+            .with_max_anisotropy(0);
+        let sampler = context.add_sampler(sampler_config);
+        let quad_renderer = TexturedQuadRenderer::new(context, vp_uniform, mesh_manager, texture, sampler);
+
+        let mut presenter = LowResolutionPresenter {
+            resolution,
+            texture,
+            render_pass,
+            quad_renderer,
+        };
+        presenter.handle_window_resize(window_extent);
+
+        presenter
+    }
+
+    pub fn get_render_pass(&self) -> RenderPassHandle {
+        self.render_pass
+    }
+
+    pub fn get_texture(&self) -> TextureHandle {
+        self.texture
+    }
+
+    pub fn handle_window_resize(&mut self, window_extent: WindowExtent) {
+        let (offset, size) = integer_scaled_viewport(self.resolution, window_extent);
+
+        self.quad_renderer.set(
+            Vector2::new((offset.x + size.x / 2) as f32, (offset.y + size.y / 2) as f32),
+            Vector2::new(size.x as f32, size.y as f32),
+            COLOR_WHITE,
+        );
+    }
+
+    pub fn draw(&mut self, frame: &mut Frame) {
+        self.quad_renderer.draw(frame);
+    }
+}
+
+// Returns offset and size of the largest integer multiple of the resolution that fits in the window.
+// Windows smaller than the resolution still get a scale of 1 and are cropped.
+pub fn integer_scaled_viewport(resolution: WindowExtent, window_extent: WindowExtent) -> (Vector2<u32>, Vector2<u32>) {
+    let scale_x = window_extent.width / resolution.width.max(1);
+    let scale_y = window_extent.height / resolution.height.max(1);
+    let scale = scale_x.min(scale_y).max(1);
+
+    let size = Vector2::new(resolution.width * scale, resolution.height * scale);
+    let offset = Vector2::new(
+        window_extent.width.saturating_sub(size.x) / 2,
+        window_extent.height.saturating_sub(size.y) / 2,
+    );
+
+    (offset, size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integer_scaling() {
+        let resolution = WindowExtent::new(384, 216);
+
+        let (offset, size) = integer_scaled_viewport(resolution, WindowExtent::new(1920, 1080));
+        assert_eq!((offset, size), (Vector2::new(0, 0), Vector2::new(1920, 1080)));
+
+        let (offset, size) = integer_scaled_viewport(resolution, WindowExtent::new(1280, 1024));
+        assert_eq!((offset, size), (Vector2::new(64, 188), Vector2::new(1152, 648)));
+
+        let (_, size) = integer_scaled_viewport(resolution, WindowExtent::new(200, 100));
+        assert_eq!(size, Vector2::new(384, 216));
+    }
+}
@@ -1,4 +1,10 @@
 mod colors;
-mod draw;
+#[cfg(feature = "console")]
+pub mod cvarpanel;
+pub(crate) mod draw;
 pub(crate) mod hud;
+pub mod lowres;
+pub mod pip;
+pub mod renderscale;
+pub mod transparency;
 pub mod widgets;
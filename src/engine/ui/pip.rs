@@ -0,0 +1,197 @@
+use cgmath::Vector2;
+
+use crate::engine::datatypes::{ViewProjectionUniform, WindowExtent};
+use crate::engine::mesh::MeshManager;
+use crate::engine::math::color::Color;
+use crate::engine::ui::colors::COLOR_WHITE;
+use crate::engine::ui::widgets::TexturedQuadRenderer;
+use crate::renderer::context::{Context, Frame};
+use crate::renderer::types::{RenderPassHandle, SamplerConfiguration, TextureHandle, UniformHandle};
+
+const DEFAULT_MARGIN: u32 = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Placement {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+// Renders the view of a second camera into a texture every frame and shows it on top of the window, e.g. a minimap
+// or a rear view mirror. Pipelines drawing into the view are added to get_render_pass configured with_pass_camera.
+pub struct PictureInPicture {
+    texture: TextureHandle,
+    render_pass: RenderPassHandle,
+    view_renderer: TexturedQuadRenderer,
+    border_renderer: TexturedQuadRenderer,
+
+    size: WindowExtent,
+    placement: Placement,
+    margin: u32,
+    border_width: u32,
+    border_color: Color,
+    window_extent: WindowExtent,
+    enabled: bool,
+}
+
+impl PictureInPicture {
+    pub fn new(
+        context: &mut Context,
+        vp_uniform: UniformHandle<ViewProjectionUniform>,
+        mesh_manager: &MeshManager,
+        camera_uniform: UniformHandle<ViewProjectionUniform>,
+        resolution: WindowExtent,
+        pass_order: u32,
+        window_extent: WindowExtent,
+    ) -> Self {
+        let texture = context.add_render_texture(resolution.width, resolution.height);
+        let render_pass = context
+            .create_render_pass(texture, pass_order)
+            .expect("Failed to create picture in picture render pass");
+        context
+            .set_render_pass_camera(render_pass, camera_uniform)
+            .expect("Failed to set picture in picture camera");
+
+        let sampler = context.add_sampler(SamplerConfiguration::default());
+        let border_texture = context.add_texture(1, 1, &[255, 255, 255, 255]);
+        // The border is drawn first, the view covers all but its edges
+        let border_renderer = TexturedQuadRenderer::new(context, vp_uniform, mesh_manager, border_texture, sampler);
+        let view_renderer = TexturedQuadRenderer::new(context, vp_uniform, mesh_manager, texture, sampler);
+
+        let mut pip = PictureInPicture {
+            texture,
+            render_pass,
+            view_renderer,
+            border_renderer,
+
+            size: resolution,
+            placement: Placement::TopRight,
+            margin: DEFAULT_MARGIN,
+            border_width: 0,
+            border_color: COLOR_WHITE,
+            window_extent,
+            enabled: true,
+        };
+        pip.update_layout();
+
+        pip
+    }
+
+    // On screen size in pixels, defaults to the resolution of the render texture
+    pub fn with_size(mut self, size: WindowExtent) -> Self {
+        self.size = size;
+        self.update_layout();
+
+        self
+    }
+
+    pub fn with_placement(mut self, placement: Placement, margin: u32) -> Self {
+        self.placement = placement;
+        self.margin = margin;
+        self.update_layout();
+
+        self
+    }
+
+    pub fn with_border(mut self, width: u32, color: Color) -> Self {
+        self.border_width = width;
+        self.border_color = color;
+        self.update_layout();
+
+        self
+    }
+
+    pub fn get_render_pass(&self) -> RenderPassHandle {
+        self.render_pass
+    }
+
+    pub fn get_texture(&self) -> TextureHandle {
+        self.texture
+    }
+
+    pub fn set_camera(
+        &mut self,
+        context: &mut Context,
+        camera_uniform: UniformHandle<ViewProjectionUniform>,
+    ) -> Result<(), &'static str> {
+        context.set_render_pass_camera(self.render_pass, camera_uniform)
+    }
+
+    // A disabled view is neither rendered nor shown, draw commands added to its pass are dropped
+    pub fn set_enabled(&mut self, context: &mut Context, enabled: bool) {
+        self.enabled = enabled;
+        context
+            .set_render_pass_enabled(self.render_pass, enabled)
+            .expect("Invalid picture in picture render pass");
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn handle_window_resize(&mut self, window_extent: WindowExtent) {
+        self.window_extent = window_extent;
+        self.update_layout();
+    }
+
+    pub fn draw(&mut self, frame: &mut Frame) {
+        if !self.enabled {
+            return;
+        }
+
+        if self.border_width > 0 {
+            self.border_renderer.draw(frame);
+        }
+        self.view_renderer.draw(frame);
+    }
+
+    fn update_layout(&mut self) {
+        let center = placement_center(self.placement, self.margin + self.border_width, self.size, self.window_extent);
+        let size = Vector2::new(self.size.width as f32, self.size.height as f32);
+        let border = Vector2::new(2.0 * self.border_width as f32, 2.0 * self.border_width as f32);
+
+        self.view_renderer.set(center, size, COLOR_WHITE);
+        self.border_renderer.set(center, size + border, self.border_color);
+    }
+}
+
+// Center of a quad of the given size placed in a corner of the window, hud coordinates have their origin in the
+// lower left corner.
+pub fn placement_center(placement: Placement, margin: u32, size: WindowExtent, window_extent: WindowExtent) -> Vector2<f32> {
+    let left = margin as f32 + size.width as f32 / 2.0;
+    let right = window_extent.width as f32 - left;
+    let bottom = margin as f32 + size.height as f32 / 2.0;
+    let top = window_extent.height as f32 - bottom;
+
+    match placement {
+        Placement::TopLeft => Vector2::new(left, top),
+        Placement::TopRight => Vector2::new(right, top),
+        Placement::BottomLeft => Vector2::new(left, bottom),
+        Placement::BottomRight => Vector2::new(right, bottom),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corner_placement() {
+        let window_extent = WindowExtent::new(1280, 720);
+        let size = WindowExtent::new(200, 100);
+
+        assert_eq!(
+            placement_center(Placement::TopRight, 10, size, window_extent),
+            Vector2::new(1170.0, 660.0)
+        );
+        assert_eq!(
+            placement_center(Placement::BottomLeft, 10, size, window_extent),
+            Vector2::new(110.0, 60.0)
+        );
+        assert_eq!(
+            placement_center(Placement::TopLeft, 0, window_extent, window_extent),
+            Vector2::new(640.0, 360.0)
+        );
+    }
+}
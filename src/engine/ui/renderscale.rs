@@ -0,0 +1,243 @@
+use std::time::Duration;
+
+use cgmath::Vector2;
+
+use crate::engine::cvars::{MAX_RENDER_SCALE, MIN_RENDER_SCALE};
+use crate::engine::datatypes::{ViewProjectionUniform, WindowExtent};
+use crate::engine::mesh::MeshManager;
+use crate::engine::ui::colors::COLOR_WHITE;
+use crate::engine::ui::widgets::TexturedQuadRenderer;
+use crate::renderer::context::{Context, Frame};
+use crate::renderer::types::{RenderPassHandle, SamplerAddressMode, SamplerConfiguration, TextureHandle, UniformHandle};
+
+// After the image passes of applications, e.g. shadow maps sampled by the scene
+pub const SCENE_PASS_ORDER: u32 = 10_000;
+
+// GPU times are averaged this long before the scale is adjusted
+const ADJUST_INTERVAL_S: f32 = 1.0;
+// The scale only goes up once the GPU time is this fraction below the target, so that it does not flip back and
+// forth around it
+const HYSTERESIS: f32 = 0.15;
+const MAX_SCALE_STEP: f32 = 0.25;
+// Scales are rounded to avoid recreating the scene target for tiny changes
+const SCALE_INCREMENT: f32 = 0.05;
+
+// Renders the main scene into a texture scaled relative to the swapchain and stretches it over the window before
+// anything else is drawn there, set by the r_renderscale cvar. Scales below 1 trade sharpness for fill rate, scales
+// above 1 supersample. Pipelines drawing the scene are added to get_render_pass.
+pub struct ScaledScenePresenter {
+    texture: TextureHandle,
+    render_pass: RenderPassHandle,
+    quad_renderer: TexturedQuadRenderer,
+    render_scale: f32,
+    enabled: bool,
+}
+
+impl ScaledScenePresenter {
+    pub fn new(
+        context: &mut Context,
+        vp_uniform: UniformHandle<ViewProjectionUniform>,
+        mesh_manager: &MeshManager,
+        render_scale: f32,
+        window_extent: WindowExtent,
+    ) -> Self {
+        let render_scale = render_scale.clamp(MIN_RENDER_SCALE, MAX_RENDER_SCALE);
+        let texture = context.add_render_texture_scaled(render_scale);
+        let render_pass = context
+            .create_render_pass(texture, SCENE_PASS_ORDER)
+            .expect("Failed to create scene render pass");
+
+        let sampler_config = SamplerConfiguration::new()
+            .with_address_mode(SamplerAddressMode::ClampToEdge)
+            .with_max_anisotropy(0);
+        let sampler = context.add_sampler(sampler_config);
+        let quad_renderer = TexturedQuadRenderer::new(context, vp_uniform, mesh_manager, texture, sampler);
+
+        let mut presenter = ScaledScenePresenter {
+            texture,
+            render_pass,
+            quad_renderer,
+            render_scale,
+            enabled: true,
+        };
+        presenter.handle_window_resize(window_extent);
+
+        presenter
+    }
+
+    pub fn get_render_pass(&self) -> RenderPassHandle {
+        self.render_pass
+    }
+
+    pub fn get_texture(&self) -> TextureHandle {
+        self.texture
+    }
+
+    pub fn render_scale(&self) -> f32 {
+        self.render_scale
+    }
+
+    // Waits for the device and recreates the texture, only when the scale actually changes
+    pub fn set_render_scale(&mut self, context: &mut Context, render_scale: f32) {
+        let render_scale = render_scale.clamp(MIN_RENDER_SCALE, MAX_RENDER_SCALE);
+        if render_scale == self.render_scale {
+            return;
+        }
+
+        self.render_scale = render_scale;
+        context.set_render_texture_scale(self.texture, render_scale);
+    }
+
+    // Disabled for applications drawing straight to the swapchain, the pass is then skipped as well
+    pub fn set_enabled(&mut self, context: &mut Context, enabled: bool) {
+        self.enabled = enabled;
+        context
+            .set_render_pass_enabled(self.render_pass, enabled)
+            .expect("Invalid scene render pass");
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn handle_window_resize(&mut self, window_extent: WindowExtent) {
+        let size = Vector2::new(window_extent.width as f32, window_extent.height as f32);
+        self.quad_renderer.set(size / 2.0, size, COLOR_WHITE);
+    }
+
+    pub fn draw(&mut self, frame: &mut Frame) {
+        if self.enabled {
+            self.quad_renderer.draw(frame);
+        }
+    }
+}
+
+// Adjusts the render scale once a second so that the GPU time of a frame stays below a target, set by the
+// r_dynres_target cvar. The scale never exceeds r_renderscale.
+pub struct DynamicResolution {
+    // 0 = disabled
+    target_frame_time_s: f32,
+    elapsed_s: f32,
+    gpu_time_sum_s: f32,
+    sample_count: u32,
+}
+
+impl Default for DynamicResolution {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DynamicResolution {
+    pub fn new() -> Self {
+        DynamicResolution {
+            target_frame_time_s: 0.0,
+            elapsed_s: 0.0,
+            gpu_time_sum_s: 0.0,
+            sample_count: 0,
+        }
+    }
+
+    pub fn set_target_frame_time(&mut self, target_frame_time_s: f32) {
+        if target_frame_time_s != self.target_frame_time_s {
+            self.target_frame_time_s = target_frame_time_s;
+            self.reset();
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.target_frame_time_s > 0.0
+    }
+
+    // Returns the new scale at the end of an interval if it has to change. Frames without a GPU time, e.g. without
+    // timestamp support, are ignored.
+    pub fn update(
+        &mut self,
+        delta_time_s: f32,
+        gpu_time: Option<Duration>,
+        render_scale: f32,
+        max_scale: f32,
+    ) -> Option<f32> {
+        if !self.is_enabled() {
+            return None;
+        }
+
+        self.elapsed_s += delta_time_s;
+        if let Some(gpu_time) = gpu_time {
+            self.gpu_time_sum_s += gpu_time.as_secs_f32();
+            self.sample_count += 1;
+        }
+        if self.elapsed_s < ADJUST_INTERVAL_S {
+            return None;
+        }
+
+        let average_s = self.gpu_time_sum_s / self.sample_count.max(1) as f32;
+        let has_samples = self.sample_count > 0;
+        self.reset();
+        if !has_samples {
+            return None;
+        }
+
+        let new_scale = _adjusted_scale(render_scale, average_s, self.target_frame_time_s, max_scale);
+        (new_scale != render_scale).then_some(new_scale)
+    }
+
+    fn reset(&mut self) {
+        self.elapsed_s = 0.0;
+        self.gpu_time_sum_s = 0.0;
+        self.sample_count = 0;
+    }
+}
+
+// The GPU time is assumed to grow with the pixel count, i.e. the square of the scale. Scaling up aims at the middle
+// of the hysteresis band rather than the target itself.
+fn _adjusted_scale(render_scale: f32, gpu_time_s: f32, target_s: f32, max_scale: f32) -> f32 {
+    let aim_s = if gpu_time_s > target_s {
+        target_s
+    } else if gpu_time_s < target_s * (1.0 - HYSTERESIS) {
+        target_s * (1.0 - HYSTERESIS / 2.0)
+    } else {
+        return render_scale;
+    };
+
+    let ideal = render_scale * (aim_s / gpu_time_s.max(f32::EPSILON)).sqrt();
+    let stepped = ideal.clamp(render_scale - MAX_SCALE_STEP, render_scale + MAX_SCALE_STEP);
+    let rounded = (stepped / SCALE_INCREMENT).round() * SCALE_INCREMENT;
+
+    rounded.clamp(MIN_RENDER_SCALE, max_scale.clamp(MIN_RENDER_SCALE, MAX_RENDER_SCALE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dynamic_resolution() {
+        let mut dynamic_resolution = DynamicResolution::new();
+        let frame = |dynamic_resolution: &mut DynamicResolution, gpu_time_ms: u64, render_scale: f32| {
+            dynamic_resolution.update(0.1, Some(Duration::from_millis(gpu_time_ms)), render_scale, 1.0)
+        };
+        assert_eq!(frame(&mut dynamic_resolution, 40, 1.0), None);
+
+        dynamic_resolution.set_target_frame_time(0.010);
+        // Only once per interval
+        for _ in 0..9 {
+            assert_eq!(frame(&mut dynamic_resolution, 20, 1.0), None);
+        }
+        // Twice the target, half the pixels
+        let scale = frame(&mut dynamic_resolution, 20, 1.0).unwrap();
+        assert!((scale - 0.75).abs() < 1.0e-5, "{}", scale);
+
+        // Within the hysteresis band or at the maximum
+        for _ in 0..10 {
+            assert_eq!(frame(&mut dynamic_resolution, 9, 0.75), None);
+        }
+        for _ in 0..10 {
+            assert_eq!(frame(&mut dynamic_resolution, 2, 1.0), None);
+        }
+
+        let scale = (0..10).find_map(|_| frame(&mut dynamic_resolution, 7, 0.75)).unwrap();
+        assert!((scale - 0.85).abs() < 1.0e-5, "{}", scale);
+        assert_eq!(_adjusted_scale(0.5, 0.1, 0.01, 1.0), MIN_RENDER_SCALE);
+    }
+}
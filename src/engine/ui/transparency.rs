@@ -0,0 +1,64 @@
+use std::path::Path;
+
+use cgmath::{Vector2, Vector4};
+
+use crate::engine::datatypes::{Mesh, PosSizeColor2dPushConstant, TexturedVertex, ViewProjectionUniform};
+use crate::engine::mesh::PredefinedMesh::TexturedQuad;
+use crate::engine::mesh::{MeshHandle, MeshManager};
+use crate::renderer::context::{Context, Frame};
+use crate::renderer::types::{
+    DrawCommand, PipelineConfiguration, PipelineHandle, RenderPassHandle, SamplerConfiguration, TransparencyTargets,
+    UniformHandle,
+};
+
+// Blends the result of a transparency pass over the opaque image, usually drawn right after the quad showing the
+// texture of the depth source pass.
+pub struct TransparencyCompositor {
+    pipeline: PipelineHandle,
+    push_constant_buf: PosSizeColor2dPushConstant,
+    mesh: Mesh,
+}
+
+impl TransparencyCompositor {
+    pub fn new(
+        context: &mut Context,
+        render_pass: RenderPassHandle,
+        vp_uniform: UniformHandle<ViewProjectionUniform>,
+        mesh_manager: &MeshManager,
+        targets: TransparencyTargets,
+    ) -> Self {
+        let mesh = *mesh_manager.get_mesh(TexturedQuad as MeshHandle);
+        let sampler = context.add_sampler(SamplerConfiguration::new());
+        let pipeline_config = PipelineConfiguration::builder()
+            .with_name("transparency composite")
+            .with_vertex_shader_file(Path::new("./resources/shaders/2d_texture_push_vert.spv"))
+            .with_fragment_shader_file(Path::new("./resources/shaders/oit_composite_frag.spv"))
+            .with_vertex_uniform(0, vp_uniform)
+            .with_push_constant::<PosSizeColor2dPushConstant>()
+            .with_alpha_blending()
+            .add_texture(1, targets.accumulation, sampler)
+            .add_texture(2, targets.revealage, sampler)
+            .build()
+            .expect("Invalid pipeline configuration!");
+
+        let pipeline = context.add_pipeline::<TexturedVertex>(render_pass, pipeline_config);
+
+        TransparencyCompositor {
+            pipeline,
+            push_constant_buf: PosSizeColor2dPushConstant::default(),
+            mesh,
+        }
+    }
+
+    pub fn set(&mut self, position: Vector2<f32>, size: Vector2<f32>, color: Vector4<f32>) {
+        self.push_constant_buf = PosSizeColor2dPushConstant::new(position, size, color);
+    }
+
+    pub fn draw(&mut self, frame: &mut Frame) {
+        frame.add_draw_command(DrawCommand::new_buffered(
+            self.pipeline,
+            &self.push_constant_buf,
+            self.mesh,
+        ));
+    }
+}
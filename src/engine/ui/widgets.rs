@@ -1,60 +1,92 @@
 use std::path::Path;
+#[cfg(feature = "console")]
 use crate::engine::console::Console;
-use crate::engine::datatypes::{InstancedCharacter, InstancedQuad, Mesh, PosSizeColor2dPushConstant, TexturedVertex, WindowExtent};
+use crate::engine::math::color::Color;
+use crate::engine::datatypes::{InstancedCharacter, Mesh, PosSizeColor2dPushConstant, FULL_UV_RECT, TexturedVertex, ViewProjectionUniform, WindowExtent};
 use crate::engine::stats;
-use crate::engine::ui::colors::{COLOR_BLACK, COLOR_INPUT_TEXT, COLOR_TEXT, COLOR_TEXT_CVAR, COLOR_TEXT_DEBUG, COLOR_TEXT_ERROR, COLOR_TEXT_INFO, COLOR_TEXT_KHRONOS, COLOR_WHITE};
-use crate::engine::ui::draw::{draw_quad, draw_text, draw_text_shadowed};
-use crate::log::logger;
-use crate::log::logger::{MessageLevel};
-use crate::renderer::context::Context;
+use crate::engine::ui::colors::{COLOR_BLACK, COLOR_WHITE};
+use crate::engine::ui::draw::{draw_text, draw_text_shadowed, text_width};
+#[cfg(feature = "console")]
+use crate::engine::datatypes::InstancedQuad;
+#[cfg(feature = "console")]
+use crate::engine::ui::colors::{COLOR_INPUT_TEXT, COLOR_TEXT, COLOR_TEXT_CVAR, COLOR_TEXT_DEBUG, COLOR_TEXT_ERROR, COLOR_TEXT_INFO, COLOR_TEXT_KHRONOS};
+#[cfg(feature = "console")]
+use crate::engine::ui::draw::draw_quad;
+#[cfg(feature = "console")]
+use crate::log::logger::MessageLevel;
+use crate::renderer::context::{Context, Frame};
 use crate::renderer::types::{BufferObjectHandle, DrawCommand, PipelineConfiguration, PipelineHandle, RenderPassHandle, SamplerHandle, SWAPCHAIN_PASS, TextureHandle, UniformHandle};
 use crate::ENGINE_VERSION;
 
 use cgmath::{Vector2, Vector4};
-use std::ptr;
 use crate::engine::mesh::{MeshHandle, MeshManager};
 use crate::engine::mesh::PredefinedMesh::TexturedQuad;
-use crate::util::file;
 
 // Console
+#[cfg(feature = "console")]
 const BORDER_OFFSET: u32 = 4;
+#[cfg(feature = "console")]
 const CONSOLE_HEIGHT_FACTOR: f32 = 0.75;
+#[cfg(feature = "console")]
 const TEXT_SIZE_PX: u32 = 16;
+#[cfg(feature = "console")]
 const LINE_SPACING: u32 = 2;
+#[cfg(feature = "console")]
 const INPUT_BOX_OFFSET: u32 = 2;
+// Room for the longest message prefix and a space
+#[cfg(feature = "console")]
+const PREFIX_COLUMNS: u32 = 8;
+
+// Characters per line of the console history, longer messages are wrapped
+#[cfg(feature = "console")]
+pub fn console_history_columns(extent: WindowExtent) -> usize {
+    let columns = extent.width.saturating_sub(2 * BORDER_OFFSET) / TEXT_SIZE_PX;
+    columns.saturating_sub(PREFIX_COLUMNS).max(1) as usize
+}
 
 pub struct TexturedQuadRenderer {
     pipeline: PipelineHandle,
     push_constant_buf: PosSizeColor2dPushConstant,
+    uv_rect: Vector4<f32>,
     mesh: Mesh,
 }
 
 impl TexturedQuadRenderer {
-    pub fn new(context: &mut Context, vp_uniform: UniformHandle, mesh_manager: &MeshManager, texture: TextureHandle, sampler: SamplerHandle) -> Self {
+    pub fn new(context: &mut Context, vp_uniform: UniformHandle<ViewProjectionUniform>, mesh_manager: &MeshManager, texture: TextureHandle, sampler: SamplerHandle) -> Self {
         let mesh = *mesh_manager.get_mesh(TexturedQuad as MeshHandle);
         let textured_quad_pipeline_config = PipelineConfiguration::builder()
-            .with_vertex_shader(file::read_file(Path::new("./resources/shaders/2d_texture_push_vert.spv")))
-            .with_fragment_shader(file::read_file(Path::new("./resources/shaders/2d_texture_ssbo_frag.spv")))
+            .with_name("textured quad")
+            .with_vertex_shader_file(Path::new("./resources/shaders/2d_texture_push_vert.spv"))
+            .with_fragment_shader_file(Path::new("./resources/shaders/2d_texture_ssbo_frag.spv"))
             .with_vertex_uniform(0, vp_uniform)
             .with_push_constant::<PosSizeColor2dPushConstant>()
             .add_texture(1, texture, sampler)
-            .build();
+            .build()
+            .expect("Invalid pipeline configuration!");
 
         let pipeline = context.add_pipeline::<TexturedVertex>(SWAPCHAIN_PASS, textured_quad_pipeline_config);
 
         TexturedQuadRenderer {
             pipeline,
             push_constant_buf: PosSizeColor2dPushConstant::default(),
+            uv_rect: FULL_UV_RECT,
             mesh,
         }
     }
 
-    pub fn set(&mut self, position: Vector2<f32>, size: Vector2<f32>, color: Vector4<f32>) {
-        self.push_constant_buf = PosSizeColor2dPushConstant::new(position, size, color);
+    pub fn set(&mut self, position: Vector2<f32>, size: Vector2<f32>, color: Color) {
+        self.push_constant_buf =
+            PosSizeColor2dPushConstant::new(position, size, color.to_linear()).with_uv_rect(self.uv_rect);
+    }
+
+    // Part of the texture to draw, e.g. Flipbook::uv_rect
+    pub fn set_uv_rect(&mut self, uv_rect: Vector4<f32>) {
+        self.uv_rect = uv_rect;
+        self.push_constant_buf = self.push_constant_buf.with_uv_rect(uv_rect);
     }
 
-    pub fn draw(&mut self, context: &mut Context) {
-        context.add_draw_command(DrawCommand::new_buffered(
+    pub fn draw(&mut self, frame: &mut Frame) {
+        frame.add_draw_command(DrawCommand::new_buffered(
             self.pipeline,
             &self.push_constant_buf,
             self.mesh,
@@ -65,32 +97,34 @@ impl TexturedQuadRenderer {
 
 pub struct TextRenderer {
     pipeline: PipelineHandle,
-    sbo: BufferObjectHandle,
+    sbo: BufferObjectHandle<InstancedCharacter>,
     mesh: Mesh,
 
     position: Vector2<u32>,
     size: u32,
-    color: Vector4<f32>,
+    color: Color,
 }
 
 impl TextRenderer {
     pub fn new(
         context: &mut Context,
         render_pass: RenderPassHandle,
-        vp_uniform: BufferObjectHandle,
+        vp_uniform: UniformHandle<ViewProjectionUniform>,
         mesh: Mesh,
         font_texture: TextureHandle,
         sampler: SamplerHandle) -> Self {
         let text_sbo = context.create_storage_buffer::<InstancedCharacter>(10);
 
         let text_pipeline_config = PipelineConfiguration::builder()
-            .with_vertex_shader(file::read_file(Path::new("./resources/shaders/2d_text_ssbo_vert.spv")))
-            .with_fragment_shader(file::read_file(Path::new("./resources/shaders/2d_texture_ssbo_frag.spv")))
+            .with_name("text")
+            .with_vertex_shader_file(Path::new("./resources/shaders/2d_text_ssbo_vert.spv"))
+            .with_fragment_shader_file(Path::new("./resources/shaders/2d_texture_ssbo_frag.spv"))
             .with_vertex_uniform(0, vp_uniform)
             .with_storage_buffer_object(2, text_sbo)
             .with_alpha_blending()
             .add_texture(1, font_texture, sampler)
-            .build();
+            .build()
+            .expect("Invalid pipeline configuration!");
         let text_pipeline = context.add_pipeline::<TexturedVertex>(render_pass, text_pipeline_config);
 
         TextRenderer {
@@ -99,14 +133,14 @@ impl TextRenderer {
             mesh,
             position: Vector2::new(0, 0),
             size: 16,
-            color: Vector4::new(1.0, 1.0, 1.0, 1.0),
+            color: COLOR_WHITE,
         }
     }
-    pub fn draw(&mut self, context: &mut Context, text: &str) {
-        context.reset_buffer_object(self.sbo);
-        let n = draw_text(context, self.sbo, text, self.position, self.size, self.color);
+    pub fn draw(&mut self, frame: &mut Frame, text: &str) {
+        frame.reset_buffer_object(self.sbo);
+        let n = draw_text(frame, self.sbo, text, self.position, self.size, self.color);
 
-        context.add_draw_command(DrawCommand::new_buffered_instanced_nopush(
+        frame.add_draw_command(DrawCommand::new_buffered_instanced_nopush(
             self.pipeline,
             self.mesh,
             n,
@@ -122,23 +156,25 @@ impl TextRenderer {
         self.size = size;
     }
 
-    pub fn set_color(&mut self, color: Vector4<f32>) {
+    pub fn set_color(&mut self, color: Color) {
         self.color = color;
     }
 }
 
+#[cfg(feature = "console")]
 pub struct ConsoleRenderer {
     extent: WindowExtent,
-    text_sbo: BufferObjectHandle,
-    quad_sbo: BufferObjectHandle,
+    text_sbo: BufferObjectHandle<InstancedCharacter>,
+    quad_sbo: BufferObjectHandle<InstancedQuad>,
     text_pipeline: PipelineHandle,
     quad_pipeline: PipelineHandle,
     mesh: Mesh,
 }
 
+#[cfg(feature = "console")]
 impl ConsoleRenderer {
     pub fn new(context: &mut Context,
-               vp_uniform: BufferObjectHandle,
+               vp_uniform: UniformHandle<ViewProjectionUniform>,
                mesh: Mesh,
                extent: WindowExtent,
                font_texture: TextureHandle,
@@ -147,21 +183,25 @@ impl ConsoleRenderer {
         let quad_sbo = context.create_storage_buffer::<InstancedQuad>(10);
 
         let text_pipeline_config = PipelineConfiguration::builder()
-            .with_vertex_shader(file::read_file(Path::new("./resources/shaders/2d_text_ssbo_vert.spv")))
-            .with_fragment_shader(file::read_file(Path::new("./resources/shaders/2d_texture_ssbo_frag.spv")))
+            .with_name("console text")
+            .with_vertex_shader_file(Path::new("./resources/shaders/2d_text_ssbo_vert.spv"))
+            .with_fragment_shader_file(Path::new("./resources/shaders/2d_texture_ssbo_frag.spv"))
             .with_vertex_uniform(0, vp_uniform)
             .with_storage_buffer_object(2, text_sbo)
             .with_alpha_blending()
             .add_texture(1, font_texture, sampler)
-            .build();
+            .build()
+            .expect("Invalid pipeline configuration!");
         let text_pipeline = context.add_pipeline::<TexturedVertex>(SWAPCHAIN_PASS, text_pipeline_config);
         let quad_pipeline_config = PipelineConfiguration::builder()
-            .with_vertex_shader(file::read_file(Path::new("./resources/shaders/2d_flat_ssbo_vert.spv")))
-            .with_fragment_shader(file::read_file(Path::new("./resources/shaders/2d_flat_ssbo_frag.spv")))
+            .with_name("console quad")
+            .with_vertex_shader_file(Path::new("./resources/shaders/2d_flat_ssbo_vert.spv"))
+            .with_fragment_shader_file(Path::new("./resources/shaders/2d_flat_ssbo_frag.spv"))
             .with_vertex_uniform(0, vp_uniform)
             .with_storage_buffer_object(2, quad_sbo)
             .with_alpha_blending()
-            .build();
+            .build()
+            .expect("Invalid pipeline configuration!");
         let quad_pipeline = context.add_pipeline::<TexturedVertex>(SWAPCHAIN_PASS, quad_pipeline_config);
 
         ConsoleRenderer { extent, text_sbo, quad_sbo, text_pipeline, quad_pipeline, mesh }
@@ -173,10 +213,10 @@ impl ConsoleRenderer {
 
     pub fn draw(
         &mut self,
-        context: &mut Context,
+        frame: &mut Frame,
         console: &Console) {
-        context.reset_buffer_object(self.text_sbo);
-        context.reset_buffer_object(self.quad_sbo);
+        frame.reset_buffer_object(self.text_sbo);
+        frame.reset_buffer_object(self.quad_sbo);
 
         let height = (self.extent.height as f32 * CONSOLE_HEIGHT_FACTOR) as u32;
         let offset = (console.get_current_y_offset() * height as f32) as u32;
@@ -186,17 +226,16 @@ impl ConsoleRenderer {
 
         // Draw console bg
         quad_instance_count += draw_quad(
-            context,
+            frame,
             self.quad_sbo,
             Vector2::new(0, self.extent.height - height + offset),
             Vector2::new(self.extent.width, height),
-            //Vector4::new(0.02, 0.02, 0.02, 0.95),
-            Vector4::new(0.02, 0.02, 0.02, 0.95),
+            console.get_background_color(),
         );
 
         // Draw prompt
         text_instance_count += draw_text(
-            context,
+            frame,
             self.text_sbo,
             &format!("> {}", console.get_current_input()),
             Vector2::new(BORDER_OFFSET, self.extent.height - height + offset + BORDER_OFFSET),
@@ -207,7 +246,7 @@ impl ConsoleRenderer {
         // Draw caret
         if console.is_caret_visible() && console.is_active() {
             quad_instance_count += draw_quad(
-                context,
+                frame,
                 self.quad_sbo,
                 Vector2::new(
                     BORDER_OFFSET + console.get_input_index() * TEXT_SIZE_PX + (2 * TEXT_SIZE_PX),
@@ -219,16 +258,16 @@ impl ConsoleRenderer {
         }
 
         // Draw history
-        text_instance_count += self._draw_console_history(context, self.text_sbo, console, height, offset);
+        text_instance_count += self._draw_console_history(frame, self.text_sbo, console, height, offset);
 
-        context.add_draw_command(DrawCommand::new_buffered_instanced_nopush(
+        frame.add_draw_command(DrawCommand::new_buffered_instanced_nopush(
             self.quad_pipeline,
             self.mesh,
             quad_instance_count,
             0,
         ));
 
-        context.add_draw_command(DrawCommand::new_buffered_instanced_nopush(
+        frame.add_draw_command(DrawCommand::new_buffered_instanced_nopush(
             self.text_pipeline,
             self.mesh,
             text_instance_count,
@@ -239,26 +278,17 @@ impl ConsoleRenderer {
     fn _draw_console_history(
         &mut self,
         context: &mut Context,
-        storage_buffer: BufferObjectHandle,
+        storage_buffer: BufferObjectHandle<InstancedCharacter>,
         console: &Console,
         height: u32,
         offset: u32,
     ) -> u32 {
         let history_count_visible = height / (TEXT_SIZE_PX + LINE_SPACING) - 1;
 
-        let mut __history_len = 0;
-        let mut __history_ptr = ptr::null();
-
         let mut instance_count = 0;
 
-        // Hack. To allow logging to occur when building the history log render data
-        {
-            let logger_mutex = logger::get();
-            let history = logger_mutex.get_history(history_count_visible as usize, console.get_scroll());
-            __history_len = history.len();
-            __history_ptr = history.as_ptr();
-        }
-        let history = unsafe { std::slice::from_raw_parts(__history_ptr, __history_len) };
+        // Owned lines so that logging can occur when building the history log render data
+        let history = console.get_history_lines(history_count_visible as usize);
 
         for (i, line) in history.iter().rev().enumerate() {
             let (prefix_text, prefix_color) = match &line.level {
@@ -271,33 +301,27 @@ impl ConsoleRenderer {
                 _ => ("---", COLOR_TEXT),
             };
 
+            let y = self.extent.height - height
+                + offset
+                + BORDER_OFFSET
+                + INPUT_BOX_OFFSET
+                + ((i + 1) as u32 * (TEXT_SIZE_PX + LINE_SPACING));
+            // Continued lines are indented like the first line of their message
+            if !line.is_continuation {
+                instance_count += draw_text(
+                    context,
+                    storage_buffer,
+                    prefix_text,
+                    Vector2::new(BORDER_OFFSET, y),
+                    TEXT_SIZE_PX,
+                    prefix_color,
+                );
+            }
             instance_count += draw_text(
                 context,
                 storage_buffer,
-                prefix_text,
-                Vector2::new(
-                    BORDER_OFFSET,
-                    self.extent.height - height
-                        + offset
-                        + BORDER_OFFSET
-                        + INPUT_BOX_OFFSET
-                        + ((i + 1) as u32 * (TEXT_SIZE_PX + LINE_SPACING)),
-                ),
-                TEXT_SIZE_PX,
-                prefix_color,
-            );
-            instance_count += draw_text(
-                context,
-                storage_buffer,
-                &line.message,
-                Vector2::new(
-                    BORDER_OFFSET + ((1 + prefix_text.len()) as u32 * TEXT_SIZE_PX),
-                    self.extent.height - height
-                        + offset
-                        + BORDER_OFFSET
-                        + INPUT_BOX_OFFSET
-                        + ((i + 1) as u32 * (TEXT_SIZE_PX + LINE_SPACING)),
-                ),
+                &line.text,
+                Vector2::new(BORDER_OFFSET + text_width(prefix_text, TEXT_SIZE_PX) + TEXT_SIZE_PX, y),
                 TEXT_SIZE_PX,
                 COLOR_TEXT,
             );
@@ -308,17 +332,19 @@ impl ConsoleRenderer {
 
 pub struct TextOverlayRenderer {
     extent: WindowExtent,
-    text_sbo: BufferObjectHandle,
+    text_sbo: BufferObjectHandle<InstancedCharacter>,
     text_pipeline: PipelineHandle,
     mesh: Mesh,
 
     renderstats_active: bool,
     version_active: bool,
+    // Cvars added with the watch console command, drawn below the version
+    watch_lines: Vec<String>,
 }
 
 impl TextOverlayRenderer {
     pub fn new(context: &mut Context,
-               vp_uniform: BufferObjectHandle,
+               vp_uniform: UniformHandle<ViewProjectionUniform>,
                mesh: Mesh,
                extent: WindowExtent,
                font_texture: TextureHandle,
@@ -326,33 +352,48 @@ impl TextOverlayRenderer {
         let text_sbo = context.create_storage_buffer::<InstancedCharacter>(500);
 
         let text_pipeline_config = PipelineConfiguration::builder()
-            .with_vertex_shader(file::read_file(Path::new("./resources/shaders/2d_text_ssbo_vert.spv")))
-            .with_fragment_shader(file::read_file(Path::new("./resources/shaders/2d_texture_ssbo_frag.spv")))
+            .with_name("text overlay")
+            .with_vertex_shader_file(Path::new("./resources/shaders/2d_text_ssbo_vert.spv"))
+            .with_fragment_shader_file(Path::new("./resources/shaders/2d_texture_ssbo_frag.spv"))
             .with_vertex_uniform(0, vp_uniform)
             .with_storage_buffer_object(2, text_sbo)
             .with_alpha_blending()
             .add_texture(1, font_texture, sampler)
-            .build();
+            .build()
+            .expect("Invalid pipeline configuration!");
         let text_pipeline = context.add_pipeline::<TexturedVertex>(SWAPCHAIN_PASS, text_pipeline_config);
 
-        TextOverlayRenderer { extent, text_sbo, text_pipeline, mesh, renderstats_active: true, version_active: true }
+        TextOverlayRenderer {
+            extent,
+            text_sbo,
+            text_pipeline,
+            mesh,
+            renderstats_active: true,
+            version_active: true,
+            watch_lines: Vec::new(),
+        }
     }
 
     pub fn handle_window_resize(&mut self, new_extent: WindowExtent) {
         self.extent = new_extent;
     }
 
-    pub fn draw(&mut self, context: &mut Context) {
-        context.reset_buffer_object(self.text_sbo);
+    pub fn set_watch_lines(&mut self, watch_lines: Vec<String>) {
+        self.watch_lines = watch_lines;
+    }
+
+    pub fn draw(&mut self, frame: &mut Frame) {
+        frame.reset_buffer_object(self.text_sbo);
 
         let mut foreground_instance_count = 0;
         if self.renderstats_active {
-            foreground_instance_count += self.draw_renderstats(context, self.text_sbo);
+            foreground_instance_count += self.draw_renderstats(frame, self.text_sbo);
         }
         if self.version_active {
-            foreground_instance_count += self.draw_engine_info(context, self.text_sbo);
+            foreground_instance_count += self.draw_engine_info(frame, self.text_sbo);
         }
-        context.add_draw_command(DrawCommand::new_buffered_instanced_nopush(
+        foreground_instance_count += self.draw_watches(frame, self.text_sbo);
+        frame.add_draw_command(DrawCommand::new_buffered_instanced_nopush(
             self.text_pipeline,
             self.mesh,
             foreground_instance_count,
@@ -360,7 +401,7 @@ impl TextOverlayRenderer {
         ));
     }
 
-    fn draw_engine_info(&mut self, context: &mut Context, text_sbo: BufferObjectHandle) -> u32 {
+    fn draw_engine_info(&mut self, context: &mut Context, text_sbo: BufferObjectHandle<InstancedCharacter>) -> u32 {
         let mut instance_count = 0;
 
         instance_count += draw_text_shadowed(
@@ -375,7 +416,27 @@ impl TextOverlayRenderer {
         instance_count
     }
 
-    fn draw_renderstats(&mut self, context: &mut Context, text_sbo: BufferObjectHandle) -> u32 {
+    // Right aligned, one line per watched cvar
+    fn draw_watches(&mut self, context: &mut Context, text_sbo: BufferObjectHandle<InstancedCharacter>) -> u32 {
+        let mut instance_count = 0;
+        for (i, line) in self.watch_lines.iter().enumerate() {
+            instance_count += draw_text_shadowed(
+                context,
+                text_sbo,
+                line,
+                Vector2::new(
+                    self.extent.width.saturating_sub(8 + text_width(line, 16)),
+                    self.extent.height.saturating_sub(24 + 18 * (i as u32 + 2)),
+                ),
+                16,
+                COLOR_WHITE,
+                COLOR_BLACK,
+            );
+        }
+        instance_count
+    }
+
+    fn draw_renderstats(&mut self, context: &mut Context, text_sbo: BufferObjectHandle<InstancedCharacter>) -> u32 {
         let position = Vector2::new(8, self.extent.height - 24);
         let renderstats = stats::get();
 
@@ -399,11 +460,17 @@ impl TextOverlayRenderer {
             COLOR_WHITE,
             COLOR_BLACK,
         );
+        let frame_pacing = renderstats.get_frame_pacing();
         instance_count += draw_text_shadowed(
             context,
             text_sbo,
-            &format!("Draw count: {}", renderstats.get_render_stats().draw_command_count),
-            position - Vector2::new(0, 18 * 3),
+            &format!(
+                "p95: {0:.2} ms  p99: {1:.2} ms  1% low: {2:.0} FPS",
+                frame_pacing.p95_frame_time * 1000f32,
+                frame_pacing.p99_frame_time * 1000f32,
+                frame_pacing.low_1_percent_fps
+            ),
+            position - Vector2::new(0, 18 * 2),
             16,
             COLOR_WHITE,
             COLOR_BLACK,
@@ -411,12 +478,25 @@ impl TextOverlayRenderer {
         instance_count += draw_text_shadowed(
             context,
             text_sbo,
-            &format!("Triangle count: {}", renderstats.get_render_stats().triangle_count),
+            &format!(
+                "Draw count: {} ({} merged)",
+                renderstats.get_render_stats().draw_command_count,
+                renderstats.get_render_stats().merged_draw_command_count
+            ),
             position - Vector2::new(0, 18 * 4),
             16,
             COLOR_WHITE,
             COLOR_BLACK,
         );
+        instance_count += draw_text_shadowed(
+            context,
+            text_sbo,
+            &format!("Triangle count: {}", renderstats.get_render_stats().triangle_count),
+            position - Vector2::new(0, 18 * 5),
+            16,
+            COLOR_WHITE,
+            COLOR_BLACK,
+        );
         instance_count += draw_text_shadowed(
             context,
             text_sbo,
@@ -424,7 +504,7 @@ impl TextOverlayRenderer {
                 "TransferCmdBuf: {0:.3} ms",
                 renderstats.get_render_stats().transfer_commands_bake_time.as_micros() as f32 / 1000f32
             ),
-            position - Vector2::new(0, 18 * 6),
+            position - Vector2::new(0, 18 * 7),
             16,
             COLOR_WHITE,
             COLOR_BLACK,
@@ -436,11 +516,46 @@ impl TextOverlayRenderer {
                 "    DrawCmdBuf: {0:.3} ms",
                 renderstats.get_render_stats().draw_commands_bake_time.as_micros() as f32 / 1000f32
             ),
-            position - Vector2::new(0, 18 * 7),
+            position - Vector2::new(0, 18 * 8),
             16,
             COLOR_WHITE,
             COLOR_BLACK,
         );
+        if let Some(gpu_time) = renderstats.get_render_stats().gpu_time {
+            instance_count += draw_text_shadowed(
+                context,
+                text_sbo,
+                &format!("           GPU: {0:.3} ms", gpu_time.as_micros() as f32 / 1000f32),
+                position - Vector2::new(0, 18 * 9),
+                16,
+                COLOR_WHITE,
+                COLOR_BLACK,
+            );
+        }
+        instance_count += draw_text_shadowed(
+            context,
+            text_sbo,
+            &format!(
+                "Descriptor sets: {} ({} pools)",
+                renderstats.get_render_stats().descriptor_set_count,
+                renderstats.get_render_stats().descriptor_pool_count
+            ),
+            position - Vector2::new(0, 18 * 10),
+            16,
+            COLOR_WHITE,
+            COLOR_BLACK,
+        );
+        if let Some((render_scale, dynamic)) = renderstats.get_render_scale() {
+            instance_count += draw_text_shadowed(
+                context,
+                text_sbo,
+                &format!("Render scale: {:.2}{}", render_scale, if dynamic { " (dynamic)" } else { "" }),
+                position - Vector2::new(0, 18 * 12),
+                16,
+                COLOR_WHITE,
+                COLOR_BLACK,
+            );
+        }
 
         instance_count
     }
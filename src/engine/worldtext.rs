@@ -0,0 +1,150 @@
+use cgmath::{Matrix4, Vector2, Vector3, Vector4};
+
+use crate::engine::billboard::BillboardMode;
+use crate::engine::datatypes::{InstancedWorldCharacter, Mesh, TexturedVertex, ViewProjectionUniform};
+use crate::engine::mesh::PredefinedMesh::TexturedQuad;
+use crate::engine::mesh::{MeshHandle, MeshManager};
+use crate::renderer::context::{Context, Frame};
+use crate::renderer::types::{
+    BufferObjectHandle, DrawCommand, PipelineConfiguration, PipelineHandle, RenderPassHandle, SamplerHandle,
+    TextureHandle, UniformHandle,
+};
+use crate::renderer::variant::{ShaderVariants, VariantSelection};
+
+const INITIAL_CAPACITY: usize = 256;
+
+// Text in the scene with the glyphs of the font texture, e.g. nameplates and damage numbers. Text is added every
+// frame and drawn instanced. With depth testing the glyphs are hidden by the geometry in front of them, without
+// they cover everything drawn before them in the pass.
+pub struct WorldTextRenderer {
+    storage_buffer: BufferObjectHandle<InstancedWorldCharacter>,
+    // Indexed like the characters, transform first and then the billboard modes
+    pipelines: [PipelineHandle; 3],
+    mesh: Mesh,
+
+    characters: [Vec<InstancedWorldCharacter>; 3],
+}
+
+impl WorldTextRenderer {
+    pub fn new(
+        context: &mut Context,
+        mesh_manager: &MeshManager,
+        render_pass: RenderPassHandle,
+        camera_uniform: UniformHandle<ViewProjectionUniform>,
+        font_texture: TextureHandle,
+        sampler: SamplerHandle,
+        depth_test: bool,
+    ) -> Self {
+        let mesh = *mesh_manager.get_mesh(TexturedQuad as MeshHandle);
+        let storage_buffer = context.create_storage_buffer::<InstancedWorldCharacter>(INITIAL_CAPACITY);
+
+        let variants = ShaderVariants::new("./resources/shaders/world_text")
+            .with_choice("ORIENTATION", &["TRANSFORM", "SPHERICAL", "CYLINDRICAL"]);
+        let mut pipeline_config = PipelineConfiguration::builder();
+        pipeline_config
+            .with_vertex_uniform(0, camera_uniform)
+            .add_texture(1, font_texture, sampler)
+            .with_storage_buffer_object(2, storage_buffer)
+            .with_alpha_blending();
+        if !depth_test {
+            pipeline_config.without_depth_test();
+        }
+        let variant_pipelines = context
+            .add_pipeline_variants::<TexturedVertex>(render_pass, variants, &mut pipeline_config)
+            .expect("Invalid pipeline configuration!");
+        let pipeline = |orientation: &str| {
+            variant_pipelines
+                .select(&VariantSelection::new().with_choice("ORIENTATION", orientation))
+                .unwrap()
+        };
+
+        WorldTextRenderer {
+            storage_buffer,
+            pipelines: [pipeline("TRANSFORM"), pipeline("SPHERICAL"), pipeline("CYLINDRICAL")],
+            mesh,
+
+            characters: [Vec::new(), Vec::new(), Vec::new()],
+        }
+    }
+
+    // A line of text centered on the origin of the transform in its xy plane, readable from +z. The size is the
+    // height of a glyph in units of the transform.
+    pub fn add(&mut self, text: &str, transform: Matrix4<f32>, size: f32, color: Vector4<f32>) {
+        self.add_characters(0, text, transform, size, color);
+    }
+
+    // A line of text centered on the position and facing the camera, size in world units
+    pub fn add_billboard(
+        &mut self,
+        text: &str,
+        position: Vector3<f32>,
+        mode: BillboardMode,
+        size: f32,
+        color: Vector4<f32>,
+    ) {
+        let index = match mode {
+            BillboardMode::Spherical => 1,
+            BillboardMode::Cylindrical => 2,
+        };
+        self.add_characters(index, text, Matrix4::from_translation(position), size, color);
+    }
+
+    // Draws and clears the text added since the last draw. All orientations share the storage buffer.
+    pub fn draw(&mut self, frame: &mut Frame) {
+        frame.reset_buffer_object(self.storage_buffer);
+
+        let mut instance_start = 0;
+        for (pipeline, characters) in self.pipelines.iter().zip(self.characters.iter_mut()) {
+            let instance_count = characters.len() as u32;
+            for character in characters.drain(..) {
+                frame.push_to_buffer_object(self.storage_buffer, character);
+            }
+
+            if instance_count > 0 {
+                frame.add_draw_command(DrawCommand::new_buffered_instanced_nopush(
+                    *pipeline,
+                    self.mesh,
+                    instance_count,
+                    instance_start,
+                ));
+            }
+            instance_start += instance_count;
+        }
+    }
+
+    fn add_characters(&mut self, index: usize, text: &str, transform: Matrix4<f32>, size: f32, color: Vector4<f32>) {
+        let characters = &mut self.characters[index];
+        for (character, position) in _layout(text, size) {
+            characters.push(InstancedWorldCharacter::new(transform, position, color, character as u32, size));
+        }
+    }
+}
+
+// Centers of the visible glyphs, the font is monospaced with square glyphs
+fn _layout(text: &str, size: f32) -> impl Iterator<Item = (char, Vector2<f32>)> + '_ {
+    let left = -(text.chars().count() as f32 - 1.0) * size / 2.0;
+    text.chars()
+        .enumerate()
+        .filter(|(_, character)| *character != ' ')
+        .map(move |(i, character)| (character, Vector2::new(left + i as f32 * size, 0.0)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn centered_layout() {
+        let glyphs: Vec<(char, Vector2<f32>)> = _layout("ab c", 2.0).collect();
+        assert_eq!(
+            glyphs,
+            vec![
+                ('a', Vector2::new(-3.0, 0.0)),
+                ('b', Vector2::new(-1.0, 0.0)),
+                ('c', Vector2::new(3.0, 0.0)),
+            ]
+        );
+        assert_eq!(_layout("x", 1.0).collect::<Vec<_>>(), vec![('x', Vector2::new(0.0, 0.0))]);
+        assert_eq!(_layout("", 1.0).count(), 0);
+    }
+}
@@ -0,0 +1,84 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use crate::renderer::types::PipelineConfigError;
+
+// Errors of loading files and the resources in them. Loaders return these and the engine logs them and falls back
+// to a default resource, e.g. the magenta fallback shaders and texture.
+#[derive(Debug)]
+pub enum VulkrapError {
+    Io { path: PathBuf, error: io::Error },
+    InvalidShader { path: PathBuf, reason: &'static str },
+    InvalidMesh { path: PathBuf, reason: &'static str },
+    InvalidImage { path: PathBuf, reason: String },
+    Pipeline(PipelineConfigError),
+    Other(&'static str),
+}
+
+impl VulkrapError {
+    pub fn io(path: impl Into<PathBuf>, error: io::Error) -> Self {
+        VulkrapError::Io { path: path.into(), error }
+    }
+}
+
+impl fmt::Display for VulkrapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VulkrapError::Io { path, error } => write!(f, "failed to read {:?}: {}", path, error),
+            VulkrapError::InvalidShader { path, reason } => write!(f, "invalid shader {:?}: {}", path, reason),
+            VulkrapError::InvalidMesh { path, reason } => write!(f, "invalid mesh {:?}: {}", path, reason),
+            VulkrapError::InvalidImage { path, reason } => write!(f, "invalid image {:?}: {}", path, reason),
+            VulkrapError::Pipeline(error) => write!(f, "invalid pipeline configuration: {}", error),
+            VulkrapError::Other(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl std::error::Error for VulkrapError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            VulkrapError::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<PipelineConfigError> for VulkrapError {
+    fn from(error: PipelineConfigError) -> Self {
+        VulkrapError::Pipeline(error)
+    }
+}
+
+impl From<&'static str> for VulkrapError {
+    fn from(reason: &'static str) -> Self {
+        VulkrapError::Other(reason)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::image;
+    use crate::util::file;
+    use std::error::Error;
+    use std::path::Path;
+
+    #[test]
+    fn loader_errors() {
+        let error = file::read_file(Path::new("./resources/missing.bin")).unwrap_err();
+        assert!(matches!(error, VulkrapError::Io { ref path, .. } if path == Path::new("./resources/missing.bin")));
+        assert!(error.to_string().contains("missing.bin"));
+        assert!(error.source().is_some());
+
+        let error = image::load_image(Path::new("./resources/shaders/noise.glsl")).err().unwrap();
+        assert!(matches!(error, VulkrapError::InvalidImage { .. }));
+        let fallback = image::Image::fallback();
+        assert_eq!((fallback.width, fallback.height, fallback.data.len()), (8, 8, 8 * 8 * 4));
+        assert_eq!(fallback.data[..8], [255, 0, 255, 255, 0, 0, 0, 255]);
+
+        assert_eq!(VulkrapError::from("Empty recording").to_string(), "Empty recording");
+        let error = VulkrapError::from(PipelineConfigError::MissingVertexShader);
+        assert_eq!(error.to_string(), "invalid pipeline configuration: missing vertex shader");
+    }
+}
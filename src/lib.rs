@@ -52,11 +52,12 @@ macro_rules! log_error {
 
 use winit::dpi::PhysicalSize;
 use winit::event_loop::EventLoop;
-use crate::engine::cvars::{ConfigVariables};
+use crate::engine::cvars::{ConfigVariables, WINDOW_TITLE};
 use crate::engine::runtime::{Runtime, VulkrapApplication, VulkrapApplicationFactory};
 
 pub mod renderer;
 pub mod engine;
+pub mod error;
 pub mod util;
 pub mod log;
 
@@ -73,6 +74,7 @@ pub fn vulkrap_start<T: VulkrapApplication + 'static>(window_title: &'static str
                                                       window_height: u32,
                                                       app_factory: VulkrapApplicationFactory<T>) {
 
+    log::crash::install_panic_hook();
     log_info!("vulkrap init...");
 
     let event_loop = EventLoop::new();
@@ -80,7 +82,9 @@ pub fn vulkrap_start<T: VulkrapApplication + 'static>(window_title: &'static str
     window.set_cursor_visible(false);
     window.set_min_inner_size(Some(PhysicalSize::new(MIN_WIDTH, MIN_HEIGHT)));
 
-    let config = ConfigVariables::new();
+    let mut config = ConfigVariables::new();
+    config.set(WINDOW_TITLE, window_title.to_string());
+    config.clear_dirty();
 
     let vulkrap_runtime = Runtime::new(&window, config, app_factory);
 
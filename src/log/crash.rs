@@ -0,0 +1,89 @@
+use std::backtrace::Backtrace;
+use std::fs::File;
+use std::io::Write;
+use std::panic;
+use std::panic::PanicHookInfo;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::log::logger;
+
+lazy_static! {
+    static ref CRASH_INFO: Mutex<CrashInfo> = Mutex::new(CrashInfo::default());
+}
+
+#[derive(Default)]
+struct CrashInfo {
+    device: Vec<String>,
+    cvars: Vec<String>,
+}
+
+pub fn set_device_info(lines: Vec<String>) {
+    _crash_info().device = lines;
+}
+
+pub fn set_cvars(lines: Vec<String>) {
+    _crash_info().cvars = lines;
+}
+
+// Writes a crash_<unix time>.txt report with the log history, cvars, device info and a backtrace before aborting
+pub fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+
+    panic::set_hook(Box::new(move |panic_info| {
+        default_hook(panic_info);
+
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs());
+        let path = format!("crash_{}.txt", timestamp);
+        match _write_report(&path, panic_info) {
+            Ok(()) => eprintln!("Crash report written to {}", path),
+            Err(err) => eprintln!("Failed to write crash report {}: {}", path, err),
+        }
+
+        std::process::abort();
+    }));
+}
+
+fn _write_report(path: &str, panic_info: &PanicHookInfo) -> std::io::Result<()> {
+    let mut file = File::create(path)?;
+
+    writeln!(file, "{} {} crashed", crate::ENGINE_NAME, _engine_version())?;
+    writeln!(file, "{}", panic_info)?;
+
+    {
+        let crash_info = _crash_info();
+        writeln!(file, "\n== Device ==")?;
+        for line in crash_info.device.iter() {
+            writeln!(file, "{}", line)?;
+        }
+        writeln!(file, "\n== Cvars ==")?;
+        for line in crash_info.cvars.iter() {
+            writeln!(file, "{}", line)?;
+        }
+    }
+
+    writeln!(file, "\n== Backtrace ==")?;
+    writeln!(file, "{}", Backtrace::force_capture())?;
+
+    writeln!(file, "\n== Log ==")?;
+    match logger::try_dump_history() {
+        Some(lines) => {
+            for line in lines {
+                writeln!(file, "{}", line)?;
+            }
+        }
+        None => writeln!(file, "Logger is locked by the panicking thread")?,
+    }
+
+    Ok(())
+}
+
+fn _engine_version() -> String {
+    let (major, minor, patch) = crate::ENGINE_VERSION;
+    format!("{}.{}.{}", major, minor, patch)
+}
+
+// Recovers from poisoning, a panic while holding the lock must not prevent the report
+fn _crash_info() -> std::sync::MutexGuard<'static, CrashInfo> {
+    CRASH_INFO.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
@@ -1,42 +1,87 @@
-use std::sync::{Mutex, MutexGuard};
+use std::collections::VecDeque;
+use std::sync::{Mutex, MutexGuard, TryLockError};
 
-const CAPACITY: usize = 10000;
+pub const DEFAULT_CAPACITY: usize = 10000;
 
 lazy_static! {
     static ref LOGGER: Mutex<Logger> = Mutex::new(Logger::new());
 }
 
 pub struct Logger {
-    history: Vec<LogMessage>,
+    history: VecDeque<LogMessage>,
+    capacity: usize,
 }
 
 impl Logger {
     pub fn new() -> Logger {
+        Logger::with_capacity(DEFAULT_CAPACITY)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Logger {
         Logger {
-            history: Vec::with_capacity(CAPACITY),
+            history: VecDeque::with_capacity(capacity),
+            capacity,
         }
     }
 
+    // Repeats of the last message only increase its count. The oldest messages are dropped once the capacity
+    // is reached.
+    fn add(&mut self, message: LogMessage) {
+        if let Some(last_message) = self.history.back_mut() {
+            if last_message.level == message.level && last_message.message == message.message {
+                last_message.repeat_count += 1;
+                return;
+            }
+        }
+        if self.history.len() >= self.capacity {
+            self.history.pop_front();
+        }
+        self.history.push_back(message);
+    }
+
     fn add_once(&mut self, message: LogMessage) {
-        if let Some(last_message) = self.history.last() {
+        if let Some(last_message) = self.history.back() {
             if last_message.message.eq(&message.message) {
                 return;
             }
         }
-        self.history.push(message);
+        self.add(message);
     }
 
-    pub fn get_history(&self, line_count: usize, scroll: usize) -> &[LogMessage] {
-        let end = if self.history.len() >= scroll {
-            self.history.len() - scroll
-        } else {
-            self.history.len()
-        };
+    pub fn set_capacity(&mut self, capacity: usize) {
+        let capacity = capacity.max(1);
+        if self.history.len() > capacity {
+            self.history.drain(..self.history.len() - capacity);
+        }
+        self.history.shrink_to(capacity);
+        self.capacity = capacity;
+    }
 
-        let start = self.history.len() - line_count.min(self.history.len());
-        let start_offset = if start >= scroll { start - scroll } else { 0 };
+    pub fn get_capacity(&self) -> usize {
+        self.capacity
+    }
+
+    // Returns the last line_count messages containing the filter, oldest first, skipping the newest scroll ones
+    pub fn get_history(&self, line_count: usize, scroll: usize, filter: Option<&str>) -> Vec<&LogMessage> {
+        let mut history: Vec<&LogMessage> = self
+            .history
+            .iter()
+            .rev()
+            .filter(|log_message| filter.is_none_or(|filter| log_message.message.contains(filter)))
+            .skip(scroll)
+            .take(line_count)
+            .collect();
+        history.reverse();
+
+        history
+    }
+
+    pub fn count_matching(&self, filter: &str) -> usize {
+        self.history.iter().filter(|log_message| log_message.message.contains(filter)).count()
+    }
 
-        &self.history[start_offset..end]
+    pub fn clear(&mut self) {
+        self.history.clear();
     }
 }
 
@@ -46,16 +91,27 @@ impl Default for Logger {
     }
 }
 
+#[derive(Clone)]
 pub struct LogMessage {
     pub level: MessageLevel,
     pub message: String,
+    pub repeat_count: u32,
 }
 
 impl LogMessage {
     pub fn new(level: MessageLevel, message: String) -> Self {
         LogMessage {
             level,
-            message
+            message,
+            repeat_count: 1,
+        }
+    }
+
+    pub fn get_text(&self) -> String {
+        if self.repeat_count > 1 {
+            format!("{} (x{})", self.message, self.repeat_count)
+        } else {
+            self.message.clone()
         }
     }
 }
@@ -73,15 +129,14 @@ pub enum MessageLevel {
 }
 
 fn fmt_line(line: &str, level: MessageLevel) -> impl Iterator<Item=LogMessage> + '_ {
+    // Long lines are wrapped by the console when drawn
     line.split('\n')
-        // TODO how do we wrap long lines?
-
         .map(move |str| LogMessage::new(level, String::from(str)))
 }
 
 fn add_line(line: &str, level: MessageLevel) {
     fmt_line(line, level)
-        .for_each(|log_message| LOGGER.lock().unwrap().history.push(log_message));
+        .for_each(|log_message| LOGGER.lock().unwrap().add(log_message));
 }
 
 fn add_line_once(line: &str, level: MessageLevel) {
@@ -132,3 +187,67 @@ pub fn get() -> MutexGuard<'static, Logger> {
 pub fn len() -> usize {
     LOGGER.lock().unwrap().history.len()
 }
+
+// Doesn't block, the logger might be locked by the panicking thread
+pub fn try_dump_history() -> Option<Vec<String>> {
+    let logger = match LOGGER.try_lock() {
+        Ok(logger) => logger,
+        Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+        Err(TryLockError::WouldBlock) => return None,
+    };
+
+    let lines = logger
+        .history
+        .iter()
+        .map(|log_message| format!("[{:?}] {}", log_message.level, log_message.get_text()))
+        .collect();
+
+    Some(lines)
+}
+
+pub fn set_capacity(capacity: usize) {
+    LOGGER.lock().unwrap().set_capacity(capacity);
+}
+
+pub fn clear() {
+    LOGGER.lock().unwrap().clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capacity_and_filter() {
+        let mut logger = Logger::with_capacity(4);
+        for i in 0..6 {
+            logger.add(LogMessage::new(MessageLevel::Info, format!("line {}", i)));
+        }
+
+        let messages = |history: Vec<&LogMessage>| history.iter().map(|m| m.message.clone()).collect::<Vec<_>>();
+        assert_eq!(messages(logger.get_history(10, 0, None)), ["line 2", "line 3", "line 4", "line 5"]);
+        assert_eq!(messages(logger.get_history(2, 1, None)), ["line 3", "line 4"]);
+        assert_eq!(messages(logger.get_history(10, 0, Some("4"))), ["line 4"]);
+        assert_eq!(logger.count_matching("line"), 4);
+
+        logger.set_capacity(2);
+        assert_eq!(messages(logger.get_history(10, 0, None)), ["line 4", "line 5"]);
+
+        logger.clear();
+        assert!(logger.get_history(10, 0, None).is_empty());
+    }
+
+    #[test]
+    fn repeated_messages_collapse() {
+        let mut logger = Logger::with_capacity(4);
+        for _ in 0..3 {
+            logger.add(LogMessage::new(MessageLevel::Warning, String::from("missing texture")));
+        }
+        logger.add(LogMessage::new(MessageLevel::Error, String::from("missing texture")));
+        logger.add(LogMessage::new(MessageLevel::Warning, String::from("missing texture")));
+
+        let history = logger.get_history(10, 0, None);
+        let texts: Vec<String> = history.iter().map(|m| m.get_text()).collect();
+        assert_eq!(texts, ["missing texture (x3)", "missing texture", "missing texture"]);
+    }
+}
@@ -0,0 +1,197 @@
+use ash::vk;
+
+// Free ranges of a memory block, first fit. Freed ranges are merged with their free neighbours, so a block with
+// all allocations freed is one range again.
+pub struct FreeList {
+    size: vk::DeviceSize,
+    // Offset and size, sorted by offset and never touching
+    ranges: Vec<(vk::DeviceSize, vk::DeviceSize)>,
+}
+
+impl FreeList {
+    pub fn new(size: vk::DeviceSize) -> Self {
+        FreeList {
+            size,
+            ranges: vec![(0, size)],
+        }
+    }
+
+    // Offset of the allocation, the alignment is a power of two
+    pub fn allocate(&mut self, size: vk::DeviceSize, alignment: vk::DeviceSize) -> Option<vk::DeviceSize> {
+        debug_assert!(alignment.is_power_of_two());
+
+        let (index, offset) = self.ranges.iter().enumerate().find_map(|(index, (start, length))| {
+            let offset = (start + alignment - 1) & !(alignment - 1);
+            (offset + size <= start + length).then_some((index, offset))
+        })?;
+
+        // The padding before and the rest after the allocation stay free
+        let (start, length) = self.ranges[index];
+        let rest = (offset + size, start + length - offset - size);
+        match (offset > start, rest.1 > 0) {
+            (true, true) => {
+                self.ranges[index] = (start, offset - start);
+                self.ranges.insert(index + 1, rest);
+            }
+            (true, false) => self.ranges[index] = (start, offset - start),
+            (false, true) => self.ranges[index] = rest,
+            (false, false) => {
+                self.ranges.remove(index);
+            }
+        }
+
+        Some(offset)
+    }
+
+    pub fn free(&mut self, offset: vk::DeviceSize, size: vk::DeviceSize) {
+        let index = self.ranges.partition_point(|(start, _)| *start < offset);
+        debug_assert!(index == 0 || self.ranges[index - 1].0 + self.ranges[index - 1].1 <= offset);
+        debug_assert!(index == self.ranges.len() || offset + size <= self.ranges[index].0);
+
+        self.ranges.insert(index, (offset, size));
+        if index + 1 < self.ranges.len() && offset + size == self.ranges[index + 1].0 {
+            self.ranges[index].1 += self.ranges.remove(index + 1).1;
+        }
+        if index > 0 && self.ranges[index - 1].0 + self.ranges[index - 1].1 == offset {
+            self.ranges[index - 1].1 += self.ranges.remove(index).1;
+        }
+    }
+
+    pub fn size(&self) -> vk::DeviceSize {
+        self.size
+    }
+
+    pub fn used(&self) -> vk::DeviceSize {
+        self.size - self.ranges.iter().map(|(_, length)| length).sum::<vk::DeviceSize>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.used() == 0
+    }
+}
+
+// Linear allocations wrapping around a buffer, freed a frame at a time once the frame has finished. Frames are
+// recorded and finish in the order of their slots, so the end of the frame in a slot that finished frees everything
+// allocated before it too.
+#[derive(Clone)]
+pub struct Ring {
+    size: vk::DeviceSize,
+    // Bytes ever allocated and freed, positions in the buffer are these modulo the size
+    head: vk::DeviceSize,
+    tail: vk::DeviceSize,
+    // Head at the end of the frame last recorded in each slot
+    frame_ends: Vec<Option<vk::DeviceSize>>,
+}
+
+impl Ring {
+    pub fn new(size: vk::DeviceSize, frame_count: usize) -> Self {
+        Ring {
+            size,
+            head: 0,
+            tail: 0,
+            frame_ends: vec![None; frame_count],
+        }
+    }
+
+    // The frame last recorded in the slot has finished
+    pub fn begin_frame(&mut self, frame_index: usize) {
+        if let Some(end) = self.frame_ends[frame_index].take() {
+            self.tail = self.tail.max(end);
+        }
+    }
+
+    pub fn end_frame(&mut self, frame_index: usize) {
+        self.frame_ends[frame_index] = Some(self.head);
+    }
+
+    // Offset of the allocation, the alignment is a power of two dividing the size
+    pub fn allocate(&mut self, size: vk::DeviceSize, alignment: vk::DeviceSize) -> Option<vk::DeviceSize> {
+        debug_assert!(alignment.is_power_of_two() && self.size.is_multiple_of(alignment));
+
+        // Nothing in use, start over at the beginning of the buffer
+        if self.head == self.tail {
+            self.head = self.head.next_multiple_of(self.size);
+            self.tail = self.head;
+        }
+
+        let mut start = (self.head + alignment - 1) & !(alignment - 1);
+        // Allocations don't wrap, the rest of the buffer is skipped instead
+        let position = start % self.size;
+        if position + size > self.size {
+            start += self.size - position;
+        }
+        if start + size - self.tail > self.size {
+            return None;
+        }
+
+        self.head = start + size;
+        Some(start % self.size)
+    }
+
+    pub fn size(&self) -> vk::DeviceSize {
+        self.size
+    }
+
+    pub fn used(&self) -> vk::DeviceSize {
+        self.head - self.tail
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn free_list() {
+        let mut list = FreeList::new(1024);
+        let a = list.allocate(100, 1).unwrap();
+        let b = list.allocate(100, 256).unwrap();
+        let c = list.allocate(300, 4).unwrap();
+        assert_eq!((a, b, c), (0, 256, 356));
+        assert_eq!(list.used(), 500);
+        assert!(list.allocate(512, 1).is_none());
+
+        // The padding before b is reused
+        assert_eq!(list.allocate(64, 64), Some(128));
+
+        // Merged with the free range before it
+        list.free(b, 100);
+        assert_eq!(list.allocate(100, 16), Some(192));
+        list.free(192, 100);
+        list.free(a, 100);
+        list.free(128, 64);
+        list.free(c, 300);
+        assert!(list.is_empty());
+        assert_eq!(list.ranges, vec![(0, 1024)]);
+        assert_eq!(list.allocate(1024, 1024), Some(0));
+        assert!(list.allocate(1, 1).is_none());
+    }
+
+    #[test]
+    fn ring() {
+        let mut ring = Ring::new(256, 2);
+        ring.begin_frame(0);
+        assert_eq!(ring.allocate(100, 16), Some(0));
+        assert_eq!(ring.allocate(50, 16), Some(112));
+        ring.end_frame(0);
+
+        // Frame 0 is still in flight
+        ring.begin_frame(1);
+        assert_eq!(ring.allocate(64, 16), Some(176));
+        assert!(ring.allocate(64, 16).is_none());
+        ring.end_frame(1);
+
+        // Frame 0 finished, the allocation skips the end of the buffer
+        ring.begin_frame(0);
+        assert_eq!(ring.used(), 78);
+        assert_eq!(ring.allocate(32, 16), Some(0));
+        assert!(ring.allocate(160, 16).is_none());
+        assert_eq!(ring.allocate(128, 16), Some(32));
+        ring.end_frame(0);
+
+        ring.begin_frame(1);
+        ring.begin_frame(0);
+        assert_eq!(ring.used(), 0);
+        assert_eq!(ring.allocate(256, 16), Some(0));
+    }
+}
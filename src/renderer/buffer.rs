@@ -3,22 +3,46 @@ use std::time::Instant;
 
 use ash::vk;
 
+use crate::renderer::allocator::Ring;
+use crate::renderer::graph::GraphBuffer;
 use crate::renderer::memory::MemoryManager;
 use crate::renderer::pass::RenderPassManager;
+use crate::renderer::queue::QueueFamilyIndices;
 use crate::renderer::rawarray::{PushError, RawArray, RawArrayPtr};
 use crate::renderer::stats::RenderStats;
 use crate::renderer::types::{BufferObjectHandle, PipelineHandle, UniformStage};
 
+const INITIAL_STAGING_SIZE: vk::DeviceSize = 1 << 20;
+const STAGING_ALIGNMENT: vk::DeviceSize = 16;
+
+// Mapped buffer the uploads of all buffer objects are packed into, allocated per frame from the ring
+struct StagingRing {
+    buffer: vk::Buffer,
+    ring: Ring,
+}
+
 pub struct BufferObjectManager {
     image_count: usize,
     buffer_objects: Vec<BufferObject>,
+    // Created with the first upload that needs it, never when device local memory is host visible
+    staging: Option<StagingRing>,
+    // Transfer and graphics queue family when they differ, uploads are released by the one and acquired by the
+    // other
+    ownership_transfer: Option<(u32, u32)>,
+    // Device buffers and sizes released by the last bake_command_buffer
+    released: Vec<(vk::Buffer, vk::DeviceSize)>,
 }
 
 impl BufferObjectManager {
-    pub fn new(image_count: usize) -> BufferObjectManager {
+    pub fn new(image_count: usize, queue_families: &QueueFamilyIndices) -> BufferObjectManager {
         BufferObjectManager {
             image_count,
             buffer_objects: Vec::new(),
+            staging: None,
+            ownership_transfer: queue_families
+                .needs_ownership_transfer()
+                .then_some((queue_families.transfer.family_index, queue_families.graphics.family_index)),
+            released: Vec::new(),
         }
     }
 
@@ -29,8 +53,8 @@ impl BufferObjectManager {
         capacity: usize,
         buffer_object_type: BufferObjectType,
         is_growable: bool,
-    ) -> BufferObjectHandle {
-        let handle = self.buffer_objects.len();
+    ) -> BufferObjectHandle<T> {
+        let handle = BufferObjectHandle::new(self.buffer_objects.len());
 
         let mut dynamic_buffer = BufferObject::new::<T>(capacity, self.image_count, buffer_object_type, is_growable);
         dynamic_buffer.build(device, memory_manager, self.image_count);
@@ -40,30 +64,50 @@ impl BufferObjectManager {
         handle
     }
 
-    pub fn push_to_buf<T>(&mut self, handle: BufferObjectHandle, data: T) -> Result<RawArrayPtr, PushError> {
-        debug_assert!(self.buffer_objects.len() > handle);
-        self.buffer_objects[handle].push(data)
+    pub fn push_to_buf<T>(&mut self, handle: BufferObjectHandle<T>, data: T) -> Result<RawArrayPtr, PushError> {
+        debug_assert!(self.buffer_objects.len() > handle.index());
+        self.buffer_objects[handle.index()].push(data)
     }
 
-    pub fn borrow_buffer(&self, handle: BufferObjectHandle) -> &BufferObject {
+    pub fn borrow_buffer(&self, handle: usize) -> &BufferObject {
         debug_assert!(self.buffer_objects.len() > handle);
         &self.buffer_objects[handle]
     }
 
-    pub fn reset_buffer(&mut self, handle: BufferObjectHandle) {
+    pub fn reset_buffer(&mut self, handle: usize) {
         debug_assert!(self.buffer_objects.len() > handle);
         self.buffer_objects[handle].reset();
     }
 
-    pub fn rebuild(&mut self, device: &ash::Device, memory_manager: &mut MemoryManager, image_count: usize) {
+    // Buffer objects survive swapchain recreation, only the per-image buffers are added or dropped
+    // when the number of swapchain images changes. Returns true if any buffer handles changed.
+    pub fn resize_image_count(
+        &mut self,
+        device: &ash::Device,
+        memory_manager: &mut MemoryManager,
+        image_count: usize,
+    ) -> bool {
+        if self.image_count == image_count {
+            return false;
+        }
+        log_debug!(
+            "resizing buffer objects from {} to {} images",
+            self.image_count,
+            image_count
+        );
         self.image_count = image_count;
 
         for buffer in self.buffer_objects.iter_mut() {
             unsafe {
-                buffer.destroy(device, memory_manager);
-                buffer.build(device, memory_manager, image_count);
+                buffer.resize_image_count(device, memory_manager, image_count);
             }
         }
+        // Called with the device idle, no frame uses the staging ring
+        if let Some(staging) = self.staging.as_mut() {
+            staging.ring = Ring::new(staging.ring.size(), image_count);
+        }
+
+        true
     }
 
     pub fn reassign_pipeline_buffers(&self, render_pass_manager: &mut RenderPassManager) {
@@ -76,16 +120,46 @@ impl BufferObjectManager {
                 for pipeline in buffer_object.assigned_pipelines.iter() {
                     render_pass_manager.borrow_pipeline_mut(*pipeline).set_storage_buffers(buffer_object.devices());
                 }
+            } else if let BufferObjectType::FrameGlobals = buffer_object.buffer_object_type {
+                for pipeline in buffer_object.assigned_pipelines.iter() {
+                    render_pass_manager.borrow_pipeline_mut(*pipeline).set_frame_globals_buffers(buffer_object.devices());
+                }
             }
         }
     }
 
-    pub fn assign_pipeline(&mut self, bo_handle: BufferObjectHandle, pipeline_handle: PipelineHandle) {
+    pub fn assign_pipeline(&mut self, bo_handle: usize, pipeline_handle: PipelineHandle) {
         debug_assert!(self.buffer_objects.len() > bo_handle);
 
         self.buffer_objects[bo_handle].assign_pipeline(pipeline_handle);
     }
 
+    pub fn graph_buffers(&self) -> Vec<GraphBuffer> {
+        self.buffer_objects
+            .iter()
+            .enumerate()
+            .map(|(index, buffer_object)| GraphBuffer {
+                index,
+                kind: match buffer_object.buffer_object_type {
+                    BufferObjectType::Uniform(UniformStage::Vertex) => "vertex uniform",
+                    BufferObjectType::Uniform(UniformStage::Fragment) => "fragment uniform",
+                    BufferObjectType::Storage => "storage",
+                    BufferObjectType::Vertex => "vertex",
+                    BufferObjectType::FrameGlobals => "frame globals",
+                },
+                pipelines: buffer_object.assigned_pipelines.clone(),
+            })
+            .collect()
+    }
+
+    pub fn unassign_pipeline(&mut self, bo_handle: usize, pipeline_handle: PipelineHandle) {
+        debug_assert!(self.buffer_objects.len() > bo_handle);
+
+        self.buffer_objects[bo_handle]
+            .assigned_pipelines
+            .retain(|pipeline| *pipeline != pipeline_handle);
+    }
+
     pub fn bake_command_buffer(
         &mut self,
         logical_device: &ash::Device,
@@ -96,6 +170,7 @@ impl BufferObjectManager {
     ) -> bool {
         let start_time = Instant::now();
         let mut transferring = false;
+        let mut released = Vec::new();
 
         let command_buffer_begin_info = vk::CommandBufferBeginInfo::builder()
             .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)
@@ -110,40 +185,90 @@ impl BufferObjectManager {
                 .expect("Failed to begin recording of Transfer command buffer!");
         }
 
-        self.buffer_objects
-            .iter_mut()
-            .filter(|bo| bo.is_dirty[image_index])
-            .for_each(|bo| {
-                let staging_buffer = bo.staging(image_index);
-                let device_buffer = bo.device(image_index);
+        if let Some(staging) = self.staging.as_mut() {
+            staging.ring.begin_frame(image_index);
+        }
 
+        let mut staged = Vec::new();
+        for (index, bo) in self.buffer_objects.iter_mut().enumerate() {
+            if !bo.is_dirty[image_index] {
+                continue;
+            }
+            bo.is_dirty[image_index] = false;
+            // Unchanged since the last upload to this image, e.g. a uniform set again with the same value
+            if !bo.snapshot(image_index) {
+                continue;
+            }
+
+            let data_slice = bo.frame_data[image_index].as_slice();
+            if data_slice.is_empty() {
+                continue;
+            }
+
+            // Device buffers in host visible memory are written directly and need no transfer.
+            if bo.is_host_visible {
                 unsafe {
-                    let data_slice = from_raw_parts(bo.raw_array.start(), bo.raw_array.len_bytes());
-                    if data_slice.is_empty() {
-                        bo.is_dirty[image_index] = false;
-                        return;
-                    }
-                    memory_manager.copy_to_buffer_memory(logical_device, staging_buffer, data_slice);
+                    memory_manager.copy_to_buffer_memory(logical_device, bo.device(image_index), data_slice);
                 }
+                continue;
+            }
 
-                transferring = true;
+            staged.push(index);
+        }
 
-                let copy_region = [vk::BufferCopy::builder()
-                    .src_offset(0)
-                    .dst_offset(0)
-                    .size(bo.raw_array.len_bytes() as vk::DeviceSize)
-                    .build()];
+        if !staged.is_empty() {
+            let sizes: Vec<vk::DeviceSize> = staged
+                .iter()
+                .map(|index| self.buffer_objects[*index].frame_data[image_index].len() as vk::DeviceSize)
+                .collect();
+            self.reserve_staging(logical_device, memory_manager, image_index, &sizes);
+            let staging = self.staging.as_mut().unwrap();
+
+            for (index, size) in staged.iter().zip(sizes) {
+                let bo = &self.buffer_objects[*index];
+                let device_buffer = bo.device(image_index);
+                let offset = staging.ring.allocate(size, STAGING_ALIGNMENT).unwrap();
+                let copy_region = [vk::BufferCopy::builder().src_offset(offset).dst_offset(0).size(size).build()];
                 unsafe {
+                    memory_manager.copy_to_buffer_memory_at(
+                        logical_device,
+                        staging.buffer,
+                        offset,
+                        &bo.frame_data[image_index],
+                    );
                     logical_device.cmd_copy_buffer(
                         transfer_command_buffer,
-                        staging_buffer,
+                        staging.buffer,
                         device_buffer,
                         &copy_region,
                     );
                 }
+                released.push((device_buffer, size));
+            }
+            transferring = true;
+        }
+
+        if let Some(staging) = self.staging.as_mut() {
+            staging.ring.end_frame(image_index);
+        }
 
-                bo.is_dirty[image_index] = false;
-            });
+        if let Some((transfer_family, graphics_family)) = self.ownership_transfer {
+            let barriers = _ownership_barriers(&released, transfer_family, graphics_family, true);
+            if !barriers.is_empty() {
+                unsafe {
+                    logical_device.cmd_pipeline_barrier(
+                        transfer_command_buffer,
+                        vk::PipelineStageFlags::TRANSFER,
+                        vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                        vk::DependencyFlags::empty(),
+                        &[],
+                        &barriers,
+                        &[],
+                    );
+                }
+            }
+            self.released = released;
+        }
 
         unsafe {
             logical_device
@@ -155,11 +280,79 @@ impl BufferObjectManager {
         transferring
     }
 
+    // Size of the staging ring and bytes of it used by frames in flight, None until the first staged upload
+    pub fn staging_usage(&self) -> Option<(vk::DeviceSize, vk::DeviceSize)> {
+        self.staging.as_ref().map(|staging| (staging.ring.size(), staging.ring.used()))
+    }
+
+    // Makes sure the staging ring has room for the sizes in this frame. A ring that is too small is replaced by one
+    // at least twice as large, after waiting for the frames using it.
+    fn reserve_staging(
+        &mut self,
+        device: &ash::Device,
+        memory_manager: &mut MemoryManager,
+        image_index: usize,
+        sizes: &[vk::DeviceSize],
+    ) {
+        let fits = |ring: &Ring| {
+            let mut probe = ring.clone();
+            sizes.iter().all(|size| probe.allocate(*size, STAGING_ALIGNMENT).is_some())
+        };
+        if self.staging.as_ref().is_some_and(|staging| fits(&staging.ring)) {
+            return;
+        }
+
+        let needed: vk::DeviceSize = sizes.iter().map(|size| size.next_multiple_of(STAGING_ALIGNMENT)).sum();
+        let mut size = INITIAL_STAGING_SIZE;
+        if let Some(staging) = self.staging.take() {
+            size = staging.ring.size() * 2;
+            unsafe {
+                device.device_wait_idle().expect("Failed to wait device idle!");
+                memory_manager.destroy_buffer(device, staging.buffer);
+            }
+        }
+        while size < 2 * needed {
+            size *= 2;
+        }
+        log_debug!("staging ring: {} bytes", size);
+
+        let mut ring = Ring::new(size, self.image_count);
+        ring.begin_frame(image_index);
+        self.staging = Some(StagingRing {
+            buffer: memory_manager.create_staging_buffer(device, size),
+            ring,
+        });
+    }
+
+    // Recorded first in the draw command buffer of the frame, after the transfer finished semaphore. Nothing to do
+    // when the transfer queue belongs to the graphics family.
+    pub unsafe fn cmd_acquire_transferred(&self, logical_device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        let (transfer_family, graphics_family) = match self.ownership_transfer {
+            Some(families) if !self.released.is_empty() => families,
+            _ => return,
+        };
+
+        let barriers = _ownership_barriers(&self.released, transfer_family, graphics_family, false);
+        logical_device.cmd_pipeline_barrier(
+            command_buffer,
+            // The stages the draw submission waits for the transfer in
+            vk::PipelineStageFlags::VERTEX_INPUT | vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::VERTEX_INPUT
+                | vk::PipelineStageFlags::VERTEX_SHADER
+                | vk::PipelineStageFlags::FRAGMENT_SHADER
+                | vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &barriers,
+            &[],
+        );
+    }
+
     pub fn handle_buffer_overflow(
         &mut self,
         device: &ash::Device,
         memory_manager: &mut MemoryManager,
-        handle: BufferObjectHandle,
+        handle: usize,
         image_count: usize,
     ) -> bool {
         debug_assert!(self.buffer_objects.len() > handle);
@@ -173,6 +366,11 @@ impl BufferObjectManager {
                 buffer.destroy(device, memory_manager);
             }
         }
+        if let Some(staging) = self.staging.take() {
+            unsafe {
+                memory_manager.destroy_buffer(device, staging.buffer);
+            }
+        }
     }
 }
 
@@ -180,17 +378,22 @@ pub enum BufferObjectType {
     Uniform(UniformStage),
     Storage,
     Vertex,
+    // The engine managed FrameGlobals uniform, bound with PipelineConfigurationBuilder::with_frame_globals
+    FrameGlobals,
 }
 
 pub struct BufferObject {
     buffer_object_type: BufferObjectType,
     capacity_bytes: usize,
-    staging_buffer: Vec<vk::Buffer>,
     device_buffer: Vec<vk::Buffer>,
     raw_array: RawArray,
+    // Bytes last uploaded to each image. Every image is uploaded from its own copy taken when its frame is baked,
+    // so later pushes and resets by the application never reach a frame that is already in flight.
+    frame_data: Vec<Vec<u8>>,
 
     assigned_pipelines: Vec<PipelineHandle>,
     is_growable: bool,
+    is_host_visible: bool,
     is_dirty: Vec<bool>,
 }
 
@@ -201,7 +404,6 @@ impl BufferObject {
         buffer_object_type: BufferObjectType,
         is_growable: bool,
     ) -> Self {
-        let staging_buffer = Vec::with_capacity(image_count);
         let device_buffer = Vec::with_capacity(image_count);
 
         let capacity_bytes = capacity * std::mem::size_of::<T>();
@@ -211,30 +413,69 @@ impl BufferObject {
         BufferObject {
             buffer_object_type,
             capacity_bytes,
-            staging_buffer,
             device_buffer,
             raw_array: RawArray::new::<T>(capacity).unwrap(),
+            frame_data: vec![Vec::new(); image_count],
             assigned_pipelines: Vec::new(),
             is_growable,
+            is_host_visible: false,
             is_dirty: dirty_array,
         }
     }
 
     pub fn build(&mut self, device: &ash::Device, memory_manager: &mut MemoryManager, image_count: usize) {
+        self.is_host_visible = memory_manager.supports_host_visible_device_local();
+
         for _i in 0..image_count {
-            let usage = match self.buffer_object_type {
-                BufferObjectType::Uniform(_) => vk::BufferUsageFlags::UNIFORM_BUFFER,
-                BufferObjectType::Storage => vk::BufferUsageFlags::STORAGE_BUFFER,
-                BufferObjectType::Vertex => vk::BufferUsageFlags::VERTEX_BUFFER,
-            };
+            self.build_image_buffer(device, memory_manager);
+        }
+        self.is_dirty = vec![true; image_count];
+        self.frame_data = vec![Vec::new(); image_count];
+    }
 
-            let staging_buf = memory_manager.create_staging_buffer(device, self.capacity_bytes as vk::DeviceSize);
-            let device_buf = memory_manager.create_device_buffer(device, self.capacity_bytes as vk::DeviceSize, usage);
+    fn build_image_buffer(&mut self, device: &ash::Device, memory_manager: &mut MemoryManager) {
+        let usage = match self.buffer_object_type {
+            BufferObjectType::Uniform(_) | BufferObjectType::FrameGlobals => vk::BufferUsageFlags::UNIFORM_BUFFER,
+            BufferObjectType::Storage => vk::BufferUsageFlags::STORAGE_BUFFER,
+            BufferObjectType::Vertex => vk::BufferUsageFlags::VERTEX_BUFFER,
+        };
+
+        // Uploaded through the staging ring of the manager unless host visible. The memory types allowed for a
+        // buffer only depend on its usage, so the first buffer decides for all images.
+        let size = self.capacity_bytes as vk::DeviceSize;
+        let host_visible_buf = if self.is_host_visible {
+            memory_manager.create_host_visible_device_buffer(device, size, usage)
+        } else {
+            None
+        };
+        let device_buf = match host_visible_buf {
+            Some(buf) => buf,
+            None => {
+                debug_assert!(!self.is_host_visible || self.device_buffer.is_empty());
+                self.is_host_visible = false;
+                memory_manager.create_device_buffer(device, size, usage)
+            }
+        };
+
+        self.device_buffer.push(device_buf);
+    }
 
-            self.staging_buffer.push(staging_buf);
-            self.device_buffer.push(device_buf);
+    // Keeps the buffers of the images that still exist, new images start out dirty
+    pub unsafe fn resize_image_count(
+        &mut self,
+        device: &ash::Device,
+        memory_manager: &mut MemoryManager,
+        image_count: usize,
+    ) {
+        while self.device_buffer.len() > image_count {
+            let buf = self.device_buffer.pop().unwrap();
+            memory_manager.destroy_buffer(device, buf);
         }
-        self.is_dirty = vec![true; image_count];
+        while self.device_buffer.len() < image_count {
+            self.build_image_buffer(device, memory_manager);
+        }
+        self.is_dirty.resize(image_count, true);
+        self.frame_data.resize(image_count, Vec::new());
     }
 
     pub fn assign_pipeline(&mut self, pipeline_handle: PipelineHandle) {
@@ -245,21 +486,54 @@ impl BufferObject {
         &self.assigned_pipelines
     }
 
+    pub fn is_vertex_uniform(&self) -> bool {
+        matches!(self.buffer_object_type, BufferObjectType::Uniform(UniformStage::Vertex))
+    }
+
     pub fn push<T>(&mut self, data: T) -> Result<RawArrayPtr, PushError> {
         self.is_dirty.fill(true);
         self.raw_array.push(data)
     }
 
     pub fn reset(&mut self) {
+        self.is_dirty.fill(true);
         self.raw_array.reset();
     }
 
-    pub unsafe fn destroy(&mut self, device: &ash::Device, memory_manager: &mut MemoryManager) {
-        for buf in self.staging_buffer.iter() {
-            memory_manager.destroy_buffer(device, *buf);
+    // Number of elements pushed since the last reset
+    pub fn len(&self) -> usize {
+        self.raw_array.len()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.raw_array.len() >= self.raw_array.capacity()
+    }
+
+    // Elements, see capacity_bytes for the size of the device buffers
+    pub fn capacity(&self) -> usize {
+        self.raw_array.capacity()
+    }
+
+    // Bytes between the elements in the device buffers
+    pub fn stride(&self) -> usize {
+        self.raw_array.stride()
+    }
+
+    // Copies the current data into the image's own copy, returns false if it's already up to date
+    fn snapshot(&mut self, image_index: usize) -> bool {
+        let data = unsafe { from_raw_parts(self.raw_array.start(), self.raw_array.len_bytes()) };
+        let frame_data = &mut self.frame_data[image_index];
+        if frame_data.as_slice() == data {
+            return false;
         }
-        self.staging_buffer.clear();
 
+        frame_data.clear();
+        frame_data.extend_from_slice(data);
+
+        true
+    }
+
+    pub unsafe fn destroy(&mut self, device: &ash::Device, memory_manager: &mut MemoryManager) {
         for buf in self.device_buffer.iter() {
             memory_manager.destroy_buffer(device, *buf);
             log_debug!("Destroying buffer {:?}", buf);
@@ -267,11 +541,6 @@ impl BufferObject {
         self.device_buffer.clear();
     }
 
-    pub fn staging(&self, image_index: usize) -> vk::Buffer {
-        debug_assert!(self.staging_buffer.len() > image_index);
-        self.staging_buffer[image_index]
-    }
-
     pub fn device(&self, image_index: usize) -> vk::Buffer {
         debug_assert!(self.device_buffer.len() > image_index);
 
@@ -312,3 +581,60 @@ impl BufferObject {
         self.capacity_bytes
     }
 }
+
+// Both halves of a queue family ownership transfer use the same families and ranges, the release makes the copy
+// available and the acquire visible to the readers
+fn _ownership_barriers(
+    buffers: &[(vk::Buffer, vk::DeviceSize)],
+    transfer_family: u32,
+    graphics_family: u32,
+    release: bool,
+) -> Vec<vk::BufferMemoryBarrier> {
+    let (src_access, dst_access) = if release {
+        (vk::AccessFlags::TRANSFER_WRITE, vk::AccessFlags::empty())
+    } else {
+        (
+            vk::AccessFlags::empty(),
+            vk::AccessFlags::UNIFORM_READ | vk::AccessFlags::SHADER_READ | vk::AccessFlags::VERTEX_ATTRIBUTE_READ,
+        )
+    };
+
+    buffers
+        .iter()
+        .map(|(buffer, size)| {
+            vk::BufferMemoryBarrier::builder()
+                .src_access_mask(src_access)
+                .dst_access_mask(dst_access)
+                .src_queue_family_index(transfer_family)
+                .dst_queue_family_index(graphics_family)
+                .buffer(*buffer)
+                .offset(0)
+                .size(*size)
+                .build()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_snapshots() {
+        let mut bo = BufferObject::new::<u32>(4, 2, BufferObjectType::Storage, false);
+        let _ = bo.push(1u32);
+        assert!(bo.snapshot(0));
+        assert!(!bo.snapshot(0));
+
+        // Frame 0 keeps its copy while the application writes the next frame
+        bo.reset();
+        let _ = bo.push(2u32);
+        assert_eq!(bo.frame_data[0], 1u32.to_ne_bytes());
+        assert!(bo.snapshot(1));
+        assert_eq!(bo.frame_data[1], 2u32.to_ne_bytes());
+
+        bo.reset();
+        assert!(bo.snapshot(0));
+        assert!(bo.frame_data[0].is_empty());
+    }
+}
@@ -0,0 +1,187 @@
+use std::fmt::Write;
+
+use crate::renderer::types::{PipelineHandle, RenderPassHandle, SamplerHandle, TextureHandle};
+
+pub(super) struct CapturedPass {
+    // None for the swapchain pass
+    pub(super) handle: Option<RenderPassHandle>,
+    pub(super) enabled: bool,
+    // In the order they are recorded, transparent ones last
+    pub(super) draws: Vec<CapturedDraw>,
+}
+
+// Buffer handles of buffered draws, the buffer object of dynamic ones
+pub(super) enum CapturedMesh {
+    Buffered { index_buffer: u64, vertex_buffers: Vec<u64>, index_count: u32, indirect: bool },
+    Dynamic { buffer_object: usize, vertex_start: u32, vertex_count: u32 },
+}
+
+pub(super) struct CapturedDraw {
+    pub(super) pipeline: PipelineHandle,
+    pub(super) pipeline_name: Option<String>,
+    pub(super) mesh: CapturedMesh,
+    // None for indirect draws
+    pub(super) instances: Option<(u32, u32)>,
+    pub(super) texture: Option<(TextureHandle, SamplerHandle)>,
+    pub(super) depth: Option<f32>,
+    pub(super) push_constants: Vec<u8>,
+}
+
+// JSON of the draw commands of one frame in pass order, one draw per line. Written by the dumpframe command.
+pub(super) fn to_json(frame_index: u32, passes: &[CapturedPass]) -> String {
+    let mut json = String::new();
+    let _ = writeln!(json, "{{");
+    let _ = writeln!(json, "  \"frame\": {},", frame_index);
+    let _ = writeln!(json, "  \"passes\": [");
+
+    for (pass_index, pass) in passes.iter().enumerate() {
+        let handle = match pass.handle {
+            Some(handle) => handle.to_string(),
+            None => "\"swapchain\"".to_string(),
+        };
+        let _ = writeln!(json, "    {{");
+        let _ = writeln!(json, "      \"pass\": {},", handle);
+        let _ = writeln!(json, "      \"enabled\": {},", pass.enabled);
+        let _ = writeln!(json, "      \"draws\": [");
+        for (draw_index, draw) in pass.draws.iter().enumerate() {
+            let separator = if draw_index + 1 < pass.draws.len() { "," } else { "" };
+            let _ = writeln!(json, "        {}{}", _draw_json(draw), separator);
+        }
+        let _ = writeln!(json, "      ]");
+        let separator = if pass_index + 1 < passes.len() { "," } else { "" };
+        let _ = writeln!(json, "    }}{}", separator);
+    }
+
+    let _ = writeln!(json, "  ]");
+    json.push_str("}\n");
+    json
+}
+
+fn _draw_json(draw: &CapturedDraw) -> String {
+    let pipeline_name = match &draw.pipeline_name {
+        Some(name) => _json_string(name),
+        None => "null".to_string(),
+    };
+    let mesh = match &draw.mesh {
+        CapturedMesh::Buffered { index_buffer, vertex_buffers, index_count, indirect } => {
+            let vertex_buffers: Vec<String> =
+                vertex_buffers.iter().map(|buffer| format!("\"{:#x}\"", buffer)).collect();
+            format!(
+                "{{\"index_buffer\": \"{:#x}\", \"vertex_buffers\": [{}], \"index_count\": {}, \"indirect\": {}}}",
+                index_buffer,
+                vertex_buffers.join(", "),
+                index_count,
+                indirect
+            )
+        }
+        CapturedMesh::Dynamic { buffer_object, vertex_start, vertex_count } => format!(
+            "{{\"buffer_object\": {}, \"vertex_start\": {}, \"vertex_count\": {}}}",
+            buffer_object, vertex_start, vertex_count
+        ),
+    };
+    let instances = match draw.instances {
+        Some((start, count)) => format!("{{\"start\": {}, \"count\": {}}}", start, count),
+        None => "null".to_string(),
+    };
+    let texture = match draw.texture {
+        Some((texture, sampler)) => format!("{{\"texture\": {}, \"sampler\": {}}}", texture, sampler),
+        None => "null".to_string(),
+    };
+    let depth = match draw.depth {
+        Some(depth) if depth.is_finite() => depth.to_string(),
+        _ => "null".to_string(),
+    };
+    let push_constants: String = draw.push_constants.iter().map(|byte| format!("{:02x}", byte)).collect();
+
+    format!(
+        "{{\"pipeline\": {}, \"handle\": [{}, {}], \"mesh\": {}, \"instances\": {}, \"texture\": {}, \"depth\": {}, \
+         \"push_constants\": \"{}\"}}",
+        pipeline_name,
+        draw.pipeline.render_pass,
+        draw.pipeline.index(),
+        mesh,
+        instances,
+        texture,
+        depth,
+        push_constants
+    )
+}
+
+fn _json_string(value: &str) -> String {
+    let mut string = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => string.push_str("\\\""),
+            '\\' => string.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(string, "\\u{:04x}", c as u32);
+            }
+            c => string.push(c),
+        }
+    }
+    string.push('"');
+    string
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::types::SWAPCHAIN_PASS;
+
+    #[test]
+    fn frame_capture_json() {
+        let passes = vec![
+            CapturedPass {
+                handle: Some(2),
+                enabled: false,
+                draws: Vec::new(),
+            },
+            CapturedPass {
+                handle: None,
+                enabled: true,
+                draws: vec![
+                    CapturedDraw {
+                        pipeline: PipelineHandle::new(SWAPCHAIN_PASS, 1),
+                        pipeline_name: Some("hud \"text\"".to_string()),
+                        mesh: CapturedMesh::Dynamic { buffer_object: 4, vertex_start: 6, vertex_count: 12 },
+                        instances: Some((0, 1)),
+                        texture: Some((3, 0)),
+                        depth: None,
+                        push_constants: vec![0x00, 0x7f, 0xff],
+                    },
+                    CapturedDraw {
+                        pipeline: PipelineHandle::new(SWAPCHAIN_PASS, 0),
+                        pipeline_name: None,
+                        mesh: CapturedMesh::Buffered {
+                            index_buffer: 0x10,
+                            vertex_buffers: vec![0x20, 0x30],
+                            index_count: 36,
+                            indirect: true,
+                        },
+                        instances: None,
+                        texture: None,
+                        depth: Some(2.5),
+                        push_constants: Vec::new(),
+                    },
+                ],
+            },
+        ];
+
+        let json = to_json(7, &passes);
+        assert!(json.starts_with("{\n  \"frame\": 7,\n  \"passes\": [\n    {\n      \"pass\": 2,\n"));
+        assert!(json.contains("      \"enabled\": false,\n      \"draws\": [\n      ]\n    },\n"));
+        assert!(json.contains("\"pass\": \"swapchain\""));
+        assert!(json.contains(
+            "        {\"pipeline\": \"hud \\\"text\\\"\", \"handle\": [100000, 1], \"mesh\": {\"buffer_object\": 4, \
+             \"vertex_start\": 6, \"vertex_count\": 12}, \"instances\": {\"start\": 0, \"count\": 1}, \"texture\": \
+             {\"texture\": 3, \"sampler\": 0}, \"depth\": null, \"push_constants\": \"007fff\"},\n"
+        ));
+        assert!(json.contains(
+            "{\"pipeline\": null, \"handle\": [100000, 0], \"mesh\": {\"index_buffer\": \"0x10\", \"vertex_buffers\": \
+             [\"0x20\", \"0x30\"], \"index_count\": 36, \"indirect\": true}, \"instances\": null, \"texture\": null, \
+             \"depth\": 2.5, \"push_constants\": \"\"}\n"
+        ));
+        assert!(json.ends_with("      ]\n    }\n  ]\n}\n"));
+        assert_eq!(_json_string("a\\b\n"), "\"a\\\\b\\u000a\"");
+    }
+}
@@ -0,0 +1,125 @@
+use std::ffi::CString;
+use std::path::Path;
+
+use ash::vk;
+
+use crate::renderer::descriptor::DescriptorCounts;
+use crate::renderer::shader;
+
+// Compute pipeline with a single descriptor set, one descriptor per binding in order, and an optional push
+// constant range
+pub(super) struct ComputePipeline {
+    pub(super) descriptor_set_layout: vk::DescriptorSetLayout,
+    pub(super) descriptor_counts: DescriptorCounts,
+    pub(super) pipeline_layout: vk::PipelineLayout,
+    pub(super) pipeline: vk::Pipeline,
+}
+
+impl ComputePipeline {
+    pub(super) fn new(
+        device: &ash::Device,
+        shader_path: &str,
+        bindings: &[vk::DescriptorType],
+        push_constant_size: u32,
+    ) -> Self {
+        let code = shader::load_shader_or_fallback(Path::new(shader_path), shader::FALLBACK_COMPUTE_SHADER);
+        Self::from_code(device, &code, bindings, push_constant_size)
+    }
+
+    pub(super) fn from_code(
+        device: &ash::Device,
+        code: &[u8],
+        bindings: &[vk::DescriptorType],
+        push_constant_size: u32,
+    ) -> Self {
+        let layout_bindings: Vec<vk::DescriptorSetLayoutBinding> = bindings
+            .iter()
+            .enumerate()
+            .map(|(binding, descriptor_type)| {
+                vk::DescriptorSetLayoutBinding::builder()
+                    .binding(binding as u32)
+                    .descriptor_type(*descriptor_type)
+                    .descriptor_count(1)
+                    .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                    .build()
+            })
+            .collect();
+        let descriptor_set_layout_create_info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&layout_bindings);
+
+        let push_constant_ranges: Vec<vk::PushConstantRange> = (push_constant_size > 0)
+            .then_some(vk::PushConstantRange {
+                stage_flags: vk::ShaderStageFlags::COMPUTE,
+                offset: 0,
+                size: push_constant_size,
+            })
+            .into_iter()
+            .collect();
+
+        let shader_module_create_info = vk::ShaderModuleCreateInfo {
+            code_size: code.len(),
+            p_code: code.as_ptr() as *const u32,
+            ..Default::default()
+        };
+        let entry_point = CString::new("main").unwrap();
+
+        unsafe {
+            let descriptor_set_layout = device
+                .create_descriptor_set_layout(&descriptor_set_layout_create_info, None)
+                .expect("Failed to create Descriptor Set Layout!");
+            let set_layouts = [descriptor_set_layout];
+            let pipeline_layout_create_info = vk::PipelineLayoutCreateInfo::builder()
+                .set_layouts(&set_layouts)
+                .push_constant_ranges(&push_constant_ranges);
+            let pipeline_layout = device
+                .create_pipeline_layout(&pipeline_layout_create_info, None)
+                .expect("Failed to create Pipeline Layout!");
+
+            let shader_module = device
+                .create_shader_module(&shader_module_create_info, None)
+                .expect("Failed to create Shader Module!");
+            let stage = vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::COMPUTE)
+                .module(shader_module)
+                .name(&entry_point)
+                .build();
+            let pipeline_create_infos = [vk::ComputePipelineCreateInfo::builder()
+                .stage(stage)
+                .layout(pipeline_layout)
+                .build()];
+            let pipeline = device
+                .create_compute_pipelines(vk::PipelineCache::null(), &pipeline_create_infos, None)
+                .expect("Failed to create Compute Pipeline!")[0];
+            device.destroy_shader_module(shader_module, None);
+
+            ComputePipeline {
+                descriptor_set_layout,
+                descriptor_counts: DescriptorCounts::from_bindings(&layout_bindings),
+                pipeline_layout,
+                pipeline,
+            }
+        }
+    }
+
+    pub(super) unsafe fn bind(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        descriptor_set: vk::DescriptorSet,
+    ) {
+        device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, self.pipeline);
+        device.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::COMPUTE,
+            self.pipeline_layout,
+            0,
+            &[descriptor_set],
+            &[],
+        );
+    }
+
+    pub(super) unsafe fn destroy(&self, device: &ash::Device) {
+        device.destroy_pipeline(self.pipeline, None);
+        device.destroy_pipeline_layout(self.pipeline_layout, None);
+        device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+    }
+}
@@ -1,4 +1,4 @@
-use ash::vk::make_api_version;
+use ash::vk::{make_api_version, Format};
 pub const API_VERSION: u32 = make_api_version(0, 1, 0, 92);
 pub const APPLICATION_VERSION: u32 = make_api_version(
     0,
@@ -15,12 +15,22 @@ pub const ENGINE_VERSION: u32 = make_api_version(
 pub const DEVICE_EXTENSIONS: [&str; 2] = ["VK_KHR_swapchain", "VK_KHR_maintenance1"];
 pub const USE_VSYNC: bool = false;
 
-#[cfg(debug_assertions)]
 pub const VALIDATION_LAYER_NAME: &str = "VK_LAYER_KHRONOS_validation";
 
-// TODO this should removed. See where swapchain images are created.
-pub const MAX_FRAMES_IN_FLIGHT: usize = 3;
-
-pub const UNIFORM_DESCRIPTOR_POOL_SIZE: u32 = 10;
-pub const SAMPLER_DESCRIPTOR_POOL_SIZE: u32 = 5;
+// Minimum sizes of descriptor pools, allocations exceeding them get a larger pool
+pub const DESCRIPTOR_POOL_MAX_SETS: u32 = 64;
+pub const UNIFORM_DESCRIPTOR_POOL_SIZE: u32 = 128;
+pub const STORAGE_DESCRIPTOR_POOL_SIZE: u32 = 64;
+pub const STORAGE_DYNAMIC_DESCRIPTOR_POOL_SIZE: u32 = 64;
+pub const SAMPLER_DESCRIPTOR_POOL_SIZE: u32 = 128;
+pub const STORAGE_IMAGE_DESCRIPTOR_POOL_SIZE: u32 = 32;
 pub const DYNAMIC_BUFFER_INITIAL_CAPACITY: usize = 100;
+// Slots of the bindless texture array, clamped to the limits of the device
+pub const BINDLESS_TEXTURE_CAPACITY: u32 = 4096;
+
+// Minimum maxMultiviewViewCount guaranteed by the spec
+pub const MAX_MULTIVIEW_VIEWS: u32 = 6;
+
+// Weighted blended transparency targets, both formats support blending on every device
+pub const OIT_ACCUMULATION_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+pub const OIT_REVEALAGE_FORMAT: Format = Format::R16_SFLOAT;
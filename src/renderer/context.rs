@@ -1,39 +1,65 @@
-use std::collections::HashSet;
-use std::ffi::CString;
+use std::ffi::{CStr, CString};
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
 use std::ptr;
 
 use ash::vk;
 use ash::vk::{PhysicalDevice, PhysicalDeviceMemoryProperties};
+use cgmath::Matrix4;
 use winit::window::Window;
-use raw_window_handle::HasRawDisplayHandle;
+use raw_window_handle::{HasRawDisplayHandle, RawDisplayHandle, RawWindowHandle};
 
+use crate::engine::datatypes::{InstanceBounds, NormalVertex, SkinnedVertex};
+use crate::engine::image::Image;
+use crate::log::crash;
 use crate::renderer::memory::MemoryManager;
 use crate::renderer::synchronization::SynchronizationHandler;
 use crate::renderer::types::{
-    BufferObjectHandle, DrawCommand, Index, PipelineConfiguration, PipelineHandle, RenderPassHandle, UniformStage,
+    BlendMode, BufferObjectHandle, ContextConfiguration, DrawCommand, FrameGlobals, Index, PipelineConfiguration,
+    PipelineConfigurationBuilder, PipelineConfigError, PipelineHandle, RenderPassHandle, UniformHandle, UniformStage,
+    Viewport,
 };
+use crate::renderer::variant::{PipelineVariants, ShaderVariants};
+use crate::renderer::resources::{ResourceQueue, Resources};
+use crate::renderer::vertex::VertexLayout;
 use crate::ENGINE_NAME;
 
 use super::constants;
 use super::constants::{API_VERSION, APPLICATION_VERSION, ENGINE_VERSION};
+use super::capture;
 use super::debug;
+use super::graph;
 use super::image;
+use super::layouts;
 use super::queue::QueueFamilyIndices;
 use super::surface::SurfaceContainer;
 use super::swapchain;
 use super::vulkan_util;
 use crate::renderer::buffer::{BufferObjectManager, BufferObjectType};
-use crate::renderer::constants::DYNAMIC_BUFFER_INITIAL_CAPACITY;
+use crate::renderer::constants::{
+    DYNAMIC_BUFFER_INITIAL_CAPACITY, MAX_MULTIVIEW_VIEWS, OIT_ACCUMULATION_FORMAT, OIT_REVEALAGE_FORMAT,
+};
+use crate::renderer::dispatch::DispatchManager;
+use crate::renderer::occlusion::OcclusionCuller;
 use crate::renderer::pass::RenderPassManager;
+use crate::renderer::skinning::SkinningManager;
+use crate::renderer::timestamp::GpuTimer;
+#[cfg(feature = "raw-vulkan")]
+use crate::renderer::raw::{RawContext, RawFrameCallback};
 use crate::renderer::stats::RenderStats;
 use crate::renderer::texture::TextureManager;
-use crate::renderer::types::{SamplerHandle, TextureHandle};
+use crate::renderer::types::{
+    ComputeBufferHandle, ComputePipelineConfiguration, ComputePipelineHandle, CulledInstances, CulledInstancesHandle,
+    SampleCount, SamplerConfiguration, SamplerHandle, SkinnedMeshHandle, TextureHandle, TextureUsage,
+    TransparencyTargets, DEFAULT_FRAMES_IN_FLIGHT, MAX_FRAMES_IN_FLIGHT,
+};
 use crate::renderer::types::VertexInputDescription;
 use ash::extensions::ext::DebugUtils;
-use std::time::Instant;
+use ash::extensions::khr::{GetPhysicalDeviceProperties2, PushDescriptor};
+use std::time::{Duration, Instant};
 
 pub struct Context {
-    _entry: ash::Entry,
+    entry: ash::Entry,
     instance: ash::Instance,
 
     physical_device: PhysicalDevice,
@@ -45,103 +71,246 @@ pub struct Context {
     transfer_queue: vk::Queue,
     present_queue: vk::Queue,
 
-    surface_container: SurfaceContainer,
+    surface_container: Option<SurfaceContainer>,
 
     render_pass_manager: RenderPassManager,
     texture_manager: TextureManager,
+    // Created on first use, see fallback_texture
+    fallback_texture: Option<TextureHandle>,
     memory_manager: MemoryManager,
     buffer_object_manager: BufferObjectManager,
+    dispatch_manager: DispatchManager,
+    skinning_manager: SkinningManager,
+    occlusion_culler: OcclusionCuller,
+    gpu_timer: Option<GpuTimer>,
 
     command_pool: vk::CommandPool,
     draw_command_buffers: Vec<vk::CommandBuffer>,
+    // Of the transfer queue family
+    transfer_command_pool: vk::CommandPool,
     transfer_command_buffers: Vec<vk::CommandBuffer>,
 
     sync_handler: SynchronizationHandler,
 
-    #[allow(dead_code)]
     debug_utils_loader: DebugUtils,
-    #[allow(dead_code)]
+    // Null without validation
     debug_utils_messenger: vk::DebugUtilsMessengerEXT,
+    gpu_validation: bool,
+    // The image layouts of the passes are checked every frame, also without the validation layer installed
+    layout_tracking: bool,
 
     is_framebuffer_resized: bool,
+    multiview_supported: bool,
+    dual_source_blending_supported: bool,
+    // Format and present mode, None for headless contexts
+    swapchain_config: Option<(vk::Format, vk::PresentModeKHR)>,
+
+    frame_globals: BufferObjectHandle<FrameGlobals>,
+    frame_globals_data: FrameGlobals,
+
+    resource_queue: ResourceQueue,
+
+    // Set by capture_next_frame, the capture is taken when the frame is submitted
+    frame_capture_requested: bool,
+    frame_capture: Option<String>,
+
+    #[cfg(feature = "raw-vulkan")]
+    raw_frame_callback: std::cell::RefCell<Option<RawFrameCallback>>,
 }
 
 impl Context {
     pub fn new(window: &Window) -> Context {
+        Self::new_with_configuration(window, ContextConfiguration::new())
+    }
+
+    pub fn new_with_configuration(window: &Window, configuration: ContextConfiguration) -> Context {
         let entry = unsafe { ash::Entry::load().unwrap() };
+
+        Self::_new(entry, Some(SurfaceSource::Window(window)), configuration).expect("Failed to create context")
+    }
+
+    // For hosts that own the window and the event loop. They drive the frames with begin_frame/end_frame
+    // and call resize_surface when the window size changes.
+    //
+    // Safety: the handles must stay valid until the context is dropped.
+    pub unsafe fn from_raw_handles(
+        display: RawDisplayHandle,
+        window: RawWindowHandle,
+        extent: (u32, u32),
+    ) -> Result<Context, &'static str> {
+        Self::from_raw_handles_with_configuration(display, window, extent, ContextConfiguration::new())
+    }
+
+    pub unsafe fn from_raw_handles_with_configuration(
+        display: RawDisplayHandle,
+        window: RawWindowHandle,
+        extent: (u32, u32),
+        configuration: ContextConfiguration,
+    ) -> Result<Context, &'static str> {
+        let entry = ash::Entry::load().map_err(|_| "Failed to load Vulkan library")?;
+
+        let extent = vk::Extent2D {
+            width: extent.0,
+            height: extent.1,
+        };
+        Self::_new(entry, Some(SurfaceSource::RawHandles(display, window, extent)), configuration)
+    }
+
+    pub fn new_headless() -> Result<Context, &'static str> {
+        let entry = unsafe { ash::Entry::load() }.map_err(|_| "Failed to load Vulkan library")?;
+
+        Self::_new(entry, None, ContextConfiguration::new())
+    }
+
+    fn _new(
+        entry: ash::Entry,
+        surface_source: Option<SurfaceSource>,
+        configuration: ContextConfiguration,
+    ) -> Result<Context, &'static str> {
         debug::log_instance_layer_properties(&entry);
 
-        #[cfg(debug_assertions)]
         let mut layers = Vec::new();
-        #[cfg(debug_assertions)]
-        if _check_instance_layer_support(&entry, constants::VALIDATION_LAYER_NAME) {
-            layers.push(constants::VALIDATION_LAYER_NAME);
+        if configuration.validation() {
+            if _check_instance_layer_support(&entry, constants::VALIDATION_LAYER_NAME) {
+                layers.push(constants::VALIDATION_LAYER_NAME);
+            } else {
+                log_warning!("{} not installed, validation is disabled", constants::VALIDATION_LAYER_NAME);
+            }
         }
-        #[cfg(not(debug_assertions))]
-        let layers: Vec<&str> = Vec::new();
-
-        let instance = _create_instance(&entry, &layers, window);
-        let (debug_utils_loader, debug_utils_messenger) = debug::setup_debug_utils(&entry, &instance);
+        let validation = !layers.is_empty();
+        let layout_tracking = configuration.validation();
+        let gpu_validation = validation && configuration.gpu_validation && _check_gpu_validation_support(&entry);
+
+        for extension in configuration.instance_extensions.iter() {
+            if !_check_instance_extension_support(&entry, extension) {
+                log_error!("Requested instance extension {:?} is not supported", extension);
+                return Err("Requested instance extension is not supported");
+            }
+        }
+        let instance = _create_instance(
+            &entry,
+            &layers,
+            surface_source.as_ref().map(SurfaceSource::display_handle),
+            &configuration,
+            gpu_validation,
+        );
+        let (debug_utils_loader, debug_utils_messenger) = debug::setup_debug_utils(&entry, &instance, validation);
 
         debug::log_physical_devices(&instance);
 
-        let surface_container = SurfaceContainer::new(&entry, &instance, window);
+        let surface_container = surface_source.map(|source| match source {
+            SurfaceSource::Window(window) => SurfaceContainer::new(&entry, &instance, window),
+            SurfaceSource::RawHandles(display, window, extent) => {
+                SurfaceContainer::from_raw_handles(&entry, &instance, display, window, extent)
+            }
+        });
 
-        let physical_device = _pick_physical_device(&instance);
+        // Headless contexts are mainly used for testing, so prefer software rasterizers when available.
+        let physical_device = _pick_physical_device(&instance, surface_container.is_none())?;
         let physical_device_memory_properties =
             unsafe { instance.get_physical_device_memory_properties(physical_device) };
         log_info!("Picked Physical device: ");
         debug::log_physical_device(&instance, &physical_device);
+        crash::set_device_info(debug::describe_physical_device(&instance, &physical_device));
         debug::log_device_queue_families(&instance, &physical_device);
         debug::log_physical_device_extensions(&instance, &physical_device);
 
-        let queue_families = QueueFamilyIndices::new(&instance, &physical_device, &surface_container);
+        let queue_families = QueueFamilyIndices::new(&instance, &physical_device, surface_container.as_ref());
         log_info!("Picked Queue families: {}", queue_families);
 
-        let logical_device = create_logical_device(&instance, &physical_device, &queue_families);
+        for extension in configuration.device_extensions.iter() {
+            if !_check_device_extension_support(&instance, &physical_device, extension) {
+                log_error!("Requested device extension {:?} is not supported", extension);
+                return Err("Requested device extension is not supported");
+            }
+        }
+        let bindless_texture_capacity = _bindless_texture_capacity(&entry, &instance, &physical_device);
+        let (logical_device, push_descriptor_supported, multiview_supported, dual_source_blending_supported) =
+            create_logical_device(
+                &instance,
+                &physical_device,
+                &queue_families,
+                &configuration,
+                bindless_texture_capacity.is_some(),
+            );
         let graphics_queue = unsafe {
             logical_device.get_device_queue(
                 queue_families.graphics.family_index,
                 queue_families.graphics.queue_index,
             )
         };
-        // TODO: transfer queue should be its own thing
         let transfer_queue = unsafe {
             logical_device.get_device_queue(
-                queue_families.graphics.family_index,
-                queue_families.graphics.queue_index,
+                queue_families.transfer.family_index,
+                queue_families.transfer.queue_index,
             )
         };
         let present_queue = unsafe {
             logical_device.get_device_queue(queue_families.present.family_index, queue_families.present.queue_index)
         };
 
-        let command_pool = _create_command_pool(&logical_device, &queue_families);
+        let frames_in_flight = configuration.frames_in_flight.unwrap_or(DEFAULT_FRAMES_IN_FLIGHT);
+        if !(1..=MAX_FRAMES_IN_FLIGHT).contains(&frames_in_flight) {
+            return Err("Invalid number of frames in flight!");
+        }
 
-        let swapchain_container = swapchain::create_swapchain(
-            &instance,
-            &logical_device,
-            physical_device,
-            &surface_container,
-            &queue_families,
-        );
+        let command_pool = _create_command_pool(&logical_device, queue_families.graphics.family_index);
+        let transfer_command_pool = _create_command_pool(&logical_device, queue_families.transfer.family_index);
 
-        let image_count = swapchain_container.image_views.len();
+        let push_descriptor = if push_descriptor_supported {
+            Some(PushDescriptor::new(&instance, &logical_device))
+        } else {
+            None
+        };
+        let mut render_pass_handler = RenderPassManager::new(&instance, physical_device, push_descriptor);
+        render_pass_handler.set_frames_in_flight(frames_in_flight);
+
+        let mut swapchain_config = None;
+        if let Some(surface_container) = &surface_container {
+            let swapchain_container = swapchain::create_swapchain(
+                &instance,
+                &logical_device,
+                physical_device,
+                surface_container,
+                &queue_families,
+            );
+
+            swapchain_config = Some((swapchain_container.format, swapchain_container.present_mode));
+            render_pass_handler.create_swapchain_pass(
+                &logical_device,
+                &physical_device_memory_properties,
+                swapchain_container,
+            );
+        }
 
-        let mut render_pass_handler = RenderPassManager::new(&instance, physical_device);
-        render_pass_handler.create_swapchain_pass(
+        let mut texture_manager = TextureManager::new();
+        if let Some(capacity) = bindless_texture_capacity {
+            texture_manager.enable_bindless(&logical_device, capacity);
+        }
+
+        let mut memory_manager = MemoryManager::new(physical_device_memory_properties);
+        let mut buffer_object_manager = BufferObjectManager::new(frames_in_flight, &queue_families);
+        let frame_globals = buffer_object_manager.create_buffer::<FrameGlobals>(
             &logical_device,
-            &physical_device_memory_properties,
-            swapchain_container,
+            &mut memory_manager,
+            1,
+            BufferObjectType::FrameGlobals,
+            false,
+        );
+        let draw_command_buffers = _create_command_buffers(&logical_device, command_pool, frames_in_flight);
+        let transfer_command_buffers =
+            _create_command_buffers(&logical_device, transfer_command_pool, frames_in_flight);
+        let sync_handler = SynchronizationHandler::new(&logical_device, frames_in_flight);
+        let gpu_timer = GpuTimer::new(
+            &instance,
+            physical_device,
+            &logical_device,
+            queue_families.graphics.family_index,
+            frames_in_flight,
         );
 
-        let memory_manager = MemoryManager::new(physical_device_memory_properties);
-        let draw_command_buffers = _create_command_buffers(&logical_device, command_pool, image_count);
-        let transfer_command_buffers = _create_command_buffers(&logical_device, command_pool, image_count);
-        let sync_handler = SynchronizationHandler::new(&logical_device);
-
-        Context {
-            _entry: entry,
+        Ok(Context {
+            entry,
             instance,
             physical_device,
             physical_device_memory_properties,
@@ -152,28 +321,84 @@ impl Context {
             present_queue,
             surface_container,
             render_pass_manager: render_pass_handler,
-            texture_manager: TextureManager::new(),
+            texture_manager,
+            fallback_texture: None,
             memory_manager,
-            buffer_object_manager: BufferObjectManager::new(image_count),
+            buffer_object_manager,
             command_pool,
             draw_command_buffers,
+            transfer_command_pool,
             transfer_command_buffers,
             sync_handler,
             debug_utils_loader,
             debug_utils_messenger,
+            gpu_validation,
+            layout_tracking,
             is_framebuffer_resized: false,
-        }
+            multiview_supported,
+            dual_source_blending_supported,
+            swapchain_config,
+            frame_globals,
+            frame_globals_data: FrameGlobals::default(),
+            resource_queue: ResourceQueue::new(),
+            frame_capture_requested: false,
+            frame_capture: None,
+            dispatch_manager: DispatchManager::new(),
+            skinning_manager: SkinningManager::new(),
+            occlusion_culler: OcclusionCuller::new(),
+            gpu_timer,
+            #[cfg(feature = "raw-vulkan")]
+            raw_frame_callback: std::cell::RefCell::new(None),
+        })
     }
 
-    pub fn begin_frame(&mut self) {
-        self.render_pass_manager.reset_draw_command_buffers();
+    // Advances the values of the FrameGlobals uniform, delta_time_s is the simulation time of the frame.
+    pub fn update_frame_globals(&mut self, delta_time_s: f32) {
+        let resolution = if self.surface_container.is_some() {
+            let extent = self.render_pass_manager.swapchain_extent();
+            [extent.width as f32, extent.height as f32]
+        } else {
+            [0.0, 0.0]
+        };
+
+        let data = &mut self.frame_globals_data;
+        data.resolution = resolution;
+        data.time += delta_time_s;
+        data.delta_time = delta_time_s;
+        data.frame_index = data.frame_index.wrapping_add(1);
+
+        self.set_buffer_object(self.frame_globals, self.frame_globals_data);
     }
 
-    pub fn add_draw_command(&mut self, draw_command: DrawCommand) {
-        self.render_pass_manager.add_draw_command(draw_command);
+    // Draw commands are added to the returned frame, which renders them when submitted
+    pub fn begin_frame(&mut self) -> Frame<'_> {
+        self.process_resource_requests();
+        self.render_pass_manager.reset_draw_command_buffers();
+        self.dispatch_manager.reset_queue();
+        self.skinning_manager.reset_queue();
+        self.occlusion_culler.reset_queue();
+
+        Frame { context: self }
     }
 
-    pub fn end_frame(&mut self) -> RenderStats {
+    fn end_frame(&mut self) -> RenderStats {
+        debug_assert!(
+            !self.render_pass_manager.is_pipeline_batch_active(),
+            "Pipeline batch still active at end of frame!"
+        );
+        if std::mem::take(&mut self.frame_capture_requested) {
+            let passes = self.render_pass_manager.captured_passes();
+            self.frame_capture = Some(capture::to_json(self.frame_globals_data.frame_index, &passes));
+        }
+        if self.layout_tracking {
+            if let Err(message) = layouts::validate(&self.render_pass_manager.layout_passes()) {
+                panic!("Image layout mismatch: {}", message);
+            }
+        }
+        if self.surface_container.is_none() {
+            return self.end_frame_headless();
+        }
+
         let mut stats = RenderStats::new();
 
         let wait_fences = [self.sync_handler.inflight_fence()];
@@ -186,7 +411,7 @@ impl Context {
                 .expect("Failed to reset Fence!");
         }
 
-        let (image_index, _is_sub_optimal) = unsafe {
+        let (image_index, is_sub_optimal) = unsafe {
             let result = self.render_pass_manager.swapchain_target().loader().acquire_next_image(
                 self.render_pass_manager.swapchain_target().swapchain(),
                 u64::MAX,
@@ -201,41 +426,33 @@ impl Context {
                         self.recreate_swapchain();
                         return stats;
                     }
-                    _ => panic!("Failed to acquire Swap Chain Image!"),
+                    vk::Result::ERROR_SURFACE_LOST_KHR => {
+                        log_warning!("Surface lost on acquire, recreating surface...");
+                        self.recreate_surface();
+                        return stats;
+                    }
+                    _ => panic!("Failed to acquire Swap Chain Image! {:?}", vk_result),
                 },
             }
         };
 
-        let image_index_usize = image_index as usize;
+        // The acquired image is still presentable, so finish the frame and recreate after present.
+        if is_sub_optimal {
+            log_info!("Suboptimal swapchain on acquire, recreating after present...");
+            self.is_framebuffer_resized = true;
+        }
 
-        // Transfer data
-        let transfer_command_buffer = self.transfer_command_buffers[image_index_usize];
-        let transfer_required = self.buffer_object_manager.bake_command_buffer(
-            &self.logical_device,
-            &mut self.memory_manager,
-            transfer_command_buffer,
-            image_index_usize,
-            &mut stats,
-        );
+        // Everything but the framebuffer is replicated per frame in flight, the fence waited on above guards them
+        let frame_index = self.sync_handler.frame_index();
 
-        if transfer_required {
-            // Submit
-            let transfer_command_buffers = [transfer_command_buffer];
-            let transfer_signal_semaphores = [self.sync_handler.transfer_finished_semaphore()];
-            let transfer_submit_infos = [vk::SubmitInfo::builder()
-                .command_buffers(&transfer_command_buffers)
-                .signal_semaphores(&transfer_signal_semaphores)
-                .build()];
-            unsafe {
-                self.logical_device
-                    .queue_submit(self.transfer_queue, &transfer_submit_infos, vk::Fence::null())
-                    .expect("Failed to execute queue submit.");
-            }
-        }
+        let transfer_required = self.submit_transfer(frame_index, &mut stats);
 
         // Draw
-        let draw_command_buffer = self.draw_command_buffers[image_index_usize];
-        self.bake_draw_command_buffer(draw_command_buffer, image_index_usize, &mut stats);
+        self.render_pass_manager.update_stale_descriptor_sets(&self.logical_device, frame_index);
+        let draw_command_buffer = self.draw_command_buffers[frame_index];
+        stats.gpu_time = self.gpu_time(frame_index);
+        self.prepare_occlusion_culling();
+        self.bake_draw_command_buffer(draw_command_buffer, frame_index, image_index as usize, &mut stats);
 
         let draw_command_buffers = [draw_command_buffer];
 
@@ -246,7 +463,7 @@ impl Context {
         draw_wait_semaphores.push(self.sync_handler.image_available_semaphore());
 
         let draw_wait_stages = [
-            vk::PipelineStageFlags::VERTEX_INPUT,
+            vk::PipelineStageFlags::VERTEX_INPUT | vk::PipelineStageFlags::COMPUTE_SHADER,
             vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
         ];
 
@@ -268,6 +485,9 @@ impl Context {
                 )
                 .expect("Failed to execute queue submit.");
         }
+        if let Some(gpu_timer) = &mut self.gpu_timer {
+            gpu_timer.set_submitted(frame_index);
+        }
 
         // Present
         let swapchains = [self.render_pass_manager.swapchain_target().swapchain()];
@@ -287,10 +507,17 @@ impl Context {
                 .queue_present(self.present_queue, &present_info);
 
             let is_resized = match result {
-                Ok(_) => self.is_framebuffer_resized,
+                Ok(is_sub_optimal) => is_sub_optimal || self.is_framebuffer_resized,
                 Err(vk_result) => match vk_result {
                     vk::Result::ERROR_OUT_OF_DATE_KHR | vk::Result::SUBOPTIMAL_KHR => true,
-                    _ => panic!("Failed to execute queue present."),
+                    vk::Result::ERROR_SURFACE_LOST_KHR => {
+                        log_warning!("Surface lost on present, recreating surface...");
+                        self.is_framebuffer_resized = false;
+                        self.recreate_surface();
+                        self.sync_handler.step();
+                        return stats;
+                    }
+                    _ => panic!("Failed to execute queue present. {:?}", vk_result),
                 },
             };
             if is_resized {
@@ -307,6 +534,136 @@ impl Context {
         stats
     }
 
+    fn end_frame_headless(&mut self) -> RenderStats {
+        let mut stats = RenderStats::new();
+
+        let wait_fences = [self.sync_handler.inflight_fence()];
+        unsafe {
+            self.logical_device
+                .wait_for_fences(&wait_fences, true, u64::MAX)
+                .expect("Failed to wait for Fence!");
+            self.logical_device
+                .reset_fences(&wait_fences)
+                .expect("Failed to reset Fence!");
+        }
+
+        let frame_index = self.sync_handler.frame_index();
+        let transfer_required = self.submit_transfer(frame_index, &mut stats);
+
+        self.render_pass_manager.update_stale_descriptor_sets(&self.logical_device, frame_index);
+
+        let draw_command_buffer = self.draw_command_buffers[frame_index];
+        stats.gpu_time = self.gpu_time(frame_index);
+        self.prepare_occlusion_culling();
+        self.bake_draw_command_buffer(draw_command_buffer, frame_index, 0, &mut stats);
+
+        // Without a swapchain only the transfer is waited for
+        let command_buffers = [draw_command_buffer];
+        let wait_semaphores = [self.sync_handler.transfer_finished_semaphore()];
+        let wait_stages = [vk::PipelineStageFlags::VERTEX_INPUT | vk::PipelineStageFlags::COMPUTE_SHADER];
+        let wait_count = if transfer_required { 1 } else { 0 };
+
+        let submit_infos = [vk::SubmitInfo::builder()
+            .wait_semaphores(&wait_semaphores[..wait_count])
+            .wait_dst_stage_mask(&wait_stages[..wait_count])
+            .command_buffers(&command_buffers)
+            .build()];
+        unsafe {
+            self.logical_device
+                .queue_submit(self.graphics_queue, &submit_infos, self.sync_handler.inflight_fence())
+                .expect("Failed to execute queue submit.");
+        }
+        if let Some(gpu_timer) = &mut self.gpu_timer {
+            gpu_timer.set_submitted(frame_index);
+        }
+
+        self.sync_handler.step();
+
+        stats
+    }
+
+    // Uploads the dirty buffer objects of the frame on the transfer queue, returns true when the draw submission
+    // has to wait for the transfer finished semaphore
+    fn submit_transfer(&mut self, frame_index: usize, stats: &mut RenderStats) -> bool {
+        let transfer_command_buffer = self.transfer_command_buffers[frame_index];
+        let transfer_required = self.buffer_object_manager.bake_command_buffer(
+            &self.logical_device,
+            &mut self.memory_manager,
+            transfer_command_buffer,
+            frame_index,
+            stats,
+        );
+        if !transfer_required {
+            return false;
+        }
+
+        let transfer_command_buffers = [transfer_command_buffer];
+        let transfer_signal_semaphores = [self.sync_handler.transfer_finished_semaphore()];
+        let transfer_submit_infos = [vk::SubmitInfo::builder()
+            .command_buffers(&transfer_command_buffers)
+            .signal_semaphores(&transfer_signal_semaphores)
+            .build()];
+        unsafe {
+            self.logical_device
+                .queue_submit(self.transfer_queue, &transfer_submit_infos, vk::Fence::null())
+                .expect("Failed to execute queue submit.");
+        }
+
+        true
+    }
+
+    fn prepare_occlusion_culling(&mut self) {
+        self.occlusion_culler.prepare(
+            &self.logical_device,
+            self.command_pool,
+            self.graphics_queue,
+            &mut self.memory_manager,
+            &self.render_pass_manager,
+        );
+    }
+
+    // The command buffer of the frame is about to be recorded again, so its last submission has finished
+    fn gpu_time(&self, frame_index: usize) -> Option<Duration> {
+        self.gpu_timer
+            .as_ref()
+            .and_then(|gpu_timer| gpu_timer.elapsed(&self.logical_device, frame_index))
+    }
+
+    pub fn read_render_texture(&mut self, texture: TextureHandle) -> Vec<u8> {
+        assert!(
+            self.texture_manager.get_usage(texture).transfer_src,
+            "Texture can't be read back, it has to be created with the transfer_src usage"
+        );
+
+        unsafe {
+            self.wait_idle();
+        }
+
+        let image = self.texture_manager.get_image(texture);
+        let (width, height) = self.texture_manager.get_extent(texture);
+
+        image::read_color_image(
+            &self.logical_device,
+            self.command_pool,
+            self.graphics_queue,
+            &mut self.memory_manager,
+            image,
+            width,
+            height,
+        )
+    }
+
+    // Handle for creating resources from other threads, the requests are executed by begin_frame
+    pub fn resources(&self) -> Resources {
+        self.resource_queue.resources()
+    }
+
+    pub fn process_resource_requests(&mut self) {
+        for request in self.resource_queue.take_requests() {
+            request(self);
+        }
+    }
+
     pub fn create_static_vertex_buffer_sync<T: VertexInputDescription>(&mut self, vertices: &[T]) -> vk::Buffer {
         self.memory_manager.create_static_vertex_buffer_sync(
             &self.logical_device,
@@ -316,12 +673,129 @@ impl Context {
         )
     }
 
+    // Vertices packed according to a VertexLayout
+    pub fn create_static_vertex_buffer_from_bytes_sync(&mut self, vertex_data: &[u8]) -> vk::Buffer {
+        self.memory_manager.create_static_vertex_buffer_from_bytes_sync(
+            &self.logical_device,
+            self.command_pool,
+            self.graphics_queue,
+            vertex_data,
+        )
+    }
+
     pub fn create_static_index_buffer_sync(&mut self, indices: &[Index]) -> vk::Buffer {
         self.memory_manager
             .create_index_buffer(&self.logical_device, self.command_pool, self.graphics_queue, indices)
     }
 
-    pub fn create_uniform_buffer<T>(&mut self, stage: UniformStage) -> BufferObjectHandle {
+    // Mesh skinned on the GPU with the joint matrices of the storage buffer, see AnimatedModel::joint_buffer.
+    // Queue it with Frame::skin_mesh and draw the vertex buffer of skinned_vertex_buffer, it holds NormalVertex.
+    pub fn add_skinned_mesh(
+        &mut self,
+        vertices: &[SkinnedVertex],
+        joint_buffer: BufferObjectHandle<Matrix4<f32>>,
+    ) -> SkinnedMeshHandle {
+        let source_buffer = self.memory_manager.create_static_storage_buffer_sync(
+            &self.logical_device,
+            self.command_pool,
+            self.graphics_queue,
+            vertices,
+        );
+        let vertex_buffer = self.memory_manager.create_device_buffer(
+            &self.logical_device,
+            (vertices.len() * std::mem::size_of::<NormalVertex>()) as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::VERTEX_BUFFER,
+        );
+
+        self.skinning_manager.add_mesh(
+            &self.logical_device,
+            source_buffer,
+            vertex_buffer,
+            vertices.len() as u32,
+            joint_buffer.index(),
+        )
+    }
+
+    pub fn skinned_vertex_buffer(&self, skinned_mesh: SkinnedMeshHandle) -> vk::Buffer {
+        self.skinning_manager.vertex_buffer(skinned_mesh)
+    }
+
+    // Compute shader run with Frame::dispatch before the render passes of the frame, its writes are visible to
+    // all of them
+    pub fn add_compute_pipeline(&mut self, config: ComputePipelineConfiguration) -> ComputePipelineHandle {
+        if let Err(err) = config.validate_limits(&self.device_limits()) {
+            panic!("Invalid compute pipeline configuration: {}", err);
+        }
+        self.dispatch_manager.add_pipeline(&self.logical_device, config)
+    }
+
+    // Device local buffer only written by compute pipelines, e.g. simulated particles. It can be drawn as vertex,
+    // index or indirect buffer with the buffer of compute_buffer. The contents are undefined until written.
+    pub fn create_compute_buffer(&mut self, size: usize) -> ComputeBufferHandle {
+        let buffer = self.memory_manager.create_device_buffer(
+            &self.logical_device,
+            size as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER
+                | vk::BufferUsageFlags::VERTEX_BUFFER
+                | vk::BufferUsageFlags::INDEX_BUFFER
+                | vk::BufferUsageFlags::INDIRECT_BUFFER,
+        );
+
+        self.dispatch_manager.add_buffer(buffer)
+    }
+
+    pub fn compute_buffer(&self, buffer: ComputeBufferHandle) -> vk::Buffer {
+        self.dispatch_manager.buffer(buffer)
+    }
+
+    // Linear RGBA texture written by compute pipelines and sampled like any other texture in between
+    pub fn add_storage_texture(&mut self, image_width: u32, image_height: u32) -> TextureHandle {
+        let format = vk::Format::R8G8B8A8_UNORM;
+        let (image, image_memory) = image::create_compute_texture_image(
+            &self.logical_device,
+            self.command_pool,
+            self.graphics_queue,
+            &mut self.memory_manager,
+            vk::Extent2D { width: image_width, height: image_height },
+            format,
+        );
+        let image_view = image::create_image_view(&self.logical_device, image, format, vk::ImageAspectFlags::COLOR, 1);
+
+        let texture =
+            self.texture_manager.add_texture(image, image_memory, image_view, image_width, image_height, format);
+        self.texture_manager.set_usage(texture, TextureUsage::STORAGE);
+        self.dispatch_manager.add_storage_texture(texture);
+
+        texture
+    }
+
+    // Depth buffer of the image pass the HiZ pyramid of occlusion culling is built from, e.g. the main scene.
+    // Culls of a frame test against the depth of the previous one.
+    pub fn set_hiz_source(&mut self, pass: RenderPassHandle) -> Result<(), &'static str> {
+        self.render_pass_manager.depth_target(pass)?;
+        self.occlusion_culler.set_hiz_source(pass, self.render_pass_manager.depth_format());
+
+        Ok(())
+    }
+
+    // Instances of a mesh culled on the GPU against their world space bounds, the instance index selects the
+    // bounds. Queue the cull with Frame::cull_instances and draw with DrawCommand::with_culled_instances.
+    pub fn add_culled_instances(&mut self, index_count: u32, bounds: &[InstanceBounds]) -> CulledInstancesHandle {
+        self.occlusion_culler.add_group(
+            &self.logical_device,
+            self.command_pool,
+            self.graphics_queue,
+            &mut self.memory_manager,
+            index_count,
+            bounds,
+        )
+    }
+
+    pub fn culled_instances(&self, culled_instances: CulledInstancesHandle) -> CulledInstances {
+        self.occlusion_culler.culled_instances(culled_instances)
+    }
+
+    pub fn create_uniform_buffer<T>(&mut self, stage: UniformStage) -> BufferObjectHandle<T> {
         self.buffer_object_manager.create_buffer::<T>(
             &self.logical_device,
             &mut self.memory_manager,
@@ -331,7 +805,7 @@ impl Context {
         )
     }
 
-    pub fn create_vertex_buffer<T>(&mut self) -> BufferObjectHandle {
+    pub fn create_vertex_buffer<T>(&mut self) -> BufferObjectHandle<T> {
         self.buffer_object_manager.create_buffer::<T>(
             &self.logical_device,
             &mut self.memory_manager,
@@ -341,7 +815,7 @@ impl Context {
         )
     }
 
-    pub fn create_storage_buffer<T>(&mut self, capacity: usize) -> BufferObjectHandle {
+    pub fn create_storage_buffer<T>(&mut self, capacity: usize) -> BufferObjectHandle<T> {
         self.buffer_object_manager.create_buffer::<T>(
             &self.logical_device,
             &mut self.memory_manager,
@@ -351,7 +825,18 @@ impl Context {
         )
     }
 
+    // RGBA, data that doesn't match the size is logged and the fallback texture returned instead
     pub fn add_texture(&mut self, image_width: u32, image_height: u32, image_data: &[u8]) -> TextureHandle {
+        if image_width == 0 || image_height == 0 || image_data.len() != (image_width * image_height * 4) as usize {
+            log_error!(
+                "add_texture: {} bytes of data for a {}x{} texture, using the fallback texture",
+                image_data.len(),
+                image_width,
+                image_height
+            );
+            return self.fallback_texture();
+        }
+
         let (image, image_memory) = image::create_static_image(
             &self.logical_device,
             self.command_pool,
@@ -374,31 +859,267 @@ impl Context {
         self.texture_manager.add_texture(image, image_memory, image_view, image_width, image_height, format)
     }
 
+    // RGBA layers packed one after another, sampled as sampler2DArray. Data that doesn't match the size is logged and
+    // the fallback checkers are used for every layer instead.
+    pub fn add_texture_array(
+        &mut self,
+        image_width: u32,
+        image_height: u32,
+        layers: u32,
+        image_data: &[u8],
+    ) -> TextureHandle {
+        let max_layers = unsafe { self.instance.get_physical_device_properties(self.physical_device) }
+            .limits
+            .max_image_array_layers;
+        let layers = layers.clamp(1, max_layers);
+        if image_width == 0
+            || image_height == 0
+            || image_data.len() != (image_width * image_height * 4) as usize * layers as usize
+        {
+            log_error!(
+                "add_texture_array: {} bytes of data for {} layers of {}x{}, using the fallback texture",
+                image_data.len(),
+                layers,
+                image_width,
+                image_height
+            );
+            let fallback = Image::fallback();
+            return self.add_texture_array(
+                fallback.width,
+                fallback.height,
+                layers,
+                &fallback.data.repeat(layers as usize),
+            );
+        }
+
+        let extent = vk::Extent2D {
+            width: image_width,
+            height: image_height,
+        };
+        let (image, image_memory) = image::create_static_image_array(
+            &self.logical_device,
+            self.command_pool,
+            self.graphics_queue,
+            &mut self.memory_manager,
+            extent,
+            layers,
+            image_data,
+        );
+
+        let format = vk::Format::R8G8B8A8_SRGB;
+        let image_view = image::create_array_image_view(&self.logical_device, image, format, layers);
+
+        let texture =
+            self.texture_manager.add_texture(image, image_memory, image_view, image_width, image_height, format);
+        self.texture_manager.set_layer_count(texture, layers);
+
+        texture
+    }
+
+    // Six square RGBA faces packed in the order +x, -x, +y, -y, +z, -z, sampled as samplerCube, e.g. by a skybox. Data
+    // that doesn't match the size is logged and the fallback checkers are used for every face instead.
+    pub fn add_cube_texture(&mut self, face_size: u32, image_data: &[u8]) -> TextureHandle {
+        if face_size == 0 || image_data.len() != (face_size * face_size * 4) as usize * 6 {
+            log_error!(
+                "add_cube_texture: {} bytes of data for six {}x{} faces, using the fallback texture",
+                image_data.len(),
+                face_size,
+                face_size
+            );
+            let fallback = Image::fallback();
+            return self.add_cube_texture(fallback.width, &fallback.data.repeat(6));
+        }
+
+        let (image, image_memory) = image::create_static_cube_image(
+            &self.logical_device,
+            self.command_pool,
+            self.graphics_queue,
+            &mut self.memory_manager,
+            face_size,
+            image_data,
+        );
+
+        let format = vk::Format::R8G8B8A8_SRGB;
+        let image_view = image::create_cube_image_view(&self.logical_device, image, format);
+
+        self.texture_manager.add_cube_texture(image, image_memory, image_view, face_size, format)
+    }
+
+    // Magenta and black checkers standing in for textures that failed to load
+    pub fn fallback_texture(&mut self) -> TextureHandle {
+        if let Some(texture) = self.fallback_texture {
+            return texture;
+        }
+
+        let fallback = Image::fallback();
+        let texture = self.add_texture(fallback.width, fallback.height, &fallback.data);
+        self.fallback_texture = Some(texture);
+
+        texture
+    }
+
     pub fn add_render_texture(&mut self, image_width: u32, image_height: u32) -> TextureHandle {
+        self.add_render_texture_with_usage(image_width, image_height, TextureUsage::RENDER_TARGET)
+            .expect("Failed to create render texture!")
+    }
+
+    // Storage render textures are linear, sRGB formats can rarely be written by compute shaders. They are
+    // dispatched like textures of add_storage_texture.
+    pub fn add_render_texture_with_usage(
+        &mut self,
+        image_width: u32,
+        image_height: u32,
+        usage: TextureUsage,
+    ) -> Result<TextureHandle, &'static str> {
+        let format = if usage.storage {
+            vk::Format::R8G8B8A8_UNORM
+        } else {
+            vk::Format::R8G8B8A8_SRGB
+        };
+        let format_properties =
+            unsafe { self.instance.get_physical_device_format_properties(self.physical_device, format) };
+        usage.validate(format_properties.optimal_tiling_features)?;
+
+        let (image, image_memory, image_view, format) =
+            self.create_render_texture_image(image_width, image_height, 1, format, usage);
+
+        let texture =
+            self.texture_manager.add_texture(image, image_memory, image_view, image_width, image_height, format);
+        self.texture_manager.set_usage(texture, usage);
+        if usage.storage {
+            self.dispatch_manager.add_storage_texture(texture);
+        }
+
+        Ok(texture)
+    }
+
+    // Render texture with one layer per view, sampled as a 2D array. Render passes created with it use multiview.
+    pub fn add_layered_render_texture(&mut self, image_width: u32, image_height: u32, layers: u32) -> TextureHandle {
+        assert!(layers > 0 && layers <= MAX_MULTIVIEW_VIEWS, "Unsupported layer count for render texture");
+
+        let usage = TextureUsage::RENDER_TARGET;
+        let (image, image_memory, image_view, format) =
+            self.create_render_texture_image(image_width, image_height, layers, vk::Format::R8G8B8A8_SRGB, usage);
+
+        let texture =
+            self.texture_manager.add_texture(image, image_memory, image_view, image_width, image_height, format);
+        self.texture_manager.set_layer_count(texture, layers);
+        self.texture_manager.set_usage(texture, usage);
+
+        texture
+    }
+
+    // The texture and the passes rendering into it are recreated whenever the swapchain is resized.
+    pub fn add_render_texture_scaled(&mut self, factor: f32) -> TextureHandle {
+        let (width, height) = _scaled_extent(self.render_pass_manager.swapchain_extent(), factor);
+
+        let texture = self.add_render_texture(width, height);
+        self.texture_manager.set_render_scale(texture, factor);
+
+        texture
+    }
+
+    // Changes the factor of a texture created with add_render_texture_scaled, which is recreated right away
+    pub fn set_render_texture_scale(&mut self, texture: TextureHandle, factor: f32) {
+        assert!(
+            self.texture_manager.scaled_render_textures().iter().any(|(scaled, _)| *scaled == texture),
+            "Texture is not a scaled render texture"
+        );
+
+        self.texture_manager.set_render_scale(texture, factor);
+        unsafe {
+            self.wait_idle();
+        }
+        self.resize_scaled_render_textures(self.render_pass_manager.swapchain_extent());
+    }
+
+    fn create_render_texture_image(
+        &mut self,
+        image_width: u32,
+        image_height: u32,
+        layers: u32,
+        format: vk::Format,
+        usage: TextureUsage,
+    ) -> (vk::Image, vk::DeviceMemory, vk::ImageView, vk::Format) {
         let (image, image_memory) = image::create_colorattachment_image(
             &self.logical_device,
             self.command_pool,
             self.graphics_queue,
             &mut self.memory_manager,
-            image_width,
-            image_height
+            vk::Extent2D { width: image_width, height: image_height },
+            layers,
+            format,
+            usage.image_usage_flags(),
         );
 
-        let format = vk::Format::R8G8B8A8_SRGB;
-        let image_view = image::create_image_view(
+        let image_view = image::create_layered_image_view(
             &self.logical_device,
             image,
             format,
             vk::ImageAspectFlags::COLOR,
-            1,
+            layers,
         );
 
-        self.texture_manager.add_texture(image, image_memory, image_view, image_width, image_height, format)
+        (image, image_memory, image_view, format)
+    }
+
+    fn resize_scaled_render_textures(&mut self, swapchain_extent: vk::Extent2D) {
+        for (texture, factor) in self.texture_manager.scaled_render_textures() {
+            let (width, height) = _scaled_extent(swapchain_extent, factor);
+            if self.texture_manager.get_extent(texture) == (width, height) {
+                continue;
+            }
+
+            let format = self.texture_manager.get_format(texture);
+            let usage = self.texture_manager.get_usage(texture);
+            let (image, image_memory, image_view, format) =
+                self.create_render_texture_image(width, height, 1, format, usage);
+            unsafe {
+                self.texture_manager
+                    .replace_texture(&self.logical_device, texture, image, image_memory, image_view, (width, height));
+            }
+
+            self.render_pass_manager.replace_texture_view(texture, image_view);
+            self.render_pass_manager.resize_image_targets(
+                &self.logical_device,
+                &self.physical_device_memory_properties,
+                texture,
+                image_view,
+                format,
+                vk::Extent2D { width, height },
+            );
+        }
+    }
+
+
+    pub fn add_sampler(&mut self, cfg: SamplerConfiguration) -> SamplerHandle {
+        self.texture_manager.add_sampler(&self.logical_device, cfg)
     }
 
+    // Index of the texture in the array bound by pipelines built with_bindless_textures, for the shaders to sample
+    // it with. Registering the same texture and sampler again returns the same index.
+    pub fn add_bindless_texture(
+        &mut self,
+        texture: TextureHandle,
+        sampler: SamplerHandle,
+    ) -> Result<u32, &'static str> {
+        self.texture_manager.add_bindless_texture(&self.logical_device, texture, sampler)
+    }
 
-    pub fn add_sampler(&mut self) -> SamplerHandle {
-        self.texture_manager.add_sampler(&self.logical_device)
+    pub fn set_pipeline_texture(
+        &mut self,
+        pipeline: PipelineHandle,
+        binding: u8,
+        texture: TextureHandle,
+        sampler: SamplerHandle,
+    ) -> Result<(), &'static str> {
+        self.render_pass_manager.set_pipeline_texture(
+            pipeline,
+            binding,
+            texture,
+            self.texture_manager.get_imageview(texture),
+            self.texture_manager.get_sampler(sampler),
+        )
     }
 
     pub fn add_pipeline<T: VertexInputDescription>(
@@ -406,12 +1127,72 @@ impl Context {
         render_pass: RenderPassHandle,
         config: PipelineConfiguration,
     ) -> PipelineHandle {
-        self.render_pass_manager.add_pipeline::<T>(
+        self.add_pipeline_with_layout(render_pass, config, VertexLayout::from_description::<T>())
+    }
+
+    // Vertex format only known at runtime, e.g. matching the attributes of a loaded model
+    pub fn add_pipeline_with_layout(
+        &mut self,
+        render_pass: RenderPassHandle,
+        mut config: PipelineConfiguration,
+        vertex_layout: VertexLayout,
+    ) -> PipelineHandle {
+        assert!(
+            config.blend_mode != BlendMode::DualSourceBlending || self.dual_source_blending_supported,
+            "Dual source blending is not supported by the device!"
+        );
+        if let Err(err) = config.validate_limits(&self.device_limits()) {
+            panic!("Invalid pipeline configuration: {}", err);
+        }
+        config.vertex_layout = vertex_layout;
+        self.render_pass_manager.add_pipeline(
             &self.logical_device,
             &mut self.buffer_object_manager,
             &self.texture_manager,
             config,
             render_pass,
+            self.frame_globals.index(),
+        )
+    }
+
+    // Adds one pipeline per shader permutation, the shaders of the builder are replaced by the compiled variants
+    pub fn add_pipeline_variants<T: VertexInputDescription>(
+        &mut self,
+        render_pass: RenderPassHandle,
+        variants: ShaderVariants,
+        config: &mut PipelineConfigurationBuilder,
+    ) -> Result<PipelineVariants, PipelineConfigError> {
+        let mut pipelines = Vec::with_capacity(variants.permutation_count());
+        for permutation in 0..variants.permutation_count() {
+            let pipeline_config = config
+                .with_vertex_shader_file(Path::new(&variants.vertex_shader_path(permutation)))
+                .with_fragment_shader_file(Path::new(&variants.fragment_shader_path(permutation)))
+                .build()?;
+            pipeline_config.validate_limits(&self.device_limits())?;
+            pipelines.push(self.add_pipeline::<T>(render_pass, pipeline_config));
+        }
+
+        Ok(PipelineVariants::new(variants, pipelines))
+    }
+
+    // Defers building the pipelines added from now on, end_pipeline_batch builds them all in parallel.
+    // The pipelines can't be drawn with until then.
+    pub fn begin_pipeline_batch(&mut self) {
+        self.render_pass_manager.begin_pipeline_batch();
+    }
+
+    pub fn end_pipeline_batch(&mut self) {
+        self.render_pass_manager.end_pipeline_batch(&self.logical_device);
+    }
+
+    // The view projection uniform bound by pipelines of the pass configured with_pass_camera. Can be changed at any
+    // time, e.g. to render a minimap from another camera than the main view.
+    pub fn set_render_pass_camera<T>(&mut self, pass: RenderPassHandle, uniform: UniformHandle<T>) -> Result<(), &'static str> {
+        self.render_pass_manager.set_pass_camera(
+            &mut self.buffer_object_manager,
+            pass,
+            uniform.index(),
+            std::mem::size_of::<T>(),
         )
     }
 
@@ -419,24 +1200,198 @@ impl Context {
         &mut self,
         target_texture: TextureHandle,
         pass_order: u32,
-    ) -> Result<RenderPassHandle, &str> {
+    ) -> Result<RenderPassHandle, &'static str> {
+        self.create_render_pass_with_samples(target_texture, pass_order, SampleCount::One)
+    }
+
+    // Multisampled passes resolve into the target texture at the end of the pass. Their pipelines are built with
+    // the sample count of the pass, they can't be used as depth source or render layered textures.
+    pub fn create_render_pass_with_samples(
+        &mut self,
+        target_texture: TextureHandle,
+        pass_order: u32,
+        samples: SampleCount,
+    ) -> Result<RenderPassHandle, &'static str> {
+        if !self.supports_sample_count(samples) {
+            return Err("Sample count is not supported by the device!");
+        }
+        if !self.texture_manager.get_usage(target_texture).color_target {
+            return Err("Target texture is not a color target!");
+        }
 
         let image_view = self.texture_manager.get_imageview(target_texture);
         let (width, height) = self.texture_manager.get_extent(target_texture);
         let format = self.texture_manager.get_format(target_texture);
 
+        // Layered targets render all views in one pass, see add_layered_render_texture
+        let view_count = self.texture_manager.get_layer_count(target_texture);
+        if view_count > 1 && !self.multiview_supported {
+            return Err("Multiview is not supported by the device!");
+        }
+
         self.render_pass_manager.create_image_target_pass(
             &self.logical_device,
             &self.physical_device_memory_properties,
+            target_texture,
             image_view,
             width,
             height,
+            view_count,
+            samples,
             format,
             pass_order,
-            self.render_pass_manager.swapchain_target().image_count()
         )
     }
 
+    // Recreates the swapchain pass and rebuilds its pipelines with the new sample count
+    pub fn set_swapchain_samples(&mut self, samples: SampleCount) -> Result<(), &'static str> {
+        if self.surface_container.is_none() {
+            return Err("Headless contexts have no swapchain!");
+        }
+        if !self.supports_sample_count(samples) {
+            return Err("Sample count is not supported by the device!");
+        }
+        if samples == self.render_pass_manager.swapchain_samples() {
+            return Ok(());
+        }
+
+        self.render_pass_manager.set_swapchain_samples(samples);
+        self.recreate_swapchain();
+
+        Ok(())
+    }
+
+    // Weighted blended order independent transparency. The pass tests against the depth buffer of the opaque
+    // depth source pass and renders into an accumulation and a revealage texture of the same size.
+    pub fn create_transparency_pass(
+        &mut self,
+        depth_source: RenderPassHandle,
+        pass_order: u32,
+    ) -> Result<TransparencyTargets, &'static str> {
+        let (source_texture, extent) = self.render_pass_manager.transparency_depth_source(depth_source, pass_order)?;
+        if self.texture_manager.scaled_render_textures().iter().any(|(texture, _)| *texture == source_texture) {
+            return Err("Scaled render textures can't be used as depth source!");
+        }
+
+        let mut add_target = |format| {
+            let usage = TextureUsage::RENDER_TARGET;
+            let (image, image_memory, image_view, format) =
+                self.create_render_texture_image(extent.width, extent.height, 1, format, usage);
+            let texture =
+                self.texture_manager.add_texture(image, image_memory, image_view, extent.width, extent.height, format);
+            self.texture_manager.set_usage(texture, usage);
+
+            (texture, image_view)
+        };
+        let (accumulation, accumulation_view) = add_target(OIT_ACCUMULATION_FORMAT);
+        let (revealage, revealage_view) = add_target(OIT_REVEALAGE_FORMAT);
+
+        let pass = self.render_pass_manager.create_transparency_pass(
+            &self.logical_device,
+            depth_source,
+            (accumulation, accumulation_view),
+            (revealage, revealage_view),
+            pass_order,
+        );
+
+        Ok(TransparencyTargets { pass, accumulation, revealage })
+    }
+
+    // Passes, pipelines and their buffer and texture dependencies as Graphviz DOT, written by r_dumpgraph
+    pub fn dump_graph(&self) -> String {
+        graph::to_dot(
+            &self.render_pass_manager.graph_passes(),
+            &self.buffer_object_manager.graph_buffers(),
+            &self.skinning_manager.graph_meshes(),
+        )
+    }
+
+    // The draw commands of the next submitted frame are captured as JSON, see take_frame_capture
+    pub fn capture_next_frame(&mut self) {
+        self.frame_capture_requested = true;
+    }
+
+    pub fn take_frame_capture(&mut self) -> Option<String> {
+        self.frame_capture.take()
+    }
+
+    // Device, enabled extensions and swapchain configuration, printed by the sysinfo console command
+    pub fn system_info(&self) -> Vec<String> {
+        let mut lines = debug::describe_physical_device(&self.instance, &self.physical_device);
+
+        let mut extensions: Vec<&str> = constants::DEVICE_EXTENSIONS.to_vec();
+        if self.render_pass_manager.supports_push_descriptors() {
+            extensions.push(PushDescriptor::name().to_str().unwrap());
+        }
+        if self.multiview_supported {
+            extensions.push(vk::KhrMultiviewFn::name().to_str().unwrap());
+        }
+        if self.supports_bindless_textures() {
+            extensions.push(vk::KhrMaintenance3Fn::name().to_str().unwrap());
+            extensions.push(vk::ExtDescriptorIndexingFn::name().to_str().unwrap());
+        }
+        lines.push(format!("Enabled device extensions: {}", extensions.join(", ")));
+        lines.push(format!(
+            "Validation: {}",
+            match (self.debug_utils_messenger != vk::DebugUtilsMessengerEXT::null(), self.gpu_validation) {
+                (false, _) => "off",
+                (true, false) => "layer",
+                (true, true) => "layer, GPU-assisted",
+            }
+        ));
+
+        if let Some((format, present_mode)) = self.swapchain_config {
+            let extent = self.render_pass_manager.swapchain_extent();
+            lines.push(format!(
+                "Swapchain: {}x{}, {:?}, {:?}, {} images, {} frames in flight",
+                extent.width,
+                extent.height,
+                format,
+                present_mode,
+                self.render_pass_manager.swapchain_target().image_count(),
+                self.frames_in_flight()
+            ));
+        } else {
+            lines.push(String::from("Swapchain: none (headless)"));
+        }
+
+        let (blocks, allocated, used) = self.memory_manager.usage();
+        lines.push(format!(
+            "Buffer memory: {} blocks, {:.1} MiB allocated, {:.1} MiB used",
+            blocks,
+            allocated as f64 / (1024.0 * 1024.0),
+            used as f64 / (1024.0 * 1024.0)
+        ));
+        if let Some((size, used)) = self.buffer_object_manager.staging_usage() {
+            lines.push(format!(
+                "Staging ring: {:.1} MiB, {:.1} MiB in flight",
+                size as f64 / (1024.0 * 1024.0),
+                used as f64 / (1024.0 * 1024.0)
+            ));
+        }
+
+        lines
+    }
+
+    pub fn set_render_pass_order(&mut self, pass: RenderPassHandle, order: u32) -> Result<(), &'static str> {
+        self.render_pass_manager.set_pass_order(pass, order)
+    }
+
+    // Disabled passes keep their resources but are skipped when baking, draw commands added to them are dropped.
+    pub fn set_render_pass_enabled(&mut self, pass: RenderPassHandle, enabled: bool) -> Result<(), &'static str> {
+        self.render_pass_manager.set_pass_enabled(pass, enabled)
+    }
+
+    // Number of pipelines drawing into an image pass, e.g. to skip passes nobody renders into
+    pub fn render_pass_pipeline_count(&self, pass: RenderPassHandle) -> Result<usize, &'static str> {
+        self.render_pass_manager.pipeline_count(pass)
+    }
+
+    // Color the target is cleared to before drawing, e.g. transparent for textures drawn as sprites
+    pub fn set_render_pass_clear_color(&mut self, pass: RenderPassHandle, color: [f32; 4]) -> Result<(), &'static str> {
+        self.render_pass_manager.set_pass_clear_color(pass, color)
+    }
+
     pub fn remove_render_pass(&mut self, pass: RenderPassHandle) {
         unsafe { self.wait_idle() }
         self.render_pass_manager.remove_pass(pass);
@@ -448,6 +1403,61 @@ impl Context {
             .expect("Failed to wait device idle!");
     }
 
+    pub fn frames_in_flight(&self) -> usize {
+        self.sync_handler.frames_in_flight()
+    }
+
+    // Fewer frames in flight lower the input latency, more keep the GPU busy when the CPU time of frames varies.
+    // Waits for the device, the buffer objects, command buffers and synchronization objects are recreated and all
+    // pipelines rebuilt.
+    pub fn set_frames_in_flight(&mut self, frames_in_flight: usize) -> Result<(), &'static str> {
+        if !(1..=MAX_FRAMES_IN_FLIGHT).contains(&frames_in_flight) {
+            return Err("Invalid number of frames in flight!");
+        }
+        if frames_in_flight == self.frames_in_flight() {
+            return Ok(());
+        }
+
+        unsafe {
+            self.wait_idle();
+
+            self.sync_handler.destroy(&self.logical_device);
+            self.logical_device
+                .free_command_buffers(self.command_pool, &self.draw_command_buffers);
+            self.logical_device
+                .free_command_buffers(self.transfer_command_pool, &self.transfer_command_buffers);
+            if let Some(gpu_timer) = &mut self.gpu_timer {
+                gpu_timer.destroy(&self.logical_device);
+            }
+            self.render_pass_manager.destroy_all_pipelines(&self.logical_device);
+        }
+
+        self.sync_handler = SynchronizationHandler::new(&self.logical_device, frames_in_flight);
+        self.draw_command_buffers = _create_command_buffers(&self.logical_device, self.command_pool, frames_in_flight);
+        self.transfer_command_buffers =
+            _create_command_buffers(&self.logical_device, self.transfer_command_pool, frames_in_flight);
+        self.gpu_timer = GpuTimer::new(
+            &self.instance,
+            self.physical_device,
+            &self.logical_device,
+            self.queue_families.graphics.family_index,
+            frames_in_flight,
+        );
+
+        if self
+            .buffer_object_manager
+            .resize_image_count(&self.logical_device, &mut self.memory_manager, frames_in_flight)
+        {
+            self.buffer_object_manager
+                .reassign_pipeline_buffers(&mut self.render_pass_manager);
+        }
+        self.render_pass_manager.set_frames_in_flight(frames_in_flight);
+        self.render_pass_manager.build_pending_pipelines(&self.logical_device);
+        log_info!("Frames in flight: {}", frames_in_flight);
+
+        Ok(())
+    }
+
     fn destroy_swapchain(&mut self) {
         unsafe {
             // Destroy swapchain and all its images and pipelines
@@ -458,41 +1468,48 @@ impl Context {
             self.logical_device
                 .free_command_buffers(self.command_pool, &self.draw_command_buffers);
             self.logical_device
-                .free_command_buffers(self.command_pool, &self.transfer_command_buffers);
-
-            // Buffer objects
-            self.buffer_object_manager
-                .destroy(&self.logical_device, &mut self.memory_manager);
+                .free_command_buffers(self.transfer_command_pool, &self.transfer_command_buffers);
         }
     }
 
+    fn recreate_surface(&mut self) {
+        self._recreate_swapchain(true);
+    }
+
     fn recreate_swapchain(&mut self) {
+        self._recreate_swapchain(false);
+    }
+
+    // Pipelines are only rebuilt when the format of the swapchain changes, viewport and scissor are dynamic and
+    // the per frame resources don't depend on the number of swapchain images
+    fn _recreate_swapchain(&mut self, recreate_surface: bool) {
         unsafe {
             self.logical_device
                 .device_wait_idle()
-                .expect("Failed to wait device idle!")
+                .expect("Failed to wait device idle!");
+
+            self.render_pass_manager.destroy_swapchain_target(&self.logical_device);
         };
-        self.destroy_swapchain();
+
+        if recreate_surface {
+            let surface_container = self.surface_container.as_mut().expect("Recreating surface of a headless context");
+            unsafe {
+                surface_container.recreate(&self.entry, &self.instance);
+            }
+        }
 
         let swapchain_container = swapchain::create_swapchain(
             &self.instance,
             &self.logical_device,
             self.physical_device,
-            &self.surface_container,
+            self.surface_container.as_ref().expect("Recreating swapchain of a headless context"),
             &self.queue_families,
         );
 
-        let image_count = swapchain_container.image_views.len();
+        self.swapchain_config = Some((swapchain_container.format, swapchain_container.present_mode));
 
-        self.draw_command_buffers = _create_command_buffers(&self.logical_device, self.command_pool, image_count);
-        self.transfer_command_buffers = _create_command_buffers(&self.logical_device, self.command_pool, image_count);
+        self.resize_scaled_render_textures(swapchain_container.extent);
 
-        self.buffer_object_manager
-            .rebuild(&self.logical_device, &mut self.memory_manager, image_count);
-        self.buffer_object_manager
-            .reassign_pipeline_buffers(&mut self.render_pass_manager);
-
-        self.render_pass_manager.rebuild_image_target_pipelines(&self.logical_device, image_count);
         self.render_pass_manager.create_swapchain_pass(
             &self.logical_device,
             &self.physical_device_memory_properties,
@@ -503,7 +1520,8 @@ impl Context {
     fn bake_draw_command_buffer(
         &self,
         command_buffer: vk::CommandBuffer,
-        image_index: usize,
+        frame_index: usize,
+        swapchain_image_index: usize,
         render_stats: &mut RenderStats,
     ) -> bool {
         let start_time = Instant::now();
@@ -518,23 +1536,116 @@ impl Context {
             self.logical_device
                 .begin_command_buffer(command_buffer, &command_buffer_begin_info)
                 .expect("Failed to begin recording of Draw command buffer!");
+            if let Some(gpu_timer) = &self.gpu_timer {
+                gpu_timer.begin(&self.logical_device, command_buffer, frame_index);
+            }
+            self.buffer_object_manager
+                .cmd_acquire_transferred(&self.logical_device, command_buffer);
+
+            #[cfg(feature = "raw-vulkan")]
+            if let Some(callback) = self.raw_frame_callback.borrow_mut().as_mut() {
+                let mut raw = self.raw();
+                raw.command_buffer = Some(command_buffer);
+                raw.image_index = Some(swapchain_image_index);
+                callback(&raw);
+            }
+
+            self.dispatch_manager.bake_command_buffer(
+                &self.logical_device,
+                command_buffer,
+                frame_index,
+                &self.buffer_object_manager,
+                &self.texture_manager,
+            );
+            self.skinning_manager.bake_command_buffer(
+                &self.logical_device,
+                command_buffer,
+                frame_index,
+                &self.buffer_object_manager,
+            );
+            self.occlusion_culler.bake_command_buffer(&self.logical_device, command_buffer);
 
             self.render_pass_manager.bake_command_buffer(
                 &self.logical_device,
                 command_buffer,
-                image_index,
+                frame_index,
+                swapchain_image_index,
                 render_stats,
+                &self.texture_manager,
+                &self.buffer_object_manager,
+                |pass| self.occlusion_culler.bake_hiz(&self.logical_device, command_buffer, pass),
             );
 
+            if let Some(gpu_timer) = &self.gpu_timer {
+                gpu_timer.end(&self.logical_device, command_buffer, frame_index);
+            }
             self.logical_device
                 .end_command_buffer(command_buffer)
                 .expect("Failed to end recording of Draw command buffer!");
         }
 
         render_stats.draw_commands_bake_time = start_time.elapsed();
+
+        let descriptor_stats = self.render_pass_manager.descriptor_stats();
+        render_stats.descriptor_pool_count = descriptor_stats.pool_count;
+        render_stats.descriptor_set_count = descriptor_stats.allocated_sets;
         true
     }
 
+    // Safety: the engine assumes it owns every object it created, see RawContext
+    #[cfg(feature = "raw-vulkan")]
+    pub unsafe fn raw(&self) -> RawContext<'_> {
+        RawContext {
+            entry: &self.entry,
+            instance: &self.instance,
+            physical_device: self.physical_device,
+            device: &self.logical_device,
+            graphics_queue: self.graphics_queue,
+            graphics_queue_family: self.queue_families.graphics.family_index,
+            present_queue: self.present_queue,
+            command_pool: self.command_pool,
+            command_buffer: None,
+            image_index: None,
+        }
+    }
+
+    // Called every frame with the draw command buffer, before the render passes are recorded
+    #[cfg(feature = "raw-vulkan")]
+    pub unsafe fn set_raw_frame_callback(&mut self, callback: Option<RawFrameCallback>) {
+        *self.raw_frame_callback.borrow_mut() = callback;
+    }
+
+    pub fn supports_push_descriptors(&self) -> bool {
+        self.render_pass_manager.supports_push_descriptors()
+    }
+
+    pub fn supports_multiview(&self) -> bool {
+        self.multiview_supported
+    }
+
+    pub fn supports_bindless_textures(&self) -> bool {
+        self.texture_manager.bindless_set().is_some()
+    }
+
+    // Of both color and depth attachments
+    pub fn supports_sample_count(&self, samples: SampleCount) -> bool {
+        let limits = self.device_limits();
+        (limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts).contains(samples.flags())
+    }
+
+    fn device_limits(&self) -> vk::PhysicalDeviceLimits {
+        unsafe { self.instance.get_physical_device_properties(self.physical_device) }.limits
+    }
+
+    pub fn supports_dual_source_blending(&self) -> bool {
+        self.dual_source_blending_supported
+    }
+
+    // Blend constants of the draws of all passes, unless set with DrawCommand::with_blend_constants
+    pub fn set_blend_constants(&mut self, blend_constants: [f32; 4]) {
+        self.render_pass_manager.set_blend_constants(blend_constants);
+    }
+
     pub fn get_aspect_ratio(&self) -> f32 {
         let extent = self.render_pass_manager.swapchain_extent();
         extent.width as f32 / extent.height as f32
@@ -545,6 +1656,12 @@ impl Context {
         (extent.width, extent.height)
     }
 
+    // The whole framebuffer, used by draw commands without a viewport of their own
+    pub fn get_viewport(&self) -> Viewport {
+        let extent = self.render_pass_manager.swapchain_extent();
+        Viewport::new(0, 0, extent.width, extent.height)
+    }
+
     pub fn handle_window_resize(&mut self) {
         unsafe {
             self.wait_idle();
@@ -552,28 +1669,37 @@ impl Context {
         self.is_framebuffer_resized = true;
     }
 
-    pub fn set_buffer_object<T>(&mut self, buffer_object: BufferObjectHandle, data: T) {
-        self.buffer_object_manager.reset_buffer(buffer_object);
-        self.push_to_buffer_object(buffer_object, data);
+    // Same as handle_window_resize, also passes on the new size for surfaces that leave the extent to the swapchain
+    pub fn resize_surface(&mut self, width: u32, height: u32) {
+        if let Some(surface_container) = self.surface_container.as_mut() {
+            surface_container.set_extent(vk::Extent2D { width, height });
+        }
+        self.handle_window_resize();
     }
 
-    pub fn reset_buffer_object(&mut self, buffer_object: BufferObjectHandle) {
-        self.buffer_object_manager.reset_buffer(buffer_object);
+    pub fn set_buffer_object<T>(&mut self, buffer_object: BufferObjectHandle<T>, data: T) {
+        self.buffer_object_manager.reset_buffer(buffer_object.index());
+        self.push_to_buffer_object(buffer_object, data);
     }
 
-    pub fn push_to_buffer_object<T>(&mut self, buffer_object: BufferObjectHandle, data: T) {
-        let result = self.buffer_object_manager.push_to_buf(buffer_object, data);
+    pub fn reset_buffer_object<T>(&mut self, buffer_object: BufferObjectHandle<T>) {
+        self.buffer_object_manager.reset_buffer(buffer_object.index());
+    }
 
-        if result.is_err() {
+    // Growable buffers are resized before the element is pushed, pushing first would drop it and shift the instances
+    // of every later element by one.
+    pub fn push_to_buffer_object<T>(&mut self, buffer_object: BufferObjectHandle<T>, data: T) {
+        if self.buffer_object_manager.borrow_buffer(buffer_object.index()).is_full() {
+            let frames_in_flight = self.frames_in_flight();
             let resized = self.buffer_object_manager.handle_buffer_overflow(
                 &self.logical_device,
                 &mut self.memory_manager,
-                buffer_object,
-                self.render_pass_manager.swapchain_target().image_count(),
+                buffer_object.index(),
+                frames_in_flight,
             );
 
             if resized {
-                let sbo = self.buffer_object_manager.borrow_buffer(buffer_object);
+                let sbo = self.buffer_object_manager.borrow_buffer(buffer_object.index());
                 let new_capacity = sbo.capacity_bytes();
                 for pipeline in sbo.assigned_pipelines().iter() {
                     self.render_pass_manager.update_storage_buffer(*pipeline, sbo.devices(), new_capacity);
@@ -587,6 +1713,10 @@ impl Context {
                 }
             }
         }
+
+        if self.buffer_object_manager.push_to_buf(buffer_object, data).is_err() {
+            log_debug_once!("buffer object full, element dropped: handle={}", buffer_object.index());
+        }
     }
 }
 
@@ -600,10 +1730,20 @@ impl Drop for Context {
             // Shaders and descriptor sets
             self.render_pass_manager
                 .destroy_static_pipeline_objects(&self.logical_device);
+            self.dispatch_manager.destroy(&self.logical_device);
+            self.skinning_manager.destroy(&self.logical_device);
+            self.occlusion_culler.destroy(&self.logical_device);
+            if let Some(gpu_timer) = &mut self.gpu_timer {
+                gpu_timer.destroy(&self.logical_device);
+            }
 
             // Swapchain
             self.destroy_swapchain();
 
+            // Buffer objects
+            self.buffer_object_manager
+                .destroy(&self.logical_device, &mut self.memory_manager);
+
             // All render passes
             self.render_pass_manager.destroy_all(&self.logical_device);
 
@@ -615,26 +1755,30 @@ impl Drop for Context {
 
             // Command pool
             self.logical_device.destroy_command_pool(self.command_pool, None);
+            self.logical_device.destroy_command_pool(self.transfer_command_pool, None);
 
             // Device
             self.logical_device.destroy_device(None);
 
-            #[cfg(debug_assertions)]
-            self.debug_utils_loader
-                .destroy_debug_utils_messenger(self.debug_utils_messenger, None);
+            if self.debug_utils_messenger != vk::DebugUtilsMessengerEXT::null() {
+                self.debug_utils_loader
+                    .destroy_debug_utils_messenger(self.debug_utils_messenger, None);
+            }
 
-            self.surface_container.destroy();
+            if let Some(surface_container) = &self.surface_container {
+                surface_container.destroy();
+            }
             self.instance.destroy_instance(None);
         }
     }
 }
 
-fn _create_command_pool(device: &ash::Device, queue_families: &QueueFamilyIndices) -> vk::CommandPool {
+fn _create_command_pool(device: &ash::Device, queue_family_index: u32) -> vk::CommandPool {
     let command_pool_create_info = vk::CommandPoolCreateInfo {
         s_type: vk::StructureType::COMMAND_POOL_CREATE_INFO,
         p_next: ptr::null(),
         flags: vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
-        queue_family_index: queue_families.graphics.family_index,
+        queue_family_index,
     };
 
     unsafe {
@@ -644,7 +1788,85 @@ fn _create_command_pool(device: &ash::Device, queue_families: &QueueFamilyIndice
     }
 }
 
-fn _create_instance(entry: &ash::Entry, layers: &[&str], window: &Window) -> ash::Instance {
+// Draw submission of one frame, see Context::begin_frame. Everything else of the context stays reachable
+// through the frame.
+pub struct Frame<'a> {
+    context: &'a mut Context,
+}
+
+impl Frame<'_> {
+    pub fn add_draw_command(&mut self, draw_command: DrawCommand) {
+        self.context.render_pass_manager.add_draw_command(draw_command);
+    }
+
+    // Skins the mesh before the render passes of this frame, once however many passes draw it
+    pub fn skin_mesh(&mut self, skinned_mesh: SkinnedMeshHandle) {
+        self.context.skinning_manager.queue(skinned_mesh);
+    }
+
+    // Culls the instances before the render passes of this frame, with the camera of the HiZ source pass
+    pub fn cull_instances(&mut self, culled_instances: CulledInstancesHandle, view_projection: Matrix4<f32>) {
+        self.context.occlusion_culler.queue(culled_instances, view_projection);
+    }
+
+    // Runs the compute pipeline before the render passes of this frame, after the dispatches queued before it.
+    // The group count is in workgroups of the shader.
+    pub fn dispatch(&mut self, pipeline: ComputePipelineHandle, group_count: [u32; 3]) {
+        self.context.dispatch_manager.queue(pipeline, group_count, &[]);
+    }
+
+    pub fn dispatch_with_push_constant<T>(
+        &mut self,
+        pipeline: ComputePipelineHandle,
+        group_count: [u32; 3],
+        push_constant: &T,
+    ) {
+        let bytes = unsafe {
+            std::slice::from_raw_parts(push_constant as *const T as *const u8, std::mem::size_of::<T>())
+        };
+        self.context.dispatch_manager.queue(pipeline, group_count, bytes);
+    }
+
+    pub fn submit(self) -> RenderStats {
+        self.context.end_frame()
+    }
+}
+
+impl Deref for Frame<'_> {
+    type Target = Context;
+
+    fn deref(&self) -> &Context {
+        self.context
+    }
+}
+
+impl DerefMut for Frame<'_> {
+    fn deref_mut(&mut self) -> &mut Context {
+        self.context
+    }
+}
+
+enum SurfaceSource<'a> {
+    Window(&'a Window),
+    RawHandles(RawDisplayHandle, RawWindowHandle, vk::Extent2D),
+}
+
+impl SurfaceSource<'_> {
+    fn display_handle(&self) -> RawDisplayHandle {
+        match self {
+            SurfaceSource::Window(window) => window.raw_display_handle(),
+            SurfaceSource::RawHandles(display, _, _) => *display,
+        }
+    }
+}
+
+fn _create_instance(
+    entry: &ash::Entry,
+    layers: &[&str],
+    display_handle: Option<RawDisplayHandle>,
+    configuration: &ContextConfiguration,
+    gpu_validation: bool,
+) -> ash::Instance {
     let app_name = CString::new(ENGINE_NAME).unwrap();
     let engine_name = CString::new(ENGINE_NAME).unwrap();
     let app_info = vk::ApplicationInfo {
@@ -662,19 +1884,29 @@ fn _create_instance(entry: &ash::Entry, layers: &[&str], window: &Window) -> ash
 
     layers.iter().for_each(|layer| log_debug!("Enabling layer:  {}", layer));
 
-    let mut required_extensions =
-        ash_window::enumerate_required_extensions(window.raw_display_handle())
+    let mut required_extensions = match display_handle {
+        Some(display_handle) => ash_window::enumerate_required_extensions(display_handle)
             .expect("Failed to enumerate extensions")
-            .to_vec();
-
-    #[cfg(debug_assertions)]
-    let debug = true;
-    #[cfg(not(debug_assertions))]
-    let debug = false;
+            .to_vec(),
+        None => Vec::new(),
+    };
 
+    let debug = layers.contains(&constants::VALIDATION_LAYER_NAME);
     if debug {
         required_extensions.push(DebugUtils::name().as_ptr());
     }
+    if gpu_validation {
+        required_extensions.push(vk::ExtValidationFeaturesFn::name().as_ptr());
+    }
+    // Needed to query the features of device extensions, see _bindless_texture_capacity
+    if _check_instance_extension_support(entry, GetPhysicalDeviceProperties2::name()) {
+        required_extensions.push(GetPhysicalDeviceProperties2::name().as_ptr());
+    }
+    for extension in configuration.instance_extensions.iter() {
+        if !required_extensions.iter().any(|name| unsafe { CStr::from_ptr(*name) } == extension.as_c_str()) {
+            required_extensions.push(extension.as_ptr());
+        }
+    }
 
     let mut create_info_builder = vk::InstanceCreateInfo::builder()
         .application_info(&app_info)
@@ -685,6 +1917,15 @@ fn _create_instance(entry: &ash::Entry, layers: &[&str], window: &Window) -> ash
     if debug {
         create_info_builder = create_info_builder.push_next(&mut debug_messenger_create_info);
     }
+    let enabled_validation_features = [
+        vk::ValidationFeatureEnableEXT::GPU_ASSISTED,
+        vk::ValidationFeatureEnableEXT::GPU_ASSISTED_RESERVE_BINDING_SLOT,
+    ];
+    let mut validation_features =
+        vk::ValidationFeaturesEXT::builder().enabled_validation_features(&enabled_validation_features);
+    if gpu_validation {
+        create_info_builder = create_info_builder.push_next(&mut validation_features);
+    }
 
     let create_info = create_info_builder.build();
 
@@ -697,22 +1938,31 @@ fn _create_instance(entry: &ash::Entry, layers: &[&str], window: &Window) -> ash
     instance
 }
 
-fn _pick_physical_device(instance: &ash::Instance) -> PhysicalDevice {
+fn _pick_physical_device(instance: &ash::Instance, prefer_cpu: bool) -> Result<PhysicalDevice, &'static str> {
     unsafe {
         let physical_devices = instance
             .enumerate_physical_devices()
-            .expect("Failed to enumerate Physical devices!");
+            .map_err(|_| "Failed to enumerate Physical devices!")?;
 
         if physical_devices.is_empty() {
-            panic!("No available physical device.");
+            return Err("No available physical device.");
+        }
+
+        if prefer_cpu {
+            for device in physical_devices.iter() {
+                let properties = instance.get_physical_device_properties(*device);
+                if properties.device_type == vk::PhysicalDeviceType::CPU && _is_physical_device_suitable(device) {
+                    return Ok(*device);
+                }
+            }
         }
 
         for device in physical_devices.iter() {
             if _is_physical_device_suitable(device) {
-                return *device;
+                return Ok(*device);
             }
         }
-        panic!("No suitable physical device!");
+        Err("No suitable physical device!")
     }
 }
 
@@ -727,6 +1977,34 @@ fn _is_physical_device_suitable(_device: &PhysicalDevice) -> bool {
     true
 }
 
+fn _check_instance_extension_support(entry: &ash::Entry, name: &CStr) -> bool {
+    let extensions = entry
+        .enumerate_instance_extension_properties(None)
+        .expect("Failed to enumerate instance extensions!");
+
+    extensions
+        .iter()
+        .any(|extension| unsafe { CStr::from_ptr(extension.extension_name.as_ptr()) } == name)
+}
+
+// GPU-assisted validation is enabled with VK_EXT_validation_features, which the validation layer provides
+fn _check_gpu_validation_support(entry: &ash::Entry) -> bool {
+    let layer_name = CString::new(constants::VALIDATION_LAYER_NAME).unwrap();
+    let supported = entry
+        .enumerate_instance_extension_properties(Some(&layer_name))
+        .expect("Failed to enumerate validation layer extensions!")
+        .iter()
+        .any(|extension| {
+            let name = unsafe { CStr::from_ptr(extension.extension_name.as_ptr()) };
+            name == vk::ExtValidationFeaturesFn::name()
+        });
+    if !supported {
+        log_warning!("{:?} not supported, GPU-assisted validation is disabled", vk::ExtValidationFeaturesFn::name());
+    }
+
+    supported
+}
+
 fn _check_instance_layer_support(entry: &ash::Entry, layer_name: &str) -> bool {
     let layer_properties = entry
         .enumerate_instance_layer_properties()
@@ -747,20 +2025,15 @@ fn create_logical_device(
     instance: &ash::Instance,
     physical_device: &PhysicalDevice,
     queue_families: &QueueFamilyIndices,
-) -> ash::Device {
-    let distinct_queue_familes: HashSet<u32> = [
-        queue_families.graphics.family_index,
-        queue_families.present.family_index,
-    ]
-    .iter()
-    .cloned()
-    .collect();
+    configuration: &ContextConfiguration,
+    bindless_textures: bool,
+) -> (ash::Device, bool, bool, bool) {
+    let queue_counts = queue_families.queue_counts();
     let mut queue_create_infos = Vec::new();
 
-    let queue_priorities = [1.0_f32];
-    let queue_count = 1;
+    let queue_priorities = vec![1.0_f32; queue_counts.values().copied().max().unwrap_or(1) as usize];
 
-    for queue_family_index in distinct_queue_familes {
+    for (queue_family_index, queue_count) in queue_counts {
         let queue_create_info = vk::DeviceQueueCreateInfo {
             s_type: vk::StructureType::DEVICE_QUEUE_CREATE_INFO,
             p_next: ptr::null(),
@@ -773,15 +2046,67 @@ fn create_logical_device(
     }
 
     let extensions_temp = vulkan_util::copy_str_slice_to_cstring_vec(&constants::DEVICE_EXTENSIONS);
-    let extensions_converted = extensions_temp.iter().map(|layer| layer.as_ptr()).collect::<Vec<_>>();
+    let mut extensions_converted = extensions_temp.iter().map(|layer| layer.as_ptr()).collect::<Vec<_>>();
+
+    let push_descriptor_supported = _check_device_extension_support(instance, physical_device, PushDescriptor::name());
+    if push_descriptor_supported {
+        extensions_converted.push(PushDescriptor::name().as_ptr());
+    } else {
+        log_warning!("{:?} not supported, pushed textures are disabled", PushDescriptor::name());
+    }
+
+    // The multiview feature is mandatory for devices exposing the extension
+    let multiview_supported = _check_device_extension_support(instance, physical_device, vk::KhrMultiviewFn::name());
+    if multiview_supported {
+        extensions_converted.push(vk::KhrMultiviewFn::name().as_ptr());
+    } else {
+        log_warning!("{:?} not supported, layered render passes are disabled", vk::KhrMultiviewFn::name());
+    }
+    if bindless_textures {
+        extensions_converted.push(vk::KhrMaintenance3Fn::name().as_ptr());
+        extensions_converted.push(vk::ExtDescriptorIndexingFn::name().as_ptr());
+    }
+    for extension in configuration.device_extensions.iter() {
+        if !extensions_converted.iter().any(|name| unsafe { CStr::from_ptr(*name) } == extension.as_c_str()) {
+            extensions_converted.push(extension.as_ptr());
+        }
+    }
+
+    let dual_source_blending_supported =
+        unsafe { instance.get_physical_device_features(*physical_device).dual_src_blend == vk::TRUE };
+    if !dual_source_blending_supported {
+        log_warning!("Dual source blending not supported");
+    }
 
-    let physical_device_features = vk::PhysicalDeviceFeatures::builder().sampler_anisotropy(true).build();
+    let physical_device_features = vk::PhysicalDeviceFeatures::builder()
+        .sampler_anisotropy(true)
+        .dual_src_blend(dual_source_blending_supported)
+        .build();
+    let mut multiview_features = vk::PhysicalDeviceMultiviewFeatures::builder().multiview(true).build();
+    let mut descriptor_indexing_features = vk::PhysicalDeviceDescriptorIndexingFeatures::builder()
+        .shader_sampled_image_array_non_uniform_indexing(true)
+        .descriptor_binding_sampled_image_update_after_bind(true)
+        .descriptor_binding_partially_bound(true)
+        .runtime_descriptor_array(true)
+        .build();
 
-    let device_create_info = vk::DeviceCreateInfo::builder()
+    let mut device_create_info_builder = vk::DeviceCreateInfo::builder()
         .queue_create_infos(&queue_create_infos)
         .enabled_extension_names(&extensions_converted)
-        .enabled_features(&physical_device_features)
-        .build();
+        .enabled_features(&physical_device_features);
+    if multiview_supported {
+        device_create_info_builder = device_create_info_builder.push_next(&mut multiview_features);
+    }
+    if bindless_textures {
+        device_create_info_builder = device_create_info_builder.push_next(&mut descriptor_indexing_features);
+    }
+    let mut device_create_info = device_create_info_builder.build();
+    for feature in configuration.device_features.iter() {
+        unsafe {
+            (**feature).p_next = device_create_info.p_next as *mut vk::BaseOutStructure;
+        }
+        device_create_info.p_next = *feature as *const std::ffi::c_void;
+    }
 
     let device: ash::Device = unsafe {
         instance
@@ -789,7 +2114,69 @@ fn create_logical_device(
             .expect("Failed to create logical Device!")
     };
 
-    device
+    (device, push_descriptor_supported, multiview_supported, dual_source_blending_supported)
+}
+
+// Slots of the bindless texture array, None without the descriptor indexing features it needs
+fn _bindless_texture_capacity(
+    entry: &ash::Entry,
+    instance: &ash::Instance,
+    physical_device: &PhysicalDevice,
+) -> Option<u32> {
+    let supported = _check_instance_extension_support(entry, GetPhysicalDeviceProperties2::name())
+        && _check_device_extension_support(instance, physical_device, vk::KhrMaintenance3Fn::name())
+        && _check_device_extension_support(instance, physical_device, vk::ExtDescriptorIndexingFn::name());
+    if !supported {
+        log_warning!("{:?} not supported, bindless textures are disabled", vk::ExtDescriptorIndexingFn::name());
+        return None;
+    }
+
+    let properties2 = GetPhysicalDeviceProperties2::new(entry, instance);
+    let mut features = vk::PhysicalDeviceDescriptorIndexingFeatures::default();
+    let mut features2 = vk::PhysicalDeviceFeatures2::builder().push_next(&mut features).build();
+    let mut properties = vk::PhysicalDeviceDescriptorIndexingProperties::default();
+    let mut device_properties2 = vk::PhysicalDeviceProperties2::builder().push_next(&mut properties).build();
+    unsafe {
+        properties2.get_physical_device_features2(*physical_device, &mut features2);
+        properties2.get_physical_device_properties2(*physical_device, &mut device_properties2);
+    }
+
+    if features.shader_sampled_image_array_non_uniform_indexing != vk::TRUE
+        || features.descriptor_binding_sampled_image_update_after_bind != vk::TRUE
+        || features.descriptor_binding_partially_bound != vk::TRUE
+        || features.runtime_descriptor_array != vk::TRUE
+    {
+        log_warning!("Descriptor indexing features missing, bindless textures are disabled");
+        return None;
+    }
+
+    Some(
+        constants::BINDLESS_TEXTURE_CAPACITY
+            .min(properties.max_per_stage_descriptor_update_after_bind_samplers)
+            .min(properties.max_per_stage_descriptor_update_after_bind_sampled_images)
+            .min(properties.max_per_stage_update_after_bind_resources)
+            .min(properties.max_descriptor_set_update_after_bind_samplers)
+            .min(properties.max_descriptor_set_update_after_bind_sampled_images),
+    )
+}
+
+fn _check_device_extension_support(instance: &ash::Instance, physical_device: &PhysicalDevice, name: &CStr) -> bool {
+    let extensions = unsafe {
+        instance
+            .enumerate_device_extension_properties(*physical_device)
+            .expect("Failed to enumerate physical device extensions!")
+    };
+
+    extensions
+        .iter()
+        .any(|extension| unsafe { CStr::from_ptr(extension.extension_name.as_ptr()) } == name)
+}
+
+fn _scaled_extent(extent: vk::Extent2D, factor: f32) -> (u32, u32) {
+    let width = ((extent.width as f32 * factor).round() as u32).max(1);
+    let height = ((extent.height as f32 * factor).round() as u32).max(1);
+
+    (width, height)
 }
 
 fn _create_command_buffers(
@@ -6,27 +6,26 @@ use ash::vk::{PhysicalDevice, QueueFlags};
 
 use super::vulkan_util::{vk_cstr_to_str, vk_format_version};
 
+// The messenger is null when validation is disabled
 pub fn setup_debug_utils(
     entry: &ash::Entry,
     instance: &ash::Instance,
+    validation: bool,
 ) -> (ash::extensions::ext::DebugUtils, vk::DebugUtilsMessengerEXT) {
     let debug_utils_loader = ash::extensions::ext::DebugUtils::new(entry, instance);
-    #[cfg(not(debug_assertions))]
-    {
+    if !validation {
         return (debug_utils_loader, ash::vk::DebugUtilsMessengerEXT::null());
     }
-    #[cfg(debug_assertions)]
-    {
-        let messenger_ci = create_debug_messenger_create_info();
 
-        let utils_messenger = unsafe {
-            debug_utils_loader
-                .create_debug_utils_messenger(&messenger_ci, None)
-                .expect("Debug Utils Callback")
-        };
+    let messenger_ci = create_debug_messenger_create_info();
 
-        (debug_utils_loader, utils_messenger)
-    }
+    let utils_messenger = unsafe {
+        debug_utils_loader
+            .create_debug_utils_messenger(&messenger_ci, None)
+            .expect("Debug Utils Callback")
+    };
+
+    (debug_utils_loader, utils_messenger)
 }
 
 pub fn create_debug_messenger_create_info() -> vk::DebugUtilsMessengerCreateInfoEXT {
@@ -87,6 +86,18 @@ pub fn log_physical_devices(instance: &ash::Instance) {
     }
 }
 
+// Properties of the device for diagnostics, e.g. the crash report
+pub fn describe_physical_device(instance: &ash::Instance, device: &PhysicalDevice) -> Vec<String> {
+    let prop = unsafe { instance.get_physical_device_properties(*device) };
+
+    vec![
+        format!("Device: {} ({:?})", vk_cstr_to_str(&prop.device_name), prop.device_type),
+        format!("Vendor id: {:#06x}, device id: {:#06x}", prop.vendor_id, prop.device_id),
+        format!("Driver version: {}", vk_format_version(prop.driver_version)),
+        format!("Vulkan API version: {}", vk_format_version(prop.api_version)),
+    ]
+}
+
 pub fn log_physical_device(instance: &ash::Instance, device: &PhysicalDevice) {
     let prop = unsafe { instance.get_physical_device_properties(*device) };
     let name_str = vk_cstr_to_str(&prop.device_name);
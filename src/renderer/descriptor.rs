@@ -0,0 +1,250 @@
+use ash::vk;
+
+use crate::renderer::constants::{
+    DESCRIPTOR_POOL_MAX_SETS, SAMPLER_DESCRIPTOR_POOL_SIZE, STORAGE_DESCRIPTOR_POOL_SIZE,
+    STORAGE_DYNAMIC_DESCRIPTOR_POOL_SIZE, STORAGE_IMAGE_DESCRIPTOR_POOL_SIZE, UNIFORM_DESCRIPTOR_POOL_SIZE,
+};
+
+pub struct DescriptorAllocation {
+    pool: vk::DescriptorPool,
+    sets: Vec<vk::DescriptorSet>,
+}
+
+impl DescriptorAllocation {
+    pub fn sets(&self) -> &[vk::DescriptorSet] {
+        &self.sets
+    }
+}
+
+// Descriptors of each type in one set. Pools are created large enough for the sets allocated from them, so
+// pipelines with many textures don't run out of descriptors.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DescriptorCounts {
+    pub uniform_buffers: u32,
+    pub storage_buffers: u32,
+    // The instance buffers of graphics pipelines, see StorageWindow
+    pub dynamic_storage_buffers: u32,
+    pub combined_image_samplers: u32,
+    pub storage_images: u32,
+}
+
+impl DescriptorCounts {
+    pub fn from_bindings(bindings: &[vk::DescriptorSetLayoutBinding]) -> Self {
+        let mut counts = DescriptorCounts::default();
+        for binding in bindings.iter() {
+            let count = match binding.descriptor_type {
+                vk::DescriptorType::UNIFORM_BUFFER => &mut counts.uniform_buffers,
+                vk::DescriptorType::STORAGE_BUFFER => &mut counts.storage_buffers,
+                vk::DescriptorType::STORAGE_BUFFER_DYNAMIC => &mut counts.dynamic_storage_buffers,
+                vk::DescriptorType::COMBINED_IMAGE_SAMPLER => &mut counts.combined_image_samplers,
+                vk::DescriptorType::STORAGE_IMAGE => &mut counts.storage_images,
+                descriptor_type => panic!("Unsupported descriptor type {:?}", descriptor_type),
+            };
+            *count += binding.descriptor_count;
+        }
+
+        counts
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DescriptorStats {
+    pub pool_count: u32,
+    pub allocated_sets: u32,
+    pub total_allocations: u64,
+}
+
+pub struct DescriptorAllocator {
+    pools: Vec<vk::DescriptorPool>,
+    current_pool: usize,
+
+    stats: DescriptorStats,
+}
+
+impl DescriptorAllocator {
+    pub fn new() -> Self {
+        DescriptorAllocator {
+            pools: Vec::new(),
+            current_pool: 0,
+            stats: DescriptorStats::default(),
+        }
+    }
+
+    pub fn allocate(
+        &mut self,
+        device: &ash::Device,
+        layout: vk::DescriptorSetLayout,
+        descriptor_counts: DescriptorCounts,
+        count: usize,
+    ) -> DescriptorAllocation {
+        let layouts = vec![layout; count];
+
+        // Try the pools we already have before growing the chain.
+        while self.current_pool < self.pools.len() {
+            let pool = self.pools[self.current_pool];
+            match _allocate_sets(device, pool, &layouts) {
+                Ok(sets) => return self.track_allocation(pool, sets),
+                Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY | vk::Result::ERROR_FRAGMENTED_POOL) => {
+                    self.current_pool += 1;
+                }
+                Err(err) => panic!("Failed to allocate descriptor sets! {:?}", err),
+            }
+        }
+
+        let pool = _create_descriptor_pool(device, descriptor_counts, count as u32);
+        self.pools.push(pool);
+        self.current_pool = self.pools.len() - 1;
+        self.stats.pool_count = self.pools.len() as u32;
+        log_debug!("descriptor allocator: growing to {} pools", self.pools.len());
+
+        let sets = _allocate_sets(device, pool, &layouts).expect("Failed to allocate descriptor sets!");
+        self.track_allocation(pool, sets)
+    }
+
+    pub unsafe fn free(&mut self, device: &ash::Device, allocation: DescriptorAllocation) {
+        if allocation.sets.is_empty() {
+            return;
+        }
+
+        device
+            .free_descriptor_sets(allocation.pool, &allocation.sets)
+            .expect("Failed to free descriptor sets!");
+        self.stats.allocated_sets -= allocation.sets.len() as u32;
+
+        // Freed sets make room in older pools again.
+        self.current_pool = 0;
+    }
+
+    pub fn stats(&self) -> DescriptorStats {
+        self.stats
+    }
+
+    pub unsafe fn destroy(&mut self, device: &ash::Device) {
+        for pool in self.pools.drain(..) {
+            device.destroy_descriptor_pool(pool, None);
+        }
+        self.current_pool = 0;
+        self.stats = DescriptorStats::default();
+    }
+
+    fn track_allocation(&mut self, pool: vk::DescriptorPool, sets: Vec<vk::DescriptorSet>) -> DescriptorAllocation {
+        self.stats.allocated_sets += sets.len() as u32;
+        self.stats.total_allocations += 1;
+
+        DescriptorAllocation { pool, sets }
+    }
+}
+
+impl Default for DescriptorAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn _allocate_sets(
+    device: &ash::Device,
+    pool: vk::DescriptorPool,
+    layouts: &[vk::DescriptorSetLayout],
+) -> Result<Vec<vk::DescriptorSet>, vk::Result> {
+    let descriptor_set_allocate_info = vk::DescriptorSetAllocateInfo::builder()
+        .descriptor_pool(pool)
+        .set_layouts(layouts)
+        .build();
+
+    unsafe { device.allocate_descriptor_sets(&descriptor_set_allocate_info) }
+}
+
+// The pool fits at least the allocation that creates it, set_count sets with the given descriptors each
+fn _pool_sizes(descriptor_counts: DescriptorCounts, set_count: u32) -> (u32, [vk::DescriptorPoolSize; 5]) {
+    let pool_size = |ty: vk::DescriptorType, minimum: u32, per_set: u32| {
+        vk::DescriptorPoolSize::builder()
+            .ty(ty)
+            .descriptor_count(minimum.max(per_set * set_count))
+            .build()
+    };
+    let pool_sizes = [
+        pool_size(
+            vk::DescriptorType::UNIFORM_BUFFER,
+            UNIFORM_DESCRIPTOR_POOL_SIZE,
+            descriptor_counts.uniform_buffers,
+        ),
+        pool_size(
+            vk::DescriptorType::STORAGE_BUFFER,
+            STORAGE_DESCRIPTOR_POOL_SIZE,
+            descriptor_counts.storage_buffers,
+        ),
+        pool_size(
+            vk::DescriptorType::STORAGE_BUFFER_DYNAMIC,
+            STORAGE_DYNAMIC_DESCRIPTOR_POOL_SIZE,
+            descriptor_counts.dynamic_storage_buffers,
+        ),
+        pool_size(
+            vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            SAMPLER_DESCRIPTOR_POOL_SIZE,
+            descriptor_counts.combined_image_samplers,
+        ),
+        pool_size(
+            vk::DescriptorType::STORAGE_IMAGE,
+            STORAGE_IMAGE_DESCRIPTOR_POOL_SIZE,
+            descriptor_counts.storage_images,
+        ),
+    ];
+
+    (DESCRIPTOR_POOL_MAX_SETS.max(set_count), pool_sizes)
+}
+
+fn _create_descriptor_pool(
+    device: &ash::Device,
+    descriptor_counts: DescriptorCounts,
+    set_count: u32,
+) -> vk::DescriptorPool {
+    let (max_sets, pool_sizes) = _pool_sizes(descriptor_counts, set_count);
+    let descriptor_pool_create_info = vk::DescriptorPoolCreateInfo::builder()
+        .flags(vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET)
+        .max_sets(max_sets)
+        .pool_sizes(&pool_sizes);
+
+    unsafe {
+        device
+            .create_descriptor_pool(&descriptor_pool_create_info, None)
+            .expect("Failed to create Descriptor Pool!")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pool_sizes_fit_allocation() {
+        let bindings: Vec<vk::DescriptorSetLayoutBinding> = [
+            vk::DescriptorType::UNIFORM_BUFFER,
+            vk::DescriptorType::STORAGE_BUFFER_DYNAMIC,
+        ]
+        .iter()
+        .chain([vk::DescriptorType::COMBINED_IMAGE_SAMPLER; 48].iter())
+        .enumerate()
+        .map(|(binding, descriptor_type)| {
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(binding as u32)
+                .descriptor_type(*descriptor_type)
+                .descriptor_count(1)
+                .build()
+        })
+        .collect();
+        let counts = DescriptorCounts::from_bindings(&bindings);
+        assert_eq!(counts.uniform_buffers, 1);
+        assert_eq!(counts.dynamic_storage_buffers, 1);
+        assert_eq!(counts.combined_image_samplers, 48);
+
+        // Three images of a pipeline with 48 textures exceed the default sampler count
+        let (max_sets, pool_sizes) = _pool_sizes(counts, 3);
+        assert_eq!(max_sets, DESCRIPTOR_POOL_MAX_SETS);
+        let count_of = |ty| pool_sizes.iter().find(|size| size.ty == ty).unwrap().descriptor_count;
+        assert_eq!(count_of(vk::DescriptorType::UNIFORM_BUFFER), UNIFORM_DESCRIPTOR_POOL_SIZE);
+        assert_eq!(count_of(vk::DescriptorType::COMBINED_IMAGE_SAMPLER), 144);
+
+        let (max_sets, _) = _pool_sizes(DescriptorCounts::default(), 100);
+        assert_eq!(max_sets, 100);
+    }
+}
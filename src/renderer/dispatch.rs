@@ -0,0 +1,332 @@
+use ash::vk;
+
+use crate::renderer::buffer::BufferObjectManager;
+use crate::renderer::compute::ComputePipeline;
+use crate::renderer::descriptor::{DescriptorAllocation, DescriptorAllocator};
+use crate::renderer::texture::TextureManager;
+use crate::renderer::types::{
+    ComputeBinding, ComputeBufferHandle, ComputePipelineConfiguration, ComputePipelineHandle, TextureHandle,
+};
+
+struct ApplicationComputePipeline {
+    pipeline: ComputePipeline,
+    bindings: Vec<ComputeBinding>,
+    push_constant_size: usize,
+    descriptor_sets: DescriptorAllocation,
+}
+
+struct Dispatch {
+    pipeline: ComputePipelineHandle,
+    group_count: [u32; 3],
+    push_constant: Vec<u8>,
+}
+
+// Compute pipelines of applications, e.g. particle simulation or culling. Dispatches run in the order they are
+// queued, before the skinning and culling pre-passes and the render passes of the frame.
+pub(super) struct DispatchManager {
+    descriptor_allocator: DescriptorAllocator,
+    pipelines: Vec<ApplicationComputePipeline>,
+    buffers: Vec<vk::Buffer>,
+    storage_textures: Vec<TextureHandle>,
+    queued: Vec<Dispatch>,
+}
+
+impl DispatchManager {
+    pub(super) fn new() -> Self {
+        DispatchManager {
+            descriptor_allocator: DescriptorAllocator::new(),
+            pipelines: Vec::new(),
+            buffers: Vec::new(),
+            storage_textures: Vec::new(),
+            queued: Vec::new(),
+        }
+    }
+
+    // Owned by the memory manager, like the vertex buffers of skinned meshes
+    pub(super) fn add_buffer(&mut self, buffer: vk::Buffer) -> ComputeBufferHandle {
+        self.buffers.push(buffer);
+
+        self.buffers.len() - 1
+    }
+
+    pub(super) fn buffer(&self, handle: ComputeBufferHandle) -> vk::Buffer {
+        self.buffers[handle]
+    }
+
+    // Owned by the texture manager
+    pub(super) fn add_storage_texture(&mut self, texture: TextureHandle) {
+        self.storage_textures.push(texture);
+    }
+
+    pub(super) fn add_pipeline(
+        &mut self,
+        device: &ash::Device,
+        config: ComputePipelineConfiguration,
+    ) -> ComputePipelineHandle {
+        for binding in config.bindings.iter() {
+            match binding {
+                ComputeBinding::Buffer(buffer) => assert!(*buffer < self.buffers.len(), "Unknown compute buffer"),
+                ComputeBinding::Texture(texture) => {
+                    assert!(self.storage_textures.contains(texture), "Texture is not a storage texture")
+                }
+                ComputeBinding::BufferObject(_) => {}
+            }
+        }
+
+        let descriptor_types: Vec<vk::DescriptorType> =
+            config.bindings.iter().map(|binding| binding.descriptor_type()).collect();
+        let push_constant_size = config.push_constant_size.unwrap_or(0);
+        let pipeline =
+            ComputePipeline::from_code(device, &config.shader_code, &descriptor_types, push_constant_size as u32);
+        let descriptor_sets = self.descriptor_allocator.allocate(
+            device,
+            pipeline.descriptor_set_layout,
+            pipeline.descriptor_counts,
+            1,
+        );
+
+        self.pipelines.push(ApplicationComputePipeline {
+            pipeline,
+            bindings: config.bindings,
+            push_constant_size,
+            descriptor_sets,
+        });
+
+        self.pipelines.len() - 1
+    }
+
+    pub(super) fn queue(&mut self, pipeline: ComputePipelineHandle, group_count: [u32; 3], push_constant: &[u8]) {
+        assert!(pipeline < self.pipelines.len(), "Unknown compute pipeline");
+        assert_eq!(
+            push_constant.len(),
+            self.pipelines[pipeline].push_constant_size,
+            "Push constant does not match the compute pipeline"
+        );
+
+        self.queued.push(Dispatch {
+            pipeline,
+            group_count,
+            push_constant: push_constant.to_vec(),
+        });
+    }
+
+    pub(super) fn reset_queue(&mut self) {
+        self.queued.clear();
+    }
+
+    // Storage textures are sampled outside of the dispatches, they are moved to the general layout before the
+    // first dispatch and back after the last. Dispatches see the writes of the ones before them, the render
+    // passes and pre-passes see all writes as vertices, indices, indirect draws and shader reads. The descriptor
+    // sets are written once per pipeline as buffer objects are per frame in flight and may grow; they are not in
+    // use as the frame last recorded with them has finished. Buffer object uploads are waited for by the draw
+    // submission.
+    pub(super) unsafe fn bake_command_buffer(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        frame_index: usize,
+        buffer_object_manager: &BufferObjectManager,
+        texture_manager: &TextureManager,
+    ) {
+        if self.queued.is_empty() {
+            return;
+        }
+
+        let mut pipelines: Vec<ComputePipelineHandle> = self.queued.iter().map(|dispatch| dispatch.pipeline).collect();
+        pipelines.sort_unstable();
+        pipelines.dedup();
+        let mut images: Vec<vk::Image> = Vec::new();
+        for handle in pipelines.iter() {
+            let pipeline = &self.pipelines[*handle];
+            let resources: Vec<(vk::Buffer, vk::ImageView)> = pipeline
+                .bindings
+                .iter()
+                .map(|binding| match binding {
+                    ComputeBinding::BufferObject(index) => {
+                        (buffer_object_manager.borrow_buffer(*index).device(frame_index), vk::ImageView::null())
+                    }
+                    ComputeBinding::Buffer(buffer) => (self.buffers[*buffer], vk::ImageView::null()),
+                    ComputeBinding::Texture(texture) => (vk::Buffer::null(), texture_manager.get_imageview(*texture)),
+                })
+                .collect();
+            _write_descriptor_set(device, pipeline.descriptor_sets.sets()[0], &pipeline.bindings, &resources);
+            for binding in pipeline.bindings.iter() {
+                if let ComputeBinding::Texture(texture) = binding {
+                    let image = texture_manager.get_image(*texture);
+                    if !images.contains(&image) {
+                        images.push(image);
+                    }
+                }
+            }
+        }
+
+        let to_general = _image_barriers(
+            &images,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::ImageLayout::GENERAL,
+            vk::AccessFlags::SHADER_READ,
+            vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+        );
+        if !to_general.is_empty() {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::VERTEX_SHADER
+                    | vk::PipelineStageFlags::FRAGMENT_SHADER
+                    | vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &to_general,
+            );
+        }
+
+        for (index, dispatch) in self.queued.iter().enumerate() {
+            if index > 0 {
+                let memory_barriers = [vk::MemoryBarrier::builder()
+                    .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+                    .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)
+                    .build()];
+                device.cmd_pipeline_barrier(
+                    command_buffer,
+                    vk::PipelineStageFlags::COMPUTE_SHADER,
+                    vk::PipelineStageFlags::COMPUTE_SHADER,
+                    vk::DependencyFlags::empty(),
+                    &memory_barriers,
+                    &[],
+                    &[],
+                );
+            }
+
+            let pipeline = &self.pipelines[dispatch.pipeline];
+            pipeline
+                .pipeline
+                .bind(device, command_buffer, pipeline.descriptor_sets.sets()[0]);
+            if !dispatch.push_constant.is_empty() {
+                device.cmd_push_constants(
+                    command_buffer,
+                    pipeline.pipeline.pipeline_layout,
+                    vk::ShaderStageFlags::COMPUTE,
+                    0,
+                    &dispatch.push_constant,
+                );
+            }
+            let [x, y, z] = dispatch.group_count;
+            device.cmd_dispatch(command_buffer, x, y, z);
+        }
+
+        let memory_barriers = [vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(
+                vk::AccessFlags::VERTEX_ATTRIBUTE_READ
+                    | vk::AccessFlags::INDEX_READ
+                    | vk::AccessFlags::INDIRECT_COMMAND_READ
+                    | vk::AccessFlags::SHADER_READ,
+            )
+            .build()];
+        let to_sampled = _image_barriers(
+            &images,
+            vk::ImageLayout::GENERAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::AccessFlags::SHADER_WRITE,
+            vk::AccessFlags::SHADER_READ,
+        );
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::DRAW_INDIRECT
+                | vk::PipelineStageFlags::VERTEX_INPUT
+                | vk::PipelineStageFlags::VERTEX_SHADER
+                | vk::PipelineStageFlags::FRAGMENT_SHADER
+                | vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::DependencyFlags::empty(),
+            &memory_barriers,
+            &[],
+            &to_sampled,
+        );
+    }
+
+    pub(super) unsafe fn destroy(&mut self, device: &ash::Device) {
+        self.queued.clear();
+        self.buffers.clear();
+        self.storage_textures.clear();
+        self.descriptor_allocator.destroy(device);
+        for pipeline in self.pipelines.drain(..) {
+            pipeline.pipeline.destroy(device);
+        }
+    }
+}
+
+unsafe fn _write_descriptor_set(
+    device: &ash::Device,
+    descriptor_set: vk::DescriptorSet,
+    bindings: &[ComputeBinding],
+    resources: &[(vk::Buffer, vk::ImageView)],
+) {
+    let buffer_infos: Vec<[vk::DescriptorBufferInfo; 1]> = resources
+        .iter()
+        .map(|(buffer, _)| {
+            [vk::DescriptorBufferInfo {
+                buffer: *buffer,
+                offset: 0,
+                range: vk::WHOLE_SIZE,
+            }]
+        })
+        .collect();
+    let image_infos: Vec<[vk::DescriptorImageInfo; 1]> = resources
+        .iter()
+        .map(|(_, image_view)| {
+            [vk::DescriptorImageInfo {
+                sampler: vk::Sampler::null(),
+                image_view: *image_view,
+                image_layout: vk::ImageLayout::GENERAL,
+            }]
+        })
+        .collect();
+    let writes: Vec<vk::WriteDescriptorSet> = bindings
+        .iter()
+        .enumerate()
+        .map(|(index, binding)| {
+            let write = vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(index as u32)
+                .descriptor_type(binding.descriptor_type());
+            match binding {
+                ComputeBinding::Texture(_) => write.image_info(&image_infos[index]).build(),
+                _ => write.buffer_info(&buffer_infos[index]).build(),
+            }
+        })
+        .collect();
+
+    device.update_descriptor_sets(&writes, &[]);
+}
+
+fn _image_barriers(
+    images: &[vk::Image],
+    old_layout: vk::ImageLayout,
+    new_layout: vk::ImageLayout,
+    src_access_mask: vk::AccessFlags,
+    dst_access_mask: vk::AccessFlags,
+) -> Vec<vk::ImageMemoryBarrier> {
+    images
+        .iter()
+        .map(|image| {
+            vk::ImageMemoryBarrier::builder()
+                .src_access_mask(src_access_mask)
+                .dst_access_mask(dst_access_mask)
+                .old_layout(old_layout)
+                .new_layout(new_layout)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(*image)
+                .subresource_range(vk::ImageSubresourceRange {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    base_mip_level: 0,
+                    level_count: 1,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                })
+                .build()
+        })
+        .collect()
+}
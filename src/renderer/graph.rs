@@ -0,0 +1,202 @@
+use std::fmt::Write;
+
+use crate::renderer::types::{PipelineHandle, RenderPassHandle, TextureHandle};
+
+pub(super) enum GraphTarget {
+    Texture(TextureHandle),
+    Transparency,
+    Swapchain,
+}
+
+pub(super) struct GraphPass {
+    pub(super) handle: RenderPassHandle,
+    pub(super) order: u32,
+    pub(super) enabled: bool,
+    pub(super) target: GraphTarget,
+    pub(super) extent: (u32, u32),
+    pub(super) pipelines: Vec<GraphPipeline>,
+}
+
+pub(super) struct GraphPipeline {
+    pub(super) handle: PipelineHandle,
+    // Binding and sampled texture
+    pub(super) textures: Vec<(u8, TextureHandle)>,
+}
+
+pub(super) struct GraphBuffer {
+    pub(super) index: usize,
+    pub(super) kind: &'static str,
+    pub(super) pipelines: Vec<PipelineHandle>,
+}
+
+pub(super) struct GraphSkinnedMesh {
+    pub(super) index: usize,
+    pub(super) joint_buffer: usize,
+    // Skinned by the pre-pass of the current frame
+    pub(super) queued: bool,
+}
+
+// Graphviz DOT of the passes in execution order. Pipelines are linked to the pass they belong to, the buffer
+// objects and textures they read and the passes rendering into those textures. Skinned meshes run before the
+// first pass.
+pub(super) fn to_dot(passes: &[GraphPass], buffers: &[GraphBuffer], skinned_meshes: &[GraphSkinnedMesh]) -> String {
+    let mut dot = String::new();
+    let _ = writeln!(dot, "digraph frame {{");
+    let _ = writeln!(dot, "    rankdir=LR;");
+
+    for pass in passes.iter() {
+        let style = if pass.enabled { "solid" } else { "dashed" };
+        let _ = writeln!(
+            dot,
+            "    {} [shape=box, style={}, label=\"{}\\norder {}\\n{}x{}\"];",
+            _pass_node(pass.handle, &pass.target),
+            style,
+            _pass_name(pass.handle, &pass.target),
+            pass.order,
+            pass.extent.0,
+            pass.extent.1
+        );
+        if let GraphTarget::Texture(texture) = pass.target {
+            let _ = writeln!(dot, "    {} -> texture_{} [label=\"target\"];", _pass_node(pass.handle, &pass.target), texture);
+        }
+
+        for pipeline in pass.pipelines.iter() {
+            let node = _pipeline_node(pipeline.handle);
+            let _ = writeln!(dot, "    {} [shape=ellipse, label=\"pipeline {}\"];", node, pipeline.handle.index());
+            let _ = writeln!(dot, "    {} -> {};", _pass_node(pass.handle, &pass.target), node);
+            for (binding, texture) in pipeline.textures.iter() {
+                let _ = writeln!(dot, "    texture_{} -> {} [label=\"binding {}\"];", texture, node, binding);
+            }
+        }
+    }
+
+    for (previous, next) in passes.iter().zip(passes.iter().skip(1)) {
+        let _ = writeln!(
+            dot,
+            "    {} -> {} [style=dotted, label=\"then\"];",
+            _pass_node(previous.handle, &previous.target),
+            _pass_node(next.handle, &next.target)
+        );
+    }
+
+    let mut textures: Vec<TextureHandle> = passes
+        .iter()
+        .flat_map(|pass| {
+            let target = match pass.target {
+                GraphTarget::Texture(texture) => Some(texture),
+                _ => None,
+            };
+            pass.pipelines
+                .iter()
+                .flat_map(|pipeline| pipeline.textures.iter().map(|(_, texture)| *texture))
+                .chain(target)
+        })
+        .collect();
+    textures.sort_unstable();
+    textures.dedup();
+    for texture in textures {
+        let _ = writeln!(dot, "    texture_{} [shape=note, label=\"texture {}\"];", texture, texture);
+    }
+
+    for mesh in skinned_meshes.iter() {
+        let style = if mesh.queued { "solid" } else { "dashed" };
+        let _ = writeln!(
+            dot,
+            "    skinning_{} [shape=box, style={}, label=\"skinning {}\\ncompute\"];",
+            mesh.index, style, mesh.index
+        );
+        let _ = writeln!(dot, "    buffer_{} -> skinning_{} [label=\"joints\"];", mesh.joint_buffer, mesh.index);
+        if let Some(first) = passes.first() {
+            let _ = writeln!(
+                dot,
+                "    skinning_{} -> {} [style=dotted, label=\"then\"];",
+                mesh.index,
+                _pass_node(first.handle, &first.target)
+            );
+        }
+    }
+
+    let is_read = |buffer: &&GraphBuffer| {
+        !buffer.pipelines.is_empty() || skinned_meshes.iter().any(|mesh| mesh.joint_buffer == buffer.index)
+    };
+    for buffer in buffers.iter().filter(is_read) {
+        let _ = writeln!(
+            dot,
+            "    buffer_{} [shape=cylinder, label=\"buffer {}\\n{}\"];",
+            buffer.index, buffer.index, buffer.kind
+        );
+        for pipeline in buffer.pipelines.iter() {
+            let _ = writeln!(dot, "    buffer_{} -> {};", buffer.index, _pipeline_node(*pipeline));
+        }
+    }
+
+    dot.push_str("}\n");
+    dot
+}
+
+fn _pass_name(handle: RenderPassHandle, target: &GraphTarget) -> String {
+    match target {
+        GraphTarget::Swapchain => "swapchain pass".to_string(),
+        GraphTarget::Transparency => format!("transparency pass {}", handle),
+        GraphTarget::Texture(_) => format!("pass {}", handle),
+    }
+}
+
+fn _pass_node(handle: RenderPassHandle, target: &GraphTarget) -> String {
+    match target {
+        GraphTarget::Swapchain => "pass_swapchain".to_string(),
+        _ => format!("pass_{}", handle),
+    }
+}
+
+fn _pipeline_node(handle: PipelineHandle) -> String {
+    format!("pipeline_{}_{}", handle.render_pass, handle.index())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::types::SWAPCHAIN_PASS;
+
+    #[test]
+    fn render_to_texture_graph() {
+        let offscreen_pipeline = PipelineHandle::new(0, 0);
+        let present_pipeline = PipelineHandle::new(SWAPCHAIN_PASS, 0);
+        let passes = vec![
+            GraphPass {
+                handle: 0,
+                order: 0,
+                enabled: true,
+                target: GraphTarget::Texture(3),
+                extent: (384, 216),
+                pipelines: vec![GraphPipeline { handle: offscreen_pipeline, textures: Vec::new() }],
+            },
+            GraphPass {
+                handle: SWAPCHAIN_PASS,
+                order: SWAPCHAIN_PASS,
+                enabled: true,
+                target: GraphTarget::Swapchain,
+                extent: (1280, 720),
+                pipelines: vec![GraphPipeline { handle: present_pipeline, textures: vec![(1, 3)] }],
+            },
+        ];
+        let buffers = vec![
+            GraphBuffer { index: 0, kind: "vertex uniform", pipelines: vec![offscreen_pipeline, present_pipeline] },
+            GraphBuffer { index: 1, kind: "storage", pipelines: Vec::new() },
+            GraphBuffer { index: 2, kind: "storage", pipelines: Vec::new() },
+        ];
+
+        let skinned_meshes = vec![GraphSkinnedMesh { index: 0, joint_buffer: 1, queued: true }];
+
+        let dot = to_dot(&passes, &buffers, &skinned_meshes);
+        assert!(dot.starts_with("digraph frame {"));
+        assert!(dot.contains("pass_0 -> texture_3 [label=\"target\"];"));
+        assert!(dot.contains("texture_3 -> pipeline_100000_0 [label=\"binding 1\"];"));
+        assert!(dot.contains("pass_0 -> pass_swapchain [style=dotted"));
+        assert!(dot.contains("buffer_0 -> pipeline_0_0;"));
+        assert!(dot.contains("buffer_1 -> skinning_0 [label=\"joints\"];"));
+        assert!(dot.contains("skinning_0 -> pass_0 [style=dotted"));
+        assert!(!dot.contains("buffer_2"));
+        assert_eq!(dot.matches("texture_3 [shape=note").count(), 1);
+    }
+}
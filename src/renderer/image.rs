@@ -11,10 +11,83 @@ pub fn create_static_image(
     image_height: u32,
     image_data: &[u8],
 ) -> (vk::Image, vk::DeviceMemory) {
-    if image_width == 0 || image_height == 0 || image_data.is_empty() {
+    let extent = vk::Extent2D {
+        width: image_width,
+        height: image_height,
+    };
+    create_static_image_array(
+        device,
+        command_pool,
+        submit_queue,
+        memory_manager,
+        extent,
+        1,
+        image_data,
+    )
+}
+
+// The layers are tightly packed one after another in the data
+pub fn create_static_image_array(
+    device: &ash::Device,
+    command_pool: vk::CommandPool,
+    submit_queue: vk::Queue,
+    memory_manager: &mut MemoryManager,
+    extent: vk::Extent2D,
+    layers: u32,
+    image_data: &[u8],
+) -> (vk::Image, vk::DeviceMemory) {
+    _create_static_image(
+        device,
+        command_pool,
+        submit_queue,
+        memory_manager,
+        extent,
+        layers,
+        vk::ImageCreateFlags::empty(),
+        image_data,
+    )
+}
+
+// Six square faces in the order +x, -x, +y, -y, +z, -z
+pub fn create_static_cube_image(
+    device: &ash::Device,
+    command_pool: vk::CommandPool,
+    submit_queue: vk::Queue,
+    memory_manager: &mut MemoryManager,
+    face_size: u32,
+    image_data: &[u8],
+) -> (vk::Image, vk::DeviceMemory) {
+    let extent = vk::Extent2D {
+        width: face_size,
+        height: face_size,
+    };
+    _create_static_image(
+        device,
+        command_pool,
+        submit_queue,
+        memory_manager,
+        extent,
+        6,
+        vk::ImageCreateFlags::CUBE_COMPATIBLE,
+        image_data,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn _create_static_image(
+    device: &ash::Device,
+    command_pool: vk::CommandPool,
+    submit_queue: vk::Queue,
+    memory_manager: &mut MemoryManager,
+    extent: vk::Extent2D,
+    layers: u32,
+    flags: vk::ImageCreateFlags,
+    image_data: &[u8],
+) -> (vk::Image, vk::DeviceMemory) {
+    if extent.width == 0 || extent.height == 0 || layers == 0 || image_data.is_empty() {
         panic!("Failed to crate texture image!")
     }
-    assert_eq!((image_width * image_height * 4) as usize, image_data.len());
+    assert_eq!((extent.width * extent.height * 4 * layers) as usize, image_data.len());
 
     let staging_buffer = memory_manager.create_staging_buffer(device, image_data.len() as vk::DeviceSize);
 
@@ -24,9 +97,11 @@ pub fn create_static_image(
 
     let (texture_image, texture_image_memory) = create_image(
         device,
-        image_width,
-        image_height,
+        extent.width,
+        extent.height,
         1,
+        layers,
+        flags,
         vk::SampleCountFlags::TYPE_1,
         vk::Format::R8G8B8A8_SRGB,
         vk::ImageTiling::OPTIMAL,
@@ -40,7 +115,7 @@ pub fn create_static_image(
         command_pool,
         submit_queue,
         texture_image,
-        vk::Format::R8G8B8A8_UNORM,
+        layers,
         vk::ImageLayout::UNDEFINED,
         vk::ImageLayout::TRANSFER_DST_OPTIMAL,
     );
@@ -51,8 +126,8 @@ pub fn create_static_image(
         submit_queue,
         staging_buffer,
         texture_image,
-        image_width,
-        image_height,
+        extent,
+        layers,
     );
 
     transition_image_layout(
@@ -60,7 +135,7 @@ pub fn create_static_image(
         command_pool,
         submit_queue,
         texture_image,
-        vk::Format::R8G8B8A8_UNORM,
+        layers,
         vk::ImageLayout::TRANSFER_DST_OPTIMAL,
         vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
     );
@@ -72,27 +147,116 @@ pub fn create_static_image(
     (texture_image, texture_image_memory)
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn create_colorattachment_image(
     device: &ash::Device,
     command_pool: vk::CommandPool,
     submit_queue: vk::Queue,
     memory_manager: &mut MemoryManager,
-    image_width: u32,
-    image_height: u32,
+    extent: vk::Extent2D,
+    layers: u32,
+    format: vk::Format,
+    usage: vk::ImageUsageFlags,
 ) -> (vk::Image, vk::DeviceMemory) {
-    if image_width == 0 || image_height == 0 {
+    if extent.width == 0 || extent.height == 0 || layers == 0 {
         panic!("Failed to crate texture image!")
     }
 
     let (texture_image, texture_image_memory) = create_image(
         device,
-        image_width,
-        image_height,
+        extent.width,
+        extent.height,
         1,
+        layers,
+        vk::ImageCreateFlags::empty(),
         vk::SampleCountFlags::TYPE_1,
-        vk::Format::R8G8B8A8_SRGB,
+        format,
+        vk::ImageTiling::OPTIMAL,
+        usage,
+        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        memory_manager.physical_device_memory_properties(),
+    );
+
+    transition_image_layout(
+        device,
+        command_pool,
+        submit_queue,
+        texture_image,
+        layers,
+        vk::ImageLayout::UNDEFINED,
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+    );
+
+    (texture_image, texture_image_memory)
+}
+
+// Sampled and written by compute shaders in the general layout, every level is transitioned
+pub fn create_storage_image(
+    device: &ash::Device,
+    command_pool: vk::CommandPool,
+    submit_queue: vk::Queue,
+    memory_manager: &mut MemoryManager,
+    image_extent: vk::Extent2D,
+    mip_levels: u32,
+    format: vk::Format,
+) -> (vk::Image, vk::DeviceMemory) {
+    if image_extent.width == 0 || image_extent.height == 0 || mip_levels == 0 {
+        panic!("Failed to crate storage image!")
+    }
+
+    let (storage_image, storage_image_memory) = create_image(
+        device,
+        image_extent.width,
+        image_extent.height,
+        mip_levels,
+        1,
+        vk::ImageCreateFlags::empty(),
+        vk::SampleCountFlags::TYPE_1,
+        format,
         vk::ImageTiling::OPTIMAL,
-        vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+        vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED,
+        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        memory_manager.physical_device_memory_properties(),
+    );
+
+    transition_image_layout(
+        device,
+        command_pool,
+        submit_queue,
+        storage_image,
+        1,
+        vk::ImageLayout::UNDEFINED,
+        vk::ImageLayout::GENERAL,
+    );
+
+    (storage_image, storage_image_memory)
+}
+
+// Written by compute pipelines in the general layout, sampled in between. Starts out sampled, see
+// DispatchManager::bake_command_buffer.
+pub fn create_compute_texture_image(
+    device: &ash::Device,
+    command_pool: vk::CommandPool,
+    submit_queue: vk::Queue,
+    memory_manager: &mut MemoryManager,
+    image_extent: vk::Extent2D,
+    format: vk::Format,
+) -> (vk::Image, vk::DeviceMemory) {
+    if image_extent.width == 0 || image_extent.height == 0 {
+        panic!("Failed to create compute texture image!")
+    }
+
+    let (texture_image, texture_image_memory) = create_image(
+        device,
+        image_extent.width,
+        image_extent.height,
+        1,
+        1,
+        vk::ImageCreateFlags::empty(),
+        vk::SampleCountFlags::TYPE_1,
+        format,
+        vk::ImageTiling::OPTIMAL,
+        vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED,
         vk::MemoryPropertyFlags::DEVICE_LOCAL,
         memory_manager.physical_device_memory_properties(),
     );
@@ -102,7 +266,7 @@ pub fn create_colorattachment_image(
         command_pool,
         submit_queue,
         texture_image,
-        vk::Format::R8G8B8A8_UNORM,
+        1,
         vk::ImageLayout::UNDEFINED,
         vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
     );
@@ -110,41 +274,226 @@ pub fn create_colorattachment_image(
     (texture_image, texture_image_memory)
 }
 
+pub fn read_color_image(
+    device: &ash::Device,
+    command_pool: vk::CommandPool,
+    submit_queue: vk::Queue,
+    memory_manager: &mut MemoryManager,
+    image: vk::Image,
+    image_width: u32,
+    image_height: u32,
+) -> Vec<u8> {
+    let size = (image_width * image_height * 4) as usize;
+    let readback_buffer = memory_manager.create_readback_buffer(device, size as vk::DeviceSize);
+
+    transition_image_layout(
+        device,
+        command_pool,
+        submit_queue,
+        image,
+        1,
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+    );
+
+    copy_image_to_buffer(
+        device,
+        command_pool,
+        submit_queue,
+        image,
+        readback_buffer,
+        image_width,
+        image_height,
+    );
+
+    transition_image_layout(
+        device,
+        command_pool,
+        submit_queue,
+        image,
+        1,
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+    );
+
+    let mut data = vec![0u8; size];
+    unsafe {
+        memory_manager.copy_from_buffer_memory(device, readback_buffer, &mut data);
+        memory_manager.destroy_buffer(device, readback_buffer);
+    }
+
+    data
+}
+
 pub fn create_depth_resources(
     device: &ash::Device,
     image_extent: vk::Extent2D,
     device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
     depth_format: vk::Format,
+    layers: u32,
+    samples: vk::SampleCountFlags,
 ) -> (vk::Image, vk::ImageView, vk::DeviceMemory) {
+    // Sampled when building the HiZ pyramid of the pass, which multisampled passes don't support
+    let usage = if samples == vk::SampleCountFlags::TYPE_1 {
+        vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED
+    } else {
+        vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
+    };
     let (depth_image, depth_image_memory) = create_image(
         device,
         image_extent.width,
         image_extent.height,
         1,
-        vk::SampleCountFlags::TYPE_1,
+        layers,
+        vk::ImageCreateFlags::empty(),
+        samples,
         depth_format,
         vk::ImageTiling::OPTIMAL,
-        vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+        usage,
         vk::MemoryPropertyFlags::DEVICE_LOCAL,
         device_memory_properties,
     );
-    let depth_image_view = create_image_view(device, depth_image, depth_format, vk::ImageAspectFlags::DEPTH, 1);
+    let depth_image_view =
+        create_layered_image_view(device, depth_image, depth_format, vk::ImageAspectFlags::DEPTH, layers);
 
     (depth_image, depth_image_view, depth_image_memory)
 }
 
+// Color attachment of a multisampled pass, only resolved into the target and never stored
+pub fn create_multisample_color_resources(
+    device: &ash::Device,
+    image_extent: vk::Extent2D,
+    device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
+    color_format: vk::Format,
+    samples: vk::SampleCountFlags,
+) -> (vk::Image, vk::ImageView, vk::DeviceMemory) {
+    let (color_image, color_image_memory) = create_image(
+        device,
+        image_extent.width,
+        image_extent.height,
+        1,
+        1,
+        vk::ImageCreateFlags::empty(),
+        samples,
+        color_format,
+        vk::ImageTiling::OPTIMAL,
+        vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
+        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        device_memory_properties,
+    );
+    let color_image_view = create_image_view(device, color_image, color_format, vk::ImageAspectFlags::COLOR, 1);
+
+    (color_image, color_image_view, color_image_memory)
+}
+
 pub fn create_image_view(
     device: &ash::Device,
     image: vk::Image,
     format: vk::Format,
     aspect_flags: vk::ImageAspectFlags,
     mip_levels: u32,
+) -> vk::ImageView {
+    _create_image_view(
+        device,
+        image,
+        format,
+        aspect_flags,
+        mip_levels,
+        1,
+        vk::ImageViewType::TYPE_2D,
+    )
+}
+
+// Views a single mip level, e.g. to write it as storage image
+pub fn create_mip_level_image_view(
+    device: &ash::Device,
+    image: vk::Image,
+    format: vk::Format,
+    aspect_flags: vk::ImageAspectFlags,
+    mip_level: u32,
+) -> vk::ImageView {
+    let imageview_create_info = vk::ImageViewCreateInfo::builder()
+        .image(image)
+        .view_type(vk::ImageViewType::TYPE_2D)
+        .format(format)
+        .subresource_range(vk::ImageSubresourceRange {
+            aspect_mask: aspect_flags,
+            base_mip_level: mip_level,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        });
+
+    unsafe {
+        device
+            .create_image_view(&imageview_create_info, None)
+            .expect("Failed to create Image View!")
+    }
+}
+
+// Views all layers of the image as a 2D array, used for multiview targets
+pub fn create_layered_image_view(
+    device: &ash::Device,
+    image: vk::Image,
+    format: vk::Format,
+    aspect_flags: vk::ImageAspectFlags,
+    layer_count: u32,
+) -> vk::ImageView {
+    _create_image_view(
+        device,
+        image,
+        format,
+        aspect_flags,
+        1,
+        layer_count,
+        _layered_view_type(layer_count),
+    )
+}
+
+pub fn create_cube_image_view(device: &ash::Device, image: vk::Image, format: vk::Format) -> vk::ImageView {
+    _create_image_view(device, image, format, vk::ImageAspectFlags::COLOR, 1, 6, vk::ImageViewType::CUBE)
+}
+
+// Always a 2D array view, also for a single layer, so it can be sampled as sampler2DArray
+pub fn create_array_image_view(
+    device: &ash::Device,
+    image: vk::Image,
+    format: vk::Format,
+    layer_count: u32,
+) -> vk::ImageView {
+    _create_image_view(
+        device,
+        image,
+        format,
+        vk::ImageAspectFlags::COLOR,
+        1,
+        layer_count,
+        vk::ImageViewType::TYPE_2D_ARRAY,
+    )
+}
+
+fn _layered_view_type(layer_count: u32) -> vk::ImageViewType {
+    if layer_count > 1 {
+        vk::ImageViewType::TYPE_2D_ARRAY
+    } else {
+        vk::ImageViewType::TYPE_2D
+    }
+}
+
+fn _create_image_view(
+    device: &ash::Device,
+    image: vk::Image,
+    format: vk::Format,
+    aspect_flags: vk::ImageAspectFlags,
+    mip_levels: u32,
+    layer_count: u32,
+    view_type: vk::ImageViewType,
 ) -> vk::ImageView {
     let imageview_create_info = vk::ImageViewCreateInfo {
         s_type: vk::StructureType::IMAGE_VIEW_CREATE_INFO,
         p_next: ptr::null(),
         flags: vk::ImageViewCreateFlags::empty(),
-        view_type: vk::ImageViewType::TYPE_2D,
+        view_type,
         format,
         components: vk::ComponentMapping {
             r: vk::ComponentSwizzle::IDENTITY,
@@ -157,7 +506,7 @@ pub fn create_image_view(
             base_mip_level: 0,
             level_count: mip_levels,
             base_array_layer: 0,
-            layer_count: 1,
+            layer_count,
         },
         image,
     };
@@ -179,15 +528,18 @@ pub fn find_depth_format(instance: &ash::Instance, physical_device: vk::Physical
             vk::Format::D24_UNORM_S8_UINT,
         ],
         vk::ImageTiling::OPTIMAL,
-        vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT,
+        vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT | vk::FormatFeatureFlags::SAMPLED_IMAGE,
     )
 }
 
+#[allow(clippy::too_many_arguments)]
 fn create_image(
     device: &ash::Device,
     width: u32,
     height: u32,
     mip_levels: u32,
+    array_layers: u32,
+    flags: vk::ImageCreateFlags,
     num_samples: vk::SampleCountFlags,
     format: vk::Format,
     tiling: vk::ImageTiling,
@@ -198,11 +550,11 @@ fn create_image(
     let image_create_info = vk::ImageCreateInfo {
         s_type: vk::StructureType::IMAGE_CREATE_INFO,
         p_next: ptr::null(),
-        flags: vk::ImageCreateFlags::empty(),
+        flags,
         image_type: vk::ImageType::TYPE_2D,
         format,
         mip_levels,
-        array_layers: 1,
+        array_layers,
         samples: num_samples,
         tiling,
         usage,
@@ -356,7 +708,7 @@ fn transition_image_layout(
     command_pool: vk::CommandPool,
     submit_queue: vk::Queue,
     image: vk::Image,
-    _format: vk::Format,
+    layer_count: u32,
     old_layout: vk::ImageLayout,
     new_layout: vk::ImageLayout,
 ) {
@@ -384,6 +736,26 @@ fn transition_image_layout(
         dst_access_mask = vk::AccessFlags::SHADER_READ;
         source_stage = vk::PipelineStageFlags::TOP_OF_PIPE;
         destination_stage = vk::PipelineStageFlags::FRAGMENT_SHADER;
+    } else if old_layout == vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+        && new_layout == vk::ImageLayout::TRANSFER_SRC_OPTIMAL
+    {
+        // Render passes and dispatches leave the image sampled, their writes are already made available
+        src_access_mask = vk::AccessFlags::SHADER_READ;
+        dst_access_mask = vk::AccessFlags::TRANSFER_READ;
+        source_stage = vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER;
+        destination_stage = vk::PipelineStageFlags::TRANSFER;
+    } else if old_layout == vk::ImageLayout::TRANSFER_SRC_OPTIMAL
+        && new_layout == vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+    {
+        src_access_mask = vk::AccessFlags::TRANSFER_READ;
+        dst_access_mask = vk::AccessFlags::SHADER_READ;
+        source_stage = vk::PipelineStageFlags::TRANSFER;
+        destination_stage = vk::PipelineStageFlags::FRAGMENT_SHADER;
+    } else if old_layout == vk::ImageLayout::UNDEFINED && new_layout == vk::ImageLayout::GENERAL {
+        src_access_mask = vk::AccessFlags::empty();
+        dst_access_mask = vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE;
+        source_stage = vk::PipelineStageFlags::TOP_OF_PIPE;
+        destination_stage = vk::PipelineStageFlags::COMPUTE_SHADER;
     } else {
         unreachable!("Unsupported layout transition! {:?}- > {:?}", old_layout, new_layout)
     }
@@ -401,9 +773,10 @@ fn transition_image_layout(
         subresource_range: vk::ImageSubresourceRange {
             aspect_mask: vk::ImageAspectFlags::COLOR,
             base_mip_level: 0,
-            level_count: 1,
+            // Storage images stay in the general layout with all their levels
+            level_count: if new_layout == vk::ImageLayout::GENERAL { vk::REMAINING_MIP_LEVELS } else { 1 },
             base_array_layer: 0,
-            layer_count: 1,
+            layer_count,
         },
     }];
 
@@ -428,6 +801,48 @@ fn copy_buffer_to_image(
     submit_queue: vk::Queue,
     buffer: vk::Buffer,
     image: vk::Image,
+    extent: vk::Extent2D,
+    layer_count: u32,
+) {
+    let command_buffer = begin_single_time_command(device, command_pool);
+
+    let buffer_image_regions = [vk::BufferImageCopy {
+        image_subresource: vk::ImageSubresourceLayers {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_level: 0,
+            base_array_layer: 0,
+            layer_count,
+        },
+        image_extent: vk::Extent3D {
+            width: extent.width,
+            height: extent.height,
+            depth: 1,
+        },
+        buffer_offset: 0,
+        buffer_image_height: 0,
+        buffer_row_length: 0,
+        image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+    }];
+
+    unsafe {
+        device.cmd_copy_buffer_to_image(
+            command_buffer,
+            buffer,
+            image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &buffer_image_regions,
+        );
+    }
+
+    end_single_time_command(device, command_pool, submit_queue, command_buffer);
+}
+
+fn copy_image_to_buffer(
+    device: &ash::Device,
+    command_pool: vk::CommandPool,
+    submit_queue: vk::Queue,
+    image: vk::Image,
+    buffer: vk::Buffer,
     width: u32,
     height: u32,
 ) {
@@ -452,11 +867,11 @@ fn copy_buffer_to_image(
     }];
 
     unsafe {
-        device.cmd_copy_buffer_to_image(
+        device.cmd_copy_image_to_buffer(
             command_buffer,
-            buffer,
             image,
-            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            buffer,
             &buffer_image_regions,
         );
     }
@@ -497,9 +912,18 @@ pub fn create_framebuffer(
         attachments.push(depth_view);
     }
 
+    create_framebuffer_with_attachments(device, &attachments, extent, render_pass)
+}
+
+pub fn create_framebuffer_with_attachments(
+    device: &ash::Device,
+    attachments: &[vk::ImageView],
+    extent: vk::Extent2D,
+    render_pass: vk::RenderPass,
+) -> vk::Framebuffer {
     let framebuffer_create_info = vk::FramebufferCreateInfo::builder()
         .render_pass(render_pass)
-        .attachments(&attachments)
+        .attachments(attachments)
         .width(extent.width)
         .height(extent.height)
         .layers(1)
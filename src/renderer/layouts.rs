@@ -0,0 +1,187 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use ash::vk;
+
+use crate::renderer::types::{PipelineHandle, RenderPassHandle, TextureHandle};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(super) enum LayoutImage {
+    Texture(TextureHandle),
+    // Depth buffer of an image pass
+    Depth(RenderPassHandle),
+}
+
+impl fmt::Display for LayoutImage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LayoutImage::Texture(texture) => write!(f, "texture {}", texture),
+            LayoutImage::Depth(pass) => write!(f, "the depth buffer of pass {}", pass),
+        }
+    }
+}
+
+// Layouts of an attachment as declared by the render pass: before, during and after the subpass
+pub(super) struct LayoutAttachment {
+    pub(super) image: LayoutImage,
+    pub(super) initial_layout: vk::ImageLayout,
+    pub(super) layout: vk::ImageLayout,
+    pub(super) final_layout: vk::ImageLayout,
+}
+
+pub(super) struct LayoutPass {
+    // None for the swapchain pass
+    pub(super) handle: Option<RenderPassHandle>,
+    pub(super) attachments: Vec<LayoutAttachment>,
+    // Bound by the pipelines or pushed by the draw commands of the frame, sampled in the shader read only layout
+    pub(super) sampled: Vec<(PipelineHandle, TextureHandle)>,
+}
+
+// Follows the layouts of attachments and sampled textures through the enabled passes in execution order, starting
+// from the layouts images are created in: textures are sampled, depth buffers undefined. Attachments must be in the
+// initial layout of their render pass unless it is undefined, sampled textures must not be attachments of the
+// pass sampling them and textures have to be sampled again at the end of the frame.
+pub(super) fn validate(passes: &[LayoutPass]) -> Result<(), String> {
+    let mut layouts: BTreeMap<LayoutImage, vk::ImageLayout> = BTreeMap::new();
+
+    for pass in passes.iter() {
+        for attachment in pass.attachments.iter() {
+            let layout = _current_layout(&layouts, attachment.image);
+            if attachment.initial_layout != vk::ImageLayout::UNDEFINED && attachment.initial_layout != layout {
+                return Err(format!(
+                    "{} expects {} in {:?} but it is in {:?}, the pass writing it must be enabled and come first",
+                    _pass_name(pass.handle),
+                    attachment.image,
+                    attachment.initial_layout,
+                    layout
+                ));
+            }
+            layouts.insert(attachment.image, attachment.layout);
+        }
+
+        for (pipeline, texture) in pass.sampled.iter() {
+            let layout = _current_layout(&layouts, LayoutImage::Texture(*texture));
+            if layout != vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL {
+                return Err(format!(
+                    "{} samples texture {} with pipeline {} while it is in {:?} as an attachment of the pass",
+                    _pass_name(pass.handle),
+                    texture,
+                    pipeline.index(),
+                    layout
+                ));
+            }
+        }
+
+        for attachment in pass.attachments.iter() {
+            layouts.insert(attachment.image, attachment.final_layout);
+        }
+    }
+
+    for (image, layout) in layouts.iter() {
+        if let LayoutImage::Texture(_) = image {
+            if *layout != vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL {
+                return Err(format!("At the end of the frame {} is left in {:?}", image, layout));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn _current_layout(layouts: &BTreeMap<LayoutImage, vk::ImageLayout>, image: LayoutImage) -> vk::ImageLayout {
+    match (layouts.get(&image), image) {
+        (Some(layout), _) => *layout,
+        (None, LayoutImage::Texture(_)) => vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        (None, LayoutImage::Depth(_)) => vk::ImageLayout::UNDEFINED,
+    }
+}
+
+fn _pass_name(handle: Option<RenderPassHandle>) -> String {
+    match handle {
+        Some(handle) => format!("Pass {}", handle),
+        None => "The swapchain pass".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::types::SWAPCHAIN_PASS;
+
+    fn _color(texture: TextureHandle) -> LayoutAttachment {
+        LayoutAttachment {
+            image: LayoutImage::Texture(texture),
+            initial_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            final_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        }
+    }
+
+    fn _depth(pass: RenderPassHandle, initial_layout: vk::ImageLayout) -> LayoutAttachment {
+        LayoutAttachment {
+            image: LayoutImage::Depth(pass),
+            initial_layout,
+            layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            final_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        }
+    }
+
+    #[test]
+    fn layout_tracking() {
+        let scene = LayoutPass {
+            handle: Some(1),
+            attachments: vec![_color(10), _depth(1, vk::ImageLayout::UNDEFINED)],
+            sampled: vec![(PipelineHandle::new(1, 0), 20)],
+        };
+        let transparency = LayoutPass {
+            handle: Some(2),
+            attachments: vec![_color(11), _color(12), _depth(1, vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)],
+            sampled: Vec::new(),
+        };
+        let swapchain = LayoutPass {
+            handle: None,
+            attachments: Vec::new(),
+            sampled: vec![(PipelineHandle::new(SWAPCHAIN_PASS, 0), 10), (PipelineHandle::new(SWAPCHAIN_PASS, 1), 11)],
+        };
+        assert_eq!(validate(&[scene, transparency, swapchain]), Ok(()));
+
+        // Transparency baked before the pass writing its depth buffer, or with that pass disabled
+        let transparency = LayoutPass {
+            handle: Some(2),
+            attachments: vec![_color(11), _depth(1, vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)],
+            sampled: Vec::new(),
+        };
+        assert_eq!(
+            validate(&[transparency]),
+            Err("Pass 2 expects the depth buffer of pass 1 in DEPTH_STENCIL_ATTACHMENT_OPTIMAL but it is in \
+                 UNDEFINED, the pass writing it must be enabled and come first"
+                .to_string())
+        );
+
+        // Sampling the texture the pass renders into
+        let feedback = LayoutPass {
+            handle: Some(3),
+            attachments: vec![_color(10), _depth(3, vk::ImageLayout::UNDEFINED)],
+            sampled: vec![(PipelineHandle::new(3, 2), 10)],
+        };
+        assert_eq!(
+            validate(&[feedback]),
+            Err("Pass 3 samples texture 10 with pipeline 2 while it is in COLOR_ATTACHMENT_OPTIMAL as an attachment of \
+                 the pass"
+                .to_string())
+        );
+
+        let unfinished = LayoutPass {
+            handle: Some(4),
+            attachments: vec![LayoutAttachment {
+                final_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                .._color(13)
+            }],
+            sampled: Vec::new(),
+        };
+        assert_eq!(
+            validate(&[unfinished]),
+            Err("At the end of the frame texture 13 is left in COLOR_ATTACHMENT_OPTIMAL".to_string())
+        );
+    }
+}
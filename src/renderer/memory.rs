@@ -2,28 +2,68 @@ use std::ptr;
 
 use ash::vk;
 
+use crate::renderer::allocator::FreeList;
 use crate::renderer::types::Index;
 use crate::renderer::types::VertexInputDescription;
 use ash::vk::PhysicalDeviceMemoryProperties;
 use std::collections::HashMap;
 
-//  TODO: make it possible allocate a buffer on preexisting memory.
-//  TODO: Maybe create an allocator that can handle the memory allocation completety separate from buffer allocation
+const HOST_VISIBLE_DEVICE_LOCAL: vk::MemoryPropertyFlags = vk::MemoryPropertyFlags::from_raw(
+    vk::MemoryPropertyFlags::DEVICE_LOCAL.as_raw()
+        | vk::MemoryPropertyFlags::HOST_VISIBLE.as_raw()
+        | vk::MemoryPropertyFlags::HOST_COHERENT.as_raw(),
+);
+
+// Buffers are placed in blocks of memory per memory type, so that the number of allocations stays far below
+// maxMemoryAllocationCount. Blocks of host visible memory stay mapped.
+const BLOCK_SIZE: vk::DeviceSize = 64 * 1024 * 1024;
+
+struct MemoryBlock {
+    memory: vk::DeviceMemory,
+    memory_type: u32,
+    free_list: FreeList,
+    // Null when not host visible
+    mapped: *mut u8,
+    // Of a single buffer larger than half a block, freed with it
+    dedicated: bool,
+}
+
+#[derive(Clone, Copy)]
+struct Allocation {
+    block: usize,
+    offset: vk::DeviceSize,
+    size: vk::DeviceSize,
+}
+
 pub struct MemoryManager {
     physical_device_memory_properties: vk::PhysicalDeviceMemoryProperties,
-    buffer_to_chunk_map: HashMap<vk::Buffer, vk::DeviceMemory>,
-}
+    // Freed blocks leave a hole so that the allocations keep their index
+    blocks: Vec<Option<MemoryBlock>>,
+    buffer_allocations: HashMap<vk::Buffer, Allocation>,
 
-impl MemoryManager {}
+    host_visible_device_local: bool,
+}
 
 impl MemoryManager {
     pub fn new(physical_device_memory_properties: vk::PhysicalDeviceMemoryProperties) -> Self {
+        let host_visible_device_local = _has_memory_type(&physical_device_memory_properties, HOST_VISIBLE_DEVICE_LOCAL);
+        if host_visible_device_local {
+            log_info!("Host visible device local memory available, skipping staging buffers for buffer objects");
+        }
+
         MemoryManager {
             physical_device_memory_properties,
-            buffer_to_chunk_map: HashMap::new(),
+            blocks: Vec::new(),
+            buffer_allocations: HashMap::new(),
+            host_visible_device_local,
         }
     }
 
+    // Not necessarily for every usage, see create_host_visible_device_buffer
+    pub fn supports_host_visible_device_local(&self) -> bool {
+        self.host_visible_device_local
+    }
+
     pub fn create_static_vertex_buffer_sync<T: VertexInputDescription>(
         &mut self,
         device: &ash::Device,
@@ -31,18 +71,45 @@ impl MemoryManager {
         submit_queue: vk::Queue,
         vertices: &[T],
     ) -> vk::Buffer {
-        let (buffer, device_memory) = create_device_local_buffer_sync(
+        self.create_device_local_buffer_sync(
             device,
-            &self.physical_device_memory_properties,
             command_pool,
             submit_queue,
             vk::BufferUsageFlags::VERTEX_BUFFER,
             vertices,
-        );
+        )
+    }
 
-        self.buffer_to_chunk_map.insert(buffer, device_memory);
+    pub fn create_static_vertex_buffer_from_bytes_sync(
+        &mut self,
+        device: &ash::Device,
+        command_pool: vk::CommandPool,
+        submit_queue: vk::Queue,
+        vertex_data: &[u8],
+    ) -> vk::Buffer {
+        self.create_device_local_buffer_sync(
+            device,
+            command_pool,
+            submit_queue,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            vertex_data,
+        )
+    }
 
-        buffer
+    pub fn create_static_storage_buffer_sync<T>(
+        &mut self,
+        device: &ash::Device,
+        command_pool: vk::CommandPool,
+        submit_queue: vk::Queue,
+        data: &[T],
+    ) -> vk::Buffer {
+        self.create_device_local_buffer_sync(
+            device,
+            command_pool,
+            submit_queue,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            data,
+        )
     }
 
     pub fn create_index_buffer(
@@ -52,32 +119,31 @@ impl MemoryManager {
         submit_queue: vk::Queue,
         indicies: &[Index],
     ) -> vk::Buffer {
-        let (buffer, device_memory) = create_device_local_buffer_sync(
+        self.create_device_local_buffer_sync(
             device,
-            &self.physical_device_memory_properties,
             command_pool,
             submit_queue,
             vk::BufferUsageFlags::INDEX_BUFFER,
             indicies,
-        );
-
-        self.buffer_to_chunk_map.insert(buffer, device_memory);
-
-        buffer
+        )
     }
 
     pub fn create_staging_buffer(&mut self, logical_device: &ash::Device, buffer_size: vk::DeviceSize) -> vk::Buffer {
-        let (staging_buffer, staging_buffer_memory) = _create_buffer(
+        self.create_buffer(
             logical_device,
             buffer_size,
             vk::BufferUsageFlags::TRANSFER_SRC,
             vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
-            &self.physical_device_memory_properties,
-        );
-
-        self.buffer_to_chunk_map.insert(staging_buffer, staging_buffer_memory);
+        )
+    }
 
-        staging_buffer
+    pub fn create_readback_buffer(&mut self, logical_device: &ash::Device, buffer_size: vk::DeviceSize) -> vk::Buffer {
+        self.create_buffer(
+            logical_device,
+            buffer_size,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        )
     }
 
     pub fn create_device_buffer(
@@ -86,37 +152,65 @@ impl MemoryManager {
         buffer_size: vk::DeviceSize,
         usage: vk::BufferUsageFlags,
     ) -> vk::Buffer {
-        let (staging_buffer, staging_buffer_memory) = _create_buffer(
+        self.create_buffer(
             logical_device,
             buffer_size,
             vk::BufferUsageFlags::TRANSFER_DST | usage,
             vk::MemoryPropertyFlags::DEVICE_LOCAL,
-            &self.physical_device_memory_properties,
-        );
+        )
+    }
 
-        self.buffer_to_chunk_map.insert(staging_buffer, staging_buffer_memory);
+    // None when none of the host visible device local memory types can back a buffer of the usage
+    pub fn create_host_visible_device_buffer(
+        &mut self,
+        logical_device: &ash::Device,
+        buffer_size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+    ) -> Option<vk::Buffer> {
+        debug_assert!(self.host_visible_device_local);
 
-        staging_buffer
+        self.try_create_buffer(logical_device, buffer_size, usage, HOST_VISIBLE_DEVICE_LOCAL)
     }
 
     pub unsafe fn copy_to_buffer_memory<T>(&mut self, logical_device: &ash::Device, buffer: vk::Buffer, data: &[T]) {
-        let memory = self.get_device_memory(buffer);
-        _copy_to_buffer_memory(logical_device, data, memory);
+        self.copy_to_buffer_memory_at(logical_device, buffer, 0, data);
     }
 
-    pub unsafe fn destroy_buffer(&mut self, logical_device: &ash::Device, buffer: vk::Buffer) {
-        let memory_option = self.buffer_to_chunk_map.remove(&buffer);
+    pub unsafe fn copy_to_buffer_memory_at<T>(
+        &mut self,
+        _logical_device: &ash::Device,
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        data: &[T],
+    ) {
+        debug_assert!(!data.is_empty());
+
+        // Bytewise, the offset in the block is aligned for the buffer and not necessarily for T
+        let size = std::mem::size_of_val(data);
+        self.mapped_range(buffer, offset, size)
+            .copy_from_nonoverlapping(data.as_ptr() as *const u8, size);
+    }
+
+    pub unsafe fn copy_from_buffer_memory<T>(&self, _logical_device: &ash::Device, buffer: vk::Buffer, data: &mut [T]) {
+        let size = std::mem::size_of_val(data);
+        self.mapped_range(buffer, 0, size)
+            .copy_to_nonoverlapping(data.as_mut_ptr() as *mut u8, size);
+    }
 
-        if let Some(memory) = memory_option {
+    pub unsafe fn destroy_buffer(&mut self, logical_device: &ash::Device, buffer: vk::Buffer) {
+        if let Some(allocation) = self.buffer_allocations.remove(&buffer) {
             logical_device.destroy_buffer(buffer, None);
-            logical_device.free_memory(memory, None);
+            self.free(logical_device, allocation);
         }
     }
 
     pub unsafe fn destroy(&mut self, logical_device: &ash::Device) {
-        for (buffer, memory) in self.buffer_to_chunk_map.iter() {
+        for buffer in self.buffer_allocations.keys() {
             logical_device.destroy_buffer(*buffer, None);
-            logical_device.free_memory(*memory, None);
+        }
+        self.buffer_allocations.clear();
+        for block in self.blocks.drain(..).flatten() {
+            logical_device.free_memory(block.memory, None);
         }
     }
 
@@ -124,138 +218,257 @@ impl MemoryManager {
         &self.physical_device_memory_properties
     }
 
-    pub fn get_device_memory(&self, buffer: vk::Buffer) -> vk::DeviceMemory {
-        *self.buffer_to_chunk_map.get(&buffer).expect("Unknown buffer memory!")
+    // Blocks, bytes allocated from the device and bytes of them in use by buffers
+    pub fn usage(&self) -> (usize, vk::DeviceSize, vk::DeviceSize) {
+        self.blocks.iter().flatten().fold((0, 0, 0), |(count, allocated, used), block| {
+            (count + 1, allocated + block.free_list.size(), used + block.free_list.used())
+        })
     }
-}
 
-fn create_device_local_buffer_sync<T>(
-    device: &ash::Device,
-    device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
-    command_pool: vk::CommandPool,
-    submit_queue: vk::Queue,
-    usage: vk::BufferUsageFlags,
-    data: &[T],
-) -> (vk::Buffer, vk::DeviceMemory) {
-    let buffer_size = std::mem::size_of_val(data) as vk::DeviceSize;
-
-    let (staging_buffer, staging_buffer_memory) = _create_buffer(
-        device,
-        buffer_size,
-        vk::BufferUsageFlags::TRANSFER_SRC,
-        vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
-        device_memory_properties,
-    );
-
-    unsafe {
-        _copy_to_buffer_memory(device, data, staging_buffer_memory);
+    fn create_buffer(
+        &mut self,
+        device: &ash::Device,
+        size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+        required_memory_properties: vk::MemoryPropertyFlags,
+    ) -> vk::Buffer {
+        self.try_create_buffer(device, size, usage, required_memory_properties)
+            .expect("Failed to find suitable memory type!")
     }
 
-    let (device_local_buffer, device_local_buffer_memory) = _create_buffer(
-        device,
-        buffer_size,
-        vk::BufferUsageFlags::TRANSFER_DST | usage,
-        vk::MemoryPropertyFlags::DEVICE_LOCAL,
-        device_memory_properties,
-    );
-
-    _copy_buffer_device_blocking(
-        device,
-        submit_queue,
-        command_pool,
-        staging_buffer,
-        device_local_buffer,
-        buffer_size,
-    );
+    // None when no memory type with the properties is allowed for the buffer
+    fn try_create_buffer(
+        &mut self,
+        device: &ash::Device,
+        size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+        required_memory_properties: vk::MemoryPropertyFlags,
+    ) -> Option<vk::Buffer> {
+        let buffer_create_info = vk::BufferCreateInfo {
+            s_type: vk::StructureType::BUFFER_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::BufferCreateFlags::empty(),
+            size,
+            usage,
+            sharing_mode: vk::SharingMode::EXCLUSIVE,
+            queue_family_index_count: 0,
+            p_queue_family_indices: ptr::null(),
+        };
+
+        let buffer = unsafe {
+            device
+                .create_buffer(&buffer_create_info, None)
+                .expect("Failed to create Vertex Buffer")
+        };
+
+        let mem_requirements = unsafe { device.get_buffer_memory_requirements(buffer) };
+        let memory_type = match _find_memory_type(
+            mem_requirements.memory_type_bits,
+            required_memory_properties,
+            &self.physical_device_memory_properties,
+        ) {
+            Some(memory_type) => memory_type,
+            None => {
+                unsafe {
+                    device.destroy_buffer(buffer, None);
+                }
+                return None;
+            }
+        };
+        let allocation = self.allocate(device, memory_type, mem_requirements.size, mem_requirements.alignment);
+
+        unsafe {
+            let memory = self.blocks[allocation.block].as_ref().unwrap().memory;
+            device
+                .bind_buffer_memory(buffer, memory, allocation.offset)
+                .expect("Failed to bind Buffer");
+        }
+        self.buffer_allocations.insert(buffer, allocation);
 
-    unsafe {
-        device.destroy_buffer(staging_buffer, None);
-        device.free_memory(staging_buffer_memory, None);
+        Some(buffer)
     }
 
-    (device_local_buffer, device_local_buffer_memory)
-}
+    fn allocate(
+        &mut self,
+        device: &ash::Device,
+        memory_type: u32,
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+    ) -> Allocation {
+        let existing = self.blocks.iter_mut().enumerate().find_map(|(index, block)| {
+            let block = block.as_mut().filter(|block| block.memory_type == memory_type && !block.dedicated)?;
+            block.free_list.allocate(size, alignment).map(|offset| (index, offset))
+        });
+        if let Some((block, offset)) = existing {
+            return Allocation { block, offset, size };
+        }
 
-fn _create_buffer(
-    device: &ash::Device,
-    size: vk::DeviceSize,
-    usage: vk::BufferUsageFlags,
-    required_memory_properties: vk::MemoryPropertyFlags,
-    device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
-) -> (vk::Buffer, vk::DeviceMemory) {
-    let buffer_create_info = vk::BufferCreateInfo {
-        s_type: vk::StructureType::BUFFER_CREATE_INFO,
-        p_next: ptr::null(),
-        flags: vk::BufferCreateFlags::empty(),
-        size,
-        usage,
-        sharing_mode: vk::SharingMode::EXCLUSIVE,
-        queue_family_index_count: 0,
-        p_queue_family_indices: ptr::null(),
-    };
+        let block_size = self.block_size(memory_type);
+        let dedicated = size > block_size / 2;
+        let block = if dedicated {
+            self.allocate_block(device, memory_type, size, true)
+        } else {
+            // Small heaps may not have room for a whole block
+            self.try_allocate_block(device, memory_type, block_size, false)
+                .unwrap_or_else(|| self.allocate_block(device, memory_type, size, true))
+        };
+        let offset = self.blocks[block].as_mut().unwrap().free_list.allocate(size, alignment).unwrap();
+
+        Allocation { block, offset, size }
+    }
 
-    let buffer = unsafe {
-        device
-            .create_buffer(&buffer_create_info, None)
-            .expect("Failed to create Vertex Buffer")
-    };
+    // An eighth of heaps smaller than 512 MiB, e.g. the host visible part of device local memory
+    fn block_size(&self, memory_type: u32) -> vk::DeviceSize {
+        let heap_index = self.physical_device_memory_properties.memory_types[memory_type as usize].heap_index;
+        let heap_size = self.physical_device_memory_properties.memory_heaps[heap_index as usize].size;
 
-    let mem_requirements = unsafe { device.get_buffer_memory_requirements(buffer) };
-    let memory_type = find_memory_type(
-        mem_requirements.memory_type_bits,
-        required_memory_properties,
-        device_memory_properties,
-    );
+        BLOCK_SIZE.min(heap_size / 8)
+    }
 
-    let allocate_info = vk::MemoryAllocateInfo {
-        s_type: vk::StructureType::MEMORY_ALLOCATE_INFO,
-        p_next: ptr::null(),
-        allocation_size: mem_requirements.size,
-        memory_type_index: memory_type,
-    };
+    fn allocate_block(
+        &mut self,
+        device: &ash::Device,
+        memory_type: u32,
+        size: vk::DeviceSize,
+        dedicated: bool,
+    ) -> usize {
+        self.try_allocate_block(device, memory_type, size, dedicated)
+            .expect("Failed to allocate buffer memory!")
+    }
 
-    let buffer_memory = unsafe {
-        device
-            .allocate_memory(&allocate_info, None)
-            .expect("Failed to allocate vertex buffer memory!")
-    };
+    fn try_allocate_block(
+        &mut self,
+        device: &ash::Device,
+        memory_type: u32,
+        size: vk::DeviceSize,
+        dedicated: bool,
+    ) -> Option<usize> {
+        let allocate_info = vk::MemoryAllocateInfo {
+            s_type: vk::StructureType::MEMORY_ALLOCATE_INFO,
+            p_next: ptr::null(),
+            allocation_size: size,
+            memory_type_index: memory_type,
+        };
+        let memory = unsafe { device.allocate_memory(&allocate_info, None) }.ok()?;
+
+        let property_flags = self.physical_device_memory_properties.memory_types[memory_type as usize].property_flags;
+        let mapped = if property_flags.contains(vk::MemoryPropertyFlags::HOST_VISIBLE) {
+            unsafe {
+                device
+                    .map_memory(memory, 0, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty())
+                    .expect("Failed to Map Memory") as *mut u8
+            }
+        } else {
+            ptr::null_mut()
+        };
+        log_debug!(
+            "memory: allocated {} KiB block of type {}{}",
+            size / 1024,
+            memory_type,
+            if dedicated { ", dedicated" } else { "" }
+        );
 
-    unsafe {
-        device
-            .bind_buffer_memory(buffer, buffer_memory, 0)
-            .expect("Failed to bind Buffer");
+        let block = MemoryBlock {
+            memory,
+            memory_type,
+            free_list: FreeList::new(size),
+            mapped,
+            dedicated,
+        };
+        match self.blocks.iter().position(|block| block.is_none()) {
+            Some(index) => {
+                self.blocks[index] = Some(block);
+                Some(index)
+            }
+            None => {
+                self.blocks.push(Some(block));
+                Some(self.blocks.len() - 1)
+            }
+        }
     }
 
-    (buffer, buffer_memory)
-}
+    // Empty blocks are freed, except for the last shared block of a memory type, which is kept for reuse
+    unsafe fn free(&mut self, device: &ash::Device, allocation: Allocation) {
+        let block = self.blocks[allocation.block].as_mut().unwrap();
+        block.free_list.free(allocation.offset, allocation.size);
+        if !block.free_list.is_empty() {
+            return;
+        }
 
-fn find_memory_type(
-    type_filter: u32,
-    required_properties: vk::MemoryPropertyFlags,
-    mem_properties: &vk::PhysicalDeviceMemoryProperties,
-) -> u32 {
-    for (i, memory_type) in mem_properties.memory_types.iter().enumerate() {
-        if (type_filter & (1 << i)) > 0 && memory_type.property_flags.contains(required_properties) {
-            return i as u32;
+        let (memory_type, dedicated) = (block.memory_type, block.dedicated);
+        let shared_blocks = self
+            .blocks
+            .iter()
+            .flatten()
+            .filter(|block| block.memory_type == memory_type && !block.dedicated)
+            .count();
+        if dedicated || shared_blocks > 1 {
+            let block = self.blocks[allocation.block].take().unwrap();
+            device.free_memory(block.memory, None);
         }
     }
 
-    panic!("Failed to find suitable memory type!")
-}
+    unsafe fn mapped_range(&self, buffer: vk::Buffer, offset: vk::DeviceSize, size: usize) -> *mut u8 {
+        let allocation = self.buffer_allocations.get(&buffer).expect("Unknown buffer memory!");
+        let block = self.blocks[allocation.block].as_ref().unwrap();
+        assert!(!block.mapped.is_null(), "Buffer memory is not host visible!");
+        assert!(offset + size as vk::DeviceSize <= allocation.size, "Copy larger than the buffer!");
+
+        block.mapped.add((allocation.offset + offset) as usize)
+    }
+
+    fn create_device_local_buffer_sync<T>(
+        &mut self,
+        device: &ash::Device,
+        command_pool: vk::CommandPool,
+        submit_queue: vk::Queue,
+        usage: vk::BufferUsageFlags,
+        data: &[T],
+    ) -> vk::Buffer {
+        let buffer_size = std::mem::size_of_val(data) as vk::DeviceSize;
+
+        let staging_buffer = self.create_staging_buffer(device, buffer_size);
+        unsafe {
+            self.copy_to_buffer_memory(device, staging_buffer, data);
+        }
+
+        let device_local_buffer = self.create_device_buffer(device, buffer_size, usage);
 
-unsafe fn _copy_to_buffer_memory<T>(device: &ash::Device, data: &[T], dst_memory: vk::DeviceMemory) {
-    debug_assert!(!data.is_empty());
+        _copy_buffer_device_blocking(
+            device,
+            submit_queue,
+            command_pool,
+            staging_buffer,
+            device_local_buffer,
+            buffer_size,
+        );
 
-    let buffer_size = std::mem::size_of_val(data) as vk::DeviceSize;
+        unsafe {
+            self.destroy_buffer(device, staging_buffer);
+        }
 
-    // TODO the size should be checked here. Need to track the capcity of the memory.
-    let data_ptr = device
-        .map_memory(dst_memory, 0, buffer_size, vk::MemoryMapFlags::empty())
-        .expect("Failed to Map Memory") as *mut T;
+        device_local_buffer
+    }
+}
 
-    data_ptr.copy_from_nonoverlapping(data.as_ptr(), data.len());
+fn _has_memory_type(
+    mem_properties: &vk::PhysicalDeviceMemoryProperties,
+    required_properties: vk::MemoryPropertyFlags,
+) -> bool {
+    mem_properties.memory_types[..mem_properties.memory_type_count as usize]
+        .iter()
+        .any(|memory_type| memory_type.property_flags.contains(required_properties))
+}
 
-    device.unmap_memory(dst_memory);
+fn _find_memory_type(
+    type_filter: u32,
+    required_properties: vk::MemoryPropertyFlags,
+    mem_properties: &vk::PhysicalDeviceMemoryProperties,
+) -> Option<u32> {
+    (0..mem_properties.memory_type_count).find(|i| {
+        let memory_type = mem_properties.memory_types[*i as usize];
+        (type_filter & (1 << i)) > 0 && memory_type.property_flags.contains(required_properties)
+    })
 }
 
 fn _copy_buffer_device_blocking(
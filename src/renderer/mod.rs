@@ -1,19 +1,36 @@
 pub mod context;
+#[cfg(feature = "raw-vulkan")]
+pub mod raw;
 pub mod rawarray;
+pub mod resources;
+pub mod shader;
 pub mod stats;
 pub mod types;
+pub mod variant;
+pub mod vertex;
 
+mod allocator;
 mod buffer;
+mod capture;
+mod compute;
 mod constants;
 mod debug;
+mod descriptor;
+mod dispatch;
+mod graph;
 mod image;
+mod layouts;
 mod memory;
+mod occlusion;
 mod pass;
 mod pipeline;
 mod queue;
+mod reflection;
+mod skinning;
 mod surface;
 mod swapchain;
 mod synchronization;
 mod target;
 mod texture;
+mod timestamp;
 mod vulkan_util;
@@ -0,0 +1,780 @@
+use ash::vk;
+use cgmath::Matrix4;
+
+use crate::engine::datatypes::InstanceBounds;
+use crate::renderer::compute::ComputePipeline;
+use crate::renderer::descriptor::{DescriptorAllocation, DescriptorAllocator};
+use crate::renderer::image;
+use crate::renderer::memory::MemoryManager;
+use crate::renderer::pass::RenderPassManager;
+use crate::renderer::types::{CulledInstances, CulledInstancesHandle, RenderPassHandle};
+
+const HIZ_DOWNSAMPLE_SHADER: &str = "./resources/shaders/hiz_downsample_comp.spv";
+const OCCLUSION_CULL_SHADER: &str = "./resources/shaders/occlusion_cull_comp.spv";
+const HIZ_FORMAT: vk::Format = vk::Format::R32_SFLOAT;
+const HIZ_WORKGROUP_SIZE: u32 = 8;
+const CULL_WORKGROUP_SIZE: u32 = 64;
+// std430 size of the parameters in occlusion_cull.comp, padded to the alignment of its matrices
+const CULL_PARAMETERS_SIZE: usize = 160;
+
+struct CullPipelines {
+    downsample: ComputePipeline,
+    cull: ComputePipeline,
+    // Nearest texel of the HiZ levels and the depth buffer
+    sampler: vk::Sampler,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct HizSource {
+    depth_image: vk::Image,
+    depth_image_view: vk::ImageView,
+    extent: vk::Extent2D,
+}
+
+// Farthest depth of the source pass per texel of each level, kept in the general layout. Without a source it is a
+// single texel placeholder, so that the descriptor sets of the culling stay valid.
+struct HizPyramid {
+    image: vk::Image,
+    memory: vk::DeviceMemory,
+    view: vk::ImageView,
+    level_views: Vec<vk::ImageView>,
+    level_extents: Vec<vk::Extent2D>,
+    source: Option<HizSource>,
+    // One per level, the previous level (the depth buffer for level 0) and the level itself
+    level_descriptor_sets: Option<DescriptorAllocation>,
+}
+
+struct CulledGroup {
+    bounds_buffer: vk::Buffer,
+    parameters_buffer: vk::Buffer,
+    indirect_buffer: vk::Buffer,
+    visible_buffer: vk::Buffer,
+    index_count: u32,
+    instance_count: u32,
+    descriptor_sets: DescriptorAllocation,
+    // Frame and camera of the last cull, the HiZ pyramid read by a cull was rendered the frame before
+    last_cull: Option<(u64, Matrix4<f32>)>,
+}
+
+struct QueuedCull {
+    handle: CulledInstancesHandle,
+    view_projection: Matrix4<f32>,
+    hiz_view_projection: Option<Matrix4<f32>>,
+}
+
+// Compute pre-pass testing instance bounds against the frustum and a HiZ pyramid of the depth buffer of the
+// previous frame, writing the visible instances into indexed indirect draws. The pyramid is built right after its
+// source pass, so passes drawing culled instances should use the same camera.
+pub(super) struct OcclusionCuller {
+    pipelines: Option<CullPipelines>,
+    descriptor_allocator: DescriptorAllocator,
+    hiz: Option<HizPyramid>,
+    hiz_source: Option<RenderPassHandle>,
+    depth_aspect: vk::ImageAspectFlags,
+    // Whether the frame being recorded builds the pyramid and whether the previous one did
+    hiz_built: bool,
+    hiz_valid: bool,
+    frame: u64,
+    groups: Vec<CulledGroup>,
+    queued: Vec<QueuedCull>,
+}
+
+impl OcclusionCuller {
+    pub(super) fn new() -> Self {
+        OcclusionCuller {
+            pipelines: None,
+            descriptor_allocator: DescriptorAllocator::new(),
+            hiz: None,
+            hiz_source: None,
+            depth_aspect: vk::ImageAspectFlags::DEPTH,
+            hiz_built: false,
+            hiz_valid: false,
+            frame: 0,
+            groups: Vec::new(),
+            queued: Vec::new(),
+        }
+    }
+
+    // The pyramid is created before the next frame, see prepare
+    pub(super) fn set_hiz_source(&mut self, pass: RenderPassHandle, depth_format: vk::Format) {
+        self.hiz_source = Some(pass);
+        self.depth_aspect = _depth_aspect(depth_format);
+    }
+
+    pub(super) fn add_group(
+        &mut self,
+        device: &ash::Device,
+        command_pool: vk::CommandPool,
+        submit_queue: vk::Queue,
+        memory_manager: &mut MemoryManager,
+        index_count: u32,
+        bounds: &[InstanceBounds],
+    ) -> CulledInstancesHandle {
+        assert!(!bounds.is_empty(), "Culled instances need at least one instance");
+        self.initialize(device, command_pool, submit_queue, memory_manager);
+
+        let bounds_buffer =
+            memory_manager.create_static_storage_buffer_sync(device, command_pool, submit_queue, bounds);
+        let parameters_buffer = memory_manager.create_device_buffer(
+            device,
+            CULL_PARAMETERS_SIZE as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+        );
+        let indirect_buffer = memory_manager.create_device_buffer(
+            device,
+            std::mem::size_of::<vk::DrawIndexedIndirectCommand>() as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::INDIRECT_BUFFER,
+        );
+        let visible_buffer = memory_manager.create_device_buffer(
+            device,
+            (bounds.len() * std::mem::size_of::<u32>()) as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::VERTEX_BUFFER,
+        );
+
+        let pipelines = self.pipelines.as_ref().unwrap();
+        let descriptor_sets = self.descriptor_allocator.allocate(
+            device,
+            pipelines.cull.descriptor_set_layout,
+            pipelines.cull.descriptor_counts,
+            1,
+        );
+        let group = CulledGroup {
+            bounds_buffer,
+            parameters_buffer,
+            indirect_buffer,
+            visible_buffer,
+            index_count,
+            instance_count: bounds.len() as u32,
+            descriptor_sets,
+            last_cull: None,
+        };
+        unsafe {
+            _write_cull_descriptor_set(device, &group, pipelines.sampler, self.hiz.as_ref().unwrap().view);
+        }
+        self.groups.push(group);
+
+        self.groups.len() - 1
+    }
+
+    pub(super) fn culled_instances(&self, handle: CulledInstancesHandle) -> CulledInstances {
+        let group = &self.groups[handle];
+
+        CulledInstances {
+            indirect_buffer: group.indirect_buffer,
+            visible_buffer: group.visible_buffer,
+            instance_count: group.instance_count,
+        }
+    }
+
+    // Culls the group in the next frame, the last camera wins if it is queued several times
+    pub(super) fn queue(&mut self, handle: CulledInstancesHandle, view_projection: Matrix4<f32>) {
+        assert!(handle < self.groups.len(), "Unknown culled instances");
+        let frame = self.frame;
+        let group = &mut self.groups[handle];
+        if let Some(queued) = self.queued.iter_mut().find(|queued| queued.handle == handle) {
+            queued.view_projection = view_projection;
+            group.last_cull = Some((frame, view_projection));
+            return;
+        }
+
+        let hiz_view_projection = group
+            .last_cull
+            .filter(|(last_frame, _)| last_frame + 1 == frame)
+            .map(|(_, view_projection)| view_projection);
+        group.last_cull = Some((frame, view_projection));
+        self.queued.push(QueuedCull {
+            handle,
+            view_projection,
+            hiz_view_projection,
+        });
+    }
+
+    pub(super) fn reset_queue(&mut self) {
+        self.queued.clear();
+        self.frame += 1;
+    }
+
+    // Recreates the pyramid when the depth buffer of the source pass changed, waiting for the device. Called
+    // before the frame is recorded.
+    pub(super) fn prepare(
+        &mut self,
+        device: &ash::Device,
+        command_pool: vk::CommandPool,
+        submit_queue: vk::Queue,
+        memory_manager: &mut MemoryManager,
+        render_pass_manager: &RenderPassManager,
+    ) {
+        if self.pipelines.is_none() {
+            return;
+        }
+
+        let source = self.hiz_source.and_then(|pass| {
+            let (depth_image, depth_image_view, extent) = render_pass_manager.depth_target(pass).ok()?;
+            Some(HizSource {
+                depth_image,
+                depth_image_view,
+                extent,
+            })
+        });
+        if self.hiz.as_ref().is_none_or(|hiz| hiz.source != source) {
+            unsafe {
+                device.device_wait_idle().expect("Failed to wait device idle!");
+            }
+            self.recreate_hiz(device, command_pool, submit_queue, memory_manager, source);
+            self.hiz_built = false;
+        }
+
+        let source_enabled = self
+            .hiz_source
+            .is_some_and(|pass| render_pass_manager.is_pass_enabled(pass).unwrap_or(false));
+        self.hiz_valid = self.hiz_built;
+        self.hiz_built = source.is_some() && source_enabled;
+    }
+
+    // Records the culls of the frame before its render passes. The previous frame wrote the HiZ pyramid and read
+    // the buffers of the draws, both have to finish first.
+    pub(super) unsafe fn bake_command_buffer(&self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        let (pipelines, hiz) = match (self.pipelines.as_ref(), self.hiz.as_ref()) {
+            (Some(pipelines), Some(hiz)) if !self.queued.is_empty() => (pipelines, hiz),
+            _ => return,
+        };
+
+        _memory_barrier(
+            device,
+            command_buffer,
+            vk::PipelineStageFlags::COMPUTE_SHADER
+                | vk::PipelineStageFlags::DRAW_INDIRECT
+                | vk::PipelineStageFlags::VERTEX_INPUT,
+            vk::AccessFlags::SHADER_WRITE,
+            vk::PipelineStageFlags::TRANSFER | vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::TRANSFER_WRITE | vk::AccessFlags::SHADER_READ,
+        );
+
+        let hiz_size = hiz.level_extents[0];
+        for queued in self.queued.iter() {
+            let group = &self.groups[queued.handle];
+            let parameters = _cull_parameters(
+                queued.view_projection,
+                queued.hiz_view_projection,
+                [hiz_size.width as f32, hiz_size.height as f32],
+                group.instance_count,
+                hiz.level_extents.len() as u32,
+                self.hiz_valid,
+            );
+            device.cmd_update_buffer(command_buffer, group.parameters_buffer, 0, &parameters);
+            device.cmd_update_buffer(command_buffer, group.indirect_buffer, 0, &_indirect_command(group.index_count));
+        }
+
+        _memory_barrier(
+            device,
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+        );
+
+        for queued in self.queued.iter() {
+            let group = &self.groups[queued.handle];
+            pipelines.cull.bind(device, command_buffer, group.descriptor_sets.sets()[0]);
+            device.cmd_dispatch(command_buffer, group.instance_count.div_ceil(CULL_WORKGROUP_SIZE), 1, 1);
+        }
+
+        _memory_barrier(
+            device,
+            command_buffer,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::SHADER_WRITE,
+            vk::PipelineStageFlags::DRAW_INDIRECT | vk::PipelineStageFlags::VERTEX_INPUT,
+            vk::AccessFlags::INDIRECT_COMMAND_READ | vk::AccessFlags::VERTEX_ATTRIBUTE_READ,
+        );
+    }
+
+    // Records the build of the pyramid once its source pass has been recorded. The depth buffer is sampled in
+    // between and returned to the attachment layout for later passes sharing it.
+    pub(super) unsafe fn bake_hiz(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        pass: RenderPassHandle,
+    ) {
+        if !self.hiz_built || self.hiz_source != Some(pass) {
+            return;
+        }
+        let (pipelines, hiz) = match (self.pipelines.as_ref(), self.hiz.as_ref()) {
+            (Some(pipelines), Some(hiz)) => (pipelines, hiz),
+            _ => return,
+        };
+        let (source, level_descriptor_sets) = match (hiz.source, hiz.level_descriptor_sets.as_ref()) {
+            (Some(source), Some(level_descriptor_sets)) => (source, level_descriptor_sets),
+            _ => return,
+        };
+
+        // The culls of this frame read the pyramid of the previous one
+        let depth_barrier = self.depth_barrier(
+            source.depth_image,
+            vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            vk::AccessFlags::SHADER_READ,
+            vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+        );
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::LATE_FRAGMENT_TESTS | vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[depth_barrier],
+        );
+
+        let mut source_extent = source.extent;
+        for (level, extent) in hiz.level_extents.iter().enumerate() {
+            pipelines.downsample.bind(device, command_buffer, level_descriptor_sets.sets()[level]);
+            let push_constants: Vec<u8> = [source_extent.width, source_extent.height, extent.width, extent.height]
+                .iter()
+                .flat_map(|size| (*size as i32).to_ne_bytes())
+                .collect();
+            device.cmd_push_constants(
+                command_buffer,
+                pipelines.downsample.pipeline_layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                &push_constants,
+            );
+            device.cmd_dispatch(
+                command_buffer,
+                extent.width.div_ceil(HIZ_WORKGROUP_SIZE),
+                extent.height.div_ceil(HIZ_WORKGROUP_SIZE),
+                1,
+            );
+
+            let level_barrier = vk::ImageMemoryBarrier::builder()
+                .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+                .dst_access_mask(vk::AccessFlags::SHADER_READ)
+                .old_layout(vk::ImageLayout::GENERAL)
+                .new_layout(vk::ImageLayout::GENERAL)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(hiz.image)
+                .subresource_range(vk::ImageSubresourceRange {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    base_mip_level: level as u32,
+                    level_count: 1,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                })
+                .build();
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[level_barrier],
+            );
+            source_extent = *extent;
+        }
+
+        let depth_barrier = self.depth_barrier(
+            source.depth_image,
+            vk::AccessFlags::SHADER_READ,
+            vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+            vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        );
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[depth_barrier],
+        );
+    }
+
+    pub(super) unsafe fn destroy(&mut self, device: &ash::Device) {
+        self.groups.clear();
+        self.queued.clear();
+        if let Some(hiz) = self.hiz.take() {
+            _destroy_hiz(device, hiz);
+        }
+        self.descriptor_allocator.destroy(device);
+        if let Some(pipelines) = self.pipelines.take() {
+            pipelines.downsample.destroy(device);
+            pipelines.cull.destroy(device);
+            device.destroy_sampler(pipelines.sampler, None);
+        }
+    }
+
+    fn initialize(
+        &mut self,
+        device: &ash::Device,
+        command_pool: vk::CommandPool,
+        submit_queue: vk::Queue,
+        memory_manager: &mut MemoryManager,
+    ) {
+        if self.pipelines.is_some() {
+            return;
+        }
+
+        // Source level and destination level, the sizes of both as push constant
+        let downsample = ComputePipeline::new(
+            device,
+            HIZ_DOWNSAMPLE_SHADER,
+            &[vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::DescriptorType::STORAGE_IMAGE],
+            4 * std::mem::size_of::<i32>() as u32,
+        );
+        // HiZ pyramid, bounds, parameters, indirect draw and visible instances
+        let cull = ComputePipeline::new(
+            device,
+            OCCLUSION_CULL_SHADER,
+            &[
+                vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                vk::DescriptorType::STORAGE_BUFFER,
+                vk::DescriptorType::STORAGE_BUFFER,
+                vk::DescriptorType::STORAGE_BUFFER,
+                vk::DescriptorType::STORAGE_BUFFER,
+            ],
+            0,
+        );
+
+        let sampler_create_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(vk::Filter::NEAREST)
+            .min_filter(vk::Filter::NEAREST)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .max_lod(vk::LOD_CLAMP_NONE);
+        let sampler = unsafe {
+            device
+                .create_sampler(&sampler_create_info, None)
+                .expect("Failed to create Sampler!")
+        };
+
+        self.pipelines = Some(CullPipelines {
+            downsample,
+            cull,
+            sampler,
+        });
+        self.recreate_hiz(device, command_pool, submit_queue, memory_manager, None);
+    }
+
+    // The device must be idle, the descriptor sets of the groups are rewritten
+    fn recreate_hiz(
+        &mut self,
+        device: &ash::Device,
+        command_pool: vk::CommandPool,
+        submit_queue: vk::Queue,
+        memory_manager: &mut MemoryManager,
+        source: Option<HizSource>,
+    ) {
+        if let Some(hiz) = self.hiz.take() {
+            unsafe {
+                if let Some(level_descriptor_sets) = hiz.level_descriptor_sets {
+                    self.descriptor_allocator.free(device, level_descriptor_sets);
+                }
+                _destroy_hiz(
+                    device,
+                    HizPyramid {
+                        level_descriptor_sets: None,
+                        ..hiz
+                    },
+                );
+            }
+        }
+
+        let extent = source.map_or(vk::Extent2D { width: 1, height: 1 }, |source| source.extent);
+        let level_extents = _level_extents(extent);
+        let level_count = level_extents.len() as u32;
+        let (hiz_image, hiz_memory) = image::create_storage_image(
+            device,
+            command_pool,
+            submit_queue,
+            memory_manager,
+            extent,
+            level_count,
+            HIZ_FORMAT,
+        );
+        let view = image::create_image_view(device, hiz_image, HIZ_FORMAT, vk::ImageAspectFlags::COLOR, level_count);
+        let level_views: Vec<vk::ImageView> = (0..level_count)
+            .map(|level| {
+                image::create_mip_level_image_view(device, hiz_image, HIZ_FORMAT, vk::ImageAspectFlags::COLOR, level)
+            })
+            .collect();
+
+        let pipelines = self.pipelines.as_ref().expect("BUG! HiZ pyramid created before its pipelines");
+        let level_descriptor_sets = if let Some(source) = source {
+            let allocation = self.descriptor_allocator.allocate(
+                device,
+                pipelines.downsample.descriptor_set_layout,
+                pipelines.downsample.descriptor_counts,
+                level_count as usize,
+            );
+            for (level, descriptor_set) in allocation.sets().iter().enumerate() {
+                let (source_view, source_layout) = if level == 0 {
+                    (source.depth_image_view, vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL)
+                } else {
+                    (level_views[level - 1], vk::ImageLayout::GENERAL)
+                };
+                unsafe {
+                    _write_downsample_descriptor_set(
+                        device,
+                        *descriptor_set,
+                        pipelines.sampler,
+                        (source_view, source_layout),
+                        level_views[level],
+                    );
+                }
+            }
+
+            Some(allocation)
+        } else {
+            None
+        };
+
+        for group in self.groups.iter() {
+            unsafe {
+                _write_cull_descriptor_set(device, group, pipelines.sampler, view);
+            }
+        }
+
+        self.hiz = Some(HizPyramid {
+            image: hiz_image,
+            memory: hiz_memory,
+            view,
+            level_views,
+            level_extents,
+            source,
+            level_descriptor_sets,
+        });
+    }
+
+    fn depth_barrier(
+        &self,
+        depth_image: vk::Image,
+        src_access_mask: vk::AccessFlags,
+        dst_access_mask: vk::AccessFlags,
+        old_layout: vk::ImageLayout,
+        new_layout: vk::ImageLayout,
+    ) -> vk::ImageMemoryBarrier {
+        vk::ImageMemoryBarrier::builder()
+            .src_access_mask(src_access_mask)
+            .dst_access_mask(dst_access_mask)
+            .old_layout(old_layout)
+            .new_layout(new_layout)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(depth_image)
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask: self.depth_aspect,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1,
+            })
+            .build()
+    }
+}
+
+unsafe fn _destroy_hiz(device: &ash::Device, hiz: HizPyramid) {
+    for level_view in hiz.level_views.iter() {
+        device.destroy_image_view(*level_view, None);
+    }
+    device.destroy_image_view(hiz.view, None);
+    device.destroy_image(hiz.image, None);
+    device.free_memory(hiz.memory, None);
+}
+
+unsafe fn _memory_barrier(
+    device: &ash::Device,
+    command_buffer: vk::CommandBuffer,
+    src_stage_mask: vk::PipelineStageFlags,
+    src_access_mask: vk::AccessFlags,
+    dst_stage_mask: vk::PipelineStageFlags,
+    dst_access_mask: vk::AccessFlags,
+) {
+    let memory_barriers = [vk::MemoryBarrier::builder()
+        .src_access_mask(src_access_mask)
+        .dst_access_mask(dst_access_mask)
+        .build()];
+    device.cmd_pipeline_barrier(
+        command_buffer,
+        src_stage_mask,
+        dst_stage_mask,
+        vk::DependencyFlags::empty(),
+        &memory_barriers,
+        &[],
+        &[],
+    );
+}
+
+unsafe fn _write_downsample_descriptor_set(
+    device: &ash::Device,
+    descriptor_set: vk::DescriptorSet,
+    sampler: vk::Sampler,
+    (source_view, source_layout): (vk::ImageView, vk::ImageLayout),
+    destination_view: vk::ImageView,
+) {
+    let source_infos = [vk::DescriptorImageInfo {
+        sampler,
+        image_view: source_view,
+        image_layout: source_layout,
+    }];
+    let destination_infos = [vk::DescriptorImageInfo {
+        sampler: vk::Sampler::null(),
+        image_view: destination_view,
+        image_layout: vk::ImageLayout::GENERAL,
+    }];
+    let writes = [
+        vk::WriteDescriptorSet::builder()
+            .dst_set(descriptor_set)
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(&source_infos)
+            .build(),
+        vk::WriteDescriptorSet::builder()
+            .dst_set(descriptor_set)
+            .dst_binding(1)
+            .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+            .image_info(&destination_infos)
+            .build(),
+    ];
+
+    device.update_descriptor_sets(&writes, &[]);
+}
+
+unsafe fn _write_cull_descriptor_set(
+    device: &ash::Device,
+    group: &CulledGroup,
+    sampler: vk::Sampler,
+    hiz_view: vk::ImageView,
+) {
+    let descriptor_set = group.descriptor_sets.sets()[0];
+    let image_infos = [vk::DescriptorImageInfo {
+        sampler,
+        image_view: hiz_view,
+        image_layout: vk::ImageLayout::GENERAL,
+    }];
+    let buffers = [
+        group.bounds_buffer,
+        group.parameters_buffer,
+        group.indirect_buffer,
+        group.visible_buffer,
+    ];
+    let buffer_infos: Vec<[vk::DescriptorBufferInfo; 1]> = buffers
+        .iter()
+        .map(|buffer| {
+            [vk::DescriptorBufferInfo {
+                buffer: *buffer,
+                offset: 0,
+                range: vk::WHOLE_SIZE,
+            }]
+        })
+        .collect();
+
+    let mut writes = vec![vk::WriteDescriptorSet::builder()
+        .dst_set(descriptor_set)
+        .dst_binding(0)
+        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+        .image_info(&image_infos)
+        .build()];
+    writes.extend(buffer_infos.iter().enumerate().map(|(index, buffer_info)| {
+        vk::WriteDescriptorSet::builder()
+            .dst_set(descriptor_set)
+            .dst_binding(index as u32 + 1)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .buffer_info(buffer_info)
+            .build()
+    }));
+
+    device.update_descriptor_sets(&writes, &[]);
+}
+
+fn _depth_aspect(depth_format: vk::Format) -> vk::ImageAspectFlags {
+    match depth_format {
+        vk::Format::D32_SFLOAT_S8_UINT | vk::Format::D24_UNORM_S8_UINT | vk::Format::D16_UNORM_S8_UINT => {
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        }
+        _ => vk::ImageAspectFlags::DEPTH,
+    }
+}
+
+// Level 0 has the size of the depth buffer, each further level halves it down to a single texel
+fn _level_extents(extent: vk::Extent2D) -> Vec<vk::Extent2D> {
+    let mut level_extents = vec![extent];
+    while let Some(last) = level_extents.last().filter(|last| last.width > 1 || last.height > 1) {
+        let next = vk::Extent2D {
+            width: (last.width / 2).max(1),
+            height: (last.height / 2).max(1),
+        };
+        level_extents.push(next);
+    }
+
+    level_extents
+}
+
+// Without the camera of the previous frame only the frustum is tested
+fn _cull_parameters(
+    view_projection: Matrix4<f32>,
+    hiz_view_projection: Option<Matrix4<f32>>,
+    hiz_size: [f32; 2],
+    instance_count: u32,
+    hiz_mip_count: u32,
+    hiz_valid: bool,
+) -> Vec<u8> {
+    let hiz_valid = hiz_valid && hiz_view_projection.is_some();
+    let hiz_view_projection = hiz_view_projection.unwrap_or(view_projection);
+
+    let mut parameters = Vec::with_capacity(CULL_PARAMETERS_SIZE);
+    for matrix in [view_projection, hiz_view_projection].iter() {
+        let elements: &[f32; 16] = matrix.as_ref();
+        parameters.extend(elements.iter().flat_map(|element| element.to_ne_bytes()));
+    }
+    parameters.extend(hiz_size.iter().flat_map(|size| size.to_ne_bytes()));
+    parameters.extend(instance_count.to_ne_bytes());
+    parameters.extend(hiz_mip_count.to_ne_bytes());
+    parameters.extend((hiz_valid as u32).to_ne_bytes());
+    parameters.resize(CULL_PARAMETERS_SIZE, 0);
+
+    parameters
+}
+
+// The culling counts the visible instances up from zero
+fn _indirect_command(index_count: u32) -> Vec<u8> {
+    [index_count, 0, 0, 0, 0].iter().flat_map(|value| value.to_ne_bytes()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::SquareMatrix;
+
+    #[test]
+    fn hiz_levels_and_parameters() {
+        let sizes = |width, height| -> Vec<(u32, u32)> {
+            _level_extents(vk::Extent2D { width, height })
+                .iter()
+                .map(|extent| (extent.width, extent.height))
+                .collect()
+        };
+        assert_eq!(sizes(1, 1), vec![(1, 1)]);
+        assert_eq!(sizes(8, 8), vec![(8, 8), (4, 4), (2, 2), (1, 1)]);
+        assert_eq!(sizes(13, 3), vec![(13, 3), (6, 1), (3, 1), (1, 1)]);
+
+        let parameters = _cull_parameters(Matrix4::identity(), None, [640.0, 480.0], 7, 10, true);
+        assert_eq!(parameters.len(), CULL_PARAMETERS_SIZE);
+        assert_eq!(&parameters[0..4], &1.0_f32.to_ne_bytes());
+        assert_eq!(&parameters[128..132], &640.0_f32.to_ne_bytes());
+        assert_eq!(&parameters[136..140], &7_u32.to_ne_bytes());
+        // Not valid without the camera of the previous frame
+        assert_eq!(&parameters[144..148], &0_u32.to_ne_bytes());
+        let parameters = _cull_parameters(Matrix4::identity(), Some(Matrix4::identity()), [1.0, 1.0], 7, 1, true);
+        assert_eq!(&parameters[144..148], &1_u32.to_ne_bytes());
+
+        assert_eq!(_indirect_command(36).len(), std::mem::size_of::<vk::DrawIndexedIndirectCommand>());
+    }
+}
@@ -1,98 +1,198 @@
 use crate::renderer::buffer::BufferObjectManager;
-use crate::renderer::pipeline::PipelineContainer;
+use crate::renderer::capture::{CapturedDraw, CapturedMesh, CapturedPass};
+use crate::renderer::descriptor::{DescriptorAllocator, DescriptorStats};
+use crate::renderer::graph::{GraphPass, GraphPipeline, GraphTarget};
+use crate::renderer::layouts::{LayoutAttachment, LayoutImage, LayoutPass};
+use crate::renderer::pipeline::{self, PipelineBindings, PipelineContainer};
 use crate::renderer::stats::RenderStats;
 use crate::renderer::swapchain::SwapChainContainer;
 use crate::renderer::texture::TextureManager;
-use crate::renderer::types::{BufferObjectBindingConfiguration, DrawCommand, PipelineConfiguration, PipelineHandle, RenderPassHandle, SamplerBindingConfiguration, UniformStage, VertexInputDescription, VertexTopology, SWAPCHAIN_PASS};
+use crate::renderer::constants::{OIT_ACCUMULATION_FORMAT, OIT_REVEALAGE_FORMAT};
+use crate::renderer::types::{BlendMode, BufferObjectBindingConfiguration, DrawCommand, DrawData, PipelineConfiguration, PipelineHandle, RenderPassHandle, SamplerBindingConfiguration, SamplerBindingType, SampleCount, StorageWindow, UniformStage, VertexTopology, DEFAULT_FRAMES_IN_FLIGHT, SWAPCHAIN_PASS, TextureHandle, Viewport};
 use ash::vk::{Extent2D, ImageView, PhysicalDeviceMemoryProperties};
+use ash::extensions::khr::PushDescriptor;
+use ash::vk::Handle;
 use ash::{vk, Device};
 use std::collections::HashMap;
 use std::ptr;
 
 use crate::renderer::image;
-use crate::renderer::target::{RenderTarget, SwapchainTarget, ImageTarget};
+use crate::renderer::target::{RenderTarget, SwapchainTarget, ImageTarget, MultisampleImage, TransparencyTarget};
 
+const DEFAULT_CLEAR_COLOR: [f32; 4] = [0.05, 0.05, 0.1, 1.0];
+// Render textures are sampled outside of the passes rendering into them
+const RENDER_TEXTURE_LAYOUT: vk::ImageLayout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
 
 pub struct RenderPass {
     handle: RenderPassHandle,
+    order: u32,
+    target_texture: Option<TextureHandle>,
+    // Of transparency passes, the image pass whose depth buffer is tested against and the accumulation and
+    // revealage texture
+    transparency_inputs: Option<(RenderPassHandle, [TextureHandle; 2])>,
     extent: Extent2D,
+    // Number of layers of the target rendered by a single draw with VK_KHR_multiview, 1 for regular passes
+    view_count: u32,
+    samples: SampleCount,
+    color_format: vk::Format,
     target: RenderTarget,
     render_pass: vk::RenderPass,
     pipelines: Vec<PipelineContainer>,
     draw_cmd_buffer: Vec<DrawCommand>,
+    merged_draw_count: u32,
     active: bool,
+    enabled: bool,
+    clear_color: [f32; 4],
 }
 
 impl RenderPass {
+    #[allow(clippy::too_many_arguments)]
     fn new_image_render_pass(
         handle: RenderPassHandle,
         device: &Device,
+        target_texture: TextureHandle,
         image_view: ImageView,
         image_extent: Extent2D,
+        view_count: u32,
+        samples: SampleCount,
         color_format: vk::Format,
         depth_format: vk::Format,
         physical_device_memory_properties: &PhysicalDeviceMemoryProperties,
-        swapchain_image_count: usize,
     ) -> Self {
-        let (depth_image, depth_image_view, depth_image_memory) = image::create_depth_resources(
+        let render_pass =
+            create_imagetarget_render_pass(device, color_format, depth_format, view_count, samples.flags());
+        let target = _create_image_target(
             device,
+            render_pass,
+            image_view,
             image_extent,
-            physical_device_memory_properties,
+            view_count,
+            samples,
+            color_format,
             depth_format,
+            physical_device_memory_properties,
         );
 
-        let render_pass = create_imagetarget_render_pass(device, color_format, depth_format);
-        let framebuffer = image::create_framebuffer(
-            device,
-            Some(image_view),
-            Some(depth_image_view),
-            image_extent,
+        RenderPass {
+            handle,
+            order: handle,
+            target_texture: Some(target_texture),
+            transparency_inputs: None,
+            extent: image_extent,
+            view_count,
+            samples,
+            color_format,
+            target: RenderTarget::ImageTarget(target),
             render_pass,
-        );
+            pipelines: Vec::new(),
+            draw_cmd_buffer: Vec::new(),
+            merged_draw_count: 0,
+            active: true,
+            enabled: true,
+            clear_color: DEFAULT_CLEAR_COLOR,
+        }
+    }
 
-        let target = ImageTarget::new(
-            depth_image,
-            depth_image_view,
-            depth_image_memory,
-            framebuffer,
-            swapchain_image_count,
+    #[allow(clippy::too_many_arguments)]
+    fn new_transparency_pass(
+        handle: RenderPassHandle,
+        device: &Device,
+        accumulation_view: ImageView,
+        revealage_view: ImageView,
+        depth_image_view: ImageView,
+        extent: Extent2D,
+        depth_format: vk::Format,
+        transparency_inputs: (RenderPassHandle, [TextureHandle; 2]),
+    ) -> Self {
+        let render_pass = create_transparency_render_pass(device, depth_format);
+        let framebuffer = image::create_framebuffer_with_attachments(
+            device,
+            &[accumulation_view, revealage_view, depth_image_view],
+            extent,
+            render_pass,
         );
 
         RenderPass {
             handle,
-            extent: image_extent,
-            target: RenderTarget::ImageTarget(target),
+            order: handle,
+            target_texture: None,
+            transparency_inputs: Some(transparency_inputs),
+            extent,
+            view_count: 1,
+            samples: SampleCount::One,
+            color_format: OIT_ACCUMULATION_FORMAT,
+            target: RenderTarget::TransparencyTarget(TransparencyTarget::new(framebuffer)),
             render_pass,
             pipelines: Vec::new(),
             draw_cmd_buffer: Vec::new(),
+            merged_draw_count: 0,
             active: true,
+            enabled: true,
+            clear_color: DEFAULT_CLEAR_COLOR,
         }
     }
 
+    // The render pass of the previous swapchain is reused when given, with the pipelines built for it
     fn new_swapchain_pass(
         device: &Device,
         depth_format: vk::Format,
         physical_device_memory_properties: &PhysicalDeviceMemoryProperties,
         swapchain_container: SwapChainContainer,
-        pipelines: Vec<PipelineContainer>,
+        samples: SampleCount,
+        render_pass: Option<vk::RenderPass>,
+        mut pipelines: Vec<PipelineContainer>,
     ) -> Self {
         let (depth_image, depth_image_view, depth_image_memory) = image::create_depth_resources(
             device,
             swapchain_container.extent,
             physical_device_memory_properties,
             depth_format,
+            1,
+            samples.flags(),
         );
 
-        let render_pass = create_swapchain_render_pass(device, swapchain_container.format, depth_format);
-        let framebuffers = image::create_framebuffers(
-            device,
-            &swapchain_container.image_views,
-            depth_image_view,
-            swapchain_container.extent,
-            render_pass,
-        );
+        let render_pass = render_pass.unwrap_or_else(|| {
+            create_swapchain_render_pass(device, swapchain_container.format, depth_format, samples.flags())
+        });
+        let multisample_image = samples.is_multisampled().then(|| {
+            let (image, image_view, memory) = image::create_multisample_color_resources(
+                device,
+                swapchain_container.extent,
+                physical_device_memory_properties,
+                swapchain_container.format,
+                samples.flags(),
+            );
+            MultisampleImage::new(image, image_view, memory)
+        });
+        let framebuffers = match &multisample_image {
+            Some(multisample_image) => swapchain_container
+                .image_views
+                .iter()
+                .map(|image_view| {
+                    image::create_framebuffer_with_attachments(
+                        device,
+                        &[multisample_image.image_view(), depth_image_view, *image_view],
+                        swapchain_container.extent,
+                        render_pass,
+                    )
+                })
+                .collect(),
+            None => image::create_framebuffers(
+                device,
+                &swapchain_container.image_views,
+                depth_image_view,
+                swapchain_container.extent,
+                render_pass,
+            ),
+        };
+
+        // Kept from the previous swapchain, whose sample count may have changed
+        for pipeline in pipelines.iter_mut().filter(|pipeline| !pipeline.is_built()) {
+            pipeline.set_sample_count(samples.flags());
+        }
 
         let extent = swapchain_container.extent;
+        let color_format = swapchain_container.format;
         let target = SwapchainTarget::new(
             swapchain_container.loader,
             swapchain_container.swapchain,
@@ -100,28 +200,38 @@ impl RenderPass {
             depth_image,
             depth_image_view,
             depth_image_memory,
+            multisample_image,
             framebuffers,
         );
 
         RenderPass {
             handle: SWAPCHAIN_PASS,
+            order: SWAPCHAIN_PASS,
+            target_texture: None,
+            transparency_inputs: None,
             extent,
+            view_count: 1,
+            samples,
+            color_format,
             target: RenderTarget::SwapchainTarget(target),
             render_pass,
             pipelines,
             draw_cmd_buffer: Vec::new(),
+            merged_draw_count: 0,
             active: true,
+            enabled: true,
+            clear_color: DEFAULT_CLEAR_COLOR,
         }
     }
 
-    pub unsafe fn destroy(&mut self, device: &Device) {
+    pub unsafe fn destroy(&mut self, device: &Device, descriptor_allocator: &mut DescriptorAllocator) {
         debug_assert!(self.active);
 
         self.target.destroy(device);
 
         // Pipeline & render pass
         for pipeline_container in self.pipelines.iter_mut() {
-            pipeline_container.destroy_pipeline(device);
+            pipeline_container.destroy_pipeline(device, descriptor_allocator);
         }
 
         // Render pass
@@ -130,9 +240,9 @@ impl RenderPass {
         self.active = false;
     }
 
-    pub unsafe fn destroy_pipelines(&mut self, device: &Device) {
+    pub unsafe fn destroy_pipelines(&mut self, device: &Device, descriptor_allocator: &mut DescriptorAllocator) {
         for pipeline_container in self.pipelines.iter_mut() {
-            pipeline_container.destroy_pipeline(device);
+            pipeline_container.destroy_pipeline(device, descriptor_allocator);
         }
     }
 
@@ -143,33 +253,159 @@ impl RenderPass {
         }
     }
 
-    pub(super) fn add_pipeline(&mut self, pipeline: PipelineContainer) -> PipelineHandle {
+    pub(super) fn add_pipeline(&mut self, mut pipeline: PipelineContainer) -> PipelineHandle {
         let pipeline_index = self.pipelines.len();
 
+        pipeline.set_sample_count(self.samples.flags());
+
         self.pipelines.push(pipeline);
 
         PipelineHandle::new(self.handle, pipeline_index as u32)
     }
 
-    fn build_pipeline(&mut self, device: &Device, handle: PipelineHandle) {
-        debug_assert!(self.pipelines.len() > handle.index());
+    // Transparent draws go last, back to front
+    fn ordered_draw_commands(&self) -> Vec<&DrawCommand> {
+        let (mut draw_commands, mut sorted_draw_commands): (Vec<&DrawCommand>, Vec<&DrawCommand>) =
+            self.draw_cmd_buffer.iter().partition(|cmd| !self.pipelines[cmd.pipeline.index()].is_sorted());
+        sorted_draw_commands.sort_by(|a, b| b.depth.unwrap_or(0.0).total_cmp(&a.depth.unwrap_or(0.0)));
+        draw_commands.extend(sorted_draw_commands);
 
-        let image_count = self.target.image_count();
-        self.pipelines[handle.index()].build(device, self.render_pass, self.extent, image_count);
+        draw_commands
     }
 
-    fn rebuild_all_pipelines(&mut self, device: &Device) {
-        let image_count = self.target.image_count();
-        for pipeline in self.pipelines.iter_mut() {
-            pipeline.build(device, self.render_pass, self.extent, image_count);
+    // Reads the push constants of the draws, which only live until the frame is submitted
+    fn captured_pass(&self) -> CapturedPass {
+        let draws = self
+            .ordered_draw_commands()
+            .into_iter()
+            .map(|draw_command| {
+                let pipeline = &self.pipelines[draw_command.pipeline.index()];
+                let vertex_buffers = draw_command.vertex_buffers.as_slice().iter().map(|buffer| buffer.as_raw());
+                let mesh = match &draw_command.vertex_data {
+                    DrawData::Buffered(data) => CapturedMesh::Buffered {
+                        index_buffer: data.index_buffer.as_raw(),
+                        vertex_buffers: vertex_buffers.collect(),
+                        index_count: data.index_count,
+                        indirect: data.indirect_buffer.is_some(),
+                    },
+                    DrawData::Dynamic(data) => CapturedMesh::Dynamic {
+                        buffer_object: data.buffer_object,
+                        vertex_start: data.vertex_start,
+                        vertex_count: data.vertex_count,
+                    },
+                };
+                let push_constants = match pipeline.push_constant_buffer_size() {
+                    Some(size) if !draw_command.push_constant_ptr.is_null() => unsafe {
+                        std::slice::from_raw_parts(draw_command.push_constant_ptr, size).to_vec()
+                    },
+                    _ => Vec::new(),
+                };
+
+                CapturedDraw {
+                    pipeline: draw_command.pipeline,
+                    pipeline_name: pipeline.name().map(str::to_string),
+                    mesh,
+                    instances: draw_command.instance_range(),
+                    texture: draw_command.texture,
+                    depth: draw_command.depth,
+                    push_constants,
+                }
+            })
+            .collect();
+
+        CapturedPass {
+            handle: (!matches!(self.target, RenderTarget::SwapchainTarget(_))).then_some(self.handle),
+            enabled: self.enabled,
+            draws,
         }
     }
 
-    fn destroy_pipeline(&mut self, device: &Device, handle: PipelineHandle) {
+    fn graph_pass(&self) -> GraphPass {
+        let target = match (&self.target, self.target_texture) {
+            (RenderTarget::SwapchainTarget(_), _) => GraphTarget::Swapchain,
+            (RenderTarget::TransparencyTarget(_), _) => GraphTarget::Transparency,
+            (_, Some(texture)) => GraphTarget::Texture(texture),
+            (_, None) => unreachable!("BUG! Image pass without target texture"),
+        };
+
+        GraphPass {
+            handle: self.handle,
+            order: self.order,
+            enabled: self.enabled,
+            target,
+            extent: (self.extent.width, self.extent.height),
+            pipelines: self
+                .pipelines
+                .iter()
+                .enumerate()
+                .map(|(index, pipeline)| GraphPipeline {
+                    handle: PipelineHandle::new(self.handle, index as u32),
+                    textures: pipeline.sampled_textures(),
+                })
+                .collect(),
+        }
+    }
+
+    // Attachment layouts as declared by create_imagetarget_render_pass and create_transparency_render_pass. The
+    // attachments of the swapchain pass are not sampled and not tracked.
+    fn layout_pass(&self) -> LayoutPass {
+        let color = |texture| LayoutAttachment {
+            image: LayoutImage::Texture(texture),
+            initial_layout: RENDER_TEXTURE_LAYOUT,
+            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            final_layout: RENDER_TEXTURE_LAYOUT,
+        };
+        let depth = |pass, initial_layout| LayoutAttachment {
+            image: LayoutImage::Depth(pass),
+            initial_layout,
+            layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            final_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        };
+        let attachments = match (&self.target, self.target_texture, self.transparency_inputs) {
+            (RenderTarget::ImageTarget(_), Some(texture), _) => {
+                vec![color(texture), depth(self.handle, vk::ImageLayout::UNDEFINED)]
+            }
+            (RenderTarget::TransparencyTarget(_), _, Some((depth_source, [accumulation, revealage]))) => vec![
+                color(accumulation),
+                color(revealage),
+                depth(depth_source, vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL),
+            ],
+            _ => Vec::new(),
+        };
+
+        let bound = self.pipelines.iter().enumerate().flat_map(|(index, pipeline)| {
+            let handle = PipelineHandle::new(self.handle, index as u32);
+            pipeline.sampled_textures().into_iter().map(move |(_, texture)| (handle, texture))
+        });
+        let pushed = self
+            .draw_cmd_buffer
+            .iter()
+            .filter_map(|draw_command| draw_command.texture.map(|(texture, _)| (draw_command.pipeline, texture)));
+
+        LayoutPass {
+            handle: (!matches!(self.target, RenderTarget::SwapchainTarget(_))).then_some(self.handle),
+            attachments,
+            sampled: bound.chain(pushed).collect(),
+        }
+    }
+
+    fn build_pipeline(
+        &mut self,
+        device: &Device,
+        handle: PipelineHandle,
+        frames_in_flight: usize,
+        descriptor_allocator: &mut DescriptorAllocator,
+    ) {
+        debug_assert!(self.pipelines.len() > handle.index());
+
+        self.pipelines[handle.index()].build(device, self.render_pass, frames_in_flight, descriptor_allocator);
+    }
+
+    fn destroy_pipeline(&mut self, device: &Device, handle: PipelineHandle, descriptor_allocator: &mut DescriptorAllocator) {
         debug_assert!(self.pipelines.len() > handle.index());
 
         unsafe {
-            self.pipelines[handle.index()].destroy_pipeline(device);
+            self.pipelines[handle.index()].destroy_pipeline(device, descriptor_allocator);
         }
     }
 
@@ -177,25 +413,16 @@ impl RenderPass {
         &self,
         device: &Device,
         command_buffer: vk::CommandBuffer,
-        image_index: usize,
+        frame_index: usize,
+        swapchain_image_index: usize,
         render_stats: &mut RenderStats,
+        resources: &BakeResources,
     ) {
 
-        // TODO optioanl
-        let clear_values = [
-            vk::ClearValue {
-                color: vk::ClearColorValue {
-                    float32: [0.05, 0.05, 0.1, 1.0],
-                },
-            },
-            vk::ClearValue {
-                // clear value for depth buffer
-                depth_stencil: vk::ClearDepthStencilValue { depth: 1.0, stencil: 0 },
-            },
-        ];
+        let clear_values = _clear_values(&self.target, self.clear_color);
         let render_pass_begin_info = vk::RenderPassBeginInfo::builder()
             .render_pass(self.render_pass)
-            .framebuffer(self.target.framebuffer(image_index))
+            .framebuffer(self.target.framebuffer(swapchain_image_index))
             .render_area(vk::Rect2D {
                 offset: vk::Offset2D { x: 0, y: 0 },
                 extent: self.extent,
@@ -205,95 +432,445 @@ impl RenderPass {
 
         device.cmd_begin_render_pass(command_buffer, &render_pass_begin_info, vk::SubpassContents::INLINE);
 
+        let full_viewport = Viewport::new(0, 0, self.extent.width, self.extent.height);
+        PipelineContainer::set_viewport(device, command_buffer, full_viewport);
+        let mut current_viewport = full_viewport;
+        PipelineContainer::set_blend_constants(device, command_buffer, resources.blend_constants);
+        let mut current_blend_constants = resources.blend_constants;
+
         let mut bound_pipeline = None;
-        for draw_command in self.draw_cmd_buffer.iter() {
+        for draw_command in self.ordered_draw_commands() {
             debug_assert!(self.pipelines.len() > draw_command.pipeline.index());
-            let stats = self.pipelines[draw_command.pipeline.index()].bake_command_buffer(
-                device,
-                command_buffer,
-                draw_command,
-                image_index,
-                bound_pipeline.is_none() || bound_pipeline.unwrap() != draw_command.pipeline.index(),
-            );
-            bound_pipeline = Some(draw_command.pipeline.index());
-            render_stats.add_draw_command(stats);
+            let viewport = draw_command.viewport.unwrap_or(full_viewport);
+            if viewport != current_viewport {
+                debug_assert!(viewport.x + viewport.width <= self.extent.width);
+                debug_assert!(viewport.y + viewport.height <= self.extent.height);
+                PipelineContainer::set_viewport(device, command_buffer, viewport);
+                current_viewport = viewport;
+            }
+            let blend_constants = draw_command.blend_constants.unwrap_or(resources.blend_constants);
+            if blend_constants != current_blend_constants {
+                PipelineContainer::set_blend_constants(device, command_buffer, blend_constants);
+                current_blend_constants = blend_constants;
+            }
+            let texture_manager = resources.texture_manager;
+            let pushed_texture = resources.push_descriptor.zip(draw_command.texture).map(|(push_descriptor, (texture, sampler))| {
+                (push_descriptor, texture_manager.get_imageview(texture), texture_manager.get_sampler(sampler))
+            });
+            // Buffer objects may have been reallocated since the command was added, the device buffer of
+            // this frame is only looked up now. Binding it here leaves the draw call to the pipeline.
+            if let DrawData::Dynamic(dynamic_data) = &draw_command.vertex_data {
+                let bo = resources.buffer_object_manager.borrow_buffer(dynamic_data.buffer_object);
+                device.cmd_bind_vertex_buffers(command_buffer, 0, &[bo.device(frame_index)], &[0]);
+            }
+            // Instances are read from the storage buffer of the pipeline, a draw must not reach past what was pushed
+            // to it this frame. Draws with instances in more than one window of the buffer are split into a command
+            // per window, each binding the buffer at the offset of its window.
+            let pipeline = &self.pipelines[draw_command.pipeline.index()];
+            let mut split_draw_commands = Vec::new();
+            if let Some(((bo, window), (instance_start, instance_count))) =
+                pipeline.storage_buffer_object().zip(draw_command.instance_range())
+            {
+                let buffer = resources.buffer_object_manager.borrow_buffer(bo);
+                let (start, count) = match _valid_instance_range(instance_start, instance_count, buffer.len() as u32) {
+                    Some(range) => range,
+                    None => {
+                        log_debug_once!("draw instances past the end of buffer object {}, skipped", bo);
+                        continue;
+                    }
+                };
+                if (start, count) != (instance_start, instance_count) {
+                    log_debug_once!("draw instances past the end of buffer object {}, clamped", bo);
+                }
+
+                let commands = window.split(start, count, buffer.capacity() as u32);
+                if commands.iter().map(|(_, _, count)| count).sum::<u32>() < count {
+                    log_debug_once!("draw instances out of reach of the windows of buffer object {}", bo);
+                }
+                if commands != [(0, instance_start, instance_count)] {
+                    split_draw_commands = commands
+                        .into_iter()
+                        .map(|(window_start, first, count)| {
+                            draw_command
+                                .clone()
+                                .with_instances(count, first)
+                                .with_storage_offset(window_start * window.stride)
+                        })
+                        .collect();
+                    if split_draw_commands.is_empty() {
+                        continue;
+                    }
+                }
+            }
+
+            let unsplit = split_draw_commands.is_empty().then_some(draw_command);
+            for draw_command in unsplit.into_iter().chain(split_draw_commands.iter()) {
+                let stats = pipeline.bake_command_buffer(
+                    device,
+                    command_buffer,
+                    draw_command,
+                    frame_index,
+                    bound_pipeline.is_none() || bound_pipeline.unwrap() != draw_command.pipeline.index(),
+                    pushed_texture,
+                );
+                bound_pipeline = Some(draw_command.pipeline.index());
+                render_stats.add_draw_command(stats);
+            }
         }
 
+        render_stats.merged_draw_command_count += self.merged_draw_count;
+
         device.cmd_end_render_pass(command_buffer);
     }
+
+    fn add_draw_command(&mut self, draw_command: DrawCommand) {
+        let pipeline = &self.pipelines[draw_command.pipeline.index()];
+        if let Some(last) = self.draw_cmd_buffer.last_mut().filter(|_| !pipeline.is_sorted()) {
+            if last.try_merge(&draw_command, pipeline.push_constant_buffer_size()) {
+                self.merged_draw_count += 1;
+                return;
+            }
+        }
+
+        self.draw_cmd_buffer.push(draw_command);
+    }
+}
+
+// Instances of a draw that were pushed to its storage buffer, None if there are none
+fn _valid_instance_range(instance_start: u32, instance_count: u32, available: u32) -> Option<(u32, u32)> {
+    let end = instance_start.saturating_add(instance_count).min(available);
+    (end > instance_start).then(|| (instance_start, end - instance_start))
+}
+
+// Shared by all passes while baking the draw command buffer of a frame
+pub struct BakeResources<'a> {
+    texture_manager: &'a TextureManager,
+    buffer_object_manager: &'a BufferObjectManager,
+    push_descriptor: Option<&'a PushDescriptor>,
+    blend_constants: [f32; 4],
+}
+
+// Camera uniform of a pass, bound by its pipelines configured with_pass_camera
+struct PassCamera {
+    buffer_object: usize,
+    size: usize,
+    pipelines: Vec<PipelineHandle>,
 }
 
 pub struct RenderPassManager {
     render_passes: HashMap<RenderPassHandle, RenderPass>,
+    pass_cameras: HashMap<RenderPassHandle, PassCamera>,
     pass_order: Vec<RenderPassHandle>,
     swapchain_pass: Option<RenderPass>,
+    // Applied when the swapchain pass is created
+    swapchain_samples: SampleCount,
+    frames_in_flight: usize,
 
     depth_format: vk::Format,
-
+    descriptor_allocator: DescriptorAllocator,
+    push_descriptor: Option<PushDescriptor>,
+    batch_pipeline_builds: bool,
+    // Of draw commands without blend constants of their own
+    blend_constants: [f32; 4],
+    // maxStorageBufferRange and minStorageBufferOffsetAlignment of the device
+    storage_buffer_limits: (u32, vk::DeviceSize),
 }
 
 impl RenderPassManager {
     pub fn new(
         instance: &ash::Instance,
         physical_device: vk::PhysicalDevice,
+        push_descriptor: Option<PushDescriptor>,
     ) -> Self {
         let depth_format = image::find_depth_format(instance, physical_device);
+        let limits = unsafe { instance.get_physical_device_properties(physical_device) }.limits;
 
         Self {
             render_passes: HashMap::new(),
+            pass_cameras: HashMap::new(),
             pass_order: Vec::new(),
             swapchain_pass: None,
+            swapchain_samples: SampleCount::One,
+            frames_in_flight: DEFAULT_FRAMES_IN_FLIGHT,
 
             depth_format,
+            descriptor_allocator: DescriptorAllocator::new(),
+            push_descriptor,
+            batch_pipeline_builds: false,
+            blend_constants: [0.0; 4],
+            storage_buffer_limits: (limits.max_storage_buffer_range, limits.min_storage_buffer_offset_alignment),
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn create_image_target_pass(&mut self,
                                     device: &Device,
                                     physical_device_memory_properties: &PhysicalDeviceMemoryProperties,
+                                    target_texture: TextureHandle,
                                     image_view: ImageView,
                                     image_width: u32,
                                     image_height: u32,
+                                    view_count: u32,
+                                    samples: SampleCount,
                                     image_format: vk::Format,
-                                    pass_order: u32,
-                                    swapchain_image_count: usize) -> Result<RenderPassHandle, &str> {
+                                    pass_order: u32) -> Result<RenderPassHandle, &'static str> {
         if self.render_passes.contains_key(&pass_order) {
             return Err("a render pass with same order already exists!");
         }
+        if view_count > 1 && samples.is_multisampled() {
+            return Err("Layered passes can't be multisampled!");
+        }
 
         let handle = pass_order;
         let extent = Extent2D { width: image_width, height: image_height };
 
         let render_pass = RenderPass::new_image_render_pass(handle,
                                                             device,
+                                                            target_texture,
                                                             image_view,
                                                             extent,
+                                                            view_count,
+                                                            samples,
                                                             image_format,
                                                             self.depth_format,
-                                                            physical_device_memory_properties,
-                                                            swapchain_image_count);
+                                                            physical_device_memory_properties);
 
         self.render_passes.insert(handle, render_pass);
 
         self.pass_order.push(handle);
-        self.pass_order.sort_unstable();
+        self.sort_pass_order();
 
         Ok(handle)
     }
 
-    pub fn create_swapchain_pass(
+    // Returns target texture and extent of an image pass whose depth buffer can be shared with a transparency pass
+    // baked after it.
+    pub fn transparency_depth_source(
+        &self,
+        depth_source: RenderPassHandle,
+        pass_order: u32,
+    ) -> Result<(TextureHandle, Extent2D), &'static str> {
+        if self.render_passes.contains_key(&pass_order) {
+            return Err("a render pass with same order already exists!");
+        }
+
+        let source = self.render_passes.get(&depth_source).ok_or("Invalid depth source pass")?;
+        if !matches!(source.target, RenderTarget::ImageTarget(_)) {
+            return Err("The depth source must render into a texture!");
+        }
+        if source.view_count > 1 {
+            return Err("Layered passes can't be used as depth source!");
+        }
+        if source.samples.is_multisampled() {
+            return Err("Multisampled passes can't be used as depth source!");
+        }
+        if pass_order <= source.order {
+            return Err("The transparency pass must be baked after its depth source!");
+        }
+
+        Ok((source.target_texture.unwrap(), source.extent))
+    }
+
+    // Depth image, its view and extent of an image pass, sampled to build the HiZ pyramid of occlusion culling
+    pub fn depth_target(
+        &self,
+        handle: RenderPassHandle,
+    ) -> Result<(vk::Image, ImageView, Extent2D), &'static str> {
+        let pass = self.render_passes.get(&handle).ok_or("Invalid render pass")?;
+        if pass.view_count > 1 {
+            return Err("Layered passes can't be used as depth source!");
+        }
+        if pass.samples.is_multisampled() {
+            return Err("Multisampled passes can't be used as depth source!");
+        }
+
+        match &pass.target {
+            RenderTarget::ImageTarget(target) => Ok((target.depth_image(), target.depth_image_view(), pass.extent)),
+            _ => Err("The depth source must render into a texture!"),
+        }
+    }
+
+    pub fn depth_format(&self) -> vk::Format {
+        self.depth_format
+    }
+
+    // The depth source must be validated with transparency_depth_source first
+    pub fn create_transparency_pass(
+        &mut self,
+        device: &Device,
+        depth_source: RenderPassHandle,
+        (accumulation, accumulation_view): (TextureHandle, ImageView),
+        (revealage, revealage_view): (TextureHandle, ImageView),
+        pass_order: u32,
+    ) -> RenderPassHandle {
+        let source = &self.render_passes[&depth_source];
+        let depth_image_view = match &source.target {
+            RenderTarget::ImageTarget(target) => target.depth_image_view(),
+            _ => unreachable!("BUG! Depth source of a transparency pass must be an image pass"),
+        };
+
+        let handle = pass_order;
+        let render_pass = RenderPass::new_transparency_pass(
+            handle,
+            device,
+            accumulation_view,
+            revealage_view,
+            depth_image_view,
+            source.extent,
+            self.depth_format,
+            (depth_source, [accumulation, revealage]),
+        );
+
+        self.render_passes.insert(handle, render_pass);
+
+        self.pass_order.push(handle);
+        self.sort_pass_order();
+
+        handle
+    }
+
+    // Recreates framebuffers and depth buffers of passes rendering into a texture whose image was replaced. The
+    // render pass and the pipelines are kept, their viewport and scissor are set while baking.
+    pub fn resize_image_targets(
         &mut self,
         device: &Device,
         physical_device_memory_properties: &PhysicalDeviceMemoryProperties,
-        swapchain_container: SwapChainContainer,
+        texture: TextureHandle,
+        image_view: ImageView,
+        format: vk::Format,
+        extent: Extent2D,
     ) {
-        debug_assert!(self.swapchain_pass.is_none() || !self.swapchain_pass.as_ref().unwrap().active);
+        let passes = self.render_passes.values_mut().filter(|pass| pass.target_texture == Some(texture));
+        for pass in passes {
+            unsafe {
+                pass.target.destroy(device);
+            }
+            pass.target = RenderTarget::ImageTarget(_create_image_target(
+                device,
+                pass.render_pass,
+                image_view,
+                extent,
+                pass.view_count,
+                pass.samples,
+                format,
+                self.depth_format,
+                physical_device_memory_properties,
+            ));
+            pass.extent = extent;
+        }
+    }
+
+    pub fn replace_texture_view(&mut self, texture: TextureHandle, image_view: ImageView) {
+        let passes = self.render_passes.values_mut().chain(self.swapchain_pass.iter_mut());
+        for pass in passes {
+            for pipeline in pass.pipelines.iter_mut() {
+                pipeline.replace_texture_view(texture, image_view);
+            }
+        }
+    }
+
+    // Image passes are baked in ascending order, the swapchain pass always comes last.
+    pub fn set_pass_order(&mut self, handle: RenderPassHandle, order: u32) -> Result<(), &'static str> {
+        let pass = self.render_passes.get_mut(&handle).ok_or("Invalid render pass")?;
+        pass.order = order;
+        self.sort_pass_order();
+
+        Ok(())
+    }
+
+    pub fn set_pass_enabled(&mut self, handle: RenderPassHandle, enabled: bool) -> Result<(), &'static str> {
+        let pass = self.render_passes.get_mut(&handle).ok_or("Invalid render pass")?;
+        pass.enabled = enabled;
+
+        Ok(())
+    }
+
+    pub fn is_pass_enabled(&self, handle: RenderPassHandle) -> Result<bool, &'static str> {
+        let pass = self.render_passes.get(&handle).ok_or("Invalid render pass")?;
 
-        let old_pass = self.swapchain_pass.take();
-        let pipelines = if let Some(old_pass) = old_pass {
-            old_pass.pipelines
+        Ok(pass.enabled)
+    }
+
+    pub fn pipeline_count(&self, handle: RenderPassHandle) -> Result<usize, &'static str> {
+        let pass = self.render_passes.get(&handle).ok_or("Invalid render pass")?;
+
+        Ok(pass.pipelines.len())
+    }
+
+    // Ignored by transparency passes, which always clear to their blend identities
+    pub fn set_pass_clear_color(&mut self, handle: RenderPassHandle, color: [f32; 4]) -> Result<(), &'static str> {
+        let pass = if handle == SWAPCHAIN_PASS {
+            self.swapchain_pass.as_mut()
         } else {
-            Vec::new()
+            self.render_passes.get_mut(&handle)
+        };
+        pass.ok_or("Invalid render pass")?.clear_color = color;
+
+        Ok(())
+    }
+
+    // In execution order, the swapchain pass last
+    pub fn graph_passes(&self) -> Vec<GraphPass> {
+        self.pass_order
+            .iter()
+            .map(|handle| &self.render_passes[handle])
+            .chain(self.swapchain_pass.iter())
+            .map(|pass| pass.graph_pass())
+            .collect()
+    }
+
+    // Enabled passes in execution order, see layouts::validate
+    pub fn layout_passes(&self) -> Vec<LayoutPass> {
+        self.pass_order
+            .iter()
+            .map(|handle| &self.render_passes[handle])
+            .chain(self.swapchain_pass.iter())
+            .filter(|pass| pass.enabled)
+            .map(|pass| pass.layout_pass())
+            .collect()
+    }
+
+    // The draw commands of the current frame, see capture::to_json
+    pub fn captured_passes(&self) -> Vec<CapturedPass> {
+        self.pass_order
+            .iter()
+            .map(|handle| &self.render_passes[handle])
+            .chain(self.swapchain_pass.iter())
+            .map(|pass| pass.captured_pass())
+            .collect()
+    }
+
+    fn sort_pass_order(&mut self) {
+        let render_passes = &self.render_passes;
+        self.pass_order.sort_by_key(|handle| (render_passes[handle].order, *handle));
+    }
+
+    // The render pass and the pipelines of the previous swapchain pass are kept as long as they are compatible with
+    // the new swapchain, i.e. when only its extent changed
+    pub fn create_swapchain_pass(
+        &mut self,
+        device: &Device,
+        physical_device_memory_properties: &PhysicalDeviceMemoryProperties,
+        swapchain_container: SwapChainContainer,
+    ) {
+        let samples = self.swapchain_samples;
+        let (render_pass, pipelines, clear_color) = match self.swapchain_pass.take() {
+            Some(mut old_pass) => {
+                let compatible = old_pass.color_format == swapchain_container.format && old_pass.samples == samples;
+                let render_pass = if old_pass.active && compatible {
+                    Some(old_pass.render_pass)
+                } else {
+                    unsafe {
+                        old_pass.destroy_pipelines(device, &mut self.descriptor_allocator);
+                        if old_pass.active {
+                            device.destroy_render_pass(old_pass.render_pass, None);
+                        }
+                    }
+                    None
+                };
+                (render_pass, old_pass.pipelines, old_pass.clear_color)
+            }
+            None => (None, Vec::new(), DEFAULT_CLEAR_COLOR),
         };
 
         let mut swapchain_pass = RenderPass::new_swapchain_pass(
@@ -301,45 +878,83 @@ impl RenderPassManager {
             self.depth_format,
             physical_device_memory_properties,
             swapchain_container,
+            samples,
+            render_pass,
             pipelines,
         );
-        swapchain_pass.rebuild_all_pipelines(device);
-
+        swapchain_pass.clear_color = clear_color;
         self.swapchain_pass = Some(swapchain_pass);
+
+        // Also builds the image pass pipelines destroyed along with the old swapchain
+        self.build_pending_pipelines(device);
     }
 
-    pub fn rebuild_image_target_pipelines(&mut self, device: &Device, swapchain_image_count: usize) {
-        for pass in self.render_passes.values_mut() {
-            if let RenderTarget::ImageTarget(target) = &mut pass.target {
-                target.set_image_count(swapchain_image_count)
-            } else {
-                unreachable!("BUG! Render target of a image pass must be an ImageTarget");
-            }
-            pass.rebuild_all_pipelines(device);
-        }
+    // Takes effect when the swapchain is recreated
+    pub fn set_swapchain_samples(&mut self, samples: SampleCount) {
+        self.swapchain_samples = samples;
+    }
+
+    pub fn swapchain_samples(&self) -> SampleCount {
+        self.swapchain_samples
+    }
+
+    // The descriptor sets of the pipelines are allocated per frame in flight, built pipelines have to be destroyed
+    // first with destroy_all_pipelines and are rebuilt by build_pending_pipelines
+    pub fn set_frames_in_flight(&mut self, frames_in_flight: usize) {
+        self.frames_in_flight = frames_in_flight;
     }
 
     pub unsafe  fn destroy_image_pass_pipelines(&mut self, device: &Device) {
         for pass in self.render_passes.values_mut() {
-            pass.destroy_pipelines(device);
+            pass.destroy_pipelines(device, &mut self.descriptor_allocator);
+        }
+    }
+
+    // Only the swapchain, its framebuffers and its depth buffer. Followed by create_swapchain_pass.
+    pub unsafe fn destroy_swapchain_target(&mut self, device: &Device) {
+        if let Some(swapchain_pass) = self.swapchain_pass.as_mut() {
+            swapchain_pass.target.destroy(device);
+        }
+    }
+
+    // Of all passes, e.g. as their descriptor sets are allocated per frame in flight. They are rebuilt by
+    // create_swapchain_pass or build_pending_pipelines.
+    pub unsafe fn destroy_all_pipelines(&mut self, device: &Device) {
+        for pass in self.render_passes.values_mut().chain(self.swapchain_pass.iter_mut()) {
+            pass.destroy_pipelines(device, &mut self.descriptor_allocator);
         }
     }
 
     pub unsafe fn destroy_swapchain_pass(&mut self, device: &Device) {
-        debug_assert!(self.swapchain_pass.is_some());
-        self.swapchain_pass.as_mut().unwrap().destroy(device);
+        if let Some(swapchain_pass) = self.swapchain_pass.as_mut() {
+            swapchain_pass.destroy(device, &mut self.descriptor_allocator);
+        }
     }
 
     pub unsafe fn destroy_all(&mut self, device: &Device) {
         for pass in self.render_passes.values_mut() {
-            pass.destroy(device);
+            pass.destroy(device, &mut self.descriptor_allocator);
         }
+
+        self.descriptor_allocator.destroy(device);
     }
 
-    pub unsafe fn destroy_static_pipeline_objects(&mut self, device: &Device) {
-        debug_assert!(self.swapchain_pass.is_some());
+    pub fn supports_push_descriptors(&self) -> bool {
+        self.push_descriptor.is_some()
+    }
 
-        self.swapchain_pass.as_mut().unwrap().destroy_static_pipeline_objects(device);
+    pub fn set_blend_constants(&mut self, blend_constants: [f32; 4]) {
+        self.blend_constants = blend_constants;
+    }
+
+    pub fn descriptor_stats(&self) -> DescriptorStats {
+        self.descriptor_allocator.stats()
+    }
+
+    pub unsafe fn destroy_static_pipeline_objects(&mut self, device: &Device) {
+        if let Some(swapchain_pass) = self.swapchain_pass.as_mut() {
+            swapchain_pass.destroy_static_pipeline_objects(device);
+        }
 
         for pass in self.render_passes.values_mut() {
             pass.destroy_static_pipeline_objects(device);
@@ -358,12 +973,49 @@ impl RenderPassManager {
     }
 
     pub fn borrow_pipeline_mut(&mut self, handle: PipelineHandle) -> &mut PipelineContainer {
-        if handle.render_pass == SWAPCHAIN_PASS {
-            debug_assert!(self.swapchain_pass.is_some());
-            &mut self.swapchain_pass.as_mut().unwrap().pipelines[handle.index()]
+        let pass = _get_pass_mut(&mut self.swapchain_pass, &mut self.render_passes, handle.render_pass);
+        &mut pass.pipelines[handle.index()]
+    }
+
+    // Pipelines already using the camera of the pass are rebound to the new uniform
+    pub fn set_pass_camera(
+        &mut self,
+        buffer_object_manager: &mut BufferObjectManager,
+        pass: RenderPassHandle,
+        buffer_object: usize,
+        size: usize,
+    ) -> Result<(), &'static str> {
+        let pass_exists = if pass == SWAPCHAIN_PASS {
+            self.swapchain_pass.is_some()
         } else {
-            &mut self.render_passes.get_mut(&handle.render_pass).unwrap().pipelines[handle.index()]
+            self.render_passes.contains_key(&pass)
+        };
+        if !pass_exists {
+            return Err("Invalid render pass");
+        }
+        if !buffer_object_manager.borrow_buffer(buffer_object).is_vertex_uniform() {
+            return Err("The pass camera must be a vertex stage uniform");
+        }
+
+        let camera = self.pass_cameras.entry(pass).or_insert(PassCamera {
+            buffer_object,
+            size,
+            pipelines: Vec::new(),
+        });
+        if !camera.pipelines.is_empty() && camera.size != size {
+            return Err("The pass camera must be of the same type as the one bound by the pipelines of the pass");
+        }
+        let previous = std::mem::replace(&mut camera.buffer_object, buffer_object);
+        camera.size = size;
+
+        for pipeline in camera.pipelines.clone() {
+            buffer_object_manager.unassign_pipeline(previous, pipeline);
+            buffer_object_manager.assign_pipeline(buffer_object, pipeline);
+            self.borrow_pipeline_mut(pipeline)
+                .rebind_uniform_buffers(UniformStage::Vertex, buffer_object_manager.borrow_buffer(buffer_object).devices());
         }
+
+        Ok(())
     }
 
     pub fn swapchain_extent(&self) -> Extent2D {
@@ -372,22 +1024,46 @@ impl RenderPassManager {
         self.swapchain_pass.as_ref().unwrap().extent
     }
 
-    pub fn add_pipeline<T: VertexInputDescription>(
+    pub fn add_pipeline(
         &mut self,
         device: &Device,
         buffer_object_manager: &mut BufferObjectManager,
         texture_manager: &TextureManager,
-        config: PipelineConfiguration,
+        mut config: PipelineConfiguration,
         render_pass_handle: RenderPassHandle,
+        frame_globals: usize,
     ) -> PipelineHandle {
-        let render_pass = if render_pass_handle == SWAPCHAIN_PASS {
-            debug_assert!(self.swapchain_pass.is_some());
-            self.swapchain_pass.as_mut().unwrap()
-        } else {
-            let pass = self.render_passes.get_mut(&render_pass_handle);
-            debug_assert!(pass.is_some());
-            pass.unwrap()
-        };
+        assert!(
+            config.pushed_texture_binding.is_none() || self.push_descriptor.is_some(),
+            "Pushed textures require VK_KHR_push_descriptor, which is not supported by the device!"
+        );
+        assert!(
+            !config.bindless_textures || texture_manager.bindless_set().is_some(),
+            "Bindless textures require VK_EXT_descriptor_indexing, which is not supported by the device!"
+        );
+
+        if config.pass_camera_binding.is_some() {
+            let camera = self
+                .pass_cameras
+                .get(&render_pass_handle)
+                .expect("The render pass has no camera, set one with Context::set_render_pass_camera!");
+            if let Err(err) = config.bind_pass_camera(camera.buffer_object, camera.size) {
+                panic!("Invalid pass camera: {}", err);
+            }
+        }
+
+        let render_pass = _get_pass_mut(&mut self.swapchain_pass, &mut self.render_passes, render_pass_handle);
+        assert_eq!(
+            config.blend_mode == BlendMode::WeightedBlended,
+            matches!(render_pass.target, RenderTarget::TransparencyTarget(_)),
+            "Weighted blended pipelines and transparency passes can only be used together!"
+        );
+        if let Some(sample_count) = config.sample_count {
+            assert_eq!(
+                sample_count, render_pass.samples,
+                "The sample count of the pipeline doesn't match the one of its render pass!"
+            );
+        }
 
         let vertex_uniform_binding_cfg = config.vertex_uniform_cfg.map(|cfg| {
             BufferObjectBindingConfiguration::new(
@@ -415,6 +1091,13 @@ impl RenderPassManager {
             )
         });
 
+        let frame_globals_binding_cfg = config.frame_globals_binding.map(|binding| {
+            BufferObjectBindingConfiguration::new(
+                binding,
+                buffer_object_manager.borrow_buffer(frame_globals).capacity_bytes(),
+            )
+        });
+
         let vertex_topology = match config.vertex_topology {
             VertexTopology::Triangle => vk::PrimitiveTopology::TRIANGLE_LIST,
             VertexTopology::TriangeStrip => vk::PrimitiveTopology::TRIANGLE_STRIP,
@@ -424,26 +1107,43 @@ impl RenderPassManager {
             .texture_cfgs
             .iter()
             .map(|cfg| {
+                assert_eq!(
+                    texture_manager.is_cube(cfg.texture),
+                    cfg.binding_type == SamplerBindingType::SamplerCube,
+                    "Cube textures can only be bound with add_cube_texture and other textures only with add_texture!"
+                );
                 SamplerBindingConfiguration::new(
                     cfg.binding,
+                    cfg.texture,
                     texture_manager.get_imageview(cfg.texture),
                     texture_manager.get_sampler(cfg.sampler),
                 )
             })
             .collect();
 
-        let mut pipeline_container = PipelineContainer::new::<T>(
+        let bindless_textures = config.bindless_textures;
+        let bindings = PipelineBindings {
+            vertex_uniform_cfg: vertex_uniform_binding_cfg,
+            fragment_uniform_cfg: fragment_uniform_binding_cfg,
+            storage_buffer_cfg: storage_buffer_binding_cfg,
+            frame_globals_cfg: frame_globals_binding_cfg,
+            sampler_cfgs,
+            pushed_texture_binding: config.pushed_texture_binding,
+            bindless_set: texture_manager.bindless_set().filter(|_| bindless_textures),
+        };
+        let mut pipeline_container = PipelineContainer::new(
             device,
             config.vertex_shader_code,
             config.fragment_shader_code,
-            vertex_uniform_binding_cfg,
-            fragment_uniform_binding_cfg,
-            storage_buffer_binding_cfg,
-            sampler_cfgs,
+            bindings,
             vertex_topology,
             config.push_constant_buffer_size,
-            config.alpha_blending,
+            config.blend_mode,
         );
+        pipeline_container.set_vertex_layout(&config.vertex_layout);
+        pipeline_container.set_depth_test(config.depth_test);
+        pipeline_container.set_color_write_mask(config.color_write_mask);
+        pipeline_container.set_name(config.name);
 
         if let Some(cfg) = config.vertex_uniform_cfg {
             pipeline_container.set_uniform_buffers(
@@ -458,8 +1158,16 @@ impl RenderPassManager {
             );
         }
         if let Some(cfg) = config.storage_buffer_cfg {
-            pipeline_container
-                .set_storage_buffers(buffer_object_manager.borrow_buffer(cfg.buffer_object_handle).devices());
+            let buffer = buffer_object_manager.borrow_buffer(cfg.buffer_object_handle);
+            let (max_range, offset_alignment) = self.storage_buffer_limits;
+            pipeline_container.set_storage_buffers(buffer.devices());
+            pipeline_container.set_storage_buffer_object(
+                cfg.buffer_object_handle,
+                StorageWindow::new(buffer.stride(), max_range, offset_alignment),
+            );
+        }
+        if config.frame_globals_binding.is_some() {
+            pipeline_container.set_frame_globals_buffers(buffer_object_manager.borrow_buffer(frame_globals).devices());
         }
 
         let pipeline_handle = render_pass.add_pipeline(pipeline_container);
@@ -473,81 +1181,270 @@ impl RenderPassManager {
         if let Some(storage_cfg) = config.storage_buffer_cfg {
             buffer_object_manager.assign_pipeline(storage_cfg.buffer_object_handle, pipeline_handle);
         }
+        if config.frame_globals_binding.is_some() {
+            buffer_object_manager.assign_pipeline(frame_globals, pipeline_handle);
+        }
+        if config.pass_camera_binding.is_some() {
+            self.pass_cameras.get_mut(&render_pass_handle).unwrap().pipelines.push(pipeline_handle);
+        }
 
-        render_pass.build_pipeline(device, pipeline_handle);
+        if !self.batch_pipeline_builds {
+            render_pass.build_pipeline(device, pipeline_handle, self.frames_in_flight, &mut self.descriptor_allocator);
+        }
 
         pipeline_handle
     }
 
+    // Pipelines added until end_pipeline_batch are only built then, all at once across multiple threads
+    pub fn begin_pipeline_batch(&mut self) {
+        self.batch_pipeline_builds = true;
+    }
+
+    pub fn end_pipeline_batch(&mut self, device: &Device) {
+        self.batch_pipeline_builds = false;
+        self.build_pending_pipelines(device);
+    }
+
+    pub fn is_pipeline_batch_active(&self) -> bool {
+        self.batch_pipeline_builds
+    }
+
+    pub fn build_pending_pipelines(&mut self, device: &Device) {
+        let frames_in_flight = self.frames_in_flight;
+        let jobs = self
+            .swapchain_pass
+            .iter_mut()
+            .chain(self.render_passes.values_mut())
+            .filter(|pass| pass.active)
+            .flat_map(|pass| {
+                let render_pass = pass.render_pass;
+                pass.pipelines
+                    .iter_mut()
+                    .filter(|pipeline| !pipeline.is_built())
+                    .map(move |pipeline| (pipeline, render_pass, frames_in_flight))
+            })
+            .collect();
+
+        pipeline::build_pipelines(device, jobs, &mut self.descriptor_allocator);
+    }
+
     pub fn rebuild_pipeline(
         &mut self,
         device: &Device,
         pipeline_handle: PipelineHandle,
     ) {
-        let pass = if pipeline_handle.render_pass == SWAPCHAIN_PASS {
-            debug_assert!(self.swapchain_pass.is_some());
-            self.swapchain_pass.as_mut().unwrap()
-        } else {
-            self.render_passes.get_mut(&pipeline_handle.render_pass).unwrap()
-        };
+        let pass = _get_pass_mut(&mut self.swapchain_pass, &mut self.render_passes, pipeline_handle.render_pass);
 
-        pass.destroy_pipeline(device, pipeline_handle);
-        pass.build_pipeline(device, pipeline_handle);
+        // Works the same for swapchain and image passes
+        pass.destroy_pipeline(device, pipeline_handle, &mut self.descriptor_allocator);
+        pass.build_pipeline(device, pipeline_handle, self.frames_in_flight, &mut self.descriptor_allocator);
     }
 
     pub fn update_storage_buffer(&mut self, pipeline: PipelineHandle, new_buffers: &[vk::Buffer], new_capacity: usize) {
-        if pipeline.render_pass == SWAPCHAIN_PASS {
-            debug_assert!(self.swapchain_pass.is_some());
-            self.swapchain_pass.as_mut().unwrap().pipelines[pipeline.index()].update_storage_buffer(new_buffers, new_capacity);
+        self.borrow_pipeline_mut(pipeline).update_storage_buffer(new_buffers, new_capacity);
+    }
+
+    pub fn set_pipeline_texture(
+        &mut self,
+        pipeline: PipelineHandle,
+        binding: u8,
+        texture: TextureHandle,
+        image: ImageView,
+        sampler: vk::Sampler,
+    ) -> Result<(), &'static str> {
+        let pass = if pipeline.render_pass == SWAPCHAIN_PASS {
+            self.swapchain_pass.as_mut()
         } else {
-            self.render_passes.get_mut(&pipeline.render_pass).unwrap().pipelines[pipeline.index()].update_storage_buffer(new_buffers, new_capacity);
+            self.render_passes.get_mut(&pipeline.render_pass)
         }
+        .ok_or("Invalid render pass")?;
+
+        pass.pipelines
+            .get_mut(pipeline.index())
+            .ok_or("Invalid pipeline")?
+            .set_texture(binding, texture, image, sampler)
     }
 
+    pub fn update_stale_descriptor_sets(&mut self, device: &Device, frame_index: usize) {
+        let passes = self.render_passes.values_mut().chain(self.swapchain_pass.iter_mut());
+        for pass in passes {
+            for pipeline in pass.pipelines.iter_mut() {
+                pipeline.update_stale_descriptor_set(device, frame_index);
+            }
+        }
+    }
+
+    // after_pass is called once each image pass has been recorded, e.g. to build the HiZ pyramid of its depth
+    #[allow(clippy::too_many_arguments)]
     pub unsafe fn bake_command_buffer(
         &self,
         device: &Device,
         command_buffer: vk::CommandBuffer,
-        image_index: usize,
+        frame_index: usize,
+        swapchain_image_index: usize,
         render_stats: &mut RenderStats,
+        texture_manager: &TextureManager,
+        buffer_object_manager: &BufferObjectManager,
+        mut after_pass: impl FnMut(RenderPassHandle),
     ) {
+        let resources = BakeResources {
+            texture_manager,
+            buffer_object_manager,
+            push_descriptor: self.push_descriptor.as_ref(),
+            blend_constants: self.blend_constants,
+        };
 
-        // TODO: use render pass order
-        for pass in self.render_passes.values() {
-            pass.bake_command_buffer(device, command_buffer, image_index, render_stats);
+        let enabled_passes = self.pass_order.iter().map(|handle| &self.render_passes[handle]).filter(|pass| pass.enabled);
+        for pass in enabled_passes {
+            pass.bake_command_buffer(device, command_buffer, frame_index, 0, render_stats, &resources);
+            after_pass(pass.handle);
         }
 
-        debug_assert!(self.swapchain_pass.is_some());
-        self.swapchain_pass.as_ref().unwrap().bake_command_buffer(device, command_buffer, image_index, render_stats);
+        if let Some(swapchain_pass) = self.swapchain_pass.as_ref() {
+            swapchain_pass.bake_command_buffer(
+                device,
+                command_buffer,
+                frame_index,
+                swapchain_image_index,
+                render_stats,
+                &resources,
+            );
+        }
     }
 
     pub fn reset_draw_command_buffers(&mut self) {
-        debug_assert!(self.swapchain_pass.is_some());
-
-        self.swapchain_pass.as_mut().unwrap().draw_cmd_buffer.clear();
+        if let Some(swapchain_pass) = self.swapchain_pass.as_mut() {
+            swapchain_pass.draw_cmd_buffer.clear();
+            swapchain_pass.merged_draw_count = 0;
+        }
 
         for render_pass in self.render_passes.values_mut() {
             render_pass.draw_cmd_buffer.clear();
+            render_pass.merged_draw_count = 0;
         }
     }
 
     pub fn add_draw_command(&mut self, draw_command: DrawCommand) {
-        let handle = draw_command.pipeline.render_pass;
+        let pass = _get_pass_mut(&mut self.swapchain_pass, &mut self.render_passes, draw_command.pipeline.render_pass);
 
-        let pass = if handle == SWAPCHAIN_PASS {
-            debug_assert!(self.swapchain_pass.is_some());
-            self.swapchain_pass.as_mut().unwrap()
-        } else {
-            let pass = self.render_passes.get_mut(&handle);
-            debug_assert!(pass.is_some());
-            pass.unwrap()
-        };
+        if pass.enabled {
+            pass.add_draw_command(draw_command);
+        }
+    }
+}
 
-        pass.draw_cmd_buffer.push(draw_command);
+#[allow(clippy::too_many_arguments)]
+fn _create_image_target(
+    device: &Device,
+    render_pass: vk::RenderPass,
+    image_view: ImageView,
+    extent: Extent2D,
+    view_count: u32,
+    samples: SampleCount,
+    color_format: vk::Format,
+    depth_format: vk::Format,
+    physical_device_memory_properties: &PhysicalDeviceMemoryProperties,
+) -> ImageTarget {
+    let (depth_image, depth_image_view, depth_image_memory) = image::create_depth_resources(
+        device,
+        extent,
+        physical_device_memory_properties,
+        depth_format,
+        view_count,
+        samples.flags(),
+    );
+
+    let multisample_image = samples.is_multisampled().then(|| {
+        let (image, image_view, memory) = image::create_multisample_color_resources(
+            device,
+            extent,
+            physical_device_memory_properties,
+            color_format,
+            samples.flags(),
+        );
+        MultisampleImage::new(image, image_view, memory)
+    });
+    let framebuffer = match &multisample_image {
+        Some(multisample_image) => image::create_framebuffer_with_attachments(
+            device,
+            &[multisample_image.image_view(), depth_image_view, image_view],
+            extent,
+            render_pass,
+        ),
+        None => image::create_framebuffer(device, Some(image_view), Some(depth_image_view), extent, render_pass),
+    };
+
+    ImageTarget::new(
+        depth_image,
+        depth_image_view,
+        depth_image_memory,
+        multisample_image,
+        framebuffer,
+    )
+}
+
+fn _clear_values(target: &RenderTarget, clear_color: [f32; 4]) -> Vec<vk::ClearValue> {
+    let depth = vk::ClearValue {
+        depth_stencil: vk::ClearDepthStencilValue { depth: 1.0, stencil: 0 },
+    };
+
+    match target {
+        // Depth is loaded from the opaque pass
+        RenderTarget::TransparencyTarget(_) => vec![
+            vk::ClearValue { color: vk::ClearColorValue { float32: [0.0, 0.0, 0.0, 0.0] } },
+            vk::ClearValue { color: vk::ClearColorValue { float32: [1.0, 0.0, 0.0, 0.0] } },
+            depth,
+        ],
+        _ => vec![
+            vk::ClearValue { color: vk::ClearColorValue { float32: clear_color } },
+            depth,
+        ],
+    }
+}
+
+fn _get_pass_mut<'a>(
+    swapchain_pass: &'a mut Option<RenderPass>,
+    render_passes: &'a mut HashMap<RenderPassHandle, RenderPass>,
+    handle: RenderPassHandle,
+) -> &'a mut RenderPass {
+    if handle == SWAPCHAIN_PASS {
+        swapchain_pass.as_mut().expect("No swapchain pass")
+    } else {
+        render_passes.get_mut(&handle).expect("Invalid render pass handle")
     }
 }
 
-fn create_swapchain_render_pass(device: &Device, color_format: vk::Format, depth_format: vk::Format) -> vk::RenderPass {
+// Multisampled passes render into an additional color attachment, resolved into the target at the end of the
+// subpass. The target is then only written by the resolve.
+fn _render_pass_attachments(
+    color_attachment: vk::AttachmentDescription,
+    depth_attachment: vk::AttachmentDescription,
+) -> Vec<vk::AttachmentDescription> {
+    if depth_attachment.samples == vk::SampleCountFlags::TYPE_1 {
+        return vec![color_attachment, depth_attachment];
+    }
+
+    let multisample_attachment = vk::AttachmentDescription {
+        samples: depth_attachment.samples,
+        store_op: vk::AttachmentStoreOp::DONT_CARE,
+        initial_layout: vk::ImageLayout::UNDEFINED,
+        final_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        ..color_attachment
+    };
+    let resolve_attachment = vk::AttachmentDescription {
+        load_op: vk::AttachmentLoadOp::DONT_CARE,
+        ..color_attachment
+    };
+
+    vec![multisample_attachment, depth_attachment, resolve_attachment]
+}
+
+fn create_swapchain_render_pass(
+    device: &Device,
+    color_format: vk::Format,
+    depth_format: vk::Format,
+    samples: vk::SampleCountFlags,
+) -> vk::RenderPass {
     let color_attachment = vk::AttachmentDescription {
         flags: vk::AttachmentDescriptionFlags::empty(),
         format: color_format,
@@ -563,7 +1460,7 @@ fn create_swapchain_render_pass(device: &Device, color_format: vk::Format, depth
     let depth_attachment = vk::AttachmentDescription {
         flags: vk::AttachmentDescriptionFlags::empty(),
         format: depth_format,
-        samples: vk::SampleCountFlags::TYPE_1,
+        samples,
         load_op: vk::AttachmentLoadOp::CLEAR,
         store_op: vk::AttachmentStoreOp::DONT_CARE,
         stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
@@ -581,6 +1478,10 @@ fn create_swapchain_render_pass(device: &Device, color_format: vk::Format, depth
         .attachment(1)
         .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
         .build();
+    let resolve_attachment_ref = vk::AttachmentReference::builder()
+        .attachment(2)
+        .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+        .build();
 
     let subpass = vk::SubpassDescription {
         flags: vk::SubpassDescriptionFlags::empty(),
@@ -589,13 +1490,17 @@ fn create_swapchain_render_pass(device: &Device, color_format: vk::Format, depth
         p_input_attachments: ptr::null(),
         color_attachment_count: 1,
         p_color_attachments: &color_attachment_ref,
-        p_resolve_attachments: ptr::null(),
+        p_resolve_attachments: if samples == vk::SampleCountFlags::TYPE_1 {
+            ptr::null()
+        } else {
+            &resolve_attachment_ref
+        },
         p_depth_stencil_attachment: &depth_attachment_ref,
         preserve_attachment_count: 0,
         p_preserve_attachments: ptr::null(),
     };
 
-    let render_pass_attachments = [color_attachment, depth_attachment];
+    let render_pass_attachments = _render_pass_attachments(color_attachment, depth_attachment);
 
     let subpass_dependencies = [vk::SubpassDependency {
         src_subpass: vk::SUBPASS_EXTERNAL,
@@ -626,7 +1531,13 @@ fn create_swapchain_render_pass(device: &Device, color_format: vk::Format, depth
     }
 }
 
-fn create_imagetarget_render_pass(device: &Device, color_format: vk::Format, depth_format: vk::Format) -> vk::RenderPass {
+fn create_imagetarget_render_pass(
+    device: &Device,
+    color_format: vk::Format,
+    depth_format: vk::Format,
+    view_count: u32,
+    samples: vk::SampleCountFlags,
+) -> vk::RenderPass {
     let color_attachment = vk::AttachmentDescription {
         flags: vk::AttachmentDescriptionFlags::empty(),
         format: color_format,
@@ -635,16 +1546,17 @@ fn create_imagetarget_render_pass(device: &Device, color_format: vk::Format, dep
         store_op: vk::AttachmentStoreOp::STORE,
         stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
         stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
-        initial_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-        final_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        initial_layout: RENDER_TEXTURE_LAYOUT,
+        final_layout: RENDER_TEXTURE_LAYOUT,
     };
 
     let depth_attachment = vk::AttachmentDescription {
         flags: vk::AttachmentDescriptionFlags::empty(),
         format: depth_format,
-        samples: vk::SampleCountFlags::TYPE_1,
+        samples,
         load_op: vk::AttachmentLoadOp::CLEAR,
-        store_op: vk::AttachmentStoreOp::DONT_CARE,
+        // Kept for transparency passes sharing the depth buffer
+        store_op: vk::AttachmentStoreOp::STORE,
         stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
         stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
         initial_layout: vk::ImageLayout::UNDEFINED,
@@ -660,6 +1572,10 @@ fn create_imagetarget_render_pass(device: &Device, color_format: vk::Format, dep
         .attachment(1)
         .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
         .build();
+    let resolve_attachment_ref = vk::AttachmentReference::builder()
+        .attachment(2)
+        .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+        .build();
 
     let subpass = vk::SubpassDescription {
         flags: vk::SubpassDescriptionFlags::empty(),
@@ -668,13 +1584,17 @@ fn create_imagetarget_render_pass(device: &Device, color_format: vk::Format, dep
         p_input_attachments: ptr::null(),
         color_attachment_count: 1,
         p_color_attachments: &color_attachment_ref,
-        p_resolve_attachments: ptr::null(),
+        p_resolve_attachments: if samples == vk::SampleCountFlags::TYPE_1 {
+            ptr::null()
+        } else {
+            &resolve_attachment_ref
+        },
         p_depth_stencil_attachment: &depth_attachment_ref,
         preserve_attachment_count: 0,
         p_preserve_attachments: ptr::null(),
     };
 
-    let render_pass_attachments = [color_attachment, depth_attachment];
+    let render_pass_attachments = _render_pass_attachments(color_attachment, depth_attachment);
 
     let subpass_dependencies = [
         vk::SubpassDependency {
@@ -697,6 +1617,118 @@ fn create_imagetarget_render_pass(device: &Device, color_format: vk::Format, dep
         }
     ];
 
+    // Every view renders into its own layer, views are assumed to be spatially correlated (e.g. two eyes)
+    let view_masks = [(1_u32 << view_count) - 1];
+    let multiview_create_info = vk::RenderPassMultiviewCreateInfo::builder()
+        .view_masks(&view_masks)
+        .correlation_masks(&view_masks)
+        .build();
+
+    let renderpass_create_info = vk::RenderPassCreateInfo {
+        s_type: vk::StructureType::RENDER_PASS_CREATE_INFO,
+        flags: vk::RenderPassCreateFlags::empty(),
+        p_next: if view_count > 1 {
+            &multiview_create_info as *const vk::RenderPassMultiviewCreateInfo as *const std::ffi::c_void
+        } else {
+            ptr::null()
+        },
+        attachment_count: render_pass_attachments.len() as u32,
+        p_attachments: render_pass_attachments.as_ptr(),
+        subpass_count: 1,
+        p_subpasses: &subpass,
+        dependency_count: subpass_dependencies.len() as u32,
+        p_dependencies: subpass_dependencies.as_ptr(),
+    };
+
+    unsafe {
+        device
+            .create_render_pass(&renderpass_create_info, None)
+            .expect("Failed to create render pass!")
+    }
+}
+
+fn create_transparency_render_pass(device: &Device, depth_format: vk::Format) -> vk::RenderPass {
+    let accumulation_attachment = vk::AttachmentDescription {
+        flags: vk::AttachmentDescriptionFlags::empty(),
+        format: OIT_ACCUMULATION_FORMAT,
+        samples: vk::SampleCountFlags::TYPE_1,
+        load_op: vk::AttachmentLoadOp::CLEAR,
+        store_op: vk::AttachmentStoreOp::STORE,
+        stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+        stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+        initial_layout: RENDER_TEXTURE_LAYOUT,
+        final_layout: RENDER_TEXTURE_LAYOUT,
+    };
+
+    let revealage_attachment = vk::AttachmentDescription {
+        format: OIT_REVEALAGE_FORMAT,
+        ..accumulation_attachment
+    };
+
+    // Written by the opaque pass, only tested against
+    let depth_attachment = vk::AttachmentDescription {
+        flags: vk::AttachmentDescriptionFlags::empty(),
+        format: depth_format,
+        samples: vk::SampleCountFlags::TYPE_1,
+        load_op: vk::AttachmentLoadOp::LOAD,
+        store_op: vk::AttachmentStoreOp::DONT_CARE,
+        stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+        stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+        initial_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        final_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+    };
+
+    let color_attachment_refs = [
+        vk::AttachmentReference::builder()
+            .attachment(0)
+            .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .build(),
+        vk::AttachmentReference::builder()
+            .attachment(1)
+            .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .build(),
+    ];
+    let depth_attachment_ref = vk::AttachmentReference::builder()
+        .attachment(2)
+        .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+        .build();
+
+    let subpass = vk::SubpassDescription {
+        flags: vk::SubpassDescriptionFlags::empty(),
+        pipeline_bind_point: vk::PipelineBindPoint::GRAPHICS,
+        input_attachment_count: 0,
+        p_input_attachments: ptr::null(),
+        color_attachment_count: color_attachment_refs.len() as u32,
+        p_color_attachments: color_attachment_refs.as_ptr(),
+        p_resolve_attachments: ptr::null(),
+        p_depth_stencil_attachment: &depth_attachment_ref,
+        preserve_attachment_count: 0,
+        p_preserve_attachments: ptr::null(),
+    };
+
+    let render_pass_attachments = [accumulation_attachment, revealage_attachment, depth_attachment];
+
+    let subpass_dependencies = [
+        vk::SubpassDependency {
+            src_subpass: vk::SUBPASS_EXTERNAL,
+            dst_subpass: 0,
+            src_stage_mask: vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+            src_access_mask: vk::AccessFlags::SHADER_READ | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ,
+            dependency_flags: vk::DependencyFlags::BY_REGION,
+        },
+        vk::SubpassDependency {
+            src_subpass: 0,
+            dst_subpass: vk::SUBPASS_EXTERNAL,
+            src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            dst_stage_mask: vk::PipelineStageFlags::FRAGMENT_SHADER,
+            src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            dst_access_mask: vk::AccessFlags::SHADER_READ,
+            dependency_flags: vk::DependencyFlags::BY_REGION,
+        }
+    ];
+
     let renderpass_create_info = vk::RenderPassCreateInfo {
         s_type: vk::StructureType::RENDER_PASS_CREATE_INFO,
         flags: vk::RenderPassCreateFlags::empty(),
@@ -715,3 +1747,17 @@ fn create_imagetarget_render_pass(device: &Device, color_format: vk::Format, dep
             .expect("Failed to create render pass!")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instance_range_validation() {
+        assert_eq!(_valid_instance_range(0, 10, 10), Some((0, 10)));
+        assert_eq!(_valid_instance_range(4, 10, 10), Some((4, 6)));
+        assert_eq!(_valid_instance_range(10, 1, 10), None);
+        assert_eq!(_valid_instance_range(0, 0, 10), None);
+        assert_eq!(_valid_instance_range(u32::MAX, 2, 10), None);
+    }
+}
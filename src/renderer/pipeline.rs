@@ -1,18 +1,19 @@
 use std::ffi::CString;
 use std::ptr;
+use std::thread;
+use std::time::Instant;
 
+use ash::extensions::khr::PushDescriptor;
 use ash::vk;
-use ash::vk::{
-    DescriptorPoolCreateFlags, DescriptorType, PrimitiveTopology, ShaderStageFlags, VertexInputAttributeDescription,
-    VertexInputBindingDescription,
-};
+use ash::vk::{PrimitiveTopology, ShaderStageFlags, VertexInputAttributeDescription, VertexInputBindingDescription};
 
-use crate::renderer::constants::{MAX_FRAMES_IN_FLIGHT, SAMPLER_DESCRIPTOR_POOL_SIZE, UNIFORM_DESCRIPTOR_POOL_SIZE};
+use crate::renderer::descriptor::{DescriptorAllocation, DescriptorAllocator, DescriptorCounts};
 use crate::renderer::stats::DrawCommandStats;
-use crate::renderer::types::DrawData::Buffered;
-use crate::renderer::types::VertexInputDescription;
+use crate::renderer::types::DrawData::{Buffered, Dynamic};
+use crate::renderer::vertex::VertexLayout;
 use crate::renderer::types::{
-    BufferObjectBindingConfiguration, DrawCommand, SamplerBindingConfiguration, UniformStage,
+    BlendMode, BufferObjectBindingConfiguration, ColorWriteMask, DrawCommand, SamplerBindingConfiguration,
+    StorageWindow, TextureHandle, UniformStage, Viewport, MAX_VERTEX_BINDINGS,
 };
 
 const SHADER_ENTRYPOINT: &str = "main";
@@ -32,51 +33,85 @@ pub struct PipelineContainer {
     vertex_uniform_cfg: Option<BufferObjectBindingConfiguration>,
     fragment_uniform_cfg: Option<BufferObjectBindingConfiguration>,
     storage_buffer_cfg: Option<BufferObjectBindingConfiguration>,
+    frame_globals_cfg: Option<BufferObjectBindingConfiguration>,
     vertex_uniform_buffers: Vec<vk::Buffer>,
     fragment_uniform_buffers: Vec<vk::Buffer>,
     storage_buffers: Vec<vk::Buffer>,
+    // Buffer object behind the storage buffers, indexed by the instance in the vertex shader, and the part of it
+    // one dynamic offset reaches
+    storage_buffer_object: Option<(usize, StorageWindow)>,
+    frame_globals_buffers: Vec<vk::Buffer>,
     sampler_cfgs: Vec<SamplerBindingConfiguration>,
 
     push_constant_buffer_size: Option<usize>,
     vertex_topology: vk::PrimitiveTopology,
 
-    descriptor_pool: vk::DescriptorPool,
+    descriptor_allocation: Option<DescriptorAllocation>,
     descriptor_sets: Vec<vk::DescriptorSet>,
+    stale_descriptor_sets: Vec<bool>,
     descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_counts: DescriptorCounts,
+    pushed_texture_binding: Option<u8>,
+    push_descriptor_set_layout: Option<vk::DescriptorSetLayout>,
+    // Layout and set of the TextureManager, bound after the pushed texture set
+    bindless_set: Option<(vk::DescriptorSetLayout, vk::DescriptorSet)>,
 
     vertex_attribute_descriptions: Vec<VertexInputAttributeDescription>,
     vertex_binding_descriptions: Vec<VertexInputBindingDescription>,
 
     // Configuration
-    alpha_blending: bool,
+    name: Option<String>,
+    blend_mode: BlendMode,
+    depth_test: bool,
+    color_write_mask: vk::ColorComponentFlags,
+    // Of the render pass, see RenderPass::add_pipeline
+    sample_count: vk::SampleCountFlags,
+}
+
+// Descriptor bindings of a pipeline, resolved by the RenderPassManager from the buffer objects and textures of its
+// configuration
+pub(super) struct PipelineBindings {
+    pub(super) vertex_uniform_cfg: Option<BufferObjectBindingConfiguration>,
+    pub(super) fragment_uniform_cfg: Option<BufferObjectBindingConfiguration>,
+    pub(super) storage_buffer_cfg: Option<BufferObjectBindingConfiguration>,
+    pub(super) frame_globals_cfg: Option<BufferObjectBindingConfiguration>,
+    pub(super) sampler_cfgs: Vec<SamplerBindingConfiguration>,
+    pub(super) pushed_texture_binding: Option<u8>,
+    pub(super) bindless_set: Option<(vk::DescriptorSetLayout, vk::DescriptorSet)>,
 }
 
 impl PipelineContainer {
-    pub(super) fn new<T: VertexInputDescription>(
+    pub(super) fn new(
         logical_device: &ash::Device,
         vertex_shader_code: Vec<u8>,
         fragment_shader_code: Vec<u8>,
-        vertex_uniform_cfg: Option<BufferObjectBindingConfiguration>,
-        fragment_uniform_cfg: Option<BufferObjectBindingConfiguration>,
-        storage_buffer_cfg: Option<BufferObjectBindingConfiguration>,
-        sampler_cfgs: Vec<SamplerBindingConfiguration>,
+        bindings: PipelineBindings,
         vertex_topology: PrimitiveTopology,
         push_constant_buffer_size: Option<usize>,
-        alpha_blending: bool,
+        blend_mode: BlendMode,
     ) -> PipelineContainer {
+        let PipelineBindings {
+            vertex_uniform_cfg,
+            fragment_uniform_cfg,
+            storage_buffer_cfg,
+            frame_globals_cfg,
+            sampler_cfgs,
+            pushed_texture_binding,
+            bindless_set,
+        } = bindings;
         let vertex_shader = create_shader_module(logical_device, &vertex_shader_code);
         let fragment_shader = create_shader_module(logical_device, &fragment_shader_code);
 
-        let descriptor_set_layout = create_descriptor_set_layout(
+        let (descriptor_set_layout, descriptor_counts) = create_descriptor_set_layout(
             logical_device,
             vertex_uniform_cfg.as_ref(),
             fragment_uniform_cfg.as_ref(),
             storage_buffer_cfg.as_ref(),
+            frame_globals_cfg.as_ref(),
             &sampler_cfgs,
         );
-
-        let vertex_attribute_descriptions = T::attribute_descriptions();
-        let vertex_binding_descriptions = T::binding_descriptions();
+        let push_descriptor_set_layout =
+            pushed_texture_binding.map(|binding| create_push_descriptor_set_layout(logical_device, binding));
 
         PipelineContainer {
             is_built: false,
@@ -87,31 +122,86 @@ impl PipelineContainer {
             vertex_uniform_cfg,
             fragment_uniform_cfg,
             storage_buffer_cfg,
+            frame_globals_cfg,
             vertex_uniform_buffers: Vec::new(),
             fragment_uniform_buffers: Vec::new(),
             storage_buffers: Vec::new(),
+            storage_buffer_object: None,
+            frame_globals_buffers: Vec::new(),
             sampler_cfgs,
             push_constant_buffer_size,
             vertex_topology,
 
-            descriptor_pool: vk::DescriptorPool::null(),
+            descriptor_allocation: None,
             descriptor_sets: Vec::with_capacity(0),
+            stale_descriptor_sets: Vec::with_capacity(0),
             descriptor_set_layout,
-
-            vertex_attribute_descriptions,
-            vertex_binding_descriptions,
-            alpha_blending,
+            descriptor_counts,
+            pushed_texture_binding,
+            push_descriptor_set_layout,
+            bindless_set,
+
+            vertex_attribute_descriptions: Vec::new(),
+            vertex_binding_descriptions: Vec::new(),
+            name: None,
+            blend_mode,
+            depth_test: true,
+            color_write_mask: vk::ColorComponentFlags::RGBA,
+            sample_count: vk::SampleCountFlags::TYPE_1,
         }
     }
 
+    pub(super) fn set_vertex_layout(&mut self, vertex_layout: &VertexLayout) {
+        assert!(!self.is_built);
+        let vertex_binding_descriptions = vertex_layout.binding_descriptions();
+        assert!(vertex_binding_descriptions.len() <= MAX_VERTEX_BINDINGS, "Too many vertex bindings");
+
+        self.vertex_attribute_descriptions = vertex_layout.attribute_descriptions();
+        self.vertex_binding_descriptions = vertex_binding_descriptions;
+    }
+
+    pub(super) fn set_name(&mut self, name: Option<String>) {
+        self.name = name;
+    }
+
+    pub(super) fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub(super) fn set_depth_test(&mut self, depth_test: bool) {
+        assert!(!self.is_built);
+        self.depth_test = depth_test;
+    }
+
+    pub(super) fn set_color_write_mask(&mut self, color_write_mask: ColorWriteMask) {
+        assert!(!self.is_built);
+        self.color_write_mask = _to_vk_color_write_mask(color_write_mask);
+    }
+
+    pub(super) fn set_sample_count(&mut self, sample_count: vk::SampleCountFlags) {
+        assert!(!self.is_built);
+        self.sample_count = sample_count;
+    }
+
     pub fn build(
         &mut self,
         logical_device: &ash::Device,
         render_pass: vk::RenderPass,
-        swapchain_extent: vk::Extent2D,
         image_count: usize,
+        descriptor_allocator: &mut DescriptorAllocator,
     ) {
         assert!(!self.is_built);
+        let (vk_pipeline, layout) = self.create_vk_pipeline(logical_device, render_pass);
+        self.finish_build(logical_device, vk_pipeline, layout, image_count, descriptor_allocator);
+    }
+
+    pub(super) fn is_built(&self) -> bool {
+        self.is_built
+    }
+
+    // Only reads the configuration, so it can run on any thread. Pipeline creation does not need external
+    // synchronization as long as no pipeline cache is shared between the threads.
+    fn create_vk_pipeline(&self, logical_device: &ash::Device, render_pass: vk::RenderPass) -> (vk::Pipeline, vk::PipelineLayout) {
         let main_function_name = CString::new(SHADER_ENTRYPOINT).unwrap();
 
         let shader_stages = [
@@ -153,30 +243,22 @@ impl PipelineContainer {
             .primitive_restart_enable(self.vertex_topology == PrimitiveTopology::TRIANGLE_STRIP)
             .build();
 
-        let viewports = [vk::Viewport {
-            x: 0.0,
-            y: swapchain_extent.height as f32,
-            width: swapchain_extent.width as f32,
-            height: -(swapchain_extent.height as f32),
-            min_depth: 0.0,
-            max_depth: 1.0,
-        }];
-
-        let scissors = [vk::Rect2D {
-            offset: vk::Offset2D { x: 0, y: 0 },
-            extent: swapchain_extent,
-        }];
-
+        // Viewport and scissor are set by the render pass while baking, see set_viewport
         let viewport_state_create_info = vk::PipelineViewportStateCreateInfo {
             s_type: vk::StructureType::PIPELINE_VIEWPORT_STATE_CREATE_INFO,
             p_next: ptr::null(),
             flags: vk::PipelineViewportStateCreateFlags::empty(),
-            scissor_count: scissors.len() as u32,
-            p_scissors: scissors.as_ptr(),
-            viewport_count: viewports.len() as u32,
-            p_viewports: viewports.as_ptr(),
+            scissor_count: 1,
+            p_scissors: ptr::null(),
+            viewport_count: 1,
+            p_viewports: ptr::null(),
         };
 
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR, vk::DynamicState::BLEND_CONSTANTS];
+        let dynamic_state_create_info = vk::PipelineDynamicStateCreateInfo::builder()
+            .dynamic_states(&dynamic_states)
+            .build();
+
         let rasterization_statue_create_info = vk::PipelineRasterizationStateCreateInfo::builder()
             .depth_clamp_enable(false)
             .cull_mode(vk::CullModeFlags::BACK)
@@ -194,7 +276,7 @@ impl PipelineContainer {
             s_type: vk::StructureType::PIPELINE_MULTISAMPLE_STATE_CREATE_INFO,
             flags: vk::PipelineMultisampleStateCreateFlags::empty(),
             p_next: ptr::null(),
-            rasterization_samples: vk::SampleCountFlags::TYPE_1,
+            rasterization_samples: self.sample_count,
             sample_shading_enable: vk::FALSE,
             min_sample_shading: 0.0,
             p_sample_mask: ptr::null(),
@@ -216,8 +298,8 @@ impl PipelineContainer {
             s_type: vk::StructureType::PIPELINE_DEPTH_STENCIL_STATE_CREATE_INFO,
             p_next: ptr::null(),
             flags: vk::PipelineDepthStencilStateCreateFlags::empty(),
-            depth_test_enable: vk::TRUE,
-            depth_write_enable: vk::TRUE,
+            depth_test_enable: if self.depth_test { vk::TRUE } else { vk::FALSE },
+            depth_write_enable: if self.depth_test && self.blend_mode.writes_depth() { vk::TRUE } else { vk::FALSE },
             depth_compare_op: vk::CompareOp::LESS_OR_EQUAL,
             depth_bounds_test_enable: vk::FALSE,
             stencil_test_enable: vk::FALSE,
@@ -227,22 +309,67 @@ impl PipelineContainer {
             min_depth_bounds: 0.0,
         };
 
-        let color_blend_attachment_states = if self.alpha_blending {
-            [vk::PipelineColorBlendAttachmentState::builder()
+        let color_blend_attachment_states = match self.blend_mode {
+            BlendMode::Opaque => vec![vk::PipelineColorBlendAttachmentState::builder()
+                .blend_enable(false)
+                .color_write_mask(self.color_write_mask)
+                .build()],
+            BlendMode::AlphaBlending | BlendMode::SortedAlphaBlending => {
+                vec![vk::PipelineColorBlendAttachmentState::builder()
+                    .blend_enable(true)
+                    .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+                    .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+                    .color_blend_op(vk::BlendOp::ADD)
+                    .src_alpha_blend_factor(vk::BlendFactor::ONE)
+                    .dst_alpha_blend_factor(vk::BlendFactor::ZERO)
+                    .alpha_blend_op(vk::BlendOp::ADD)
+                    .color_write_mask(self.color_write_mask)
+                    .build()]
+            }
+            BlendMode::ConstantBlending => vec![vk::PipelineColorBlendAttachmentState::builder()
+                .blend_enable(true)
+                .src_color_blend_factor(vk::BlendFactor::CONSTANT_COLOR)
+                .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_CONSTANT_COLOR)
+                .color_blend_op(vk::BlendOp::ADD)
+                .src_alpha_blend_factor(vk::BlendFactor::CONSTANT_ALPHA)
+                .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_CONSTANT_ALPHA)
+                .alpha_blend_op(vk::BlendOp::ADD)
+                .color_write_mask(self.color_write_mask)
+                .build()],
+            // The second output of the fragment shader is the coverage per channel
+            BlendMode::DualSourceBlending => vec![vk::PipelineColorBlendAttachmentState::builder()
                 .blend_enable(true)
-                .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
-                .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+                .src_color_blend_factor(vk::BlendFactor::ONE)
+                .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC1_COLOR)
                 .color_blend_op(vk::BlendOp::ADD)
                 .src_alpha_blend_factor(vk::BlendFactor::ONE)
-                .dst_alpha_blend_factor(vk::BlendFactor::ZERO)
+                .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC1_ALPHA)
                 .alpha_blend_op(vk::BlendOp::ADD)
-                .color_write_mask(vk::ColorComponentFlags::RGBA)
-                .build()]
-        } else {
-            [vk::PipelineColorBlendAttachmentState::builder()
-                .blend_enable(false)
-                .color_write_mask(vk::ColorComponentFlags::RGBA)
-                .build()]
+                .color_write_mask(self.color_write_mask)
+                .build()],
+            // Accumulation sums up the weighted colors, revealage multiplies (1 - alpha) of all fragments
+            BlendMode::WeightedBlended => vec![
+                vk::PipelineColorBlendAttachmentState::builder()
+                    .blend_enable(true)
+                    .src_color_blend_factor(vk::BlendFactor::ONE)
+                    .dst_color_blend_factor(vk::BlendFactor::ONE)
+                    .color_blend_op(vk::BlendOp::ADD)
+                    .src_alpha_blend_factor(vk::BlendFactor::ONE)
+                    .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+                    .alpha_blend_op(vk::BlendOp::ADD)
+                    .color_write_mask(vk::ColorComponentFlags::RGBA)
+                    .build(),
+                vk::PipelineColorBlendAttachmentState::builder()
+                    .blend_enable(true)
+                    .src_color_blend_factor(vk::BlendFactor::ZERO)
+                    .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_COLOR)
+                    .color_blend_op(vk::BlendOp::ADD)
+                    .src_alpha_blend_factor(vk::BlendFactor::ZERO)
+                    .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+                    .alpha_blend_op(vk::BlendOp::ADD)
+                    .color_write_mask(vk::ColorComponentFlags::R)
+                    .build(),
+            ],
         };
 
         let color_blend_state = vk::PipelineColorBlendStateCreateInfo {
@@ -253,15 +380,22 @@ impl PipelineContainer {
             logic_op: vk::LogicOp::COPY,
             attachment_count: color_blend_attachment_states.len() as u32,
             p_attachments: color_blend_attachment_states.as_ptr(),
+            // Dynamic like the viewport, see set_blend_constants
             blend_constants: [0.0, 0.0, 0.0, 0.0],
         };
 
-        let set_layouts = [self.descriptor_set_layout];
+        let mut set_layouts = vec![self.descriptor_set_layout];
+        if let Some(push_descriptor_set_layout) = self.push_descriptor_set_layout {
+            set_layouts.push(push_descriptor_set_layout);
+        }
+        if let Some((bindless_set_layout, _)) = self.bindless_set {
+            set_layouts.push(bindless_set_layout);
+        }
         let mut push_constant_ranges = Vec::with_capacity(2);
         if let Some(push_constant_buf_size) = self.push_constant_buffer_size {
             push_constant_ranges.push(
                 vk::PushConstantRange::builder()
-                    .stage_flags(ShaderStageFlags::VERTEX)
+                    .stage_flags(self.push_constant_stages())
                     .size(push_constant_buf_size as u32)
                     .offset(0)
                     .build(),
@@ -294,7 +428,7 @@ impl PipelineContainer {
             p_multisample_state: &multisample_state_create_info,
             p_depth_stencil_state: &depth_state_create_info,
             p_color_blend_state: &color_blend_state,
-            p_dynamic_state: ptr::null(),
+            p_dynamic_state: &dynamic_state_create_info,
             layout: pipeline_layout,
             render_pass,
             subpass: 0,
@@ -308,11 +442,30 @@ impl PipelineContainer {
                 .expect("Failed to create Graphics Pipeline!.")
         };
 
-        self.vk_pipeline = graphics_pipelines[0];
-        self.layout = pipeline_layout;
+        (graphics_pipelines[0], pipeline_layout)
+    }
 
-        self.descriptor_pool = create_descriptor_pool(logical_device);
-        self.descriptor_sets = self.create_descriptor_sets(logical_device, image_count);
+    fn finish_build(
+        &mut self,
+        logical_device: &ash::Device,
+        vk_pipeline: vk::Pipeline,
+        layout: vk::PipelineLayout,
+        image_count: usize,
+        descriptor_allocator: &mut DescriptorAllocator,
+    ) {
+        self.vk_pipeline = vk_pipeline;
+        self.layout = layout;
+
+        let descriptor_allocation = descriptor_allocator.allocate(
+            logical_device,
+            self.descriptor_set_layout,
+            self.descriptor_counts,
+            image_count,
+        );
+        self.descriptor_sets = descriptor_allocation.sets().to_vec();
+        self.descriptor_allocation = Some(descriptor_allocation);
+        self.stale_descriptor_sets = vec![false; image_count];
+        self.write_descriptor_sets(logical_device);
 
         self.is_built = true;
     }
@@ -324,53 +477,120 @@ impl PipelineContainer {
         draw_command: &DrawCommand,
         image_index: usize,
         bind: bool,
+        pushed_texture: Option<(&PushDescriptor, vk::ImageView, vk::Sampler)>,
     ) -> DrawCommandStats {
         if bind {
             logical_device.cmd_bind_pipeline(draw_command_buffer, vk::PipelineBindPoint::GRAPHICS, self.vk_pipeline);
         }
 
-        let offsets = [0_u64];
+        let offsets = [0_u64; MAX_VERTEX_BINDINGS];
         let descriptor_sets_to_bind = [self.descriptor_sets[image_index]];
 
         if let Some(push_constant_buf_size) = self.push_constant_buffer_size {
             logical_device.cmd_push_constants(
                 draw_command_buffer,
                 self.layout,
-                ShaderStageFlags::VERTEX,
+                self.push_constant_stages(),
                 0,
                 std::slice::from_raw_parts(draw_command.push_constant_ptr, push_constant_buf_size),
             );
         }
 
+        let storage_offsets = [draw_command.storage_offset];
+        let dynamic_offsets = if self.storage_buffer_cfg.is_some() { &storage_offsets[..] } else { &[] };
         logical_device.cmd_bind_descriptor_sets(
             draw_command_buffer,
             vk::PipelineBindPoint::GRAPHICS,
             self.layout,
             0,
             &descriptor_sets_to_bind,
-            &[],
+            dynamic_offsets,
         );
+        if let Some((_, bindless_set)) = self.bindless_set {
+            logical_device.cmd_bind_descriptor_sets(
+                draw_command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.layout,
+                1 + self.push_descriptor_set_layout.is_some() as u32,
+                &[bindless_set],
+                &[],
+            );
+        }
+
+        if let (Some(binding), Some((push_descriptor, image_view, sampler))) = (self.pushed_texture_binding, pushed_texture) {
+            let image_infos = [vk::DescriptorImageInfo {
+                sampler,
+                image_view,
+                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            }];
+            let descriptor_writes = [vk::WriteDescriptorSet::builder()
+                .dst_binding(binding as u32)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .image_info(&image_infos)
+                .build()];
+            push_descriptor.cmd_push_descriptor_set(
+                draw_command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.layout,
+                1,
+                &descriptor_writes,
+            );
+        }
+
+        debug_assert_eq!(
+            draw_command.vertex_buffers.as_slice().len(),
+            self.vertex_binding_descriptions.len(),
+            "Vertex buffers of the draw command don't match the bindings of the pipeline"
+        );
+        // Binding 0 of dynamic draws is bound by the render pass, see RenderPass::bake_command_buffer
+        let first_binding = match draw_command.vertex_data {
+            Buffered(_) => 0,
+            Dynamic(_) => 1,
+        };
+        let vertex_buffers = &draw_command.vertex_buffers.as_slice()[first_binding..];
+        if !vertex_buffers.is_empty() {
+            logical_device.cmd_bind_vertex_buffers(
+                draw_command_buffer,
+                first_binding as u32,
+                vertex_buffers,
+                &offsets[..vertex_buffers.len()],
+            );
+        }
 
-        #[allow(irrefutable_let_patterns)]
         if let Buffered(buffer_data) = &draw_command.vertex_data {
-            let vertex_buffers = [buffer_data.vertex_buffer];
-            logical_device.cmd_bind_vertex_buffers(draw_command_buffer, 0, &vertex_buffers, &offsets);
             logical_device.cmd_bind_index_buffer(
                 draw_command_buffer,
                 buffer_data.index_buffer,
                 0,
                 vk::IndexType::UINT32,
             );
-            logical_device.cmd_draw_indexed(
+            if let Some(indirect_buffer) = buffer_data.indirect_buffer {
+                logical_device.cmd_draw_indexed_indirect(
+                    draw_command_buffer,
+                    indirect_buffer,
+                    0,
+                    1,
+                    std::mem::size_of::<vk::DrawIndexedIndirectCommand>() as u32,
+                );
+            } else {
+                logical_device.cmd_draw_indexed(
+                    draw_command_buffer,
+                    buffer_data.index_count,
+                    buffer_data.instance_count,
+                    0,
+                    0,
+                    buffer_data.instance_start,
+                );
+            }
+        } else if let Dynamic(dynamic_data) = &draw_command.vertex_data {
+            logical_device.cmd_draw(
                 draw_command_buffer,
-                buffer_data.index_count,
-                buffer_data.instance_count,
-                0,
-                0,
-                buffer_data.instance_start,
+                dynamic_data.vertex_count,
+                dynamic_data.instance_count,
+                dynamic_data.vertex_start,
+                dynamic_data.instance_start,
             );
-        } else {
-            unreachable!()
         }
 
         // Stats
@@ -379,6 +599,55 @@ impl PipelineContainer {
         DrawCommandStats::new(triangle_count)
     }
 
+    pub unsafe fn set_blend_constants(
+        logical_device: &ash::Device,
+        draw_command_buffer: vk::CommandBuffer,
+        blend_constants: [f32; 4],
+    ) {
+        logical_device.cmd_set_blend_constants(draw_command_buffer, &blend_constants);
+    }
+
+    pub unsafe fn set_viewport(logical_device: &ash::Device, draw_command_buffer: vk::CommandBuffer, viewport: Viewport) {
+        // Flipped so that y points up in clip space
+        let viewports = [vk::Viewport {
+            x: viewport.x as f32,
+            y: (viewport.y + viewport.height) as f32,
+            width: viewport.width as f32,
+            height: -(viewport.height as f32),
+            min_depth: 0.0,
+            max_depth: 1.0,
+        }];
+        let scissors = [vk::Rect2D {
+            offset: vk::Offset2D {
+                x: viewport.x as i32,
+                y: viewport.y as i32,
+            },
+            extent: vk::Extent2D {
+                width: viewport.width,
+                height: viewport.height,
+            },
+        }];
+
+        logical_device.cmd_set_viewport(draw_command_buffer, 0, &viewports);
+        logical_device.cmd_set_scissor(draw_command_buffer, 0, &scissors);
+    }
+
+    pub(super) fn is_sorted(&self) -> bool {
+        self.blend_mode == BlendMode::SortedAlphaBlending
+    }
+
+    pub(super) fn push_constant_buffer_size(&self) -> Option<usize> {
+        self.push_constant_buffer_size
+    }
+
+    // The fragment shaders of bindless pipelines read the texture indices from the push constants
+    fn push_constant_stages(&self) -> ShaderStageFlags {
+        match self.bindless_set {
+            Some(_) => ShaderStageFlags::VERTEX | ShaderStageFlags::FRAGMENT,
+            None => ShaderStageFlags::VERTEX,
+        }
+    }
+
     pub(super) fn set_uniform_buffers(&mut self, stage: UniformStage, buffers: &[vk::Buffer]) {
         match stage {
             UniformStage::Vertex => {
@@ -396,6 +665,13 @@ impl PipelineContainer {
         }
     }
 
+    pub(super) fn rebind_uniform_buffers(&mut self, stage: UniformStage, buffers: &[vk::Buffer]) {
+        self.set_uniform_buffers(stage, buffers);
+
+        // Sets may still be in use by frames in flight, they are rewritten when their image comes around again.
+        self.stale_descriptor_sets.iter_mut().for_each(|stale| *stale = true);
+    }
+
     pub(super) fn set_storage_buffers(&mut self, buffers: &[vk::Buffer]) {
         self.storage_buffers.clear();
         for buf in buffers {
@@ -403,120 +679,184 @@ impl PipelineContainer {
         }
     }
 
+    pub(super) fn set_storage_buffer_object(&mut self, buffer_object: usize, window: StorageWindow) {
+        self.storage_buffer_object = Some((buffer_object, window));
+    }
+
+    pub(super) fn storage_buffer_object(&self) -> Option<(usize, StorageWindow)> {
+        self.storage_buffer_object
+    }
+
+    pub(super) fn set_frame_globals_buffers(&mut self, buffers: &[vk::Buffer]) {
+        self.frame_globals_buffers = buffers.to_vec();
+    }
+
     pub(super) fn update_storage_buffer(&mut self, new_buffers: &[vk::Buffer], new_capacity: usize) {
         let binding = self.storage_buffer_cfg.unwrap().binding;
         self.storage_buffer_cfg = Some(BufferObjectBindingConfiguration::new(binding, new_capacity));
         self.storage_buffers = new_buffers.to_vec();
     }
 
-    fn create_descriptor_sets(&mut self, device: &ash::Device, swapchain_images_size: usize) -> Vec<vk::DescriptorSet> {
-        let mut layouts: Vec<vk::DescriptorSetLayout> = vec![];
-        for _ in 0..swapchain_images_size {
-            layouts.push(self.descriptor_set_layout);
+    pub(super) fn sampled_textures(&self) -> Vec<(u8, TextureHandle)> {
+        self.sampler_cfgs.iter().map(|cfg| (cfg.binding, cfg.texture)).collect()
+    }
+
+    pub(super) fn set_texture(
+        &mut self,
+        binding: u8,
+        texture: TextureHandle,
+        image: vk::ImageView,
+        sampler: vk::Sampler,
+    ) -> Result<(), &'static str> {
+        let cfg = self
+            .sampler_cfgs
+            .iter_mut()
+            .find(|cfg| cfg.binding == binding)
+            .ok_or("Pipeline has no texture at binding")?;
+        *cfg = SamplerBindingConfiguration::new(binding, texture, image, sampler);
+
+        // Sets may still be in use by frames in flight, they are rewritten when their image comes around again.
+        self.stale_descriptor_sets.iter_mut().for_each(|stale| *stale = true);
+
+        Ok(())
+    }
+
+    // The old view is destroyed right after, the caller has waited for the device to be idle. Built pipelines
+    // rewrite their descriptor sets before the next frame, destroyed ones pick up the view when they are rebuilt.
+    pub(super) fn replace_texture_view(&mut self, texture: TextureHandle, image: vk::ImageView) {
+        let mut replaced = false;
+        for cfg in self.sampler_cfgs.iter_mut().filter(|cfg| cfg.texture == texture) {
+            cfg.image = image;
+            replaced = true;
         }
-        let descriptor_set_allocate_info = vk::DescriptorSetAllocateInfo {
-            s_type: vk::StructureType::DESCRIPTOR_SET_ALLOCATE_INFO,
-            p_next: ptr::null(),
-            descriptor_pool: self.descriptor_pool,
-            descriptor_set_count: swapchain_images_size as u32,
-            p_set_layouts: layouts.as_ptr(),
-        };
 
-        let descriptor_sets = unsafe {
-            device
-                .allocate_descriptor_sets(&descriptor_set_allocate_info)
-                .expect("Failed to allocate descriptor sets!")
-        };
+        if replaced {
+            self.stale_descriptor_sets.iter_mut().for_each(|stale| *stale = true);
+        }
+    }
 
-        for (i, &descriptor_set) in descriptor_sets.iter().enumerate() {
-            let mut descriptor_write_sets = Vec::new();
-
-            // This needs to be stored here so they are not deleted before the vulkan call
-            let mut vertex_descriptor_buffer_infos = Vec::new();
-            let mut fragment_descriptor_buffer_infos = Vec::new();
-            let mut storage_descriptor_buffer_infos = Vec::new();
-            let mut descriptor_image_infos = Vec::new();
-
-            if let Some(cfg) = self.vertex_uniform_cfg {
-                vertex_descriptor_buffer_infos.push(vk::DescriptorBufferInfo {
-                    buffer: self.vertex_uniform_buffers[i],
-                    offset: 0,
-                    range: cfg.size as u64,
-                });
-                descriptor_write_sets.push(
-                    vk::WriteDescriptorSet::builder()
-                        .dst_set(descriptor_set)
-                        .dst_binding(cfg.binding as u32)
-                        .dst_array_element(0)
-                        .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
-                        .buffer_info(&vertex_descriptor_buffer_infos)
-                        .build(),
-                );
-            }
+    pub(super) fn update_stale_descriptor_set(&mut self, device: &ash::Device, image_index: usize) {
+        if self.stale_descriptor_sets.get(image_index).copied().unwrap_or(false) {
+            self.write_descriptor_set(device, image_index);
+            self.stale_descriptor_sets[image_index] = false;
+        }
+    }
 
-            if let Some(cfg) = self.storage_buffer_cfg {
-                storage_descriptor_buffer_infos.push(vk::DescriptorBufferInfo {
-                    buffer: self.storage_buffers[i],
-                    offset: 0,
-                    range: cfg.size as u64,
-                });
-                descriptor_write_sets.push(
-                    vk::WriteDescriptorSet::builder()
-                        .dst_set(descriptor_set)
-                        .dst_binding(cfg.binding as u32)
-                        .dst_array_element(0)
-                        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
-                        .buffer_info(&storage_descriptor_buffer_infos)
-                        .build(),
-                );
-            }
+    fn write_descriptor_sets(&self, device: &ash::Device) {
+        for image_index in 0..self.descriptor_sets.len() {
+            self.write_descriptor_set(device, image_index);
+        }
+    }
 
-            if let Some(cfg) = self.fragment_uniform_cfg {
-                fragment_descriptor_buffer_infos.push(vk::DescriptorBufferInfo {
-                    buffer: self.fragment_uniform_buffers[i],
-                    offset: 0,
-                    range: cfg.size as u64,
-                });
-                descriptor_write_sets.push(
-                    vk::WriteDescriptorSet::builder()
-                        .dst_set(descriptor_set)
-                        .dst_binding(cfg.binding as u32)
-                        .dst_array_element(0)
-                        .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
-                        .buffer_info(&fragment_descriptor_buffer_infos)
-                        .build(),
-                );
-            }
+    fn write_descriptor_set(&self, device: &ash::Device, i: usize) {
+        let descriptor_set = self.descriptor_sets[i];
+        let mut descriptor_write_sets = Vec::new();
+
+        // This needs to be stored here so they are not deleted before the vulkan call
+        let mut vertex_descriptor_buffer_infos = Vec::new();
+        let mut fragment_descriptor_buffer_infos = Vec::new();
+        let mut storage_descriptor_buffer_infos = Vec::new();
+        let mut frame_globals_descriptor_buffer_infos = Vec::new();
+        let mut descriptor_image_infos = Vec::new();
+
+        if let Some(cfg) = self.vertex_uniform_cfg {
+            vertex_descriptor_buffer_infos.push(vk::DescriptorBufferInfo {
+                buffer: self.vertex_uniform_buffers[i],
+                offset: 0,
+                range: cfg.size as u64,
+            });
+            descriptor_write_sets.push(
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(descriptor_set)
+                    .dst_binding(cfg.binding as u32)
+                    .dst_array_element(0)
+                    .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                    .buffer_info(&vertex_descriptor_buffer_infos)
+                    .build(),
+            );
+        }
 
-            for (i, cfg) in self.sampler_cfgs.iter().enumerate() {
-                let info = vec![vk::DescriptorImageInfo {
-                    sampler: cfg.sampler,
-                    image_view: cfg.image,
-                    image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-                }];
-                descriptor_image_infos.push(info);
-
-                descriptor_write_sets.push(
-                    vk::WriteDescriptorSet::builder()
-                        .dst_set(descriptor_set)
-                        .dst_binding(cfg.binding as u32)
-                        .dst_array_element(0)
-                        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                        .buffer_info(&fragment_descriptor_buffer_infos)
-                        .image_info(&descriptor_image_infos[i])
-                        .build(),
-                );
-            }
+        if let Some(cfg) = self.storage_buffer_cfg {
+            let range = match self.storage_buffer_object {
+                Some((_, window)) => cfg.size.min(window.range()),
+                None => cfg.size,
+            };
+            storage_descriptor_buffer_infos.push(vk::DescriptorBufferInfo {
+                buffer: self.storage_buffers[i],
+                offset: 0,
+                range: range as u64,
+            });
+            descriptor_write_sets.push(
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(descriptor_set)
+                    .dst_binding(cfg.binding as u32)
+                    .dst_array_element(0)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER_DYNAMIC)
+                    .buffer_info(&storage_descriptor_buffer_infos)
+                    .build(),
+            );
+        }
 
-            unsafe {
-                device.update_descriptor_sets(&descriptor_write_sets, &[]);
-            }
+        if let Some(cfg) = self.fragment_uniform_cfg {
+            fragment_descriptor_buffer_infos.push(vk::DescriptorBufferInfo {
+                buffer: self.fragment_uniform_buffers[i],
+                offset: 0,
+                range: cfg.size as u64,
+            });
+            descriptor_write_sets.push(
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(descriptor_set)
+                    .dst_binding(cfg.binding as u32)
+                    .dst_array_element(0)
+                    .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                    .buffer_info(&fragment_descriptor_buffer_infos)
+                    .build(),
+            );
+        }
+
+        if let Some(cfg) = self.frame_globals_cfg {
+            frame_globals_descriptor_buffer_infos.push(vk::DescriptorBufferInfo {
+                buffer: self.frame_globals_buffers[i],
+                offset: 0,
+                range: cfg.size as u64,
+            });
+            descriptor_write_sets.push(
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(descriptor_set)
+                    .dst_binding(cfg.binding as u32)
+                    .dst_array_element(0)
+                    .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                    .buffer_info(&frame_globals_descriptor_buffer_infos)
+                    .build(),
+            );
         }
 
-        descriptor_sets
+        for (i, cfg) in self.sampler_cfgs.iter().enumerate() {
+            let info = vec![vk::DescriptorImageInfo {
+                sampler: cfg.sampler,
+                image_view: cfg.image,
+                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            }];
+            descriptor_image_infos.push(info);
+
+            descriptor_write_sets.push(
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(descriptor_set)
+                    .dst_binding(cfg.binding as u32)
+                    .dst_array_element(0)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .buffer_info(&fragment_descriptor_buffer_infos)
+                    .image_info(&descriptor_image_infos[i])
+                    .build(),
+            );
+        }
+
+        unsafe {
+            device.update_descriptor_sets(&descriptor_write_sets, &[]);
+        }
     }
 
-    pub unsafe fn destroy_pipeline(&mut self, device: &ash::Device) {
+    pub unsafe fn destroy_pipeline(&mut self, device: &ash::Device, descriptor_allocator: &mut DescriptorAllocator) {
         if !self.is_built {
             return;
         }
@@ -524,8 +864,11 @@ impl PipelineContainer {
         device.destroy_pipeline(self.vk_pipeline, None);
         device.destroy_pipeline_layout(self.layout, None);
 
-        device.destroy_descriptor_pool(self.descriptor_pool, None);
+        if let Some(descriptor_allocation) = self.descriptor_allocation.take() {
+            descriptor_allocator.free(device, descriptor_allocation);
+        }
         self.descriptor_sets.clear();
+        self.stale_descriptor_sets.clear();
 
         self.is_built = false;
     }
@@ -537,7 +880,72 @@ impl PipelineContainer {
 
     pub unsafe fn destroy_descriptor_set_layout(&self, device: &ash::Device) {
         device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        if let Some(push_descriptor_set_layout) = self.push_descriptor_set_layout {
+            device.destroy_descriptor_set_layout(push_descriptor_set_layout, None);
+        }
+    }
+}
+
+// Creates the vulkan pipelines of a batch spread over the available cores, the descriptor sets are
+// allocated afterwards on the calling thread since the allocator is not shared.
+pub(super) fn build_pipelines(
+    logical_device: &ash::Device,
+    mut jobs: Vec<(&mut PipelineContainer, vk::RenderPass, usize)>,
+    descriptor_allocator: &mut DescriptorAllocator,
+) {
+    if jobs.is_empty() {
+        return;
+    }
+    let start = Instant::now();
+
+    let thread_count = thread::available_parallelism()
+        .map(|count| count.get())
+        .unwrap_or(1)
+        .min(jobs.len());
+    let chunk_size = jobs.len().div_ceil(thread_count);
+
+    let created: Vec<(vk::Pipeline, vk::PipelineLayout)> = if thread_count == 1 {
+        jobs.iter()
+            .map(|(pipeline, render_pass, _)| pipeline.create_vk_pipeline(logical_device, *render_pass))
+            .collect()
+    } else {
+        let chunks: Vec<Vec<(&PipelineContainer, vk::RenderPass)>> = jobs
+            .chunks(chunk_size)
+            .map(|chunk| chunk.iter().map(|(pipeline, render_pass, _)| (&**pipeline, *render_pass)).collect())
+            .collect();
+
+        thread::scope(|scope| {
+            let handles: Vec<_> = chunks
+                .into_iter()
+                .map(|chunk| {
+                    scope.spawn(move || {
+                        chunk
+                            .into_iter()
+                            .map(|(pipeline, render_pass)| pipeline.create_vk_pipeline(logical_device, render_pass))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().expect("Pipeline creation thread panicked!"))
+                .collect()
+        })
+    };
+
+    let pipeline_count = jobs.len();
+    for ((pipeline, _, image_count), (vk_pipeline, layout)) in jobs.iter_mut().zip(created) {
+        assert!(!pipeline.is_built);
+        pipeline.finish_build(logical_device, vk_pipeline, layout, *image_count, descriptor_allocator);
     }
+
+    log_info!(
+        "Created {} pipelines on {} threads in {:.2} ms",
+        pipeline_count,
+        thread_count,
+        start.elapsed().as_secs_f64() * 1000.0
+    );
 }
 
 fn create_shader_module(device: &ash::Device, code: &[u8]) -> vk::ShaderModule {
@@ -561,8 +969,9 @@ fn create_descriptor_set_layout(
     vertex_uniform_cfg: Option<&BufferObjectBindingConfiguration>,
     fragment_uniform_cfg: Option<&BufferObjectBindingConfiguration>,
     storage_buffer_cfg: Option<&BufferObjectBindingConfiguration>,
+    frame_globals_cfg: Option<&BufferObjectBindingConfiguration>,
     sampler_cfgs: &[SamplerBindingConfiguration],
-) -> vk::DescriptorSetLayout {
+) -> (vk::DescriptorSetLayout, DescriptorCounts) {
     let mut layout_bindings = Vec::new();
 
     if let Some(uniform_cfg) = vertex_uniform_cfg {
@@ -589,19 +998,29 @@ fn create_descriptor_set_layout(
         layout_bindings.push(
             vk::DescriptorSetLayoutBinding::builder()
                 .binding(storage_cfg.binding as u32)
-                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER_DYNAMIC)
                 .descriptor_count(1) // TODO
                 .stage_flags(vk::ShaderStageFlags::VERTEX)
                 .build(),
         );
     }
+    if let Some(frame_globals_cfg) = frame_globals_cfg {
+        layout_bindings.push(
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(frame_globals_cfg.binding as u32)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)
+                .build(),
+        );
+    }
 
     for sampler_cfg in sampler_cfgs {
         layout_bindings.push(
             vk::DescriptorSetLayoutBinding::builder()
                 .binding(sampler_cfg.binding as u32)
                 .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                // TODO store textures as an array instead of separate bindings
+                // Pipelines sampling many textures use the bindless array instead, see with_bindless_textures
                 .descriptor_count(1)
                 .stage_flags(vk::ShaderStageFlags::FRAGMENT)
                 .build(),
@@ -613,33 +1032,47 @@ fn create_descriptor_set_layout(
         .bindings(&layout_bindings)
         .build();
 
-    unsafe {
+    let descriptor_set_layout = unsafe {
         device
             .create_descriptor_set_layout(&ubo_layout_create_info, None)
             .expect("Failed to create Descriptor Set Layout!")
-    }
+    };
+
+    (descriptor_set_layout, DescriptorCounts::from_bindings(&layout_bindings))
 }
 
-fn create_descriptor_pool(device: &ash::Device) -> vk::DescriptorPool {
-    let pool_sizes = [
-        vk::DescriptorPoolSize::builder()
-            .ty(DescriptorType::UNIFORM_BUFFER)
-            .descriptor_count(UNIFORM_DESCRIPTOR_POOL_SIZE)
-            .build(),
-        vk::DescriptorPoolSize::builder()
-            .ty(DescriptorType::COMBINED_IMAGE_SAMPLER)
-            .descriptor_count(SAMPLER_DESCRIPTOR_POOL_SIZE)
-            .build(),
-    ];
-
-    let descriptor_pool_create_info = vk::DescriptorPoolCreateInfo::builder()
-        .flags(DescriptorPoolCreateFlags::empty())
-        .max_sets(MAX_FRAMES_IN_FLIGHT as u32)
-        .pool_sizes(&pool_sizes);
+fn create_push_descriptor_set_layout(device: &ash::Device, binding: u8) -> vk::DescriptorSetLayout {
+    let layout_bindings = [vk::DescriptorSetLayoutBinding::builder()
+        .binding(binding as u32)
+        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+        .descriptor_count(1)
+        .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+        .build()];
+
+    let layout_create_info = vk::DescriptorSetLayoutCreateInfo::builder()
+        .flags(vk::DescriptorSetLayoutCreateFlags::PUSH_DESCRIPTOR_KHR)
+        .bindings(&layout_bindings)
+        .build();
 
     unsafe {
         device
-            .create_descriptor_pool(&descriptor_pool_create_info, None)
-            .expect("Failed to create Descriptor Pool!")
+            .create_descriptor_set_layout(&layout_create_info, None)
+            .expect("Failed to create push Descriptor Set Layout!")
     }
 }
+
+fn _to_vk_color_write_mask(color_write_mask: ColorWriteMask) -> vk::ColorComponentFlags {
+    let mut flags = vk::ColorComponentFlags::empty();
+    for (enabled, component) in [
+        (color_write_mask.red, vk::ColorComponentFlags::R),
+        (color_write_mask.green, vk::ColorComponentFlags::G),
+        (color_write_mask.blue, vk::ColorComponentFlags::B),
+        (color_write_mask.alpha, vk::ColorComponentFlags::A),
+    ] {
+        if enabled {
+            flags |= component;
+        }
+    }
+
+    flags
+}
@@ -1,5 +1,6 @@
 use crate::renderer::surface::SurfaceContainer;
 use ash::vk::{PhysicalDevice, QueueFlags};
+use std::collections::HashMap;
 use std::fmt;
 use std::fmt::Display;
 
@@ -18,11 +19,14 @@ impl QueueFamilyIndices {
     pub fn new(
         instance: &ash::Instance,
         device: &PhysicalDevice,
-        surface_container: &SurfaceContainer,
+        surface_container: Option<&SurfaceContainer>,
     ) -> QueueFamilyIndices {
         let graphics = pick_graphics_queue_family(instance, device);
         let transfer = pick_transfer_queue_family(instance, device, graphics.as_ref().unwrap());
-        let present = pick_present_queue_family(instance, device, surface_container);
+        let present = match surface_container {
+            Some(surface_container) => pick_present_queue_family(instance, device, surface_container),
+            None => pick_graphics_queue_family(instance, device),
+        };
 
         // TODO: better handling
         assert!(graphics.is_some());
@@ -35,6 +39,22 @@ impl QueueFamilyIndices {
             present: present.unwrap(),
         }
     }
+
+    // Buffers written on the transfer queue change their owner to the graphics queue family
+    pub fn needs_ownership_transfer(&self) -> bool {
+        self.transfer.family_index != self.graphics.family_index
+    }
+
+    // Number of queues to create per family
+    pub fn queue_counts(&self) -> HashMap<u32, u32> {
+        let mut counts = HashMap::new();
+        for queue in [&self.graphics, &self.transfer, &self.present] {
+            let count = counts.entry(queue.family_index).or_insert(0);
+            *count = (*count).max(queue.queue_index + 1);
+        }
+
+        counts
+    }
 }
 
 impl Display for QueueFamilyIndices {
@@ -67,32 +87,39 @@ fn pick_graphics_queue_family(instance: &ash::Instance, device: &PhysicalDevice)
     Option::None
 }
 
+// Prefers a transfer only family, usually the DMA engine, then any other family that can transfer, then a second
+// queue of the graphics family. Shares the graphics queue as a last resort.
 fn pick_transfer_queue_family(
     instance: &ash::Instance,
     device: &PhysicalDevice,
     graphics_queue: &QueueRef,
 ) -> Option<QueueRef> {
     let queue_family_properties = unsafe { instance.get_physical_device_queue_family_properties(*device) };
-
-    for (index, family_properties) in queue_family_properties.iter().enumerate() {
-        if family_properties.queue_flags.contains(QueueFlags::TRANSFER) {
-            if family_properties.queue_count == 1 && graphics_queue.family_index == index as u32 {
-                continue;
-            } else if graphics_queue.family_index == index as u32 {
-                return Option::Some(QueueRef {
-                    family_index: index as u32,
-                    queue_index: 1,
-                });
-            } else {
-                return Option::Some(QueueRef {
-                    family_index: index as u32,
-                    queue_index: 0,
-                });
-            }
-        }
+    let other_families = || {
+        queue_family_properties
+            .iter()
+            .enumerate()
+            .filter(|(index, properties)| {
+                *index as u32 != graphics_queue.family_index
+                    && properties.queue_count > 0
+                    && properties.queue_flags.contains(QueueFlags::TRANSFER)
+            })
+    };
+
+    let dedicated = other_families()
+        .find(|(_, properties)| !properties.queue_flags.intersects(QueueFlags::GRAPHICS | QueueFlags::COMPUTE));
+    if let Some((index, _)) = dedicated.or_else(|| other_families().next()) {
+        return Option::Some(QueueRef {
+            family_index: index as u32,
+            queue_index: 0,
+        });
     }
 
-    Option::None
+    let graphics_family = queue_family_properties.get(graphics_queue.family_index as usize)?;
+    Option::Some(QueueRef {
+        family_index: graphics_queue.family_index,
+        queue_index: if graphics_family.queue_count > 1 { 1 } else { 0 },
+    })
 }
 
 fn pick_present_queue_family(
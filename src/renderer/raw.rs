@@ -0,0 +1,22 @@
+use ash::vk;
+
+// Escape hatch for Vulkan work the engine doesn't cover, see Context::raw and Context::set_raw_frame_callback.
+// Nothing here is tracked by the engine: objects created with these handles must be destroyed by the user before
+// the context is dropped, and the state of the command buffer must be left as it was found.
+pub struct RawContext<'a> {
+    pub entry: &'a ash::Entry,
+    pub instance: &'a ash::Instance,
+    pub physical_device: vk::PhysicalDevice,
+    pub device: &'a ash::Device,
+    pub graphics_queue: vk::Queue,
+    pub graphics_queue_family: u32,
+    pub present_queue: vk::Queue,
+    pub command_pool: vk::CommandPool,
+
+    // The draw command buffer being recorded and its swapchain image, only set inside the raw frame callback.
+    // It is outside of any render pass, before the first one of the frame begins.
+    pub command_buffer: Option<vk::CommandBuffer>,
+    pub image_index: Option<usize>,
+}
+
+pub type RawFrameCallback = Box<dyn FnMut(&RawContext)>;
@@ -1,4 +1,4 @@
-use std::alloc::{alloc, dealloc, Layout};
+use std::alloc::{alloc_zeroed, dealloc, Layout};
 use std::fmt::Debug;
 use std::slice;
 
@@ -6,48 +6,74 @@ pub type RawArrayPtr = *const u8;
 
 type RawArrayInternalPtr = *mut u8;
 
+// Minimum alignment of the buffer, enough for any vector or matrix type copied to the GPU
+const MIN_ALIGNMENT: usize = 16;
+
+// Element stride of arrays in GPU buffers. std430 (storage buffers) packs elements by their size, std140 (uniform
+// buffers) rounds every element up to 16 bytes. Element types still need the padding of their own members.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArrayLayout {
+    Std430,
+    Std140,
+}
+
+impl ArrayLayout {
+    pub fn stride<T>(&self) -> usize {
+        let size = std::mem::size_of::<T>();
+        match self {
+            ArrayLayout::Std430 => size,
+            ArrayLayout::Std140 => _round_up(size, MIN_ALIGNMENT.max(std::mem::align_of::<T>())),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct RawArray {
     buf_ptr: RawArrayInternalPtr,
     capacity: usize,
 
     data_size: usize,
+    stride: usize,
+    alignment: usize,
     write_index: usize,
 }
 
 #[allow(dead_code)]
 impl RawArray {
     pub fn new<T>(capacity: usize) -> Result<RawArray, &'static str> {
-        let size = std::mem::size_of::<T>();
+        RawArray::with_layout::<T>(capacity, ArrayLayout::Std430)
+    }
 
+    pub fn with_layout<T>(capacity: usize, array_layout: ArrayLayout) -> Result<RawArray, &'static str> {
         if capacity == 0 {
             return Err("Empty capacity");
         }
-
-        let buf_ptr = unsafe {
-            let layout = Layout::from_size_align_unchecked(capacity * size, 1);
-            alloc(layout) as RawArrayInternalPtr
-        };
-
-        if buf_ptr.is_null() {
-            return Err("Failed to allocate memory");
+        if std::mem::size_of::<T>() == 0 {
+            return Err("Zero sized element type");
         }
 
+        let stride = array_layout.stride::<T>();
+        let alignment = MIN_ALIGNMENT.max(std::mem::align_of::<T>());
+        let buf_ptr = _allocate(capacity * stride, alignment)?;
+
         Ok(RawArray {
             buf_ptr,
             capacity,
-            data_size: size,
+            data_size: std::mem::size_of::<T>(),
+            stride,
+            alignment,
             write_index: 0,
         })
     }
 
     pub fn push<T>(&mut self, data: T) -> Result<RawArrayPtr, PushError> {
+        debug_assert_eq!(std::mem::size_of::<T>(), self.data_size, "RawArray element type size mismatch");
         if self.write_index >= self.capacity {
             return Err(PushError::Overflow);
         }
 
         unsafe {
-            let ptr = self.buf_ptr.add(self.write_index * self.data_size);
+            let ptr = self.buf_ptr.add(self.write_index * self.stride);
             std::ptr::copy_nonoverlapping(&data as *const T as *const u8, ptr, self.data_size);
 
             self.write_index += 1;
@@ -64,16 +90,24 @@ impl RawArray {
         self.data_size
     }
 
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
     pub fn len(&self) -> usize {
         self.write_index
     }
 
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
     pub fn is_empty(&self) -> bool {
         self.write_index == 0
     }
 
     pub fn len_bytes(&self) -> usize {
-        self.write_index * self.data_size
+        self.write_index * self.stride
     }
 
     pub fn reset(&mut self) {
@@ -85,16 +119,9 @@ impl RawArray {
             return Err("Empty new size");
         }
 
-        let new_ptr = unsafe {
-            let layout = Layout::from_size_align_unchecked(new_size * self.data_size, 1);
-            alloc(layout) as RawArrayInternalPtr
-        };
-
-        if new_ptr.is_null() {
-            return Err("Failed to allocate memory");
-        }
+        let new_ptr = _allocate(new_size * self.stride, self.alignment)?;
 
-        let copy_count = (self.capacity * self.data_size()).min(new_size * self.data_size);
+        let copy_count = (self.capacity * self.stride).min(new_size * self.stride);
         unsafe {
             std::ptr::copy_nonoverlapping(self.buf_ptr, new_ptr, copy_count);
         }
@@ -102,21 +129,43 @@ impl RawArray {
         unsafe {
             dealloc(
                 self.buf_ptr,
-                Layout::from_size_align_unchecked(self.capacity * self.data_size, 1),
+                Layout::from_size_align_unchecked(self.capacity * self.stride, self.alignment),
             )
         };
 
         self.capacity = new_size;
         self.buf_ptr = new_ptr;
-        self.write_index = copy_count / self.data_size;
+        self.write_index = self.write_index.min(new_size);
 
         Ok(())
     }
 
+    // Panics if T is not the type the array was created with
+    pub fn get<T>(&self, index: usize) -> Option<&T> {
+        self.assert_element_type::<T>();
+        if index >= self.write_index {
+            return None;
+        }
+
+        // The buffer is aligned for T and the stride is a multiple of its alignment
+        unsafe { Some(&*(self.buf_ptr.add(index * self.stride) as *const T)) }
+    }
+
+    pub fn iter<'a, T: 'a>(&'a self) -> impl Iterator<Item = &'a T> + 'a {
+        self.assert_element_type::<T>();
+        (0..self.write_index).map(move |index| unsafe { &*(self.buf_ptr.add(index * self.stride) as *const T) })
+    }
+
     pub unsafe fn slice<T>(&self) -> &[T] {
         debug_assert!(std::mem::size_of::<T>() == self.data_size);
+        debug_assert!(self.stride == self.data_size, "RawArray with padded elements can't be sliced");
         slice::from_raw_parts(self.buf_ptr as *const T, self.write_index)
     }
+
+    fn assert_element_type<T>(&self) {
+        assert_eq!(std::mem::size_of::<T>(), self.data_size, "RawArray element type size mismatch");
+        assert!(std::mem::align_of::<T>() <= self.alignment, "RawArray element type alignment mismatch");
+    }
 }
 
 impl Drop for RawArray {
@@ -124,19 +173,34 @@ impl Drop for RawArray {
         unsafe {
             dealloc(
                 self.buf_ptr,
-                Layout::from_size_align_unchecked(self.capacity * self.data_size, 1),
+                Layout::from_size_align_unchecked(self.capacity * self.stride, self.alignment),
             )
         };
     }
 }
 
+// Zeroed, so padding between elements is never uninitialized memory
+fn _allocate(size: usize, alignment: usize) -> Result<RawArrayInternalPtr, &'static str> {
+    let layout = Layout::from_size_align(size, alignment).map_err(|_| "Invalid RawArray layout")?;
+    let ptr = unsafe { alloc_zeroed(layout) as RawArrayInternalPtr };
+    if ptr.is_null() {
+        return Err("Failed to allocate memory");
+    }
+
+    Ok(ptr)
+}
+
+fn _round_up(value: usize, alignment: usize) -> usize {
+    value.div_ceil(alignment) * alignment
+}
+
 pub enum PushError {
     Overflow,
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::renderer::rawarray::RawArray;
+    use crate::renderer::rawarray::{ArrayLayout, RawArray};
 
     #[test]
     fn test_insert() {
@@ -199,4 +263,37 @@ mod tests {
         assert_eq!(slice, &[1, 2, 3, 4]);
         println!("{:?}", slice);
     }
+
+    #[test]
+    fn test_iter_and_alignment() {
+        let mut raw_array = RawArray::new::<[f32; 3]>(2).unwrap();
+        assert_eq!(raw_array.start() as usize % 16, 0);
+        let _ = raw_array.push([1.0f32, 2.0, 3.0]);
+        let _ = raw_array.push([4.0f32, 5.0, 6.0]);
+
+        assert_eq!(raw_array.stride(), 12);
+        assert_eq!(raw_array.len_bytes(), 24);
+        assert_eq!(raw_array.iter::<[f32; 3]>().copied().collect::<Vec<_>>(), vec![[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]]);
+        assert_eq!(raw_array.get::<[f32; 3]>(1), Some(&[4.0, 5.0, 6.0]));
+        assert_eq!(raw_array.get::<[f32; 3]>(2), None);
+
+        // std140 arrays round every element up to 16 bytes, padding stays zeroed
+        let mut raw_array = RawArray::with_layout::<[f32; 3]>(2, ArrayLayout::Std140).unwrap();
+        let _ = raw_array.push([1.0f32, 2.0, 3.0]);
+        let _ = raw_array.push([4.0f32, 5.0, 6.0]);
+        raw_array.resize(3).unwrap();
+
+        assert_eq!(raw_array.stride(), 16);
+        assert_eq!(raw_array.len_bytes(), 32);
+        assert_eq!(raw_array.iter::<[f32; 3]>().nth(1), Some(&[4.0, 5.0, 6.0]));
+        let bytes = unsafe { std::slice::from_raw_parts(raw_array.start(), raw_array.len_bytes()) };
+        assert_eq!(&bytes[12..16], &[0, 0, 0, 0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "element type size mismatch")]
+    fn test_iter_type_mismatch() {
+        let raw_array = RawArray::new::<u32>(2).unwrap();
+        let _ = raw_array.iter::<u64>().count();
+    }
 }
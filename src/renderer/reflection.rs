@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+
+// Minimal SPIR-V reflection, only reads what is needed to validate the uniform blocks and push constants
+// of a pipeline configuration against the sizes of the Rust types bound to them.
+
+const MAGIC_NUMBER: u32 = 0x0723_0203;
+const HEADER_WORDS: usize = 5;
+
+const OP_TYPE_BOOL: u32 = 20;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_OFFSET: u32 = 35;
+
+const STORAGE_CLASS_UNIFORM: u32 = 2;
+const STORAGE_CLASS_PUSH_CONSTANT: u32 = 9;
+
+enum SpirvType {
+    Scalar(usize),
+    Vector(u32, u32),
+    Matrix(u32, u32),
+    Array(u32, u32),
+    RuntimeArray,
+    Struct(Vec<u32>),
+    Pointer(u32, u32),
+}
+
+pub(super) struct ShaderReflection {
+    uniform_blocks: Vec<(u32, usize)>,
+    push_constant_size: Option<usize>,
+}
+
+impl ShaderReflection {
+    pub(super) fn parse(code: &[u8]) -> Result<Self, &'static str> {
+        if !code.len().is_multiple_of(4) || code.len() < HEADER_WORDS * 4 {
+            return Err("Shader code is not SPIR-V");
+        }
+        let words: Vec<u32> = code
+            .chunks_exact(4)
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect();
+        if words[0] != MAGIC_NUMBER {
+            return Err("Shader code is not SPIR-V");
+        }
+
+        let mut module = SpirvModule::default();
+        let mut variables = Vec::new();
+
+        let mut offset = HEADER_WORDS;
+        while offset < words.len() {
+            let word_count = (words[offset] >> 16) as usize;
+            let opcode = words[offset] & 0xffff;
+            if word_count == 0 || offset + word_count > words.len() {
+                return Err("Malformed SPIR-V instruction");
+            }
+            let operands = &words[offset + 1..offset + word_count];
+            module.read_instruction(opcode, operands, &mut variables)?;
+            offset += word_count;
+        }
+
+        let mut uniform_blocks = Vec::new();
+        let mut push_constant_size = None;
+        for (pointer_type, variable) in variables {
+            let (storage_class, block_type) = match module.types.get(&pointer_type) {
+                Some(SpirvType::Pointer(storage_class, pointee)) => (*storage_class, *pointee),
+                _ => return Err("Variable is not of pointer type"),
+            };
+            match storage_class {
+                STORAGE_CLASS_UNIFORM => {
+                    if let Some(binding) = module.bindings.get(&variable) {
+                        uniform_blocks.push((*binding, module.size_of(block_type, None)?));
+                    }
+                }
+                STORAGE_CLASS_PUSH_CONSTANT => push_constant_size = Some(module.size_of(block_type, None)?),
+                _ => {}
+            }
+        }
+
+        Ok(ShaderReflection {
+            uniform_blocks,
+            push_constant_size,
+        })
+    }
+
+    // Storage blocks are declared in the Uniform storage class as well, their runtime array does not count
+    pub(super) fn uniform_block_size(&self, binding: u8) -> Option<usize> {
+        self.uniform_blocks
+            .iter()
+            .find(|(block_binding, _)| *block_binding == binding as u32)
+            .map(|(_, size)| *size)
+    }
+
+    pub(super) fn push_constant_size(&self) -> Option<usize> {
+        self.push_constant_size
+    }
+}
+
+#[derive(Default)]
+struct SpirvModule {
+    types: HashMap<u32, SpirvType>,
+    constants: HashMap<u32, u32>,
+    bindings: HashMap<u32, u32>,
+    array_strides: HashMap<u32, usize>,
+    member_offsets: HashMap<(u32, u32), usize>,
+    member_matrix_strides: HashMap<(u32, u32), usize>,
+}
+
+impl SpirvModule {
+    fn read_instruction(&mut self, opcode: u32, operands: &[u32], variables: &mut Vec<(u32, u32)>) -> Result<(), &'static str> {
+        let operand = |index: usize| operands.get(index).copied().ok_or("Missing SPIR-V operand");
+
+        match opcode {
+            OP_TYPE_BOOL => {
+                self.types.insert(operand(0)?, SpirvType::Scalar(4));
+            }
+            OP_TYPE_INT | OP_TYPE_FLOAT => {
+                self.types.insert(operand(0)?, SpirvType::Scalar(operand(1)? as usize / 8));
+            }
+            OP_TYPE_VECTOR => {
+                self.types.insert(operand(0)?, SpirvType::Vector(operand(1)?, operand(2)?));
+            }
+            OP_TYPE_MATRIX => {
+                self.types.insert(operand(0)?, SpirvType::Matrix(operand(1)?, operand(2)?));
+            }
+            OP_TYPE_ARRAY => {
+                self.types.insert(operand(0)?, SpirvType::Array(operand(1)?, operand(2)?));
+            }
+            OP_TYPE_RUNTIME_ARRAY => {
+                self.types.insert(operand(0)?, SpirvType::RuntimeArray);
+            }
+            OP_TYPE_STRUCT => {
+                self.types.insert(operand(0)?, SpirvType::Struct(operands[1..].to_vec()));
+            }
+            OP_TYPE_POINTER => {
+                self.types.insert(operand(0)?, SpirvType::Pointer(operand(1)?, operand(2)?));
+            }
+            OP_CONSTANT => {
+                // Only 32 bit constants are used as array lengths
+                self.constants.insert(operand(1)?, operand(2)?);
+            }
+            OP_VARIABLE => variables.push((operand(0)?, operand(1)?)),
+            OP_DECORATE => match operand(1)? {
+                DECORATION_BINDING => {
+                    self.bindings.insert(operand(0)?, operand(2)?);
+                }
+                DECORATION_ARRAY_STRIDE => {
+                    self.array_strides.insert(operand(0)?, operand(2)? as usize);
+                }
+                _ => {}
+            },
+            OP_MEMBER_DECORATE => match operand(2)? {
+                DECORATION_OFFSET => {
+                    self.member_offsets.insert((operand(0)?, operand(1)?), operand(3)? as usize);
+                }
+                DECORATION_MATRIX_STRIDE => {
+                    self.member_matrix_strides
+                        .insert((operand(0)?, operand(1)?), operand(3)? as usize);
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    // The size a block member occupies, not rounded up to its alignment
+    fn size_of(&self, type_id: u32, matrix_stride: Option<usize>) -> Result<usize, &'static str> {
+        let size = match self.types.get(&type_id).ok_or("Unknown SPIR-V type")? {
+            SpirvType::Scalar(size) => *size,
+            SpirvType::Vector(component_type, count) => self.size_of(*component_type, None)? * *count as usize,
+            SpirvType::Matrix(column_type, columns) => match matrix_stride {
+                Some(stride) => stride * *columns as usize,
+                None => self.size_of(*column_type, None)? * *columns as usize,
+            },
+            SpirvType::Array(element_type, length) => {
+                let length = *self.constants.get(length).ok_or("Array length is not a constant")? as usize;
+                let stride = match self.array_strides.get(&type_id) {
+                    Some(stride) => *stride,
+                    None => self.size_of(*element_type, matrix_stride)?,
+                };
+                stride * length
+            }
+            SpirvType::RuntimeArray => 0,
+            SpirvType::Struct(members) => {
+                let mut size = 0;
+                for (index, member_type) in members.iter().enumerate() {
+                    let key = (type_id, index as u32);
+                    let offset = self.member_offsets.get(&key).copied().unwrap_or(size);
+                    let member_size = self.size_of(*member_type, self.member_matrix_strides.get(&key).copied())?;
+                    size = size.max(offset + member_size);
+                }
+                size
+            }
+            SpirvType::Pointer(_, _) => return Err("Pointer in a block"),
+        };
+
+        Ok(size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::datatypes::TransformColorPushConstant;
+    use crate::util::file;
+    use cgmath::Matrix4;
+    use std::path::Path;
+
+    #[test]
+    fn block_sizes() {
+        let code = file::read_file(Path::new("./resources/shaders/default_ppl_vert.spv")).unwrap();
+        let reflection = ShaderReflection::parse(&code).unwrap();
+
+        assert_eq!(reflection.uniform_block_size(0), Some(2 * std::mem::size_of::<Matrix4<f32>>()));
+        assert_eq!(reflection.uniform_block_size(1), None);
+        assert_eq!(
+            reflection.push_constant_size(),
+            Some(std::mem::size_of::<TransformColorPushConstant>())
+        );
+
+        assert!(ShaderReflection::parse(&[0, 1, 2, 3]).is_err());
+    }
+}
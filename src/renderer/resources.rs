@@ -0,0 +1,172 @@
+use std::sync::mpsc;
+use std::sync::mpsc::{Receiver, Sender, TryRecvError};
+
+use ash::vk;
+
+use crate::renderer::context::Context;
+use crate::renderer::types::{
+    BufferObjectHandle, Index, PipelineConfiguration, PipelineHandle, RenderPassHandle, TextureHandle, UniformStage,
+    VertexInputDescription,
+};
+use crate::renderer::vertex::VertexLayout;
+
+type ResourceRequest = Box<dyn FnOnce(&mut Context) + Send>;
+
+// Resource creation usable from other threads, e.g. loaders streaming in models and textures. Requests are
+// queued and executed by the render thread at the start of the next frame, see Context::resources.
+#[derive(Clone)]
+pub struct Resources {
+    sender: Sender<ResourceRequest>,
+}
+
+impl Resources {
+    pub fn create_static_vertex_buffer<T: VertexInputDescription + Send + 'static>(
+        &self,
+        vertices: Vec<T>,
+    ) -> Pending<vk::Buffer> {
+        self.request(move |context| context.create_static_vertex_buffer_sync(&vertices))
+    }
+
+    pub fn create_static_vertex_buffer_from_bytes(&self, vertex_data: Vec<u8>) -> Pending<vk::Buffer> {
+        self.request(move |context| context.create_static_vertex_buffer_from_bytes_sync(&vertex_data))
+    }
+
+    pub fn create_static_index_buffer(&self, indices: Vec<Index>) -> Pending<vk::Buffer> {
+        self.request(move |context| context.create_static_index_buffer_sync(&indices))
+    }
+
+    pub fn create_uniform_buffer<T: 'static>(&self, stage: UniformStage) -> Pending<BufferObjectHandle<T>> {
+        self.request(move |context| context.create_uniform_buffer::<T>(stage))
+    }
+
+    pub fn create_vertex_buffer<T: 'static>(&self) -> Pending<BufferObjectHandle<T>> {
+        self.request(|context| context.create_vertex_buffer::<T>())
+    }
+
+    pub fn create_storage_buffer<T: 'static>(&self, capacity: usize) -> Pending<BufferObjectHandle<T>> {
+        self.request(move |context| context.create_storage_buffer::<T>(capacity))
+    }
+
+    pub fn add_texture(&self, image_width: u32, image_height: u32, image_data: Vec<u8>) -> Pending<TextureHandle> {
+        self.request(move |context| context.add_texture(image_width, image_height, &image_data))
+    }
+
+    pub fn add_texture_array(
+        &self,
+        image_width: u32,
+        image_height: u32,
+        layers: u32,
+        image_data: Vec<u8>,
+    ) -> Pending<TextureHandle> {
+        self.request(move |context| context.add_texture_array(image_width, image_height, layers, &image_data))
+    }
+
+    pub fn add_cube_texture(&self, face_size: u32, image_data: Vec<u8>) -> Pending<TextureHandle> {
+        self.request(move |context| context.add_cube_texture(face_size, &image_data))
+    }
+
+    pub fn add_pipeline<T: VertexInputDescription + 'static>(
+        &self,
+        render_pass: RenderPassHandle,
+        config: PipelineConfiguration,
+    ) -> Pending<PipelineHandle> {
+        self.request(move |context| context.add_pipeline::<T>(render_pass, config))
+    }
+
+    pub fn add_pipeline_with_layout(
+        &self,
+        render_pass: RenderPassHandle,
+        config: PipelineConfiguration,
+        vertex_layout: VertexLayout,
+    ) -> Pending<PipelineHandle> {
+        self.request(move |context| context.add_pipeline_with_layout(render_pass, config, vertex_layout))
+    }
+
+    fn request<T: Send + 'static>(&self, create: impl FnOnce(&mut Context) -> T + Send + 'static) -> Pending<T> {
+        let (sender, receiver) = mpsc::channel();
+        let request: ResourceRequest = Box::new(move |context| {
+            // The requester may have given up on the resource
+            let _ = sender.send(create(context));
+        });
+        self.sender.send(request).expect("Context has been dropped!");
+
+        Pending {
+            receiver,
+            resource: None,
+        }
+    }
+}
+
+// Resource created by the render thread once it has processed the request
+pub struct Pending<T> {
+    receiver: Receiver<T>,
+    resource: Option<T>,
+}
+
+impl<T: Copy> Pending<T> {
+    pub fn try_get(&mut self) -> Option<T> {
+        if self.resource.is_none() {
+            match self.receiver.try_recv() {
+                Ok(resource) => self.resource = Some(resource),
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => panic!("Resource request was dropped!"),
+            }
+        }
+
+        self.resource
+    }
+
+    // Blocks until the next frame has started, so never call it on the render thread
+    pub fn wait(mut self) -> T {
+        if let Some(resource) = self.resource.take() {
+            return resource;
+        }
+
+        self.receiver.recv().expect("Resource request was dropped!")
+    }
+}
+
+pub(super) struct ResourceQueue {
+    sender: Sender<ResourceRequest>,
+    receiver: Receiver<ResourceRequest>,
+}
+
+impl ResourceQueue {
+    pub(super) fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        ResourceQueue { sender, receiver }
+    }
+
+    pub(super) fn resources(&self) -> Resources {
+        Resources {
+            sender: self.sender.clone(),
+        }
+    }
+
+    // Requests queued so far, taken before executing them since they need the whole context
+    pub(super) fn take_requests(&self) -> Vec<ResourceRequest> {
+        self.receiver.try_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pending_resource() {
+        let (sender, receiver) = mpsc::channel();
+        let mut pending = Pending {
+            receiver,
+            resource: None,
+        };
+        assert_eq!(pending.try_get(), None);
+
+        let loader = std::thread::spawn(move || sender.send(7_u32).unwrap());
+        loader.join().unwrap();
+        assert_eq!(pending.try_get(), Some(7));
+        // Still there once the channel is closed
+        assert_eq!(pending.try_get(), Some(7));
+        assert_eq!(pending.wait(), 7);
+    }
+}
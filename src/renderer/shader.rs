@@ -0,0 +1,60 @@
+use std::path::Path;
+
+use crate::error::VulkrapError;
+use crate::renderer::reflection::ShaderReflection;
+use crate::util::file;
+
+// Built into the binary so they are there when the shader files are not
+pub const FALLBACK_VERTEX_SHADER: &[u8] = include_bytes!("../../resources/shaders/fallback_vert.spv");
+pub const FALLBACK_TRANSFORM_VERTEX_SHADER: &[u8] =
+    include_bytes!("../../resources/shaders/fallback_transform_vert.spv");
+pub const FALLBACK_FRAGMENT_SHADER: &[u8] = include_bytes!("../../resources/shaders/fallback_frag.spv");
+pub const FALLBACK_COMPUTE_SHADER: &[u8] = include_bytes!("../../resources/shaders/fallback_comp.spv");
+
+// SPIR-V code of the file, checked to be SPIR-V that the reflection can read
+pub fn load_shader(path: &Path) -> Result<Vec<u8>, VulkrapError> {
+    let code = file::read_file(path)?;
+    ShaderReflection::parse(&code).map_err(|reason| VulkrapError::InvalidShader {
+        path: path.to_path_buf(),
+        reason,
+    })?;
+
+    Ok(code)
+}
+
+// Logs why the file could not be loaded and returns the fallback instead
+pub fn load_shader_or_fallback(path: &Path, fallback: &[u8]) -> Vec<u8> {
+    load_shader(path).unwrap_or_else(|error| {
+        log_error!("{}, using the fallback shader", error);
+        fallback.to_vec()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shader_fallback() {
+        assert!(load_shader(Path::new("./resources/shaders/flat_color_vert.spv")).is_ok());
+        assert!(matches!(
+            load_shader(Path::new("./resources/shaders/missing_vert.spv")),
+            Err(VulkrapError::Io { .. })
+        ));
+        assert!(matches!(
+            load_shader(Path::new("./resources/shaders/noise.glsl")),
+            Err(VulkrapError::InvalidShader { .. })
+        ));
+
+        let code = load_shader_or_fallback(Path::new("./resources/shaders/missing_frag.spv"), FALLBACK_FRAGMENT_SHADER);
+        assert_eq!(code, FALLBACK_FRAGMENT_SHADER);
+        for fallback in [
+            FALLBACK_VERTEX_SHADER,
+            FALLBACK_TRANSFORM_VERTEX_SHADER,
+            FALLBACK_FRAGMENT_SHADER,
+            FALLBACK_COMPUTE_SHADER,
+        ] {
+            assert!(ShaderReflection::parse(fallback).is_ok());
+        }
+    }
+}
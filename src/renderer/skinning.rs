@@ -0,0 +1,185 @@
+use ash::vk;
+
+use crate::renderer::buffer::BufferObjectManager;
+use crate::renderer::compute::ComputePipeline;
+use crate::renderer::descriptor::{DescriptorAllocation, DescriptorAllocator};
+use crate::renderer::graph::GraphSkinnedMesh;
+use crate::renderer::types::SkinnedMeshHandle;
+
+const SKINNING_SHADER: &str = "./resources/shaders/skinning_comp.spv";
+const WORKGROUP_SIZE: u32 = 64;
+
+struct SkinnedMesh {
+    source_buffer: vk::Buffer,
+    vertex_buffer: vk::Buffer,
+    vertex_count: u32,
+    joint_buffer: usize,
+    descriptor_sets: DescriptorAllocation,
+}
+
+// Compute pre-pass skinning meshes into vertex buffers before the render passes of the frame, so that a mesh
+// drawn by several passes (shadows, main, reflections) is only skinned once.
+pub(super) struct SkinningManager {
+    pipeline: Option<ComputePipeline>,
+    descriptor_allocator: DescriptorAllocator,
+    meshes: Vec<SkinnedMesh>,
+    queued: Vec<SkinnedMeshHandle>,
+}
+
+impl SkinningManager {
+    pub(super) fn new() -> Self {
+        SkinningManager {
+            pipeline: None,
+            descriptor_allocator: DescriptorAllocator::new(),
+            meshes: Vec::new(),
+            queued: Vec::new(),
+        }
+    }
+
+    // The buffers are owned by the memory manager, the vertex buffer needs STORAGE_BUFFER and VERTEX_BUFFER usage
+    pub(super) fn add_mesh(
+        &mut self,
+        device: &ash::Device,
+        source_buffer: vk::Buffer,
+        vertex_buffer: vk::Buffer,
+        vertex_count: u32,
+        joint_buffer: usize,
+    ) -> SkinnedMeshHandle {
+        // Joint matrices, source vertices and skinned vertices in bindings 0 to 2, the vertex count as push constant
+        let pipeline = self.pipeline.get_or_insert_with(|| {
+            ComputePipeline::new(
+                device,
+                SKINNING_SHADER,
+                &[vk::DescriptorType::STORAGE_BUFFER; 3],
+                std::mem::size_of::<u32>() as u32,
+            )
+        });
+        let descriptor_sets = self.descriptor_allocator.allocate(
+            device,
+            pipeline.descriptor_set_layout,
+            pipeline.descriptor_counts,
+            1,
+        );
+
+        self.meshes.push(SkinnedMesh {
+            source_buffer,
+            vertex_buffer,
+            vertex_count,
+            joint_buffer,
+            descriptor_sets,
+        });
+
+        self.meshes.len() - 1
+    }
+
+    pub(super) fn vertex_buffer(&self, handle: SkinnedMeshHandle) -> vk::Buffer {
+        self.meshes[handle].vertex_buffer
+    }
+
+    // Skins the mesh in the next frame, once no matter how often it is queued
+    pub(super) fn queue(&mut self, handle: SkinnedMeshHandle) {
+        assert!(handle < self.meshes.len(), "Unknown skinned mesh");
+        if !self.queued.contains(&handle) {
+            self.queued.push(handle);
+        }
+    }
+
+    pub(super) fn reset_queue(&mut self) {
+        self.queued.clear();
+    }
+
+    // Records the dispatches followed by a barrier making the vertices visible to the vertex input of the
+    // render passes. The descriptor sets are written here since joint buffers are per frame in flight and may
+    // grow; they are not in use as the frame last recorded with them has finished.
+    pub(super) unsafe fn bake_command_buffer(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        frame_index: usize,
+        buffer_object_manager: &BufferObjectManager,
+    ) {
+        let pipeline = match self.pipeline.as_ref() {
+            Some(pipeline) if !self.queued.is_empty() => pipeline,
+            _ => return,
+        };
+
+        for handle in self.queued.iter() {
+            let mesh = &self.meshes[*handle];
+            let descriptor_set = mesh.descriptor_sets.sets()[0];
+            let joint_buffer = buffer_object_manager.borrow_buffer(mesh.joint_buffer).device(frame_index);
+            _write_descriptor_set(device, descriptor_set, [joint_buffer, mesh.source_buffer, mesh.vertex_buffer]);
+
+            pipeline.bind(device, command_buffer, descriptor_set);
+            device.cmd_push_constants(
+                command_buffer,
+                pipeline.pipeline_layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                &mesh.vertex_count.to_ne_bytes(),
+            );
+            device.cmd_dispatch(command_buffer, mesh.vertex_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+
+        let memory_barriers = [vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(vk::AccessFlags::VERTEX_ATTRIBUTE_READ)
+            .build()];
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::VERTEX_INPUT,
+            vk::DependencyFlags::empty(),
+            &memory_barriers,
+            &[],
+            &[],
+        );
+    }
+
+    pub(super) fn graph_meshes(&self) -> Vec<GraphSkinnedMesh> {
+        self.meshes
+            .iter()
+            .enumerate()
+            .map(|(index, mesh)| GraphSkinnedMesh {
+                index,
+                joint_buffer: mesh.joint_buffer,
+                queued: self.queued.contains(&index),
+            })
+            .collect()
+    }
+
+    pub(super) unsafe fn destroy(&mut self, device: &ash::Device) {
+        self.meshes.clear();
+        self.queued.clear();
+        self.descriptor_allocator.destroy(device);
+        if let Some(pipeline) = self.pipeline.take() {
+            pipeline.destroy(device);
+        }
+    }
+}
+
+unsafe fn _write_descriptor_set(device: &ash::Device, descriptor_set: vk::DescriptorSet, buffers: [vk::Buffer; 3]) {
+    let buffer_infos: Vec<[vk::DescriptorBufferInfo; 1]> = buffers
+        .iter()
+        .map(|buffer| {
+            [vk::DescriptorBufferInfo {
+                buffer: *buffer,
+                offset: 0,
+                range: vk::WHOLE_SIZE,
+            }]
+        })
+        .collect();
+    let writes: Vec<vk::WriteDescriptorSet> = buffer_infos
+        .iter()
+        .enumerate()
+        .map(|(binding, buffer_info)| {
+            vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(binding as u32)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(buffer_info)
+                .build()
+        })
+        .collect();
+
+    device.update_descriptor_sets(&writes, &[]);
+}
@@ -2,19 +2,31 @@ use std::time::Duration;
 
 pub struct RenderStats {
     pub draw_command_count: u32,
+    // Draw commands folded into instanced draws of a previous command
+    pub merged_draw_command_count: u32,
     pub triangle_count: u64,
 
     pub transfer_commands_bake_time: Duration,
     pub draw_commands_bake_time: Duration,
+
+    pub descriptor_pool_count: u32,
+    pub descriptor_set_count: u32,
+
+    // Draw command buffer of an earlier frame, the latest that finished. None without timestamp support.
+    pub gpu_time: Option<Duration>,
 }
 
 impl RenderStats {
     pub fn new() -> Self {
         RenderStats {
             draw_command_count: 0,
+            merged_draw_command_count: 0,
             triangle_count: 0,
             transfer_commands_bake_time: Duration::ZERO,
             draw_commands_bake_time: Duration::ZERO,
+            descriptor_pool_count: 0,
+            descriptor_set_count: 0,
+            gpu_time: None,
         }
     }
 
@@ -1,31 +1,66 @@
 use ash::vk;
-use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
+use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle, RawDisplayHandle, RawWindowHandle};
 
 use super::swapchain::SwapChainSupportDetail;
 
 pub struct SurfaceContainer {
     pub(crate) surface: vk::SurfaceKHR,
     pub(crate) loader: ash::extensions::khr::Surface,
+
+    display_handle: RawDisplayHandle,
+    window_handle: RawWindowHandle,
+    // Used by the swapchain when the surface leaves the extent up to it, e.g. on Wayland
+    extent: vk::Extent2D,
 }
 
 impl SurfaceContainer {
     pub fn new(entry: &ash::Entry, instance: &ash::Instance, window: &winit::window::Window) -> SurfaceContainer {
-        let surface =
-            unsafe { ash_window::create_surface(entry,
-                                                instance,
-                                                window.raw_display_handle(),
-                                                window.raw_window_handle(),
-                                                None)
-                .expect("Failed to create surface.")
-            };
+        let size = window.inner_size();
+
+        Self::from_raw_handles(
+            entry,
+            instance,
+            window.raw_display_handle(),
+            window.raw_window_handle(),
+            vk::Extent2D {
+                width: size.width,
+                height: size.height,
+            },
+        )
+    }
+
+    pub fn from_raw_handles(
+        entry: &ash::Entry,
+        instance: &ash::Instance,
+        display_handle: RawDisplayHandle,
+        window_handle: RawWindowHandle,
+        extent: vk::Extent2D,
+    ) -> SurfaceContainer {
+        let surface = _create_surface(entry, instance, display_handle, window_handle);
         let surface_loader = ash::extensions::khr::Surface::new(entry, instance);
 
         SurfaceContainer {
             surface,
             loader: surface_loader,
+            display_handle,
+            window_handle,
+            extent,
         }
     }
 
+    pub fn extent(&self) -> vk::Extent2D {
+        self.extent
+    }
+
+    pub fn set_extent(&mut self, extent: vk::Extent2D) {
+        self.extent = extent;
+    }
+
+    pub unsafe fn recreate(&mut self, entry: &ash::Entry, instance: &ash::Instance) {
+        self.destroy();
+        self.surface = _create_surface(entry, instance, self.display_handle, self.window_handle);
+    }
+
     pub unsafe fn destroy(&self) {
         self.loader.destroy_surface(self.surface, None);
     }
@@ -53,3 +88,15 @@ impl SurfaceContainer {
         }
     }
 }
+
+fn _create_surface(
+    entry: &ash::Entry,
+    instance: &ash::Instance,
+    display_handle: RawDisplayHandle,
+    window_handle: RawWindowHandle,
+) -> vk::SurfaceKHR {
+    unsafe {
+        ash_window::create_surface(entry, instance, display_handle, window_handle, None)
+            .expect("Failed to create surface.")
+    }
+}
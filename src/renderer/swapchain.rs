@@ -15,6 +15,7 @@ pub struct SwapChainContainer {
     pub swapchain: vk::SwapchainKHR,
     pub images: Vec<vk::Image>,
     pub format: Format,
+    pub present_mode: vk::PresentModeKHR,
     pub extent: Extent2D,
     pub image_views: Vec<vk::ImageView>,
 }
@@ -32,7 +33,7 @@ pub fn create_swapchain(
     let surface_format = _choose_swapchain_format(&swapchain_support.formats);
     let present_mode = _choose_swapchain_present_mode(&swapchain_support.present_modes, USE_VSYNC);
 
-    let extent = choose_swapchain_extent(&swapchain_support.capabilities);
+    let extent = choose_swapchain_extent(&swapchain_support.capabilities, surface_container.extent());
 
     let image_count = 3;
     if swapchain_support.capabilities.min_image_count > image_count || swapchain_support.capabilities.max_image_count < image_count {
@@ -94,6 +95,7 @@ pub fn create_swapchain(
         loader: swapchain_loader,
         swapchain,
         format: surface_format.format,
+        present_mode,
         extent,
         images,
         image_views,
@@ -136,11 +138,18 @@ fn _choose_swapchain_present_mode(available_present_modes: &[vk::PresentModeKHR]
     vk::PresentModeKHR::FIFO
 }
 
-fn choose_swapchain_extent(capabilities: &vk::SurfaceCapabilitiesKHR) -> Extent2D {
+fn choose_swapchain_extent(capabilities: &vk::SurfaceCapabilitiesKHR, window_extent: Extent2D) -> Extent2D {
     if capabilities.current_extent.width != u32::MAX {
         capabilities.current_extent
     } else {
-        unreachable!();
+        Extent2D {
+            width: window_extent
+                .width
+                .clamp(capabilities.min_image_extent.width, capabilities.max_image_extent.width),
+            height: window_extent
+                .height
+                .clamp(capabilities.min_image_extent.height, capabilities.max_image_extent.height),
+        }
     }
 }
 
@@ -1,4 +1,3 @@
-use crate::renderer::constants::MAX_FRAMES_IN_FLIGHT;
 use ash::vk;
 
 pub struct SynchronizationHandler {
@@ -7,24 +6,23 @@ pub struct SynchronizationHandler {
     render_finished_semaphores: Vec<vk::Semaphore>,
     inflight_fences: Vec<vk::Fence>,
 
+    // Index of the current frame, the draw and transfer resources are replicated per frame in flight
     inflight_counter: usize,
 }
 
 impl SynchronizationHandler {
-    pub fn new(logical_device: &ash::Device) -> SynchronizationHandler {
-        // TODO dont use MAX_FRAMES_IN_FLIGHT.
-        // TODO Use the ACTUAL! number of images in the swapchain, and fix recreate function so that it works if it changes.
-        let mut image_available_semaphores = Vec::with_capacity(MAX_FRAMES_IN_FLIGHT);
-        let mut transfer_finished_semaphores = Vec::with_capacity(MAX_FRAMES_IN_FLIGHT);
-        let mut render_finished_semaphores = Vec::with_capacity(MAX_FRAMES_IN_FLIGHT);
-        let mut inflight_fences = Vec::with_capacity(MAX_FRAMES_IN_FLIGHT);
+    pub fn new(logical_device: &ash::Device, frames_in_flight: usize) -> SynchronizationHandler {
+        let mut image_available_semaphores = Vec::with_capacity(frames_in_flight);
+        let mut transfer_finished_semaphores = Vec::with_capacity(frames_in_flight);
+        let mut render_finished_semaphores = Vec::with_capacity(frames_in_flight);
+        let mut inflight_fences = Vec::with_capacity(frames_in_flight);
 
         let semaphore_create_info = vk::SemaphoreCreateInfo::builder().build();
         let fence_create_info = vk::FenceCreateInfo::builder()
             .flags(vk::FenceCreateFlags::SIGNALED)
             .build();
 
-        for _ in 0..MAX_FRAMES_IN_FLIGHT {
+        for _ in 0..frames_in_flight {
             unsafe {
                 let image_available_semaphore = logical_device
                     .create_semaphore(&semaphore_create_info, None)
@@ -57,7 +55,7 @@ impl SynchronizationHandler {
     }
 
     pub unsafe fn destroy(&mut self, logical_device: &ash::Device) {
-        for i in 0..MAX_FRAMES_IN_FLIGHT {
+        for i in 0..self.frames_in_flight() {
             logical_device.destroy_semaphore(self.image_available_semaphores[i], None);
             logical_device.destroy_semaphore(self.transfer_finished_semaphores[i], None);
             logical_device.destroy_semaphore(self.render_finished_semaphores[i], None);
@@ -65,6 +63,14 @@ impl SynchronizationHandler {
         }
     }
 
+    pub fn frames_in_flight(&self) -> usize {
+        self.inflight_fences.len()
+    }
+
+    pub fn frame_index(&self) -> usize {
+        self.inflight_counter
+    }
+
     pub fn image_available_semaphore(&self) -> vk::Semaphore {
         self.image_available_semaphores[self.inflight_counter]
     }
@@ -82,6 +88,6 @@ impl SynchronizationHandler {
     }
 
     pub fn step(&mut self) {
-        self.inflight_counter = (self.inflight_counter + 1) % MAX_FRAMES_IN_FLIGHT;
+        self.inflight_counter = (self.inflight_counter + 1) % self.frames_in_flight();
     }
 }
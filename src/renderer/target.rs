@@ -1,9 +1,11 @@
 use ash::{Device, vk};
 use ash::vk::SwapchainKHR;
 
+#[allow(clippy::enum_variant_names)]
 pub enum RenderTarget {
     ImageTarget(ImageTarget),
     SwapchainTarget(SwapchainTarget),
+    TransparencyTarget(TransparencyTarget),
 }
 
 impl RenderTarget {
@@ -11,6 +13,7 @@ impl RenderTarget {
         match self {
             RenderTarget::ImageTarget(image) => { image.destroy(device); }
             RenderTarget::SwapchainTarget(swapchain) => { swapchain.destroy(device); }
+            RenderTarget::TransparencyTarget(transparency) => { transparency.destroy(device); }
         }
     }
 
@@ -21,19 +24,42 @@ impl RenderTarget {
         }
     }
 
-    pub fn image_count(&self) -> usize {
-        match self {
-            RenderTarget::ImageTarget(image_target) => { image_target.swapchain_image_count }
-            RenderTarget::SwapchainTarget(swapchain) => { swapchain.image_count() }
-        }
-    }
-
+    // The image index only selects the framebuffer of swapchain targets
     pub fn framebuffer(&self, image_index: usize) -> vk::Framebuffer {
         match self {
             RenderTarget::ImageTarget(image) => { image.framebuffer }
             RenderTarget::SwapchainTarget(swapchain) => { swapchain.framebuffers[image_index] }
+            RenderTarget::TransparencyTarget(transparency) => { transparency.framebuffer }
+        }
+    }
+}
+
+
+// Color attachment of a multisampled pass, resolved into the target image at the end of the pass
+pub struct MultisampleImage {
+    image: vk::Image,
+    image_view: vk::ImageView,
+    memory: vk::DeviceMemory,
+}
+
+impl MultisampleImage {
+    pub fn new(image: vk::Image, image_view: vk::ImageView, memory: vk::DeviceMemory) -> Self {
+        Self {
+            image,
+            image_view,
+            memory,
         }
     }
+
+    pub fn image_view(&self) -> vk::ImageView {
+        self.image_view
+    }
+
+    unsafe fn destroy(&mut self, device: &Device) {
+        device.destroy_image_view(self.image_view, None);
+        device.destroy_image(self.image, None);
+        device.free_memory(self.memory, None);
+    }
 }
 
 
@@ -41,9 +67,9 @@ pub struct ImageTarget {
     depth_image: vk::Image,
     depth_image_view: vk::ImageView,
     depth_image_memory: vk::DeviceMemory,
+    multisample_image: Option<MultisampleImage>,
 
     framebuffer: vk::Framebuffer,
-    swapchain_image_count: usize,
 }
 
 impl ImageTarget {
@@ -51,15 +77,15 @@ impl ImageTarget {
         depth_image: vk::Image,
         depth_image_view: vk::ImageView,
         depth_image_memory: vk::DeviceMemory,
+        multisample_image: Option<MultisampleImage>,
         framebuffer: vk::Framebuffer,
-        swapchain_image_count: usize,
     ) -> Self {
         Self {
             depth_image,
             depth_image_view,
             depth_image_memory,
+            multisample_image,
             framebuffer,
-            swapchain_image_count,
         }
     }
 
@@ -69,12 +95,36 @@ impl ImageTarget {
         device.destroy_image(self.depth_image, None);
         device.free_memory(self.depth_image_memory, None);
 
+        if let Some(multisample_image) = self.multisample_image.as_mut() {
+            multisample_image.destroy(device);
+        }
+
         // Framebuffer
         device.destroy_framebuffer(self.framebuffer, None);
     }
 
-    pub fn set_image_count(&mut self, swapchain_image_count: usize) {
-        self.swapchain_image_count = swapchain_image_count;
+    pub fn depth_image(&self) -> vk::Image {
+        self.depth_image
+    }
+
+    pub fn depth_image_view(&self) -> vk::ImageView {
+        self.depth_image_view
+    }
+}
+
+
+// The depth buffer is borrowed from the image pass drawing the opaque geometry and not owned by the target
+pub struct TransparencyTarget {
+    framebuffer: vk::Framebuffer,
+}
+
+impl TransparencyTarget {
+    pub fn new(framebuffer: vk::Framebuffer) -> Self {
+        Self { framebuffer }
+    }
+
+    unsafe fn destroy(&mut self, device: &Device) {
+        device.destroy_framebuffer(self.framebuffer, None);
     }
 }
 
@@ -88,11 +138,13 @@ pub struct SwapchainTarget {
     depth_image: vk::Image,
     depth_image_view: vk::ImageView,
     depth_image_memory: vk::DeviceMemory,
+    multisample_image: Option<MultisampleImage>,
 
     framebuffers: Vec<vk::Framebuffer>,
 }
 
 impl SwapchainTarget {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         swapchain_loader: ash::extensions::khr::Swapchain,
         swapchain: SwapchainKHR,
@@ -100,6 +152,7 @@ impl SwapchainTarget {
         depth_image: vk::Image,
         depth_image_view: vk::ImageView,
         depth_image_memory: vk::DeviceMemory,
+        multisample_image: Option<MultisampleImage>,
         framebuffers: Vec<vk::Framebuffer>,
     ) -> Self {
         SwapchainTarget {
@@ -109,6 +162,7 @@ impl SwapchainTarget {
             depth_image,
             depth_image_view,
             depth_image_memory,
+            multisample_image,
             framebuffers,
         }
     }
@@ -119,6 +173,10 @@ impl SwapchainTarget {
         device.destroy_image(self.depth_image, None);
         device.free_memory(self.depth_image_memory, None);
 
+        if let Some(multisample_image) = self.multisample_image.as_mut() {
+            multisample_image.destroy(device);
+        }
+
         // Color buffers
         for color_imageview in self.color_imageviews.iter() {
             device.destroy_image_view(*color_imageview, None);
@@ -1,6 +1,9 @@
-use crate::renderer::types::{SamplerHandle, TextureHandle};
+use crate::renderer::types::{
+    SamplerAddressMode, SamplerConfiguration, SamplerFilter, SamplerHandle, TextureHandle, TextureUsage,
+};
 use ash::vk;
 use ash::vk::{DeviceMemory, Image, ImageView, Sampler};
+use std::collections::HashMap;
 use std::ptr;
 
 struct Texture {
@@ -11,11 +14,30 @@ struct Texture {
     width: u32,
     height: u32,
     format: vk::Format,
+    layer_count: u32,
+    // Six layers viewed as a cube, sampled as samplerCube
+    cube: bool,
+    usage: TextureUsage,
+
+    // Render textures sized relative to the swapchain
+    render_scale: Option<f32>,
+}
+
+// Global array of combined image samplers at binding 0 of one descriptor set, indexed by the shaders of pipelines
+// built with_bindless_textures. The slots stay written for the lifetime of the context.
+struct BindlessTextures {
+    layout: vk::DescriptorSetLayout,
+    pool: vk::DescriptorPool,
+    set: vk::DescriptorSet,
+    capacity: u32,
+    indices: HashMap<(TextureHandle, SamplerHandle), u32>,
 }
 
 pub struct TextureManager {
     textures: Vec<Texture>,
     samplers: Vec<Sampler>,
+    sampler_cache: HashMap<SamplerConfiguration, SamplerHandle>,
+    bindless: Option<BindlessTextures>,
 }
 
 impl TextureManager {
@@ -23,9 +45,50 @@ impl TextureManager {
         TextureManager {
             textures: Vec::new(),
             samplers: Vec::new(),
+            sampler_cache: HashMap::new(),
+            bindless: None,
         }
     }
 
+    // Requires the descriptor indexing features, see Context::supports_bindless_textures
+    pub fn enable_bindless(&mut self, device: &ash::Device, capacity: u32) {
+        debug_assert!(self.bindless.is_none());
+
+        let layout = _create_bindless_set_layout(device, capacity);
+        let pool_sizes = [vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            descriptor_count: capacity,
+        }];
+        let pool_create_info = vk::DescriptorPoolCreateInfo::builder()
+            .flags(vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND)
+            .max_sets(1)
+            .pool_sizes(&pool_sizes)
+            .build();
+        let pool = unsafe {
+            device
+                .create_descriptor_pool(&pool_create_info, None)
+                .expect("Failed to create bindless Descriptor Pool!")
+        };
+        let layouts = [layout];
+        let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(pool)
+            .set_layouts(&layouts)
+            .build();
+        let set = unsafe {
+            device
+                .allocate_descriptor_sets(&allocate_info)
+                .expect("Failed to allocate bindless Descriptor Set!")[0]
+        };
+
+        self.bindless = Some(BindlessTextures {
+            layout,
+            pool,
+            set,
+            capacity,
+            indices: HashMap::new(),
+        });
+    }
+
     pub fn destroy(&mut self, device: &ash::Device) {
         for texture in self.textures.iter() {
             unsafe {
@@ -40,6 +103,13 @@ impl TextureManager {
                 device.destroy_sampler(*sampler, None);
             }
         }
+
+        if let Some(bindless) = self.bindless.take() {
+            unsafe {
+                device.destroy_descriptor_pool(bindless.pool, None);
+                device.destroy_descriptor_set_layout(bindless.layout, None);
+            }
+        }
     }
 
     pub fn add_texture(&mut self,
@@ -57,22 +127,165 @@ impl TextureManager {
             image_view,
             width,
             height,
-            format
+            format,
+            layer_count: 1,
+            cube: false,
+            usage: TextureUsage::UPLOADED,
+            render_scale: None,
         };
         self.textures.push(texture);
 
         handle
     }
 
-    pub fn add_sampler(&mut self, device: &ash::Device) -> SamplerHandle {
+    pub fn add_cube_texture(
+        &mut self,
+        image: Image,
+        image_memory: DeviceMemory,
+        image_view: ImageView,
+        face_size: u32,
+        format: vk::Format,
+    ) -> TextureHandle {
+        let handle = self.add_texture(image, image_memory, image_view, face_size, face_size, format);
+        self.textures[handle].layer_count = 6;
+        self.textures[handle].cube = true;
+
+        handle
+    }
+
+    pub fn is_cube(&self, texture: TextureHandle) -> bool {
+        debug_assert!(self.textures.len() > texture);
+
+        self.textures[texture].cube
+    }
+
+    pub fn add_sampler(&mut self, device: &ash::Device, cfg: SamplerConfiguration) -> SamplerHandle {
+        if let Some(&handle) = self.sampler_cache.get(&cfg) {
+            return handle;
+        }
+
         let handle = self.samplers.len();
 
-        let sampler = _create_texture_sampler(device);
+        let sampler = _create_texture_sampler(device, &cfg);
         self.samplers.push(sampler);
+        self.sampler_cache.insert(cfg, handle);
 
         handle
     }
 
+    // Index of the texture in the bindless array, the same texture and sampler share one slot
+    pub fn add_bindless_texture(
+        &mut self,
+        device: &ash::Device,
+        texture: TextureHandle,
+        sampler: SamplerHandle,
+    ) -> Result<u32, &'static str> {
+        debug_assert!(self.textures.len() > texture);
+        debug_assert!(self.samplers.len() > sampler);
+
+        let bindless = self.bindless.as_mut().ok_or("Bindless textures are not supported by the device")?;
+        if self.textures[texture].cube || self.textures[texture].layer_count > 1 {
+            return Err("Only 2D textures can be added to the bindless texture array");
+        }
+        if let Some(&index) = bindless.indices.get(&(texture, sampler)) {
+            return Ok(index);
+        }
+        let index = bindless.indices.len() as u32;
+        if index == bindless.capacity {
+            return Err("The bindless texture array is full");
+        }
+
+        bindless.indices.insert((texture, sampler), index);
+        _write_bindless_texture(
+            device,
+            bindless.set,
+            index,
+            self.textures[texture].image_view,
+            self.samplers[sampler],
+        );
+
+        Ok(index)
+    }
+
+    pub fn bindless_set(&self) -> Option<(vk::DescriptorSetLayout, vk::DescriptorSet)> {
+        self.bindless.as_ref().map(|bindless| (bindless.layout, bindless.set))
+    }
+
+    pub fn set_layer_count(&mut self, texture: TextureHandle, layer_count: u32) {
+        debug_assert!(self.textures.len() > texture);
+
+        self.textures[texture].layer_count = layer_count;
+    }
+
+    pub fn get_layer_count(&self, texture: TextureHandle) -> u32 {
+        debug_assert!(self.textures.len() > texture);
+
+        self.textures[texture].layer_count
+    }
+
+    pub fn set_usage(&mut self, texture: TextureHandle, usage: TextureUsage) {
+        debug_assert!(self.textures.len() > texture);
+
+        self.textures[texture].usage = usage;
+    }
+
+    pub fn get_usage(&self, texture: TextureHandle) -> TextureUsage {
+        debug_assert!(self.textures.len() > texture);
+
+        self.textures[texture].usage
+    }
+
+    pub fn set_render_scale(&mut self, texture: TextureHandle, render_scale: f32) {
+        debug_assert!(self.textures.len() > texture);
+
+        self.textures[texture].render_scale = Some(render_scale);
+    }
+
+    pub fn scaled_render_textures(&self) -> Vec<(TextureHandle, f32)> {
+        self.textures
+            .iter()
+            .enumerate()
+            .filter_map(|(handle, texture)| texture.render_scale.map(|render_scale| (handle, render_scale)))
+            .collect()
+    }
+
+    pub unsafe fn replace_texture(
+        &mut self,
+        device: &ash::Device,
+        texture: TextureHandle,
+        image: Image,
+        image_memory: DeviceMemory,
+        image_view: ImageView,
+        (width, height): (u32, u32),
+    ) {
+        debug_assert!(self.textures.len() > texture);
+
+        let texture_handle = texture;
+        let texture = &mut self.textures[texture];
+        device.destroy_image_view(texture.image_view, None);
+        device.destroy_image(texture.image, None);
+        device.free_memory(texture.image_memory, None);
+
+        texture.image = image;
+        texture.image_memory = image_memory;
+        texture.image_view = image_view;
+        texture.width = width;
+        texture.height = height;
+
+        if let Some(bindless) = &self.bindless {
+            let indices = bindless.indices.iter().filter(|((handle, _), _)| *handle == texture_handle);
+            for (&(_, sampler), &index) in indices {
+                _write_bindless_texture(device, bindless.set, index, image_view, self.samplers[sampler]);
+            }
+        }
+    }
+
+    pub fn get_image(&self, texture: TextureHandle) -> Image {
+        debug_assert!(self.textures.len() > texture);
+
+        self.textures[texture].image
+    }
+
     pub fn get_imageview(&self, texture: TextureHandle) -> ImageView {
         debug_assert!(self.textures.len() > texture);
 
@@ -100,25 +313,31 @@ impl TextureManager {
     }
 }
 
-fn _create_texture_sampler(device: &ash::Device) -> Sampler {
+fn _create_texture_sampler(device: &ash::Device, cfg: &SamplerConfiguration) -> Sampler {
+    let address_mode = _to_vk_address_mode(cfg.address_mode);
+    let mipmap_mode = match cfg.mipmap_filter {
+        SamplerFilter::Nearest => vk::SamplerMipmapMode::NEAREST,
+        SamplerFilter::Linear => vk::SamplerMipmapMode::LINEAR,
+    };
+
     let sampler_create_info = vk::SamplerCreateInfo {
         s_type: vk::StructureType::SAMPLER_CREATE_INFO,
         p_next: ptr::null(),
         flags: vk::SamplerCreateFlags::empty(),
-        mag_filter: vk::Filter::NEAREST,
-        min_filter: vk::Filter::LINEAR,
-        address_mode_u: vk::SamplerAddressMode::REPEAT,
-        address_mode_v: vk::SamplerAddressMode::REPEAT,
-        address_mode_w: vk::SamplerAddressMode::REPEAT,
-        max_anisotropy: 16.0,
+        mag_filter: _to_vk_filter(cfg.mag_filter),
+        min_filter: _to_vk_filter(cfg.min_filter),
+        address_mode_u: address_mode,
+        address_mode_v: address_mode,
+        address_mode_w: address_mode,
+        max_anisotropy: cfg.max_anisotropy.max(1) as f32,
         compare_enable: vk::FALSE,
         compare_op: vk::CompareOp::ALWAYS,
-        mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+        mipmap_mode,
         min_lod: 0.0,
         max_lod: 1.0,
         mip_lod_bias: 0.0,
         border_color: vk::BorderColor::INT_OPAQUE_BLACK,
-        anisotropy_enable: vk::TRUE,
+        anisotropy_enable: if cfg.max_anisotropy > 0 { vk::TRUE } else { vk::FALSE },
         unnormalized_coordinates: vk::FALSE,
     };
 
@@ -128,3 +347,69 @@ fn _create_texture_sampler(device: &ash::Device) -> Sampler {
             .expect("Failed to create Sampler!")
     }
 }
+
+fn _create_bindless_set_layout(device: &ash::Device, capacity: u32) -> vk::DescriptorSetLayout {
+    let layout_bindings = [vk::DescriptorSetLayoutBinding::builder()
+        .binding(0)
+        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+        .descriptor_count(capacity)
+        .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+        .build()];
+    // Slots past the registered textures are never written
+    let binding_flags =
+        [vk::DescriptorBindingFlags::PARTIALLY_BOUND | vk::DescriptorBindingFlags::UPDATE_AFTER_BIND];
+    let mut binding_flags_create_info =
+        vk::DescriptorSetLayoutBindingFlagsCreateInfo::builder().binding_flags(&binding_flags);
+
+    let layout_create_info = vk::DescriptorSetLayoutCreateInfo::builder()
+        .flags(vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL)
+        .bindings(&layout_bindings)
+        .push_next(&mut binding_flags_create_info)
+        .build();
+
+    unsafe {
+        device
+            .create_descriptor_set_layout(&layout_create_info, None)
+            .expect("Failed to create bindless Descriptor Set Layout!")
+    }
+}
+
+fn _write_bindless_texture(
+    device: &ash::Device,
+    set: vk::DescriptorSet,
+    index: u32,
+    image_view: ImageView,
+    sampler: Sampler,
+) {
+    let image_infos = [vk::DescriptorImageInfo {
+        sampler,
+        image_view,
+        image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+    }];
+    let descriptor_writes = [vk::WriteDescriptorSet::builder()
+        .dst_set(set)
+        .dst_binding(0)
+        .dst_array_element(index)
+        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+        .image_info(&image_infos)
+        .build()];
+
+    unsafe {
+        device.update_descriptor_sets(&descriptor_writes, &[]);
+    }
+}
+
+fn _to_vk_filter(filter: SamplerFilter) -> vk::Filter {
+    match filter {
+        SamplerFilter::Nearest => vk::Filter::NEAREST,
+        SamplerFilter::Linear => vk::Filter::LINEAR,
+    }
+}
+
+fn _to_vk_address_mode(address_mode: SamplerAddressMode) -> vk::SamplerAddressMode {
+    match address_mode {
+        SamplerAddressMode::Repeat => vk::SamplerAddressMode::REPEAT,
+        SamplerAddressMode::MirroredRepeat => vk::SamplerAddressMode::MIRRORED_REPEAT,
+        SamplerAddressMode::ClampToEdge => vk::SamplerAddressMode::CLAMP_TO_EDGE,
+    }
+}
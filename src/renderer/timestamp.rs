@@ -0,0 +1,123 @@
+use std::time::Duration;
+
+use ash::vk;
+
+// GPU time of the draw command buffers, measured with a timestamp at the start and one at the end. Every
+// command buffer has its own pair of queries, so that results are read once it has finished executing.
+pub(super) struct GpuTimer {
+    query_pool: vk::QueryPool,
+    // Nanoseconds per tick
+    timestamp_period: f32,
+    valid_bits: u32,
+    written: Vec<bool>,
+}
+
+impl GpuTimer {
+    // None if the queue family does not support timestamps
+    pub(super) fn new(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        device: &ash::Device,
+        queue_family_index: u32,
+        command_buffer_count: usize,
+    ) -> Option<Self> {
+        let (timestamp_period, valid_bits) = unsafe {
+            let properties = instance.get_physical_device_properties(physical_device);
+            let queue_families = instance.get_physical_device_queue_family_properties(physical_device);
+            (
+                properties.limits.timestamp_period,
+                queue_families[queue_family_index as usize].timestamp_valid_bits,
+            )
+        };
+        if valid_bits == 0 || timestamp_period <= 0.0 {
+            log_warning!("GPU timestamps not supported, GPU frame time unavailable");
+            return None;
+        }
+
+        let query_pool_create_info = vk::QueryPoolCreateInfo::builder()
+            .query_type(vk::QueryType::TIMESTAMP)
+            .query_count(command_buffer_count as u32 * 2);
+        let query_pool = unsafe {
+            device
+                .create_query_pool(&query_pool_create_info, None)
+                .expect("Failed to create Query Pool!")
+        };
+
+        Some(GpuTimer {
+            query_pool,
+            timestamp_period,
+            valid_bits,
+            written: vec![false; command_buffer_count],
+        })
+    }
+
+    // Recorded first in the command buffer
+    pub(super) unsafe fn begin(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, index: usize) {
+        let first_query = index as u32 * 2;
+        device.cmd_reset_query_pool(command_buffer, self.query_pool, first_query, 2);
+        device.cmd_write_timestamp(command_buffer, vk::PipelineStageFlags::TOP_OF_PIPE, self.query_pool, first_query);
+    }
+
+    // Recorded last in the command buffer
+    pub(super) unsafe fn end(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, index: usize) {
+        device.cmd_write_timestamp(
+            command_buffer,
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            self.query_pool,
+            index as u32 * 2 + 1,
+        );
+    }
+
+    pub(super) fn set_submitted(&mut self, index: usize) {
+        self.written[index] = true;
+    }
+
+    // Time of the last submission of the command buffer, None before its first submission or if it is still
+    // executing
+    pub(super) fn elapsed(&self, device: &ash::Device, index: usize) -> Option<Duration> {
+        if !self.written[index] {
+            return None;
+        }
+
+        let mut timestamps = [0_u64; 2];
+        unsafe {
+            device
+                .get_query_pool_results(
+                    self.query_pool,
+                    index as u32 * 2,
+                    2,
+                    &mut timestamps,
+                    vk::QueryResultFlags::TYPE_64,
+                )
+                .ok()?;
+        }
+
+        Some(_elapsed(timestamps[0], timestamps[1], self.valid_bits, self.timestamp_period))
+    }
+
+    pub(super) unsafe fn destroy(&mut self, device: &ash::Device) {
+        device.destroy_query_pool(self.query_pool, None);
+    }
+}
+
+// Only the valid bits of the timestamps are defined, the counter may wrap around between them
+fn _elapsed(start: u64, end: u64, valid_bits: u32, timestamp_period: f32) -> Duration {
+    let mask = if valid_bits >= 64 { u64::MAX } else { (1 << valid_bits) - 1 };
+    let ticks = (end & mask).wrapping_sub(start & mask) & mask;
+
+    Duration::from_nanos((ticks as f64 * timestamp_period as f64) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elapsed_ticks() {
+        assert_eq!(_elapsed(1000, 3000, 64, 1.0), Duration::from_nanos(2000));
+        assert_eq!(_elapsed(1000, 3000, 64, 2.5), Duration::from_nanos(5000));
+        // Wrapped around the 36 valid bits, the upper bits are undefined
+        let wrap = 1_u64 << 36;
+        assert_eq!(_elapsed(wrap - 10, 5 | (7 << 40), 36, 1.0), Duration::from_nanos(15));
+    }
+}
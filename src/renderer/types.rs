@@ -1,18 +1,143 @@
+use std::any::Any;
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::marker::PhantomData;
+use std::path::Path;
 use std::ptr;
 use crate::renderer::rawarray::RawArrayPtr;
-use crate::renderer::types::DrawData::Buffered;
+use crate::renderer::reflection::ShaderReflection;
+use crate::renderer::shader;
+use crate::renderer::vertex::VertexLayout;
+use crate::renderer::types::DrawData::{Buffered, Dynamic};
 use ash::vk;
 use ash::vk::{Buffer, ImageView, PrimitiveTopology, Sampler};
 
+//
+// Context
+//
+// Extra extensions and device features for integrations like OpenXR or external memory, see
+// Context::new_with_configuration and VulkrapApplication::context_configuration.
+#[derive(Default)]
+pub struct ContextConfiguration {
+    pub(super) instance_extensions: Vec<CString>,
+    pub(super) device_extensions: Vec<CString>,
+    pub(super) device_features: Vec<*mut vk::BaseOutStructure>,
+    // Owns the structs device_features points into
+    device_feature_storage: Vec<Box<dyn Any>>,
+    pub(super) frames_in_flight: Option<usize>,
+    validation: Option<bool>,
+    pub(super) gpu_validation: bool,
+}
+
+// Frames recorded on the CPU while the GPU still works on earlier ones. More smooth out spikes at the cost of
+// input latency, see Context::set_frames_in_flight.
+pub const DEFAULT_FRAMES_IN_FLIGHT: usize = 3;
+pub const MAX_FRAMES_IN_FLIGHT: usize = 4;
+
+impl ContextConfiguration {
+    pub fn new() -> Self {
+        ContextConfiguration::default()
+    }
+
+    pub fn with_instance_extension(mut self, name: &CStr) -> Self {
+        self.instance_extensions.push(name.to_owned());
+
+        self
+    }
+
+    pub fn with_device_extension(mut self, name: &CStr) -> Self {
+        self.device_extensions.push(name.to_owned());
+
+        self
+    }
+
+    // Chained into the p_next of VkDeviceCreateInfo, its own p_next is overwritten. Must not be one the engine
+    // chains itself: PhysicalDeviceFeatures2 (features are given with enabled_features) or PhysicalDeviceMultiviewFeatures.
+    pub fn with_device_feature<T: vk::ExtendsDeviceCreateInfo + 'static>(mut self, feature: T) -> Self {
+        let mut feature = Box::new(feature);
+        self.device_features
+            .push(feature.as_mut() as *mut T as *mut vk::BaseOutStructure);
+        self.device_feature_storage.push(feature);
+
+        self
+    }
+
+    // Between 1 and MAX_FRAMES_IN_FLIGHT, DEFAULT_FRAMES_IN_FLIGHT when not set
+    pub fn with_frames_in_flight(mut self, frames_in_flight: usize) -> Self {
+        self.frames_in_flight = Some(frames_in_flight);
+
+        self
+    }
+
+    // The Khronos validation layer and the debug messenger logging its messages, plus the engine's own checks of the
+    // image layouts of the passes every frame. When not set only in debug builds.
+    pub fn with_validation(mut self, validation: bool) -> Self {
+        self.validation = Some(validation);
+
+        self
+    }
+
+    // GPU-assisted validation instruments the shaders to catch e.g. out of bounds descriptor indexing, which is
+    // slow. Enables the validation layer too.
+    pub fn with_gpu_validation(mut self, gpu_validation: bool) -> Self {
+        self.gpu_validation = gpu_validation;
+
+        self
+    }
+
+    pub(super) fn validation(&self) -> bool {
+        self.validation.unwrap_or(cfg!(debug_assertions)) || self.gpu_validation
+    }
+}
+
 //
 // Render pass
 //
 pub const SWAPCHAIN_PASS: RenderPassHandle = 100_000;
 
+// Samples per pixel of a render pass, passes with more than one resolve into their target at the end
+#[derive(Clone, Debug, Copy, PartialEq, Eq, Hash, Default)]
+pub enum SampleCount {
+    #[default]
+    One,
+    Two,
+    Four,
+    Eight,
+}
+
+impl SampleCount {
+    pub fn from_count(count: u32) -> Option<Self> {
+        match count {
+            1 => Some(SampleCount::One),
+            2 => Some(SampleCount::Two),
+            4 => Some(SampleCount::Four),
+            8 => Some(SampleCount::Eight),
+            _ => None,
+        }
+    }
+
+    pub fn count(&self) -> u32 {
+        self.flags().as_raw()
+    }
+
+    pub(super) fn flags(&self) -> vk::SampleCountFlags {
+        match self {
+            SampleCount::One => vk::SampleCountFlags::TYPE_1,
+            SampleCount::Two => vk::SampleCountFlags::TYPE_2,
+            SampleCount::Four => vk::SampleCountFlags::TYPE_4,
+            SampleCount::Eight => vk::SampleCountFlags::TYPE_8,
+        }
+    }
+
+    pub(super) fn is_multisampled(&self) -> bool {
+        *self != SampleCount::One
+    }
+}
+
 pub type RenderPassHandle = u32;
 
 #[repr(C)]
-#[derive(Clone, Debug, Copy)]
+#[derive(Clone, Debug, Copy, PartialEq, Eq)]
 pub struct PipelineHandle {
     pub(super) render_pass: RenderPassHandle,
     pipeline_index: u32,
@@ -36,22 +161,289 @@ impl PipelineHandle {
 //
 pub type TextureHandle = usize;
 pub type SamplerHandle = usize;
+pub type SkinnedMeshHandle = usize;
+pub type CulledInstancesHandle = usize;
+
+// Buffers written by the occlusion culling pre-pass: an indexed indirect draw and the indices of the visible
+// instances, drawn as the instance rate stream of a CulledInstance
+#[derive(Clone, Debug, Copy, PartialEq, Eq)]
+pub struct CulledInstances {
+    pub(super) indirect_buffer: Buffer,
+    pub(super) visible_buffer: Buffer,
+    pub(super) instance_count: u32,
+}
+
+#[derive(Clone, Debug, Copy, PartialEq, Eq, Hash)]
+pub enum SamplerFilter {
+    Nearest,
+    Linear,
+}
+
+#[derive(Clone, Debug, Copy, PartialEq, Eq, Hash)]
+pub enum SamplerAddressMode {
+    Repeat,
+    MirroredRepeat,
+    ClampToEdge,
+}
+
+#[derive(Clone, Debug, Copy, PartialEq, Eq, Hash)]
+pub struct SamplerConfiguration {
+    pub(super) mag_filter: SamplerFilter,
+    pub(super) min_filter: SamplerFilter,
+    pub(super) mipmap_filter: SamplerFilter,
+    pub(super) address_mode: SamplerAddressMode,
+    pub(super) max_anisotropy: u8,
+}
+
+impl SamplerConfiguration {
+    pub fn new() -> Self {
+        SamplerConfiguration {
+            mag_filter: SamplerFilter::Nearest,
+            min_filter: SamplerFilter::Linear,
+            mipmap_filter: SamplerFilter::Nearest,
+            address_mode: SamplerAddressMode::Repeat,
+            max_anisotropy: 16,
+        }
+    }
+
+    pub fn with_filter(mut self, mag_filter: SamplerFilter, min_filter: SamplerFilter) -> Self {
+        self.mag_filter = mag_filter;
+        self.min_filter = min_filter;
+
+        self
+    }
+
+    pub fn with_mipmap_filter(mut self, mipmap_filter: SamplerFilter) -> Self {
+        self.mipmap_filter = mipmap_filter;
+
+        self
+    }
+
+    pub fn with_address_mode(mut self, address_mode: SamplerAddressMode) -> Self {
+        self.address_mode = address_mode;
+
+        self
+    }
+
+    // 0 disables anisotropic filtering
+    pub fn with_max_anisotropy(mut self, max_anisotropy: u8) -> Self {
+        self.max_anisotropy = max_anisotropy;
+
+        self
+    }
+}
+
+impl Default for SamplerConfiguration {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 //
 // Buffers
 //
-pub type BufferObjectHandle = usize;
+// Typed by the data pushed into the buffer object, so pushing the wrong struct is a compile error.
+pub struct BufferObjectHandle<T> {
+    index: usize,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> BufferObjectHandle<T> {
+    pub(super) fn new(index: usize) -> Self {
+        BufferObjectHandle {
+            index,
+            _marker: PhantomData,
+        }
+    }
+
+    pub(super) fn index(&self) -> usize {
+        self.index
+    }
+}
+
+impl<T> Clone for BufferObjectHandle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for BufferObjectHandle<T> {}
+
+impl<T> PartialEq for BufferObjectHandle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+    }
+}
+
+impl<T> Eq for BufferObjectHandle<T> {}
+
+impl<T> fmt::Debug for BufferObjectHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "BufferObjectHandle<{}>({})", std::any::type_name::<T>(), self.index)
+    }
+}
 
 //
 // Pipeline
 //
-pub type UniformHandle = usize;
+pub type UniformHandle<T> = BufferObjectHandle<T>;
 
 pub trait VertexInputDescription {
     fn binding_descriptions() -> Vec<vk::VertexInputBindingDescription>;
     fn attribute_descriptions() -> Vec<vk::VertexInputAttributeDescription>;
 }
 
+// Two vertex streams, e.g. mesh vertices and per instance data. The bindings and locations of the second
+// stream follow the ones of the first, its buffers are set with DrawCommand::with_vertex_buffer.
+impl<A: VertexInputDescription, B: VertexInputDescription> VertexInputDescription for (A, B) {
+    fn binding_descriptions() -> Vec<vk::VertexInputBindingDescription> {
+        let first = A::binding_descriptions();
+        let binding_offset = first.len() as u32;
+        let second = B::binding_descriptions().into_iter().map(|description| vk::VertexInputBindingDescription {
+            binding: description.binding + binding_offset,
+            ..description
+        });
+
+        first.into_iter().chain(second).collect()
+    }
+
+    fn attribute_descriptions() -> Vec<vk::VertexInputAttributeDescription> {
+        let first = A::attribute_descriptions();
+        let binding_offset = A::binding_descriptions().len() as u32;
+        let location_offset = first.iter().map(|description| description.location + 1).max().unwrap_or(0);
+        let second = B::attribute_descriptions().into_iter().map(|description| vk::VertexInputAttributeDescription {
+            binding: description.binding + binding_offset,
+            location: description.location + location_offset,
+            ..description
+        });
+
+        first.into_iter().chain(second).collect()
+    }
+}
+
+#[derive(Clone, Debug, Copy, PartialEq, Eq)]
+pub(super) enum BlendMode {
+    Opaque,
+    AlphaBlending,
+    SortedAlphaBlending,
+    WeightedBlended,
+    ConstantBlending,
+    DualSourceBlending,
+}
+
+impl BlendMode {
+    pub(super) fn writes_depth(&self) -> bool {
+        matches!(
+            self,
+            BlendMode::Opaque | BlendMode::AlphaBlending | BlendMode::ConstantBlending | BlendMode::DualSourceBlending
+        )
+    }
+}
+
+// Channels of the color attachment a pipeline writes, the others keep their value. Applies to blended pipelines as
+// well, e.g. RGB keeps the alpha of the target while blending the color.
+#[derive(Clone, Debug, Copy, PartialEq, Eq, Hash)]
+pub struct ColorWriteMask {
+    pub red: bool,
+    pub green: bool,
+    pub blue: bool,
+    pub alpha: bool,
+}
+
+impl ColorWriteMask {
+    pub const RGBA: ColorWriteMask = ColorWriteMask::new(true, true, true, true);
+    pub const RGB: ColorWriteMask = ColorWriteMask::new(true, true, true, false);
+    pub const ALPHA: ColorWriteMask = ColorWriteMask::new(false, false, false, true);
+    // Only depth is written, e.g. a depth prepass in a color pass
+    pub const NONE: ColorWriteMask = ColorWriteMask::new(false, false, false, false);
+
+    pub const fn new(red: bool, green: bool, blue: bool, alpha: bool) -> Self {
+        ColorWriteMask {
+            red,
+            green,
+            blue,
+            alpha,
+        }
+    }
+}
+
+// Usage of a render texture, fixed when the image is created. Render textures are kept in the shader read only
+// layout between passes, so they are always sampled.
+#[derive(Clone, Debug, Copy, PartialEq, Eq, Hash)]
+pub struct TextureUsage {
+    pub sampled: bool,
+    pub color_target: bool,
+    // Written by compute pipelines, see Context::add_storage_texture
+    pub storage: bool,
+    // Read back or blitted from, see Context::read_render_texture
+    pub transfer_src: bool,
+    pub transfer_dst: bool,
+}
+
+impl TextureUsage {
+    // Textures uploaded by Context::add_texture and its array and cube variants
+    pub const UPLOADED: TextureUsage = TextureUsage::new(true, false, false, false, true);
+    pub const RENDER_TARGET: TextureUsage = TextureUsage::new(true, true, false, true, false);
+    pub const STORAGE: TextureUsage = TextureUsage::new(true, false, true, false, false);
+
+    pub const fn new(sampled: bool, color_target: bool, storage: bool, transfer_src: bool, transfer_dst: bool) -> Self {
+        TextureUsage {
+            sampled,
+            color_target,
+            storage,
+            transfer_src,
+            transfer_dst,
+        }
+    }
+
+    pub fn with_storage(self) -> Self {
+        TextureUsage { storage: true, ..self }
+    }
+
+    pub fn with_transfer_dst(self) -> Self {
+        TextureUsage { transfer_dst: true, ..self }
+    }
+
+    pub(super) fn image_usage_flags(&self) -> vk::ImageUsageFlags {
+        let mut flags = vk::ImageUsageFlags::empty();
+        if self.sampled {
+            flags |= vk::ImageUsageFlags::SAMPLED;
+        }
+        if self.color_target {
+            flags |= vk::ImageUsageFlags::COLOR_ATTACHMENT;
+        }
+        if self.storage {
+            flags |= vk::ImageUsageFlags::STORAGE;
+        }
+        if self.transfer_src {
+            flags |= vk::ImageUsageFlags::TRANSFER_SRC;
+        }
+        if self.transfer_dst {
+            flags |= vk::ImageUsageFlags::TRANSFER_DST;
+        }
+
+        flags
+    }
+
+    // Transfers are supported by every format on Vulkan 1.0, the transfer format features only exist from 1.1 on
+    pub(super) fn validate(&self, format_features: vk::FormatFeatureFlags) -> Result<(), &'static str> {
+        if !self.sampled {
+            return Err("Render textures must be sampled!");
+        }
+        if !format_features.contains(vk::FormatFeatureFlags::SAMPLED_IMAGE) {
+            return Err("Texture format can't be sampled on this device!");
+        }
+        if self.color_target && !format_features.contains(vk::FormatFeatureFlags::COLOR_ATTACHMENT) {
+            return Err("Texture format can't be rendered to on this device!");
+        }
+        if self.storage && !format_features.contains(vk::FormatFeatureFlags::STORAGE_IMAGE) {
+            return Err("Texture format can't be used as storage image on this device!");
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Clone, Debug, Copy)]
 pub enum VertexTopology {
     Triangle,
@@ -59,46 +451,82 @@ pub enum VertexTopology {
 }
 
 pub struct PipelineConfiguration {
+    pub(super) name: Option<String>,
     pub(super) vertex_shader_code: Vec<u8>,
     pub(super) fragment_shader_code: Vec<u8>,
     pub(super) push_constant_buffer_size: Option<usize>,
     pub(super) vertex_topology: VertexTopology,
     pub(super) vertex_uniform_cfg: Option<BufferObjectConfiguration>,
+    pub(super) pass_camera_binding: Option<u8>,
     pub(super) fragment_uniform_cfg: Option<BufferObjectConfiguration>,
     pub(super) storage_buffer_cfg: Option<BufferObjectConfiguration>,
     pub(super) texture_cfgs: Vec<TextureConfiguration>,
-    pub(super) alpha_blending: bool,
+    pub(super) pushed_texture_binding: Option<u8>,
+    pub(super) bindless_textures: bool,
+    pub(super) frame_globals_binding: Option<u8>,
+    pub(super) blend_mode: BlendMode,
+    pub(super) depth_test: bool,
+    pub(super) color_write_mask: ColorWriteMask,
+    pub(super) sample_count: Option<SampleCount>,
+    // Set when the pipeline is added, see Context::add_pipeline_with_layout
+    pub(super) vertex_layout: VertexLayout,
 }
 
 impl PipelineConfiguration {
     pub fn builder() -> PipelineConfigurationBuilder {
         PipelineConfigurationBuilder {
+            name: None,
             vertex_shader_code: None,
             fragment_shader_code: None,
             push_constant_buffer_size: None,
             vertex_topology: None,
             vertex_uniform_cfg: None,
+            pass_camera_binding: None,
             fragment_uniform_cfg: None,
             storage_buffer_cfg: None,
             texture_cfgs: Vec::new(),
-            alpha_blending: false,
+            pushed_texture_binding: None,
+            bindless_textures: false,
+            frame_globals_binding: None,
+            blend_mode: BlendMode::Opaque,
+            depth_test: true,
+            color_write_mask: ColorWriteMask::RGBA,
+            sample_count: None,
+            vertex_shader_failed: false,
         }
     }
 }
 
 pub struct PipelineConfigurationBuilder {
+    name: Option<String>,
     vertex_shader_code: Option<Vec<u8>>,
     fragment_shader_code: Option<Vec<u8>>,
     push_constant_buffer_size: Option<usize>,
     vertex_topology: Option<VertexTopology>,
     vertex_uniform_cfg: Option<BufferObjectConfiguration>,
+    pass_camera_binding: Option<u8>,
     fragment_uniform_cfg: Option<BufferObjectConfiguration>,
     storage_buffer_cfg: Option<BufferObjectConfiguration>,
     texture_cfgs: Vec<TextureConfiguration>,
-    alpha_blending: bool,
+    pushed_texture_binding: Option<u8>,
+    bindless_textures: bool,
+    frame_globals_binding: Option<u8>,
+    blend_mode: BlendMode,
+    depth_test: bool,
+    color_write_mask: ColorWriteMask,
+    sample_count: Option<SampleCount>,
+    // Set by with_vertex_shader_file, build makes an error pipeline then
+    vertex_shader_failed: bool,
 }
 
 impl PipelineConfigurationBuilder {
+    // Shown by debugging tools like the dumpframe command
+    pub fn with_name(&mut self, name: &str) -> &mut Self {
+        self.name = Some(name.to_string());
+
+        self
+    }
+
     pub fn with_fragment_shader(&mut self, code: Vec<u8>) -> &mut Self {
         self.fragment_shader_code = Some(code);
 
@@ -107,10 +535,30 @@ impl PipelineConfigurationBuilder {
 
     pub fn with_vertex_shader(&mut self, code: Vec<u8>) -> &mut Self {
         self.vertex_shader_code = Some(code);
+        self.vertex_shader_failed = false;
 
         self
     }
 
+    // A file that fails to load is logged and the pipeline becomes an error pipeline, see build
+    pub fn with_vertex_shader_file(&mut self, path: &Path) -> &mut Self {
+        match shader::load_shader(path) {
+            Ok(code) => self.with_vertex_shader(code),
+            Err(error) => {
+                log_error!("{}, using the error pipeline", error);
+                self.vertex_shader_code = Some(shader::FALLBACK_VERTEX_SHADER.to_vec());
+                self.vertex_shader_failed = true;
+
+                self
+            }
+        }
+    }
+
+    // A file that fails to load is logged and replaced by the fallback shader, which draws magenta
+    pub fn with_fragment_shader_file(&mut self, path: &Path) -> &mut Self {
+        self.with_fragment_shader(shader::load_shader_or_fallback(path, shader::FALLBACK_FRAGMENT_SHADER))
+    }
+
     pub fn with_push_constant<T>(&mut self) -> &mut Self {
         self.push_constant_buffer_size = Some(std::mem::size_of::<T>());
 
@@ -123,26 +571,93 @@ impl PipelineConfigurationBuilder {
         self
     }
 
-    pub fn with_vertex_uniform(&mut self, binding: u8, buffer_object_handle: BufferObjectHandle) -> &mut Self {
-        self.vertex_uniform_cfg = Some(BufferObjectConfiguration::new(binding, buffer_object_handle));
+    pub fn with_vertex_uniform<T>(&mut self, binding: u8, buffer_object_handle: BufferObjectHandle<T>) -> &mut Self {
+        self.vertex_uniform_cfg = Some(BufferObjectConfiguration::new::<T>(binding, buffer_object_handle.index()));
 
         self
     }
 
-    pub fn with_fragment_uniform(&mut self, binding: u8, buffer_object_handle: BufferObjectHandle) -> &mut Self {
-        self.fragment_uniform_cfg = Some(BufferObjectConfiguration::new(binding, buffer_object_handle));
+    // Binds the camera uniform of the render pass the pipeline is added to as vertex uniform,
+    // see Context::set_render_pass_camera
+    pub fn with_pass_camera(&mut self, binding: u8) -> &mut Self {
+        self.pass_camera_binding = Some(binding);
 
         self
     }
 
-    pub fn with_storage_buffer_object(&mut self, binding: u8, buffer_object_handle: BufferObjectHandle) -> &mut Self {
-        self.storage_buffer_cfg = Some(BufferObjectConfiguration::new(binding, buffer_object_handle));
+    pub fn with_fragment_uniform<T>(&mut self, binding: u8, buffer_object_handle: BufferObjectHandle<T>) -> &mut Self {
+        self.fragment_uniform_cfg = Some(BufferObjectConfiguration::new::<T>(binding, buffer_object_handle.index()));
+
+        self
+    }
+
+    pub fn with_storage_buffer_object<T>(
+        &mut self,
+        binding: u8,
+        buffer_object_handle: BufferObjectHandle<T>,
+    ) -> &mut Self {
+        self.storage_buffer_cfg = Some(BufferObjectConfiguration::new::<T>(binding, buffer_object_handle.index()));
 
         self
     }
 
     pub fn with_alpha_blending(&mut self) -> &mut Self {
-        self.alpha_blending = true;
+        self.blend_mode = BlendMode::AlphaBlending;
+
+        self
+    }
+
+    // Alpha blended without depth writes. The pass draws these after all other draws, sorted back to front by
+    // the depth given with DrawCommand::with_depth.
+    pub fn with_transparency(&mut self) -> &mut Self {
+        self.blend_mode = BlendMode::SortedAlphaBlending;
+
+        self
+    }
+
+    // Order independent transparency. Fragment shaders write the weighted premultiplied color to location 0 and
+    // the alpha to location 1, the pipeline must be added to a pass created with Context::create_transparency_pass.
+    pub fn with_weighted_blended_transparency(&mut self) -> &mut Self {
+        self.blend_mode = BlendMode::WeightedBlended;
+
+        self
+    }
+
+    // Mixes the color with the target per channel by the blend constants, constant * color + (1 - constant) * target,
+    // e.g. crossfades. See Context::set_blend_constants and DrawCommand::with_blend_constants.
+    pub fn with_constant_blending(&mut self) -> &mut Self {
+        self.blend_mode = BlendMode::ConstantBlending;
+
+        self
+    }
+
+    // Fragment shaders write the color premultiplied by the coverage to location 0 index 0 and the coverage per
+    // channel to location 0 index 1, e.g. subpixel font rendering. Check Context::supports_dual_source_blending.
+    pub fn with_dual_source_blending(&mut self) -> &mut Self {
+        self.blend_mode = BlendMode::DualSourceBlending;
+
+        self
+    }
+
+    // Neither tests nor writes depth, the draws cover everything drawn before them in the pass, e.g. labels that
+    // stay visible behind walls
+    pub fn without_depth_test(&mut self) -> &mut Self {
+        self.depth_test = false;
+
+        self
+    }
+
+    // Not supported by weighted blended transparency, its targets need all of their channels
+    pub fn with_color_write_mask(&mut self, color_write_mask: ColorWriteMask) -> &mut Self {
+        self.color_write_mask = color_write_mask;
+
+        self
+    }
+
+    // Pipelines follow the sample count of their render pass, a count set here is checked against it when the
+    // pipeline is added
+    pub fn with_sample_count(&mut self, sample_count: SampleCount) -> &mut Self {
+        self.sample_count = Some(sample_count);
 
         self
     }
@@ -154,23 +669,261 @@ impl PipelineConfigurationBuilder {
         self
     }
 
-    pub fn build(&mut self) -> PipelineConfiguration {
+    // Cube texture of Context::add_cube_texture, declared as samplerCube in the shader
+    pub fn add_cube_texture(&mut self, binding: u8, texture: TextureHandle, sampler: SamplerHandle) -> &mut Self {
+        self.texture_cfgs.push(
+            TextureConfiguration::new(binding, texture, sampler).with_binding_type(SamplerBindingType::SamplerCube),
+        );
+
+        self
+    }
+
+    // The texture is taken from each DrawCommand and bound with VK_KHR_push_descriptor at descriptor set 1.
+    pub fn with_pushed_texture(&mut self, binding: u8) -> &mut Self {
+        self.pushed_texture_binding = Some(binding);
+
+        self
+    }
+
+    // Binds the global texture array of Context::add_bindless_texture at binding 0 of the set after the pushed
+    // texture one, set 1 or 2. The push constants are visible to the fragment shader too, to pass it the indices.
+    pub fn with_bindless_textures(&mut self) -> &mut Self {
+        self.bindless_textures = true;
+
+        self
+    }
+
+    // Binds the engine managed FrameGlobals uniform, visible to both vertex and fragment stage.
+    pub fn with_frame_globals(&mut self, binding: u8) -> &mut Self {
+        self.frame_globals_binding = Some(binding);
+
+        self
+    }
+
+    pub fn build(&mut self) -> Result<PipelineConfiguration, PipelineConfigError> {
         // TODO Load a default shader if not present
-        let vertex_shader_code = self.vertex_shader_code.as_ref().expect("error").clone();
-        let fragment_shader_code = self.fragment_shader_code.as_ref().expect("error").clone();
+        let mut vertex_shader_code = self
+            .vertex_shader_code
+            .clone()
+            .ok_or(PipelineConfigError::MissingVertexShader)?;
+        let mut fragment_shader_code = self
+            .fragment_shader_code
+            .clone()
+            .ok_or(PipelineConfigError::MissingFragmentShader)?;
+
+        // The error pipeline draws in the magenta of the fallback fragment shader. Without the usual model transform
+        // push constant and view projection uniform at binding 0 the geometry can't be placed and nothing is drawn.
+        if self.vertex_shader_failed {
+            if self.has_transform_and_camera() {
+                vertex_shader_code = shader::FALLBACK_TRANSFORM_VERTEX_SHADER.to_vec();
+            }
+            fragment_shader_code = shader::FALLBACK_FRAGMENT_SHADER.to_vec();
+        }
+
+        if self.vertex_uniform_cfg.is_some() && self.pass_camera_binding.is_some() {
+            return Err(PipelineConfigError::ConflictingVertexUniform);
+        }
+        if self.blend_mode == BlendMode::WeightedBlended && self.color_write_mask != ColorWriteMask::RGBA {
+            return Err(PipelineConfigError::UnsupportedColorWriteMask);
+        }
+
+        let mut bindings: Vec<u8> = self
+            .vertex_uniform_cfg
+            .iter()
+            .chain(self.fragment_uniform_cfg.iter())
+            .chain(self.storage_buffer_cfg.iter())
+            .map(|cfg| cfg.binding)
+            .chain(self.texture_cfgs.iter().map(|cfg| cfg.binding))
+            .chain(self.frame_globals_binding)
+            .chain(self.pass_camera_binding)
+            .collect();
+        bindings.sort_unstable();
+        if let Some(binding) = bindings.windows(2).find(|pair| pair[0] == pair[1]) {
+            return Err(PipelineConfigError::DuplicateBinding(binding[0]));
+        }
+
+        // Catches Rust types that don't match the blocks declared in the shaders
+        let vertex_reflection =
+            ShaderReflection::parse(&vertex_shader_code).map_err(PipelineConfigError::InvalidShaderCode)?;
+        let fragment_reflection =
+            ShaderReflection::parse(&fragment_shader_code).map_err(PipelineConfigError::InvalidShaderCode)?;
+        let frame_globals_size = std::mem::size_of::<FrameGlobals>();
+        // The error pipeline only reads the start of the vertex stage blocks, their sizes are not validated
+        let reflections = if self.vertex_shader_failed {
+            vec![(&fragment_reflection, self.fragment_uniform_cfg)]
+        } else {
+            vec![(&vertex_reflection, self.vertex_uniform_cfg), (&fragment_reflection, self.fragment_uniform_cfg)]
+        };
+        for (reflection, uniform_cfg) in reflections {
+            if let Some(cfg) = uniform_cfg {
+                _validate_block_size(reflection.uniform_block_size(cfg.binding), cfg.size, cfg.binding)?;
+            }
+            if let Some(binding) = self.frame_globals_binding {
+                _validate_block_size(reflection.uniform_block_size(binding), frame_globals_size, binding)?;
+            }
+        }
+        match (vertex_reflection.push_constant_size(), self.push_constant_buffer_size) {
+            _ if self.vertex_shader_failed => {}
+            (Some(shader_size), None) => return Err(PipelineConfigError::MissingPushConstant(shader_size)),
+            (Some(shader_size), Some(size)) if !_block_size_matches(shader_size, size) => {
+                return Err(PipelineConfigError::PushConstantSizeMismatch { shader_size, size })
+            }
+            _ => {}
+        }
 
         let vertex_topology = self.vertex_topology.unwrap_or(VertexTopology::Triangle);
 
-        PipelineConfiguration {
+        Ok(PipelineConfiguration {
+            name: self.name.clone(),
             vertex_shader_code,
             fragment_shader_code,
             push_constant_buffer_size: self.push_constant_buffer_size.take(),
             vertex_topology,
             vertex_uniform_cfg: self.vertex_uniform_cfg,
+            pass_camera_binding: self.pass_camera_binding,
             fragment_uniform_cfg: self.fragment_uniform_cfg,
             storage_buffer_cfg: self.storage_buffer_cfg,
             texture_cfgs: self.texture_cfgs.clone(),
-            alpha_blending: self.alpha_blending,
+            pushed_texture_binding: self.pushed_texture_binding,
+            bindless_textures: self.bindless_textures,
+            frame_globals_binding: self.frame_globals_binding,
+            blend_mode: self.blend_mode,
+            depth_test: self.depth_test,
+            color_write_mask: self.color_write_mask,
+            sample_count: self.sample_count,
+            vertex_layout: VertexLayout::new(),
+        })
+    }
+
+    fn has_transform_and_camera(&self) -> bool {
+        let matrix_size = std::mem::size_of::<[[f32; 4]; 4]>();
+        self.push_constant_buffer_size.is_some_and(|size| size >= matrix_size)
+            && (self.pass_camera_binding == Some(0)
+                || self.vertex_uniform_cfg.is_some_and(|cfg| cfg.binding == 0 && cfg.size >= 2 * matrix_size))
+    }
+}
+
+impl PipelineConfiguration {
+    // The device is only known once the pipeline is added, see Context::add_pipeline_with_layout
+    pub(super) fn validate_limits(&self, limits: &vk::PhysicalDeviceLimits) -> Result<(), PipelineConfigError> {
+        _validate_push_constant_size(self.push_constant_buffer_size, limits)?;
+        let limit = limits.max_per_stage_descriptor_samplers as usize;
+        if self.texture_cfgs.len() > limit {
+            return Err(PipelineConfigError::TooManyTextures {
+                count: self.texture_cfgs.len(),
+                limit,
+            });
+        }
+
+        Ok(())
+    }
+
+    // The camera of the pass is only known once the pipeline is added, its size is validated then
+    pub(super) fn bind_pass_camera(&mut self, buffer_object_handle: usize, size: usize) -> Result<(), PipelineConfigError> {
+        let binding = match self.pass_camera_binding {
+            Some(binding) => binding,
+            None => return Ok(()),
+        };
+
+        let reflection =
+            ShaderReflection::parse(&self.vertex_shader_code).map_err(PipelineConfigError::InvalidShaderCode)?;
+        _validate_block_size(reflection.uniform_block_size(binding), size, binding)?;
+
+        self.vertex_uniform_cfg = Some(BufferObjectConfiguration {
+            binding,
+            buffer_object_handle,
+            size,
+        });
+
+        Ok(())
+    }
+}
+
+// Bindings the shader doesn't declare are not validated
+fn _validate_block_size(shader_size: Option<usize>, size: usize, binding: u8) -> Result<(), PipelineConfigError> {
+    match shader_size {
+        Some(shader_size) if !_block_size_matches(shader_size, size) => Err(PipelineConfigError::UniformSizeMismatch {
+            binding,
+            shader_size,
+            size,
+        }),
+        _ => Ok(()),
+    }
+}
+
+// The Rust type may be larger by the padding up to the 16 byte alignment of a block, but never smaller
+fn _block_size_matches(shader_size: usize, size: usize) -> bool {
+    size >= shader_size && size <= (shader_size + 15) & !15
+}
+
+fn _validate_push_constant_size(
+    size: Option<usize>,
+    limits: &vk::PhysicalDeviceLimits,
+) -> Result<(), PipelineConfigError> {
+    let limit = limits.max_push_constants_size as usize;
+    match size {
+        Some(size) if size > limit => Err(PipelineConfigError::PushConstantTooLarge { size, limit }),
+        _ => Ok(()),
+    }
+}
+
+#[derive(Clone, Debug, Copy, PartialEq, Eq)]
+pub enum PipelineConfigError {
+    MissingVertexShader,
+    MissingFragmentShader,
+    PushConstantTooLarge { size: usize, limit: usize },
+    DuplicateBinding(u8),
+    TooManyTextures { count: usize, limit: usize },
+    InvalidShaderCode(&'static str),
+    UniformSizeMismatch { binding: u8, shader_size: usize, size: usize },
+    PushConstantSizeMismatch { shader_size: usize, size: usize },
+    MissingPushConstant(usize),
+    ConflictingVertexUniform,
+    UnsupportedColorWriteMask,
+    MissingComputeShader,
+    MissingBinding(u8),
+}
+
+impl fmt::Display for PipelineConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PipelineConfigError::MissingVertexShader => write!(f, "missing vertex shader"),
+            PipelineConfigError::MissingFragmentShader => write!(f, "missing fragment shader"),
+            PipelineConfigError::PushConstantTooLarge { size, limit } => {
+                write!(f, "push constant of {} bytes exceeds {} bytes", size, limit)
+            }
+            PipelineConfigError::DuplicateBinding(binding) => write!(f, "binding {} is used more than once", binding),
+            PipelineConfigError::TooManyTextures { count, limit } => {
+                write!(f, "{} textures exceeds the limit of {}", count, limit)
+            }
+            PipelineConfigError::InvalidShaderCode(reason) => write!(f, "invalid shader code: {}", reason),
+            PipelineConfigError::UniformSizeMismatch {
+                binding,
+                shader_size,
+                size,
+            } => write!(
+                f,
+                "uniform at binding {} is {} bytes in the shader but the bound type is {} bytes",
+                binding, shader_size, size
+            ),
+            PipelineConfigError::PushConstantSizeMismatch { shader_size, size } => write!(
+                f,
+                "push constant is {} bytes in the shader but the bound type is {} bytes",
+                shader_size, size
+            ),
+            PipelineConfigError::MissingPushConstant(shader_size) => {
+                write!(f, "shader declares a push constant of {} bytes that is not configured", shader_size)
+            }
+            PipelineConfigError::ConflictingVertexUniform => {
+                write!(f, "the pass camera and a vertex uniform can't be used together")
+            }
+            PipelineConfigError::UnsupportedColorWriteMask => {
+                write!(f, "weighted blended transparency writes all channels of its targets")
+            }
+            PipelineConfigError::MissingComputeShader => write!(f, "missing compute shader"),
+            PipelineConfigError::MissingBinding(binding) => {
+                write!(f, "binding {} is missing, compute bindings are numbered from 0", binding)
+            }
         }
     }
 }
@@ -178,23 +931,33 @@ impl PipelineConfigurationBuilder {
 #[derive(Clone, Debug, Copy)]
 pub struct BufferObjectConfiguration {
     pub(super) binding: u8,
-    pub(super) buffer_object_handle: BufferObjectHandle,
+    pub(super) buffer_object_handle: usize,
+    pub(super) size: usize,
 }
 
 impl BufferObjectConfiguration {
-    pub fn new(binding: u8, buffer_object_handle: BufferObjectHandle) -> Self {
+    pub(super) fn new<T>(binding: u8, buffer_object_handle: usize) -> Self {
         BufferObjectConfiguration {
             binding,
             buffer_object_handle,
+            size: std::mem::size_of::<T>(),
         }
     }
 }
 
+// How a texture binding is declared in the shader, sampler2D also stands for sampler2DArray
+#[derive(Clone, Debug, Copy, PartialEq, Eq)]
+pub enum SamplerBindingType {
+    Sampler2D,
+    SamplerCube,
+}
+
 #[derive(Clone, Debug, Copy)]
 pub struct TextureConfiguration {
     pub(super) binding: u8,
     pub(super) texture: TextureHandle,
     pub(super) sampler: SamplerHandle,
+    pub(super) binding_type: SamplerBindingType,
 }
 
 impl TextureConfiguration {
@@ -203,21 +966,30 @@ impl TextureConfiguration {
             binding,
             texture,
             sampler,
+            binding_type: SamplerBindingType::Sampler2D,
         }
     }
+
+    pub fn with_binding_type(mut self, binding_type: SamplerBindingType) -> Self {
+        self.binding_type = binding_type;
+
+        self
+    }
 }
 
 #[derive(Clone, Debug, Copy)]
 pub struct SamplerBindingConfiguration {
     pub(super) binding: u8,
+    pub(super) texture: TextureHandle,
     pub(super) image: ImageView,
     pub(super) sampler: Sampler,
 }
 
 impl SamplerBindingConfiguration {
-    pub fn new(binding: u8, image: ImageView, sampler: Sampler) -> Self {
+    pub fn new(binding: u8, texture: TextureHandle, image: ImageView, sampler: Sampler) -> Self {
         SamplerBindingConfiguration {
             binding,
+            texture,
             image,
             sampler,
         }
@@ -236,6 +1008,61 @@ impl BufferObjectBindingConfiguration {
     }
 }
 
+// Instances of a storage buffer reachable through one dynamic offset of its descriptor. The descriptor range is
+// limited by maxStorageBufferRange, draws with instances in several windows are split into one command per window.
+#[derive(Clone, Debug, Copy, PartialEq, Eq)]
+pub(super) struct StorageWindow {
+    // Bytes per instance
+    pub stride: u32,
+    pub instances: u32,
+    // Windows start at multiples of this many instances, so that offsets meet minStorageBufferOffsetAlignment
+    pub granularity: u32,
+}
+
+impl StorageWindow {
+    pub(super) fn new(stride: usize, max_range: u32, offset_alignment: vk::DeviceSize) -> Self {
+        let stride = stride as u32;
+        let granularity = (num::integer::lcm(stride as u64, offset_alignment.max(1)) / stride as u64) as u32;
+        let instances = max_range / stride / granularity * granularity;
+        assert!(instances > 0, "Storage buffer element is larger than the maximum descriptor range");
+
+        StorageWindow {
+            stride,
+            instances,
+            granularity,
+        }
+    }
+
+    pub(super) fn range(&self) -> usize {
+        self.instances as usize * self.stride as usize
+    }
+
+    // Commands drawing the instances of a buffer holding capacity elements: the first instance of its window, the
+    // first instance relative to the window and the instance count of each. Instances of the last granularity - 1
+    // elements may be out of reach if the capacity isn't a multiple of the granularity.
+    pub(super) fn split(&self, instance_start: u32, instance_count: u32, capacity: u32) -> Vec<(u32, u32, u32)> {
+        if capacity <= self.instances {
+            return vec![(0, instance_start, instance_count)];
+        }
+
+        let last_window = (capacity - self.instances) / self.granularity * self.granularity;
+        let end = instance_start.saturating_add(instance_count);
+        let mut commands = Vec::new();
+        let mut first = instance_start;
+        while first < end {
+            let window = first.min(last_window) / self.granularity * self.granularity;
+            let last = end.min(window + self.instances);
+            if last <= first {
+                break;
+            }
+            commands.push((window, first - window, last - first));
+            first = last;
+        }
+
+        commands
+    }
+}
+
 #[derive(Clone, Debug, Copy)]
 pub struct VertexData {
     pub vertex_buffer: Buffer,
@@ -253,10 +1080,46 @@ impl VertexData {
     }
 }
 
+// Rectangle in pixels within the target of the render pass, origin is the top left corner.
+#[derive(Clone, Debug, Copy, PartialEq, Eq)]
+pub struct Viewport {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Viewport {
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Viewport { x, y, width, height }
+    }
+
+    pub fn aspect_ratio(&self) -> f32 {
+        self.width as f32 / self.height as f32
+    }
+}
+
+// Pass and render targets of weighted blended transparency, the targets are composited with
+// TransparencyCompositor after the pass.
+#[derive(Clone, Debug, Copy)]
+pub struct TransparencyTargets {
+    pub pass: RenderPassHandle,
+    pub accumulation: TextureHandle,
+    pub revealage: TextureHandle,
+}
+
+#[derive(Clone)]
 pub struct DrawCommand {
     pub pipeline: PipelineHandle,
     pub(super) push_constant_ptr: RawArrayPtr,
     pub(super) vertex_data: DrawData,
+    pub(super) vertex_buffers: VertexBuffers,
+    pub(super) texture: Option<(TextureHandle, SamplerHandle)>,
+    pub(super) viewport: Option<Viewport>,
+    pub(super) depth: Option<f32>,
+    pub(super) blend_constants: Option<[f32; 4]>,
+    // Dynamic offset of the storage buffer of the pipeline, set when the draw is split at bake time
+    pub(super) storage_offset: u32,
 }
 
 impl DrawCommand {
@@ -268,12 +1131,17 @@ impl DrawCommand {
             pipeline,
             push_constant_ptr: ptr::null(),
             vertex_data: Buffered(BufferDrawData::new(
-                vertex_data.vertex_buffer,
                 vertex_data.index_buffer,
                 vertex_data.index_count,
                 1,
                 0,
             )),
+            vertex_buffers: VertexBuffers::new(vertex_data.vertex_buffer),
+            texture: None,
+            viewport: None,
+            depth: None,
+            blend_constants: None,
+            storage_offset: 0,
         }
     }
 
@@ -286,12 +1154,17 @@ impl DrawCommand {
             pipeline,
             push_constant_ptr: push_constant_ptr as *const T as RawArrayPtr,
             vertex_data: Buffered(BufferDrawData::new(
-                vertex_data.vertex_buffer,
                 vertex_data.index_buffer,
                 vertex_data.index_count,
                 1,
                 0,
             )),
+            vertex_buffers: VertexBuffers::new(vertex_data.vertex_buffer),
+            texture: None,
+            viewport: None,
+            depth: None,
+            blend_constants: None,
+            storage_offset: 0,
         }
     }
 
@@ -306,12 +1179,17 @@ impl DrawCommand {
             pipeline,
             push_constant_ptr: push_constant_ptr as *const T as RawArrayPtr,
             vertex_data: Buffered(BufferDrawData::new(
-                vertex_data.vertex_buffer,
                 vertex_data.index_buffer,
                 vertex_data.index_count,
                 instance_count,
                 instance_start,
             )),
+            vertex_buffers: VertexBuffers::new(vertex_data.vertex_buffer),
+            texture: None,
+            viewport: None,
+            depth: None,
+            blend_constants: None,
+            storage_offset: 0,
         }
     }
 
@@ -325,63 +1203,757 @@ impl DrawCommand {
             pipeline,
             push_constant_ptr: ptr::null(),
             vertex_data: Buffered(BufferDrawData::new(
-                vertex_data.vertex_buffer,
                 vertex_data.index_buffer,
                 vertex_data.index_count,
                 instance_count,
                 instance_start,
             )),
+            vertex_buffers: VertexBuffers::new(vertex_data.vertex_buffer),
+            texture: None,
+            viewport: None,
+            depth: None,
+            blend_constants: None,
+            storage_offset: 0,
         }
     }
 
+    // Non indexed draw of vertices pushed into a buffer object created with Context::create_vertex_buffer.
+    // The device buffer is looked up when the frame is baked, so the buffer object may grow in between.
+    pub fn new_dynamic<T, V>(
+        pipeline: PipelineHandle,
+        push_constant_ptr: &T,
+        vertex_buffer: BufferObjectHandle<V>,
+        vertex_start: u32,
+        vertex_count: u32,
+    ) -> DrawCommand {
+        DrawCommand {
+            push_constant_ptr: push_constant_ptr as *const T as RawArrayPtr,
+            ..DrawCommand::new_dynamic_nopush(pipeline, vertex_buffer, vertex_start, vertex_count)
+        }
+    }
+
+    pub fn new_dynamic_nopush<V>(
+        pipeline: PipelineHandle,
+        vertex_buffer: BufferObjectHandle<V>,
+        vertex_start: u32,
+        vertex_count: u32,
+    ) -> DrawCommand {
+        DrawCommand {
+            pipeline,
+            push_constant_ptr: ptr::null(),
+            vertex_data: Dynamic(DynamicDrawData {
+                buffer_object: vertex_buffer.index(),
+                vertex_start,
+                vertex_count,
+                instance_count: 1,
+                instance_start: 0,
+            }),
+            // Binding 0 is resolved from the buffer object when baking
+            vertex_buffers: VertexBuffers::new(Buffer::null()),
+            texture: None,
+            viewport: None,
+            depth: None,
+            blend_constants: None,
+            storage_offset: 0,
+        }
+    }
+
+    pub fn with_instances(mut self, instance_count: u32, instance_start: u32) -> Self {
+        match &mut self.vertex_data {
+            Buffered(data) => {
+                data.instance_count = instance_count;
+                data.instance_start = instance_start;
+            }
+            Dynamic(data) => {
+                data.instance_count = instance_count;
+                data.instance_start = instance_start;
+            }
+        }
+
+        self
+    }
+
+    // Buffer of another vertex stream of the pipeline, binding 0 is the mesh of the command.
+    // Bindings have to be set in order.
+    pub fn with_vertex_buffer(mut self, binding: u32, buffer: Buffer) -> Self {
+        self.vertex_buffers.set(binding, buffer);
+
+        self
+    }
+
+    pub fn with_texture(mut self, texture: TextureHandle, sampler: SamplerHandle) -> Self {
+        self.texture = Some((texture, sampler));
+
+        self
+    }
+
+    // View space distance to the camera, used to sort draws of transparent pipelines. See Camera::view_depth
+    pub fn with_depth(mut self, depth: f32) -> Self {
+        self.depth = Some(depth);
+
+        self
+    }
+
+    // Draws the instances left by the occlusion culling pre-pass, see Frame::cull_instances. The visible instance
+    // indices are bound as vertex stream 1.
+    pub fn with_culled_instances(mut self, culled_instances: CulledInstances) -> Self {
+        match &mut self.vertex_data {
+            Buffered(data) => {
+                data.indirect_buffer = Some(culled_instances.indirect_buffer);
+                // Upper bound for the stats, the visible count is only known on the GPU
+                data.instance_count = culled_instances.instance_count;
+                data.instance_start = 0;
+            }
+            Dynamic(_) => panic!("Culled instances need a buffered draw command"),
+        }
+        self.vertex_buffers.set(1, culled_instances.visible_buffer);
+
+        self
+    }
+
+    // Without a viewport the command covers the whole target of its render pass.
+    pub fn with_viewport(mut self, viewport: Viewport) -> Self {
+        self.viewport = Some(viewport);
+
+        self
+    }
+
+    // Overrides the blend constants of Context::set_blend_constants for this draw, see with_constant_blending
+    pub fn with_blend_constants(mut self, blend_constants: [f32; 4]) -> Self {
+        self.blend_constants = Some(blend_constants);
+
+        self
+    }
+
+    pub(super) fn with_storage_offset(mut self, storage_offset: u32) -> Self {
+        self.storage_offset = storage_offset;
+
+        self
+    }
+
+    // Folds other into this command when it draws the next instances of the same mesh with identical state,
+    // push constants are compared byte for byte.
+    pub(super) fn try_merge(&mut self, other: &DrawCommand, push_constant_size: Option<usize>) -> bool {
+        if self.pipeline.render_pass != other.pipeline.render_pass
+            || self.pipeline.index() != other.pipeline.index()
+            || self.vertex_buffers != other.vertex_buffers
+            || self.texture != other.texture
+            || self.viewport != other.viewport
+            || self.blend_constants != other.blend_constants
+        {
+            return false;
+        }
+
+        if let Some(size) = push_constant_size {
+            let equal = unsafe {
+                std::slice::from_raw_parts(self.push_constant_ptr, size)
+                    == std::slice::from_raw_parts(other.push_constant_ptr, size)
+            };
+            if !equal {
+                return false;
+            }
+        }
+
+        match (&mut self.vertex_data, &other.vertex_data) {
+            (Buffered(data), Buffered(other_data)) => {
+                let mergeable = data.indirect_buffer.is_none()
+                    && other_data.indirect_buffer.is_none()
+                    && data.index_buffer == other_data.index_buffer
+                    && data.index_count == other_data.index_count
+                    && data.instance_start + data.instance_count == other_data.instance_start;
+                if mergeable {
+                    data.instance_count += other_data.instance_count;
+                }
+
+                mergeable
+            }
+            (Dynamic(data), Dynamic(other_data)) => {
+                let mergeable = data.buffer_object == other_data.buffer_object
+                    && data.vertex_start == other_data.vertex_start
+                    && data.vertex_count == other_data.vertex_count
+                    && data.instance_start + data.instance_count == other_data.instance_start;
+                if mergeable {
+                    data.instance_count += other_data.instance_count;
+                }
+
+                mergeable
+            }
+            _ => false,
+        }
+    }
+
+    // Start and count of the instances, None for indirect draws whose instances are only known on the GPU
+    pub(super) fn instance_range(&self) -> Option<(u32, u32)> {
+        match &self.vertex_data {
+            Buffered(data) => data.indirect_buffer.is_none().then_some((data.instance_start, data.instance_count)),
+            Dynamic(data) => Some((data.instance_start, data.instance_count)),
+        }
+    }
 
     pub fn triangle_count(&self, primitive_topology: PrimitiveTopology) -> u32 {
         match primitive_topology {
             PrimitiveTopology::TRIANGLE_LIST => match &self.vertex_data {
                 Buffered(buffer_data) => buffer_data.index_count / 3 * buffer_data.instance_count,
+                Dynamic(dynamic_data) => dynamic_data.vertex_count / 3 * dynamic_data.instance_count,
             },
             PrimitiveTopology::TRIANGLE_STRIP => match &self.vertex_data {
                 Buffered(buffer_data) => (buffer_data.index_count - 2) * buffer_data.instance_count,
+                // Empty strips are fine, the vertex count depends on what was generated this frame
+                Dynamic(dynamic_data) => dynamic_data.vertex_count.saturating_sub(2) * dynamic_data.instance_count,
             },
             _ => unreachable!(),
         }
     }
 }
 
+#[derive(Clone)]
 pub(super) struct BufferDrawData {
-    pub vertex_buffer: Buffer,
     pub index_buffer: Buffer,
     pub index_count: u32,
     pub instance_count: u32,
     pub instance_start: u32,
+    // Draw parameters written on the GPU, see DrawCommand::with_culled_instances
+    pub indirect_buffer: Option<Buffer>,
 }
 
 impl BufferDrawData {
-    pub(super) fn new(
-        vertex_buffer: Buffer,
-        index_buffer: Buffer,
-        index_count: u32,
-        instance_count: u32,
-        instance_start: u32,
-    ) -> Self {
+    pub(super) fn new(index_buffer: Buffer, index_count: u32, instance_count: u32, instance_start: u32) -> Self {
         BufferDrawData {
-            vertex_buffer,
             index_buffer,
             index_count,
             instance_count,
             instance_start,
+            indirect_buffer: None,
         }
     }
 }
 
+pub const MAX_VERTEX_BINDINGS: usize = 4;
+
+// Vertex buffer per binding, in binding order
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) struct VertexBuffers {
+    buffers: [Buffer; MAX_VERTEX_BINDINGS],
+    count: usize,
+}
+
+impl VertexBuffers {
+    fn new(buffer: Buffer) -> Self {
+        let mut buffers = [Buffer::null(); MAX_VERTEX_BINDINGS];
+        buffers[0] = buffer;
+        VertexBuffers { buffers, count: 1 }
+    }
+
+    fn set(&mut self, binding: u32, buffer: Buffer) {
+        let binding = binding as usize;
+        assert!(binding < MAX_VERTEX_BINDINGS, "Too many vertex bindings");
+        assert!(binding <= self.count, "Vertex buffer bindings must be set in order");
+        self.buffers[binding] = buffer;
+        self.count = self.count.max(binding + 1);
+    }
+
+    pub(super) fn as_slice(&self) -> &[Buffer] {
+        &self.buffers[..self.count]
+    }
+}
+
+// Vertices of a growable vertex buffer object, resolved to the device buffer of the image when baking
+#[derive(Clone)]
+pub(super) struct DynamicDrawData {
+    pub buffer_object: usize,
+    pub vertex_start: u32,
+    pub vertex_count: u32,
+    pub instance_count: u32,
+    pub instance_start: u32,
+}
+
+#[derive(Clone)]
 pub(super) enum DrawData {
     Buffered(BufferDrawData),
+    Dynamic(DynamicDrawData),
 }
 
 pub type Index = u32;
 
+// Layout matches std140, see with_frame_globals
+#[repr(C)]
+#[derive(Clone, Debug, Copy, Default)]
+pub struct FrameGlobals {
+    pub resolution: [f32; 2],
+    pub time: f32,
+    pub delta_time: f32,
+    pub frame_index: u32,
+}
+
 #[derive(Clone, Debug, Copy)]
 pub enum UniformStage {
     Vertex,
     Fragment,
 }
+
+//
+// Compute
+//
+pub type ComputePipelineHandle = usize;
+pub type ComputeBufferHandle = usize;
+
+#[derive(Clone, Debug, Copy, PartialEq, Eq)]
+pub(super) enum ComputeBinding {
+    // Device buffer of the image, like the storage buffers of graphics pipelines
+    BufferObject(usize),
+    Buffer(ComputeBufferHandle),
+    Texture(TextureHandle),
+}
+
+impl ComputeBinding {
+    pub(super) fn descriptor_type(&self) -> vk::DescriptorType {
+        match self {
+            ComputeBinding::BufferObject(_) | ComputeBinding::Buffer(_) => vk::DescriptorType::STORAGE_BUFFER,
+            ComputeBinding::Texture(_) => vk::DescriptorType::STORAGE_IMAGE,
+        }
+    }
+}
+
+// Compute shader with storage buffers and images, see Context::add_compute_pipeline. The bindings are numbered
+// from 0 without gaps.
+#[derive(Clone, Debug)]
+pub struct ComputePipelineConfiguration {
+    pub(super) shader_code: Vec<u8>,
+    // In binding order
+    pub(super) bindings: Vec<ComputeBinding>,
+    pub(super) push_constant_size: Option<usize>,
+}
+
+impl ComputePipelineConfiguration {
+    pub fn builder() -> ComputePipelineConfigurationBuilder {
+        ComputePipelineConfigurationBuilder {
+            shader_code: None,
+            bindings: Vec::new(),
+            push_constant_size: None,
+        }
+    }
+
+    // See Context::add_compute_pipeline
+    pub(super) fn validate_limits(&self, limits: &vk::PhysicalDeviceLimits) -> Result<(), PipelineConfigError> {
+        _validate_push_constant_size(self.push_constant_size, limits)
+    }
+}
+
+pub struct ComputePipelineConfigurationBuilder {
+    shader_code: Option<Vec<u8>>,
+    bindings: Vec<(u8, ComputeBinding)>,
+    push_constant_size: Option<usize>,
+}
+
+impl ComputePipelineConfigurationBuilder {
+    pub fn with_shader(&mut self, code: Vec<u8>) -> &mut Self {
+        self.shader_code = Some(code);
+
+        self
+    }
+
+    // A file that fails to load is logged and replaced by the fallback shader, which does nothing
+    pub fn with_shader_file(&mut self, path: &Path) -> &mut Self {
+        self.with_shader(shader::load_shader_or_fallback(path, shader::FALLBACK_COMPUTE_SHADER))
+    }
+
+    pub fn with_push_constant<T>(&mut self) -> &mut Self {
+        self.push_constant_size = Some(std::mem::size_of::<T>());
+
+        self
+    }
+
+    // Read and written by the shader, e.g. particles simulated on the GPU and drawn instanced
+    pub fn add_storage_buffer_object<T>(&mut self, binding: u8, buffer_object: BufferObjectHandle<T>) -> &mut Self {
+        self.bindings.push((binding, ComputeBinding::BufferObject(buffer_object.index())));
+
+        self
+    }
+
+    // See Context::create_compute_buffer
+    pub fn add_compute_buffer(&mut self, binding: u8, buffer: ComputeBufferHandle) -> &mut Self {
+        self.bindings.push((binding, ComputeBinding::Buffer(buffer)));
+
+        self
+    }
+
+    // See Context::add_storage_texture
+    pub fn add_storage_texture(&mut self, binding: u8, texture: TextureHandle) -> &mut Self {
+        self.bindings.push((binding, ComputeBinding::Texture(texture)));
+
+        self
+    }
+
+    pub fn build(&mut self) -> Result<ComputePipelineConfiguration, PipelineConfigError> {
+        let shader_code = self.shader_code.clone().ok_or(PipelineConfigError::MissingComputeShader)?;
+
+        let mut bindings = self.bindings.clone();
+        bindings.sort_by_key(|(binding, _)| *binding);
+        if let Some(pair) = bindings.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+            return Err(PipelineConfigError::DuplicateBinding(pair[0].0));
+        }
+        if let Some(index) = (0..bindings.len()).find(|index| bindings[*index].0 as usize != *index) {
+            return Err(PipelineConfigError::MissingBinding(index as u8));
+        }
+
+        let reflection = ShaderReflection::parse(&shader_code).map_err(PipelineConfigError::InvalidShaderCode)?;
+        match (reflection.push_constant_size(), self.push_constant_size) {
+            (Some(shader_size), None) => return Err(PipelineConfigError::MissingPushConstant(shader_size)),
+            (Some(shader_size), Some(size)) if !_block_size_matches(shader_size, size) => {
+                return Err(PipelineConfigError::PushConstantSizeMismatch { shader_size, size })
+            }
+            _ => {}
+        }
+
+        Ok(ComputePipelineConfiguration {
+            shader_code,
+            bindings: bindings.into_iter().map(|(_, binding)| binding).collect(),
+            push_constant_size: self.push_constant_size,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn storage_window_split() {
+        // 48 byte offsets are the first multiples of 12 meeting the alignment of 16
+        let window = StorageWindow::new(12, 120, 16);
+        assert_eq!((window.instances, window.granularity, window.range()), (8, 4, 96));
+        assert_eq!(StorageWindow::new(64, 1 << 27, 256).instances, 1 << 21);
+
+        // Buffers within the range are drawn in one command
+        assert_eq!(window.split(2, 5, 8), vec![(0, 2, 5)]);
+        assert_eq!(window.split(3, 20, 30), vec![(0, 3, 5), (8, 0, 8), (16, 0, 7)]);
+        // The last window ends before the last two elements
+        assert_eq!(window.split(21, 9, 30), vec![(20, 1, 7)]);
+        assert_eq!(window.split(28, 2, 30), vec![]);
+    }
+
+    #[test]
+    fn context_configuration_validation() {
+        assert_eq!(ContextConfiguration::new().validation(), cfg!(debug_assertions));
+        assert!(!ContextConfiguration::new().with_validation(false).validation());
+        assert!(ContextConfiguration::new().with_validation(true).validation());
+        // GPU-assisted validation runs in the validation layer
+        assert!(ContextConfiguration::new().with_validation(false).with_gpu_validation(true).validation());
+    }
+
+    #[test]
+    fn pipeline_configuration_validation() {
+        assert_eq!(
+            PipelineConfiguration::builder().with_vertex_shader(vec![0]).build().err(),
+            Some(PipelineConfigError::MissingFragmentShader)
+        );
+
+        let uniform = BufferObjectHandle::<u32>::new(0);
+        let result = PipelineConfiguration::builder()
+            .with_vertex_shader(vec![0])
+            .with_fragment_shader(vec![0])
+            .with_vertex_uniform(1, uniform)
+            .add_texture(1, 0, 0)
+            .build();
+        assert_eq!(result.err(), Some(PipelineConfigError::DuplicateBinding(1)));
+
+        let result = PipelineConfiguration::builder()
+            .with_vertex_shader(vec![0])
+            .with_fragment_shader(vec![0])
+            .with_vertex_uniform(0, uniform)
+            .with_frame_globals(0)
+            .build();
+        assert_eq!(result.err(), Some(PipelineConfigError::DuplicateBinding(0)));
+
+        let result = PipelineConfiguration::builder()
+            .with_vertex_shader(vec![0])
+            .with_fragment_shader(vec![0])
+            .with_vertex_uniform(0, uniform)
+            .with_pass_camera(1)
+            .build();
+        assert_eq!(result.err(), Some(PipelineConfigError::ConflictingVertexUniform));
+
+
+        let result = PipelineConfiguration::builder()
+            .with_vertex_shader(vec![0])
+            .with_fragment_shader(vec![0])
+            .with_weighted_blended_transparency()
+            .with_color_write_mask(ColorWriteMask::RGB)
+            .build();
+        assert_eq!(result.err(), Some(PipelineConfigError::UnsupportedColorWriteMask));
+
+        let vertex_shader = std::fs::read("./resources/shaders/default_ppl_vert.spv").unwrap();
+        let fragment_shader = std::fs::read("./resources/shaders/default_ppl_frag.spv").unwrap();
+        let result = PipelineConfiguration::builder()
+            .with_vertex_shader(vertex_shader.clone())
+            .with_fragment_shader(fragment_shader.clone())
+            .with_vertex_uniform(0, uniform)
+            .with_push_constant::<[f32; 20]>()
+            .build();
+        assert_eq!(
+            result.err(),
+            Some(PipelineConfigError::UniformSizeMismatch { binding: 0, shader_size: 128, size: 4 })
+        );
+
+        let result = PipelineConfiguration::builder()
+            .with_vertex_shader(vertex_shader.clone())
+            .with_fragment_shader(fragment_shader.clone())
+            .with_push_constant::<[f32; 16]>()
+            .build();
+        assert_eq!(
+            result.err(),
+            Some(PipelineConfigError::PushConstantSizeMismatch { shader_size: 80, size: 64 })
+        );
+
+        let mut config = PipelineConfiguration::builder()
+            .with_vertex_shader(vertex_shader)
+            .with_fragment_shader(fragment_shader)
+            .with_pass_camera(0)
+            .with_push_constant::<[f32; 20]>()
+            .build()
+            .unwrap();
+        assert_eq!(
+            config.bind_pass_camera(0, 64).err(),
+            Some(PipelineConfigError::UniformSizeMismatch { binding: 0, shader_size: 128, size: 64 })
+        );
+        assert!(config.bind_pass_camera(0, 128).is_ok());
+        assert_eq!(config.vertex_uniform_cfg.map(|cfg| cfg.size), Some(128));
+
+        // Limits of the device are only checked when the pipeline is added
+        let limits = vk::PhysicalDeviceLimits {
+            max_push_constants_size: 64,
+            max_per_stage_descriptor_samplers: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            config.validate_limits(&limits),
+            Err(PipelineConfigError::PushConstantTooLarge { size: 80, limit: 64 })
+        );
+        let limits = vk::PhysicalDeviceLimits {
+            max_push_constants_size: 256,
+            ..limits
+        };
+        assert_eq!(config.validate_limits(&limits), Ok(()));
+
+        let config = PipelineConfiguration::builder()
+            .with_vertex_shader_file(Path::new("./resources/shaders/skybox_vert.spv"))
+            .with_fragment_shader_file(Path::new("./resources/shaders/skybox_frag.spv"))
+            .add_texture(2, 0, 0)
+            .add_cube_texture(1, 1, 0)
+            .build()
+            .unwrap();
+        let binding_types: Vec<_> = config.texture_cfgs.iter().map(|cfg| (cfg.binding, cfg.binding_type)).collect();
+        assert_eq!(binding_types, vec![(2, SamplerBindingType::Sampler2D), (1, SamplerBindingType::SamplerCube)]);
+        assert_eq!(
+            config.validate_limits(&limits),
+            Err(PipelineConfigError::TooManyTextures { count: 2, limit: 1 })
+        );
+    }
+
+    #[test]
+    fn error_pipeline() {
+        use crate::engine::datatypes::{TransformColorPushConstant, ViewProjectionUniform};
+
+        let missing = Path::new("./resources/shaders/missing_vert.spv");
+        let config = PipelineConfiguration::builder()
+            .with_vertex_shader_file(missing)
+            .with_fragment_shader_file(Path::new("./resources/shaders/flat_color_frag.spv"))
+            .with_push_constant::<TransformColorPushConstant>()
+            .with_vertex_uniform(0, BufferObjectHandle::<ViewProjectionUniform>::new(0))
+            .build()
+            .unwrap();
+        assert_eq!(config.vertex_shader_code, shader::FALLBACK_TRANSFORM_VERTEX_SHADER);
+        assert_eq!(config.fragment_shader_code, shader::FALLBACK_FRAGMENT_SHADER);
+
+        // Nothing to place the geometry with
+        let config = PipelineConfiguration::builder()
+            .with_vertex_shader_file(missing)
+            .with_fragment_shader_file(Path::new("./resources/shaders/2d_flat_ssbo_frag.spv"))
+            .build()
+            .unwrap();
+        assert_eq!(config.vertex_shader_code, shader::FALLBACK_VERTEX_SHADER);
+
+        // A shader given afterwards replaces the failed one
+        let config = PipelineConfiguration::builder()
+            .with_vertex_shader_file(missing)
+            .with_vertex_shader_file(Path::new("./resources/shaders/flat_color_vert.spv"))
+            .with_fragment_shader_file(Path::new("./resources/shaders/missing_frag.spv"))
+            .with_push_constant::<TransformColorPushConstant>()
+            .with_vertex_uniform(0, BufferObjectHandle::<ViewProjectionUniform>::new(0))
+            .build()
+            .unwrap();
+        assert_ne!(config.vertex_shader_code, shader::FALLBACK_TRANSFORM_VERTEX_SHADER);
+        assert_eq!(config.fragment_shader_code, shader::FALLBACK_FRAGMENT_SHADER);
+    }
+
+    #[test]
+    fn draw_command_merging() {
+        use ash::vk::Handle;
+
+        let pipeline = PipelineHandle::new(SWAPCHAIN_PASS, 0);
+        let mesh = VertexData::new(Buffer::from_raw(1), Buffer::from_raw(2), 6);
+        let other_mesh = VertexData::new(Buffer::from_raw(3), Buffer::from_raw(4), 6);
+        let color = [1.0_f32, 0.0, 0.0, 1.0];
+        let other_color = [0.0_f32, 1.0, 0.0, 1.0];
+        let size = Some(std::mem::size_of_val(&color));
+
+        let mut command = DrawCommand::new_buffered_instanced(pipeline, &color, mesh, 1, 0);
+        assert!(command.try_merge(&DrawCommand::new_buffered_instanced(pipeline, &color, mesh, 2, 1), size));
+        assert_eq!(command.triangle_count(PrimitiveTopology::TRIANGLE_LIST), 6);
+
+        // Gap in the instance range
+        assert!(!command.try_merge(&DrawCommand::new_buffered_instanced(pipeline, &color, mesh, 1, 4), size));
+        assert!(!command.try_merge(&DrawCommand::new_buffered_instanced(pipeline, &color, other_mesh, 1, 3), size));
+        assert!(!command.try_merge(&DrawCommand::new_buffered_instanced(pipeline, &other_color, mesh, 1, 3), size));
+        assert!(command.try_merge(&DrawCommand::new_buffered_instanced(pipeline, &other_color, mesh, 1, 3), None));
+        let blended = DrawCommand::new_buffered_instanced(pipeline, &color, mesh, 1, 4).with_blend_constants(color);
+        assert!(!command.try_merge(&blended, size));
+
+        let vertices = BufferObjectHandle::<[f32; 3]>::new(0);
+        let mut dynamic = DrawCommand::new_dynamic(pipeline, &color, vertices, 0, 8);
+        assert_eq!(dynamic.triangle_count(PrimitiveTopology::TRIANGLE_STRIP), 6);
+        let next = DrawCommand::new_dynamic(pipeline, &color, vertices, 0, 8).with_instances(1, 1);
+        assert!(dynamic.try_merge(&next, size));
+        // Other vertex range, and mixing with indexed meshes
+        let other_range = DrawCommand::new_dynamic(pipeline, &color, vertices, 8, 8).with_instances(1, 2);
+        assert!(!dynamic.try_merge(&other_range, size));
+        assert!(!dynamic.try_merge(&DrawCommand::new_buffered_instanced(pipeline, &color, mesh, 1, 2), size));
+        let empty = DrawCommand::new_dynamic_nopush(pipeline, vertices, 0, 0);
+        assert_eq!(empty.triangle_count(PrimitiveTopology::TRIANGLE_STRIP), 0);
+
+        // Instances in other per instance buffers
+        let mut streams = DrawCommand::new_buffered_instanced(pipeline, &color, mesh, 1, 0)
+            .with_vertex_buffer(1, Buffer::from_raw(5));
+        assert_eq!(streams.vertex_buffers.as_slice(), &[Buffer::from_raw(1), Buffer::from_raw(5)]);
+        let other_stream = DrawCommand::new_buffered_instanced(pipeline, &color, mesh, 1, 1)
+            .with_vertex_buffer(1, Buffer::from_raw(6));
+        assert!(!streams.try_merge(&other_stream, size));
+        assert!(!streams.try_merge(&DrawCommand::new_buffered_instanced(pipeline, &color, mesh, 1, 1), size));
+    }
+
+    struct TwoAttributes;
+    struct Instance;
+
+    fn binding(stride: u32, input_rate: vk::VertexInputRate) -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription { binding: 0, stride, input_rate }
+    }
+
+    fn attribute(location: u32, offset: u32) -> vk::VertexInputAttributeDescription {
+        vk::VertexInputAttributeDescription { binding: 0, location, format: vk::Format::R32G32B32_SFLOAT, offset }
+    }
+
+    impl VertexInputDescription for TwoAttributes {
+        fn binding_descriptions() -> Vec<vk::VertexInputBindingDescription> {
+            vec![binding(24, vk::VertexInputRate::VERTEX)]
+        }
+
+        fn attribute_descriptions() -> Vec<vk::VertexInputAttributeDescription> {
+            vec![attribute(0, 0), attribute(1, 12)]
+        }
+    }
+
+    impl VertexInputDescription for Instance {
+        fn binding_descriptions() -> Vec<vk::VertexInputBindingDescription> {
+            vec![binding(12, vk::VertexInputRate::INSTANCE)]
+        }
+
+        fn attribute_descriptions() -> Vec<vk::VertexInputAttributeDescription> {
+            vec![attribute(0, 0)]
+        }
+    }
+
+    #[test]
+    fn vertex_streams() {
+        let bindings = <(TwoAttributes, Instance)>::binding_descriptions();
+        assert_eq!(bindings.iter().map(|b| (b.binding, b.stride)).collect::<Vec<_>>(), vec![(0, 24), (1, 12)]);
+        assert_eq!(bindings[1].input_rate, vk::VertexInputRate::INSTANCE);
+
+        let attributes = <(TwoAttributes, Instance)>::attribute_descriptions();
+        let layout: Vec<_> = attributes.iter().map(|a| (a.binding, a.location, a.offset)).collect();
+        assert_eq!(layout, vec![(0, 0, 0), (0, 1, 12), (1, 2, 0)]);
+    }
+
+    #[test]
+    fn compute_pipeline_configuration_validation() {
+        assert_eq!(
+            ComputePipelineConfiguration::builder().build().err(),
+            Some(PipelineConfigError::MissingComputeShader)
+        );
+
+        let buffer_object = BufferObjectHandle::<u32>::new(0);
+        let result = ComputePipelineConfiguration::builder()
+            .with_shader(vec![0])
+            .add_storage_buffer_object(0, buffer_object)
+            .add_storage_texture(0, 0)
+            .build();
+        assert_eq!(result.err(), Some(PipelineConfigError::DuplicateBinding(0)));
+
+        let result = ComputePipelineConfiguration::builder()
+            .with_shader(vec![0])
+            .add_storage_buffer_object(0, buffer_object)
+            .add_compute_buffer(2, 0)
+            .build();
+        assert_eq!(result.err(), Some(PipelineConfigError::MissingBinding(1)));
+
+        // Vertex count as push constant
+        let shader = std::fs::read("./resources/shaders/skinning_comp.spv").unwrap();
+        let result = ComputePipelineConfiguration::builder().with_shader(shader.clone()).build();
+        assert_eq!(result.err(), Some(PipelineConfigError::MissingPushConstant(4)));
+
+        let config = ComputePipelineConfiguration::builder()
+            .with_shader(shader)
+            .add_compute_buffer(2, 1)
+            .add_storage_buffer_object(0, buffer_object)
+            .add_storage_texture(1, 3)
+            .with_push_constant::<u32>()
+            .build()
+            .unwrap();
+        assert_eq!(
+            config.bindings,
+            vec![ComputeBinding::BufferObject(0), ComputeBinding::Texture(3), ComputeBinding::Buffer(1)]
+        );
+        assert_eq!(config.bindings[1].descriptor_type(), vk::DescriptorType::STORAGE_IMAGE);
+    }
+
+    #[test]
+    fn sample_counts() {
+        for count in [1, 2, 4, 8] {
+            assert_eq!(SampleCount::from_count(count).unwrap().count(), count);
+        }
+        assert_eq!(SampleCount::from_count(3), None);
+        assert_eq!(SampleCount::from_count(16), None);
+        assert_eq!(SampleCount::Four.flags(), vk::SampleCountFlags::TYPE_4);
+        assert!(!SampleCount::default().is_multisampled());
+    }
+
+    #[test]
+    fn texture_usage_validation() {
+        assert_eq!(
+            TextureUsage::RENDER_TARGET.image_usage_flags(),
+            vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC
+        );
+        assert_eq!(
+            TextureUsage::UPLOADED.image_usage_flags(),
+            vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST
+        );
+
+        let srgb = vk::FormatFeatureFlags::SAMPLED_IMAGE | vk::FormatFeatureFlags::COLOR_ATTACHMENT;
+        assert_eq!(TextureUsage::RENDER_TARGET.validate(srgb), Ok(()));
+        assert_eq!(
+            TextureUsage::RENDER_TARGET.with_storage().validate(srgb),
+            Err("Texture format can't be used as storage image on this device!")
+        );
+        assert_eq!(
+            TextureUsage::RENDER_TARGET.with_storage().validate(srgb | vk::FormatFeatureFlags::STORAGE_IMAGE),
+            Ok(())
+        );
+        assert_eq!(
+            TextureUsage::RENDER_TARGET.validate(vk::FormatFeatureFlags::SAMPLED_IMAGE),
+            Err("Texture format can't be rendered to on this device!")
+        );
+        assert_eq!(
+            TextureUsage { sampled: false, ..TextureUsage::RENDER_TARGET }.validate(srgb),
+            Err("Render textures must be sampled!")
+        );
+    }
+}
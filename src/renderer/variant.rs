@@ -0,0 +1,215 @@
+use crate::renderer::types::PipelineHandle;
+
+// Declares the preprocessor defines a shader is compiled with. build_shaders.sh compiles one SPIR-V file per
+// permutation from the <name>.variants file next to the shader, this must declare the same defines in the same order.
+//
+// A flag FOG is compiled with and without -DFOG, a choice LIGHTING=PHONG,DIFFUSE with either -DLIGHTING_PHONG
+// or -DLIGHTING_DIFFUSE. Enabled flags and chosen values are appended to the file name in lowercase,
+// e.g. <name>_fog_lighting_diffuse_vert.spv.
+#[derive(Clone, Debug)]
+pub struct ShaderVariants {
+    shader_path: String,
+    defines: Vec<ShaderDefine>,
+}
+
+#[derive(Clone, Debug)]
+enum ShaderDefine {
+    Flag(String),
+    Choice(String, Vec<String>),
+}
+
+impl ShaderDefine {
+    fn name(&self) -> &str {
+        match self {
+            ShaderDefine::Flag(name) | ShaderDefine::Choice(name, _) => name,
+        }
+    }
+
+    fn value_count(&self) -> usize {
+        match self {
+            ShaderDefine::Flag(_) => 2,
+            ShaderDefine::Choice(_, values) => values.len(),
+        }
+    }
+}
+
+impl ShaderVariants {
+    // The path of the compiled shaders without the stage suffix, e.g. "./resources/shaders/default_ppl"
+    pub fn new(shader_path: &str) -> Self {
+        ShaderVariants {
+            shader_path: shader_path.to_string(),
+            defines: Vec::new(),
+        }
+    }
+
+    pub fn with_flag(mut self, name: &str) -> Self {
+        self.defines.push(ShaderDefine::Flag(name.to_string()));
+
+        self
+    }
+
+    // The first value is used when a selection does not mention the define
+    pub fn with_choice(mut self, name: &str, values: &[&str]) -> Self {
+        assert!(!values.is_empty(), "Shader define {} needs at least one value", name);
+        self.defines.push(ShaderDefine::Choice(
+            name.to_string(),
+            values.iter().map(|value| value.to_string()).collect(),
+        ));
+
+        self
+    }
+
+    pub fn permutation_count(&self) -> usize {
+        self.defines.iter().map(|define| define.value_count()).product()
+    }
+
+    pub(crate) fn vertex_shader_path(&self, permutation: usize) -> String {
+        format!("{}{}_vert.spv", self.shader_path, self.permutation_suffix(permutation))
+    }
+
+    pub(crate) fn fragment_shader_path(&self, permutation: usize) -> String {
+        format!("{}{}_frag.spv", self.shader_path, self.permutation_suffix(permutation))
+    }
+
+    // Permutations are numbered in mixed radix, the first declared define changes fastest
+    fn permutation_suffix(&self, permutation: usize) -> String {
+        let mut remainder = permutation;
+        let mut suffix = String::new();
+
+        for define in self.defines.iter() {
+            let value = remainder % define.value_count();
+            remainder /= define.value_count();
+
+            match define {
+                ShaderDefine::Flag(name) if value == 1 => suffix.push_str(&format!("_{}", name.to_lowercase())),
+                ShaderDefine::Flag(_) => {}
+                ShaderDefine::Choice(name, values) => {
+                    suffix.push_str(&format!("_{}_{}", name.to_lowercase(), values[value].to_lowercase()))
+                }
+            }
+        }
+
+        suffix
+    }
+
+    fn permutation_of(&self, selection: &VariantSelection) -> Result<usize, &'static str> {
+        if selection
+            .defines
+            .iter()
+            .any(|(name, _)| !self.defines.iter().any(|define| define.name() == name))
+        {
+            return Err("Unknown shader define in variant selection");
+        }
+
+        let mut permutation = 0;
+        let mut stride = 1;
+        for define in self.defines.iter() {
+            let selected = selection
+                .defines
+                .iter()
+                .find(|(name, _)| name == define.name())
+                .map(|(_, value)| value.as_deref());
+
+            let value = match (define, selected) {
+                (_, None) => 0,
+                (ShaderDefine::Flag(_), Some(None)) => 1,
+                (ShaderDefine::Choice(_, values), Some(Some(selected))) => values
+                    .iter()
+                    .position(|value| value == selected)
+                    .ok_or("Unknown value of shader define in variant selection")?,
+                _ => return Err("Flags and choices of shader defines can't be mixed up"),
+            };
+
+            permutation += value * stride;
+            stride *= define.value_count();
+        }
+
+        Ok(permutation)
+    }
+}
+
+// The defines to pick a variant by, flags not mentioned are off and choices take their first value
+#[derive(Clone, Debug, Default)]
+pub struct VariantSelection {
+    defines: Vec<(String, Option<String>)>,
+}
+
+impl VariantSelection {
+    pub fn new() -> Self {
+        VariantSelection { defines: Vec::new() }
+    }
+
+    pub fn with_flag(mut self, name: &str) -> Self {
+        self.defines.push((name.to_string(), None));
+
+        self
+    }
+
+    pub fn with_choice(mut self, name: &str, value: &str) -> Self {
+        self.defines.push((name.to_string(), Some(value.to_string())));
+
+        self
+    }
+
+    pub fn set_flag(&mut self, name: &str, enabled: bool) {
+        self.defines.retain(|(define, _)| define != name);
+        if enabled {
+            self.defines.push((name.to_string(), None));
+        }
+    }
+}
+
+// One pipeline per permutation, created by Context::add_pipeline_variants
+pub struct PipelineVariants {
+    variants: ShaderVariants,
+    pipelines: Vec<PipelineHandle>,
+}
+
+impl PipelineVariants {
+    pub(crate) fn new(variants: ShaderVariants, pipelines: Vec<PipelineHandle>) -> Self {
+        debug_assert_eq!(variants.permutation_count(), pipelines.len());
+
+        PipelineVariants { variants, pipelines }
+    }
+
+    pub fn select(&self, selection: &VariantSelection) -> Result<PipelineHandle, &'static str> {
+        let permutation = self.variants.permutation_of(selection)?;
+
+        Ok(self.pipelines[permutation])
+    }
+
+    pub fn all(&self) -> &[PipelineHandle] {
+        &self.pipelines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn permutation_paths() {
+        let variants = ShaderVariants::new("shaders/lit")
+            .with_flag("FOG")
+            .with_choice("LIGHTING", &["PHONG", "DIFFUSE", "NONE"]);
+        assert_eq!(variants.permutation_count(), 6);
+
+        let base = variants.permutation_of(&VariantSelection::new()).unwrap();
+        assert_eq!(variants.vertex_shader_path(base), "shaders/lit_lighting_phong_vert.spv");
+
+        let selection = VariantSelection::new().with_choice("LIGHTING", "DIFFUSE").with_flag("FOG");
+        let permutation = variants.permutation_of(&selection).unwrap();
+        assert_eq!(variants.fragment_shader_path(permutation), "shaders/lit_fog_lighting_diffuse_frag.spv");
+
+        let paths: Vec<String> = (0..variants.permutation_count())
+            .map(|permutation| variants.vertex_shader_path(permutation))
+            .collect();
+        for (i, path) in paths.iter().enumerate() {
+            assert!(!paths[i + 1..].contains(path));
+        }
+
+        assert!(variants.permutation_of(&VariantSelection::new().with_flag("SHADOWS")).is_err());
+        assert!(variants.permutation_of(&VariantSelection::new().with_choice("LIGHTING", "PBR")).is_err());
+        assert!(variants.permutation_of(&VariantSelection::new().with_flag("LIGHTING")).is_err());
+    }
+}
@@ -0,0 +1,197 @@
+use ash::vk;
+
+use crate::engine::mesh::{pack_rgb10a2, quantize_half, quantize_unorm8};
+use crate::renderer::types::VertexInputDescription;
+
+// What a vertex attribute holds, used by mesh loaders to fill vertices of any layout
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VertexSemantic {
+    Position,
+    Normal,
+    TextureCoord,
+    Color,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct VertexAttribute {
+    // None for attributes of a VertexInputDescription
+    pub semantic: Option<VertexSemantic>,
+    pub description: vk::VertexInputAttributeDescription,
+}
+
+// Vertex format described at runtime, e.g. from the attributes found in a model file. Attributes are packed
+// in the order they are added, into the binding added last.
+#[derive(Clone, Debug, Default)]
+pub struct VertexLayout {
+    bindings: Vec<vk::VertexInputBindingDescription>,
+    attributes: Vec<VertexAttribute>,
+}
+
+impl VertexLayout {
+    pub fn new() -> Self {
+        VertexLayout::default()
+    }
+
+    pub fn from_description<T: VertexInputDescription>() -> Self {
+        VertexLayout {
+            bindings: T::binding_descriptions(),
+            attributes: T::attribute_descriptions()
+                .into_iter()
+                .map(|description| VertexAttribute {
+                    semantic: None,
+                    description,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn with_binding(mut self, input_rate: vk::VertexInputRate) -> Self {
+        self.bindings.push(vk::VertexInputBindingDescription {
+            binding: self.bindings.len() as u32,
+            stride: 0,
+            input_rate,
+        });
+
+        self
+    }
+
+    // Next free location, in a per vertex binding if none was added
+    pub fn with_attribute(mut self, semantic: VertexSemantic, format: vk::Format) -> Self {
+        if self.bindings.is_empty() {
+            self = self.with_binding(vk::VertexInputRate::VERTEX);
+        }
+        let location = self.attributes.iter().map(|attribute| attribute.description.location + 1).max().unwrap_or(0);
+        let binding = self.bindings.last_mut().unwrap();
+        self.attributes.push(VertexAttribute {
+            semantic: Some(semantic),
+            description: vk::VertexInputAttributeDescription {
+                binding: binding.binding,
+                location,
+                format,
+                offset: binding.stride,
+            },
+        });
+        binding.stride += format_size(format);
+
+        self
+    }
+
+    pub fn binding_descriptions(&self) -> Vec<vk::VertexInputBindingDescription> {
+        self.bindings.clone()
+    }
+
+    pub fn attribute_descriptions(&self) -> Vec<vk::VertexInputAttributeDescription> {
+        self.attributes.iter().map(|attribute| attribute.description).collect()
+    }
+
+    pub fn stride(&self, binding: u32) -> u32 {
+        self.bindings[binding as usize].stride
+    }
+
+    pub fn attribute(&self, semantic: VertexSemantic) -> Option<&VertexAttribute> {
+        self.attributes.iter().find(|attribute| attribute.semantic == Some(semantic))
+    }
+
+    // Writes the values into the attribute of one vertex of binding 0, ignored if the layout lacks the attribute.
+    // Missing components are zero, values are quantized to half float and normalized formats. Normals in
+    // normalized formats are mapped to 0..1 like pack_normal.
+    pub fn write(&self, vertex: &mut [u8], semantic: VertexSemantic, values: &[f32]) {
+        if let Some(attribute) = self.attribute(semantic) {
+            debug_assert_eq!(attribute.description.binding, 0, "Only binding 0 can be written");
+            let format = attribute.description.format;
+            let offset = attribute.description.offset as usize;
+            let size = format_size(format) as usize;
+            let unorm = matches!(format, vk::Format::R8G8B8A8_UNORM | vk::Format::A2B10G10R10_UNORM_PACK32);
+            if semantic == VertexSemantic::Normal && unorm {
+                let mapped: Vec<f32> = values.iter().map(|value| value * 0.5 + 0.5).collect();
+                _write_format(format, &mapped, &mut vertex[offset..offset + size]);
+            } else {
+                _write_format(format, values, &mut vertex[offset..offset + size]);
+            }
+        }
+    }
+}
+
+// Size in bytes of a vertex attribute format
+pub fn format_size(format: vk::Format) -> u32 {
+    match format {
+        vk::Format::R8G8B8A8_UNORM | vk::Format::A2B10G10R10_UNORM_PACK32 | vk::Format::R16G16_SFLOAT => 4,
+        vk::Format::R16G16B16A16_SFLOAT => 8,
+        vk::Format::R32_SFLOAT | vk::Format::R32_UINT | vk::Format::R32_SINT => 4,
+        vk::Format::R32G32_SFLOAT | vk::Format::R32G32_UINT | vk::Format::R32G32_SINT => 8,
+        vk::Format::R32G32B32_SFLOAT | vk::Format::R32G32B32_UINT | vk::Format::R32G32B32_SINT => 12,
+        vk::Format::R32G32B32A32_SFLOAT | vk::Format::R32G32B32A32_UINT | vk::Format::R32G32B32A32_SINT => 16,
+        _ => panic!("Unsupported vertex format {:?}", format),
+    }
+}
+
+fn _write_format(format: vk::Format, values: &[f32], out: &mut [u8]) {
+    match format {
+        vk::Format::R32_SFLOAT | vk::Format::R32G32_SFLOAT | vk::Format::R32G32B32_SFLOAT | vk::Format::R32G32B32A32_SFLOAT => {
+            for (i, component) in out.chunks_exact_mut(4).enumerate() {
+                let value = values.get(i).copied().unwrap_or(0.0);
+                component.copy_from_slice(&value.to_ne_bytes());
+            }
+        }
+        vk::Format::R16G16_SFLOAT | vk::Format::R16G16B16A16_SFLOAT => {
+            for (i, component) in out.chunks_exact_mut(2).enumerate() {
+                let value = values.get(i).copied().unwrap_or(0.0);
+                component.copy_from_slice(&quantize_half(value).to_ne_bytes());
+            }
+        }
+        vk::Format::R8G8B8A8_UNORM => {
+            for (i, component) in out.iter_mut().enumerate() {
+                *component = quantize_unorm8(values.get(i).copied().unwrap_or(0.0));
+            }
+        }
+        vk::Format::A2B10G10R10_UNORM_PACK32 => {
+            let value = |i: usize| values.get(i).copied().unwrap_or(0.0);
+            out.copy_from_slice(&pack_rgb10a2(value(0), value(1), value(2), value(3)).to_ne_bytes());
+        }
+        _ => panic!("Can't write vertex format {:?}", format),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runtime_layout() {
+        let layout = VertexLayout::new()
+            .with_attribute(VertexSemantic::Position, vk::Format::R32G32B32_SFLOAT)
+            .with_attribute(VertexSemantic::TextureCoord, vk::Format::R32G32_SFLOAT)
+            .with_binding(vk::VertexInputRate::INSTANCE)
+            .with_attribute(VertexSemantic::Color, vk::Format::R32G32B32A32_SFLOAT);
+
+        assert_eq!(layout.stride(0), 20);
+        assert_eq!(layout.stride(1), 16);
+        let attributes: Vec<_> = layout
+            .attribute_descriptions()
+            .iter()
+            .map(|a| (a.binding, a.location, a.offset))
+            .collect();
+        assert_eq!(attributes, vec![(0, 0, 0), (0, 1, 12), (1, 2, 0)]);
+        assert!(layout.attribute(VertexSemantic::Normal).is_none());
+
+        let mut vertex = vec![0xff_u8; layout.stride(0) as usize];
+        layout.write(&mut vertex, VertexSemantic::TextureCoord, &[0.5]);
+        layout.write(&mut vertex, VertexSemantic::Normal, &[1.0, 0.0, 0.0]);
+        assert_eq!(&vertex[12..16], &0.5_f32.to_ne_bytes());
+        assert_eq!(&vertex[16..20], &0.0_f32.to_ne_bytes());
+        assert_eq!(&vertex[0..4], &[0xff; 4]);
+
+        let packed = VertexLayout::new()
+            .with_attribute(VertexSemantic::Position, vk::Format::R16G16B16A16_SFLOAT)
+            .with_attribute(VertexSemantic::Normal, vk::Format::A2B10G10R10_UNORM_PACK32)
+            .with_attribute(VertexSemantic::Color, vk::Format::R8G8B8A8_UNORM);
+        assert_eq!(packed.stride(0), 16);
+        let mut vertex = vec![0_u8; 16];
+        packed.write(&mut vertex, VertexSemantic::Position, &[1.0, 0.0, -2.5]);
+        packed.write(&mut vertex, VertexSemantic::Color, &[1.0, 0.5, 0.0, 1.0]);
+        packed.write(&mut vertex, VertexSemantic::Normal, &[0.0, 0.0, 1.0]);
+        assert_eq!(&vertex[8..12], &crate::engine::mesh::pack_normal(cgmath::Vector3::new(0.0, 0.0, 1.0)).to_ne_bytes());
+        assert_eq!(&vertex[0..8], &[0x00, 0x3c, 0x00, 0x00, 0x00, 0xc1, 0x00, 0x00]);
+        assert_eq!(&vertex[12..16], &[255, 128, 0, 255]);
+    }
+}
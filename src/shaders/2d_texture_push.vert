@@ -5,6 +5,7 @@ layout (push_constant) uniform pushConstants {
     vec2 position;
     vec2 size;
     vec4 color;
+    vec4 uv_rect;
 } model;
 
 layout(binding = 0) uniform UniformBufferObject {
@@ -20,7 +21,7 @@ layout(location = 1) out vec2 fragTexCoord;
 
 void main() {
     fragColor = model.color;
-    fragTexCoord = inTexCoord;
+    fragTexCoord = model.uv_rect.xy + inTexCoord * model.uv_rect.zw;
 
     vec4 position = vec4((inPosition.x * model.size.x) + model.position.x, (inPosition.y * model.size.y ) + model.position.y, 0.0, 1.0);
 
@@ -0,0 +1,48 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+struct billboard_data {
+    vec3 position;
+    float _padding;
+    vec4 color;
+    vec4 uv_rect;
+    vec2 size;
+    vec2 _padding2;
+};
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4 view;
+    mat4 proj;
+} vp;
+
+layout(std430, binding = 2) buffer StorageBufferObject {
+    billboard_data instances[];
+} billboards;
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec2 inTexCoord;
+
+layout(location = 0) flat out vec4 fragColor;
+layout(location = 1) out vec2 fragTexCoord;
+
+void main() {
+    billboard_data billboard = billboards.instances[gl_InstanceIndex];
+    fragColor = billboard.color;
+    fragTexCoord = billboard.uv_rect.xy + inTexCoord * billboard.uv_rect.zw;
+
+    // The rows of the view rotation are the camera axes in world space
+    vec3 camera_right = vec3(vp.view[0][0], vp.view[1][0], vp.view[2][0]);
+#ifdef CYLINDRICAL
+    // Rotates around the world up axis only, e.g. for trees and characters standing on the ground
+    camera_right = normalize(vec3(camera_right.x, 0.0, camera_right.z));
+    vec3 camera_up = vec3(0.0, 1.0, 0.0);
+#else
+    vec3 camera_up = vec3(vp.view[0][1], vp.view[1][1], vp.view[2][1]);
+#endif
+
+    vec3 position = billboard.position
+        + camera_right * inPosition.x * billboard.size.x
+        + camera_up * inPosition.y * billboard.size.y;
+
+    gl_Position = vp.proj * vp.view * vec4(position, 1.0);
+}
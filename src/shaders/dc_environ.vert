@@ -2,7 +2,6 @@
 #extension GL_ARB_separate_shader_objects : enable
 
 layout (push_constant) uniform pushConstants {
-    mat4 transform;
     vec4 color;
 } model;
 
@@ -11,6 +10,10 @@ layout(binding = 0) uniform UniformBufferObject {
     mat4 proj;
 } vp;
 
+layout(std140, binding = 1) readonly buffer Transforms {
+    mat4 transforms[];
+};
+
 layout(location = 0) in vec3 inPosition;
 layout(location = 1) in vec3 inNormal;
 
@@ -19,7 +22,7 @@ layout(location = 1) out vec3 outNormal;
 layout(location = 2) out vec4 outColor;
 
 void main() {
-    mat4 mvp = vp.proj * vp.view * model.transform;
+    mat4 mvp = vp.proj * vp.view * transforms[gl_InstanceIndex];
     gl_Position = mvp * vec4(inPosition, 1.0);
 
     outPosition = vec3(mvp* vec4(inPosition, 1.0));
@@ -0,0 +1,36 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// dc_environ.vert for instances culled on the GPU, the transform of a visible instance is looked up through the
+// index written by the occlusion culling pre-pass
+layout (push_constant) uniform pushConstants {
+    vec4 color;
+    uint instance_start;
+} model;
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4 view;
+    mat4 proj;
+} vp;
+
+layout(std140, binding = 1) readonly buffer Transforms {
+    mat4 transforms[];
+};
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inNormal;
+layout(location = 2) in uint inInstance;
+
+layout(location = 0) out vec3 outPosition;
+layout(location = 1) out vec3 outNormal;
+layout(location = 2) out vec4 outColor;
+
+void main() {
+    mat4 mvp = vp.proj * vp.view * transforms[model.instance_start + inInstance];
+    gl_Position = mvp * vec4(inPosition, 1.0);
+
+    outPosition = vec3(mvp* vec4(inPosition, 1.0));
+    outNormal = normalize(vec3(mvp * vec4(inNormal, 0.0)));
+    outColor = model.color;
+
+}
@@ -13,6 +13,12 @@ layout(location = 0) out vec4 outColor;
 const float ambient_strength = 0.0;
 const vec3 lightColor = vec3(1.0, 0.7, 0.7);
 
+#ifdef FOG
+const vec3 fogColor = vec3(0.1, 0.1, 0.12);
+const float fogStart = 2.0;
+const float fogEnd = 6.0;
+#endif
+
 void main() {
     vec3 lightVector = normalize(inLightPosition - inPosition);
     float diffuse_factor = max(dot(inNormal, lightVector), 0.0);
@@ -23,6 +29,12 @@ void main() {
     float spec_factor = pow(max(dot(eyeVector, reflectVector), 0.0), 64);
     vec3 specular = 1 * spec_factor * lightColor;
 
-    outColor = vec4(min(diffuse + ambient_strength, 1.0) * inColor + specular, 1.0);
+    vec3 color = min(diffuse + ambient_strength, 1.0) * inColor + specular;
+#ifdef FOG
+    float fog_factor = clamp((inPosition.z - fogStart) / (fogEnd - fogStart), 0.0, 1.0);
+    color = mix(color, fogColor, fog_factor);
+#endif
+
+    outColor = vec4(color, 1.0);
 
 }
\ No newline at end of file
@@ -3,7 +3,6 @@
 
 layout (push_constant) uniform pushConstants {
     mat4 transform;
-    float wobble;
 } model;
 
 
@@ -12,6 +11,13 @@ layout(binding = 0) uniform UniformBufferObject {
     mat4 proj;
 } vp;
 
+layout(binding = 1) uniform FrameGlobals {
+    vec2 resolution;
+    float time;
+    float delta_time;
+    uint frame_index;
+} globals;
+
 layout(location = 0) in vec3 inPosition;
 layout(location = 1) in vec3 inColor;
 
@@ -25,8 +31,9 @@ vec3(0.0, 0.0, 1.0)
 );
 
 void main() {
-    float wobble_x = cos(model.wobble + gl_VertexIndex) * 0.1;
-    float wobble_y = sin(model.wobble + gl_VertexIndex) * 0.1;
+    float wobble = globals.time * 5.0;
+    float wobble_x = cos(wobble + gl_VertexIndex) * 0.1;
+    float wobble_y = sin(wobble + gl_VertexIndex) * 0.1;
 
     vec3 wobbled_position = vec3(inPosition.x + wobble_x, inPosition.y + wobble_y, inPosition.z);
 
@@ -0,0 +1,8 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// Stands in for a vertex shader that failed to load, everything is clipped so nothing is drawn
+
+void main() {
+    gl_Position = vec4(0.0, 0.0, 0.0, 0.0);
+}
@@ -0,0 +1,20 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// Stands in for a vertex shader that failed to load in pipelines with a model transform push constant and the
+// view projection uniform at binding 0, the geometry is drawn by the fallback fragment shader
+
+layout (push_constant) uniform pushConstants {
+    mat4 transform;
+} model;
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4 view;
+    mat4 proj;
+} vp;
+
+layout(location = 0) in vec3 inPosition;
+
+void main() {
+    gl_Position = vp.proj * vp.view * model.transform * vec4(inPosition, 1.0);
+}
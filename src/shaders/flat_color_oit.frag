@@ -0,0 +1,16 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) flat in vec4 fragColor;
+
+layout(location = 0) out vec4 outAccumulation;
+layout(location = 1) out float outRevealage;
+
+void main() {
+    float alpha = fragColor.a;
+    // Weight function from McGuire and Bavoil, close fragments with high alpha dominate
+    float weight = clamp(pow(min(1.0, alpha * 10.0) + 0.01, 3.0) * 1e8 * pow(1.0 - gl_FragCoord.z * 0.9, 3.0), 1e-2, 3e3);
+
+    outAccumulation = vec4(fragColor.rgb * alpha, alpha) * weight;
+    outRevealage = alpha;
+}
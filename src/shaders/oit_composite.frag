@@ -0,0 +1,26 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(binding = 1) uniform sampler2D accumulationSampler;
+layout(binding = 2) uniform sampler2D revealageSampler;
+
+layout(location = 0) flat in vec4 fragColor;
+layout(location = 1) in vec2 fragTexCoord;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    float revealage = texture(revealageSampler, fragTexCoord).r;
+    if (revealage >= 1.0) {
+        discard;
+    }
+
+    vec4 accumulation = texture(accumulationSampler, fragTexCoord);
+    // Half float overflow
+    if (isinf(max(max(abs(accumulation.r), abs(accumulation.g)), abs(accumulation.b)))) {
+        accumulation.rgb = vec3(accumulation.a);
+    }
+
+    vec3 averageColor = accumulation.rgb / max(accumulation.a, 0.00001);
+    outColor = vec4(averageColor, 1.0 - revealage) * fragColor;
+}
@@ -0,0 +1,19 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4 view;
+    mat4 proj;
+} vp;
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 0) out vec3 fragDirection;
+
+void main() {
+    // Only the rotation of the view, the sky doesn't move with the camera
+    vec4 position = vp.proj * mat4(mat3(vp.view)) * vec4(inPosition, 1.0);
+    // On the far plane, behind everything else in the pass
+    gl_Position = position.xyww;
+
+    fragDirection = inPosition;
+}
@@ -0,0 +1,19 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(binding = 1) uniform sampler2D texSampler;
+
+layout(location = 0) flat in vec4 fragColor;
+layout(location = 1) in vec2 fragTexCoord;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    vec4 texColor = texture(texSampler, fragTexCoord);
+    outColor = vec4(fragColor.rgb, texColor.x * fragColor.a);
+
+    // Drawn with depth writes, the space around the glyphs must not occlude what is behind it
+    if (texColor.x < 0.5) {
+        discard;
+    }
+}
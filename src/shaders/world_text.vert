@@ -0,0 +1,56 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+struct character_data {
+    mat4 transform;
+    vec4 color;
+    vec2 position;
+    int character;
+    float size;
+};
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4 view;
+    mat4 proj;
+} vp;
+
+layout(std430, binding = 2) buffer StorageBufferObject {
+    character_data instances[];
+} text_data;
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec2 inTexCoord;
+
+layout(location = 0) flat out vec4 fragColor;
+layout(location = 1) out vec2 fragTexCoord;
+
+const int WIDTH = 16;
+const float CHAR_WIDTH = 1.0/16.0;
+const float CHAR_HEIGHT = 1.0/6.0;
+
+void main() {
+    character_data data = text_data.instances[gl_InstanceIndex];
+    fragColor = data.color;
+
+    int character = data.character - 32; // First ASCII character in the texture will be 32
+    int offset_y = character / WIDTH;
+    int offset_x = character % WIDTH;
+    fragTexCoord = vec2(inTexCoord.x * CHAR_WIDTH + offset_x * CHAR_WIDTH, inTexCoord.y * CHAR_HEIGHT + offset_y * CHAR_HEIGHT);
+
+    vec2 local = data.position + inPosition.xy * data.size;
+#if defined(ORIENTATION_SPHERICAL) || defined(ORIENTATION_CYLINDRICAL)
+    // Only the translation of the transform is used, the text is laid out along the camera axes
+    vec3 camera_right = vec3(vp.view[0][0], vp.view[1][0], vp.view[2][0]);
+#ifdef ORIENTATION_CYLINDRICAL
+    camera_right = normalize(vec3(camera_right.x, 0.0, camera_right.z));
+    vec3 camera_up = vec3(0.0, 1.0, 0.0);
+#else
+    vec3 camera_up = vec3(vp.view[0][1], vp.view[1][1], vp.view[2][1]);
+#endif
+    vec3 position = data.transform[3].xyz + camera_right * local.x + camera_up * local.y;
+#else
+    vec3 position = (data.transform * vec4(local, 0.0, 1.0)).xyz;
+#endif
+
+    gl_Position = vp.proj * vp.view * vec4(position, 1.0);
+}
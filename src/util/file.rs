@@ -3,15 +3,14 @@ use std::io;
 use std::io::BufRead;
 use std::path::Path;
 
-pub fn read_file(path: &Path) -> Vec<u8> {
-    std::fs::read(path).unwrap_or_else(|_| panic!("Unable to open file: {:?}", path))
+use crate::error::VulkrapError;
+
+pub fn read_file(path: &Path) -> Result<Vec<u8>, VulkrapError> {
+    std::fs::read(path).map_err(|error| VulkrapError::io(path, error))
 }
 
-pub fn read_lines(path: &Path) -> Result<io::Lines<io::BufReader<File>>, &'static str> {
-    let file = File::open(path);
-    if file.is_err() {
-        return Err("Failed to open file");
-    }
+pub fn read_lines(path: &Path) -> Result<io::Lines<io::BufReader<File>>, VulkrapError> {
+    let file = File::open(path).map_err(|error| VulkrapError::io(path, error))?;
 
-    Ok(io::BufReader::new(file.unwrap()).lines())
+    Ok(io::BufReader::new(file).lines())
 }
@@ -3,3 +3,4 @@ pub(crate) mod frametimer;
 pub(crate) mod mem;
 
 mod math_test;
+mod reftest;
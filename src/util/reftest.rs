@@ -0,0 +1,102 @@
+// Reference image tests. These render small scenes with a headless context (lavapipe/SwiftShader
+// when available) and compare the result against golden images in resources/tests/golden.
+// Set VULKRAP_BLESS=1 to (re)generate the golden images, a missing golden image fails the test otherwise. Golden
+// images are only ever written from a render by a device, never by hand.
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use cgmath::{Matrix4, SquareMatrix, Vector4};
+
+    use crate::engine::datatypes::{SimpleVertex, TransformColorPushConstant, ViewProjectionUniform};
+    use crate::engine::mesh::{MeshHandle, MeshManager, PredefinedMesh};
+    use crate::renderer::context::{Context, Frame};
+    use crate::renderer::types::{DrawCommand, PipelineConfiguration, TextureHandle, UniformStage};
+
+    const WIDTH: u32 = 64;
+    const HEIGHT: u32 = 64;
+    const TOLERANCE: u8 = 2;
+
+    fn create_context() -> Option<Context> {
+        match Context::new_headless() {
+            Ok(context) => Some(context),
+            Err(err) => {
+                eprintln!("skipping reference test: {}", err);
+                None
+            }
+        }
+    }
+
+    fn render_frame(context: &mut Context, target: TextureHandle, draw: impl FnOnce(&mut Frame)) -> Vec<u8> {
+        let mut frame = context.begin_frame();
+        draw(&mut frame);
+        frame.submit();
+
+        context.read_render_texture(target)
+    }
+
+    fn compare_with_golden(name: &str, pixels: &[u8]) {
+        let path = PathBuf::from(format!("./resources/tests/golden/{}.png", name));
+
+        if std::env::var("VULKRAP_BLESS").is_ok() {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            image::save_buffer(&path, pixels, WIDTH, HEIGHT, image::ColorType::Rgba8).unwrap();
+            eprintln!("wrote golden image {:?}", path);
+            return;
+        }
+
+        assert!(path.exists(), "missing golden image {:?}, run with VULKRAP_BLESS=1 to create it", path);
+        let golden = image::open(&path).unwrap().to_rgba8();
+        assert_eq!(golden.dimensions(), (WIDTH, HEIGHT), "golden image size mismatch: {:?}", path);
+
+        let mismatches = golden
+            .as_raw()
+            .iter()
+            .zip(pixels.iter())
+            .filter(|(expected, actual)| expected.abs_diff(**actual) > TOLERANCE)
+            .count();
+        assert_eq!(mismatches, 0, "{} channels differ from {:?}", mismatches, path);
+    }
+
+    #[test]
+    fn clear() {
+        let Some(mut context) = create_context() else { return };
+
+        let target = context.add_render_texture(WIDTH, HEIGHT);
+        context.create_render_pass(target, 0).unwrap();
+
+        let pixels = render_frame(&mut context, target, |_| {});
+        compare_with_golden("clear", &pixels);
+    }
+
+    #[test]
+    fn flat_quad() {
+        let Some(mut context) = create_context() else { return };
+
+        let target = context.add_render_texture(WIDTH, HEIGHT);
+        let render_pass = context.create_render_pass(target, 0).unwrap();
+        let mesh_manager = MeshManager::new(&mut context);
+        let mesh = *mesh_manager.get_mesh(PredefinedMesh::SimpleQuad as MeshHandle);
+
+        let vp_uniform = context.create_uniform_buffer::<ViewProjectionUniform>(UniformStage::Vertex);
+        context.set_buffer_object(
+            vp_uniform,
+            ViewProjectionUniform { view: Matrix4::identity(), proj: Matrix4::identity() },
+        );
+
+        let pipeline_config = PipelineConfiguration::builder()
+            .with_vertex_shader_file(Path::new("./resources/shaders/flat_color_vert.spv"))
+            .with_fragment_shader_file(Path::new("./resources/shaders/flat_color_frag.spv"))
+            .with_push_constant::<TransformColorPushConstant>()
+            .with_vertex_uniform(0, vp_uniform)
+            .build()
+            .expect("Invalid pipeline configuration!");
+        let pipeline = context.add_pipeline::<SimpleVertex>(render_pass, pipeline_config);
+
+        let push_constant = TransformColorPushConstant::new(Matrix4::identity(), Vector4::new(1.0, 0.5, 0.0, 1.0));
+        let pixels = render_frame(&mut context, target, |frame| {
+            frame.add_draw_command(DrawCommand::new_buffered(pipeline, &push_constant, mesh));
+        });
+        compare_with_golden("flat_quad", &pixels);
+    }
+}
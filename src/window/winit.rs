@@ -2,9 +2,12 @@ use crate::engine::datatypes::WindowExtent;
 use winit::dpi::PhysicalSize;
 use winit::event::{DeviceEvent, ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
 use winit::event_loop::{ControlFlow, EventLoop};
-use winit::window::Window;
+use winit::monitor::VideoMode;
+use winit::window::{Fullscreen, Window};
 
+use crate::engine::cvars::{FULLSCREEN_BORDERLESS, FULLSCREEN_WINDOWED, WINDOW_FULLSCREEN, WINDOW_MONITOR, WINDOW_REFRESH_RATE};
 use crate::engine::runtime::{ControlSignal, Runtime, VulkrapApplication};
+use crate::log::logger;
 use crate::util::frametimer::FrameTimer;
 
 pub fn init_window(title: &'static str, width: u32, height: u32, event_loop: &EventLoop<()>) -> Window {
@@ -28,15 +31,7 @@ pub fn main_loop<T: VulkrapApplication + 'static>(event_loop: EventLoop<()>, win
                     (Some(VirtualKeyCode::Escape), ElementState::Pressed) => *control_flow = ControlFlow::Exit,
                     (Some(key), state) => {
                         let signal = vulkrap_runtime.handle_keyboard_event(key, state);
-                        if signal == ControlSignal::Quit {
-                            *control_flow = ControlFlow::Exit;
-                        }
-
-                        if signal == ControlSignal::ResizeWindow {
-                            let new_extent = vulkrap_runtime.get_configured_extent();
-                            window.set_inner_size(PhysicalSize::new(new_extent.width, new_extent.height));
-                        }
-
+                        _handle_control_signal(signal, &window, &mut vulkrap_runtime, control_flow);
                     }
                     _ => {}
                 },
@@ -56,8 +51,9 @@ pub fn main_loop<T: VulkrapApplication + 'static>(event_loop: EventLoop<()>, win
             window.request_redraw();
         }
         Event::RedrawRequested(_window_id) => {
-            vulkrap_runtime.update(frame_timer.delta_time_sec());
+            let signal = vulkrap_runtime.update(frame_timer.delta_time_sec());
             frame_timer.tick_frame();
+            _handle_control_signal(signal, &window, &mut vulkrap_runtime, control_flow);
         }
         Event::LoopDestroyed => {
             vulkrap_runtime.exit();
@@ -66,6 +62,127 @@ pub fn main_loop<T: VulkrapApplication + 'static>(event_loop: EventLoop<()>, win
     })
 }
 
+fn _handle_control_signal<T: VulkrapApplication + 'static>(
+    signal: ControlSignal,
+    window: &Window,
+    vulkrap_runtime: &mut Runtime<T>,
+    control_flow: &mut ControlFlow,
+) {
+    match signal {
+        ControlSignal::Quit => *control_flow = ControlFlow::Exit,
+        ControlSignal::ResizeWindow => _resize_window(window, vulkrap_runtime),
+        ControlSignal::UpdateWindowTitle => window.set_title(&vulkrap_runtime.get_window_title()),
+        ControlSignal::UpdateFullscreen => _update_fullscreen(window, vulkrap_runtime),
+        ControlSignal::ListVideoModes => _list_video_modes(window),
+        _ => {}
+    }
+}
+
+// The swapchain is recreated by the Resized event following the mode change
+fn _update_fullscreen<T: VulkrapApplication + 'static>(window: &Window, vulkrap_runtime: &mut Runtime<T>) {
+    let config = vulkrap_runtime.get_config();
+    let fullscreen = config.get(WINDOW_FULLSCREEN).as_int();
+    let monitor_index = config.get(WINDOW_MONITOR).as_int() as usize;
+    let refresh_rate_hz = config.get(WINDOW_REFRESH_RATE).as_int();
+
+    let monitor = window.available_monitors().nth(monitor_index).or_else(|| {
+        log_warning!("monitor {} not found, using the current monitor", monitor_index);
+        window.current_monitor()
+    });
+
+    match (fullscreen, monitor) {
+        (FULLSCREEN_WINDOWED, _) => window.set_fullscreen(None),
+        (FULLSCREEN_BORDERLESS, monitor) => window.set_fullscreen(Some(Fullscreen::Borderless(monitor))),
+        (_, Some(monitor)) => {
+            let video_modes: Vec<VideoMode> = monitor.video_modes().collect();
+            let candidates: Vec<(u32, u32, u32)> = video_modes
+                .iter()
+                .map(|mode| (mode.size().width, mode.size().height, mode.refresh_rate_millihertz()))
+                .collect();
+            let extent = vulkrap_runtime.get_configured_extent();
+
+            match _select_video_mode(&candidates, extent.width, extent.height, refresh_rate_hz) {
+                Some(index) => {
+                    let mode = &video_modes[index];
+                    log_info!(
+                        "fullscreen: {}x{} @ {:.2} Hz",
+                        mode.size().width,
+                        mode.size().height,
+                        mode.refresh_rate_millihertz() as f32 / 1000.0
+                    );
+                    window.set_fullscreen(Some(Fullscreen::Exclusive(mode.clone())));
+                }
+                None => log_error!("fullscreen: monitor {} has no video modes", monitor_index),
+            }
+        }
+        (_, None) => log_error!("fullscreen: no monitor available"),
+    }
+}
+
+fn _list_video_modes(window: &Window) {
+    for (index, monitor) in window.available_monitors().enumerate() {
+        let size = monitor.size();
+        logger::output(&format!(
+            "monitor {}: {} ({}x{})",
+            index,
+            monitor.name().unwrap_or_else(|| String::from("unknown")),
+            size.width,
+            size.height
+        ));
+        for mode in monitor.video_modes() {
+            logger::output(&format!(
+                " - {}x{} @ {:.2} Hz, {} bit",
+                mode.size().width,
+                mode.size().height,
+                mode.refresh_rate_millihertz() as f32 / 1000.0,
+                mode.bit_depth()
+            ));
+        }
+    }
+}
+
+// Picks a mode with the requested size, or the largest one if there is none, at the refresh rate closest to the
+// requested one. A refresh rate of 0 selects the highest. Modes are (width, height, refresh rate in mHz).
+fn _select_video_mode(modes: &[(u32, u32, u32)], width: u32, height: u32, refresh_rate_hz: u32) -> Option<usize> {
+    let exact_size = modes.iter().any(|&(w, h, _)| w == width && h == height);
+    let largest_area = modes.iter().map(|&(w, h, _)| w as u64 * h as u64).max()?;
+    let size_matches = |&(w, h, _): &(u32, u32, u32)| {
+        if exact_size {
+            w == width && h == height
+        } else {
+            w as u64 * h as u64 == largest_area
+        }
+    };
+
+    let candidates = modes.iter().enumerate().filter(|(_, mode)| size_matches(mode));
+    if refresh_rate_hz == 0 {
+        candidates.max_by_key(|(_, &(_, _, refresh_rate))| refresh_rate).map(|(index, _)| index)
+    } else {
+        let requested = refresh_rate_hz as i64 * 1000;
+        candidates
+            .min_by_key(|(_, &(_, _, refresh_rate))| (refresh_rate as i64 - requested).abs())
+            .map(|(index, _)| index)
+    }
+}
+
+// Applies the window_width and window_height cvars. The runtime, swapchain and application are updated when the
+// Resized event arrives.
+fn _resize_window<T: VulkrapApplication + 'static>(window: &Window, vulkrap_runtime: &mut Runtime<T>) {
+    let requested_extent = vulkrap_runtime.get_configured_extent();
+    window.set_inner_size(PhysicalSize::new(requested_extent.width, requested_extent.height));
+
+    // Platforms applying the size immediately don't necessarily send a Resized event
+    let window_extent: WindowExtent = window.inner_size().into();
+    if window_extent == requested_extent && vulkrap_runtime.get_window_extent() != window_extent {
+        vulkrap_runtime.handle_window_resize(window_extent);
+    } else if window.is_maximized() {
+        // The size is ignored, keep the cvars in sync with the actual window
+        log_warning!("window is maximized, size not changed");
+        vulkrap_runtime.handle_window_resize(window_extent);
+    }
+}
+
+#[cfg(feature = "console")]
 pub fn map_input_to_chr(key: VirtualKeyCode, state: ElementState, shift_active: bool) -> Option<char> {
     match (key, state, shift_active) {
         (VirtualKeyCode::Key1, ElementState::Pressed, false) => Some('1'),
@@ -155,3 +272,19 @@ impl From<PhysicalSize<u32>> for WindowExtent {
         WindowExtent::new(size.width, size.height)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn video_mode_selection() {
+        let modes = [(1920, 1080, 60000), (1920, 1080, 143856), (2560, 1440, 59951), (1280, 720, 60000)];
+
+        assert_eq!(_select_video_mode(&modes, 1920, 1080, 0), Some(1));
+        assert_eq!(_select_video_mode(&modes, 1920, 1080, 60), Some(0));
+        assert_eq!(_select_video_mode(&modes, 1920, 1080, 144), Some(1));
+        assert_eq!(_select_video_mode(&modes, 3840, 2160, 60), Some(2));
+        assert_eq!(_select_video_mode(&[], 1920, 1080, 0), None);
+    }
+}